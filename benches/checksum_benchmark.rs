@@ -0,0 +1,97 @@
+use std::env::current_dir;
+use tempfile::NamedTempFile;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use redb::{ChecksumAlgorithm, Database, TableDefinition};
+use std::time::{Duration, Instant};
+
+const TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("x");
+const ELEMENTS: usize = 1_000_000;
+const VALUE_SIZE: usize = 150;
+
+/// Returns pairs of key, value
+fn gen_data(count: usize) -> Vec<([u8; 16], Vec<u8>)> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut pairs = vec![];
+    for _ in 0..count {
+        let value: Vec<u8> = (0..VALUE_SIZE).map(|_| rng.gen()).collect();
+        pairs.push((rng.gen(), value));
+    }
+    pairs
+}
+
+fn benchmark(algorithm: ChecksumAlgorithm) -> Vec<(&'static str, Duration)> {
+    let mut results = Vec::new();
+    let pairs = gen_data(ELEMENTS);
+    let tmpfile: NamedTempFile = NamedTempFile::new_in(current_dir().unwrap()).unwrap();
+    let db = Database::builder()
+        .set_checksum_algorithm(algorithm)
+        .create(tmpfile.path())
+        .unwrap();
+
+    // Checksums are computed as the dirty pages are committed
+    let start = Instant::now();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(TABLE).unwrap();
+        for (key, value) in pairs.iter() {
+            table.insert(key.as_slice(), value.as_slice()).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let duration = start.elapsed();
+    println!(
+        "{:?}: Bulk loaded {} items in {}ms",
+        algorithm,
+        ELEMENTS,
+        duration.as_millis()
+    );
+    results.push(("bulk load", duration));
+
+    // Each commit rewrites, and so checksums, the path from the root to the modified leaf
+    let start = Instant::now();
+    for (key, value) in pairs.iter().take(ELEMENTS / 100) {
+        let txn = db.begin_write().unwrap();
+        txn.open_table(TABLE)
+            .unwrap()
+            .insert(key.as_slice(), value.as_slice())
+            .unwrap();
+        txn.commit().unwrap();
+    }
+    let duration = start.elapsed();
+    println!(
+        "{:?}: Wrote {} individual items in {}ms",
+        algorithm,
+        ELEMENTS / 100,
+        duration.as_millis()
+    );
+    results.push(("individual writes", duration));
+
+    results
+}
+
+fn main() {
+    let algorithms = [
+        ChecksumAlgorithm::Xxh3,
+        ChecksumAlgorithm::Crc32c,
+        ChecksumAlgorithm::Disabled,
+    ];
+    let results: Vec<_> = algorithms.iter().map(|x| benchmark(*x)).collect();
+
+    let mut table = comfy_table::Table::new();
+    table.set_width(100);
+    let mut header = vec!["".to_string()];
+    header.extend(algorithms.iter().map(|x| format!("{x:?}")));
+    table.set_header(header);
+    for (i, (name, _)) in results[0].iter().enumerate() {
+        let mut row = vec![name.to_string()];
+        for result in results.iter() {
+            row.push(format!("{}ms", result[i].1.as_millis()));
+        }
+        table.add_row(row);
+    }
+
+    println!();
+    println!("{table}");
+}
@@ -0,0 +1,165 @@
+use std::env::current_dir;
+use std::fs::File;
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use redb::{Database, Durability, ReadableTable, TableDefinition};
+use std::time::{Duration, Instant};
+
+const TABLE: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+const ELEMENTS: u64 = 1_000_000;
+const VALUE_SIZE: usize = 32;
+const READS: usize = 1_000_000;
+const SCANS: usize = 10_000;
+const SCAN_LENGTH: usize = 100;
+const COMMITS: usize = 1_000;
+
+// Set to a path to also write the results there, as JSON, for comparison between runs
+const OUTPUT_ENV_VAR: &str = "REDB_BENCH_OUTPUT";
+
+struct BenchResult {
+    name: &'static str,
+    operations: usize,
+    duration: Duration,
+}
+
+impl BenchResult {
+    fn nanos_per_op(&self) -> u128 {
+        let operations: u128 = self.operations.try_into().unwrap();
+        self.duration.as_nanos() / operations
+    }
+}
+
+fn timed(results: &mut Vec<BenchResult>, name: &'static str, operations: usize, f: impl FnOnce()) {
+    let start = Instant::now();
+    f();
+    let duration = start.elapsed();
+    println!(
+        "{}: {} operations in {}ms",
+        name,
+        operations,
+        duration.as_millis()
+    );
+    results.push(BenchResult {
+        name,
+        operations,
+        duration,
+    });
+}
+
+fn benchmark(db: &Database) -> Vec<BenchResult> {
+    let mut results = vec![];
+    let mut rng = StdRng::seed_from_u64(0);
+    let value = vec![0xFF; VALUE_SIZE];
+    let count: usize = ELEMENTS.try_into().unwrap();
+
+    timed(&mut results, "sequential insert", count, || {
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(TABLE).unwrap();
+            for i in 0..ELEMENTS {
+                table.insert(i, value.as_slice()).unwrap();
+            }
+        }
+        txn.commit().unwrap();
+    });
+
+    let keys: Vec<u64> = (0..count).map(|_| rng.gen()).collect();
+    timed(&mut results, "random insert", count, || {
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(TABLE).unwrap();
+            for key in keys.iter() {
+                table.insert(key, value.as_slice()).unwrap();
+            }
+        }
+        txn.commit().unwrap();
+    });
+
+    let keys: Vec<u64> = (0..READS).map(|_| rng.gen_range(0..ELEMENTS)).collect();
+    timed(&mut results, "point get", READS, || {
+        let txn = db.begin_read().unwrap();
+        let table = txn.open_table(TABLE).unwrap();
+        for key in keys.iter() {
+            assert_eq!(table.get(key).unwrap().unwrap().value().len(), VALUE_SIZE);
+        }
+    });
+
+    let starts: Vec<u64> = (0..SCANS).map(|_| rng.gen_range(0..ELEMENTS)).collect();
+    timed(&mut results, "range scan", SCANS * SCAN_LENGTH, || {
+        let txn = db.begin_read().unwrap();
+        let table = txn.open_table(TABLE).unwrap();
+        for start in starts.iter() {
+            let mut iter = table.range(*start..).unwrap();
+            for _ in 0..SCAN_LENGTH {
+                iter.next().unwrap().unwrap();
+            }
+        }
+    });
+
+    for (name, durability) in [
+        ("commit latency (none)", Durability::None),
+        ("commit latency (immediate)", Durability::Immediate),
+    ] {
+        timed(&mut results, name, COMMITS, || {
+            for i in 0..COMMITS {
+                let mut txn = db.begin_write().unwrap();
+                txn.set_durability(durability);
+                {
+                    let mut table = txn.open_table(TABLE).unwrap();
+                    let key: u64 = i.try_into().unwrap();
+                    table.insert(key, value.as_slice()).unwrap();
+                }
+                txn.commit().unwrap();
+            }
+        });
+    }
+
+    results
+}
+
+fn write_json(results: &[BenchResult], mut output: impl Write) {
+    writeln!(output, "[").unwrap();
+    for (i, result) in results.iter().enumerate() {
+        let separator = if i + 1 < results.len() { "," } else { "" };
+        writeln!(
+            output,
+            "  {{\"name\": \"{}\", \"operations\": {}, \"total_nanos\": {}, \"nanos_per_op\": {}}}{}",
+            result.name,
+            result.operations,
+            result.duration.as_nanos(),
+            result.nanos_per_op(),
+            separator
+        )
+        .unwrap();
+    }
+    writeln!(output, "]").unwrap();
+}
+
+fn main() {
+    let results = {
+        let tmpfile: NamedTempFile = NamedTempFile::new_in(current_dir().unwrap()).unwrap();
+        let db = Database::create(tmpfile.path()).unwrap();
+        benchmark(&db)
+    };
+
+    let mut table = comfy_table::Table::new();
+    table.set_width(100);
+    table.set_header(["", "total", "per operation"]);
+    for result in results.iter() {
+        table.add_row([
+            result.name.to_string(),
+            format!("{}ms", result.duration.as_millis()),
+            format!("{}ns", result.nanos_per_op()),
+        ]);
+    }
+
+    println!();
+    println!("{table}");
+
+    if let Some(path) = std::env::var_os(OUTPUT_ENV_VAR) {
+        write_json(&results, File::create(path).unwrap());
+    }
+}
@@ -1,3 +1,12 @@
 fn main() {
+    // Set by RUSTFLAGS="--cfg loom" to run the loom models
+    println!("cargo:rustc-check-cfg=cfg(loom)");
+    // Avoids unsafe code and platform calls which Miri can't check, such as reinterpreting stored
+    // bytes in place and file hints. Set by RUSTFLAGS="--cfg force_safe", and always when running
+    // under Miri, so that crates which depend on redb can run their tests with it
+    println!("cargo:rustc-check-cfg=cfg(force_safe)");
+    if std::env::var_os("CARGO_CFG_MIRI").is_some() {
+        println!("cargo:rustc-cfg=force_safe");
+    }
     pyo3_build_config::add_extension_module_link_args();
 }
@@ -0,0 +1,23 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+// Limit fixed width types to 64 bytes
+const MAX_FIXED_SIZE: usize = 64;
+
+#[derive(Arbitrary, Debug, Clone)]
+struct FuzzPages {
+    header: Vec<u8>,
+    pairs: Vec<(Vec<u8>, Vec<u8>)>,
+    fixed_key_size: Option<u8>,
+    fixed_value_size: Option<u8>,
+}
+
+fuzz_target!(|config: FuzzPages| {
+    redb::fuzzing::header(&config.header);
+
+    let fixed_key_size = config.fixed_key_size.map(|x| x as usize % MAX_FIXED_SIZE);
+    let fixed_value_size = config.fixed_value_size.map(|x| x as usize % MAX_FIXED_SIZE);
+    redb::fuzzing::leaf(&config.pairs, fixed_key_size, fixed_value_size);
+});
@@ -0,0 +1,376 @@
+//! Export of table entries as [Arrow](https://arrow.apache.org) record batches
+//!
+//! [`Table::to_arrow`] and [`ReadOnlyTable::to_arrow`] read a range of a table into a
+//! [`RecordBatch`] with one column for the keys and one for the values, which can be handed to
+//! Arrow based tools such as DataFusion or Polars. Each column is built directly from the stored
+//! entries, without going through an intermediate format. Fixed width types are appended into
+//! a contiguous buffer, which becomes the array's buffer without being copied again.
+//!
+//! Keys and values can be exported if their type implements [`ArrowValue`]: the integer types up
+//! to 64 bits, `f32`, `f64`, `&str`, `&[u8]`, `&[u8; N]`, and `Option` of any of these, which
+//! produces a nullable column. The `arrow_array` and `arrow_schema` crates are re-exported, so
+//! that the versions used by redb can be named.
+//!
+//! With the `parquet` feature, [`Table::export_parquet`] and [`ReadOnlyTable::export_parquet`]
+//! write a whole table to a Parquet file with the same schema, and the `parquet` crate is also
+//! re-exported.
+//!
+//! ```rust
+//! use redb::arrow::ArrowMapping;
+//! use redb::{Database, TableDefinition};
+//! # use tempfile::NamedTempFile;
+//! const TABLE: TableDefinition<u64, &str> = TableDefinition::new("names");
+//!
+//! # fn main() -> Result<(), redb::Error> {
+//! # let tmpfile = NamedTempFile::new().unwrap();
+//! let db = Database::create(tmpfile.path())?;
+//! let write_txn = db.begin_write()?;
+//! {
+//!     let mut table = write_txn.open_table(TABLE)?;
+//!     table.insert(1, "alice")?;
+//!     table.insert(2, "bob")?;
+//!     table.insert(3, "carol")?;
+//! }
+//! write_txn.commit()?;
+//!
+//! let read_txn = db.begin_read()?;
+//! let table = read_txn.open_table(TABLE)?;
+//! let batch = table.to_arrow(2.., &ArrowMapping::new("id", "name"))?;
+//! assert_eq!(batch.num_rows(), 2);
+//! assert_eq!(batch.schema().field(1).name(), "name");
+//! # Ok(())
+//! # }
+//! ```
+
+pub use arrow_array;
+pub use arrow_schema;
+#[cfg(feature = "parquet")]
+pub use parquet;
+
+use crate::types::{RedbKey, RedbValue};
+use crate::{Error, Range, ReadOnlyTable, ReadableTable, Result, Table};
+use arrow_array::builder::{
+    ArrayBuilder, BinaryBuilder, FixedSizeBinaryBuilder, PrimitiveBuilder, StringBuilder,
+};
+use arrow_array::types::{
+    Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, UInt16Type, UInt32Type,
+    UInt64Type, UInt8Type,
+};
+use arrow_array::{ArrowPrimitiveType, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use std::borrow::Borrow;
+#[cfg(feature = "parquet")]
+use std::fs::File;
+use std::io;
+use std::ops::RangeBounds;
+#[cfg(feature = "parquet")]
+use std::path::Path;
+use std::sync::Arc;
+
+// Number of entries in each row group of an exported Parquet file
+#[cfg(feature = "parquet")]
+const PARQUET_BATCH_ROWS: usize = 64 * 1024;
+
+/// An Arrow array builder which can append nulls
+pub trait ArrowBuilder: ArrayBuilder {
+    /// Appends a null to the array
+    fn append_null(&mut self);
+}
+
+impl<T: ArrowPrimitiveType> ArrowBuilder for PrimitiveBuilder<T> {
+    fn append_null(&mut self) {
+        PrimitiveBuilder::append_null(self);
+    }
+}
+
+impl ArrowBuilder for StringBuilder {
+    fn append_null(&mut self) {
+        StringBuilder::append_null(self);
+    }
+}
+
+impl ArrowBuilder for BinaryBuilder {
+    fn append_null(&mut self) {
+        BinaryBuilder::append_null(self);
+    }
+}
+
+impl ArrowBuilder for FixedSizeBinaryBuilder {
+    fn append_null(&mut self) {
+        FixedSizeBinaryBuilder::append_null(self);
+    }
+}
+
+/// A type which can be exported as an Arrow column
+pub trait ArrowValue: RedbValue {
+    /// Builder for the column
+    type Builder: ArrowBuilder;
+
+    /// Returns the Arrow type of the column
+    fn data_type() -> DataType;
+
+    /// Returns `true` if the column can contain nulls
+    fn nullable() -> bool {
+        false
+    }
+
+    /// Returns a builder with room for `capacity` values
+    fn builder(capacity: usize) -> Self::Builder;
+
+    /// Appends `value` to the column
+    fn append(builder: &mut Self::Builder, value: Self::SelfType<'_>);
+}
+
+macro_rules! arrow_primitive {
+    ($t:ty, $arrow_type:ty) => {
+        impl ArrowValue for $t {
+            type Builder = PrimitiveBuilder<$arrow_type>;
+
+            fn data_type() -> DataType {
+                <$arrow_type as ArrowPrimitiveType>::DATA_TYPE
+            }
+
+            fn builder(capacity: usize) -> Self::Builder {
+                PrimitiveBuilder::with_capacity(capacity)
+            }
+
+            fn append(builder: &mut Self::Builder, value: $t) {
+                builder.append_value(value);
+            }
+        }
+    };
+}
+
+arrow_primitive!(u8, UInt8Type);
+arrow_primitive!(u16, UInt16Type);
+arrow_primitive!(u32, UInt32Type);
+arrow_primitive!(u64, UInt64Type);
+arrow_primitive!(i8, Int8Type);
+arrow_primitive!(i16, Int16Type);
+arrow_primitive!(i32, Int32Type);
+arrow_primitive!(i64, Int64Type);
+arrow_primitive!(f32, Float32Type);
+arrow_primitive!(f64, Float64Type);
+
+impl ArrowValue for &str {
+    type Builder = StringBuilder;
+
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+
+    fn builder(capacity: usize) -> Self::Builder {
+        StringBuilder::with_capacity(capacity, 0)
+    }
+
+    fn append(builder: &mut Self::Builder, value: &str) {
+        builder.append_value(value);
+    }
+}
+
+impl ArrowValue for &[u8] {
+    type Builder = BinaryBuilder;
+
+    fn data_type() -> DataType {
+        DataType::Binary
+    }
+
+    fn builder(capacity: usize) -> Self::Builder {
+        BinaryBuilder::with_capacity(capacity, 0)
+    }
+
+    fn append(builder: &mut Self::Builder, value: &[u8]) {
+        builder.append_value(value);
+    }
+}
+
+impl<const N: usize> ArrowValue for &[u8; N] {
+    type Builder = FixedSizeBinaryBuilder;
+
+    fn data_type() -> DataType {
+        DataType::FixedSizeBinary(i32::try_from(N).unwrap())
+    }
+
+    fn builder(capacity: usize) -> Self::Builder {
+        FixedSizeBinaryBuilder::with_capacity(capacity, i32::try_from(N).unwrap())
+    }
+
+    fn append(builder: &mut Self::Builder, value: &[u8; N]) {
+        // Can only fail if the length differs from the byte width of the builder
+        builder.append_value(value).unwrap();
+    }
+}
+
+impl<T: ArrowValue> ArrowValue for Option<T> {
+    type Builder = T::Builder;
+
+    fn data_type() -> DataType {
+        T::data_type()
+    }
+
+    fn nullable() -> bool {
+        true
+    }
+
+    fn builder(capacity: usize) -> Self::Builder {
+        T::builder(capacity)
+    }
+
+    fn append(builder: &mut Self::Builder, value: Option<T::SelfType<'_>>) {
+        match value {
+            Some(value) => T::append(builder, value),
+            None => builder.append_null(),
+        }
+    }
+}
+
+/// Names of the columns which keys and values are exported to
+#[derive(Clone, Debug)]
+pub struct ArrowMapping {
+    key_name: String,
+    value_name: String,
+}
+
+impl ArrowMapping {
+    /// Exports keys to the column `key_name`, and values to the column `value_name`
+    pub fn new(key_name: impl Into<String>, value_name: impl Into<String>) -> Self {
+        Self {
+            key_name: key_name.into(),
+            value_name: value_name.into(),
+        }
+    }
+
+    /// Returns the schema of record batches exported from a table with keys of type `K` and values
+    /// of type `V`
+    pub fn schema<K: ArrowValue, V: ArrowValue>(&self) -> Schema {
+        Schema::new(vec![
+            Field::new(&self.key_name, K::data_type(), K::nullable()),
+            Field::new(&self.value_name, V::data_type(), V::nullable()),
+        ])
+    }
+}
+
+/// Exports keys to the column `key`, and values to the column `value`
+impl Default for ArrowMapping {
+    fn default() -> Self {
+        Self::new("key", "value")
+    }
+}
+
+// Reads up to `max_rows` entries from `range` into a record batch
+fn next_batch<K: RedbKey + ArrowValue + 'static, V: ArrowValue + 'static>(
+    range: &mut Range<K, V>,
+    schema: &SchemaRef,
+    max_rows: usize,
+) -> Result<RecordBatch> {
+    let capacity = range.size_hint().0.min(max_rows);
+    let mut keys = K::builder(capacity);
+    let mut values = V::builder(capacity);
+    for entry in range.take(max_rows) {
+        let (key, value) = entry?;
+        K::append(&mut keys, key.value());
+        V::append(&mut values, value.value());
+    }
+
+    RecordBatch::try_new(schema.clone(), vec![keys.finish(), values.finish()])
+        .map_err(|err| Error::Io(io::Error::new(io::ErrorKind::Other, err)))
+}
+
+fn to_arrow<'a, K: RedbKey + ArrowValue + 'static, V: ArrowValue + 'static, KR>(
+    table: &impl ReadableTable<K, V>,
+    range: impl RangeBounds<KR> + 'a,
+    mapping: &ArrowMapping,
+) -> Result<RecordBatch>
+where
+    KR: Borrow<K::SelfType<'a>> + 'a,
+{
+    let schema = Arc::new(mapping.schema::<K, V>());
+    next_batch(&mut table.range(range)?, &schema, usize::MAX)
+}
+
+#[cfg(feature = "parquet")]
+fn export_parquet<K: RedbKey + ArrowValue + 'static, V: ArrowValue + 'static>(
+    table: &impl ReadableTable<K, V>,
+    path: &Path,
+    mapping: &ArrowMapping,
+) -> Result<u64> {
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::properties::WriterProperties;
+
+    let parquet_error = |err| Error::Io(io::Error::new(io::ErrorKind::Other, err));
+    let schema = Arc::new(mapping.schema::<K, V>());
+    let properties = WriterProperties::builder()
+        .set_max_row_group_size(PARQUET_BATCH_ROWS)
+        .build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema.clone(), Some(properties))
+        .map_err(parquet_error)?;
+    let mut range = table.iter()?;
+    let mut exported = 0;
+    loop {
+        let batch = next_batch(&mut range, &schema, PARQUET_BATCH_ROWS)?;
+        if batch.num_rows() > 0 {
+            writer.write(&batch).map_err(parquet_error)?;
+            exported += batch.num_rows() as u64;
+        }
+        if batch.num_rows() < PARQUET_BATCH_ROWS {
+            break;
+        }
+    }
+    writer.close().map_err(parquet_error)?;
+
+    Ok(exported)
+}
+
+impl<'db, 'txn, K: RedbKey + ArrowValue + 'static, V: ArrowValue + 'static> Table<'db, 'txn, K, V> {
+    /// Reads the entries in `range` into a record batch, in key order
+    ///
+    /// The batch has two columns, for the keys and the values, named as given by `mapping`. All
+    /// of the entries are held in memory, so large tables should be exported a range at a time
+    pub fn to_arrow<'a, KR>(
+        &self,
+        range: impl RangeBounds<KR> + 'a,
+        mapping: &ArrowMapping,
+    ) -> Result<RecordBatch>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        to_arrow(self, range, mapping)
+    }
+
+    /// Writes every entry in the table to a new Parquet file at `path`, in key order
+    ///
+    /// The file has the schema returned by [`ArrowMapping::schema`], and the entries are written
+    /// a row group at a time, so the table does not need to fit in memory. The file contains the
+    /// table as seen by this transaction. If an error occurs, the incomplete file is left at
+    /// `path`. Returns the number of entries exported
+    #[cfg(feature = "parquet")]
+    pub fn export_parquet(&self, path: impl AsRef<Path>, mapping: &ArrowMapping) -> Result<u64> {
+        export_parquet(self, path.as_ref(), mapping)
+    }
+}
+
+impl<'txn, K: RedbKey + ArrowValue + 'static, V: ArrowValue + 'static> ReadOnlyTable<'txn, K, V> {
+    /// Reads the entries in `range` into a record batch, in key order
+    ///
+    /// See [`Table::to_arrow`]
+    pub fn to_arrow<'a, KR>(
+        &self,
+        range: impl RangeBounds<KR> + 'a,
+        mapping: &ArrowMapping,
+    ) -> Result<RecordBatch>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        to_arrow(self, range, mapping)
+    }
+
+    /// Writes every entry in the table to a new Parquet file at `path`, in key order
+    ///
+    /// See [`Table::export_parquet`]
+    #[cfg(feature = "parquet")]
+    pub fn export_parquet(&self, path: impl AsRef<Path>, mapping: &ArrowMapping) -> Result<u64> {
+        export_parquet(self, path.as_ref(), mapping)
+    }
+}
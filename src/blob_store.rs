@@ -0,0 +1,211 @@
+use crate::tree_store::xxh3_checksum;
+use crate::{
+    Error, ReadOnlyTable, ReadTransaction, ReadableTable, Result, Table, TableDefinition,
+    WriteTransaction,
+};
+use std::fmt::{Display, Formatter};
+use std::mem::size_of;
+
+// Blobs are split into fixed size chunks, and identical chunks are only stored once
+const CHUNK_SIZE: usize = 64 * 1024;
+
+const BLOBS_SUFFIX: &str = "::blobs";
+const BLOB_REFS_SUFFIX: &str = "::blob_refs";
+const CHUNKS_SUFFIX: &str = "::chunks";
+const CHUNK_REFS_SUFFIX: &str = "::chunk_refs";
+
+/// Content hash which identifies a blob in a [`BlobStore`]
+///
+/// This is a 128bit xxh3 hash. It is not a cryptographic hash, so a [`BlobStore`] should not be
+/// used to deduplicate content chosen by an adversary
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct BlobHash(u128);
+
+impl BlobHash {
+    pub fn from_u128(value: u128) -> Self {
+        Self(value)
+    }
+
+    pub fn as_u128(&self) -> u128 {
+        self.0
+    }
+}
+
+impl Display for BlobHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+fn table_name(name: &str, suffix: &str) -> String {
+    format!("{name}{suffix}")
+}
+
+fn read_blob(
+    blobs: &impl ReadableTable<u128, &'static [u8]>,
+    chunks: &impl ReadableTable<u128, &'static [u8]>,
+    hash: BlobHash,
+) -> Result<Option<Vec<u8>>> {
+    let manifest = if let Some(manifest) = blobs.get(hash.0)? {
+        manifest.value().to_vec()
+    } else {
+        return Ok(None);
+    };
+
+    let mut data = vec![];
+    for chunk_hash in manifest.chunks_exact(size_of::<u128>()) {
+        let chunk_hash = u128::from_le_bytes(chunk_hash.try_into().unwrap());
+        if let Some(chunk) = chunks.get(chunk_hash)? {
+            data.extend_from_slice(chunk.value());
+        } else {
+            return Err(Error::Corrupted(format!(
+                "Blob {hash} references missing chunk {}",
+                BlobHash(chunk_hash)
+            )));
+        }
+    }
+
+    Ok(Some(data))
+}
+
+/// Content addressable store of reference counted blobs, layered on top of regular tables
+///
+/// Each call to [`BlobStore::put`] adds a reference to the blob, and each call to
+/// [`BlobStore::release`] removes one. A blob is deleted once no references remain. Large blobs
+/// are split into chunks, and chunks which are shared between blobs are only stored once.
+///
+/// A blob store named `name` is stored in tables whose names start with `name::`
+pub struct BlobStore<'db, 'txn> {
+    blobs: Table<'db, 'txn, u128, &'static [u8]>,
+    blob_refs: Table<'db, 'txn, u128, u64>,
+    chunks: Table<'db, 'txn, u128, &'static [u8]>,
+    chunk_refs: Table<'db, 'txn, u128, u64>,
+}
+
+impl<'db, 'txn> BlobStore<'db, 'txn> {
+    /// Opens the blob store called `name`, creating it if it does not exist
+    pub fn open(transaction: &'txn WriteTransaction<'db>, name: &str) -> Result<Self> {
+        let blobs = table_name(name, BLOBS_SUFFIX);
+        let blob_refs = table_name(name, BLOB_REFS_SUFFIX);
+        let chunks = table_name(name, CHUNKS_SUFFIX);
+        let chunk_refs = table_name(name, CHUNK_REFS_SUFFIX);
+
+        Ok(Self {
+            blobs: transaction.open_table(TableDefinition::new(&blobs))?,
+            blob_refs: transaction.open_table(TableDefinition::new(&blob_refs))?,
+            chunks: transaction.open_table(TableDefinition::new(&chunks))?,
+            chunk_refs: transaction.open_table(TableDefinition::new(&chunk_refs))?,
+        })
+    }
+
+    /// Adds a reference to the blob containing `data`, storing it if it is not already present
+    ///
+    /// Returns the hash which identifies the blob
+    pub fn put(&mut self, data: &[u8]) -> Result<BlobHash> {
+        let hash = xxh3_checksum(data);
+        let chunk_hashes: Vec<u128> = data.chunks(CHUNK_SIZE).map(xxh3_checksum).collect();
+        let refs = self.blob_refs.get(hash)?.map(|x| x.value()).unwrap_or(0);
+        if refs == 0 {
+            for (chunk, chunk_hash) in data.chunks(CHUNK_SIZE).zip(chunk_hashes.iter()) {
+                self.put_chunk(chunk, *chunk_hash)?;
+            }
+            let manifest: Vec<u8> = chunk_hashes.iter().flat_map(|x| x.to_le_bytes()).collect();
+            self.blobs.insert(hash, manifest.as_slice())?;
+        } else {
+            let manifest = self.blobs.get(hash)?.unwrap();
+            let expected = manifest
+                .value()
+                .chunks_exact(size_of::<u128>())
+                .map(|x| u128::from_le_bytes(x.try_into().unwrap()));
+            if !expected.eq(chunk_hashes.iter().copied()) {
+                return Err(Error::BlobHashCollision(BlobHash(hash)));
+            }
+        }
+        self.blob_refs.insert(hash, refs + 1)?;
+
+        Ok(BlobHash(hash))
+    }
+
+    fn put_chunk(&mut self, chunk: &[u8], hash: u128) -> Result {
+        let refs = self.chunk_refs.get(hash)?.map(|x| x.value()).unwrap_or(0);
+        if refs == 0 {
+            self.chunks.insert(hash, chunk)?;
+        } else if self.chunks.get(hash)?.unwrap().value() != chunk {
+            return Err(Error::BlobHashCollision(BlobHash(hash)));
+        }
+        self.chunk_refs.insert(hash, refs + 1)?;
+
+        Ok(())
+    }
+
+    /// Returns the contents of the blob identified by `hash`
+    pub fn get(&self, hash: BlobHash) -> Result<Option<Vec<u8>>> {
+        read_blob(&self.blobs, &self.chunks, hash)
+    }
+
+    /// Returns the number of references to the blob identified by `hash`
+    pub fn references(&self, hash: BlobHash) -> Result<u64> {
+        Ok(self.blob_refs.get(hash.0)?.map(|x| x.value()).unwrap_or(0))
+    }
+
+    /// Removes a reference to the blob identified by `hash`, deleting it if no references remain
+    ///
+    /// Returns `false` if the blob does not exist
+    pub fn release(&mut self, hash: BlobHash) -> Result<bool> {
+        let refs = self.references(hash)?;
+        if refs == 0 {
+            return Ok(false);
+        }
+        if refs > 1 {
+            self.blob_refs.insert(hash.0, refs - 1)?;
+            return Ok(true);
+        }
+
+        self.blob_refs.remove(hash.0)?;
+        let manifest = self.blobs.remove(hash.0)?.unwrap().value().to_vec();
+        for chunk_hash in manifest.chunks_exact(size_of::<u128>()) {
+            let chunk_hash = u128::from_le_bytes(chunk_hash.try_into().unwrap());
+            let chunk_refs = self.chunk_refs.get(chunk_hash)?.unwrap().value();
+            if chunk_refs > 1 {
+                self.chunk_refs.insert(chunk_hash, chunk_refs - 1)?;
+            } else {
+                self.chunk_refs.remove(chunk_hash)?;
+                self.chunks.remove(chunk_hash)?;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Read-only view of a [`BlobStore`]
+pub struct ReadOnlyBlobStore<'txn> {
+    blobs: ReadOnlyTable<'txn, u128, &'static [u8]>,
+    blob_refs: ReadOnlyTable<'txn, u128, u64>,
+    chunks: ReadOnlyTable<'txn, u128, &'static [u8]>,
+}
+
+impl<'txn> ReadOnlyBlobStore<'txn> {
+    /// Opens the blob store called `name`
+    pub fn open(transaction: &'txn ReadTransaction, name: &str) -> Result<Self> {
+        let blobs = table_name(name, BLOBS_SUFFIX);
+        let blob_refs = table_name(name, BLOB_REFS_SUFFIX);
+        let chunks = table_name(name, CHUNKS_SUFFIX);
+
+        Ok(Self {
+            blobs: transaction.open_table(TableDefinition::new(&blobs))?,
+            blob_refs: transaction.open_table(TableDefinition::new(&blob_refs))?,
+            chunks: transaction.open_table(TableDefinition::new(&chunks))?,
+        })
+    }
+
+    /// Returns the contents of the blob identified by `hash`
+    pub fn get(&self, hash: BlobHash) -> Result<Option<Vec<u8>>> {
+        read_blob(&self.blobs, &self.chunks, hash)
+    }
+
+    /// Returns the number of references to the blob identified by `hash`
+    pub fn references(&self, hash: BlobHash) -> Result<u64> {
+        Ok(self.blob_refs.get(hash.0)?.map(|x| x.value()).unwrap_or(0))
+    }
+}
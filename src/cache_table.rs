@@ -0,0 +1,246 @@
+use crate::types::{RedbKey, RedbValue};
+use crate::{
+    AccessGuard, ReadOnlyTable, ReadTransaction, ReadableTable, Result, Table, TableDefinition,
+    WriteTransaction,
+};
+use std::borrow::Borrow;
+
+const ENTRIES_SUFFIX: &str = "::entries";
+const ACCESS_SUFFIX: &str = "::access";
+const ORDER_SUFFIX: &str = "::order";
+const USAGE_SUFFIX: &str = "::usage";
+const SEQUENCE_SUFFIX: &str = "::cache";
+
+fn entry_size<K: RedbKey, V: RedbValue>(key: &K::SelfType<'_>, value: &V::SelfType<'_>) -> u64 {
+    let len = K::as_bytes(key).as_ref().len() + V::as_bytes(value).as_ref().len();
+    len.try_into().unwrap()
+}
+
+/// Cache with least recently used eviction, layered on top of regular tables
+///
+/// Reading an entry with [`Self::get`] or writing it with [`Self::insert`] marks it as the most
+/// recently used. When an insert takes the cache over its entry or byte budget, the least recently
+/// used entries are evicted until it is back within budget. The access order is stored along with
+/// the entries, so it survives restarts, and is updated transactionally: if the transaction is
+/// aborted, neither its inserts nor its reads affect which entries are evicted.
+///
+/// A cache named `name` is stored in tables called `name::entries`, `name::access`, `name::order`
+/// and `name::usage`, and orders accesses with the sequence `name::cache`
+pub struct CacheTable<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> {
+    transaction: &'txn WriteTransaction<'db>,
+    sequence: String,
+    max_entries: u64,
+    max_bytes: u64,
+    entries: Table<'db, 'txn, K, V>,
+    // Key -> (access sequence number, size of the entry in bytes)
+    access: Table<'db, 'txn, K, (u64, u64)>,
+    // Access sequence number -> key, from least to most recently used
+    order: Table<'db, 'txn, u64, K>,
+    // Total size of the entries in bytes
+    usage: Table<'db, 'txn, (), u64>,
+}
+
+impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> CacheTable<'db, 'txn, K, V> {
+    /// Opens the cache called `name`, creating it if it does not exist
+    ///
+    /// The cache has no entry or byte budget until one is set with [`Self::set_max_entries`] or
+    /// [`Self::set_max_bytes`]
+    pub fn open(transaction: &'txn WriteTransaction<'db>, name: &str) -> Result<Self> {
+        let entries = format!("{name}{ENTRIES_SUFFIX}");
+        let access = format!("{name}{ACCESS_SUFFIX}");
+        let order = format!("{name}{ORDER_SUFFIX}");
+        let usage = format!("{name}{USAGE_SUFFIX}");
+        Ok(Self {
+            transaction,
+            sequence: format!("{name}{SEQUENCE_SUFFIX}"),
+            max_entries: u64::MAX,
+            max_bytes: u64::MAX,
+            entries: transaction.open_table(TableDefinition::new(&entries))?,
+            access: transaction.open_table(TableDefinition::new(&access))?,
+            order: transaction.open_table(TableDefinition::new(&order))?,
+            usage: transaction.open_table(TableDefinition::new(&usage))?,
+        })
+    }
+
+    /// Sets the maximum number of entries in the cache
+    ///
+    /// This setting is not stored in the database, so it must be set each time the cache is
+    /// opened. It takes effect on the next insert
+    pub fn set_max_entries(&mut self, max_entries: u64) {
+        self.max_entries = max_entries;
+    }
+
+    /// Sets the maximum total size of the keys and values in the cache, in bytes
+    ///
+    /// This setting is not stored in the database, so it must be set each time the cache is
+    /// opened. It takes effect on the next insert
+    pub fn set_max_bytes(&mut self, max_bytes: u64) {
+        self.max_bytes = max_bytes;
+    }
+
+    /// Returns the value for `key`, and marks it as the most recently used entry
+    pub fn get<'a>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'a>>,
+    ) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        K: 'a,
+    {
+        let size = match self.unlink(key.borrow())? {
+            Some(size) => size,
+            None => return Ok(None),
+        };
+        self.link(key.borrow(), size)?;
+
+        self.entries.get(key)
+    }
+
+    /// Returns the value for `key`, without marking it as used
+    pub fn peek<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        K: 'a,
+    {
+        self.entries.get(key)
+    }
+
+    /// Inserts `value` for `key`, marks it as the most recently used entry, and then evicts the
+    /// least recently used entries until the cache is within budget
+    ///
+    /// An entry which is larger than the byte budget on its own is evicted immediately. Returns the
+    /// number of entries which were evicted
+    pub fn insert<'a>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'a>>,
+        value: impl Borrow<V::SelfType<'a>>,
+    ) -> Result<u64>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let size = entry_size::<K, V>(key.borrow(), value.borrow());
+        let mut usage = self.size_bytes()?;
+        if let Some(old_size) = self.unlink(key.borrow())? {
+            usage -= old_size;
+        }
+        self.entries.insert(key.borrow(), value)?;
+        self.link(key.borrow(), size)?;
+
+        self.evict(usage + size)
+    }
+
+    /// Removes `key` from the cache, and returns its value
+    pub fn remove<'a>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'a>>,
+    ) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        K: 'a,
+    {
+        let size = match self.unlink(key.borrow())? {
+            Some(size) => size,
+            None => return Ok(None),
+        };
+        let usage = self.size_bytes()? - size;
+        self.usage.insert((), usage)?;
+
+        self.entries.remove(key)
+    }
+
+    /// Returns the number of entries in the cache
+    pub fn len(&self) -> Result<u64> {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the cache is empty
+    pub fn is_empty(&self) -> Result<bool> {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total size of the keys and values in the cache, in bytes
+    pub fn size_bytes(&self) -> Result<u64> {
+        size_bytes(&self.usage)
+    }
+
+    // Evicts the least recently used entries until the cache is within budget, given that its
+    // entries currently total `usage` bytes
+    fn evict(&mut self, mut usage: u64) -> Result<u64> {
+        let mut evicted = 0;
+        while self.entries.len()? > self.max_entries || usage > self.max_bytes {
+            let (_, key) = self.order.pop_first()?.unwrap();
+            let (_, size) = self.access.remove(key.value())?.unwrap().value();
+            self.entries.remove(key.value())?;
+            usage -= size;
+            evicted += 1;
+        }
+        self.usage.insert((), usage)?;
+
+        Ok(evicted)
+    }
+
+    // Marks `key` as the most recently used entry
+    fn link(&mut self, key: &K::SelfType<'_>, size: u64) -> Result {
+        let sequence = self.transaction.next_sequence(&self.sequence)?;
+        self.access.insert(key, (sequence, size))?;
+        self.order.insert(sequence, key)?;
+
+        Ok(())
+    }
+
+    // Removes `key` from the access order, and returns the size of its entry
+    fn unlink(&mut self, key: &K::SelfType<'_>) -> Result<Option<u64>> {
+        let (sequence, size) = match self.access.remove(key)? {
+            Some(value) => value.value(),
+            None => return Ok(None),
+        };
+        self.order.remove(sequence)?;
+
+        Ok(Some(size))
+    }
+}
+
+fn size_bytes(usage: &impl ReadableTable<(), u64>) -> Result<u64> {
+    Ok(usage.get(())?.map(|x| x.value()).unwrap_or(0))
+}
+
+/// Read-only view of a [`CacheTable`]
+///
+/// Reads through this view do not mark entries as used
+pub struct ReadOnlyCacheTable<'txn, K: RedbKey + 'static, V: RedbValue + 'static> {
+    entries: ReadOnlyTable<'txn, K, V>,
+    usage: ReadOnlyTable<'txn, (), u64>,
+}
+
+impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadOnlyCacheTable<'txn, K, V> {
+    /// Opens the cache called `name`
+    pub fn open(transaction: &'txn ReadTransaction, name: &str) -> Result<Self> {
+        let entries = format!("{name}{ENTRIES_SUFFIX}");
+        let usage = format!("{name}{USAGE_SUFFIX}");
+        Ok(Self {
+            entries: transaction.open_table(TableDefinition::new(&entries))?,
+            usage: transaction.open_table(TableDefinition::new(&usage))?,
+        })
+    }
+
+    /// Returns the value for `key`
+    pub fn get<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        K: 'a,
+    {
+        self.entries.get(key)
+    }
+
+    /// Returns the number of entries in the cache
+    pub fn len(&self) -> Result<u64> {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the cache is empty
+    pub fn is_empty(&self) -> Result<bool> {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total size of the keys and values in the cache, in bytes
+    pub fn size_bytes(&self) -> Result<u64> {
+        size_bytes(&self.usage)
+    }
+}
@@ -0,0 +1,68 @@
+use crate::sealed::Sealed;
+use crate::types::{RedbKey, RedbValue};
+use crate::{Result, TableDefinition, WriteTransaction};
+
+type ReferencePredicate<'a, K, CK, CV> = Box<
+    dyn for<'b, 'c> Fn(
+            <CK as RedbValue>::SelfType<'b>,
+            <CV as RedbValue>::SelfType<'b>,
+            <K as RedbValue>::SelfType<'c>,
+        ) -> bool
+        + 'a,
+>;
+
+/// A table whose rows reference the keys of a parent table, for use with
+/// [`WriteTransaction::delete_cascade`]
+pub struct ForeignKey<'a, K: RedbKey + 'static, CK: RedbKey + 'static, CV: RedbValue + 'static> {
+    definition: TableDefinition<'a, CK, CV>,
+    references: ReferencePredicate<'a, K, CK, CV>,
+}
+
+impl<'a, K: RedbKey + 'static, CK: RedbKey + 'static, CV: RedbValue + 'static>
+    ForeignKey<'a, K, CK, CV>
+{
+    /// Construct a reference from the rows of `definition` to a parent table. `references` is
+    /// called with the key and value of a row, and a parent key, and returns whether the row
+    /// references that key
+    pub fn new(
+        definition: TableDefinition<'a, CK, CV>,
+        references: impl for<'b, 'c> Fn(CK::SelfType<'b>, CV::SelfType<'b>, K::SelfType<'c>) -> bool
+            + 'a,
+    ) -> Self {
+        Self {
+            definition,
+            references: Box::new(references),
+        }
+    }
+}
+
+/// A table which references the keys of a parent table, such as a [`ForeignKey`]
+pub trait ReferencingTable<K: RedbKey + 'static>: Sealed {
+    // Removes the rows which reference `key`, and returns the number removed
+    #[doc(hidden)]
+    fn remove_references(&self, transaction: &WriteTransaction, key: &[u8]) -> Result<u64>;
+}
+
+impl<K: RedbKey + 'static, CK: RedbKey + 'static, CV: RedbValue + 'static> Sealed
+    for ForeignKey<'_, K, CK, CV>
+{
+}
+
+impl<K: RedbKey + 'static, CK: RedbKey + 'static, CV: RedbValue + 'static> ReferencingTable<K>
+    for ForeignKey<'_, K, CK, CV>
+{
+    fn remove_references(&self, transaction: &WriteTransaction, key: &[u8]) -> Result<u64> {
+        let mut table = transaction.open_table(self.definition)?;
+        let mut removed = 0;
+        // Rows aren't indexed by the key they reference, so every row must be checked
+        let drain = table.drain_filter::<&CK::SelfType<'_>, _>(.., |child_key, child_value| {
+            (self.references)(child_key, child_value, K::from_bytes(key))
+        })?;
+        for entry in drain {
+            entry?;
+            removed += 1;
+        }
+
+        Ok(removed)
+    }
+}
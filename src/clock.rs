@@ -0,0 +1,29 @@
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Source of the current time, and of delays, for the database
+///
+/// Set with [`crate::Builder::set_clock`], so that time dependent behavior, such as the creation
+/// time recorded for persistent savepoints and the backoff of [`crate::Database::run_write`], can
+/// be tested deterministically
+pub trait Clock: Send + Sync {
+    /// The current time
+    fn now(&self) -> SystemTime;
+
+    /// Blocks the calling thread for `duration`
+    fn sleep(&self, duration: Duration);
+}
+
+/// The system's wall clock. This is the default [`Clock`]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
@@ -0,0 +1,232 @@
+/// Defines tables which store a struct column by column, with one table per field
+///
+/// Scanning a single field only reads that field's table, which is much faster than reading whole
+/// rows when the struct has many fields, at the cost of one insert per field when writing a row.
+///
+/// The macro is given the names of a writable and a read-only table type to define, the key type,
+/// the name of the row struct, and the name and stored type of each field of the row. Borrowed key
+/// types must be written with a `'static` lifetime, such as `&'static str`. A row is stored as the
+/// value of each field in the table `name::field`, under the same key. Since rows are read into the
+/// struct, each field must be a type which is read as itself, such as an integer, a float, or a
+/// tuple or `Option` of those.
+///
+/// Both tables provide `open()`, `get()`, `len()` and `is_empty()`, and the writable table also
+/// provides `insert()` and `remove()`. Each field's table is returned by a method with the same
+/// name as the field, and can be used to scan that column with [`crate::ReadableTable::range`].
+///
+/// ```rust
+/// use redb::{columnar_table, Database, ReadableTable};
+/// # use tempfile::NamedTempFile;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Trade {
+///     price: f64,
+///     volume: u64,
+/// }
+///
+/// columnar_table! {
+///     /// Trades, keyed by timestamp
+///     pub struct TradeTable<u64>, ReadOnlyTradeTable for Trade {
+///         price: f64,
+///         volume: u64,
+///     }
+/// }
+///
+/// # fn main() -> Result<(), redb::Error> {
+/// # let tmpfile = NamedTempFile::new().unwrap();
+/// let db = Database::create(tmpfile.path())?;
+/// let write_txn = db.begin_write()?;
+/// {
+///     let mut trades = TradeTable::open(&write_txn, "trades")?;
+///     trades.insert(1, &Trade { price: 10.5, volume: 100 })?;
+///     trades.insert(2, &Trade { price: 11.0, volume: 50 })?;
+/// }
+/// write_txn.commit()?;
+///
+/// let read_txn = db.begin_read()?;
+/// let trades = ReadOnlyTradeTable::open(&read_txn, "trades")?;
+/// assert_eq!(trades.get(2)?, Some(Trade { price: 11.0, volume: 50 }));
+/// let mut volume = 0;
+/// for entry in trades.volume().range(1..3)? {
+///     volume += entry?.1.value();
+/// }
+/// assert_eq!(volume, 150);
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! columnar_table {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $table:ident<$key:ty>, $read_only:ident for $row:ident {
+            $first:ident: $first_ty:ty
+            $(, $field:ident: $ty:ty)*
+            $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $table<'db, 'txn> {
+            $first: $crate::Table<'db, 'txn, $key, $first_ty>,
+            $($field: $crate::Table<'db, 'txn, $key, $ty>,)*
+        }
+
+        impl<'db, 'txn> $table<'db, 'txn> {
+            /// Opens the columns of the table called `name`, creating them if they do not exist
+            $vis fn open(
+                transaction: &'txn $crate::WriteTransaction<'db>,
+                name: &str,
+            ) -> ::std::result::Result<Self, $crate::Error> {
+                Ok(Self {
+                    $first: transaction.open_table($crate::TableDefinition::new(
+                        &::std::format!("{}::{}", name, ::std::stringify!($first)),
+                    ))?,
+                    $($field: transaction.open_table($crate::TableDefinition::new(
+                        &::std::format!("{}::{}", name, ::std::stringify!($field)),
+                    ))?,)*
+                })
+            }
+
+            /// Inserts `row` under `key`, replacing any existing row
+            $vis fn insert<'k>(
+                &mut self,
+                key: impl ::std::borrow::Borrow<<$key as $crate::RedbValue>::SelfType<'k>>,
+                row: &$row,
+            ) -> ::std::result::Result<(), $crate::Error>
+            where
+                $key: 'k,
+            {
+                self.$first.insert(key.borrow(), &row.$first)?;
+                $(self.$field.insert(key.borrow(), &row.$field)?;)*
+
+                Ok(())
+            }
+
+            /// Removes the row for `key`, and returns `true` if it existed
+            $vis fn remove<'k>(
+                &mut self,
+                key: impl ::std::borrow::Borrow<<$key as $crate::RedbValue>::SelfType<'k>>,
+            ) -> ::std::result::Result<bool, $crate::Error>
+            where
+                $key: 'k,
+            {
+                let existed = self.$first.remove(key.borrow())?.is_some();
+                $(self.$field.remove(key.borrow())?;)*
+
+                Ok(existed)
+            }
+
+            /// Returns the row for `key`
+            $vis fn get<'k>(
+                &self,
+                key: impl ::std::borrow::Borrow<<$key as $crate::RedbValue>::SelfType<'k>>,
+            ) -> ::std::result::Result<Option<$row>, $crate::Error>
+            where
+                $key: 'k,
+            {
+                use $crate::ReadableTable;
+                Ok(Some($row {
+                    $first: match self.$first.get(key.borrow())? {
+                        Some(value) => value.value(),
+                        None => return Ok(None),
+                    },
+                    $($field: match self.$field.get(key.borrow())? {
+                        Some(value) => value.value(),
+                        None => return Ok(None),
+                    },)*
+                }))
+            }
+
+            /// Returns the number of rows
+            $vis fn len(&self) -> ::std::result::Result<u64, $crate::Error> {
+                use $crate::ReadableTable;
+                self.$first.len()
+            }
+
+            /// Returns `true` if there are no rows
+            $vis fn is_empty(&self) -> ::std::result::Result<bool, $crate::Error> {
+                use $crate::ReadableTable;
+                self.$first.is_empty()
+            }
+
+            /// Returns the column of this field
+            $vis fn $first(&self) -> &$crate::Table<'db, 'txn, $key, $first_ty> {
+                &self.$first
+            }
+
+            $(
+                /// Returns the column of this field
+                $vis fn $field(&self) -> &$crate::Table<'db, 'txn, $key, $ty> {
+                    &self.$field
+                }
+            )*
+        }
+
+        /// Read-only view of the columns of a table
+        $vis struct $read_only<'txn> {
+            $first: $crate::ReadOnlyTable<'txn, $key, $first_ty>,
+            $($field: $crate::ReadOnlyTable<'txn, $key, $ty>,)*
+        }
+
+        impl<'txn> $read_only<'txn> {
+            /// Opens the columns of the table called `name`
+            $vis fn open(
+                transaction: &'txn $crate::ReadTransaction,
+                name: &str,
+            ) -> ::std::result::Result<Self, $crate::Error> {
+                Ok(Self {
+                    $first: transaction.open_table($crate::TableDefinition::new(
+                        &::std::format!("{}::{}", name, ::std::stringify!($first)),
+                    ))?,
+                    $($field: transaction.open_table($crate::TableDefinition::new(
+                        &::std::format!("{}::{}", name, ::std::stringify!($field)),
+                    ))?,)*
+                })
+            }
+
+            /// Returns the row for `key`
+            $vis fn get<'k>(
+                &self,
+                key: impl ::std::borrow::Borrow<<$key as $crate::RedbValue>::SelfType<'k>>,
+            ) -> ::std::result::Result<Option<$row>, $crate::Error>
+            where
+                $key: 'k,
+            {
+                use $crate::ReadableTable;
+                Ok(Some($row {
+                    $first: match self.$first.get(key.borrow())? {
+                        Some(value) => value.value(),
+                        None => return Ok(None),
+                    },
+                    $($field: match self.$field.get(key.borrow())? {
+                        Some(value) => value.value(),
+                        None => return Ok(None),
+                    },)*
+                }))
+            }
+
+            /// Returns the number of rows
+            $vis fn len(&self) -> ::std::result::Result<u64, $crate::Error> {
+                use $crate::ReadableTable;
+                self.$first.len()
+            }
+
+            /// Returns `true` if there are no rows
+            $vis fn is_empty(&self) -> ::std::result::Result<bool, $crate::Error> {
+                use $crate::ReadableTable;
+                self.$first.is_empty()
+            }
+
+            /// Returns the column of this field
+            $vis fn $first(&self) -> &$crate::ReadOnlyTable<'txn, $key, $first_ty> {
+                &self.$first
+            }
+
+            $(
+                /// Returns the column of this field
+                $vis fn $field(&self) -> &$crate::ReadOnlyTable<'txn, $key, $ty> {
+                    &self.$field
+                }
+            )*
+        }
+    };
+}
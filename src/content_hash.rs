@@ -0,0 +1,473 @@
+use crate::tree_store::{
+    xxh3_checksum, BtreeChange, InternalTableDefinition, RawBtree, TransactionalMemory,
+};
+use crate::types::RedbKey;
+use crate::Result;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ops::Range;
+
+// Average number of entries in a chunk. Must be a power of two
+const CHUNK_TARGET_ENTRIES: u128 = 256;
+// Average number of children of each node above the chunks. Must be a power of two
+const NODE_TARGET_CHILDREN: u128 = 16;
+
+// Serialized content hash format:
+// 16 bytes: root hash
+// 8 bytes: number of chunks
+//
+// Followed by each chunk:
+// 16 bytes: hash
+// 8 bytes: number of entries
+// 4 bytes: length of the first key
+// n bytes: first key
+// 4 bytes: length of the last key
+// n bytes: last key
+//
+// The nodes above the chunks are not stored, since they only depend on the chunk hashes
+
+#[derive(Clone)]
+struct RawChunk {
+    hash: u128,
+    count: u64,
+    first: Vec<u8>,
+    last: Vec<u8>,
+}
+
+// Returns whether `key` is the last key of the chunk that contains it
+fn ends_chunk(key: &[u8]) -> bool {
+    xxh3_checksum(key) & (CHUNK_TARGET_ENTRIES - 1) == 0
+}
+
+fn read_chunks(data: &[u8]) -> Vec<RawChunk> {
+    let mut offset = size_of::<u128>();
+    let mut read = |len: usize| {
+        let result = &data[offset..(offset + len)];
+        offset += len;
+        result
+    };
+    let num_chunks = u64::from_le_bytes(read(size_of::<u64>()).try_into().unwrap());
+    let mut chunks = vec![];
+    for _ in 0..num_chunks {
+        let hash = u128::from_le_bytes(read(size_of::<u128>()).try_into().unwrap());
+        let count = u64::from_le_bytes(read(size_of::<u64>()).try_into().unwrap());
+        let len = u32::from_le_bytes(read(size_of::<u32>()).try_into().unwrap());
+        let first = read(len.try_into().unwrap()).to_vec();
+        let len = u32::from_le_bytes(read(size_of::<u32>()).try_into().unwrap());
+        let last = read(len.try_into().unwrap()).to_vec();
+        chunks.push(RawChunk {
+            hash,
+            count,
+            first,
+            last,
+        });
+    }
+    chunks
+}
+
+// Builds the levels of nodes above the chunks with the given hashes, from the lowest level up to
+// the one which contains only the root. A node ends after a child whose hash selects it as a
+// boundary, so, like the chunks, the nodes only change where the table's contents do
+fn build_levels(chunk_hashes: &[u128]) -> Vec<Vec<ContentNode>> {
+    let mut levels: Vec<Vec<ContentNode>> = vec![];
+    if chunk_hashes.is_empty() {
+        return levels;
+    }
+    // The hash and chunks of each node in the level below
+    let mut below: Vec<(u128, Range<usize>)> = chunk_hashes
+        .iter()
+        .enumerate()
+        .map(|(i, hash)| (*hash, i..(i + 1)))
+        .collect();
+    loop {
+        let mut level = vec![];
+        let mut start = 0;
+        for (i, (hash, _)) in below.iter().enumerate() {
+            // Every node, except perhaps the last, has at least two children, so that each level
+            // is smaller than the one below it
+            let boundary = i + 1 - start >= 2 && hash & (NODE_TARGET_CHILDREN - 1) == 0;
+            if boundary || i == below.len() - 1 {
+                let mut hashes = vec![];
+                for (hash, _) in &below[start..=i] {
+                    hashes.extend_from_slice(&hash.to_le_bytes());
+                }
+                level.push(ContentNode {
+                    hash: xxh3_checksum(&hashes),
+                    children: start..(i + 1),
+                    chunks: below[start].1.start..below[i].1.end,
+                });
+                start = i + 1;
+            }
+        }
+        below = level
+            .iter()
+            .map(|node| (node.hash, node.chunks.clone()))
+            .collect();
+        levels.push(level);
+        if below.len() == 1 {
+            return levels;
+        }
+    }
+}
+
+fn root_hash(levels: &[Vec<ContentNode>]) -> u128 {
+    if let Some(top) = levels.last() {
+        top[0].hash
+    } else {
+        xxh3_checksum(&[])
+    }
+}
+
+/// A chunk of the entries of a table, as summarized by a [`ContentHash`]
+pub struct ContentChunk<K: RedbKey + 'static> {
+    hash: u128,
+    count: u64,
+    first: Vec<u8>,
+    last: Vec<u8>,
+    _key_type: PhantomData<K>,
+}
+
+impl<K: RedbKey + 'static> ContentChunk<K> {
+    /// Returns the hash of the keys and values in the chunk
+    pub fn hash(&self) -> u128 {
+        self.hash
+    }
+
+    /// Returns the number of entries in the chunk
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the first key in the chunk
+    pub fn first(&self) -> K::SelfType<'_> {
+        K::from_bytes(&self.first)
+    }
+
+    /// Returns the last key in the chunk
+    pub fn last(&self) -> K::SelfType<'_> {
+        K::from_bytes(&self.last)
+    }
+}
+
+/// A node of the Merkle tree which a [`ContentHash`] forms over its chunks
+pub struct ContentNode {
+    hash: u128,
+    children: Range<usize>,
+    chunks: Range<usize>,
+}
+
+impl ContentNode {
+    /// Returns the hash of the node's children
+    pub fn hash(&self) -> u128 {
+        self.hash
+    }
+
+    /// Returns the positions of the node's children in the level below it. The children of
+    /// nodes in the lowest level are chunks
+    pub fn children(&self) -> Range<usize> {
+        self.children.clone()
+    }
+
+    /// Returns the positions of the chunks beneath the node, in [`ContentHash::chunks`]
+    pub fn chunks(&self) -> Range<usize> {
+        self.chunks.clone()
+    }
+}
+
+/// A Merkle hash of the keys and values of a table
+///
+/// The entries are divided, in key order, into chunks whose boundaries are chosen by hashing the
+/// keys, so the hash depends only on the contents of the table, and not on the order in which
+/// they were written or on how they are laid out in the database file. The chunks are the leaves
+/// of a tree of [`ContentNode`]s, whose boundaries are likewise chosen by hashing, so an insertion
+/// or removal only changes the chunk that it falls in and the nodes above it.
+///
+/// Two tables hold identical data if their [`ContentHash::root`] hashes are equal. Otherwise,
+/// [`ContentHash::divergent_chunks`] locates the key ranges which differ. Replicas can also
+/// compare [`ContentHash::levels`] from the root down, and only descend into nodes whose hashes
+/// differ
+pub struct ContentHash<K: RedbKey + 'static> {
+    root: u128,
+    chunks: Vec<ContentChunk<K>>,
+    levels: Vec<Vec<ContentNode>>,
+}
+
+impl<K: RedbKey + 'static> ContentHash<K> {
+    pub(crate) fn from_bytes(data: &[u8]) -> Self {
+        let root = u128::from_le_bytes(data[..size_of::<u128>()].try_into().unwrap());
+        let chunks: Vec<ContentChunk<K>> = read_chunks(data)
+            .into_iter()
+            .map(|chunk| ContentChunk {
+                hash: chunk.hash,
+                count: chunk.count,
+                first: chunk.first,
+                last: chunk.last,
+                _key_type: Default::default(),
+            })
+            .collect();
+        let hashes: Vec<u128> = chunks.iter().map(|x| x.hash).collect();
+
+        Self {
+            root,
+            chunks,
+            levels: build_levels(&hashes),
+        }
+    }
+
+    /// Returns the root hash, which summarizes the whole table
+    pub fn root(&self) -> u128 {
+        self.root
+    }
+
+    /// Returns the chunks, in key order
+    pub fn chunks(&self) -> &[ContentChunk<K>] {
+        &self.chunks
+    }
+
+    /// Returns the levels of nodes above the chunks, starting with the one directly above them.
+    /// The last level contains only the root, whose hash is [`ContentHash::root`]. There are no
+    /// levels if the table is empty
+    pub fn levels(&self) -> &[Vec<ContentNode>] {
+        &self.levels
+    }
+
+    /// Returns the total number of entries in the table
+    pub fn entries(&self) -> u64 {
+        self.chunks.iter().map(|x| x.count).sum()
+    }
+
+    /// Returns the chunks of this table which do not appear in `other`
+    ///
+    /// Every entry of this table which is missing from `other`, or has a different value there,
+    /// is in one of the returned chunks. Nodes whose hash appears in `other` are skipped, without
+    /// visiting the chunks beneath them
+    pub fn divergent_chunks(&self, other: &ContentHash<K>) -> Vec<&ContentChunk<K>> {
+        let other_hashes: HashSet<u128> = other
+            .levels
+            .iter()
+            .flatten()
+            .map(|x| x.hash)
+            .chain(other.chunks.iter().map(|x| x.hash))
+            .collect();
+        let mut result = vec![];
+        if let Some(top) = self.levels.last() {
+            self.divergent_helper(
+                self.levels.len() - 1,
+                0..top.len(),
+                &other_hashes,
+                &mut result,
+            );
+        }
+        result
+    }
+
+    fn divergent_helper<'a>(
+        &'a self,
+        level: usize,
+        nodes: Range<usize>,
+        other_hashes: &HashSet<u128>,
+        result: &mut Vec<&'a ContentChunk<K>>,
+    ) {
+        for node in &self.levels[level][nodes] {
+            if other_hashes.contains(&node.hash) {
+                continue;
+            }
+            if level == 0 {
+                result.extend(
+                    self.chunks[node.children()]
+                        .iter()
+                        .filter(|x| !other_hashes.contains(&x.hash)),
+                );
+            } else {
+                self.divergent_helper(level - 1, node.children(), other_hashes, result);
+            }
+        }
+    }
+}
+
+// Accumulates the chunks of a content hash
+struct ContentHashBuilder {
+    chunks: Vec<RawChunk>,
+    // The current chunk
+    entry_hashes: Vec<u8>,
+    count: u64,
+    first: Vec<u8>,
+    last: Vec<u8>,
+}
+
+impl ContentHashBuilder {
+    fn new() -> Self {
+        Self {
+            chunks: vec![],
+            entry_hashes: vec![],
+            count: 0,
+            first: vec![],
+            last: vec![],
+        }
+    }
+
+    // Adds the next entry, and returns true if it ends a chunk
+    fn push(&mut self, key: &[u8], value: &[u8]) -> bool {
+        if self.count == 0 {
+            self.first = key.to_vec();
+        }
+        let mut entry = Vec::with_capacity(size_of::<u32>() + key.len() + value.len());
+        let len: u32 = key.len().try_into().unwrap();
+        entry.extend_from_slice(&len.to_le_bytes());
+        entry.extend_from_slice(key);
+        entry.extend_from_slice(value);
+        self.entry_hashes
+            .extend_from_slice(&xxh3_checksum(&entry).to_le_bytes());
+        self.count += 1;
+        self.last.clear();
+        self.last.extend_from_slice(key);
+        // The boundary only depends on the key, so that changing a value does not move it
+        if ends_chunk(key) {
+            self.finish_chunk();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Adds a chunk which is unchanged from an earlier hash. Must only be called between chunks
+    fn push_chunk(&mut self, chunk: RawChunk) {
+        debug_assert_eq!(self.count, 0);
+        self.chunks.push(chunk);
+    }
+
+    fn finish_chunk(&mut self) {
+        self.chunks.push(RawChunk {
+            hash: xxh3_checksum(&self.entry_hashes),
+            count: self.count,
+            first: std::mem::take(&mut self.first),
+            last: self.last.clone(),
+        });
+        self.entry_hashes.clear();
+        self.count = 0;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.finish_chunk();
+        }
+        let hashes: Vec<u128> = self.chunks.iter().map(|x| x.hash).collect();
+        let mut result = vec![];
+        result.extend_from_slice(&root_hash(&build_levels(&hashes)).to_le_bytes());
+        let num_chunks: u64 = self.chunks.len().try_into().unwrap();
+        result.extend_from_slice(&num_chunks.to_le_bytes());
+        for chunk in self.chunks {
+            result.extend_from_slice(&chunk.hash.to_le_bytes());
+            result.extend_from_slice(&chunk.count.to_le_bytes());
+            let len: u32 = chunk.first.len().try_into().unwrap();
+            result.extend_from_slice(&len.to_le_bytes());
+            result.extend_from_slice(&chunk.first);
+            let len: u32 = chunk.last.len().try_into().unwrap();
+            result.extend_from_slice(&len.to_le_bytes());
+            result.extend_from_slice(&chunk.last);
+        }
+        result
+    }
+}
+
+pub(crate) fn empty_content_hash() -> Vec<u8> {
+    ContentHashBuilder::new().finish()
+}
+
+// Returns the serialized content hash of the given table. Every entry of the table is read
+pub(crate) fn compute_content_hash(
+    definition: &InternalTableDefinition,
+    mem: &TransactionalMemory,
+) -> Result<Vec<u8>> {
+    let mut builder = ContentHashBuilder::new();
+    RawBtree::new(
+        definition.get_root(),
+        definition.get_fixed_key_size(),
+        definition.get_fixed_value_size(),
+        mem,
+    )
+    .for_each_entry(|key, value| {
+        builder.push(key, value);
+        Ok(())
+    })?;
+
+    Ok(builder.finish())
+}
+
+// Returns the serialized content hash of `new`, given `previous`, the hash of `old`. Both trees
+// must belong to the same table, whose keys are ordered by `compare`. Only the chunks which
+// contain a key that differs between the trees are rehashed, by reading `new` from the start of
+// the first such chunk until its chunk boundaries line up with those of `previous` again
+pub(crate) fn update_content_hash(
+    previous: &[u8],
+    old: &RawBtree,
+    new: &RawBtree,
+    compare: fn(&[u8], &[u8]) -> Ordering,
+) -> Result<Vec<u8>> {
+    let mut changed_keys = vec![];
+    for change in old.diff(new, compare)? {
+        match change? {
+            BtreeChange::Added(entry)
+            | BtreeChange::Removed(entry)
+            | BtreeChange::Modified(_, entry) => changed_keys.push(entry.key().to_vec()),
+        }
+    }
+    let previous = read_chunks(previous);
+    let mut changed_keys = changed_keys.into_iter().peekable();
+    let mut builder = ContentHashBuilder::new();
+    // Position in `new` of the first entry of the next chunk
+    let mut position = 0;
+    let mut next = 0;
+    while next < previous.len() || changed_keys.peek().is_some() {
+        if let Some(chunk) = previous.get(next) {
+            // The last chunk also contains any keys after it, unless it ends at a boundary
+            let unbounded = next == previous.len() - 1 && !ends_chunk(&chunk.last);
+            let changed = match changed_keys.peek() {
+                Some(key) => unbounded || compare(key, &chunk.last) != Ordering::Greater,
+                None => false,
+            };
+            if !changed {
+                position += chunk.count;
+                builder.push_chunk(chunk.clone());
+                next += 1;
+                continue;
+            }
+        }
+
+        let mut aligned = false;
+        new.for_each_entry_from(position, |key, value| {
+            position += 1;
+            if !builder.push(key, value) {
+                return Ok(true);
+            }
+            while let Some(changed) = changed_keys.peek() {
+                if compare(changed, key) == Ordering::Greater {
+                    break;
+                }
+                changed_keys.next();
+            }
+            // Skip the chunks of `previous` which were merged into this one
+            while let Some(chunk) = previous.get(next) {
+                match compare(&chunk.last, key) {
+                    Ordering::Less => next += 1,
+                    Ordering::Equal => {
+                        next += 1;
+                        aligned = true;
+                        return Ok(false);
+                    }
+                    Ordering::Greater => break,
+                }
+            }
+            Ok(true)
+        })?;
+        if !aligned {
+            // Reached the end of the table
+            next = previous.len();
+            changed_keys.by_ref().for_each(drop);
+        }
+    }
+
+    Ok(builder.finish())
+}
@@ -1,27 +1,49 @@
+use crate::sync::atomic::{AtomicU64, Ordering};
+use crate::sync::Mutex;
 use crate::transaction_tracker::{SavepointId, TransactionId, TransactionTracker};
+use crate::transactions::{SequenceReservation, SAVEPOINT_TABLE};
 use crate::tree_store::{
-    AllPageNumbersBtreeIter, BtreeRangeIter, FreedTableKey, InternalTableDefinition, PageNumber,
-    RawBtree, TableType, TransactionalMemory, PAGE_SIZE,
+    apply_incremental_backup, upgrade_tree, write_copy, write_incremental_backup,
+    AllPageNumbersBtreeIter, Btree, BtreeRangeIter, Checksum, FreedTableKey, HeaderRecovery,
+    InternalTableDefinition, PageHint, PageNumber, RawBtree, RewriteValue, TableType,
+    TransactionalMemory, FILE_FORMAT_VERSION, PAGE_SIZE,
 };
 use crate::types::{RedbKey, RedbValue};
-use crate::{Durability, Error};
-use crate::{ReadTransaction, Result, WriteTransaction};
+use crate::watch::KeyWatches;
+use crate::{AllocationStrategy, CacheStats, ChecksumAlgorithm, FillPolicy};
+use crate::{DropBehavior, Durability, Error, KeyWatch, Pressure, QuarantinedPage};
+use crate::{ReadTransaction, Result, Savepoint, SavepointMetadata, SpaceReport, WriteTransaction};
+use std::borrow::Borrow;
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::fs::{File, OpenOptions};
 use std::io;
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Read, Write};
 use std::marker::PhantomData;
 use std::ops::RangeFull;
 use std::path::Path;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::multimap_table::parse_subtree_roots;
+use crate::clock::{Clock, SystemClock};
+use crate::multimap_table::{parse_subtree_roots, relocate_subtree, subtree_root};
+use crate::pressure::{PressureCallback, SizeLimit};
+use crate::quarantine::{find_corrupted_pages, Quarantine, QuarantineCallback};
 use crate::sealed::Sealed;
 use crate::Error::Corrupted;
 #[cfg(feature = "logging")]
 use log::{info, warn};
 
+// Rewrites the pages of a tree between file formats. See upgrade_tree()
+type RewriteTree = fn(
+    (PageNumber, Checksum),
+    Option<usize>,
+    Option<usize>,
+    &TransactionalMemory,
+    &mut RewriteValue,
+) -> Result<(PageNumber, Checksum)>;
+
 struct AtomicTransactionId {
     inner: AtomicU64,
 }
@@ -95,6 +117,7 @@ impl Sealed for UntypedMultimapTableHandle {}
 /// that is stored or retreived from the table
 pub struct TableDefinition<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
     name: &'a str,
+    fill_policy: FillPolicy,
     _key_type: PhantomData<K>,
     _value_type: PhantomData<V>,
 }
@@ -109,10 +132,28 @@ impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> TableDefinition<'a, K, V>
         assert!(!name.is_empty());
         Self {
             name,
+            fill_policy: FillPolicy::BALANCED,
+            _key_type: PhantomData,
+            _value_type: PhantomData,
+        }
+    }
+
+    /// Sets how full the pages of the table are kept, when it is modified through this definition
+    ///
+    /// This only affects how pages are split and merged, so it can be changed each time the table
+    /// is opened
+    pub const fn with_fill_policy(self, fill_policy: FillPolicy) -> Self {
+        Self {
+            name: self.name,
+            fill_policy,
             _key_type: PhantomData,
             _value_type: PhantomData,
         }
     }
+
+    pub(crate) fn fill_policy(&self) -> FillPolicy {
+        self.fill_policy
+    }
 }
 
 impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> TableHandle for TableDefinition<'a, K, V> {
@@ -198,6 +239,54 @@ impl<'a, K: RedbKey + 'static, V: RedbKey + 'static> Display for MultimapTableDe
     }
 }
 
+/// Describes the recovery performed when a database, which was not shutdown cleanly, was opened
+///
+/// See [`Database::last_recovery_report`]
+#[derive(Clone, Debug)]
+pub struct RecoveryReport {
+    primary_header_corrupted: bool,
+    secondary_header_corrupted: bool,
+    header_slot_swapped: bool,
+    primary_commit_discarded: bool,
+    recovered_transaction_id: u64,
+    pages_reclaimed: u64,
+}
+
+impl RecoveryReport {
+    /// Whether the checksum of the primary commit slot in the file header was invalid
+    pub fn primary_header_corrupted(&self) -> bool {
+        self.primary_header_corrupted
+    }
+
+    /// Whether the checksum of the secondary commit slot in the file header was invalid
+    pub fn secondary_header_corrupted(&self) -> bool {
+        self.secondary_header_corrupted
+    }
+
+    /// Whether the secondary commit slot was chosen, because the primary slot was corrupted or
+    /// held an older commit
+    pub fn header_slot_swapped(&self) -> bool {
+        self.header_slot_swapped
+    }
+
+    /// Whether the commit in the chosen slot failed checksum verification, and so was discarded
+    /// in favor of the previous commit
+    pub fn primary_commit_discarded(&self) -> bool {
+        self.primary_commit_discarded
+    }
+
+    /// Id of the commit which the database was recovered to
+    pub fn recovered_transaction_id(&self) -> u64 {
+        self.recovered_transaction_id
+    }
+
+    /// Number of pages which were marked as allocated, but were not reachable from the recovered
+    /// commit, and so were freed when the allocator state was rebuilt
+    pub fn pages_reclaimed(&self) -> u64 {
+        self.pages_reclaimed
+    }
+}
+
 /// Opened redb database file
 ///
 /// Use [`Self::begin_read`] to get a [`ReadTransaction`] object that can be used to read from the database
@@ -233,6 +322,12 @@ pub struct Database {
     next_transaction_id: AtomicTransactionId,
     transaction_tracker: Arc<Mutex<TransactionTracker>>,
     pub(crate) live_write_transaction: Mutex<Option<TransactionId>>,
+    // Sequence ids reserved by committed transactions, which have not yet been handed out
+    pub(crate) sequences: Mutex<HashMap<String, SequenceReservation>>,
+    max_transaction_bytes: Option<u64>,
+    recovery_report: Option<RecoveryReport>,
+    pub(crate) key_watches: KeyWatches,
+    clock: Arc<dyn Clock>,
 }
 
 impl Database {
@@ -249,10 +344,79 @@ impl Database {
         Self::builder().open(path)
     }
 
+    /// Checks whether the database at `path` uses an older file format, which must be upgraded
+    /// with [`Database::upgrade`] before it can be opened
+    ///
+    /// The file is only read, so this is safe to call on a database that is open elsewhere
+    pub fn needs_upgrade(path: impl AsRef<Path>) -> Result<bool> {
+        let file = File::open(path)?;
+        Ok(TransactionalMemory::file_format_version(&file)? < FILE_FORMAT_VERSION)
+    }
+
+    /// Upgrades the database at `path` to the current file format, in place
+    ///
+    /// Returns `Ok(true)` if the file was upgraded, or `Ok(false)` if it already uses the current
+    /// file format. Returns [`Error::DatabaseAlreadyOpen`] if the database is open.
+    ///
+    /// Files from version 114 onward are upgraded by rewriting the branch pages of every table,
+    /// which takes time proportional to the size of the database, and then opening it once, to
+    /// free the old pages. If this is interrupted, the file is left in its old format.
+    ///
+    /// Returns [`Error::UpgradeRequired`] for older files, which must be exported with the version
+    /// of redb that wrote them, and re-imported. It is also returned if the file was not shut down
+    /// cleanly, in which case it must first be opened by the version of redb that wrote it, to
+    /// recover it, or if it has persistent savepoints, which must be deleted first
+    pub fn upgrade(path: impl AsRef<Path>) -> Result<bool> {
+        let path = path.as_ref();
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mem = if let Some(mem) = TransactionalMemory::open_for_upgrade(file)? {
+            mem
+        } else {
+            return Ok(false);
+        };
+        if let Err(err) = Self::upgrade_tables(&mem) {
+            mem.rollback_uncommitted_writes()?;
+            return Err(err);
+        }
+        drop(mem);
+
+        // Recover the allocator state, which was not updated as the tables were rewritten
+        Self::open(path)?;
+
+        Ok(true)
+    }
+
+    fn upgrade_tables(mem: &TransactionalMemory) -> Result {
+        let data_root = mem
+            .get_data_root()
+            .map(|root| Self::rewrite_tables_recursive(root, mem, upgrade_tree))
+            .transpose()?;
+        let system_root = mem
+            .get_system_root()
+            .map(|root| Self::rewrite_tables_recursive(root, mem, upgrade_tree))
+            .transpose()?;
+
+        // Savepoints reference the pages of older commits, which are not rewritten
+        let system_tree: Btree<&str, InternalTableDefinition> =
+            Btree::new(system_root, PageHint::None, mem)?;
+        if let Some(definition) = system_tree.get(&SAVEPOINT_TABLE.name())? {
+            if definition.value().get_root().is_some() {
+                return Err(Error::UpgradeRequired(mem.get_version()));
+            }
+        }
+        drop(system_tree);
+
+        mem.finish_upgrade(data_root, system_root)
+    }
+
     pub(crate) fn get_memory(&self) -> &TransactionalMemory {
         &self.mem
     }
 
+    pub(crate) fn max_transaction_bytes(&self) -> Option<u64> {
+        self.max_transaction_bytes
+    }
+
     #[cfg(any(fuzzing, test))]
     pub fn set_crash_countdown(&self, value: u64) {
         self.mem.set_crash_countdown(value);
@@ -280,7 +444,7 @@ impl Database {
                     if !RawBtree::new(
                         Some((table_root, table_checksum)),
                         definition.get_fixed_key_size(),
-                        definition.get_fixed_value_size(),
+                        definition.get_fixed_tree_value_size(),
                         mem,
                     )
                     .verify_checksum()?
@@ -314,7 +478,7 @@ impl Database {
                     if !RawBtree::new(
                         Some((table_root, table_checksum)),
                         definition.get_fixed_key_size(),
-                        definition.get_fixed_value_size(),
+                        definition.get_fixed_tree_value_size(),
                         mem,
                     )
                     .verify_checksum()?
@@ -352,26 +516,34 @@ impl Database {
             return Ok(true);
         }
 
-        Self::do_repair(&mut self.mem)?;
+        self.recovery_report = Some(Self::do_repair(&mut self.mem)?);
         self.mem.begin_writable()?;
 
         Ok(false)
     }
 
+    /// Returns a report of the recovery performed when the database was opened, or `None` if it
+    /// had been shutdown cleanly
+    ///
+    /// The report is also updated if [`Database::check_integrity`] repairs the database
+    pub fn last_recovery_report(&self) -> Option<&RecoveryReport> {
+        self.recovery_report.as_ref()
+    }
+
     /// Compacts the database file
     ///
     /// Returns `true` if compaction was performed, and `false` if no futher compaction was possible
     pub fn compact(&mut self) -> Result<bool> {
         // Commit to free up any pending free pages
         // Use 2-phase commit to avoid any possible security issues. Plus this compaction is going to be so slow that it doesn't matter
-        let mut txn = self.begin_write()?;
+        let mut txn = self.begin_write_abort_on_drop()?;
         if txn.list_persistent_savepoints()?.next().is_some() {
             return Err(Error::PersistentSavepointExists);
         }
         txn.set_durability(Durability::Paranoid);
         txn.commit()?;
         // Repeat, just in case executing list_persistent_savepoints() created a new table
-        let mut txn = self.begin_write()?;
+        let mut txn = self.begin_write_abort_on_drop()?;
         txn.set_durability(Durability::Paranoid);
         txn.commit()?;
         // There can't be any outstanding transactions because we have a `&mut self`, so all pending free pages
@@ -383,7 +555,7 @@ impl Database {
         loop {
             let mut progress = false;
 
-            let mut txn = self.begin_write()?;
+            let mut txn = self.begin_write_abort_on_drop()?;
             if txn.compact_pages()? {
                 progress = true;
                 txn.commit()?;
@@ -392,7 +564,7 @@ impl Database {
             }
 
             // Double commit to free up the relocated pages for reuse
-            let mut txn = self.begin_write()?;
+            let mut txn = self.begin_write_abort_on_drop()?;
             txn.set_durability(Durability::Paranoid);
             txn.commit()?;
             assert!(self.mem.get_freed_root().is_none());
@@ -407,44 +579,73 @@ impl Database {
         Ok(compacted)
     }
 
-    fn mark_tables_recursive(root: PageNumber, mem: &mut TransactionalMemory) -> Result {
-        // Repair the allocator state
+    /// Releases the free space at the end of the database file back to the filesystem
+    ///
+    /// Commits normally only shrink the file once at least half of its last region is free.
+    /// This instead truncates the file after its last allocated page, without relocating any
+    /// pages, so it is much cheaper than [`Database::compact`] but only reclaims space at the end
+    /// of the file. Pages which are still referenced by a read transaction or savepoint are not
+    /// free, so are not reclaimed.
+    ///
+    /// Returns the number of bytes by which the file shrank
+    pub fn truncate_free_tail(&self) -> Result<u64> {
+        // Commit first, to free the pages released by earlier transactions
+        self.begin_write_abort_on_drop()?.commit()?;
+        let txn = self.begin_write_abort_on_drop()?;
+        let original_len = self.mem.file_len();
+        self.mem.trim_free_tail_on_commit();
+        txn.commit()?;
+
+        Ok(original_len - self.mem.file_len())
+    }
+
+    // Calls `visitor` with the pages of the table tree rooted at `root`, and of every table in it
+    fn visit_tables_recursive(
+        root: PageNumber,
+        mem: &TransactionalMemory,
+        visitor: &mut impl FnMut(&mut dyn Iterator<Item = Result<PageNumber>>) -> Result,
+    ) -> Result {
         // All pages in the master table
-        let master_pages_iter = AllPageNumbersBtreeIter::new(root, None, None, mem)?;
-        mem.mark_pages_allocated(master_pages_iter)?;
+        visitor(&mut AllPageNumbersBtreeIter::new(root, None, None, mem)?)?;
 
         // Iterate over all other tables
         let iter: BtreeRangeIter<&str, InternalTableDefinition> =
             BtreeRangeIter::new::<RangeFull, &str>(.., Some(root), mem)?;
 
-        // Chain all the other tables to the master table iter
         for entry in iter {
             let definition = entry?.value();
             if let Some((table_root, _)) = definition.get_root() {
-                let table_pages_iter = AllPageNumbersBtreeIter::new(
+                visitor(&mut AllPageNumbersBtreeIter::new(
                     table_root,
                     definition.get_fixed_key_size(),
-                    definition.get_fixed_value_size(),
+                    definition.get_fixed_tree_value_size(),
                     mem,
-                )?;
-                mem.mark_pages_allocated(table_pages_iter)?;
+                )?)?;
 
                 // Multimap tables may have additional subtrees in their values
                 if definition.get_type() == TableType::Multimap {
                     let table_pages_iter = AllPageNumbersBtreeIter::new(
                         table_root,
                         definition.get_fixed_key_size(),
-                        definition.get_fixed_value_size(),
+                        definition.get_fixed_tree_value_size(),
                         mem,
                     )?;
                     for table_page in table_pages_iter {
                         let page = mem.get_page(table_page?)?;
-                        let mut subtree_roots = parse_subtree_roots(
+                        let subtree_roots = parse_subtree_roots(
                             &page,
                             definition.get_fixed_key_size(),
-                            definition.get_fixed_value_size(),
+                            definition.get_fixed_tree_value_size(),
                         );
-                        mem.mark_pages_allocated(subtree_roots.drain(..).map(Ok))?;
+                        drop(page);
+                        for subtree_root in subtree_roots {
+                            visitor(&mut AllPageNumbersBtreeIter::new(
+                                subtree_root,
+                                definition.get_fixed_value_size(),
+                                <()>::fixed_width(),
+                                mem,
+                            )?)?;
+                        }
                     }
                 }
             }
@@ -453,9 +654,489 @@ impl Database {
         Ok(())
     }
 
-    fn do_repair(mem: &mut TransactionalMemory) -> Result {
+    // Rewrites the table tree rooted at `root`, and every table in it, with `rewrite_tree`, and
+    // returns its new root
+    pub(crate) fn rewrite_tables_recursive(
+        root: (PageNumber, Checksum),
+        mem: &TransactionalMemory,
+        rewrite_tree: RewriteTree,
+    ) -> Result<(PageNumber, Checksum)> {
+        rewrite_tree(
+            root,
+            <&str>::fixed_width(),
+            InternalTableDefinition::fixed_width(),
+            mem,
+            &mut |value| {
+                let mut definition = InternalTableDefinition::from_bytes(value);
+                let table_root = if let Some(table_root) = definition.get_root() {
+                    table_root
+                } else {
+                    return Ok(None);
+                };
+                let fixed_key_size = definition.get_fixed_key_size();
+                let fixed_value_size = definition.get_fixed_tree_value_size();
+                let new_root = if definition.get_type() == TableType::Multimap {
+                    // The collection of values for each key may be stored in a subtree
+                    let subtree_key_size = definition.get_fixed_value_size();
+                    rewrite_tree(
+                        table_root,
+                        fixed_key_size,
+                        fixed_value_size,
+                        mem,
+                        &mut |value| {
+                            relocate_subtree(value, |subtree_root| {
+                                rewrite_tree(
+                                    subtree_root,
+                                    subtree_key_size,
+                                    <()>::fixed_width(),
+                                    mem,
+                                    &mut |_| Ok(None),
+                                )
+                            })
+                        },
+                    )?
+                } else {
+                    rewrite_tree(
+                        table_root,
+                        fixed_key_size,
+                        fixed_value_size,
+                        mem,
+                        &mut |_| Ok(None),
+                    )?
+                };
+                if new_root == table_root {
+                    return Ok(None);
+                }
+                definition.set_root(Some(new_root));
+                Ok(Some(InternalTableDefinition::as_bytes(&definition)))
+            },
+        )
+    }
+
+    fn mark_tables_recursive(root: PageNumber, mem: &mut TransactionalMemory) -> Result {
+        // Repair the allocator state
+        let mem: &TransactionalMemory = mem;
+        Self::visit_tables_recursive(root, mem, &mut |pages| mem.mark_pages_allocated(pages))
+    }
+
+    // Returns every page reachable from the given savepoint
+    fn savepoint_pages(
+        savepoint: &Savepoint,
+        mem: &TransactionalMemory,
+    ) -> Result<Vec<PageNumber>> {
+        Self::reachable_pages(
+            [savepoint.get_user_root(), savepoint.get_system_root()],
+            mem,
+        )
+    }
+
+    // Returns every page of the table trees with the given roots, and of their tables
+    fn reachable_pages(
+        roots: [Option<(PageNumber, Checksum)>; 2],
+        mem: &TransactionalMemory,
+    ) -> Result<Vec<PageNumber>> {
+        let mut result = vec![];
+        for (root, _) in roots.into_iter().flatten() {
+            Self::visit_tables_recursive(root, mem, &mut |pages| {
+                for page in pages {
+                    result.push(page?);
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(result)
+    }
+
+    /// Repairs this database from `replica`, a trusted copy of it
+    ///
+    /// The replica must be a physical copy, such as a copy of the file or one kept up to date with
+    /// [`Database::incremental_backup`], not a database into which the same data was inserted.
+    /// Each page reachable from the replica is checked against the checksum stored for it by its
+    /// parent in the replica, from the root down. Only pages of this database which fail the check
+    /// are read from the replica and overwritten, along with any of their descendants which
+    /// differ, so a follower which is mostly intact is repaired without copying the whole replica.
+    ///
+    /// If this database has diverged from the replica, by committing transactions which the
+    /// replica does not have or by falling behind it, it is reset to the replica's latest commit.
+    /// Its persistent savepoints, and those of the replica, are discarded in that case, and any
+    /// [`Savepoint`]s become invalid. Since pages are overwritten in place, an interrupted sync may
+    /// leave this database corrupted, and should be retried.
+    ///
+    /// Returns the number of pages which were copied, or [`Error::ReplicaMismatch`] if the replica
+    /// does not store its pages with the same page size, region size and checksum algorithm
+    pub fn sync_from(&mut self, replica: &Database) -> Result<u64> {
+        if !self.mem.has_same_page_layout(&replica.mem) {
+            return Err(Error::ReplicaMismatch);
+        }
+        let diverged = self.mem.get_data_root() != replica.mem.get_data_root()
+            || self.mem.get_system_root() != replica.mem.get_system_root();
+        let savepoints = if diverged {
+            let txn = self.begin_write_abort_on_drop()?;
+            let savepoints = txn
+                .list_persistent_savepoints()?
+                .map(|id| txn.get_persistent_savepoint(id))
+                .collect::<Result<Vec<_>>>()?;
+            txn.abort()?;
+            savepoints
+        } else {
+            vec![]
+        };
+        // Cached pages may not match what is on disk
+        self.mem.clear_read_cache();
+        self.mem.grow_to_fit(&replica.mem)?;
+
+        let mut copied = 0;
+        for root in [replica.mem.get_data_root(), replica.mem.get_system_root()]
+            .into_iter()
+            .flatten()
+        {
+            copied += Self::sync_tables_recursive(root, &replica.mem, &self.mem)?;
+        }
+        self.mem.flush_pages()?;
+
+        if diverged {
+            self.mem.adopt_commit_of(&replica.mem)?;
+            Self::do_repair(&mut self.mem)?;
+            self.mem.begin_writable()?;
+            let next_transaction_id = self.mem.get_last_committed_transaction_id()?.next();
+            self.next_transaction_id
+                .inner
+                .fetch_max(next_transaction_id.0, Ordering::AcqRel);
+            self.sequences.lock().unwrap().clear();
+
+            let mut tracker = self.transaction_tracker.lock().unwrap();
+            tracker.invalidate_savepoints_after(SavepointId(0), &[]);
+            for savepoint in savepoints.iter() {
+                tracker.deallocate_savepoint(savepoint);
+            }
+            drop(tracker);
+            // The pages of the replica's savepoints were not copied
+            let txn = self.begin_write_abort_on_drop()?;
+            txn.discard_persistent_savepoint_records()?;
+            txn.commit()?;
+        }
+
+        Ok(copied)
+    }
+
+    // Copies the pages of the table tree rooted at `root` in `replica`, and of every table in it,
+    // which differ from those at the same locations in `mem`. Returns the number of pages copied
+    fn sync_tables_recursive(
+        root: (PageNumber, Checksum),
+        replica: &TransactionalMemory,
+        mem: &TransactionalMemory,
+    ) -> Result<u64> {
+        let mut tables = vec![];
+        let mut copied = RawBtree::new(
+            Some(root),
+            <&str>::fixed_width(),
+            InternalTableDefinition::fixed_width(),
+            replica,
+        )
+        .copy_differing_pages(mem, &mut |_, value| {
+            tables.push(InternalTableDefinition::from_bytes(value));
+        })?;
+
+        for definition in tables {
+            // Multimap tables may have additional subtrees in their values
+            let mut subtrees = vec![];
+            copied += RawBtree::new(
+                definition.get_root(),
+                definition.get_fixed_key_size(),
+                definition.get_fixed_tree_value_size(),
+                replica,
+            )
+            .copy_differing_pages(mem, &mut |_, value| {
+                if definition.get_type() == TableType::Multimap {
+                    subtrees.extend(subtree_root(value));
+                }
+            })?;
+            for subtree in subtrees {
+                copied += RawBtree::new(
+                    Some(subtree),
+                    definition.get_fixed_value_size(),
+                    <()>::fixed_width(),
+                    replica,
+                )
+                .copy_differing_pages(mem, &mut |_, _| {})?;
+            }
+        }
+
+        Ok(copied)
+    }
+
+    /// Writes an incremental backup of the database to `writer`
+    ///
+    /// The backup contains every page which changed since the backup marked by `since`, or every
+    /// page in the database if `since` is `None`. It is restored with
+    /// [`Database::apply_incremental`].
+    ///
+    /// Returns the marker for this backup, which should be passed as `since` to the next one. The
+    /// marker is a persistent savepoint, so the pages it references are not freed until it is
+    /// deleted with [`WriteTransaction::delete_persistent_savepoint`]. A marker should be deleted
+    /// once the backup that follows it has been made.
+    ///
+    /// The pages of persistent savepoints, including earlier markers, are not backed up, so
+    /// savepoints must not be restored in a copy of the database
+    pub fn incremental_backup(&self, since: Option<u64>, writer: impl Write) -> Result<u64> {
+        let txn = self.begin_write_abort_on_drop()?;
+        let marker = txn.persistent_savepoint()?;
+        let savepoint = txn.get_persistent_savepoint(marker)?;
+        let previous = since
+            .map(|id| txn.get_persistent_savepoint(id))
+            .transpose()?;
+        txn.commit()?;
+
+        let result = self.write_incremental_backup(&savepoint, previous.as_ref(), writer);
+        if result.is_err() {
+            let txn = self.begin_write_abort_on_drop()?;
+            txn.delete_persistent_savepoint(marker)?;
+            txn.commit()?;
+        }
+
+        result.map(|_| marker)
+    }
+
+    fn write_incremental_backup(
+        &self,
+        savepoint: &Savepoint,
+        previous: Option<&Savepoint>,
+        writer: impl Write,
+    ) -> Result<u64> {
+        // Pages are never modified after they are committed, so any page that was reachable from
+        // the previous backup is already in the copy, unchanged
+        let previous_pages: HashSet<PageNumber> = if let Some(previous) = previous {
+            Self::savepoint_pages(previous, &self.mem)?
+                .into_iter()
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        let mut pages = Self::savepoint_pages(savepoint, &self.mem)?;
+        pages.retain(|page| !previous_pages.contains(page));
+
+        write_incremental_backup(
+            &self.mem,
+            savepoint.get_user_root(),
+            savepoint.get_system_root(),
+            savepoint.get_transaction_id(),
+            pages,
+            writer,
+        )
+    }
+
+    /// Applies an incremental backup, written by [`Database::incremental_backup`], to the copy of
+    /// the database at `path`
+    ///
+    /// The copy must have had every earlier backup in the chain applied, in order, starting from a
+    /// backup made with `since` set to `None`. It must not be open, or be modified between
+    /// backups. If this returns an error, the copy is left as it was after the previous backup.
+    pub fn apply_incremental(path: impl AsRef<Path>, reader: impl Read) -> Result {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        apply_incremental_backup(file, reader)
+    }
+
+    /// List the ids of all persistent savepoints
+    ///
+    /// See [`WriteTransaction::persistent_savepoint`]
+    pub fn list_savepoints(&self) -> Result<Vec<u64>> {
+        let txn = self.begin_write_abort_on_drop()?;
+        let savepoints = txn.list_persistent_savepoints()?.collect();
+        txn.abort()?;
+        Ok(savepoints)
+    }
+
+    /// Delete the persistent savepoint with the given id, allowing the pages which it retains to be
+    /// freed
+    ///
+    /// Returns `true` if the savepoint existed
+    pub fn delete_savepoint(&self, id: u64) -> Result<bool> {
+        let txn = self.begin_write_abort_on_drop()?;
+        let existed = txn.delete_persistent_savepoint(id)?;
+        txn.commit()?;
+        Ok(existed)
+    }
+
+    /// Returns information about the persistent savepoint with the given id, including how much
+    /// space it is preventing from being freed
+    ///
+    /// Returns [`Error::InvalidSavepoint`] if the savepoint does not exist. This walks every table
+    /// of both the savepoint and the latest commit, so may be slow for large databases
+    pub fn savepoint_metadata(&self, id: u64) -> Result<SavepointMetadata> {
+        let txn = self.begin_write_abort_on_drop()?;
+        let savepoint = txn.get_persistent_savepoint(id)?;
+        let created = txn.persistent_savepoint_created(id)?;
+        let live: HashSet<PageNumber> = Self::reachable_pages(
+            [self.mem.get_data_root(), self.mem.get_system_root()],
+            &self.mem,
+        )?
+        .into_iter()
+        .collect();
+        let mut pinned_bytes = 0;
+        for page in Self::savepoint_pages(&savepoint, &self.mem)? {
+            if !live.contains(&page) {
+                let range = self.mem.page_range(page);
+                pinned_bytes += range.end - range.start;
+            }
+        }
+        txn.abort()?;
+
+        Ok(SavepointMetadata {
+            id,
+            created,
+            pinned_bytes,
+        })
+    }
+
+    /// Returns a breakdown of the space used by the database file, to help determine whether it
+    /// would be reduced by [`Database::compact`], or by deleting persistent savepoints
+    ///
+    /// This walks every table of the latest commit and of every persistent savepoint, so may be
+    /// slow for large databases
+    pub fn space_report(&self) -> Result<SpaceReport> {
+        let txn = self.begin_write_abort_on_drop()?;
+        let page_bytes = |pages: &HashSet<PageNumber>| -> u64 {
+            pages
+                .iter()
+                .map(|page| {
+                    let range = self.mem.page_range(*page);
+                    range.end - range.start
+                })
+                .sum()
+        };
+
+        let table_pages: HashSet<PageNumber> =
+            Self::reachable_pages([self.mem.get_data_root(), None], &self.mem)?
+                .into_iter()
+                .collect();
+        let system_pages: HashSet<PageNumber> =
+            Self::reachable_pages([None, self.mem.get_system_root()], &self.mem)?
+                .into_iter()
+                .collect();
+        let mut savepoint_pages = HashSet::new();
+        for id in txn.list_persistent_savepoints()? {
+            let savepoint = txn.get_persistent_savepoint(id)?;
+            for page in Self::savepoint_pages(&savepoint, &self.mem)? {
+                if !table_pages.contains(&page) && !system_pages.contains(&page) {
+                    savepoint_pages.insert(page);
+                }
+            }
+        }
+        let free_pages = self.mem.count_free_pages()?;
+        txn.abort()?;
+
+        Ok(SpaceReport {
+            file_bytes: self.mem.file_len(),
+            table_bytes: page_bytes(&table_pages),
+            system_bytes: page_bytes(&system_pages),
+            savepoint_bytes: page_bytes(&savepoint_pages),
+            free_bytes: free_pages * u64::try_from(self.mem.get_page_size()).unwrap(),
+        })
+    }
+
+    /// Returns how close the database file is to the size set by [`Builder::set_max_size`], or
+    /// `None` if no maximum size was set
+    pub fn pressure(&self) -> Option<Pressure> {
+        self.mem.pressure()
+    }
+
+    /// Returns the clock set by [`Builder::set_clock`]
+    ///
+    /// Callers which pass the current time to the database, such as [`crate::LeaseTable`], can
+    /// read it from here, so that a mock clock controls them too
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Reads the pages of the given tables into the cache, as of the latest commit
+    ///
+    /// This is intended to be called at startup, to avoid slow first accesses. See
+    /// [`ReadOnlyTable::preload`](crate::ReadOnlyTable::preload)
+    ///
+    /// Returns the total number of pages read
+    pub fn preload_tables(&self, tables: &[&str], include_leaves: bool) -> Result<u64> {
+        let txn = self.begin_read()?;
+        let mut pages = 0;
+        for name in tables {
+            let definition = txn
+                .table_tree()
+                .get_table_untyped(name, TableType::Normal)?
+                .ok_or_else(|| Error::TableDoesNotExist(name.to_string()))?;
+            pages += RawBtree::new(
+                definition.get_root(),
+                definition.get_fixed_key_size(),
+                definition.get_fixed_value_size(),
+                &self.mem,
+            )
+            .preload(include_leaves)?;
+        }
+
+        Ok(pages)
+    }
+
+    /// Returns statistics of the page cache
+    pub fn cache_stats(&self) -> CacheStats {
+        self.mem.cache_stats()
+    }
+
+    /// Discards all the pages in the read cache, to release their memory
+    ///
+    /// Pages modified by an in-progress write transaction are not discarded. Pages which are
+    /// still referenced by an open [`AccessGuard`](crate::AccessGuard) remain in memory until it
+    /// is dropped
+    pub fn evict_cache(&self) {
+        self.mem.clear_read_cache();
+    }
+
+    /// Writes a consistent copy of the database, as of the latest commit, to a new file at `path`
+    ///
+    /// Only the pages reachable from the latest commit are copied, at the same offsets, so the copy
+    /// is a valid database with the same page size and checksum algorithm. Writes may continue
+    /// while the copy is made. Any existing file at `path` is overwritten.
+    ///
+    /// Returns the number of pages copied
+    pub fn copy_to(&self, path: impl AsRef<Path>) -> Result<u64> {
+        // The savepoint prevents the pages of the copied commit from being freed until it is
+        // dropped
+        let txn = self.begin_write_abort_on_drop()?;
+        let savepoint = txn.ephemeral_savepoint()?;
+        txn.abort()?;
+
+        let pages = Self::savepoint_pages(&savepoint, &self.mem)?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        write_copy(
+            &self.mem,
+            savepoint.get_user_root(),
+            savepoint.get_system_root(),
+            savepoint.get_transaction_id(),
+            pages,
+            file,
+        )
+    }
+
+    fn do_repair(mem: &mut TransactionalMemory) -> Result<RecoveryReport> {
+        let header_recovery = mem.header_recovery().unwrap_or(HeaderRecovery {
+            primary_corrupted: false,
+            secondary_corrupted: false,
+            swapped_slot: false,
+        });
+        let mut primary_commit_discarded = false;
         if !Self::verify_primary_checksums(mem)? {
             mem.repair_primary_corrupted();
+            primary_commit_discarded = true;
             // We need to invalidate the userspace cache, because walking the tree in verify_primary_checksums() may
             // have poisoned it with pages that just got rolled back by repair_primary_corrupted(), since
             // that rolls back a partially committed transaction.
@@ -467,6 +1148,8 @@ impl Database {
             }
         }
 
+        let recovered_transaction_id = mem.get_last_committed_transaction_id()?;
+        let allocated_before = mem.count_recorded_allocated_pages();
         mem.begin_repair()?;
 
         let data_root = mem.get_data_root();
@@ -480,6 +1163,7 @@ impl Database {
         }
 
         mem.end_repair()?;
+        let allocated_after = mem.count_allocated_pages()?;
 
         // We need to invalidate the userspace cache, because we're about to implicitly free the freed table
         // by storing an empty root during the below commit()
@@ -487,18 +1171,32 @@ impl Database {
 
         // Clear the freed table. We just rebuilt the allocator state by walking all the
         // reachable data pages, which implicitly frees the pages for the freed table
-        let transaction_id = mem.get_last_committed_transaction_id()?.next();
+        let transaction_id = recovered_transaction_id.next();
         mem.commit(data_root, system_root, None, transaction_id, false, true)?;
 
-        Ok(())
+        Ok(RecoveryReport {
+            primary_header_corrupted: header_recovery.primary_corrupted,
+            secondary_header_corrupted: header_recovery.secondary_corrupted,
+            header_slot_swapped: header_recovery.swapped_slot,
+            primary_commit_discarded,
+            recovered_transaction_id: recovered_transaction_id.0,
+            pages_reclaimed: allocated_before.saturating_sub(allocated_after),
+        })
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn new(
         file: File,
         page_size: usize,
         region_size: Option<u64>,
         read_cache_size_bytes: usize,
         write_cache_size_bytes: usize,
+        checksum_algorithm: ChecksumAlgorithm,
+        allocation_strategy: AllocationStrategy,
+        max_transaction_bytes: Option<u64>,
+        quarantine_callback: Option<QuarantineCallback>,
+        size_limit: Option<SizeLimit>,
+        clock: Arc<dyn Clock>,
     ) -> Result<Self> {
         #[cfg(feature = "logging")]
         let file_path = format!("{:?}", &file);
@@ -510,25 +1208,39 @@ impl Database {
             region_size,
             read_cache_size_bytes,
             write_cache_size_bytes,
+            checksum_algorithm,
         )?;
+        mem.set_allocation_strategy(allocation_strategy);
+        if let Some(limit) = size_limit {
+            mem.set_size_limit(limit);
+        }
+        let mut recovery_report = None;
         if mem.needs_repair()? {
             #[cfg(feature = "logging")]
             warn!("Database {:?} not shutdown cleanly. Repairing", &file_path);
-            Self::do_repair(&mut mem)?;
+            let report = Self::do_repair(&mut mem)?;
+            #[cfg(feature = "logging")]
+            info!("Database {:?} repaired: {:?}", &file_path, &report);
+            recovery_report = Some(report);
         }
 
         mem.begin_writable()?;
         let next_transaction_id = mem.get_last_committed_transaction_id()?.next();
 
-        let db = Database {
+        let mut db = Database {
             mem,
             next_transaction_id: AtomicTransactionId::new(next_transaction_id),
             transaction_tracker: Arc::new(Mutex::new(TransactionTracker::new())),
             live_write_transaction: Mutex::new(None),
+            sequences: Mutex::new(HashMap::new()),
+            max_transaction_bytes,
+            recovery_report,
+            key_watches: KeyWatches::new(),
+            clock,
         };
 
         // Restore the tracker state for any persistent savepoints
-        let txn = db.begin_write()?;
+        let txn = db.begin_write_abort_on_drop()?;
         if let Some(next_id) = txn.next_persistent_savepoint_id()? {
             db.transaction_tracker
                 .lock()
@@ -544,6 +1256,19 @@ impl Database {
         }
         txn.abort()?;
 
+        if let Some(callback) = quarantine_callback {
+            let pages = find_corrupted_pages(&db.mem)?;
+            #[cfg(feature = "logging")]
+            if !pages.is_empty() {
+                warn!(
+                    "Database {:?} has {} corrupted pages. Quarantining them",
+                    &file_path,
+                    pages.len()
+                );
+            }
+            db.mem.set_quarantine(Quarantine::new(pages, callback));
+        }
+
         Ok(db)
     }
 
@@ -579,9 +1304,124 @@ impl Database {
     /// write may be in progress at a time. If a write is in progress, this function will block
     /// until it completes.
     pub fn begin_write(&self) -> Result<WriteTransaction> {
+        if matches!(self.mem.quarantine(), Some(quarantine) if !quarantine.is_empty()) {
+            return Err(Corrupted(
+                "Database has quarantined pages, and cannot be written".to_string(),
+            ));
+        }
         WriteTransaction::new(self, self.transaction_tracker.clone())
     }
 
+    /// Returns the pages which were quarantined when the database was opened
+    ///
+    /// See [`Builder::set_quarantine_corrupted_pages`]
+    pub fn quarantined_pages(&self) -> Vec<QuarantinedPage> {
+        self.mem
+            .quarantine()
+            .map(|quarantine| quarantine.pages())
+            .unwrap_or_default()
+    }
+
+    // Begins a write transaction which is aborted if dropped, regardless of the strict_drop
+    // feature. Used internally, where `?` is relied on to abort the transaction on error
+    pub(crate) fn begin_write_abort_on_drop(&self) -> Result<WriteTransaction<'_>> {
+        let mut txn = self.begin_write()?;
+        txn.set_drop_behavior(DropBehavior::Abort);
+        Ok(txn)
+    }
+
+    /// Runs `f` in a write transaction, and commits it
+    ///
+    /// If `f` or the commit fails with an error that the default [`RetryPolicy`] considers
+    /// transient, the transaction is aborted and retried, so `f` may be called multiple times.
+    /// See [`Self::run_write_with_policy`]
+    pub fn run_write<R>(&self, f: impl FnMut(&WriteTransaction) -> Result<R>) -> Result<R> {
+        self.run_write_with_policy(&RetryPolicy::default(), f)
+    }
+
+    /// Runs `f` in a write transaction, and commits it, retrying as configured by `policy`
+    ///
+    /// Returns the last error if every attempt fails, or the first error which is not transient
+    pub fn run_write_with_policy<R>(
+        &self,
+        policy: &RetryPolicy,
+        mut f: impl FnMut(&WriteTransaction) -> Result<R>,
+    ) -> Result<R> {
+        let mut backoff = policy.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            // The transaction is aborted when dropped, if f() fails
+            let result = self.begin_write_abort_on_drop().and_then(|txn| {
+                let value = f(&txn)?;
+                txn.commit()?;
+                Ok(value)
+            });
+            match result {
+                Err(err) if attempt < policy.max_attempts && (policy.is_transient)(&err) => {
+                    #[cfg(feature = "logging")]
+                    warn!("Retrying write transaction after error: {}", err);
+                    self.clock.sleep(backoff);
+                    backoff = min(backoff * 2, policy.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Inserts `entries` into the table, splitting them across as many write transactions as
+    /// needed to stay within the limit set by [`Builder::set_max_transaction_bytes`]
+    ///
+    /// Each transaction is committed before the next begins, so if an error is returned, the
+    /// entries inserted by earlier transactions remain in the table. Returns the number of
+    /// transactions committed
+    pub fn insert_split<
+        'a,
+        K: RedbKey + 'static,
+        V: RedbValue + 'static,
+        KB: Borrow<K::SelfType<'a>>,
+        VB: Borrow<V::SelfType<'a>>,
+    >(
+        &self,
+        definition: TableDefinition<K, V>,
+        entries: impl IntoIterator<Item = (KB, VB)>,
+    ) -> Result<u64> {
+        let mut entries = entries.into_iter();
+        // An entry which was rejected by a full transaction, to be retried in the next one
+        let mut pending = None;
+        let mut transactions = 0;
+        loop {
+            let txn = self.begin_write_abort_on_drop()?;
+            let mut finished = true;
+            {
+                let mut table = txn.open_table(definition)?;
+                let mut inserted = 0;
+                while let Some((key, value)) = pending.take().or_else(|| entries.next()) {
+                    match table.insert(key.borrow(), value.borrow()) {
+                        Ok(_) => {
+                            inserted += 1;
+                        }
+                        // Retrying in an empty transaction could never succeed
+                        Err(Error::TransactionTooLarge(_)) if inserted > 0 => {
+                            pending = Some((key, value));
+                            finished = false;
+                            break;
+                        }
+                        Err(err) => {
+                            return Err(err);
+                        }
+                    }
+                }
+            }
+            txn.commit()?;
+            transactions += 1;
+
+            if finished {
+                return Ok(transactions);
+            }
+        }
+    }
+
     /// Begins a read transaction
     ///
     /// Captures a snapshot of the database, so that only data committed before calling this method
@@ -599,6 +1439,108 @@ impl Database {
             id,
         ))
     }
+
+    /// Watches `key` in the table `definition`, so that changes made to it by later commits can be
+    /// awaited with [`KeyWatch::changed`]
+    ///
+    /// The key is checked after every commit, and after every [`WriteTransaction::flush`], so a
+    /// large number of watches adds to the cost of committing
+    pub fn watch_key<'a, K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+        key: impl Borrow<K::SelfType<'a>>,
+    ) -> Result<KeyWatch> {
+        self.key_watches.watch::<K, V>(
+            definition.name(),
+            K::as_bytes(key.borrow()).as_ref(),
+            || self.begin_read(),
+        )
+    }
+}
+
+fn is_transient_io_error(error: &Error) -> bool {
+    if let Error::Io(err) = error {
+        matches!(
+            err.kind(),
+            ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+        )
+    } else {
+        false
+    }
+}
+
+/// Configures how [`Database::run_write_with_policy`] retries a failed write transaction
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    is_transient: fn(&Error) -> bool,
+}
+
+impl RetryPolicy {
+    /// Construct a new [RetryPolicy] with sensible defaults.
+    ///
+    /// ## Defaults
+    ///
+    /// - `max_attempts`: 5
+    /// - `initial_backoff`: 1ms, doubling after each attempt up to `max_backoff`: 100ms
+    /// - transient errors: I/O errors of kind `Interrupted`, `WouldBlock` or `TimedOut`
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(100),
+            is_transient: is_transient_io_error,
+        }
+    }
+
+    /// Set the maximum number of times the transaction is attempted, including the first
+    ///
+    /// ## Invariant
+    ///
+    /// `attempts` must be greater than zero
+    pub fn set_max_attempts(&mut self, attempts: u32) -> &mut Self {
+        assert!(attempts > 0);
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Set the delay before the first retry. The delay doubles after each retry, up to `max`
+    pub fn set_backoff(&mut self, initial: Duration, max: Duration) -> &mut Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Set the function which decides whether an error is transient, and so should be retried
+    pub fn set_transient_errors(&mut self, is_transient: fn(&Error) -> bool) -> &mut Self {
+        self.is_transient = is_transient;
+        self
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An iOS Data Protection class, which controls when the database file can be accessed while the
+/// device is locked
+///
+/// See [`Builder::set_file_protection_class`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum FileProtectionClass {
+    /// The file is inaccessible while the device is locked
+    Complete,
+    /// The file can only be opened while the device is unlocked, but remains accessible once open
+    CompleteUnlessOpen,
+    /// The file is inaccessible until the device is first unlocked after booting
+    CompleteUntilFirstUserAuthentication,
+    /// The file is always accessible
+    NoProtection,
 }
 
 /// Configuration builder of a redb [Database].
@@ -607,6 +1549,15 @@ pub struct Builder {
     region_size: Option<u64>,
     read_cache_size_bytes: usize,
     write_cache_size_bytes: usize,
+    direct_io: bool,
+    file_protection_class: Option<FileProtectionClass>,
+    checksum_algorithm: ChecksumAlgorithm,
+    allocation_strategy: AllocationStrategy,
+    max_transaction_bytes: Option<u64>,
+    quarantine_callback: Option<QuarantineCallback>,
+    max_size: Option<u64>,
+    pressure_callback: Option<(u8, PressureCallback)>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Builder {
@@ -627,6 +1578,15 @@ impl Builder {
             read_cache_size_bytes: 0,
             // TODO: Default should probably take into account the total system memory
             write_cache_size_bytes: 0,
+            direct_io: false,
+            file_protection_class: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            allocation_strategy: AllocationStrategy::default(),
+            max_transaction_bytes: None,
+            quarantine_callback: None,
+            max_size: None,
+            pressure_callback: None,
+            clock: Arc::new(SystemClock),
         };
 
         result.set_cache_size(1024 * 1024 * 1024);
@@ -648,6 +1608,12 @@ impl Builder {
     }
 
     /// Set the amount of memory (in bytes) used for caching data
+    ///
+    /// A tenth of the cache buffers the pages modified by the current write transaction. When a
+    /// transaction modifies more than that, the oldest modified pages are written to their final
+    /// locations in the file before the transaction commits. This is safe because modified pages
+    /// are always written to newly allocated space, which is released again if the transaction
+    /// aborts. A transaction's size is therefore limited by the file, rather than by memory
     pub fn set_cache_size(&mut self, bytes: usize) -> &mut Self {
         // TODO: allow dynamic expansion of the read/write cache
         self.read_cache_size_bytes = bytes * 9 / 10;
@@ -655,6 +1621,148 @@ impl Builder {
         self
     }
 
+    /// Bypass the operating system's page cache when reading and writing the database file
+    ///
+    /// redb caches pages itself, so this avoids holding a second copy of them in the page cache,
+    /// which would otherwise be evicted from other processes. On Linux the file is opened with
+    /// `O_DIRECT`, and on macOS and iOS with `F_NOCACHE`. This setting is ignored on other
+    /// platforms.
+    ///
+    /// Opening the database will fail if the filesystem does not support unbuffered I/O
+    ///
+    /// ## Defaults
+    ///
+    /// Disabled
+    pub fn set_direct_io(&mut self, enabled: bool) -> &mut Self {
+        self.direct_io = enabled;
+        self
+    }
+
+    /// Set the iOS Data Protection class of the database file
+    ///
+    /// The class is applied each time the database is created or opened, so that it does not need
+    /// to be set on the file separately. This setting is ignored on other platforms.
+    ///
+    /// ## Defaults
+    ///
+    /// The file keeps its existing class, or for a new file, the default class of the app
+    pub fn set_file_protection_class(&mut self, class: FileProtectionClass) -> &mut Self {
+        self.file_protection_class = Some(class);
+        self
+    }
+
+    /// Set the algorithm used to checksum pages
+    ///
+    /// This only applies when a new database is created. Existing databases continue to use the
+    /// algorithm they were created with
+    ///
+    /// ## Defaults
+    ///
+    /// [`ChecksumAlgorithm::Xxh3`]
+    pub fn set_checksum_algorithm(&mut self, algorithm: ChecksumAlgorithm) -> &mut Self {
+        self.checksum_algorithm = algorithm;
+        self
+    }
+
+    /// Set the strategy used to choose which free page to allocate
+    ///
+    /// This only affects where new pages are placed in the file, so it may be changed each time
+    /// the database is opened
+    ///
+    /// ## Defaults
+    ///
+    /// [`AllocationStrategy::Any`]
+    pub fn set_allocation_strategy(&mut self, strategy: AllocationStrategy) -> &mut Self {
+        self.allocation_strategy = strategy;
+        self
+    }
+
+    /// Limit the number of bytes of pages which a write transaction may modify
+    ///
+    /// Once a transaction has modified more than `bytes`, further modifications to its tables
+    /// return [`Error::TransactionTooLarge`] without making any change. The transaction can still
+    /// be committed. [`Database::insert_split`] uses this to split a large import across several
+    /// transactions
+    ///
+    /// ## Defaults
+    ///
+    /// Unlimited
+    pub fn set_max_transaction_bytes(&mut self, bytes: u64) -> &mut Self {
+        self.max_transaction_bytes = Some(bytes);
+        self
+    }
+
+    /// Quarantine pages which fail checksum verification, so that the rest of a partially
+    /// corrupted database remains readable
+    ///
+    /// When the database is opened, the checksum of every page of every table is verified. Pages
+    /// which fail are quarantined, along with everything stored below them: iterators skip their
+    /// entries, calling `callback` each time a quarantined page is skipped, and looking up a key
+    /// stored under one returns [`Error::Corrupted`]. The quarantined pages are listed by
+    /// [`Database::quarantined_pages`]. While any page is quarantined, [`Database::begin_write`]
+    /// returns [`Error::Corrupted`], so the readable data should be copied to a new database.
+    ///
+    /// Verification reads the whole database, so opening takes time proportional to its size.
+    /// A database which was not shutdown cleanly is repaired first, as usual, and fails to open if
+    /// the repair fails.
+    ///
+    /// ## Defaults
+    ///
+    /// Disabled. Pages are not verified when they are read
+    pub fn set_quarantine_corrupted_pages(
+        &mut self,
+        callback: impl Fn(&QuarantinedPage) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.quarantine_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Set the maximum size of the database file
+    ///
+    /// Allocating a page which would grow the file beyond `bytes` returns
+    /// [`Error::DatabaseFull`], after which the write transaction should be aborted. Pages freed
+    /// by earlier transactions can still be reused. [`Database::pressure`] reports how close the
+    /// file is to this size, so that writers can slow down before it is reached. The limit is
+    /// not applied to the space allocated when a new database is created
+    ///
+    /// ## Defaults
+    ///
+    /// Unlimited
+    pub fn set_max_size(&mut self, bytes: u64) -> &mut Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Call `callback` each time the database file grows to at least `percent` percent of the
+    /// size set by [`Self::set_max_size`]
+    ///
+    /// The callback is called by the thread performing the write which grew the file, so it
+    /// should return quickly, and must not begin a write transaction. It is not called if no
+    /// maximum size is set
+    ///
+    /// ## Defaults
+    ///
+    /// No callback
+    pub fn set_pressure_callback(
+        &mut self,
+        percent: u8,
+        callback: impl Fn(&Pressure) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.pressure_callback = Some((percent, Arc::new(callback)));
+        self
+    }
+
+    /// Set the clock used to timestamp persistent savepoints, and to wait between the attempts of
+    /// [`Database::run_write`]
+    ///
+    /// ## Defaults
+    ///
+    /// [`SystemClock`]
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) -> &mut Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
     #[cfg(test)]
     fn set_region_size(&mut self, size: u64) -> &mut Self {
         assert!(size.is_power_of_two());
@@ -662,24 +1770,54 @@ impl Builder {
         self
     }
 
+    fn size_limit(&self) -> Option<SizeLimit> {
+        let max_size = self.max_size?;
+        Some(SizeLimit::new(max_size, self.pressure_callback.clone()))
+    }
+
+    // Applies the platform specific file options
+    fn configure_file(&self, file: &File) -> Result {
+        if self.direct_io {
+            enable_direct_io(file)?;
+        }
+        if let Some(class) = self.file_protection_class {
+            set_file_protection_class(file, class)?;
+        }
+        Ok(())
+    }
+
     /// Opens the specified file as a redb database.
     /// * if the file does not exist, or is an empty file, a new database will be initialized in it
     /// * if the file is a valid redb database, it will be opened
     /// * otherwise this function will return an error
     pub fn create(&self, path: impl AsRef<Path>) -> Result<Database> {
+        let created = !path.as_ref().exists();
         let file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(path)?;
+            .open(path.as_ref())?;
+        self.configure_file(&file)?;
 
-        Database::new(
+        let db = Database::new(
             file,
             self.page_size,
             self.region_size,
             self.read_cache_size_bytes,
             self.write_cache_size_bytes,
-        )
+            self.checksum_algorithm,
+            self.allocation_strategy,
+            self.max_transaction_bytes,
+            self.quarantine_callback.clone(),
+            self.size_limit(),
+            self.clock.clone(),
+        )?;
+        // The new directory entry is only durable once the parent directory has been synced
+        if created {
+            sync_parent_dir(path.as_ref())?;
+        }
+
+        Ok(db)
     }
 
     /// Opens an existing redb database.
@@ -688,12 +1826,19 @@ impl Builder {
             Err(Error::Io(ErrorKind::NotFound.into()))
         } else if File::open(path.as_ref())?.metadata()?.len() > 0 {
             let file = OpenOptions::new().read(true).write(true).open(path)?;
+            self.configure_file(&file)?;
             Database::new(
                 file,
                 self.page_size,
                 None,
                 self.read_cache_size_bytes,
                 self.write_cache_size_bytes,
+                self.checksum_algorithm,
+                self.allocation_strategy,
+                self.max_transaction_bytes,
+                self.quarantine_callback.clone(),
+                self.size_limit(),
+                self.clock.clone(),
             )
         } else {
             Err(Error::Io(io::Error::from(ErrorKind::InvalidData)))
@@ -701,6 +1846,79 @@ impl Builder {
     }
 }
 
+#[cfg(all(target_os = "linux", not(force_safe)))]
+fn enable_direct_io(file: &File) -> Result {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_DIRECT) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    Ok(())
+}
+
+#[cfg(all(any(target_os = "macos", target_os = "ios"), not(force_safe)))]
+fn enable_direct_io(file: &File) -> Result {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    Ok(())
+}
+
+#[cfg(any(
+    not(any(target_os = "linux", target_os = "macos", target_os = "ios")),
+    force_safe
+))]
+fn enable_direct_io(_file: &File) -> Result {
+    Ok(())
+}
+
+#[cfg(target_os = "ios")]
+fn set_file_protection_class(file: &File, class: FileProtectionClass) -> Result {
+    use std::os::unix::io::AsRawFd;
+
+    // From <sys/fcntl.h>
+    const F_SETPROTECTIONCLASS: libc::c_int = 64;
+    let class: libc::c_int = match class {
+        FileProtectionClass::Complete => 1,
+        FileProtectionClass::CompleteUnlessOpen => 2,
+        FileProtectionClass::CompleteUntilFirstUserAuthentication => 3,
+        FileProtectionClass::NoProtection => 4,
+    };
+    if unsafe { libc::fcntl(file.as_raw_fd(), F_SETPROTECTIONCLASS, class) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "ios"))]
+fn set_file_protection_class(_file: &File, _class: FileProtectionClass) -> Result {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> Result {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()?;
+
+    Ok(())
+}
+
+// Directory entries can't be synced on other platforms
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> Result {
+    Ok(())
+}
+
 // This just makes it easier to throw `dbg` etc statements on `Result<Database>`
 impl std::fmt::Debug for Database {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -1,5 +1,5 @@
 use crate::tree_store::{FILE_FORMAT_VERSION, MAX_VALUE_LENGTH};
-use crate::TypeName;
+use crate::{BlobHash, TypeName};
 use std::fmt::{Display, Formatter};
 use std::sync::PoisonError;
 use std::{io, panic};
@@ -40,6 +40,17 @@ pub enum Error {
     },
     /// Table name does not match any table in database
     TableDoesNotExist(String),
+    /// Different content was stored in a [`crate::BlobStore`] with the same hash
+    BlobHashCollision(BlobHash),
+    /// The replica passed to [`crate::Database::sync_from`] does not store its pages with the same
+    /// page size, region size and checksum algorithm
+    ReplicaMismatch,
+    /// The write transaction has modified more than the limit set by
+    /// [`crate::Builder::set_max_transaction_bytes`]
+    TransactionTooLarge(u64),
+    /// The database file would have to grow beyond the size set by
+    /// [`crate::Builder::set_max_size`]. The write transaction should be aborted
+    DatabaseFull(u64),
     // Tables cannot be opened for writing multiple times, since they could retrieve immutable &
     // mutable references to the same dirty pages, or multiple mutable references via insert_reserve()
     TableAlreadyOpen(String, &'static panic::Location<'static>),
@@ -109,6 +120,27 @@ impl Display for Error {
             Error::TableDoesNotExist(table) => {
                 write!(f, "Table '{table}' does not exist")
             }
+            Error::BlobHashCollision(hash) => {
+                write!(f, "Blob hash collision: {hash}")
+            }
+            Error::ReplicaMismatch => {
+                write!(
+                    f,
+                    "Replica does not have the same page layout as this database"
+                )
+            }
+            Error::TransactionTooLarge(limit) => {
+                write!(
+                    f,
+                    "Write transaction has modified more than the limit of {limit} bytes"
+                )
+            }
+            Error::DatabaseFull(limit) => {
+                write!(
+                    f,
+                    "Database file would exceed the maximum size of {limit} bytes"
+                )
+            }
             Error::TableAlreadyOpen(name, location) => {
                 write!(f, "Table '{name}' already opened at: {location}")
             }
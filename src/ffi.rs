@@ -0,0 +1,449 @@
+//! C API, for embedding redb in applications written in other languages
+//!
+//! Only tables with `&[u8]` keys and values can be accessed. Databases, transactions and
+//! iterators are returned as pointers to opaque types, which must be released with the matching
+//! `free`, `commit` or `abort` function. Every type is either opaque or `#[repr(C)]`, so a header
+//! can be generated with cbindgen.
+//!
+//! Functions which can fail return [`REDB_OK`] on success, [`REDB_NOT_FOUND`] if a key or table
+//! does not exist, or [`REDB_ERROR`], and functions which return a pointer return null on failure.
+//! The message of the last error on the calling thread is returned by [`redb_last_error`].
+//!
+//! A database must not be closed while any of its transactions are open, and a read transaction
+//! must not be freed while any of its iterators exist. Handles may be moved between threads, but
+//! must not be used by more than one thread at a time.
+
+use crate::{
+    Database, Error, ReadOnlyTable, ReadTransaction, ReadableTable, TableDefinition,
+    WriteTransaction,
+};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::ops::Bound;
+use std::ptr;
+use std::slice;
+
+/// The operation succeeded
+pub const REDB_OK: i32 = 0;
+/// The key or table does not exist
+pub const REDB_NOT_FOUND: i32 = 1;
+/// The operation failed. The error is returned by [`redb_last_error`]
+pub const REDB_ERROR: i32 = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(err: impl ToString) {
+    // Interior nul bytes can't be represented, so the message is truncated at the first one
+    let mut message = err.to_string().into_bytes();
+    if let Some(nul) = message.iter().position(|&b| b == 0) {
+        message.truncate(nul);
+    }
+    let message = CString::new(message).unwrap();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+// Converts a result into a status code, recording the error if there was one
+fn status(result: Result<bool, Error>) -> i32 {
+    match result {
+        Ok(true) => REDB_OK,
+        Ok(false) => REDB_NOT_FOUND,
+        Err(err) => {
+            set_last_error(err);
+            REDB_ERROR
+        }
+    }
+}
+
+// Converts a result into a pointer, which is null if there was an error
+fn into_ptr<T>(result: Result<T, Error>) -> *mut T {
+    match result {
+        Ok(value) => Box::into_raw(Box::new(value)),
+        Err(err) => {
+            set_last_error(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+unsafe fn str<'a>(s: *const c_char) -> Result<&'a str, Error> {
+    CStr::from_ptr(s).to_str().map_err(|_| {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "string is not valid UTF-8",
+        ))
+    })
+}
+
+fn definition(name: &str) -> TableDefinition<'_, &'static [u8], &'static [u8]> {
+    TableDefinition::new(name)
+}
+
+/// A byte buffer owned by the caller, which must be freed with [`redb_buffer_free`]
+#[repr(C)]
+pub struct RedbBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl RedbBuffer {
+    fn new(data: &[u8]) -> Self {
+        let len = data.len();
+        let data = Box::into_raw(data.to_vec().into_boxed_slice());
+        Self {
+            data: data.cast(),
+            len,
+        }
+    }
+}
+
+/// An open database
+pub struct RedbDatabase(Database);
+
+/// A write transaction
+pub struct RedbWriteTransaction(WriteTransaction<'static>);
+
+/// A read transaction
+pub struct RedbReadTransaction(ReadTransaction<'static>);
+
+/// An iterator over a range of a table in a read transaction
+pub struct RedbIterator {
+    // None if the table does not exist
+    table: Option<ReadOnlyTable<'static, &'static [u8], &'static [u8]>>,
+    // Start of the remaining range, which moves past each entry as it is returned
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+/// Returns the message of the last error which occurred on this thread, or null if there was
+/// none
+///
+/// The message is valid until the next call on this thread which fails
+#[no_mangle]
+pub extern "C" fn redb_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Frees a buffer returned by this library
+///
+/// # Safety
+///
+/// `buffer` must have been returned by this library, and not already freed
+#[no_mangle]
+pub unsafe extern "C" fn redb_buffer_free(buffer: RedbBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+}
+
+/// Creates a new database at `path`, or opens it if it already exists
+///
+/// # Safety
+///
+/// `path` must be a nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn redb_database_create(path: *const c_char) -> *mut RedbDatabase {
+    into_ptr(str(path).and_then(Database::create).map(RedbDatabase))
+}
+
+/// Opens an existing database at `path`
+///
+/// # Safety
+///
+/// `path` must be a nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn redb_database_open(path: *const c_char) -> *mut RedbDatabase {
+    into_ptr(str(path).and_then(Database::open).map(RedbDatabase))
+}
+
+/// Closes a database
+///
+/// # Safety
+///
+/// `db` must have been returned by [`redb_database_create`] or [`redb_database_open`], and have no
+/// open transactions
+#[no_mangle]
+pub unsafe extern "C" fn redb_database_close(db: *mut RedbDatabase) {
+    drop(Box::from_raw(db));
+}
+
+/// Begins a write transaction, blocking until any other write transaction completes
+///
+/// The transaction must be finished with [`redb_commit`] or [`redb_abort`]
+///
+/// # Safety
+///
+/// `db` must be an open database
+#[no_mangle]
+pub unsafe extern "C" fn redb_begin_write(db: *mut RedbDatabase) -> *mut RedbWriteTransaction {
+    // The caller guarantees that the database outlives the transaction
+    let db: &'static Database = &(*db).0;
+    into_ptr(db.begin_write_abort_on_drop().map(RedbWriteTransaction))
+}
+
+/// Inserts `value` for `key` in `table`, creating the table if it does not exist
+///
+/// # Safety
+///
+/// `txn` must be an open write transaction, `table` a nul-terminated string, and `key` and
+/// `value` must point to `key_len` and `value_len` bytes
+#[no_mangle]
+pub unsafe extern "C" fn redb_insert(
+    txn: *mut RedbWriteTransaction,
+    table: *const c_char,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) -> i32 {
+    let txn = &(*txn).0;
+    status((|| {
+        let mut table = txn.open_table(definition(str(table)?))?;
+        table.insert(bytes(key, key_len), bytes(value, value_len))?;
+        Ok(true)
+    })())
+}
+
+/// Removes `key` from `table`
+///
+/// Returns [`REDB_NOT_FOUND`] if the key did not exist
+///
+/// # Safety
+///
+/// `txn` must be an open write transaction, `table` a nul-terminated string, and `key` must point
+/// to `key_len` bytes
+#[no_mangle]
+pub unsafe extern "C" fn redb_remove(
+    txn: *mut RedbWriteTransaction,
+    table: *const c_char,
+    key: *const u8,
+    key_len: usize,
+) -> i32 {
+    let txn = &(*txn).0;
+    status((|| {
+        let mut table = txn.open_table(definition(str(table)?))?;
+        let removed = table.remove(bytes(key, key_len))?.is_some();
+        Ok(removed)
+    })())
+}
+
+/// Reads the value for `key` in `table` into `value`, including changes made by the transaction
+///
+/// Returns [`REDB_NOT_FOUND`] if the key does not exist. The value must be freed with
+/// [`redb_buffer_free`]
+///
+/// # Safety
+///
+/// `txn` must be an open write transaction, `table` a nul-terminated string, `key` must point to
+/// `key_len` bytes, and `value` must be valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn redb_write_get(
+    txn: *mut RedbWriteTransaction,
+    table: *const c_char,
+    key: *const u8,
+    key_len: usize,
+    value: *mut RedbBuffer,
+) -> i32 {
+    let txn = &(*txn).0;
+    status((|| {
+        let table = txn.open_table(definition(str(table)?))?;
+        let found = table.get(bytes(key, key_len))?;
+        if let Some(found) = &found {
+            value.write(RedbBuffer::new(found.value()));
+        }
+        Ok(found.is_some())
+    })())
+}
+
+/// Commits and frees a write transaction
+///
+/// The transaction is freed even if the commit fails
+///
+/// # Safety
+///
+/// `txn` must be an open write transaction
+#[no_mangle]
+pub unsafe extern "C" fn redb_commit(txn: *mut RedbWriteTransaction) -> i32 {
+    status(Box::from_raw(txn).0.commit().map(|_| true))
+}
+
+/// Aborts and frees a write transaction
+///
+/// # Safety
+///
+/// `txn` must be an open write transaction
+#[no_mangle]
+pub unsafe extern "C" fn redb_abort(txn: *mut RedbWriteTransaction) -> i32 {
+    status(Box::from_raw(txn).0.abort().map(|_| true))
+}
+
+/// Begins a read transaction, which must be freed with [`redb_read_transaction_free`]
+///
+/// # Safety
+///
+/// `db` must be an open database
+#[no_mangle]
+pub unsafe extern "C" fn redb_begin_read(db: *mut RedbDatabase) -> *mut RedbReadTransaction {
+    // The caller guarantees that the database outlives the transaction
+    let db: &'static Database = &(*db).0;
+    into_ptr(db.begin_read().map(RedbReadTransaction))
+}
+
+/// Reads the value for `key` in `table` into `value`
+///
+/// Returns [`REDB_NOT_FOUND`] if the key or the table does not exist. The value must be freed
+/// with [`redb_buffer_free`]
+///
+/// # Safety
+///
+/// `txn` must be an open read transaction, `table` a nul-terminated string, `key` must point to
+/// `key_len` bytes, and `value` must be valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn redb_read_get(
+    txn: *mut RedbReadTransaction,
+    table: *const c_char,
+    key: *const u8,
+    key_len: usize,
+    value: *mut RedbBuffer,
+) -> i32 {
+    let txn = &(*txn).0;
+    status((|| {
+        let table = match txn.open_table(definition(str(table)?)) {
+            Ok(table) => table,
+            Err(Error::TableDoesNotExist(_)) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        let found = table.get(bytes(key, key_len))?;
+        if let Some(found) = &found {
+            value.write(RedbBuffer::new(found.value()));
+        }
+        Ok(found.is_some())
+    })())
+}
+
+/// Frees a read transaction
+///
+/// # Safety
+///
+/// `txn` must be an open read transaction, with no iterators
+#[no_mangle]
+pub unsafe extern "C" fn redb_read_transaction_free(txn: *mut RedbReadTransaction) {
+    drop(Box::from_raw(txn));
+}
+
+/// Returns an iterator over the entries of `table` in key order, starting at `start` and ending
+/// before `end`
+///
+/// Either bound may be null, to leave that end of the range unbounded. If the table does not exist,
+/// the iterator is empty. The iterator must be freed with [`redb_iterator_free`]
+///
+/// # Safety
+///
+/// `txn` must be an open read transaction, `table` a nul-terminated string, and `start` and `end`
+/// must each be null or point to `start_len` and `end_len` bytes
+#[no_mangle]
+pub unsafe extern "C" fn redb_iter(
+    txn: *mut RedbReadTransaction,
+    table: *const c_char,
+    start: *const u8,
+    start_len: usize,
+    end: *const u8,
+    end_len: usize,
+) -> *mut RedbIterator {
+    // The caller guarantees that the transaction outlives the iterator
+    let txn: &'static ReadTransaction = &(*txn).0;
+    into_ptr((|| {
+        let name = str(table)?;
+        let table = match txn.open_table(definition(name)) {
+            Ok(table) => Some(table),
+            Err(Error::TableDoesNotExist(_)) => None,
+            Err(err) => return Err(err),
+        };
+        Ok(RedbIterator {
+            table,
+            start: if start.is_null() {
+                Bound::Unbounded
+            } else {
+                Bound::Included(bytes(start, start_len).to_vec())
+            },
+            end: if end.is_null() {
+                Bound::Unbounded
+            } else {
+                Bound::Excluded(bytes(end, end_len).to_vec())
+            },
+        })
+    })())
+}
+
+/// Reads the next entry of an iterator into `key` and `value`
+///
+/// Returns [`REDB_NOT_FOUND`] once the iterator is exhausted. The key and value must be freed
+/// with [`redb_buffer_free`]
+///
+/// # Safety
+///
+/// `iter` must have been returned by [`redb_iter`], and `key` and `value` must be valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn redb_iterator_next(
+    iter: *mut RedbIterator,
+    key: *mut RedbBuffer,
+    value: *mut RedbBuffer,
+) -> i32 {
+    let iter = &mut *iter;
+    status((|| {
+        let table = match &iter.table {
+            Some(table) => table,
+            None => return Ok(false),
+        };
+        let start = match &iter.start {
+            Bound::Included(start) => Bound::Included(start.as_slice()),
+            Bound::Excluded(start) => Bound::Excluded(start.as_slice()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let end = match &iter.end {
+            Bound::Excluded(end) => Bound::Excluded(end.as_slice()),
+            _ => Bound::Unbounded,
+        };
+        // Each call starts a new range after the previous entry, so that the iterator doesn't
+        // need to borrow the table
+        let next = table.range::<&[u8]>((start, end))?.next();
+        match next {
+            Some(entry) => {
+                let (found_key, found_value) = entry?;
+                key.write(RedbBuffer::new(found_key.value()));
+                value.write(RedbBuffer::new(found_value.value()));
+                let next_start = Bound::Excluded(found_key.value().to_vec());
+                drop((found_key, found_value));
+                iter.start = next_start;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    })())
+}
+
+/// Frees an iterator
+///
+/// # Safety
+///
+/// `iter` must have been returned by [`redb_iter`], and not already freed
+#[no_mangle]
+pub unsafe extern "C" fn redb_iterator_free(iter: *mut RedbIterator) {
+    drop(Box::from_raw(iter));
+}
@@ -0,0 +1,77 @@
+use crate::tree_store::{AllPageNumbersBtreeIter, PageNumber, TransactionalMemory};
+use crate::Result;
+
+/// How the pages of a table are laid out in the database file
+///
+/// Returned by [`crate::Table::fragmentation_report`]. A table whose pages are scattered across
+/// the file requires more seeks to scan, and can be rewritten with
+/// [`crate::WriteTransaction::defragment_table`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FragmentationReport {
+    pages: u64,
+    bytes: u64,
+    span_bytes: u64,
+    discontinuities: u64,
+}
+
+impl FragmentationReport {
+    /// Number of pages in the table
+    pub fn pages(&self) -> u64 {
+        self.pages
+    }
+
+    /// Total size of the table's pages, in bytes
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Number of bytes of the file between the start of the table's first page and the end of its
+    /// last page. Equal to [`Self::bytes`] if the table is stored contiguously
+    pub fn span_bytes(&self) -> u64 {
+        self.span_bytes
+    }
+
+    /// Number of pages which, visiting the table in key order, do not immediately follow the
+    /// previous page in the file. Zero if the table is stored contiguously and in order
+    pub fn discontinuities(&self) -> u64 {
+        self.discontinuities
+    }
+}
+
+pub(crate) fn fragmentation_report(
+    root: Option<PageNumber>,
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+    mem: &TransactionalMemory,
+) -> Result<FragmentationReport> {
+    let mut report = FragmentationReport {
+        pages: 0,
+        bytes: 0,
+        span_bytes: 0,
+        discontinuities: 0,
+    };
+    let root = if let Some(root) = root {
+        root
+    } else {
+        return Ok(report);
+    };
+
+    let mut start = u64::MAX;
+    let mut end = 0;
+    let mut previous_end = None;
+    // Pages are visited depth first, so leaves are visited in key order, each after its parent
+    for page in AllPageNumbersBtreeIter::new(root, fixed_key_size, fixed_value_size, mem)? {
+        let range = mem.page_range(page?);
+        report.pages += 1;
+        report.bytes += range.end - range.start;
+        if matches!(previous_end, Some(x) if x != range.start) {
+            report.discontinuities += 1;
+        }
+        previous_end = Some(range.end);
+        start = start.min(range.start);
+        end = end.max(range.end);
+    }
+    report.span_bytes = end - start;
+
+    Ok(report)
+}
@@ -0,0 +1,54 @@
+//! Entry points for fuzz targets which exercise the page parsers directly. Only available when
+//! built by cargo-fuzz
+use crate::tree_store::{fuzz_header_roundtrip, LeafAccessor, RawLeafBuilder};
+
+/// Parses arbitrary bytes as a database header, and checks that the parsed header can be
+/// serialized and parsed again losslessly
+pub fn header(data: &[u8]) {
+    fuzz_header_roundtrip(data);
+}
+
+/// Builds a leaf page from `pairs`, and checks that it parses back to the same pairs. Pairs whose
+/// lengths don't match the fixed sizes are skipped
+pub fn leaf(
+    pairs: &[(Vec<u8>, Vec<u8>)],
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+) {
+    let pairs: Vec<&(Vec<u8>, Vec<u8>)> = pairs
+        .iter()
+        .filter(|(key, value)| {
+            fixed_key_size.map_or(true, |x| key.len() == x)
+                && fixed_value_size.map_or(true, |x| value.len() == x)
+        })
+        .take(u16::MAX.into())
+        .collect();
+    if pairs.is_empty() {
+        return;
+    }
+
+    let key_bytes: usize = pairs.iter().map(|(key, _)| key.len()).sum();
+    let value_bytes: usize = pairs.iter().map(|(_, value)| value.len()).sum();
+    let mut page = vec![0; RawLeafBuilder::required_bytes(pairs.len(), key_bytes + value_bytes)];
+    {
+        let mut builder = RawLeafBuilder::new(
+            &mut page,
+            pairs.len(),
+            fixed_key_size,
+            fixed_value_size,
+            key_bytes,
+        );
+        for (key, value) in pairs.iter() {
+            builder.append(key, value);
+        }
+    }
+
+    let accessor = LeafAccessor::new(&page, fixed_key_size, fixed_value_size);
+    assert_eq!(accessor.num_pairs(), pairs.len());
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        let entry = accessor.entry(i).unwrap();
+        assert_eq!(entry.key(), key.as_slice());
+        assert_eq!(entry.value(), value.as_slice());
+    }
+    assert!(accessor.entry(pairs.len()).is_none());
+}
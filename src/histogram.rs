@@ -0,0 +1,309 @@
+use crate::tree_store::{
+    BranchAccessor, BtreeChange, LeafAccessor, Page, PageNumber, RawBtree, TransactionalMemory,
+    BRANCH, LEAF,
+};
+use crate::types::RedbKey;
+use crate::Result;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::mem::size_of;
+
+// Serialized histogram format:
+// 8 bytes: bucket size
+// 8 bytes: number of buckets
+//
+// Followed by each bucket:
+// 8 bytes: number of entries
+// 4 bytes: length of the smallest key
+// n bytes: smallest key
+// 4 bytes: length of the largest key
+// n bytes: largest key
+
+/// A bucket of a [`KeyHistogram`]
+pub struct HistogramBucket<K: RedbKey + 'static> {
+    min: Vec<u8>,
+    max: Vec<u8>,
+    count: u64,
+    _key_type: PhantomData<K>,
+}
+
+impl<K: RedbKey + 'static> HistogramBucket<K> {
+    /// Returns the smallest key in the bucket
+    pub fn min(&self) -> K::SelfType<'_> {
+        K::from_bytes(&self.min)
+    }
+
+    /// Returns the largest key in the bucket
+    pub fn max(&self) -> K::SelfType<'_> {
+        K::from_bytes(&self.max)
+    }
+
+    /// Returns the number of entries in the bucket
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// Distribution of the keys in a table
+///
+/// The entries of the table are divided, in key order, into buckets. When the histogram is
+/// computed, each bucket contains [`KeyHistogram::bucket_size`] entries, except for the last which
+/// may contain fewer. Each commit then updates the count, and the smallest and largest keys, of the
+/// buckets which entries were inserted into or removed from. Buckets which become empty are
+/// dropped, and those which grow to more than twice the bucket size are split
+pub struct KeyHistogram<K: RedbKey + 'static> {
+    bucket_size: u64,
+    buckets: Vec<HistogramBucket<K>>,
+}
+
+impl<K: RedbKey + 'static> KeyHistogram<K> {
+    pub(crate) fn from_bytes(data: &[u8]) -> Self {
+        let (bucket_size, buckets) = read_buckets(data);
+        let buckets = buckets
+            .into_iter()
+            .map(|bucket| HistogramBucket {
+                min: bucket.min,
+                max: bucket.max,
+                count: bucket.count,
+                _key_type: Default::default(),
+            })
+            .collect();
+
+        Self {
+            bucket_size,
+            buckets,
+        }
+    }
+
+    /// Returns the number of entries in each bucket, other than the last, when the histogram was
+    /// computed
+    pub fn bucket_size(&self) -> u64 {
+        self.bucket_size
+    }
+
+    /// Returns the buckets, in key order
+    pub fn buckets(&self) -> &[HistogramBucket<K>] {
+        &self.buckets
+    }
+
+    /// Returns the total number of entries in the table
+    pub fn entries(&self) -> u64 {
+        self.buckets.iter().map(|x| x.count).sum()
+    }
+}
+
+// A bucket, in serialized form
+struct RawBucket {
+    count: u64,
+    min: Vec<u8>,
+    max: Vec<u8>,
+}
+
+// Returns the bucket size and buckets of a serialized histogram
+fn read_buckets(data: &[u8]) -> (u64, Vec<RawBucket>) {
+    let mut offset = 0;
+    let mut read = |len: usize| {
+        let result = &data[offset..(offset + len)];
+        offset += len;
+        result
+    };
+    let bucket_size = u64::from_le_bytes(read(size_of::<u64>()).try_into().unwrap());
+    let num_buckets = u64::from_le_bytes(read(size_of::<u64>()).try_into().unwrap());
+    let mut buckets = vec![];
+    for _ in 0..num_buckets {
+        let count = u64::from_le_bytes(read(size_of::<u64>()).try_into().unwrap());
+        let len = u32::from_le_bytes(read(size_of::<u32>()).try_into().unwrap());
+        let min = read(len.try_into().unwrap()).to_vec();
+        let len = u32::from_le_bytes(read(size_of::<u32>()).try_into().unwrap());
+        let max = read(len.try_into().unwrap()).to_vec();
+        buckets.push(RawBucket { count, min, max });
+    }
+
+    (bucket_size, buckets)
+}
+
+// Accumulates the buckets of a histogram, in serialized form
+struct HistogramBuilder {
+    bucket_size: u64,
+    num_buckets: u64,
+    data: Vec<u8>,
+    // The current bucket
+    count: u64,
+    min: Vec<u8>,
+}
+
+impl HistogramBuilder {
+    fn new(bucket_size: u64) -> Self {
+        Self {
+            bucket_size,
+            num_buckets: 0,
+            data: vec![],
+            count: 0,
+            min: vec![],
+        }
+    }
+
+    fn push_leaf(&mut self, accessor: &LeafAccessor) {
+        let num_pairs: u64 = accessor.num_pairs().try_into().unwrap();
+        let mut position = 0;
+        while position < num_pairs {
+            if self.count == 0 {
+                let index: usize = position.try_into().unwrap();
+                self.min = accessor.entry(index).unwrap().key().to_vec();
+            }
+            let taken = (self.bucket_size - self.count).min(num_pairs - position);
+            self.count += taken;
+            position += taken;
+            if self.count == self.bucket_size {
+                let index: usize = (position - 1).try_into().unwrap();
+                self.finish_bucket(accessor.entry(index).unwrap().key());
+            }
+        }
+    }
+
+    fn finish_bucket(&mut self, max: &[u8]) {
+        let min = std::mem::take(&mut self.min);
+        self.push_bucket(self.count, &min, max);
+        self.count = 0;
+    }
+
+    fn push_bucket(&mut self, count: u64, min: &[u8], max: &[u8]) {
+        self.data.extend_from_slice(&count.to_le_bytes());
+        let len: u32 = min.len().try_into().unwrap();
+        self.data.extend_from_slice(&len.to_le_bytes());
+        self.data.extend_from_slice(min);
+        let len: u32 = max.len().try_into().unwrap();
+        self.data.extend_from_slice(&len.to_le_bytes());
+        self.data.extend_from_slice(max);
+        self.num_buckets += 1;
+    }
+
+    fn finish(mut self, last_key: Option<Vec<u8>>) -> Vec<u8> {
+        if self.count > 0 {
+            self.finish_bucket(&last_key.unwrap());
+        }
+        let mut result = vec![];
+        result.extend_from_slice(&self.bucket_size.to_le_bytes());
+        result.extend_from_slice(&self.num_buckets.to_le_bytes());
+        result.extend_from_slice(&self.data);
+        result
+    }
+}
+
+pub(crate) fn histogram_bucket_size(data: &[u8]) -> u64 {
+    u64::from_le_bytes(data[..size_of::<u64>()].try_into().unwrap())
+}
+
+// Returns the serialized histogram of the tree rooted at `root`. Only the keys at the boundaries of
+// each bucket are read, but every leaf page of the tree is visited
+pub(crate) fn compute_key_histogram(
+    root: Option<PageNumber>,
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+    bucket_size: u64,
+    mem: &TransactionalMemory,
+) -> Result<Vec<u8>> {
+    assert!(bucket_size > 0);
+    let mut builder = HistogramBuilder::new(bucket_size);
+    let mut last_key = None;
+    if let Some(root) = root {
+        let mut pending = vec![root];
+        while let Some(page_number) = pending.pop() {
+            let page = mem.get_page(page_number)?;
+            match page.memory()[0] {
+                LEAF => {
+                    let accessor =
+                        LeafAccessor::new(page.memory(), fixed_key_size, fixed_value_size);
+                    builder.push_leaf(&accessor);
+                    let last = accessor.entry(accessor.num_pairs() - 1).unwrap();
+                    last_key = Some(last.key().to_vec());
+                }
+                BRANCH => {
+                    let accessor = BranchAccessor::new(&page, fixed_key_size);
+                    // Pushed in reverse, so that the children are visited in key order
+                    for i in (0..accessor.count_children()).rev() {
+                        pending.push(accessor.child_page(i).unwrap());
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    Ok(builder.finish(last_key))
+}
+
+// Returns the key of the entry at `position` in `tree`
+fn key_at(tree: &RawBtree, position: u64) -> Result<Vec<u8>> {
+    let mut result = vec![];
+    tree.for_each_entry_from(position, |key, _| {
+        result = key.to_vec();
+        Ok(false)
+    })?;
+    Ok(result)
+}
+
+// Returns `previous`, the serialized histogram of `old`, updated to describe `new`. Each inserted
+// or removed key is counted in the first bucket whose largest key is not less than it, or in the
+// last bucket. The smallest and largest keys of those buckets are then read from `new` by
+// position, so only the changed entries and the boundaries of their buckets are visited
+pub(crate) fn update_key_histogram(
+    previous: &[u8],
+    old: &RawBtree,
+    new: &RawBtree,
+    compare: fn(&[u8], &[u8]) -> Ordering,
+) -> Result<Vec<u8>> {
+    let (bucket_size, mut buckets) = read_buckets(previous);
+    if buckets.is_empty() {
+        buckets.push(RawBucket {
+            count: 0,
+            min: vec![],
+            max: vec![],
+        });
+    }
+    // The change in the number of entries of each bucket, if any entries were inserted or removed
+    let mut deltas: Vec<Option<i64>> = vec![None; buckets.len()];
+    for change in old.diff(new, compare)? {
+        let (entry, delta) = match change? {
+            BtreeChange::Added(entry) => (entry, 1),
+            BtreeChange::Removed(entry) => (entry, -1),
+            BtreeChange::Modified(_, _) => continue,
+        };
+        // The last bucket has no upper bound, so its largest key is not compared
+        let index = buckets[..(buckets.len() - 1)]
+            .partition_point(|bucket| compare(&bucket.max, entry.key()) == Ordering::Less);
+        *deltas[index].get_or_insert(0) += delta;
+    }
+
+    let mut builder = HistogramBuilder::new(bucket_size);
+    // Position in `new` of the first entry of the next bucket
+    let mut position = 0;
+    for (bucket, delta) in buckets.into_iter().zip(deltas) {
+        let delta = if let Some(delta) = delta {
+            delta
+        } else {
+            if bucket.count > 0 {
+                builder.push_bucket(bucket.count, &bucket.min, &bucket.max);
+            }
+            position += bucket.count;
+            continue;
+        };
+        let count = i64::try_from(bucket.count).unwrap() + delta;
+        let mut remaining = u64::try_from(count).unwrap();
+        let split = remaining > 2 * bucket_size;
+        while remaining > 0 {
+            let part = if split {
+                remaining.min(bucket_size)
+            } else {
+                remaining
+            };
+            let min = key_at(new, position)?;
+            let max = key_at(new, position + part - 1)?;
+            builder.push_bucket(part, &min, &max);
+            position += part;
+            remaining -= part;
+        }
+    }
+
+    Ok(builder.finish(None))
+}
@@ -0,0 +1,239 @@
+use crate::{
+    Database, DropBehavior, Error, ReadableTable, Result, SystemTableDefinition, WriteTransaction,
+};
+
+const ACTIONS_SUFFIX: &str = "::actions";
+const REDO_SUFFIX: &str = "::redo";
+const DEFAULT_MAX_ACTIONS: u64 = 100;
+
+fn last_action(actions: &impl ReadableTable<u64, u64>) -> Result<Option<(u64, u64)>> {
+    match actions.iter()?.next_back() {
+        Some(entry) => {
+            let (number, id) = entry?;
+            Ok(Some((number.value(), id.value())))
+        }
+        None => Ok(None),
+    }
+}
+
+// Number of an action, and the savepoint taken before it
+type ActionEntry = (u64, u64);
+
+// Savepoints of an undone action: the state after it, which redo restores, and the state before
+// it, which becomes undoable again once it is redone
+type RedoEntry = (u64, (u64, u64));
+
+/// Undo and redo history of the actions applied to a database, built on persistent savepoints
+///
+/// Each action is made in a write transaction returned by [`Self::begin_action`], which first
+/// takes a persistent savepoint of the database. [`Self::undo`] takes a persistent savepoint of the
+/// state after the most recent action, and then restores the one taken before it.
+/// [`Self::redo`] restores the state after the most recently undone action. The savepoints of the
+/// most recent actions are retained, up to the limit set by [`Self::set_max_actions`], so actions
+/// can still be undone and redone after the database is reopened. Beginning a new action discards
+/// the actions available to redo, and their savepoints.
+///
+/// The history named `name` is stored in the system tables `name::actions` and `name::redo`, see
+/// [`WriteTransaction::open_system_table`]
+pub struct History<'db> {
+    db: &'db Database,
+    actions: String,
+    redo: String,
+    max_actions: u64,
+}
+
+impl<'db> History<'db> {
+    /// Returns the history called `name` of the actions applied to `db`
+    ///
+    /// The savepoints of up to 100 actions are retained. Use [`Self::set_max_actions`] to change
+    /// this
+    pub fn new(db: &'db Database, name: &str) -> Self {
+        Self {
+            db,
+            actions: format!("{name}{ACTIONS_SUFFIX}"),
+            redo: format!("{name}{REDO_SUFFIX}"),
+            max_actions: DEFAULT_MAX_ACTIONS,
+        }
+    }
+
+    /// Sets the number of actions which can be undone
+    ///
+    /// This setting is not stored in the database, so it must be set each time the history is
+    /// created. When the limit is exceeded, the savepoints of the oldest actions are deleted by the
+    /// next call to [`Self::begin_action`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_actions` is zero
+    pub fn set_max_actions(&mut self, max_actions: u64) {
+        assert!(max_actions > 0);
+        self.max_actions = max_actions;
+    }
+
+    /// Begins a write transaction for a new action, which can be undone once it is committed
+    ///
+    /// The actions available to redo are discarded when the transaction commits
+    pub fn begin_action(&self) -> Result<WriteTransaction<'db>> {
+        let mut transaction = self.db.begin_write_abort_on_drop()?;
+        let savepoint = transaction.persistent_savepoint()?;
+        {
+            let mut redo = transaction.open_system_table(self.redo_definition())?;
+            while let Some((_, entry)) = redo.pop_first()? {
+                let (after, before) = entry.value();
+                transaction.delete_persistent_savepoint(after)?;
+                transaction.delete_persistent_savepoint(before)?;
+            }
+        }
+        {
+            let mut actions = transaction.open_system_table(self.definition())?;
+            let number = last_action(&actions)?.map(|(x, _)| x + 1).unwrap_or(0);
+            actions.insert(number, savepoint)?;
+            while actions.len()? > self.max_actions {
+                let (_, oldest) = actions.pop_first()?.unwrap();
+                transaction.delete_persistent_savepoint(oldest.value())?;
+            }
+        }
+        transaction.set_drop_behavior(DropBehavior::default());
+
+        Ok(transaction)
+    }
+
+    /// Undoes the most recent action, by restoring the savepoint taken before it began
+    ///
+    /// Any changes committed since that savepoint, including those made outside of an action, are
+    /// also rolled back, and are restored if the action is redone. Returns `false` if there is no
+    /// action to undo
+    pub fn undo(&self) -> Result<bool> {
+        let mut transaction = self.db.begin_write_abort_on_drop()?;
+        // The savepoint must be taken before any table is opened
+        let after = transaction.persistent_savepoint()?;
+        let (mut actions, mut redo) = self.read_log(&transaction)?;
+        let (number, before) = match actions.pop() {
+            Some(action) => action,
+            None => {
+                transaction.abort()?;
+                return Ok(false);
+            }
+        };
+        redo.push((number, (after, before)));
+
+        let savepoint = transaction.get_persistent_savepoint(before)?;
+        transaction.restore_savepoint_retaining(&savepoint, None, &retained(&actions, &redo))?;
+        self.write_log(&transaction, &actions, &redo)?;
+        transaction.commit()?;
+
+        Ok(true)
+    }
+
+    /// Redoes the most recently undone action, by restoring the state as of when it was undone
+    ///
+    /// Any changes committed since it was undone, including those made outside of an action, are
+    /// rolled back. Returns `false` if there is no action to redo
+    pub fn redo(&self) -> Result<bool> {
+        let mut transaction = self.db.begin_write_abort_on_drop()?;
+        let (mut actions, mut redo) = self.read_log(&transaction)?;
+        let (number, (after, before)) = match redo.pop() {
+            Some(entry) => entry,
+            None => {
+                transaction.abort()?;
+                return Ok(false);
+            }
+        };
+        actions.push((number, before));
+
+        let savepoint = transaction.get_persistent_savepoint(after)?;
+        // The state after the action was retained when the savepoint before it was restored
+        let branch_point = transaction.get_persistent_savepoint(before)?;
+        transaction.restore_savepoint_retaining(
+            &savepoint,
+            Some(&branch_point),
+            &retained(&actions, &redo),
+        )?;
+        self.write_log(&transaction, &actions, &redo)?;
+        transaction.commit()?;
+
+        Ok(true)
+    }
+
+    /// Returns the number of actions which can be undone
+    pub fn undo_len(&self) -> Result<u64> {
+        match self.db.begin_read()?.open_system_table(self.definition()) {
+            Ok(actions) => actions.len(),
+            Err(Error::TableDoesNotExist(_)) => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns the number of actions which can be redone
+    pub fn redo_len(&self) -> Result<u64> {
+        match self
+            .db
+            .begin_read()?
+            .open_system_table(self.redo_definition())
+        {
+            Ok(redo) => redo.len(),
+            Err(Error::TableDoesNotExist(_)) => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn read_log(
+        &self,
+        transaction: &WriteTransaction,
+    ) -> Result<(Vec<ActionEntry>, Vec<RedoEntry>)> {
+        let mut actions = vec![];
+        for entry in transaction.open_system_table(self.definition())?.iter()? {
+            let (number, id) = entry?;
+            actions.push((number.value(), id.value()));
+        }
+        let mut redo = vec![];
+        for entry in transaction
+            .open_system_table(self.redo_definition())?
+            .iter()?
+        {
+            let (number, ids) = entry?;
+            redo.push((number.value(), ids.value()));
+        }
+        // Actions are undone from the most recent, so the next to redo is the lowest numbered
+        redo.reverse();
+        Ok((actions, redo))
+    }
+
+    // Replaces the log, which restoring a savepoint rolls back along with everything else
+    fn write_log(
+        &self,
+        transaction: &WriteTransaction,
+        actions: &[ActionEntry],
+        redo: &[RedoEntry],
+    ) -> Result {
+        let mut table = transaction.open_system_table(self.definition())?;
+        table.drain::<u64>(..)?;
+        for (number, id) in actions {
+            table.insert(number, id)?;
+        }
+        let mut table = transaction.open_system_table(self.redo_definition())?;
+        table.drain::<u64>(..)?;
+        for (number, ids) in redo {
+            table.insert(number, ids)?;
+        }
+        Ok(())
+    }
+
+    fn definition(&self) -> SystemTableDefinition<'_, u64, u64> {
+        SystemTableDefinition::new(&self.actions)
+    }
+
+    fn redo_definition(&self) -> SystemTableDefinition<'_, u64, (u64, u64)> {
+        SystemTableDefinition::new(&self.redo)
+    }
+}
+
+// Returns the savepoints referenced by the log
+fn retained(actions: &[ActionEntry], redo: &[RedoEntry]) -> Vec<u64> {
+    let mut result: Vec<u64> = actions.iter().map(|(_, id)| *id).collect();
+    for (_, (after, before)) in redo {
+        result.push(*after);
+        result.push(*before);
+    }
+    result
+}
@@ -0,0 +1,148 @@
+use crate::types::{RedbKey, RedbValue};
+use crate::{Database, ReadableTable, Result, SystemTableDefinition, TableDefinition};
+use std::borrow::Borrow;
+
+#[cfg(feature = "logging")]
+use log::info;
+
+// Maps the name of each unfinished import to the number of entries it has committed
+const IMPORT_CURSOR_TABLE: SystemTableDefinition<&str, u64> =
+    SystemTableDefinition::new("import_cursors");
+
+/// Progress of an [`Importer`], reported after each chunk is committed
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ImportProgress {
+    imported: u64,
+    resumed_from: u64,
+    finished: bool,
+}
+
+impl ImportProgress {
+    /// Number of entries committed so far, including those committed by earlier, interrupted,
+    /// runs of the same import
+    pub fn imported(&self) -> u64 {
+        self.imported
+    }
+
+    /// Number of entries which had been committed by earlier runs of the import, and so were
+    /// skipped
+    pub fn resumed_from(&self) -> u64 {
+        self.resumed_from
+    }
+
+    /// Whether all the entries have been imported
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+/// Imports a large number of entries into a table, in a sequence of write transactions which can
+/// be resumed if interrupted
+///
+/// Entries are committed in chunks of [`Importer::set_chunk_size`] entries. Each commit also
+/// records how many entries have been imported under the importer's name, so if the import fails,
+/// or the process crashes, calling [`Importer::import`] again with the same name and the same
+/// entries skips those which were already committed. The entries must therefore be produced in
+/// the same order each time. The record is removed once the import completes.
+pub struct Importer<'db, 'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    db: &'db Database,
+    name: String,
+    definition: TableDefinition<'a, K, V>,
+    chunk_size: u64,
+    progress: Option<Box<dyn FnMut(ImportProgress) + 'a>>,
+}
+
+impl<'db, 'a, K: RedbKey + 'static, V: RedbValue + 'static> Importer<'db, 'a, K, V> {
+    /// Creates an importer which inserts into the table `definition`
+    ///
+    /// `name` identifies the import, so that it can be resumed
+    pub fn new(db: &'db Database, name: &str, definition: TableDefinition<'a, K, V>) -> Self {
+        Self {
+            db,
+            name: name.to_string(),
+            definition,
+            chunk_size: 10_000,
+            progress: None,
+        }
+    }
+
+    /// Set the number of entries committed in each write transaction
+    ///
+    /// Defaults to 10,000
+    pub fn set_chunk_size(&mut self, entries: u64) -> &mut Self {
+        assert!(entries > 0);
+        self.chunk_size = entries;
+        self
+    }
+
+    /// Set a callback which is invoked after each chunk is committed
+    pub fn set_progress_callback(
+        &mut self,
+        callback: impl FnMut(ImportProgress) + 'a,
+    ) -> &mut Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Inserts `entries` into the table, skipping any which were committed by an earlier run of
+    /// this import
+    ///
+    /// Returns the total number of entries imported
+    pub fn import<'k, KB: Borrow<K::SelfType<'k>>, VB: Borrow<V::SelfType<'k>>>(
+        &mut self,
+        entries: impl IntoIterator<Item = (KB, VB)>,
+    ) -> Result<u64> {
+        let mut entries = entries.into_iter().peekable();
+        let mut resumed_from = None;
+        loop {
+            let txn = self.db.begin_write_abort_on_drop()?;
+            let progress = {
+                let mut cursor = txn.open_internal_system_table(IMPORT_CURSOR_TABLE)?;
+                let mut imported = cursor
+                    .get(self.name.as_str())?
+                    .map(|x| x.value())
+                    .unwrap_or(0);
+                if resumed_from.is_none() {
+                    #[cfg(feature = "logging")]
+                    if imported > 0 {
+                        info!("Resuming import {} from entry {}", self.name, imported);
+                    }
+                    for _ in 0..imported {
+                        if entries.next().is_none() {
+                            break;
+                        }
+                    }
+                    resumed_from = Some(imported);
+                }
+
+                let mut table = txn.open_table(self.definition)?;
+                for (key, value) in entries.by_ref().take(self.chunk_size.try_into().unwrap()) {
+                    table.insert(key.borrow(), value.borrow())?;
+                    imported += 1;
+                }
+                // Peek for the end, so that an import which fills its last chunk exactly does not
+                // need an empty transaction to finish
+                let finished = entries.peek().is_none();
+                if finished {
+                    cursor.remove(self.name.as_str())?;
+                } else {
+                    cursor.insert(self.name.as_str(), imported)?;
+                }
+
+                ImportProgress {
+                    imported,
+                    resumed_from: resumed_from.unwrap(),
+                    finished,
+                }
+            };
+            txn.commit()?;
+
+            if let Some(callback) = self.progress.as_mut() {
+                callback(progress);
+            }
+            if progress.finished {
+                return Ok(progress.imported);
+            }
+        }
+    }
+}
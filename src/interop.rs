@@ -0,0 +1,171 @@
+//! Utilities for moving data between redb and other key-value stores
+//!
+//! Each importer streams every key-value pair out of the source store, and inserts it into a redb
+//! table in a single write transaction. The transaction is committed only if every pair was
+//! imported, so a failed import leaves the target database unchanged.
+//!
+//! Tables can also be exported to CSV with [`Table::export_csv`] and [`ReadOnlyTable::export_csv`],
+//! and loaded from [JSON Lines](https://jsonlines.org) with [`Table::import_jsonl`].
+
+use crate::types::{RedbKey, RedbValue};
+#[cfg(any(feature = "interop_lmdb", feature = "interop_sled"))]
+use crate::{Database, TableDefinition};
+use crate::{Error, ReadOnlyTable, ReadableTable, Result, Table};
+use serde::Deserialize;
+use std::fmt::Display;
+use std::io;
+use std::io::{BufRead, Write};
+
+#[cfg(any(feature = "interop_lmdb", feature = "interop_sled"))]
+fn import_pairs<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+    target: &Database,
+    table: TableDefinition<&[u8], &[u8]>,
+    pairs: impl Iterator<Item = Result<(K, V)>>,
+) -> Result<u64> {
+    let txn = target.begin_write_abort_on_drop()?;
+    let mut imported = 0;
+    {
+        let mut table = txn.open_table(table)?;
+        for pair in pairs {
+            let (key, value) = pair?;
+            table.insert(key.as_ref(), value.as_ref())?;
+            imported += 1;
+        }
+    }
+    txn.commit()?;
+
+    Ok(imported)
+}
+
+#[cfg(any(feature = "interop_lmdb", feature = "interop_sled"))]
+fn foreign_error<E: std::error::Error + Send + Sync + 'static>(err: E) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::Other, err))
+}
+
+/// Imports every key-value pair in the lmdb database `source` into `table`
+///
+/// Returns the number of pairs imported. Errors reported by lmdb are returned as [`Error::Io`]
+#[cfg(feature = "interop_lmdb")]
+pub fn from_lmdb(
+    env: &lmdb::Environment,
+    source: lmdb::Database,
+    target: &Database,
+    table: TableDefinition<&[u8], &[u8]>,
+) -> Result<u64> {
+    use lmdb::{Cursor, Transaction};
+
+    let txn = env.begin_ro_txn().map_err(foreign_error)?;
+    let mut cursor = txn.open_ro_cursor(source).map_err(foreign_error)?;
+    let pairs = cursor.iter_start().map(|pair| pair.map_err(foreign_error));
+
+    import_pairs(target, table, pairs)
+}
+
+/// Imports every key-value pair in the sled tree `source` into `table`
+///
+/// A [`sled::Db`] can be passed directly, to import its default tree. Returns the number of pairs
+/// imported. Errors reported by sled are returned as [`Error::Io`]
+#[cfg(feature = "interop_sled")]
+pub fn from_sled(
+    source: &sled::Tree,
+    target: &Database,
+    table: TableDefinition<&[u8], &[u8]>,
+) -> Result<u64> {
+    let pairs = source.iter().map(|pair| pair.map_err(foreign_error));
+
+    import_pairs(target, table, pairs)
+}
+
+// A single line of a JSON Lines import
+#[derive(Deserialize)]
+struct JsonlEntry<K, V> {
+    key: K,
+    value: V,
+}
+
+// Writes `field` as a CSV field, quoting it if necessary (RFC 4180)
+fn write_csv_field(writer: &mut impl Write, field: impl Display) -> io::Result<()> {
+    let field = field.to_string();
+    if field.contains([',', '"', '\n', '\r']) {
+        write!(writer, "\"{}\"", field.replace('"', "\"\""))
+    } else {
+        writer.write_all(field.as_bytes())
+    }
+}
+
+fn export_csv<K: RedbKey + 'static, V: RedbValue + 'static>(
+    table: &impl ReadableTable<K, V>,
+    mut writer: impl Write,
+) -> Result<u64>
+where
+    for<'a> K::SelfType<'a>: Display,
+    for<'a> V::SelfType<'a>: Display,
+{
+    let mut exported = 0;
+    writer.write_all(b"key,value\n")?;
+    for entry in table.iter()? {
+        let (key, value) = entry?;
+        write_csv_field(&mut writer, key.value())?;
+        writer.write_all(b",")?;
+        write_csv_field(&mut writer, value.value())?;
+        writer.write_all(b"\n")?;
+        exported += 1;
+    }
+    writer.flush()?;
+
+    Ok(exported)
+}
+
+impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K, V> {
+    /// Writes every key-value pair in the table to `writer` as CSV, in key order
+    ///
+    /// The output starts with a `key,value` header row, and each field is formatted with its
+    /// [`Display`] implementation. Returns the number of pairs exported
+    pub fn export_csv(&self, writer: impl Write) -> Result<u64>
+    where
+        for<'a> K::SelfType<'a>: Display,
+        for<'a> V::SelfType<'a>: Display,
+    {
+        export_csv(self, writer)
+    }
+
+    /// Inserts the key-value pairs read from `reader`, which must contain one JSON object per
+    /// line, of the form `{"key": ..., "value": ...}`
+    ///
+    /// Blank lines are skipped. Returns the number of pairs imported, or [`Error::Io`] with
+    /// [`io::ErrorKind::InvalidData`] if a line cannot be parsed. Pairs imported before the
+    /// failing line remain in the table
+    pub fn import_jsonl(&mut self, reader: impl BufRead) -> Result<u64>
+    where
+        for<'a> K::SelfType<'a>: Deserialize<'a>,
+        for<'a> V::SelfType<'a>: Deserialize<'a>,
+    {
+        let mut imported = 0;
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: JsonlEntry<K::SelfType<'_>, V::SelfType<'_>> =
+                serde_json::from_str(&line)
+                    .map_err(|err| Error::Io(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+            self.insert(&entry.key, &entry.value)?;
+            imported += 1;
+        }
+
+        Ok(imported)
+    }
+}
+
+impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadOnlyTable<'txn, K, V> {
+    /// Writes every key-value pair in the table to `writer` as CSV, in key order
+    ///
+    /// See [`Table::export_csv`]
+    pub fn export_csv(&self, writer: impl Write) -> Result<u64>
+    where
+        for<'a> K::SelfType<'a>: Display,
+        for<'a> V::SelfType<'a>: Display,
+    {
+        export_csv(self, writer)
+    }
+}
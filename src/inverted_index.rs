@@ -0,0 +1,434 @@
+use crate::{
+    Range, ReadOnlyTable, ReadTransaction, ReadableTable, Result, Table, TableDefinition,
+    WriteTransaction,
+};
+use std::collections::BTreeSet;
+
+const POSTINGS_SUFFIX: &str = "::postings";
+const DOCUMENTS_SUFFIX: &str = "::documents";
+// Maximum number of document ids in a block of a posting list
+const MAX_BLOCK_LEN: usize = 128;
+
+type PostingsTable<'db, 'txn> = Table<'db, 'txn, (&'static str, u64), &'static [u8]>;
+type PostingBlocks<'a> = Range<'a, (&'static str, u64), &'static [u8]>;
+
+/// Splits text into the terms which are indexed by an [`InvertedIndex`]
+pub trait Tokenizer {
+    /// Returns the terms in `text`. Duplicate terms are ignored
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+impl<F: Fn(&str) -> Vec<String>> Tokenizer for F {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self(text)
+    }
+}
+
+/// Tokenizer which splits text into lowercase runs of alphanumeric characters
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DefaultTokenizer;
+
+impl Tokenizer for DefaultTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| word.to_lowercase())
+            .collect()
+    }
+}
+
+/// How [`InvertedIndex::search`] combines the documents matching each term
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SearchMode {
+    /// Documents which contain every term
+    All,
+    /// Documents which contain at least one of the terms
+    Any,
+}
+
+fn write_varint(mut value: u64, output: &mut Vec<u8>) {
+    while value >= 0x80 {
+        output.push(u8::try_from(value & 0x7F).unwrap() | 0x80);
+        value >>= 7;
+    }
+    output.push(u8::try_from(value).unwrap());
+}
+
+fn read_varint(data: &mut &[u8]) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some((byte, rest)) = data.split_first() {
+        *data = rest;
+        value |= u64::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
+
+// A block of a posting list is keyed by its first document id, and stores the gaps between
+// consecutive ids
+fn encode_block(ids: &[u64]) -> Vec<u8> {
+    let mut result = vec![];
+    for pair in ids.windows(2) {
+        write_varint(pair[1] - pair[0], &mut result);
+    }
+    result
+}
+
+fn decode_block(first: u64, mut data: &[u8]) -> Vec<u64> {
+    let mut ids = vec![first];
+    let mut id = first;
+    while !data.is_empty() {
+        id += read_varint(&mut data);
+        ids.push(id);
+    }
+    ids
+}
+
+fn encode_terms(terms: &BTreeSet<String>) -> Vec<u8> {
+    let mut result = vec![];
+    for term in terms {
+        write_varint(term.len().try_into().unwrap(), &mut result);
+        result.extend_from_slice(term.as_bytes());
+    }
+    result
+}
+
+fn decode_terms(mut data: &[u8]) -> Vec<String> {
+    let mut terms = vec![];
+    while !data.is_empty() {
+        let len = read_varint(&mut data).try_into().unwrap();
+        let (term, rest) = data.split_at(len);
+        terms.push(String::from_utf8(term.to_vec()).unwrap());
+        data = rest;
+    }
+    terms
+}
+
+// Writes the ids of a block, which must be sorted, replacing the block keyed by `old_first`
+fn write_block(
+    postings: &mut PostingsTable,
+    term: &str,
+    old_first: Option<u64>,
+    ids: &[u64],
+) -> Result {
+    if let Some(old_first) = old_first {
+        if ids.first() != Some(&old_first) {
+            postings.remove((term, old_first))?;
+        }
+    }
+    if let Some(first) = ids.first() {
+        postings.insert((term, *first), encode_block(ids).as_slice())?;
+    }
+    Ok(())
+}
+
+fn add_posting(postings: &mut PostingsTable, term: &str, id: u64) -> Result {
+    let block = postings
+        .range((term, 0)..=(term, id))?
+        .next_back()
+        .transpose()?
+        .map(|(key, value)| (key.value().1, decode_block(key.value().1, value.value())));
+    let (old_first, mut ids) = if let Some((first, ids)) = block {
+        (Some(first), ids)
+    } else {
+        // The id precedes the whole list, so prepend it to the first block if that has room
+        let block = postings
+            .range((term, id)..=(term, u64::MAX))?
+            .next()
+            .transpose()?
+            .map(|(key, value)| (key.value().1, decode_block(key.value().1, value.value())));
+        match block {
+            Some((first, ids)) if ids.len() < MAX_BLOCK_LEN => (Some(first), ids),
+            _ => (None, vec![]),
+        }
+    };
+    let position = match ids.binary_search(&id) {
+        Ok(_) => return Ok(()),
+        Err(position) => position,
+    };
+    ids.insert(position, id);
+
+    if ids.len() > MAX_BLOCK_LEN {
+        let second = ids.split_off(ids.len() / 2);
+        write_block(postings, term, None, &second)?;
+    }
+    write_block(postings, term, old_first, &ids)
+}
+
+fn remove_posting(postings: &mut PostingsTable, term: &str, id: u64) -> Result {
+    let block = postings
+        .range((term, 0)..=(term, id))?
+        .next_back()
+        .transpose()?
+        .map(|(key, value)| (key.value().1, decode_block(key.value().1, value.value())));
+    if let Some((first, mut ids)) = block {
+        if let Ok(position) = ids.binary_search(&id) {
+            ids.remove(position);
+            if ids.is_empty() {
+                postings.remove((term, first))?;
+            } else {
+                write_block(postings, term, Some(first), &ids)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Position in the posting list of a single term
+struct PostingCursor<'a> {
+    blocks: PostingBlocks<'a>,
+    // Remaining ids of the current block, in reverse order
+    ids: Vec<u64>,
+}
+
+impl<'a> PostingCursor<'a> {
+    fn peek(&mut self) -> Result<Option<u64>> {
+        while self.ids.is_empty() {
+            match self.blocks.next() {
+                Some(entry) => {
+                    let (key, value) = entry?;
+                    self.ids = decode_block(key.value().1, value.value());
+                    self.ids.reverse();
+                }
+                None => return Ok(None),
+            }
+        }
+        Ok(self.ids.last().copied())
+    }
+
+    fn advance(&mut self) {
+        self.ids.pop();
+    }
+}
+
+/// Iterator over the ids of the documents matching a search, in ascending order
+pub struct SearchResults<'a> {
+    cursors: Vec<PostingCursor<'a>>,
+    mode: SearchMode,
+    done: bool,
+}
+
+impl<'a> SearchResults<'a> {
+    fn new(
+        postings: &'a impl ReadableTable<(&'static str, u64), &'static [u8]>,
+        terms: &[&str],
+        mode: SearchMode,
+    ) -> Result<Self> {
+        let mut cursors = vec![];
+        for term in terms {
+            cursors.push(PostingCursor {
+                blocks: postings.range((*term, 0)..=(*term, u64::MAX))?,
+                ids: vec![],
+            });
+        }
+        Ok(Self {
+            done: cursors.is_empty(),
+            cursors,
+            mode,
+        })
+    }
+
+    fn next_all(&mut self) -> Result<Option<u64>> {
+        loop {
+            let mut max = 0;
+            let mut all_equal = true;
+            for (i, cursor) in self.cursors.iter_mut().enumerate() {
+                let id = match cursor.peek()? {
+                    Some(id) => id,
+                    None => return Ok(None),
+                };
+                if i > 0 && id != max {
+                    all_equal = false;
+                }
+                max = max.max(id);
+            }
+            if all_equal {
+                for cursor in self.cursors.iter_mut() {
+                    cursor.advance();
+                }
+                return Ok(Some(max));
+            }
+            for cursor in self.cursors.iter_mut() {
+                while matches!(cursor.peek()?, Some(id) if id < max) {
+                    cursor.advance();
+                }
+            }
+        }
+    }
+
+    fn next_any(&mut self) -> Result<Option<u64>> {
+        let mut min = None;
+        for cursor in self.cursors.iter_mut() {
+            if let Some(id) = cursor.peek()? {
+                min = Some(min.map_or(id, |min: u64| min.min(id)));
+            }
+        }
+        if let Some(min) = min {
+            for cursor in self.cursors.iter_mut() {
+                if cursor.peek()? == Some(min) {
+                    cursor.advance();
+                }
+            }
+        }
+        Ok(min)
+    }
+}
+
+impl<'a> Iterator for SearchResults<'a> {
+    type Item = Result<u64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = match self.mode {
+            SearchMode::All => self.next_all(),
+            SearchMode::Any => self.next_any(),
+        };
+        match result {
+            Ok(Some(id)) => Some(Ok(id)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Inverted index from terms to the documents which contain them, layered on top of regular
+/// tables, for simple full-text search
+///
+/// Documents are identified by a `u64` chosen by the caller, typically the key of the document in
+/// another table. Their text is split into terms by a [`Tokenizer`]. The posting list of each
+/// term is stored in blocks of up to 128 document ids, which are delta-encoded.
+///
+/// An inverted index named `name` is stored in tables called `name::postings` and
+/// `name::documents`
+pub struct InvertedIndex<'db, 'txn, T: Tokenizer = DefaultTokenizer> {
+    postings: PostingsTable<'db, 'txn>,
+    // Document id -> terms of the document, so that it can be removed from the posting lists
+    documents: Table<'db, 'txn, u64, &'static [u8]>,
+    tokenizer: T,
+}
+
+impl<'db, 'txn> InvertedIndex<'db, 'txn> {
+    /// Opens the inverted index called `name`, creating it if it does not exist
+    pub fn open(transaction: &'txn WriteTransaction<'db>, name: &str) -> Result<Self> {
+        Self::open_with_tokenizer(transaction, name, DefaultTokenizer)
+    }
+}
+
+impl<'db, 'txn, T: Tokenizer> InvertedIndex<'db, 'txn, T> {
+    /// Opens the inverted index called `name`, creating it if it does not exist, and uses
+    /// `tokenizer` to split documents into terms
+    ///
+    /// The same tokenizer should be used whenever the index is opened
+    pub fn open_with_tokenizer(
+        transaction: &'txn WriteTransaction<'db>,
+        name: &str,
+        tokenizer: T,
+    ) -> Result<Self> {
+        let postings = format!("{name}{POSTINGS_SUFFIX}");
+        let documents = format!("{name}{DOCUMENTS_SUFFIX}");
+        Ok(Self {
+            postings: transaction.open_table(TableDefinition::new(&postings))?,
+            documents: transaction.open_table(TableDefinition::new(&documents))?,
+            tokenizer,
+        })
+    }
+
+    /// Returns the terms of `text`, as they are stored in the index
+    ///
+    /// This can be used to turn a query into the terms passed to [`Self::search`]
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        self.tokenizer.tokenize(text)
+    }
+
+    /// Indexes `text` as the contents of document `id`, replacing its previous contents
+    pub fn insert(&mut self, id: u64, text: &str) -> Result {
+        self.remove(id)?;
+        let terms: BTreeSet<String> = self.tokenizer.tokenize(text).into_iter().collect();
+        for term in terms.iter() {
+            add_posting(&mut self.postings, term, id)?;
+        }
+        self.documents.insert(id, encode_terms(&terms).as_slice())?;
+
+        Ok(())
+    }
+
+    /// Removes document `id` from the index
+    ///
+    /// Returns `false` if the document was not indexed
+    pub fn remove(&mut self, id: u64) -> Result<bool> {
+        let terms = match self.documents.remove(id)? {
+            Some(terms) => decode_terms(terms.value()),
+            None => return Ok(false),
+        };
+        for term in terms {
+            remove_posting(&mut self.postings, &term, id)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the ids of the documents which contain all, or any, of `terms`, in ascending order
+    ///
+    /// Terms are matched exactly, so they should be normalized in the same way as the tokenizer
+    /// does, for example with [`Self::tokenize`]. Searching for no terms matches no documents
+    pub fn search(&self, terms: &[&str], mode: SearchMode) -> Result<SearchResults<'_>> {
+        SearchResults::new(&self.postings, terms, mode)
+    }
+
+    /// Returns the number of documents in the index
+    pub fn len(&self) -> Result<u64> {
+        self.documents.len()
+    }
+
+    /// Returns `true` if the index contains no documents
+    pub fn is_empty(&self) -> Result<bool> {
+        self.documents.is_empty()
+    }
+}
+
+/// Read-only view of an [`InvertedIndex`]
+pub struct ReadOnlyInvertedIndex<'txn> {
+    postings: ReadOnlyTable<'txn, (&'static str, u64), &'static [u8]>,
+    documents: ReadOnlyTable<'txn, u64, &'static [u8]>,
+}
+
+impl<'txn> ReadOnlyInvertedIndex<'txn> {
+    /// Opens the inverted index called `name`
+    pub fn open(transaction: &'txn ReadTransaction, name: &str) -> Result<Self> {
+        let postings = format!("{name}{POSTINGS_SUFFIX}");
+        let documents = format!("{name}{DOCUMENTS_SUFFIX}");
+        Ok(Self {
+            postings: transaction.open_table(TableDefinition::new(&postings))?,
+            documents: transaction.open_table(TableDefinition::new(&documents))?,
+        })
+    }
+
+    /// Returns the ids of the documents which contain all, or any, of `terms`, in ascending order
+    ///
+    /// See [`InvertedIndex::search`]
+    pub fn search(&self, terms: &[&str], mode: SearchMode) -> Result<SearchResults<'_>> {
+        SearchResults::new(&self.postings, terms, mode)
+    }
+
+    /// Returns the number of documents in the index
+    pub fn len(&self) -> Result<u64> {
+        self.documents.len()
+    }
+
+    /// Returns `true` if the index contains no documents
+    pub fn is_empty(&self) -> Result<bool> {
+        self.documents.is_empty()
+    }
+}
@@ -0,0 +1,300 @@
+use crate::lease_table::to_nanos;
+use crate::{ReadableTable, Result, Table, TableDefinition, WriteTransaction};
+use std::time::Duration;
+
+const JOBS_SUFFIX: &str = "::jobs";
+const VISIBILITY_SUFFIX: &str = "::visibility";
+const DEAD_LETTERS_SUFFIX: &str = "::dead_letters";
+const SEQUENCE_SUFFIX: &str = "::job_queue";
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// A job leased from a [`JobQueue`]
+///
+/// The lease is not released when the job is dropped, since that requires a write transaction.
+/// A job which is neither acknowledged nor rejected becomes visible again once its visibility
+/// timeout has elapsed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Job {
+    id: u64,
+    attempts: u32,
+    payload: Vec<u8>,
+    expires_at: Duration,
+}
+
+impl Job {
+    /// Returns the id of the job
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the number of times the job has been leased, including this lease
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Returns the contents of the job
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Returns the time at which the lease expires and the job becomes visible again, as measured
+    /// by the clock passed to [`JobQueue::lease`]
+    pub fn expires_at(&self) -> Duration {
+        self.expires_at
+    }
+}
+
+/// A job which was moved to the dead-letter table of a [`JobQueue`], after using up its attempts
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeadLetter {
+    id: u64,
+    attempts: u32,
+    payload: Vec<u8>,
+}
+
+impl DeadLetter {
+    /// Returns the id of the job
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the number of times the job was leased
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Returns the contents of the job
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+/// Queue of jobs with visibility timeouts, layered on top of regular tables
+///
+/// Workers [`lease`](Self::lease) visible jobs, which hides them from other workers until the
+/// visibility timeout elapses. A worker which finishes a job removes it with [`Self::ack`], and one
+/// which fails returns it to the queue with [`Self::nack`]. If a worker crashes, its jobs become
+/// visible again once their timeout elapses. A job which has been leased the maximum number of
+/// times, and fails again, is moved to the dead-letter table rather than being retried.
+///
+/// Time is supplied by the caller as the elapsed time since an epoch of its choosing, which must
+/// be the same for all users of the queue, such as the UNIX epoch. All operations happen inside
+/// the write transaction, so enqueueing a job can be made atomic with other writes, and two
+/// workers cannot lease the same job.
+///
+/// A job queue named `name` is stored in tables called `name::jobs`, `name::visibility` and
+/// `name::dead_letters`, and assigns ids from the sequence `name::job_queue`
+pub struct JobQueue<'db, 'txn> {
+    transaction: &'txn WriteTransaction<'db>,
+    sequence: String,
+    max_attempts: u32,
+    // Job id -> (time at which the job becomes visible in nanoseconds, attempts, payload)
+    jobs: Table<'db, 'txn, u64, (u64, u32, &'static [u8])>,
+    // (time at which the job becomes visible in nanoseconds, job id)
+    visibility: Table<'db, 'txn, (u64, u64), ()>,
+    // Job id -> (attempts, payload)
+    dead_letters: Table<'db, 'txn, u64, (u32, &'static [u8])>,
+}
+
+impl<'db, 'txn> JobQueue<'db, 'txn> {
+    /// Opens the job queue called `name`, creating it if it does not exist
+    ///
+    /// Jobs may be leased up to 5 times before they are moved to the dead-letter table. Use
+    /// [`Self::set_max_attempts`] to change this
+    pub fn open(transaction: &'txn WriteTransaction<'db>, name: &str) -> Result<Self> {
+        let jobs = format!("{name}{JOBS_SUFFIX}");
+        let visibility = format!("{name}{VISIBILITY_SUFFIX}");
+        let dead_letters = format!("{name}{DEAD_LETTERS_SUFFIX}");
+        Ok(Self {
+            transaction,
+            sequence: format!("{name}{SEQUENCE_SUFFIX}"),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            jobs: transaction.open_table(TableDefinition::new(&jobs))?,
+            visibility: transaction.open_table(TableDefinition::new(&visibility))?,
+            dead_letters: transaction.open_table(TableDefinition::new(&dead_letters))?,
+        })
+    }
+
+    /// Sets the number of times a job may be leased before it is moved to the dead-letter table
+    ///
+    /// This setting is not stored in the database, so it must be set each time the queue is opened
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_attempts` is zero
+    pub fn set_max_attempts(&mut self, max_attempts: u32) {
+        assert!(max_attempts > 0);
+        self.max_attempts = max_attempts;
+    }
+
+    /// Adds a job to the queue, which is visible immediately, and returns its id
+    pub fn enqueue(&mut self, payload: &[u8]) -> Result<u64> {
+        self.enqueue_at(payload, Duration::ZERO)
+    }
+
+    /// Adds a job to the queue, which becomes visible at `visible_at`, and returns its id
+    pub fn enqueue_at(&mut self, payload: &[u8], visible_at: Duration) -> Result<u64> {
+        let id = self.transaction.next_sequence(&self.sequence)?;
+        self.insert(id, to_nanos(visible_at), 0, payload)?;
+
+        Ok(id)
+    }
+
+    /// Leases up to `max_jobs` jobs which are visible as of `now`, in the order they became visible,
+    /// hiding them until `now + timeout`
+    ///
+    /// Jobs which have already used up their attempts, because their last lease expired, are moved
+    /// to the dead-letter table instead
+    pub fn lease(&mut self, now: Duration, timeout: Duration, max_jobs: usize) -> Result<Vec<Job>> {
+        let expires_at = now.saturating_add(timeout);
+        let mut leased = vec![];
+        while leased.len() < max_jobs {
+            let id = match self.visibility.range(..=(to_nanos(now), u64::MAX))?.next() {
+                Some(entry) => entry?.0.value().1,
+                None => break,
+            };
+            let (attempts, payload) = self.remove(id)?.unwrap();
+            if attempts >= self.max_attempts {
+                self.dead_letters
+                    .insert(id, (attempts, payload.as_slice()))?;
+                continue;
+            }
+            self.insert(id, to_nanos(expires_at), attempts + 1, &payload)?;
+            leased.push(Job {
+                id,
+                attempts: attempts + 1,
+                payload,
+                expires_at,
+            });
+        }
+
+        Ok(leased)
+    }
+
+    /// Acknowledges that `job` is complete, and removes it from the queue
+    ///
+    /// Returns `false` if the job has since been leased again or removed, in which case the queue
+    /// is left unchanged. A job whose lease expired, but which has not been leased again, can
+    /// still be acknowledged
+    pub fn ack(&mut self, job: &Job) -> Result<bool> {
+        if !self.is_current(job)? {
+            return Ok(false);
+        }
+        self.remove(job.id)?;
+
+        Ok(true)
+    }
+
+    /// Returns `job` to the queue after a failed attempt, to become visible again at `now + delay`
+    ///
+    /// If the job has used up its attempts, it is moved to the dead-letter table instead. Returns
+    /// `false` if the job has since been leased again or removed, in which case the queue is left
+    /// unchanged
+    pub fn nack(&mut self, job: &Job, now: Duration, delay: Duration) -> Result<bool> {
+        if !self.is_current(job)? {
+            return Ok(false);
+        }
+        let (attempts, payload) = self.remove(job.id)?.unwrap();
+        if attempts >= self.max_attempts {
+            self.dead_letters
+                .insert(job.id, (attempts, payload.as_slice()))?;
+        } else {
+            let visible_at = to_nanos(now.saturating_add(delay));
+            self.insert(job.id, visible_at, attempts, &payload)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Extends the lease on `job` until `now + timeout`
+    ///
+    /// Returns `false`, and leaves the queue unchanged, if the lease has expired as of `now`, or the
+    /// job has been leased again or removed
+    pub fn extend(&mut self, job: &mut Job, now: Duration, timeout: Duration) -> Result<bool> {
+        if !self.is_current(job)? || job.expires_at <= now {
+            return Ok(false);
+        }
+        let expires_at = now.saturating_add(timeout);
+        self.remove(job.id)?;
+        self.insert(job.id, to_nanos(expires_at), job.attempts, &job.payload)?;
+        job.expires_at = expires_at;
+
+        Ok(true)
+    }
+
+    /// Returns the number of jobs in the queue, including leased jobs but not dead letters
+    pub fn len(&self) -> Result<u64> {
+        self.jobs.len()
+    }
+
+    /// Returns `true` if the queue contains no jobs, other than dead letters
+    pub fn is_empty(&self) -> Result<bool> {
+        self.jobs.is_empty()
+    }
+
+    /// Returns up to `max_jobs` jobs from the dead-letter table, oldest first
+    pub fn dead_letters(&self, max_jobs: usize) -> Result<Vec<DeadLetter>> {
+        let mut result = vec![];
+        for entry in self.dead_letters.iter()?.take(max_jobs) {
+            let (id, value) = entry?;
+            let (attempts, payload) = value.value();
+            result.push(DeadLetter {
+                id: id.value(),
+                attempts,
+                payload: payload.to_vec(),
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Moves the job `id` from the dead-letter table back to the queue, with its attempts reset to
+    /// zero. The job is visible immediately
+    ///
+    /// Returns `false` if there is no such dead letter
+    pub fn requeue_dead_letter(&mut self, id: u64) -> Result<bool> {
+        let payload = match self.dead_letters.remove(id)? {
+            Some(value) => value.value().1.to_vec(),
+            None => return Ok(false),
+        };
+        self.insert(id, 0, 0, &payload)?;
+
+        Ok(true)
+    }
+
+    /// Removes the job `id` from the dead-letter table
+    ///
+    /// Returns `false` if there is no such dead letter
+    pub fn remove_dead_letter(&mut self, id: u64) -> Result<bool> {
+        Ok(self.dead_letters.remove(id)?.is_some())
+    }
+
+    // Returns true if the lease on `job` is the latest lease of a job still in the queue
+    fn is_current(&self, job: &Job) -> Result<bool> {
+        Ok(matches!(
+            self.jobs.get(job.id)?.map(|x| x.value().1),
+            Some(attempts) if attempts == job.attempts
+        ))
+    }
+
+    fn insert(&mut self, id: u64, visible_at: u64, attempts: u32, payload: &[u8]) -> Result {
+        self.jobs.insert(id, (visible_at, attempts, payload))?;
+        self.visibility.insert((visible_at, id), ())?;
+
+        Ok(())
+    }
+
+    // Removes the job `id`, and returns its attempts and payload
+    fn remove(&mut self, id: u64) -> Result<Option<(u32, Vec<u8>)>> {
+        let (visible_at, attempts, payload) = match self.jobs.remove(id)? {
+            Some(value) => {
+                let (visible_at, attempts, payload) = value.value();
+                (visible_at, attempts, payload.to_vec())
+            }
+            None => return Ok(None),
+        };
+        self.visibility.remove((visible_at, id))?;
+
+        Ok(Some((attempts, payload)))
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{ReadableTable, Result, Table, TableDefinition, WriteTransaction};
+use std::time::Duration;
+
+const LEASES_SUFFIX: &str = "::leases";
+
+// Times are stored as nanoseconds since the epoch of the caller's clock
+pub(crate) fn to_nanos(time: Duration) -> u64 {
+    time.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+/// A lease held on a name in a [`LeaseTable`]
+///
+/// The guard does not release the lease when dropped, since that requires a write transaction.
+/// A lease which is not released expires once its time to live has elapsed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LeaseGuard {
+    name: String,
+    token: u64,
+    expires_at: Duration,
+}
+
+impl LeaseGuard {
+    /// Returns the name of the lease
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the fencing token of this acquisition of the lease
+    ///
+    /// Tokens strictly increase with each acquisition of the same name, even after it has been
+    /// released. A resource protected by the lease should reject requests carrying a token lower
+    /// than the highest it has seen, so that a holder whose lease expired while it was paused
+    /// cannot overwrite the work of the next holder
+    pub fn token(&self) -> u64 {
+        self.token
+    }
+
+    /// Returns the time at which the lease expires, as measured by the clock passed to
+    /// [`LeaseTable::acquire`]
+    pub fn expires_at(&self) -> Duration {
+        self.expires_at
+    }
+}
+
+/// Table of named leases, layered on top of a regular table
+///
+/// A lease gives exclusive ownership of a name until it is released or its time to live elapses.
+/// Time is supplied by the caller as the elapsed time since an epoch of its choosing, which must
+/// be the same for all users of the table, such as the UNIX epoch. Since acquisition happens
+/// inside a write transaction, two callers cannot acquire the same lease even if they race.
+///
+/// A lease table named `name` is stored in a table called `name::leases`
+pub struct LeaseTable<'db, 'txn> {
+    // Lease name -> (last fencing token, expiry time in nanoseconds). Released leases are kept,
+    // with an expiry of zero, so that their tokens are not reused
+    leases: Table<'db, 'txn, &'static str, (u64, u64)>,
+}
+
+impl<'db, 'txn> LeaseTable<'db, 'txn> {
+    /// Opens the lease table called `name`, creating it if it does not exist
+    pub fn open(transaction: &'txn WriteTransaction<'db>, name: &str) -> Result<Self> {
+        let leases = format!("{name}{LEASES_SUFFIX}");
+        Ok(Self {
+            leases: transaction.open_table(TableDefinition::new(&leases))?,
+        })
+    }
+
+    /// Acquires the lease `name` until `now + ttl`
+    ///
+    /// Returns `None` if the lease is held by someone else and has not expired as of `now`
+    pub fn acquire(
+        &mut self,
+        name: &str,
+        now: Duration,
+        ttl: Duration,
+    ) -> Result<Option<LeaseGuard>> {
+        let (token, expires_at) = self.leases.get(name)?.map(|x| x.value()).unwrap_or((0, 0));
+        if expires_at > to_nanos(now) {
+            return Ok(None);
+        }
+        let expires_at = now.saturating_add(ttl);
+        self.leases
+            .insert(name, (token + 1, to_nanos(expires_at)))?;
+
+        Ok(Some(LeaseGuard {
+            name: name.to_string(),
+            token: token + 1,
+            expires_at,
+        }))
+    }
+
+    /// Extends `lease` until `now + ttl`
+    ///
+    /// Returns `false`, and leaves the table unchanged, if the lease has expired or been acquired
+    /// by someone else
+    pub fn renew(&mut self, lease: &mut LeaseGuard, now: Duration, ttl: Duration) -> Result<bool> {
+        if !self.is_held(lease, now)? {
+            return Ok(false);
+        }
+        let expires_at = now.saturating_add(ttl);
+        self.leases
+            .insert(lease.name.as_str(), (lease.token, to_nanos(expires_at)))?;
+        lease.expires_at = expires_at;
+
+        Ok(true)
+    }
+
+    /// Releases `lease`, so that it can be acquired again immediately
+    ///
+    /// Returns `false` if the lease has expired or been acquired by someone else, in which case
+    /// the table is left unchanged
+    pub fn release(&mut self, lease: LeaseGuard, now: Duration) -> Result<bool> {
+        if !self.is_held(&lease, now)? {
+            return Ok(false);
+        }
+        self.leases.insert(lease.name.as_str(), (lease.token, 0))?;
+
+        Ok(true)
+    }
+
+    /// Returns `true` if `lease` is still the current acquisition of its name, and has not expired
+    /// as of `now`
+    ///
+    /// Checking this in the same write transaction as a write protected by the lease makes the
+    /// write conditional on the lease being held
+    pub fn is_held(&self, lease: &LeaseGuard, now: Duration) -> Result<bool> {
+        Ok(matches!(
+            self.leases.get(lease.name.as_str())?.map(|x| x.value()),
+            Some((token, expires_at)) if token == lease.token && expires_at > to_nanos(now)
+        ))
+    }
+
+    /// Returns the current holder of the lease `name`, or `None` if it is not held as of `now`
+    pub fn holder(&self, name: &str, now: Duration) -> Result<Option<LeaseGuard>> {
+        match self.leases.get(name)?.map(|x| x.value()) {
+            Some((token, expires_at)) if expires_at > to_nanos(now) => Ok(Some(LeaseGuard {
+                name: name.to_string(),
+                token,
+                expires_at: Duration::from_nanos(expires_at),
+            })),
+            _ => Ok(None),
+        }
+    }
+}
@@ -6,33 +6,108 @@
     clippy::cast_sign_loss
 )]
 
+pub use blob_store::{BlobHash, BlobStore, ReadOnlyBlobStore};
+pub use cache_table::{CacheTable, ReadOnlyCacheTable};
+pub use cascade::{ForeignKey, ReferencingTable};
+pub use clock::{Clock, SystemClock};
+pub use content_hash::{ContentChunk, ContentHash, ContentNode};
 pub use db::{
-    Builder, Database, MultimapTableDefinition, MultimapTableHandle, TableDefinition, TableHandle,
-    UntypedMultimapTableHandle, UntypedTableHandle,
+    Builder, Database, FileProtectionClass, MultimapTableDefinition, MultimapTableHandle,
+    RecoveryReport, RetryPolicy, TableDefinition, TableHandle, UntypedMultimapTableHandle,
+    UntypedTableHandle,
 };
 pub use error::Error;
+pub use fragmentation::FragmentationReport;
+pub use histogram::{HistogramBucket, KeyHistogram};
+pub use history::History;
+pub use importer::{ImportProgress, Importer};
+pub use inverted_index::{
+    DefaultTokenizer, InvertedIndex, ReadOnlyInvertedIndex, SearchMode, SearchResults, Tokenizer,
+};
+pub use job_queue::{DeadLetter, Job, JobQueue};
+pub use lease_table::{LeaseGuard, LeaseTable};
 pub use multimap_table::{
     MultimapRange, MultimapTable, MultimapValue, ReadOnlyMultimapTable, ReadableMultimapTable,
 };
-pub use table::{Drain, DrainFilter, Range, ReadOnlyTable, ReadableTable, Table};
-pub use transactions::{DatabaseStats, Durability, ReadTransaction, WriteTransaction};
-pub use tree_store::{AccessGuard, AccessGuardMut, Savepoint};
-pub use types::{RedbKey, RedbValue, TypeName};
+pub use outbox::{Outbox, OutboxMessage, ReadOnlyOutbox};
+pub use pressure::Pressure;
+pub use priority_queue::{PriorityQueueTable, ReadOnlyPriorityQueueTable};
+pub use quarantine::QuarantinedPage;
+pub use sorter::{ExternalSorter, Sorted};
+pub use staging_table::{StagingRange, StagingTable};
+pub use table::{
+    diff_tables, merge_tables, DiffEntry, Drain, DrainFilter, MergedRange, OwnedReadTable, Range,
+    ReadOnlyTable, ReadableTable, Table, TableDiff,
+};
+pub use table_group::TableGroup;
+pub use time_series::{ReadOnlyTimeSeriesTable, TimeSeriesTable};
+pub use transactions::{
+    CommitSummary, DatabaseStats, DropBehavior, Durability, ReadTransaction, SavepointMetadata,
+    SpaceReport, SystemTableDefinition, TableWriteStats, WriteTransaction,
+};
+pub use tree_store::{
+    AccessGuard, AccessGuardMut, AllocationStrategy, CacheStats, ChecksumAlgorithm, FillPolicy,
+    Savepoint,
+};
+pub use types::{BigEndian, OrderedF32, OrderedF64, RedbKey, RedbValue, TypeName, TypeNameCheck};
+pub use vector::{knn_scan, Distance, FixedVector};
+pub use watch::{KeyChanged, KeyWatch};
+pub use write_queue::{WriteFuture, WriteQueue};
 
 type Result<T = (), E = Error> = std::result::Result<T, E>;
 
 #[cfg(feature = "python")]
 pub use crate::python::redb;
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
+mod blob_store;
+mod cache_table;
+mod cascade;
+mod clock;
+mod columnar;
+mod content_hash;
 mod db;
 mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fragmentation;
+#[cfg(fuzzing)]
+#[doc(hidden)]
+pub mod fuzzing;
+mod histogram;
+mod history;
+mod importer;
+#[cfg(feature = "interop")]
+pub mod interop;
+mod inverted_index;
+mod job_queue;
+mod lease_table;
 mod multimap_table;
+mod outbox;
+mod pressure;
+mod priority_queue;
 #[cfg(feature = "python")]
 mod python;
+mod quarantine;
+#[cfg(feature = "query")]
+pub mod query;
 mod sealed;
+#[cfg(feature = "server")]
+pub mod server;
+mod sorter;
+pub mod spatial;
+mod staging_table;
+mod sync;
 mod table;
+mod table_group;
+pub mod testing;
+mod time_series;
 mod transaction_tracker;
 mod transactions;
 mod tree_store;
 mod tuple_types;
 mod types;
+mod vector;
+mod watch;
+mod write_queue;
@@ -1,5 +1,6 @@
 use crate::multimap_table::DynamicCollectionType::{Inline, Subtree};
 use crate::sealed::Sealed;
+use crate::sync::Mutex;
 use crate::tree_store::{
     AllPageNumbersBtreeIter, Btree, BtreeMut, BtreeRangeIter, Checksum, LeafAccessor, Page,
     PageHint, PageNumber, RawLeafBuilder, TransactionalMemory, BRANCH, LEAF, MAX_VALUE_LENGTH,
@@ -12,7 +13,7 @@ use std::marker::PhantomData;
 use std::mem;
 use std::mem::size_of;
 use std::ops::{RangeBounds, RangeFull};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 pub(crate) fn parse_subtree_roots<T: Page>(
     page: &T,
@@ -40,6 +41,32 @@ pub(crate) fn parse_subtree_roots<T: Page>(
     }
 }
 
+// Returns the root of the subtree in which a multimap table value stores its collection, or None if
+// the collection is stored inline
+pub(crate) fn subtree_root(value: &[u8]) -> Option<(PageNumber, Checksum)> {
+    let collection = <&DynamicCollection>::from_bytes(value);
+    if matches!(collection.collection_type(), DynamicCollectionType::Subtree) {
+        Some(collection.as_subtree())
+    } else {
+        None
+    }
+}
+
+// Replaces the root of the subtree in which a multimap table value stores its collection, with the
+// one returned by `f`. Returns None if the collection is stored inline
+pub(crate) fn relocate_subtree(
+    value: &[u8],
+    f: impl FnOnce((PageNumber, Checksum)) -> Result<(PageNumber, Checksum)>,
+) -> Result<Option<Vec<u8>>> {
+    let collection = <&DynamicCollection>::from_bytes(value);
+    if matches!(collection.collection_type(), DynamicCollectionType::Subtree) {
+        let (root, checksum) = f(collection.as_subtree())?;
+        Ok(Some(DynamicCollection::make_subtree_data(root, checksum)))
+    } else {
+        Ok(None)
+    }
+}
+
 pub(crate) struct LeafKeyIter<'a> {
     inline_collection: AccessGuard<'a, &'static DynamicCollection>,
     fixed_key_size: Option<usize>,
@@ -142,10 +169,12 @@ struct DynamicCollection {
 }
 
 impl RedbValue for &DynamicCollection {
-    type SelfType<'a> = &'a DynamicCollection
+    type SelfType<'a>
+        = &'a DynamicCollection
     where
         Self: 'a;
-    type AsBytes<'a> = &'a [u8]
+    type AsBytes<'a>
+        = &'a [u8]
     where
         Self: 'a;
 
@@ -175,7 +204,8 @@ impl RedbValue for &DynamicCollection {
 
 impl DynamicCollection {
     fn new(data: &[u8]) -> &Self {
-        unsafe { mem::transmute(data) }
+        // Safety: DynamicCollection is repr(transparent) over [u8]
+        unsafe { &*(data as *const [u8] as *const DynamicCollection) }
     }
 
     fn collection_type(&self) -> DynamicCollectionType {
@@ -474,6 +504,7 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> MultimapTable<'db, '
         if key_bytes.as_ref().len() > MAX_VALUE_LENGTH {
             return Err(Error::ValueTooLarge(key_bytes.as_ref().len()));
         }
+        self.transaction.check_transaction_size()?;
         let get_result = self.tree.get(key.borrow())?;
         let existed = if get_result.is_some() {
             #[allow(clippy::unnecessary_unwrap)]
@@ -613,6 +644,7 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> MultimapTable<'db, '
         K: 'a,
         V: 'a,
     {
+        self.transaction.check_transaction_size()?;
         let get_result = self.tree.get(key.borrow())?;
         if get_result.is_none() {
             return Ok(false);
@@ -727,6 +759,7 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> MultimapTable<'db, '
     where
         K: 'a,
     {
+        self.transaction.check_transaction_size()?;
         // Safety: No other references to this table can exist.
         // Tables can only be opened mutably in one location (see Error::TableAlreadyOpen),
         // and we borrow &mut self.
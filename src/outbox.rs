@@ -0,0 +1,149 @@
+use crate::{
+    ReadOnlyTable, ReadTransaction, ReadableTable, Result, Table, TableDefinition, WriteTransaction,
+};
+
+const MESSAGES_SUFFIX: &str = "::messages";
+const SEQUENCE_SUFFIX: &str = "::outbox";
+
+/// A message stored in an [`Outbox`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutboxMessage {
+    id: u64,
+    payload: Vec<u8>,
+}
+
+impl OutboxMessage {
+    /// Returns the id of the message
+    ///
+    /// Ids are assigned in increasing order, and are never reused, so they can be sent along with
+    /// the message to allow the receiver to discard duplicate deliveries
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the contents of the message
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+fn poll_batch(
+    messages: &impl ReadableTable<u64, &'static [u8]>,
+    max_messages: usize,
+) -> Result<Vec<OutboxMessage>> {
+    let mut result = vec![];
+    for entry in messages.iter()?.take(max_messages) {
+        let (id, payload) = entry?;
+        result.push(OutboxMessage {
+            id: id.value(),
+            payload: payload.value().to_vec(),
+        });
+    }
+
+    Ok(result)
+}
+
+/// Queue of outgoing messages, layered on top of a regular table, for implementing the
+/// transactional outbox pattern
+///
+/// Messages are pushed in the same write transaction as the data changes which they announce, so
+/// that a message is stored if and only if those changes are committed. A delivery worker then
+/// reads the oldest messages with [`ReadOnlyOutbox::poll_batch`], sends them, and removes them with
+/// [`Outbox::ack`] once they have been delivered. A message is delivered at least once, since the
+/// worker may crash between sending and acknowledging it, so receivers should use
+/// [`OutboxMessage::id`] to discard duplicates.
+///
+/// An outbox named `name` is stored in a table called `name::messages`, and assigns ids from the
+/// sequence `name::outbox`
+pub struct Outbox<'db, 'txn> {
+    transaction: &'txn WriteTransaction<'db>,
+    sequence: String,
+    messages: Table<'db, 'txn, u64, &'static [u8]>,
+}
+
+impl<'db, 'txn> Outbox<'db, 'txn> {
+    /// Opens the outbox called `name`, creating it if it does not exist
+    pub fn open(transaction: &'txn WriteTransaction<'db>, name: &str) -> Result<Self> {
+        let messages = format!("{name}{MESSAGES_SUFFIX}");
+        Ok(Self {
+            transaction,
+            sequence: format!("{name}{SEQUENCE_SUFFIX}"),
+            messages: transaction.open_table(TableDefinition::new(&messages))?,
+        })
+    }
+
+    /// Adds a message to the outbox, and returns its id
+    pub fn push(&mut self, payload: &[u8]) -> Result<u64> {
+        let id = self.transaction.next_sequence(&self.sequence)?;
+        self.messages.insert(id, payload)?;
+
+        Ok(id)
+    }
+
+    /// Returns up to `max_messages` of the oldest messages, in the order they were pushed
+    pub fn poll_batch(&self, max_messages: usize) -> Result<Vec<OutboxMessage>> {
+        poll_batch(&self.messages, max_messages)
+    }
+
+    /// Removes the message with the given id, once it has been delivered
+    ///
+    /// Returns `false` if the message does not exist, for example because it was already
+    /// acknowledged
+    pub fn ack(&mut self, id: u64) -> Result<bool> {
+        Ok(self.messages.remove(id)?.is_some())
+    }
+
+    /// Removes all messages with an id less than or equal to `id`, and returns how many were removed
+    ///
+    /// This acknowledges a whole batch returned by [`Self::poll_batch`], when the messages are
+    /// delivered in order
+    pub fn ack_through(&mut self, id: u64) -> Result<u64> {
+        let mut removed = 0;
+        for entry in self.messages.drain(..=id)? {
+            entry?;
+            removed += 1;
+        }
+
+        Ok(removed)
+    }
+
+    /// Returns the number of messages which have not been acknowledged
+    pub fn len(&self) -> Result<u64> {
+        self.messages.len()
+    }
+
+    /// Returns `true` if all messages have been acknowledged
+    pub fn is_empty(&self) -> Result<bool> {
+        self.messages.is_empty()
+    }
+}
+
+/// Read-only view of an [`Outbox`], for a delivery worker to poll without blocking writers
+pub struct ReadOnlyOutbox<'txn> {
+    messages: ReadOnlyTable<'txn, u64, &'static [u8]>,
+}
+
+impl<'txn> ReadOnlyOutbox<'txn> {
+    /// Opens the outbox called `name`
+    pub fn open(transaction: &'txn ReadTransaction, name: &str) -> Result<Self> {
+        let messages = format!("{name}{MESSAGES_SUFFIX}");
+        Ok(Self {
+            messages: transaction.open_table(TableDefinition::new(&messages))?,
+        })
+    }
+
+    /// Returns up to `max_messages` of the oldest messages, in the order they were pushed
+    pub fn poll_batch(&self, max_messages: usize) -> Result<Vec<OutboxMessage>> {
+        poll_batch(&self.messages, max_messages)
+    }
+
+    /// Returns the number of messages which have not been acknowledged
+    pub fn len(&self) -> Result<u64> {
+        self.messages.len()
+    }
+
+    /// Returns `true` if all messages have been acknowledged
+    pub fn is_empty(&self) -> Result<bool> {
+        self.messages.is_empty()
+    }
+}
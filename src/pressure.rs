@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+pub(crate) type PressureCallback = Arc<dyn Fn(&Pressure) + Send + Sync>;
+
+/// How close the database file is to the size set by [`crate::Builder::set_max_size`]
+///
+/// Free pages within the file are not subtracted from its size, since they may be too fragmented
+/// to satisfy a large allocation, and a transaction which cannot reuse them grows the file
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Pressure {
+    file_bytes: u64,
+    max_bytes: u64,
+}
+
+impl Pressure {
+    /// Current length of the database file, including any growth by the in-progress transaction
+    pub fn file_bytes(&self) -> u64 {
+        self.file_bytes
+    }
+
+    /// The maximum size of the database file
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    /// Length of the file as a percentage of the maximum size, rounded down
+    ///
+    /// This is greater than 100 if the file was already larger than the maximum size when the
+    /// database was opened
+    pub fn percent(&self) -> u64 {
+        if self.max_bytes == 0 {
+            return u64::MAX;
+        }
+        let percent = u128::from(self.file_bytes) * 100 / u128::from(self.max_bytes);
+        percent.try_into().unwrap_or(u64::MAX)
+    }
+}
+
+// The maximum size of the database file, and the callback to notify as the file approaches it
+pub(crate) struct SizeLimit {
+    max_bytes: u64,
+    // Percentage of max_bytes at which the callback is notified
+    callback: Option<(u8, PressureCallback)>,
+}
+
+impl SizeLimit {
+    pub(crate) fn new(max_bytes: u64, callback: Option<(u8, PressureCallback)>) -> Self {
+        Self {
+            max_bytes,
+            callback,
+        }
+    }
+
+    pub(crate) fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    pub(crate) fn pressure(&self, file_bytes: u64) -> Pressure {
+        Pressure {
+            file_bytes,
+            max_bytes: self.max_bytes,
+        }
+    }
+
+    // Notifies the callback, if the file has reached its threshold
+    pub(crate) fn notify(&self, file_bytes: u64) {
+        if let Some((threshold, callback)) = self.callback.as_ref() {
+            let pressure = self.pressure(file_bytes);
+            if pressure.percent() >= u64::from(*threshold) {
+                callback(&pressure);
+            }
+        }
+    }
+}
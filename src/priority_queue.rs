@@ -0,0 +1,144 @@
+use crate::types::{RedbKey, RedbValue};
+use crate::{
+    AccessGuard, ReadOnlyTable, ReadTransaction, ReadableTable, Result, Table, TableDefinition,
+    WriteTransaction,
+};
+use std::borrow::Borrow;
+
+const ENTRIES_SUFFIX: &str = "::entries";
+const SEQUENCE_SUFFIX: &str = "::priority_queue";
+
+type MinEntry<'a, P, V> = Option<(AccessGuard<'a, P>, AccessGuard<'a, V>)>;
+
+fn peek_min<'a, P: RedbKey + 'static, V: RedbValue + 'static>(
+    entries: &'a impl ReadableTable<(P, u64), V>,
+) -> Result<MinEntry<'a, P, V>> {
+    match entries.iter()?.next() {
+        Some(entry) => {
+            let (key, value) = entry?;
+            let priority = P::as_bytes(&key.value().0).as_ref().to_vec();
+            Ok(Some((AccessGuard::with_owned_value(priority), value)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Priority queue, layered on top of a regular table
+///
+/// Entries are ordered by their priority, as defined by the ordering of `P`, with the smallest
+/// priority popped first. Entries with the same priority are popped in the order they were pushed.
+/// Popping a batch with [`Self::pop_min_batch`] reads the entries from the left edge of the table,
+/// and then removes them all at once, which is much cheaper than popping them one at a time.
+///
+/// A priority queue named `name` is stored in a table called `name::entries`, and orders entries
+/// of equal priority with the sequence `name::priority_queue`
+pub struct PriorityQueueTable<'db, 'txn, P: RedbKey + 'static, V: RedbValue + 'static> {
+    transaction: &'txn WriteTransaction<'db>,
+    sequence: String,
+    entries: Table<'db, 'txn, (P, u64), V>,
+}
+
+impl<'db, 'txn, P: RedbKey + 'static, V: RedbValue + 'static> PriorityQueueTable<'db, 'txn, P, V> {
+    /// Opens the priority queue called `name`, creating it if it does not exist
+    pub fn open(transaction: &'txn WriteTransaction<'db>, name: &str) -> Result<Self> {
+        let entries = format!("{name}{ENTRIES_SUFFIX}");
+        Ok(Self {
+            transaction,
+            sequence: format!("{name}{SEQUENCE_SUFFIX}"),
+            entries: transaction.open_table(TableDefinition::new(&entries))?,
+        })
+    }
+
+    /// Adds `value` to the queue with the given priority
+    pub fn push<'a>(
+        &mut self,
+        priority: P::SelfType<'a>,
+        value: impl Borrow<V::SelfType<'a>>,
+    ) -> Result
+    where
+        P: 'a,
+        V: 'a,
+    {
+        let sequence = self.transaction.next_sequence(&self.sequence)?;
+        self.entries.insert((priority, sequence), value)?;
+
+        Ok(())
+    }
+
+    /// Returns the entry with the smallest priority, without removing it
+    pub fn peek_min(&self) -> Result<MinEntry<'_, P, V>> {
+        peek_min(&self.entries)
+    }
+
+    /// Removes and returns the entry with the smallest priority
+    pub fn pop_min(&mut self) -> Result<MinEntry<'_, P, V>> {
+        Ok(self.pop_min_batch(1)?.pop())
+    }
+
+    /// Removes and returns up to `n` entries with the smallest priorities, in the order they would
+    /// be popped
+    pub fn pop_min_batch(
+        &mut self,
+        n: usize,
+    ) -> Result<Vec<(AccessGuard<'_, P>, AccessGuard<'_, V>)>> {
+        let mut popped = vec![];
+        let mut last_key = None;
+        for entry in self.entries.iter()?.take(n) {
+            let (key, value) = entry?;
+            let key = key.value();
+            popped.push((
+                AccessGuard::with_owned_value(P::as_bytes(&key.0).as_ref().to_vec()),
+                AccessGuard::with_owned_value(V::as_bytes(&value.value()).as_ref().to_vec()),
+            ));
+            last_key = Some(<(P, u64)>::as_bytes(&key));
+        }
+        if let Some(last_key) = last_key {
+            let removed = self
+                .entries
+                .remove_range(..=<(P, u64)>::from_bytes(&last_key))?;
+            assert_eq!(removed, u64::try_from(popped.len()).unwrap());
+        }
+
+        Ok(popped)
+    }
+
+    /// Returns the number of entries in the queue
+    pub fn len(&self) -> Result<u64> {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the queue is empty
+    pub fn is_empty(&self) -> Result<bool> {
+        self.entries.is_empty()
+    }
+}
+
+/// Read-only view of a [`PriorityQueueTable`]
+pub struct ReadOnlyPriorityQueueTable<'txn, P: RedbKey + 'static, V: RedbValue + 'static> {
+    entries: ReadOnlyTable<'txn, (P, u64), V>,
+}
+
+impl<'txn, P: RedbKey + 'static, V: RedbValue + 'static> ReadOnlyPriorityQueueTable<'txn, P, V> {
+    /// Opens the priority queue called `name`
+    pub fn open(transaction: &'txn ReadTransaction, name: &str) -> Result<Self> {
+        let entries = format!("{name}{ENTRIES_SUFFIX}");
+        Ok(Self {
+            entries: transaction.open_table(TableDefinition::new(&entries))?,
+        })
+    }
+
+    /// Returns the entry with the smallest priority
+    pub fn peek_min(&self) -> Result<MinEntry<'_, P, V>> {
+        peek_min(&self.entries)
+    }
+
+    /// Returns the number of entries in the queue
+    pub fn len(&self) -> Result<u64> {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the queue is empty
+    pub fn is_empty(&self) -> Result<bool> {
+        self.entries.is_empty()
+    }
+}
@@ -1,6 +1,348 @@
+//! Python bindings
+//!
+//! Only tables with `&[u8]` keys and values are exposed, and are accessed by name as `bytes`.
+//! Transactions are context managers: a write transaction is committed when its `with` block
+//! exits normally and aborted if it exits with an exception.
+
+use crate::{ReadTransaction, ReadableTable, TableDefinition, TableHandle, WriteTransaction};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::ops::Bound;
+use std::sync::Arc;
+
+// I/O errors are raised as OSError, and all others as RuntimeError
+fn to_py(err: crate::Error) -> PyErr {
+    match err {
+        crate::Error::Io(err) => err.into(),
+        err => PyRuntimeError::new_err(err.to_string()),
+    }
+}
+
+fn closed() -> PyErr {
+    PyValueError::new_err("transaction is closed")
+}
+
+fn definition(name: &str) -> TableDefinition<'_, &'static [u8], &'static [u8]> {
+    TableDefinition::new(name)
+}
+
+fn bytes(py: Python, data: &[u8]) -> PyObject {
+    PyBytes::new(py, data).into()
+}
+
+fn get(
+    py: Python,
+    table: &impl ReadableTable<&'static [u8], &'static [u8]>,
+    key: &[u8],
+) -> PyResult<Option<PyObject>> {
+    let value = table.get(key).map_err(to_py)?;
+    Ok(value.map(|value| bytes(py, value.value())))
+}
+
+fn range(
+    py: Python,
+    table: &impl ReadableTable<&'static [u8], &'static [u8]>,
+    start: Option<&[u8]>,
+    end: Option<&[u8]>,
+) -> PyResult<Vec<(PyObject, PyObject)>> {
+    let start = start.map_or(Bound::Unbounded, Bound::Included);
+    let end = end.map_or(Bound::Unbounded, Bound::Excluded);
+    let mut entries = vec![];
+    for entry in table.range::<&[u8]>((start, end)).map_err(to_py)? {
+        let (key, value) = entry.map_err(to_py)?;
+        entries.push((bytes(py, key.value()), bytes(py, value.value())));
+    }
+    Ok(entries)
+}
+
+/// An open database
+#[pyclass(name = "Database", unsendable)]
+pub struct PyDatabase {
+    inner: Arc<crate::Database>,
+}
+
+#[pymethods]
+impl PyDatabase {
+    /// Opens the database at `path`, creating it if it does not exist
+    #[staticmethod]
+    fn create(path: &str) -> PyResult<Self> {
+        let inner = crate::Database::create(path).map_err(to_py)?;
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    /// Opens the existing database at `path`
+    #[staticmethod]
+    fn open(path: &str) -> PyResult<Self> {
+        let inner = crate::Database::open(path).map_err(to_py)?;
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    fn begin_write(&self) -> PyResult<PyWriteTransaction> {
+        // The transaction holds a clone of the Arc, so the database outlives it
+        let db: &'static crate::Database = unsafe { &*Arc::as_ptr(&self.inner) };
+        let inner = db.begin_write().map_err(to_py)?;
+        Ok(PyWriteTransaction {
+            inner: Some(inner),
+            _db: self.inner.clone(),
+        })
+    }
+
+    fn begin_read(&self) -> PyResult<PyReadTransaction> {
+        // See begin_write()
+        let db: &'static crate::Database = unsafe { &*Arc::as_ptr(&self.inner) };
+        let inner = db.begin_read().map_err(to_py)?;
+        Ok(PyReadTransaction {
+            inner: Some(inner),
+            _db: self.inner.clone(),
+        })
+    }
+}
+
+/// A write transaction
+#[pyclass(name = "WriteTransaction", unsendable)]
+pub struct PyWriteTransaction {
+    // Declared before the database, so that it is dropped first. None once committed or aborted
+    inner: Option<WriteTransaction<'static>>,
+    _db: Arc<crate::Database>,
+}
+
+impl PyWriteTransaction {
+    fn txn(&self) -> PyResult<&WriteTransaction<'static>> {
+        self.inner.as_ref().ok_or_else(closed)
+    }
+}
+
+#[pymethods]
+impl PyWriteTransaction {
+    /// Returns the table named `name`, which is created if it does not exist
+    fn open_table(slf: &PyCell<Self>, name: String) -> PyResult<PyWriteTable> {
+        // Open the table once, so that a table of the wrong type is reported immediately
+        slf.borrow()
+            .txn()?
+            .open_table(definition(&name))
+            .map_err(to_py)?;
+        Ok(PyWriteTable {
+            txn: slf.into(),
+            name,
+        })
+    }
+
+    fn list_tables(&self) -> PyResult<Vec<String>> {
+        let tables = self.txn()?.list_tables().map_err(to_py)?;
+        Ok(tables.map(|table| table.name().to_string()).collect())
+    }
+
+    fn commit(&mut self) -> PyResult<()> {
+        self.inner
+            .take()
+            .ok_or_else(closed)?
+            .commit()
+            .map_err(to_py)
+    }
+
+    fn abort(&mut self) -> PyResult<()> {
+        self.inner.take().ok_or_else(closed)?.abort().map_err(to_py)
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        exc_type: &PyAny,
+        _exc_value: &PyAny,
+        _traceback: &PyAny,
+    ) -> PyResult<bool> {
+        // The transaction may already have been committed or aborted explicitly
+        if let Some(txn) = self.inner.take() {
+            if exc_type.is_none() {
+                txn.commit().map_err(to_py)?;
+            } else {
+                txn.abort().map_err(to_py)?;
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// A read transaction
+#[pyclass(name = "ReadTransaction", unsendable)]
+pub struct PyReadTransaction {
+    // Declared before the database, so that it is dropped first. None once closed
+    inner: Option<ReadTransaction<'static>>,
+    _db: Arc<crate::Database>,
+}
+
+impl PyReadTransaction {
+    fn txn(&self) -> PyResult<&ReadTransaction<'static>> {
+        self.inner.as_ref().ok_or_else(closed)
+    }
+}
+
+#[pymethods]
+impl PyReadTransaction {
+    /// Returns the table named `name`, which must exist
+    fn open_table(slf: &PyCell<Self>, name: String) -> PyResult<PyReadOnlyTable> {
+        slf.borrow()
+            .txn()?
+            .open_table(definition(&name))
+            .map_err(to_py)?;
+        Ok(PyReadOnlyTable {
+            txn: slf.into(),
+            name,
+        })
+    }
+
+    fn list_tables(&self) -> PyResult<Vec<String>> {
+        let tables = self.txn()?.list_tables().map_err(to_py)?;
+        Ok(tables.map(|table| table.name().to_string()).collect())
+    }
+
+    /// Releases the snapshot held by this transaction
+    fn close(&mut self) -> PyResult<()> {
+        self.inner.take().ok_or_else(closed)?;
+        Ok(())
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: &PyAny,
+        _exc_value: &PyAny,
+        _traceback: &PyAny,
+    ) -> PyResult<bool> {
+        self.inner = None;
+        Ok(false)
+    }
+}
+
+/// A table in a write transaction
+#[pyclass(name = "Table", unsendable)]
+pub struct PyWriteTable {
+    txn: Py<PyWriteTransaction>,
+    name: String,
+}
+
+impl PyWriteTable {
+    fn with_table<T>(
+        &self,
+        py: Python,
+        f: impl FnOnce(&mut crate::Table<&'static [u8], &'static [u8]>) -> PyResult<T>,
+    ) -> PyResult<T> {
+        let txn = self.txn.borrow(py);
+        let mut table = txn
+            .txn()?
+            .open_table(definition(&self.name))
+            .map_err(to_py)?;
+        f(&mut table)
+    }
+}
+
+#[pymethods]
+impl PyWriteTable {
+    /// Inserts `value` under `key`, and returns the previous value if there was one
+    fn insert(&self, py: Python, key: &[u8], value: &[u8]) -> PyResult<Option<PyObject>> {
+        self.with_table(py, |table| {
+            let old = table.insert(key, value).map_err(to_py)?;
+            Ok(old.map(|old| bytes(py, old.value())))
+        })
+    }
+
+    /// Removes `key`, and returns its value if it was present
+    fn remove(&self, py: Python, key: &[u8]) -> PyResult<Option<PyObject>> {
+        self.with_table(py, |table| {
+            let old = table.remove(key).map_err(to_py)?;
+            Ok(old.map(|old| bytes(py, old.value())))
+        })
+    }
+
+    fn get(&self, py: Python, key: &[u8]) -> PyResult<Option<PyObject>> {
+        self.with_table(py, |table| get(py, table, key))
+    }
+
+    /// Returns the entries with keys in `[start, end)` as a list of `(key, value)` tuples. A
+    /// bound which is `None` is unbounded
+    #[pyo3(signature = (start=None, end=None))]
+    fn range(
+        &self,
+        py: Python,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> PyResult<Vec<(PyObject, PyObject)>> {
+        self.with_table(py, |table| range(py, table, start, end))
+    }
+
+    fn __len__(&self, py: Python) -> PyResult<usize> {
+        self.with_table(py, |table| {
+            let len = table.len().map_err(to_py)?;
+            Ok(len.try_into().unwrap())
+        })
+    }
+}
+
+/// A table in a read transaction
+#[pyclass(name = "ReadOnlyTable", unsendable)]
+pub struct PyReadOnlyTable {
+    txn: Py<PyReadTransaction>,
+    name: String,
+}
+
+impl PyReadOnlyTable {
+    fn with_table<T>(
+        &self,
+        py: Python,
+        f: impl FnOnce(&crate::ReadOnlyTable<&'static [u8], &'static [u8]>) -> PyResult<T>,
+    ) -> PyResult<T> {
+        let txn = self.txn.borrow(py);
+        let table = txn
+            .txn()?
+            .open_table(definition(&self.name))
+            .map_err(to_py)?;
+        f(&table)
+    }
+}
+
+#[pymethods]
+impl PyReadOnlyTable {
+    fn get(&self, py: Python, key: &[u8]) -> PyResult<Option<PyObject>> {
+        self.with_table(py, |table| get(py, table, key))
+    }
+
+    /// Returns the entries with keys in `[start, end)` as a list of `(key, value)` tuples. A
+    /// bound which is `None` is unbounded
+    #[pyo3(signature = (start=None, end=None))]
+    fn range(
+        &self,
+        py: Python,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> PyResult<Vec<(PyObject, PyObject)>> {
+        self.with_table(py, |table| range(py, table, start, end))
+    }
+
+    fn __len__(&self, py: Python) -> PyResult<usize> {
+        self.with_table(py, |table| {
+            let len = table.len().map_err(to_py)?;
+            Ok(len.try_into().unwrap())
+        })
+    }
+}
 
 #[pymodule]
-pub fn redb(_py: Python, _m: &PyModule) -> PyResult<()> {
+pub fn redb(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyDatabase>()?;
+    m.add_class::<PyWriteTransaction>()?;
+    m.add_class::<PyReadTransaction>()?;
+    m.add_class::<PyWriteTable>()?;
+    m.add_class::<PyReadOnlyTable>()?;
     Ok(())
 }
@@ -0,0 +1,137 @@
+use crate::tree_store::{InternalTableDefinition, PageNumber, RawBtree, TransactionalMemory};
+use crate::types::RedbValue;
+use crate::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[cfg(feature = "logging")]
+use log::warn;
+
+pub(crate) type QuarantineCallback = Arc<dyn Fn(&QuarantinedPage) + Send + Sync>;
+
+/// A page which failed checksum verification, when the database was opened with
+/// [`crate::Builder::set_quarantine_corrupted_pages`]
+///
+/// The entries stored under the page are skipped by iterators, and looking up any key between
+/// [`Self::lower_bound`] and [`Self::upper_bound`] returns [`crate::Error::Corrupted`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QuarantinedPage {
+    table: Option<String>,
+    page: PageNumber,
+    lower_bound: Option<Vec<u8>>,
+    upper_bound: Option<Vec<u8>>,
+}
+
+impl QuarantinedPage {
+    /// Name of the table which the page belongs to, or `None` if it belongs to the directory of
+    /// tables, in which case the tables whose names are between the bounds cannot be opened
+    pub fn table(&self) -> Option<&str> {
+        self.table.as_deref()
+    }
+
+    /// Serialized key which all the entries under the page are greater than, or `None` if the page
+    /// holds the first entries of the table
+    pub fn lower_bound(&self) -> Option<&[u8]> {
+        self.lower_bound.as_deref()
+    }
+
+    /// Serialized key which all the entries under the page are less than or equal to, or `None` if
+    /// the page holds the last entries of the table
+    pub fn upper_bound(&self) -> Option<&[u8]> {
+        self.upper_bound.as_deref()
+    }
+}
+
+// The set of quarantined pages, consulted by reads when they descend into a page
+pub(crate) struct Quarantine {
+    pages: HashMap<PageNumber, QuarantinedPage>,
+    callback: QuarantineCallback,
+}
+
+impl Quarantine {
+    pub(crate) fn new(pages: Vec<QuarantinedPage>, callback: QuarantineCallback) -> Self {
+        Self {
+            pages: pages.into_iter().map(|p| (p.page, p)).collect(),
+            callback,
+        }
+    }
+
+    // Returns the quarantined page, and notifies the callback, if `page` is quarantined
+    pub(crate) fn check(&self, page: PageNumber) -> Option<&QuarantinedPage> {
+        let quarantined = self.pages.get(&page)?;
+        (self.callback)(quarantined);
+        Some(quarantined)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    pub(crate) fn pages(&self) -> Vec<QuarantinedPage> {
+        let mut result: Vec<QuarantinedPage> = self.pages.values().cloned().collect();
+        result.sort_by_key(|p| p.page);
+        result
+    }
+}
+
+// Verifies the checksums of the directory of tables and of every table in it, and returns the pages
+// which failed. Multimap tables are verified, but not the value collections nested within them
+pub(crate) fn find_corrupted_pages(mem: &TransactionalMemory) -> Result<Vec<QuarantinedPage>> {
+    let mut result = vec![];
+    let (root, checksum) = if let Some(root) = mem.get_data_root() {
+        root
+    } else {
+        return Ok(result);
+    };
+
+    let mut tables = vec![];
+    RawBtree::new(
+        Some((root, checksum)),
+        <&str>::fixed_width(),
+        InternalTableDefinition::fixed_width(),
+        mem,
+    )
+    .scan_for_corruption(
+        |page, lower, upper| result.push(quarantined(None, page, lower, upper)),
+        |key, value| {
+            tables.push((
+                <&str>::from_bytes(key).to_string(),
+                InternalTableDefinition::from_bytes(value),
+            ));
+        },
+    )?;
+
+    for (name, definition) in tables {
+        RawBtree::new(
+            definition.get_root(),
+            definition.get_fixed_key_size(),
+            definition.get_fixed_value_size(),
+            mem,
+        )
+        .scan_for_corruption(
+            |page, lower, upper| result.push(quarantined(Some(&name), page, lower, upper)),
+            |_, _| {},
+        )?;
+    }
+
+    Ok(result)
+}
+
+fn quarantined(
+    table: Option<&str>,
+    page: PageNumber,
+    lower: Option<&[u8]>,
+    upper: Option<&[u8]>,
+) -> QuarantinedPage {
+    #[cfg(feature = "logging")]
+    warn!(
+        "Quarantining corrupted page {:?} of table {:?}",
+        page, table
+    );
+    QuarantinedPage {
+        table: table.map(|x| x.to_string()),
+        page,
+        lower_bound: lower.map(|x| x.to_vec()),
+        upper_bound: upper.map(|x| x.to_vec()),
+    }
+}
@@ -0,0 +1,284 @@
+//! A small query layer for filtering and projecting the entries of a table
+//!
+//! A [`Query`] is built from any [`ReadableTable`] with [`Queryable::query`]. Constraints on the
+//! key, added with [`Query::key_range`], are combined into the narrowest key range and pushed down
+//! to a single range scan, so only the matching part of the table is read. Other predicates, added
+//! with [`Query::filter`], are evaluated for each entry in that range.
+//!
+//! ```rust
+//! use redb::query::Queryable;
+//! use redb::{Database, TableDefinition};
+//! # use tempfile::NamedTempFile;
+//! const TABLE: TableDefinition<u64, &str> = TableDefinition::new("users");
+//!
+//! # fn main() -> Result<(), redb::Error> {
+//! # let tmpfile = NamedTempFile::new().unwrap();
+//! let db = Database::create(tmpfile.path())?;
+//! let write_txn = db.begin_write()?;
+//! {
+//!     let mut table = write_txn.open_table(TABLE)?;
+//!     table.insert(1, "alice")?;
+//!     table.insert(2, "bob")?;
+//!     table.insert(3, "anne")?;
+//!     table.insert(4, "amos")?;
+//! }
+//! write_txn.commit()?;
+//!
+//! let read_txn = db.begin_read()?;
+//! let table = read_txn.open_table(TABLE)?;
+//! let names: Vec<String> = table
+//!     .query()
+//!     .key_range(2..)
+//!     .filter(|_, name| name.starts_with('a'))
+//!     .select(|id, name| format!("{id}:{name}"))?
+//!     .collect::<Result<_, _>>()?;
+//! assert_eq!(names, vec!["3:anne", "4:amos"]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::types::{RedbKey, RedbValue};
+use crate::{AccessGuard, Range, ReadableTable, Result};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+
+type Filter<'q, K, V> = Box<
+    dyn for<'x> Fn(<K as RedbValue>::SelfType<'x>, <V as RedbValue>::SelfType<'x>) -> bool + 'q,
+>;
+
+/// Adds [`Self::query`] to every readable table
+pub trait Queryable<K: RedbKey + 'static, V: RedbValue + 'static>: ReadableTable<K, V> {
+    /// Returns a query over all entries of this table
+    fn query(&self) -> Query<'_, K, V, Self>
+    where
+        Self: Sized,
+    {
+        Query::new(self)
+    }
+}
+
+impl<K: RedbKey + 'static, V: RedbValue + 'static, T: ReadableTable<K, V>> Queryable<K, V> for T {}
+
+fn encoded_bound<'a, K: RedbKey + 'a, KR: Borrow<K::SelfType<'a>>>(
+    bound: Bound<&KR>,
+) -> Bound<Vec<u8>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(K::as_bytes(key.borrow()).as_ref().to_vec()),
+        Bound::Excluded(key) => Bound::Excluded(K::as_bytes(key.borrow()).as_ref().to_vec()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+// Returns the narrower of two bounds on the same side of a range. `order` is the ordering of a
+// key which is further inside the range, relative to one which is less far inside
+fn narrower<K: RedbKey>(a: Bound<Vec<u8>>, b: Bound<Vec<u8>>, order: Ordering) -> Bound<Vec<u8>> {
+    let (a_key, b_key) = match (&a, &b) {
+        (Bound::Unbounded, _) => return b,
+        (_, Bound::Unbounded) => return a,
+        (
+            Bound::Included(a_key) | Bound::Excluded(a_key),
+            Bound::Included(b_key) | Bound::Excluded(b_key),
+        ) => (a_key, b_key),
+    };
+    match K::compare(a_key, b_key) {
+        Ordering::Equal => {
+            if matches!(a, Bound::Excluded(_)) {
+                a
+            } else {
+                b
+            }
+        }
+        x if x == order => a,
+        _ => b,
+    }
+}
+
+fn decoded_bound<K: RedbKey>(bound: &Bound<Vec<u8>>) -> Bound<K::SelfType<'_>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(K::from_bytes(key)),
+        Bound::Excluded(key) => Bound::Excluded(K::from_bytes(key)),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// A query over the entries of a table, built with [`Queryable::query`]
+///
+/// Entries are returned in key order, or in reverse key order after [`Self::reverse`]
+pub struct Query<'q, K: RedbKey + 'static, V: RedbValue + 'static, T: ReadableTable<K, V>> {
+    table: &'q T,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    filters: Vec<Filter<'q, K, V>>,
+    reverse: bool,
+    offset: u64,
+    limit: Option<u64>,
+}
+
+impl<'q, K: RedbKey + 'static, V: RedbValue + 'static, T: ReadableTable<K, V>> Query<'q, K, V, T> {
+    fn new(table: &'q T) -> Self {
+        Self {
+            table,
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+            filters: vec![],
+            reverse: false,
+            offset: 0,
+            limit: None,
+        }
+    }
+
+    /// Only matches entries whose keys are in `range`
+    ///
+    /// Key ranges are intersected with each other, and the table is only scanned over the
+    /// intersection
+    pub fn key_range<'a, KR>(mut self, range: impl RangeBounds<KR>) -> Self
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>>,
+    {
+        let start = encoded_bound::<K, KR>(range.start_bound());
+        let end = encoded_bound::<K, KR>(range.end_bound());
+        self.start = narrower::<K>(self.start, start, Ordering::Greater);
+        self.end = narrower::<K>(self.end, end, Ordering::Less);
+        self
+    }
+
+    /// Only matches entries for which `predicate` returns `true`
+    pub fn filter(
+        mut self,
+        predicate: impl for<'x> Fn(K::SelfType<'x>, V::SelfType<'x>) -> bool + 'q,
+    ) -> Self {
+        self.filters.push(Box::new(predicate));
+        self
+    }
+
+    /// Returns matching entries in reverse key order
+    pub fn reverse(mut self) -> Self {
+        self.reverse = !self.reverse;
+        self
+    }
+
+    /// Skips the first `n` matching entries
+    pub fn offset(mut self, n: u64) -> Self {
+        self.offset = n;
+        self
+    }
+
+    /// Returns at most `n` matching entries
+    pub fn limit(mut self, n: u64) -> Self {
+        self.limit = Some(n);
+        self
+    }
+
+    /// Returns an iterator over the result of calling `projection` on each matching entry
+    pub fn select<R, F>(self, projection: F) -> Result<Select<'q, K, V, F>>
+    where
+        F: for<'x> Fn(K::SelfType<'x>, V::SelfType<'x>) -> R,
+    {
+        let empty = match (&self.start, &self.end) {
+            (Bound::Unbounded, _) | (_, Bound::Unbounded) => false,
+            (Bound::Included(start), Bound::Included(end)) => {
+                K::compare(start, end) == Ordering::Greater
+            }
+            (Bound::Included(start) | Bound::Excluded(start), Bound::Excluded(end))
+            | (Bound::Excluded(start), Bound::Included(end)) => {
+                K::compare(start, end) != Ordering::Less
+            }
+        };
+        let mut offset = self.offset;
+        let range = if empty || self.limit == Some(0) {
+            None
+        } else {
+            let mut range = self.table.range::<K::SelfType<'_>>((
+                decoded_bound::<K>(&self.start),
+                decoded_bound::<K>(&self.end),
+            ))?;
+            // Without filters, every entry in the range matches, so the offset is found from the
+            // entry counts in the branch pages
+            if self.filters.is_empty() && !self.reverse && offset > 0 {
+                range.skip_to_nth(offset)?;
+                offset = 0;
+            }
+            Some(range)
+        };
+
+        Ok(Select {
+            range,
+            filters: self.filters,
+            reverse: self.reverse,
+            offset,
+            remaining: self.limit,
+            projection,
+        })
+    }
+
+    /// Returns the number of matching entries
+    pub fn count(self) -> Result<u64> {
+        let mut count = 0;
+        for entry in self.select(|_, _| ())? {
+            entry?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+/// Iterator over the results of a [`Query`], returned by [`Query::select`]
+pub struct Select<'q, K: RedbKey + 'static, V: RedbValue + 'static, F> {
+    range: Option<Range<'q, K, V>>,
+    filters: Vec<Filter<'q, K, V>>,
+    reverse: bool,
+    offset: u64,
+    remaining: Option<u64>,
+    projection: F,
+}
+
+impl<'q, K: RedbKey + 'static, V: RedbValue + 'static, F> Select<'q, K, V, F> {
+    #[allow(clippy::type_complexity)]
+    fn next_match(&mut self) -> Option<Result<(AccessGuard<'q, K>, AccessGuard<'q, V>)>> {
+        if self.remaining == Some(0) {
+            self.range = None;
+        }
+        let range = self.range.as_mut()?;
+        loop {
+            let entry = if self.reverse {
+                range.next_back()?
+            } else {
+                range.next()?
+            };
+            let (key, value) = match entry {
+                Ok(entry) => entry,
+                Err(err) => return Some(Err(err)),
+            };
+            if !self
+                .filters
+                .iter()
+                .all(|filter| filter(key.value(), value.value()))
+            {
+                continue;
+            }
+            if self.offset > 0 {
+                self.offset -= 1;
+                continue;
+            }
+            if let Some(remaining) = self.remaining.as_mut() {
+                *remaining -= 1;
+            }
+            return Some(Ok((key, value)));
+        }
+    }
+}
+
+impl<'q, K: RedbKey + 'static, V: RedbValue + 'static, R, F> Iterator for Select<'q, K, V, F>
+where
+    F: for<'x> Fn(K::SelfType<'x>, V::SelfType<'x>) -> R,
+{
+    type Item = Result<R>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_match()
+            .map(|entry| entry.map(|(key, value)| (self.projection)(key.value(), value.value())))
+    }
+}
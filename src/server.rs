@@ -0,0 +1,524 @@
+//! A minimal server for accessing a database from other processes
+//!
+//! A database file can only be opened by one process at a time. [`Server`] lets other processes,
+//! such as sidecars, read and write it through the process which has it open. [`Client`]
+//! connects to a server and makes requests to it.
+//!
+//! Only tables with `&[u8]` keys and values can be accessed. Each request is run in its own
+//! transaction, unless the client has begun a write transaction with [`Client::begin`], in which
+//! case requests are run in that transaction until it is committed or aborted. A client which
+//! holds a write transaction blocks writes from all other clients, and a client which disconnects
+//! while holding one has it aborted.
+//!
+//! Requests are not authenticated, so the server should only be reachable by trusted processes,
+//! for example by binding it to a loopback address.
+//!
+//! # Protocol
+//!
+//! Each message is a little-endian `u32` length followed by that many bytes. A request starts
+//! with an operation byte, and a response with a status byte: `0` for success, `1` if the key or
+//! transaction was not found, and `2` for an error, followed by the error message. Byte string
+//! fields are encoded as a `u32` length followed by the bytes.
+//!
+//! Messages are limited to the maximum length of a value plus 64KiB, for the table name, key and
+//! framing. A server closes the connection of a client which sends a longer message. A scan
+//! returns at most 1024 entries, and stops early if the next entry would make the response longer
+//! than the maximum message length.
+//!
+//! | Operation | Request fields | Response fields |
+//! |-----------|----------------|-----------------|
+//! | `1` get | table, key | value |
+//! | `2` insert | table, key, value | |
+//! | `3` remove | table, key | |
+//! | `4` scan | table, start key, `u8` end flag, end key if the flag is `1`, `u32` limit | `u32` count, then a key and value for each entry |
+//! | `5` begin | | |
+//! | `6` commit | | |
+//! | `7` abort | | |
+//!
+//! ```rust
+//! use redb::server::{Client, Server};
+//! use redb::Database;
+//! use std::net::TcpListener;
+//! use std::sync::Arc;
+//! # use tempfile::NamedTempFile;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let tmpfile = NamedTempFile::new().unwrap();
+//! let db = Arc::new(Database::create(tmpfile.path())?);
+//! let server = Server::spawn(db, TcpListener::bind("127.0.0.1:0")?)?;
+//!
+//! let mut client = Client::connect(server.local_addr())?;
+//! client.insert("my_data", b"hello", b"world")?;
+//! assert_eq!(client.get("my_data", b"hello")?, Some(b"world".to_vec()));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::tree_store::MAX_VALUE_LENGTH;
+use crate::{
+    Database, Error, ReadableTable, Result, TableDefinition, TableHandle, WriteTransaction,
+};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+const OP_GET: u8 = 1;
+const OP_INSERT: u8 = 2;
+const OP_REMOVE: u8 = 3;
+const OP_SCAN: u8 = 4;
+const OP_BEGIN: u8 = 5;
+const OP_COMMIT: u8 = 6;
+const OP_ABORT: u8 = 7;
+
+// Large enough for a value of the maximum length, along with its table name, key and framing
+const MAX_MESSAGE_LENGTH: usize = MAX_VALUE_LENGTH + 64 * 1024;
+const MAX_SCAN_ENTRIES: u32 = 1024;
+
+const STATUS_OK: u8 = 0;
+const STATUS_NOT_FOUND: u8 = 1;
+const STATUS_ERROR: u8 = 2;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_message(writer: &mut impl Write, message: &[u8]) -> io::Result<()> {
+    if message.len() > MAX_MESSAGE_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "message too large",
+        ));
+    }
+    let len: u32 = message.len().try_into().unwrap();
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(message)?;
+    writer.flush()
+}
+
+// Returns None if the stream was closed before the start of a message
+fn read_message(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    if let Err(err) = reader.read_exact(&mut len) {
+        return if err.kind() == io::ErrorKind::UnexpectedEof {
+            Ok(None)
+        } else {
+            Err(err)
+        };
+    }
+    let len: usize = u32::from_le_bytes(len).try_into().unwrap();
+    if len > MAX_MESSAGE_LENGTH {
+        return Err(invalid_data("message too large"));
+    }
+    // Grow the buffer as the message arrives, rather than trusting the length up front
+    let mut message = vec![];
+    reader.take(len as u64).read_to_end(&mut message)?;
+    if message.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(Some(message))
+}
+
+fn push_bytes(message: &mut Vec<u8>, bytes: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(bytes.len()).map_err(|_| invalid_data("field too large"))?;
+    message.extend_from_slice(&len.to_le_bytes());
+    message.extend_from_slice(bytes);
+    Ok(())
+}
+
+// Reads the fields of a message in order
+struct Fields<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(invalid_data("truncated message"));
+        }
+        let (field, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(field)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u32()?;
+        self.take(len.try_into().unwrap())
+    }
+
+    fn str(&mut self) -> io::Result<&'a str> {
+        std::str::from_utf8(self.bytes()?).map_err(|_| invalid_data("table name is not UTF-8"))
+    }
+}
+
+fn definition(name: &str) -> TableDefinition<'_, &'static [u8], &'static [u8]> {
+    TableDefinition::new(name)
+}
+
+// Returns true if the table exists, so that reads in a write transaction don't create it
+fn table_exists(txn: &WriteTransaction, name: &str) -> Result<bool> {
+    Ok(txn.list_tables()?.any(|table| table.name() == name))
+}
+
+fn scan(
+    table: &impl ReadableTable<&'static [u8], &'static [u8]>,
+    start: &[u8],
+    end: Option<&[u8]>,
+    limit: u32,
+    response: &mut Vec<u8>,
+) -> Result {
+    let range = match end {
+        Some(end) => table.range(start..end)?,
+        None => table.range(start..)?,
+    };
+    let mut entries = vec![];
+    let mut count = 0u32;
+    for entry in range.take(limit.min(MAX_SCAN_ENTRIES).try_into().unwrap()) {
+        let (key, value) = entry?;
+        let entry_len = 8 + key.value().len() + value.value().len();
+        if response.len() + 4 + entries.len() + entry_len > MAX_MESSAGE_LENGTH {
+            if count == 0 {
+                return Err(invalid_data("entry is too large to return").into());
+            }
+            break;
+        }
+        push_bytes(&mut entries, key.value())?;
+        push_bytes(&mut entries, value.value())?;
+        count += 1;
+    }
+    response.extend_from_slice(&count.to_le_bytes());
+    response.extend_from_slice(&entries);
+
+    Ok(())
+}
+
+// Runs a single request, and returns the response
+fn handle_request<'db>(
+    db: &'db Database,
+    txn: &mut Option<WriteTransaction<'db>>,
+    request: &[u8],
+) -> Result<Vec<u8>> {
+    let mut fields = Fields { data: request };
+    let mut response = vec![STATUS_OK];
+    match fields.u8()? {
+        OP_GET => {
+            let name = fields.str()?;
+            let key = fields.bytes()?;
+            let value = match txn {
+                Some(txn) if table_exists(txn, name)? => txn
+                    .open_table(definition(name))?
+                    .get(key)?
+                    .map(|value| value.value().to_vec()),
+                Some(_) => None,
+                None => match db.begin_read()?.open_table(definition(name)) {
+                    Ok(table) => table.get(key)?.map(|value| value.value().to_vec()),
+                    Err(Error::TableDoesNotExist(_)) => None,
+                    Err(err) => return Err(err),
+                },
+            };
+            match value {
+                Some(value) => push_bytes(&mut response, &value)?,
+                None => response[0] = STATUS_NOT_FOUND,
+            }
+        }
+        OP_INSERT => {
+            let name = fields.str()?;
+            let key = fields.bytes()?;
+            let value = fields.bytes()?;
+            match txn {
+                Some(txn) => {
+                    txn.open_table(definition(name))?.insert(key, value)?;
+                }
+                None => {
+                    let txn = db.begin_write_abort_on_drop()?;
+                    txn.open_table(definition(name))?.insert(key, value)?;
+                    txn.commit()?;
+                }
+            }
+        }
+        OP_REMOVE => {
+            let name = fields.str()?;
+            let key = fields.bytes()?;
+            let removed = match txn {
+                Some(txn) => {
+                    table_exists(txn, name)?
+                        && txn.open_table(definition(name))?.remove(key)?.is_some()
+                }
+                None => {
+                    let txn = db.begin_write_abort_on_drop()?;
+                    let removed = table_exists(&txn, name)?
+                        && txn.open_table(definition(name))?.remove(key)?.is_some();
+                    txn.commit()?;
+                    removed
+                }
+            };
+            if !removed {
+                response[0] = STATUS_NOT_FOUND;
+            }
+        }
+        OP_SCAN => {
+            let name = fields.str()?;
+            let start = fields.bytes()?;
+            let end = match fields.u8()? {
+                0 => None,
+                _ => Some(fields.bytes()?),
+            };
+            let limit = fields.u32()?;
+            match txn {
+                Some(txn) if table_exists(txn, name)? => {
+                    let table = txn.open_table(definition(name))?;
+                    scan(&table, start, end, limit, &mut response)?;
+                }
+                Some(_) => response.extend_from_slice(&0u32.to_le_bytes()),
+                None => match db.begin_read()?.open_table(definition(name)) {
+                    Ok(table) => scan(&table, start, end, limit, &mut response)?,
+                    Err(Error::TableDoesNotExist(_)) => {
+                        response.extend_from_slice(&0u32.to_le_bytes());
+                    }
+                    Err(err) => return Err(err),
+                },
+            }
+        }
+        OP_BEGIN => {
+            if txn.is_some() {
+                return Err(invalid_data("a transaction is already in progress").into());
+            }
+            *txn = Some(db.begin_write_abort_on_drop()?);
+        }
+        OP_COMMIT => match txn.take() {
+            Some(txn) => txn.commit()?,
+            None => response[0] = STATUS_NOT_FOUND,
+        },
+        OP_ABORT => match txn.take() {
+            Some(txn) => txn.abort()?,
+            None => response[0] = STATUS_NOT_FOUND,
+        },
+        _ => return Err(invalid_data("unknown operation").into()),
+    }
+
+    Ok(response)
+}
+
+fn serve_connection(db: &Database, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let mut txn = None;
+    while let Some(request) = read_message(&mut reader)? {
+        let response = handle_request(db, &mut txn, &request).unwrap_or_else(|err| {
+            let mut response = vec![STATUS_ERROR];
+            response.extend_from_slice(err.to_string().as_bytes());
+            response
+        });
+        write_message(&mut writer, &response)?;
+    }
+
+    Ok(())
+}
+
+/// Serves requests for a database on a background thread, with one thread per connection
+///
+/// Dropping the server stops it accepting connections. Connections which are already open are
+/// served until the client disconnects
+pub struct Server {
+    local_addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    /// Serves `db` to clients which connect to `listener`
+    pub fn spawn(db: Arc<Database>, listener: TcpListener) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let acceptor = {
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::Acquire) {
+                        break;
+                    }
+                    // Errors only affect a single connection, so they are ignored
+                    if let Ok(stream) = stream {
+                        let db = db.clone();
+                        thread::spawn(move || serve_connection(&db, stream));
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            local_addr,
+            stopped,
+            acceptor: Some(acceptor),
+        })
+    }
+
+    /// Returns the address which the server is listening on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        // Wake the acceptor, so that it sees the flag
+        let mut addr = self.local_addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr.ip() {
+                IpAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                IpAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        if TcpStream::connect(addr).is_ok() {
+            if let Some(acceptor) = self.acceptor.take() {
+                acceptor.join().unwrap();
+            }
+        }
+    }
+}
+
+/// A connection to a [`Server`]
+///
+/// Errors reported by the server are returned as [`io::Error`]s of kind [`io::ErrorKind::Other`],
+/// with the server's error message
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Client {
+    /// Connects to the server at `addr`
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    // Sends `request`, and returns the fields of the response, or None if it was not found
+    fn request(&mut self, request: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        write_message(&mut self.writer, request)?;
+        let mut response = read_message(&mut self.reader)?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        match response.first() {
+            Some(&STATUS_OK) => {
+                response.remove(0);
+                Ok(Some(response))
+            }
+            Some(&STATUS_NOT_FOUND) => Ok(None),
+            Some(&STATUS_ERROR) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                String::from_utf8_lossy(&response[1..]).into_owned(),
+            )),
+            _ => Err(invalid_data("invalid response status")),
+        }
+    }
+
+    fn key_request(&mut self, op: u8, table: &str, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let mut request = vec![op];
+        push_bytes(&mut request, table.as_bytes())?;
+        push_bytes(&mut request, key)?;
+        self.request(&request)
+    }
+
+    /// Returns the value for `key` in `table`
+    pub fn get(&mut self, table: &str, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        match self.key_request(OP_GET, table, key)? {
+            Some(response) => Ok(Some(Fields { data: &response }.bytes()?.to_vec())),
+            None => Ok(None),
+        }
+    }
+
+    /// Inserts `value` for `key` in `table`, creating the table if it does not exist
+    pub fn insert(&mut self, table: &str, key: &[u8], value: &[u8]) -> io::Result<()> {
+        let mut request = vec![OP_INSERT];
+        push_bytes(&mut request, table.as_bytes())?;
+        push_bytes(&mut request, key)?;
+        push_bytes(&mut request, value)?;
+        self.request(&request)?;
+        Ok(())
+    }
+
+    /// Removes `key` from `table`, and returns `true` if it existed
+    pub fn remove(&mut self, table: &str, key: &[u8]) -> io::Result<bool> {
+        Ok(self.key_request(OP_REMOVE, table, key)?.is_some())
+    }
+
+    /// Returns up to `limit` entries of `table` in key order, starting at `start`, and ending
+    /// before `end` if it is given
+    ///
+    /// The server returns at most 1024 entries, and fewer if they would exceed the maximum message
+    /// length, so a long scan should be continued from the last key returned with a `0` byte
+    /// appended
+    pub fn scan(
+        &mut self,
+        table: &str,
+        start: &[u8],
+        end: Option<&[u8]>,
+        limit: u32,
+    ) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut request = vec![OP_SCAN];
+        push_bytes(&mut request, table.as_bytes())?;
+        push_bytes(&mut request, start)?;
+        match end {
+            Some(end) => {
+                request.push(1);
+                push_bytes(&mut request, end)?;
+            }
+            None => request.push(0),
+        }
+        request.extend_from_slice(&limit.to_le_bytes());
+        let response = self.request(&request)?.unwrap_or_default();
+        let mut fields = Fields { data: &response };
+        let count = fields.u32()?;
+        let mut entries = vec![];
+        for _ in 0..count {
+            let key = fields.bytes()?.to_vec();
+            let value = fields.bytes()?.to_vec();
+            entries.push((key, value));
+        }
+
+        Ok(entries)
+    }
+
+    /// Begins a write transaction, in which all following requests on this connection are run
+    ///
+    /// Blocks until any other client's write transaction completes
+    pub fn begin(&mut self) -> io::Result<()> {
+        self.request(&[OP_BEGIN])?;
+        Ok(())
+    }
+
+    /// Commits the write transaction begun by [`Self::begin`]
+    ///
+    /// Returns [`io::ErrorKind::NotFound`] if no transaction is in progress
+    pub fn commit(&mut self) -> io::Result<()> {
+        match self.request(&[OP_COMMIT])? {
+            Some(_) => Ok(()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    /// Aborts the write transaction begun by [`Self::begin`]
+    ///
+    /// Returns [`io::ErrorKind::NotFound`] if no transaction is in progress
+    pub fn abort(&mut self) -> io::Result<()> {
+        match self.request(&[OP_ABORT])? {
+            Some(_) => Ok(()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
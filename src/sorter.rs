@@ -0,0 +1,148 @@
+use crate::types::{RedbKey, RedbValue};
+use crate::{AccessGuard, Range, ReadableTable, Result, Table, TableDefinition, WriteTransaction};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::sync::atomic::{self, AtomicU64};
+
+// Used to give each sorter distinct temporary table names
+static NEXT_SORTER_ID: AtomicU64 = AtomicU64::new(0);
+
+// Each run maps the position of a pair within the run to its key and value
+type RunValue = (&'static [u8], &'static [u8]);
+
+/// Sorts key-value pairs which may not fit in memory, in the order defined by `K`
+///
+/// Pairs are buffered in memory until `memory_limit` bytes of keys and values have been pushed.
+/// The buffer is then sorted and written to a temporary table (see
+/// [`WriteTransaction::open_temp_table`]), and [`ExternalSorter::sorted`] merges these runs. Pairs
+/// with equal keys are returned in the order they were pushed.
+///
+/// The runs are stored until the transaction completes
+pub struct ExternalSorter<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> {
+    transaction: &'txn WriteTransaction<'db>,
+    id: u64,
+    memory_limit: usize,
+    buffer: Vec<(Vec<u8>, Vec<u8>)>,
+    buffered_bytes: usize,
+    runs: Vec<Table<'db, 'txn, u64, RunValue>>,
+    _key_type: PhantomData<K>,
+    _value_type: PhantomData<V>,
+}
+
+impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> ExternalSorter<'db, 'txn, K, V> {
+    /// Creates a sorter which stores its runs in temporary tables of `transaction`
+    pub fn new(transaction: &'txn WriteTransaction<'db>, memory_limit: usize) -> Self {
+        Self {
+            transaction,
+            id: NEXT_SORTER_ID.fetch_add(1, atomic::Ordering::Relaxed),
+            memory_limit,
+            buffer: vec![],
+            buffered_bytes: 0,
+            runs: vec![],
+            _key_type: Default::default(),
+            _value_type: Default::default(),
+        }
+    }
+
+    /// Adds a key-value pair to be sorted
+    pub fn push<'a>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'a>>,
+        value: impl Borrow<V::SelfType<'a>>,
+    ) -> Result
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let key = K::as_bytes(key.borrow()).as_ref().to_vec();
+        let value = V::as_bytes(value.borrow()).as_ref().to_vec();
+        self.buffered_bytes += key.len() + value.len();
+        self.buffer.push((key, value));
+        if self.buffered_bytes >= self.memory_limit {
+            self.spill()?;
+        }
+
+        Ok(())
+    }
+
+    // Writes the buffered pairs to a new run
+    fn spill(&mut self) -> Result {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        // This is a stable sort, so equal keys remain in the order they were pushed
+        self.buffer.sort_by(|(a, _), (b, _)| K::compare(a, b));
+        let name = format!("redb::sorter::{}::{}", self.id, self.runs.len());
+        let mut run = self
+            .transaction
+            .open_temp_table::<u64, RunValue>(TableDefinition::new(&name))?;
+        for (i, (key, value)) in self.buffer.drain(..).enumerate() {
+            let position: u64 = i.try_into().unwrap();
+            run.insert(position, (key.as_slice(), value.as_slice()))?;
+        }
+        self.buffered_bytes = 0;
+        self.runs.push(run);
+
+        Ok(())
+    }
+
+    /// Returns an iterator over all the pairs pushed so far, in key order
+    pub fn sorted(&mut self) -> Result<Sorted<'_, K, V>> {
+        self.spill()?;
+        let mut runs = vec![];
+        for run in self.runs.iter() {
+            runs.push(run.range::<u64>(..)?);
+        }
+
+        Ok(Sorted {
+            heads: runs.iter().map(|_| None).collect(),
+            runs,
+            _key_type: Default::default(),
+            _value_type: Default::default(),
+        })
+    }
+}
+
+/// Iterator returned by [`ExternalSorter::sorted`]
+pub struct Sorted<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    runs: Vec<Range<'a, u64, RunValue>>,
+    // The next pair from each run, if it has been read
+    heads: Vec<Option<AccessGuard<'a, RunValue>>>,
+    _key_type: PhantomData<K>,
+    _value_type: PhantomData<V>,
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Iterator for Sorted<'a, K, V> {
+    type Item = Result<(AccessGuard<'a, K>, AccessGuard<'a, V>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut selected: Option<usize> = None;
+        for i in 0..self.runs.len() {
+            if self.heads[i].is_none() {
+                match self.runs[i].next() {
+                    Some(Ok((_, pair))) => self.heads[i] = Some(pair),
+                    Some(Err(err)) => return Some(Err(err)),
+                    None => continue,
+                }
+            }
+            if let Some(current) = selected {
+                let key = self.heads[i].as_ref().unwrap().value().0;
+                let current_key = self.heads[current].as_ref().unwrap().value().0;
+                // Earlier runs contain pairs which were pushed earlier, so they win ties
+                if K::compare(key, current_key) == Ordering::Less {
+                    selected = Some(i);
+                }
+            } else {
+                selected = Some(i);
+            }
+        }
+
+        let pair = self.heads[selected?].take().unwrap();
+        let (key, value) = pair.value();
+        Some(Ok((
+            AccessGuard::with_owned_value(key.to_vec()),
+            AccessGuard::with_owned_value(value.to_vec()),
+        )))
+    }
+}
@@ -0,0 +1,414 @@
+//! Spatial indexing with space-filling curves
+//!
+//! [`Morton2D`] and [`Hilbert2D`] are keys which store a point on a 2D grid as its position along
+//! a space-filling curve, so that points which are near each other in space are mostly stored
+//! near each other in the table. [`range_query`] decomposes a [`BoundingBox`] into ranges of
+//! keys, which can be passed to [`crate::ReadableTable::range`] to find the points inside the box
+//! without a separate spatial index.
+//!
+//! Real-valued coordinates, such as longitude and latitude, must first be quantized onto the
+//! `u32` grid.
+
+use crate::sealed::Sealed;
+use crate::types::{RedbKey, RedbValue, TypeName};
+use std::cmp::Ordering;
+use std::ops::RangeInclusive;
+
+// Upper limit on the number of ranges returned by range_query()
+const MAX_RANGES: usize = 64;
+
+/// A key which stores a point as its index along a space-filling curve
+///
+/// This trait is sealed, since [`range_query`] relies on every aligned square of the grid, whose
+/// side is a power of two, covering a contiguous range of the curve
+pub trait SpatialKey:
+    RedbKey + for<'a> RedbValue<SelfType<'a> = Self> + Sealed + Copy + 'static
+{
+    /// Returns the key for the point `(x, y)`
+    fn new(x: u32, y: u32) -> Self;
+
+    /// Returns the key for the given position along the curve
+    fn from_curve_index(index: u64) -> Self;
+
+    /// Returns the position of this key along the curve
+    fn curve_index(&self) -> u64;
+
+    /// Returns the x coordinate of the point
+    fn x(&self) -> u32;
+
+    /// Returns the y coordinate of the point
+    fn y(&self) -> u32;
+}
+
+// Moves the bits of `value` into the even bit positions
+fn spread_bits(value: u32) -> u64 {
+    let mut x = u64::from(value);
+    x = (x | (x << 16)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x << 8)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+// Inverse of spread_bits(). The odd bit positions are ignored
+fn compact_bits(value: u64) -> u32 {
+    let mut x = value & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x >> 4)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x >> 8)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x >> 16)) & 0x0000_0000_FFFF_FFFF;
+    x.try_into().unwrap()
+}
+
+// Reflects and transposes a quadrant, so that the curve within it has the standard orientation
+fn hilbert_rotate(extent: u32, x: &mut u32, y: &mut u32, rx: bool, ry: bool) {
+    if !ry {
+        if rx {
+            *x = extent - *x;
+            *y = extent - *y;
+        }
+        std::mem::swap(x, y);
+    }
+}
+
+fn hilbert_encode(mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut s: u32 = 1 << 31;
+    while s > 0 {
+        let rx = x & s != 0;
+        let ry = y & s != 0;
+        let quadrant = (3 * u64::from(rx)) ^ u64::from(ry);
+        index += u64::from(s) * u64::from(s) * quadrant;
+        hilbert_rotate(u32::MAX, &mut x, &mut y, rx, ry);
+        s >>= 1;
+    }
+    index
+}
+
+fn hilbert_decode(index: u64) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = index;
+    for level in 0..32 {
+        let s: u32 = 1 << level;
+        let rx = (t >> 1) & 1 == 1;
+        let ry = (t ^ u64::from(rx)) & 1 == 1;
+        hilbert_rotate(s - 1, &mut x, &mut y, rx, ry);
+        if rx {
+            x += s;
+        }
+        if ry {
+            y += s;
+        }
+        t >>= 2;
+    }
+    (x, y)
+}
+
+macro_rules! spatial_key {
+    ($name:ident, $encode:expr, $decode:expr) => {
+        impl $name {
+            /// Returns the key for the point `(x, y)`
+            pub fn new(x: u32, y: u32) -> Self {
+                Self { x, y }
+            }
+
+            /// Returns the x coordinate of the point
+            pub fn x(&self) -> u32 {
+                self.x
+            }
+
+            /// Returns the y coordinate of the point
+            pub fn y(&self) -> u32 {
+                self.y
+            }
+        }
+
+        impl Sealed for $name {}
+
+        impl SpatialKey for $name {
+            fn new(x: u32, y: u32) -> Self {
+                Self { x, y }
+            }
+
+            fn from_curve_index(index: u64) -> Self {
+                let (x, y) = $decode(index);
+                Self { x, y }
+            }
+
+            fn curve_index(&self) -> u64 {
+                $encode(self.x, self.y)
+            }
+
+            fn x(&self) -> u32 {
+                self.x
+            }
+
+            fn y(&self) -> u32 {
+                self.y
+            }
+        }
+
+        impl RedbValue for $name {
+            type SelfType<'a> = $name;
+            type AsBytes<'a> = [u8; 8] where Self: 'a;
+
+            fn fixed_width() -> Option<usize> {
+                Some(8)
+            }
+
+            fn from_bytes<'a>(data: &'a [u8]) -> $name
+            where
+                Self: 'a,
+            {
+                Self::from_curve_index(u64::from_be_bytes(data.try_into().unwrap()))
+            }
+
+            fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> [u8; 8]
+            where
+                Self: 'a,
+                Self: 'b,
+            {
+                value.curve_index().to_be_bytes()
+            }
+
+            fn type_name() -> TypeName {
+                TypeName::internal(stringify!($name))
+            }
+        }
+
+        impl RedbKey for $name {
+            fn compare(data1: &[u8], data2: &[u8]) -> Ordering {
+                data1.cmp(data2)
+            }
+        }
+    };
+}
+
+/// A point on a 2D grid, which is ordered along the Z-order (Morton) curve
+///
+/// The curve index interleaves the bits of the coordinates, so it is cheap to compute. Keys are
+/// stored in big-endian order of the index.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Morton2D {
+    x: u32,
+    y: u32,
+}
+
+spatial_key!(
+    Morton2D,
+    |x, y| spread_bits(x) | (spread_bits(y) << 1),
+    |index| (compact_bits(index), compact_bits(index >> 1))
+);
+
+/// A point on a 2D grid, which is ordered along the Hilbert curve
+///
+/// Consecutive points along the Hilbert curve are always adjacent in space, so a bounding box
+/// decomposes into fewer key ranges than with [`Morton2D`], at the cost of a slower encoding.
+/// Keys are stored in big-endian order of the index.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Hilbert2D {
+    x: u32,
+    y: u32,
+}
+
+spatial_key!(Hilbert2D, hilbert_encode, hilbert_decode);
+
+/// A rectangle on the grid, which includes its edges
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BoundingBox {
+    min_x: u32,
+    min_y: u32,
+    max_x: u32,
+    max_y: u32,
+}
+
+impl BoundingBox {
+    /// Returns the box which spans from `(min_x, min_y)` to `(max_x, max_y)`, inclusive
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_x > max_x` or `min_y > max_y`
+    pub fn new(min_x: u32, min_y: u32, max_x: u32, max_y: u32) -> Self {
+        assert!(min_x <= max_x && min_y <= max_y);
+        Self {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        }
+    }
+
+    /// Returns `true` if the point `(x, y)` is inside the box
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        self.min_x <= x && x <= self.max_x && self.min_y <= y && y <= self.max_y
+    }
+
+    fn contains_cell(&self, cell: &Cell) -> bool {
+        self.contains(cell.x, cell.y) && self.contains(cell.max_x(), cell.max_y())
+    }
+
+    fn intersects_cell(&self, cell: &Cell) -> bool {
+        cell.x <= self.max_x
+            && self.min_x <= cell.max_x()
+            && cell.y <= self.max_y
+            && self.min_y <= cell.max_y()
+    }
+}
+
+// An aligned square of the grid, whose side is 2^level
+#[derive(Copy, Clone)]
+struct Cell {
+    x: u32,
+    y: u32,
+    level: u32,
+}
+
+impl Cell {
+    const ROOT: Cell = Cell {
+        x: 0,
+        y: 0,
+        level: 32,
+    };
+
+    fn extent(&self) -> u32 {
+        ((1u64 << self.level) - 1).try_into().unwrap()
+    }
+
+    fn max_x(&self) -> u32 {
+        self.x + self.extent()
+    }
+
+    fn max_y(&self) -> u32 {
+        self.y + self.extent()
+    }
+
+    fn children(&self) -> [Cell; 4] {
+        let level = self.level - 1;
+        let side = 1 << level;
+        [
+            Cell {
+                x: self.x,
+                y: self.y,
+                level,
+            },
+            Cell {
+                x: self.x + side,
+                y: self.y,
+                level,
+            },
+            Cell {
+                x: self.x,
+                y: self.y + side,
+                level,
+            },
+            Cell {
+                x: self.x + side,
+                y: self.y + side,
+                level,
+            },
+        ]
+    }
+
+    // Returns the first and last curve indices of the points in the cell
+    fn curve_range<K: SpatialKey>(&self) -> (u64, u64) {
+        let mask = if self.level == 0 {
+            0
+        } else {
+            u64::MAX >> (64 - 2 * self.level)
+        };
+        let start = K::new(self.x, self.y).curve_index() & !mask;
+        (start, start | mask)
+    }
+}
+
+// Returns the curve ranges covered by the cells, which must be in curve order, merging those which
+// are adjacent
+fn merge_ranges<K: SpatialKey>(cells: &[(Cell, bool)]) -> Vec<(u64, u64)> {
+    let mut ranges: Vec<(u64, u64)> = vec![];
+    for (cell, _) in cells {
+        let (start, end) = cell.curve_range::<K>();
+        match ranges.last_mut() {
+            Some(last) if last.1.checked_add(1) == Some(start) => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+    ranges
+}
+
+/// Returns ranges of keys, in ascending order, which together contain every point in `bbox`
+///
+/// A box which does not line up with the curve may need a very large number of ranges to cover
+/// it exactly, so the decomposition is coarsened to at most 64 ranges, which may then also
+/// contain points outside the box. Entries read from these ranges should be filtered with
+/// [`BoundingBox::contains`].
+///
+/// ```
+/// use redb::spatial::{range_query, BoundingBox, Hilbert2D};
+/// use redb::{Database, ReadableTable, TableDefinition};
+/// # use tempfile::NamedTempFile;
+///
+/// const PLACES: TableDefinition<Hilbert2D, &str> = TableDefinition::new("places");
+///
+/// # fn main() -> Result<(), redb::Error> {
+/// # let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+/// # let filename = tmpfile.path();
+/// let db = Database::create(filename)?;
+/// let write_txn = db.begin_write()?;
+/// {
+///     let mut table = write_txn.open_table(PLACES)?;
+///     table.insert(Hilbert2D::new(10, 20), "cafe")?;
+///     table.insert(Hilbert2D::new(500, 20), "museum")?;
+/// }
+/// write_txn.commit()?;
+///
+/// let read_txn = db.begin_read()?;
+/// let table = read_txn.open_table(PLACES)?;
+/// let bbox = BoundingBox::new(0, 0, 100, 100);
+/// let mut found = vec![];
+/// for range in range_query::<Hilbert2D>(&bbox) {
+///     for entry in table.range(range)? {
+///         let (point, name) = entry?;
+///         if bbox.contains(point.value().x(), point.value().y()) {
+///             found.push(name.value().to_string());
+///         }
+///     }
+/// }
+/// assert_eq!(found, vec!["cafe"]);
+/// # Ok(())
+/// # }
+/// ```
+pub fn range_query<K: SpatialKey>(bbox: &BoundingBox) -> Vec<RangeInclusive<K>> {
+    // Cells which cover the box, in curve order, and whether each lies entirely inside it. Cells
+    // which are only partly inside are split, until they would produce too many ranges
+    let mut cover = vec![(Cell::ROOT, bbox.contains_cell(&Cell::ROOT))];
+    while cover.iter().any(|(_, inside)| !inside) {
+        let mut refined = vec![];
+        for (cell, inside) in cover.iter() {
+            if *inside {
+                refined.push((*cell, true));
+                continue;
+            }
+            let mut children: Vec<Cell> = cell
+                .children()
+                .into_iter()
+                .filter(|child| bbox.intersects_cell(child))
+                .collect();
+            children.sort_by_key(|child| child.curve_range::<K>().0);
+            refined.extend(
+                children
+                    .into_iter()
+                    .map(|child| (child, bbox.contains_cell(&child))),
+            );
+        }
+        if merge_ranges::<K>(&refined).len() > MAX_RANGES {
+            break;
+        }
+        cover = refined;
+    }
+
+    merge_ranges::<K>(&cover)
+        .into_iter()
+        .map(|(start, end)| K::from_curve_index(start)..=K::from_curve_index(end))
+        .collect()
+}
@@ -0,0 +1,253 @@
+use crate::types::{RedbKey, RedbValue};
+use crate::{AccessGuard, Range, ReadOnlyTable, ReadableTable, Result, Table};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::{btree_map, BTreeMap};
+use std::iter::Peekable;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+// An encoded key, ordered as defined by K
+struct StagedKey<K: RedbKey> {
+    data: Vec<u8>,
+    _key_type: PhantomData<K>,
+}
+
+impl<K: RedbKey> StagedKey<K> {
+    fn new(key: &K::SelfType<'_>) -> Self {
+        Self {
+            data: K::as_bytes(key).as_ref().to_vec(),
+            _key_type: Default::default(),
+        }
+    }
+}
+
+impl<K: RedbKey> Clone for StagedKey<K> {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            _key_type: Default::default(),
+        }
+    }
+}
+
+impl<K: RedbKey> PartialEq for StagedKey<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: RedbKey> Eq for StagedKey<K> {}
+
+impl<K: RedbKey> PartialOrd for StagedKey<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: RedbKey> Ord for StagedKey<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        K::compare(&self.data, &other.data)
+    }
+}
+
+// Key, and its previous entry in the staged modifications, or None if it had no entry
+type UndoEntry<K> = (StagedKey<K>, Option<Option<Vec<u8>>>);
+
+fn staged_bound<'a, K: RedbKey + 'a, KR: Borrow<K::SelfType<'a>>>(
+    bound: Bound<&KR>,
+) -> Bound<StagedKey<K>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(StagedKey::new(key.borrow())),
+        Bound::Excluded(key) => Bound::Excluded(StagedKey::new(key.borrow())),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// Buffers modifications to a table in memory, on top of a read-only snapshot of it
+///
+/// Reads see the staged modifications merged with the snapshot, so changes can be previewed and
+/// undone cheaply, without holding a write transaction. Once they are final,
+/// [`Self::apply`] writes all of them to a table in a write transaction. The snapshot is not
+/// updated by other writers, so applying overwrites any changes they made to the staged keys
+pub struct StagingTable<'txn, K: RedbKey + 'static, V: RedbValue + 'static> {
+    base: ReadOnlyTable<'txn, K, V>,
+    // Key -> staged value, or None if the key is staged for removal
+    staged: BTreeMap<StagedKey<K>, Option<Vec<u8>>>,
+    // One entry for each modification, in the order they were made
+    undo_log: Vec<UndoEntry<K>>,
+}
+
+impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> StagingTable<'txn, K, V> {
+    /// Returns a staging table with no modifications, on top of `base`
+    pub fn new(base: ReadOnlyTable<'txn, K, V>) -> Self {
+        Self {
+            base,
+            staged: Default::default(),
+            undo_log: vec![],
+        }
+    }
+
+    /// Returns the value for `key`, including staged modifications
+    pub fn get<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        K: 'a,
+    {
+        match self.staged.get(&StagedKey::new(key.borrow())) {
+            Some(Some(value)) => Ok(Some(AccessGuard::with_owned_value(value.clone()))),
+            Some(None) => Ok(None),
+            None => self.base.get(key),
+        }
+    }
+
+    /// Stages an insert of `value` for `key`
+    pub fn insert<'a>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'a>>,
+        value: impl Borrow<V::SelfType<'a>>,
+    ) where
+        K: 'a,
+        V: 'a,
+    {
+        let value = V::as_bytes(value.borrow()).as_ref().to_vec();
+        self.stage(StagedKey::new(key.borrow()), Some(value));
+    }
+
+    /// Stages the removal of `key`
+    pub fn remove<'a>(&mut self, key: impl Borrow<K::SelfType<'a>>)
+    where
+        K: 'a,
+    {
+        self.stage(StagedKey::new(key.borrow()), None);
+    }
+
+    /// Undoes the most recent staged modification
+    ///
+    /// Returns `false` if there are no modifications to undo
+    pub fn undo(&mut self) -> bool {
+        match self.undo_log.pop() {
+            Some((key, Some(previous))) => {
+                self.staged.insert(key, previous);
+                true
+            }
+            Some((key, None)) => {
+                self.staged.remove(&key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Discards all staged modifications
+    pub fn clear(&mut self) {
+        self.staged.clear();
+        self.undo_log.clear();
+    }
+
+    /// Returns the number of keys with staged modifications
+    pub fn staged_len(&self) -> usize {
+        self.staged.len()
+    }
+
+    /// Returns an iterator over a range of elements, including staged modifications
+    pub fn range<'a, KR>(&self, range: impl RangeBounds<KR> + 'a) -> Result<StagingRange<'_, K, V>>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        let start = staged_bound(range.start_bound());
+        let end = staged_bound(range.end_bound());
+        Ok(StagingRange {
+            base: self.base.range(range)?.peekable(),
+            // Only the start bound is passed to the map, since BTreeMap::range() panics on an
+            // empty range, whereas tables return no entries
+            staged: self.staged.range((start, Bound::Unbounded)).peekable(),
+            end,
+        })
+    }
+
+    /// Returns an iterator over all elements, including staged modifications
+    pub fn iter(&self) -> Result<StagingRange<'_, K, V>> {
+        self.range::<K::SelfType<'_>>(..)
+    }
+
+    /// Writes the staged modifications to `table`
+    ///
+    /// The modifications remain staged, so that the caller can discard them with [`Self::clear`]
+    /// once the write transaction has committed
+    pub fn apply(&self, table: &mut Table<'_, '_, K, V>) -> Result {
+        for (key, value) in self.staged.iter() {
+            let key = K::from_bytes(&key.data);
+            match value {
+                Some(value) => {
+                    table.insert(&key, V::from_bytes(value))?;
+                }
+                None => {
+                    table.remove(&key)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stage(&mut self, key: StagedKey<K>, value: Option<Vec<u8>>) {
+        let previous = self.staged.get(&key).cloned();
+        self.undo_log.push((key.clone(), previous));
+        self.staged.insert(key, value);
+    }
+}
+
+/// Iterator over the entries of a [`StagingTable`], with staged modifications merged in
+pub struct StagingRange<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    base: Peekable<Range<'a, K, V>>,
+    staged: Peekable<btree_map::Range<'a, StagedKey<K>, Option<Vec<u8>>>>,
+    end: Bound<StagedKey<K>>,
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> StagingRange<'a, K, V> {
+    // Returns the next staged key, unless it is past the end of the range
+    fn peek_staged(&mut self) -> Option<&'a StagedKey<K>> {
+        let (key, _) = *self.staged.peek()?;
+        let in_range = match &self.end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+        if in_range {
+            Some(key)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Iterator for StagingRange<'a, K, V> {
+    type Item = Result<(AccessGuard<'a, K>, AccessGuard<'a, V>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let staged = self.peek_staged();
+            let order = match (self.base.peek(), staged) {
+                (None, None) => return None,
+                (Some(Err(_)), _) | (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(Ok((key, _))), Some(staged)) => K::compare(key.raw_bytes(), &staged.data),
+            };
+            if order == Ordering::Less {
+                return self.base.next();
+            }
+            if order == Ordering::Equal {
+                // The staged modification replaces the entry in the snapshot
+                self.base.next();
+            }
+            let (key, value) = self.staged.next().unwrap();
+            if let Some(value) = value {
+                return Some(Ok((
+                    AccessGuard::with_owned_value(key.data.clone()),
+                    AccessGuard::with_owned_value(value.clone()),
+                )));
+            }
+        }
+    }
+}
@@ -0,0 +1,7 @@
+// Locks and atomics used by the storage engine. When built with `--cfg loom` these are replaced by
+// loom's, so that the loom models can explore their interleavings. `Arc` is always the std one,
+// since it is shared with the public API
+#[cfg(loom)]
+pub(crate) use loom::sync::{atomic, Mutex, MutexGuard, RwLock};
+#[cfg(not(loom))]
+pub(crate) use std::sync::{atomic, Mutex, MutexGuard, RwLock};
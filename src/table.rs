@@ -1,37 +1,57 @@
 use crate::sealed::Sealed;
+use crate::sync::Mutex;
 use crate::tree_store::{
-    AccessGuardMut, Btree, BtreeDrain, BtreeDrainFilter, BtreeMut, BtreeRangeIter, Checksum,
-    PageHint, PageNumber, TransactionalMemory, MAX_VALUE_LENGTH,
+    AccessGuardMut, Btree, BtreeChange, BtreeDiff, BtreeDrain, BtreeDrainFilter, BtreeMut,
+    BtreeRangeIter, Checksum, PageHint, PageNumber, TransactionalMemory, MAX_VALUE_LENGTH,
 };
 use crate::types::{RedbKey, RedbValue, RedbValueMutInPlace};
-use crate::{AccessGuard, WriteTransaction};
+use crate::{
+    AccessGuard, ContentHash, FillPolicy, FragmentationReport, KeyHistogram, ReadTransaction,
+    TableWriteStats, WriteTransaction,
+};
 use crate::{Error, Result};
 use std::borrow::Borrow;
-use std::ops::RangeBounds;
-use std::sync::{Arc, Mutex};
+use std::cmp::Ordering;
+use std::iter::FusedIterator;
+use std::ops::{Bound, RangeBounds, RangeFull};
+use std::sync::Arc;
+
+// The table tree of the transaction which a table is stored in
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum TableNamespace {
+    User,
+    System,
+    // Discarded when the transaction completes
+    Temporary,
+}
 
 /// A table containing key-value mappings
 pub struct Table<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> {
     name: String,
-    system: bool,
+    namespace: TableNamespace,
     transaction: &'txn WriteTransaction<'db>,
     tree: BtreeMut<'txn, K, V>,
+    stats: TableWriteStats,
 }
 
 impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K, V> {
     pub(crate) fn new(
         name: &str,
-        system: bool,
+        namespace: TableNamespace,
         table_root: Option<(PageNumber, Checksum)>,
+        fill_policy: FillPolicy,
         freed_pages: Arc<Mutex<Vec<PageNumber>>>,
         mem: &'db TransactionalMemory,
         transaction: &'txn WriteTransaction<'db>,
     ) -> Table<'db, 'txn, K, V> {
+        let mut tree = BtreeMut::new(table_root, mem, freed_pages);
+        tree.set_fill_policy(fill_policy);
         Table {
             name: name.to_string(),
-            system,
+            namespace,
             transaction,
-            tree: BtreeMut::new(table_root, mem, freed_pages),
+            tree,
+            stats: Default::default(),
         }
     }
 
@@ -40,6 +60,59 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K
         self.tree.print_debug(include_values)
     }
 
+    /// Returns a double-ended iterator over all elements in the table, as they were when the
+    /// transaction began
+    ///
+    /// The iterator does not borrow the table, so the table can be modified while iterating.
+    /// Modifications made in this transaction, both before and after this call, are not visible
+    pub fn snapshot_iter(&self) -> Result<Range<'txn, K, V>> {
+        self.transaction
+            .committed_table::<K, V>(&self.name, self.namespace)?
+            .range::<RangeFull, K::SelfType<'_>>(..)
+            .map(Range::new)
+    }
+
+    /// Returns the distribution of keys in the table, as of the last time it was analyzed, if a
+    /// histogram is stored
+    ///
+    /// See [`WriteTransaction::enable_key_histogram`]
+    pub fn key_histogram(&self) -> Result<Option<KeyHistogram<K>>> {
+        if self.namespace != TableNamespace::User {
+            return Ok(None);
+        }
+        self.transaction.key_histogram(&self.name)
+    }
+
+    /// Returns the content hash of the table, as of the last commit, if it is maintained
+    ///
+    /// See [`WriteTransaction::enable_content_hash`]
+    pub fn content_hash(&self) -> Result<Option<ContentHash<K>>> {
+        if self.namespace != TableNamespace::User {
+            return Ok(None);
+        }
+        self.transaction.content_hash(&self.name)
+    }
+
+    /// Returns a report of how the table's pages, including uncommitted changes, are laid out in
+    /// the database file
+    ///
+    /// See [`WriteTransaction::defragment_table`]
+    pub fn fragmentation_report(&self) -> Result<FragmentationReport> {
+        self.tree.fragmentation_report()
+    }
+
+    /// Reads the pages of the table into the cache, so that later accesses do not have to read
+    /// them from the file
+    ///
+    /// See [`ReadOnlyTable::preload`]
+    pub fn preload(&self, include_leaves: bool) -> Result<u64> {
+        self.tree.preload(include_leaves)
+    }
+
+    pub(crate) fn rewrite(&mut self) -> Result {
+        self.tree.rewrite()
+    }
+
     /// Removes and returns the first key-value pair in the table
     pub fn pop_first(&mut self) -> Result<Option<(AccessGuard<K>, AccessGuard<V>)>> {
         // TODO: optimize this
@@ -84,7 +157,25 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K
         // TODO: we should not require Clone here
         KR: Borrow<K::SelfType<'a>> + Clone + 'a,
     {
-        self.tree.drain(range).map(Drain::new)
+        self.transaction.check_transaction_size()?;
+        let (inner, removed) = self.tree.drain(range)?;
+        self.stats.removed += removed;
+        Ok(Drain::new(inner))
+    }
+
+    /// Removes the specified range, and returns the number of entries removed
+    ///
+    /// This is faster than [`Self::drain`] for large ranges, since entries are not read, and
+    /// parts of the table which lie entirely within the range are freed without being rewritten
+    pub fn remove_range<'a, KR>(&mut self, range: impl RangeBounds<KR>) -> Result<u64>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        self.transaction.check_transaction_size()?;
+        let removed = self.tree.remove_range(range)?;
+        self.stats.removed += removed;
+        Ok(removed)
     }
 
     /// Applies `predicate` to all key-value pairs in the specified range. All entries for which
@@ -99,9 +190,10 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K
         // TODO: we should not require Clone here
         KR: Borrow<K::SelfType<'a>> + Clone + 'a,
     {
-        self.tree
-            .drain_filter(range, predicate)
-            .map(DrainFilter::new)
+        self.transaction.check_transaction_size()?;
+        let (inner, removed) = self.tree.drain_filter(range, predicate)?;
+        self.stats.removed += removed;
+        Ok(DrainFilter::new(inner))
     }
 
     /// Insert mapping of the given key to the given value
@@ -124,7 +216,14 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K
         if key_len > MAX_VALUE_LENGTH {
             return Err(Error::ValueTooLarge(key_len));
         }
-        self.tree.insert(key.borrow(), value.borrow())
+        self.transaction.check_transaction_size()?;
+        let old_value = self.tree.insert(key.borrow(), value.borrow())?;
+        if old_value.is_some() {
+            self.stats.updated += 1;
+        } else {
+            self.stats.inserted += 1;
+        }
+        Ok(old_value)
     }
 
     /// Removes the given key
@@ -137,7 +236,12 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K
     where
         K: 'a,
     {
-        self.tree.remove(key.borrow())
+        self.transaction.check_transaction_size()?;
+        let old_value = self.tree.remove(key.borrow())?;
+        if old_value.is_some() {
+            self.stats.removed += 1;
+        }
+        Ok(old_value)
     }
 }
 
@@ -159,7 +263,52 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValueMutInPlace + 'static> Table<'d
         if key_len > MAX_VALUE_LENGTH {
             return Err(Error::ValueTooLarge(key_len));
         }
-        self.tree.insert_reserve(key.borrow(), value_length)
+        self.transaction.check_transaction_size()?;
+        let (guard, existed) = self.tree.insert_reserve(key.borrow(), value_length)?;
+        if existed {
+            self.stats.updated += 1;
+        } else {
+            self.stats.inserted += 1;
+        }
+        Ok(guard)
+    }
+}
+
+impl<'db, 'txn, K: RedbKey + 'static> Table<'db, 'txn, K, &'static [u8]> {
+    /// Append the given bytes to the value of the given key
+    ///
+    /// If the key is not present in the table, it is inserted with `bytes` as its value
+    ///
+    /// The value is extended in place if it has already been written in this transaction, and its
+    /// page has enough free space. Otherwise the whole value is copied, which takes time
+    /// proportional to its length
+    pub fn append<'a>(&mut self, key: impl Borrow<K::SelfType<'a>>, bytes: &[u8]) -> Result
+    where
+        K: 'a,
+    {
+        self.transaction.check_transaction_size()?;
+        if let Some(mut guard) = self.tree.reserve_in_place(key.borrow(), bytes.len())? {
+            let old_len = guard.as_mut().len();
+            let len = old_len + bytes.len();
+            if len > MAX_VALUE_LENGTH {
+                return Err(Error::ValueTooLarge(len));
+            }
+            guard.extend_to(len);
+            guard.as_mut()[old_len..].copy_from_slice(bytes);
+            self.stats.updated += 1;
+            return Ok(());
+        }
+        let old = match self.tree.get(key.borrow())? {
+            Some(old) => old.value().to_vec(),
+            None => vec![],
+        };
+        let len = old.len() + bytes.len();
+        let value_length = u32::try_from(len).map_err(|_| Error::ValueTooLarge(len))?;
+        let mut guard = self.insert_reserve(key, value_length)?;
+        let value = guard.as_mut();
+        value[..old.len()].copy_from_slice(&old);
+        value[old.len()..].copy_from_slice(bytes);
+        Ok(())
     }
 }
 
@@ -181,6 +330,24 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V
         self.tree.range(range).map(Range::new)
     }
 
+    fn sample(
+        &self,
+        n: usize,
+        rng: impl FnMut() -> u64,
+    ) -> Result<Vec<(AccessGuard<'_, K>, AccessGuard<'_, V>)>> {
+        Ok(self
+            .tree
+            .sample(n, rng)?
+            .into_iter()
+            .map(|entry| {
+                let (page, key_range, value_range) = entry.into_raw();
+                let key = AccessGuard::with_page(page.clone(), key_range);
+                let value = AccessGuard::with_page(page, value_range);
+                (key, value)
+            })
+            .collect())
+    }
+
     fn len(&self) -> Result<u64> {
         self.tree.len()
     }
@@ -194,8 +361,21 @@ impl<K: RedbKey, V: RedbValue> Sealed for Table<'_, '_, K, V> {}
 
 impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Drop for Table<'db, 'txn, K, V> {
     fn drop(&mut self) {
-        self.transaction
-            .close_table(&self.name, self.system, &mut self.tree);
+        match self.namespace {
+            TableNamespace::User => {
+                self.transaction.record_table_stats(&self.name, self.stats);
+                self.transaction
+                    .close_table(&self.name, false, &mut self.tree);
+            }
+            TableNamespace::System => {
+                self.transaction
+                    .close_table(&self.name, true, &mut self.tree);
+            }
+            TableNamespace::Temporary => {
+                self.transaction
+                    .close_temp_table(&self.name, &mut self.tree);
+            }
+        }
     }
 }
 
@@ -205,6 +385,38 @@ pub trait ReadableTable<K: RedbKey + 'static, V: RedbValue + 'static>: Sealed {
     where
         K: 'a;
 
+    /// Returns the bytes in `byte_range` of the stored value corresponding to the given key
+    ///
+    /// Only the requested bytes are copied, so this is cheaper than [`Self::get`] followed by a
+    /// copy for a small part of a large value. The range is clamped to the length of the value
+    fn get_range_of<'a>(
+        &self,
+        key: impl Borrow<K::SelfType<'a>>,
+        byte_range: impl RangeBounds<usize>,
+    ) -> Result<Option<Vec<u8>>>
+    where
+        K: 'a,
+    {
+        let guard = match self.get(key)? {
+            Some(guard) => guard,
+            None => return Ok(None),
+        };
+        let bytes = guard.raw_bytes();
+        let end = match byte_range.end_bound() {
+            Bound::Included(end) => end.saturating_add(1),
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => bytes.len(),
+        }
+        .min(bytes.len());
+        let start = match byte_range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        }
+        .min(end);
+        Ok(Some(bytes[start..end].to_vec()))
+    }
+
     /// Returns a double-ended iterator over a range of elements in the table
     ///
     /// # Examples
@@ -242,6 +454,37 @@ pub trait ReadableTable<K: RedbKey + 'static, V: RedbValue + 'static>: Sealed {
         K: 'a,
         KR: Borrow<K::SelfType<'a>> + 'a;
 
+    /// Returns `n` entries chosen at random, with replacement, using `rng` as the source of
+    /// random numbers
+    ///
+    /// Each entry is equally likely to be chosen. Entries are found by descending the tree, so only
+    /// `n` paths from the root to a leaf are read
+    fn sample(
+        &self,
+        n: usize,
+        rng: impl FnMut() -> u64,
+    ) -> Result<Vec<(AccessGuard<'_, K>, AccessGuard<'_, V>)>>;
+
+    /// Returns the entry at position `index` in key order, counting from zero
+    ///
+    /// The entry is found in O(log n) time, using [`Range::skip_to_nth`]
+    fn nth(&self, index: u64) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>> {
+        let mut iter = self.iter()?;
+        iter.skip_to_nth(index)?;
+        iter.next().transpose()
+    }
+
+    /// Returns the number of entries whose keys are less than `key`
+    ///
+    /// The entries are counted in O(log n) time, from the number of entries beneath each child
+    /// of the branch pages on the path to `key`
+    fn rank<'a>(&self, key: impl Borrow<K::SelfType<'a>> + 'a) -> Result<u64>
+    where
+        K: 'a,
+    {
+        Ok(self.range(..key)?.len().try_into().unwrap())
+    }
+
     /// Returns the number of entries in the table
     fn len(&self) -> Result<u64>;
 
@@ -269,6 +512,20 @@ impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadOnlyTable<'txn, K,
             tree: Btree::new(root_page, hint, mem)?,
         })
     }
+
+    /// Reads the pages of the table into the cache, so that later accesses do not have to read
+    /// them from the file
+    ///
+    /// The branch pages are read a level at a time, with each level read in the order the pages
+    /// are stored in the file. If `include_leaves` is true the leaves, which hold the entries, are
+    /// also read. Otherwise only the branch pages are loaded, which are typically a small fraction
+    /// of the table, and each later lookup reads a single leaf. Pages are evicted as usual if the
+    /// table does not fit in the cache set by [`crate::Builder::set_cache_size`].
+    ///
+    /// Returns the number of pages read
+    pub fn preload(&self, include_leaves: bool) -> Result<u64> {
+        self.tree.preload(include_leaves)
+    }
 }
 
 impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V>
@@ -289,6 +546,24 @@ impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V>
         self.tree.range(range).map(Range::new)
     }
 
+    fn sample(
+        &self,
+        n: usize,
+        rng: impl FnMut() -> u64,
+    ) -> Result<Vec<(AccessGuard<'_, K>, AccessGuard<'_, V>)>> {
+        Ok(self
+            .tree
+            .sample(n, rng)?
+            .into_iter()
+            .map(|entry| {
+                let (page, key_range, value_range) = entry.into_raw();
+                let key = AccessGuard::with_page(page.clone(), key_range);
+                let value = AccessGuard::with_page(page, value_range);
+                (key, value)
+            })
+            .collect())
+    }
+
     fn len(&self) -> Result<u64> {
         self.tree.len()
     }
@@ -300,6 +575,65 @@ impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V>
 
 impl<K: RedbKey, V: RedbValue> Sealed for ReadOnlyTable<'_, K, V> {}
 
+/// A read-only table which owns the transaction it was opened in
+///
+/// Created by [`ReadTransaction::into_table`]. The transaction is kept open until the table is
+/// dropped
+pub struct OwnedReadTable<'db, K: RedbKey + 'static, V: RedbValue + 'static> {
+    // Declared before the transaction, so that it is dropped first
+    table: ReadOnlyTable<'db, K, V>,
+    transaction: ReadTransaction<'db>,
+}
+
+impl<'db, K: RedbKey + 'static, V: RedbValue + 'static> OwnedReadTable<'db, K, V> {
+    pub(crate) fn new(table: ReadOnlyTable<'db, K, V>, transaction: ReadTransaction<'db>) -> Self {
+        Self { table, transaction }
+    }
+
+    /// Returns the transaction which the table was opened in, which can be used to open other
+    /// tables at the same snapshot
+    pub fn transaction(&self) -> &ReadTransaction<'db> {
+        &self.transaction
+    }
+}
+
+impl<'db, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V>
+    for OwnedReadTable<'db, K, V>
+{
+    fn get<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        K: 'a,
+    {
+        self.table.get(key)
+    }
+
+    fn range<'a, KR>(&self, range: impl RangeBounds<KR> + 'a) -> Result<Range<'_, K, V>>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        self.table.range(range)
+    }
+
+    fn sample(
+        &self,
+        n: usize,
+        rng: impl FnMut() -> u64,
+    ) -> Result<Vec<(AccessGuard<'_, K>, AccessGuard<'_, V>)>> {
+        self.table.sample(n, rng)
+    }
+
+    fn len(&self) -> Result<u64> {
+        self.table.len()
+    }
+
+    fn is_empty(&self) -> Result<bool> {
+        self.table.is_empty()
+    }
+}
+
+impl<K: RedbKey, V: RedbValue> Sealed for OwnedReadTable<'_, K, V> {}
+
 pub struct Drain<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
     inner: BtreeDrain<'a, K, V>,
 }
@@ -322,8 +656,14 @@ impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Iterator for Drain<'a, K,
             (key, value)
         }))
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
 }
 
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> ExactSizeIterator for Drain<'a, K, V> {}
+
 impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> DoubleEndedIterator for Drain<'a, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         let entry = self.inner.next_back()?;
@@ -403,6 +743,50 @@ impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Range<'a, K, V> {
     fn new(inner: BtreeRangeIter<'a, K, V>) -> Self {
         Self { inner }
     }
+
+    /// Skips the next `n` entries, so that the following call to [`Iterator::next`] returns the
+    /// `n`th remaining entry, counting from zero. If fewer than `n` entries remain, the range is
+    /// exhausted
+    ///
+    /// Returns the number of entries skipped
+    ///
+    /// Branch pages store the number of entries beneath each of their children, so the new
+    /// position is found in O(log n) time, without reading any of the skipped entries
+    pub fn skip_to_nth(&mut self, n: u64) -> Result<u64> {
+        self.inner.skip_forward(n)
+    }
+
+    /// Returns the next entry, together with all the following entries in the range which are
+    /// stored in the same leaf page
+    ///
+    /// This allows entries to be processed in page sized batches, with less overhead than calling
+    /// [`Iterator::next`] for each entry
+    #[allow(clippy::type_complexity)]
+    pub fn next_leaf(&mut self) -> Option<Result<Vec<(AccessGuard<'a, K>, AccessGuard<'a, V>)>>> {
+        self.inner.next_leaf().map(|x| {
+            x.map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|entry| {
+                        let (page, key_range, value_range) = entry.into_raw();
+                        let key = AccessGuard::with_page(page.clone(), key_range);
+                        let value = AccessGuard::with_page(page, value_range);
+                        (key, value)
+                    })
+                    .collect()
+            })
+        })
+    }
+}
+
+/// Cloning a [`Range`] produces an independent iterator over the entries that have not yet been
+/// returned by either end of the original
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Clone for Range<'a, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
 }
 
 impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Iterator for Range<'a, K, V> {
@@ -418,8 +802,19 @@ impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Iterator for Range<'a, K,
             })
         })
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
 }
 
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> FusedIterator for Range<'a, K, V> {}
+
+/// The number of remaining entries is computed from the entry counts stored in the btree, so
+/// [`ExactSizeIterator::len`] takes time logarithmic in the size of the table. In a database opened
+/// with quarantined pages, the remaining entries are instead counted by iterating over them
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> ExactSizeIterator for Range<'a, K, V> {}
+
 impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> DoubleEndedIterator for Range<'a, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.inner.next_back().map(|x| {
@@ -432,3 +827,210 @@ impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> DoubleEndedIterator for R
         })
     }
 }
+
+/// Returns a key ordered iterator over the union of the entries in `tables`
+///
+/// If a key is present in more than one table, only the entry from the table which comes last in
+/// `tables` is returned. This allows a table of changes to be layered over a base table, without
+/// materializing the combined table.
+pub fn merge_tables<'a, K, V, T>(
+    tables: impl IntoIterator<Item = &'a T>,
+) -> Result<MergedRange<'a, K, V>>
+where
+    K: RedbKey + 'static,
+    V: RedbValue + 'static,
+    T: ReadableTable<K, V> + 'a,
+{
+    let ranges = tables
+        .into_iter()
+        .map(|table| table.iter())
+        .collect::<Result<Vec<_>>>()?;
+    Ok(MergedRange::new(ranges))
+}
+
+type MergeEntry<'a, K, V> = (AccessGuard<'a, K>, AccessGuard<'a, V>);
+
+struct MergeSource<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    range: Range<'a, K, V>,
+    front: Option<MergeEntry<'a, K, V>>,
+    back: Option<MergeEntry<'a, K, V>>,
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> MergeSource<'a, K, V> {
+    fn fill_front(&mut self) -> Result {
+        if self.front.is_none() {
+            self.front = match self.range.next() {
+                Some(entry) => Some(entry?),
+                // The last entry may already have been taken by the other end
+                None => self.back.take(),
+            };
+        }
+        Ok(())
+    }
+
+    fn fill_back(&mut self) -> Result {
+        if self.back.is_none() {
+            self.back = match self.range.next_back() {
+                Some(entry) => Some(entry?),
+                None => self.front.take(),
+            };
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, front: bool) -> &mut Option<MergeEntry<'a, K, V>> {
+        if front {
+            &mut self.front
+        } else {
+            &mut self.back
+        }
+    }
+}
+
+/// Iterator returned by [`merge_tables`]
+pub struct MergedRange<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    sources: Vec<MergeSource<'a, K, V>>,
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> MergedRange<'a, K, V> {
+    /// Merges the given ranges, which may come from different tables or cover different keys.
+    /// Entries from later ranges shadow those from earlier ranges, in the same way as
+    /// [`merge_tables`]
+    pub fn new(ranges: Vec<Range<'a, K, V>>) -> Self {
+        Self {
+            sources: ranges
+                .into_iter()
+                .map(|range| MergeSource {
+                    range,
+                    front: None,
+                    back: None,
+                })
+                .collect(),
+        }
+    }
+
+    // Takes the first entry from the front, or the last from the back, choosing the last source
+    // containing it, and discards the same key from all other sources
+    fn take(&mut self, front: bool) -> Option<MergeEntry<'a, K, V>> {
+        // A key which compares this way to the currently selected one is not closer to the end
+        let skip = if front {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        };
+        let mut selected: Option<(usize, &[u8])> = None;
+        for (i, source) in self.sources.iter_mut().enumerate() {
+            if let Some((key, _)) = source.end(front) {
+                let key = key.raw_bytes();
+                match selected {
+                    Some((_, current)) if K::compare(key, current) == skip => {}
+                    _ => selected = Some((i, key)),
+                }
+            }
+        }
+        let (selected, _) = selected?;
+        let result = self.sources[selected].end(front).take().unwrap();
+        for source in self.sources.iter_mut() {
+            let entry = source.end(front);
+            if let Some((key, _)) = entry {
+                if K::compare(key.raw_bytes(), result.0.raw_bytes()) == Ordering::Equal {
+                    *entry = None;
+                }
+            }
+        }
+
+        Some(result)
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Iterator for MergedRange<'a, K, V> {
+    type Item = Result<MergeEntry<'a, K, V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for source in self.sources.iter_mut() {
+            if let Err(err) = source.fill_front() {
+                return Some(Err(err));
+            }
+        }
+        self.take(true).map(Ok)
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> FusedIterator for MergedRange<'a, K, V> {}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> DoubleEndedIterator
+    for MergedRange<'a, K, V>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        for source in self.sources.iter_mut() {
+            if let Err(err) = source.fill_back() {
+                return Some(Err(err));
+            }
+        }
+        self.take(false).map(Ok)
+    }
+}
+
+/// Returns a key ordered iterator over the entries which differ between two snapshots of a table
+///
+/// `old` and `new` are typically the same table opened in read transactions at different commits.
+/// Subtrees which are shared by the two snapshots, because they were not modified between the
+/// commits, are skipped without being read, so the cost is proportional to the size of the change
+/// rather than the size of the table. Tables from different databases can also be compared, but
+/// then every entry is read.
+pub fn diff_tables<'a, K: RedbKey + 'static, V: RedbValue + 'static>(
+    old: &'a ReadOnlyTable<'_, K, V>,
+    new: &'a ReadOnlyTable<'_, K, V>,
+) -> Result<TableDiff<'a, K, V>> {
+    Ok(TableDiff {
+        inner: old.tree.diff(&new.tree)?,
+    })
+}
+
+/// A difference between two snapshots of a table, returned by [`diff_tables`]
+pub enum DiffEntry<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    /// The key is only present in the newer snapshot
+    Added(AccessGuard<'a, K>, AccessGuard<'a, V>),
+    /// The key is only present in the older snapshot
+    Removed(AccessGuard<'a, K>, AccessGuard<'a, V>),
+    /// The key is present in both snapshots, with the older and newer values respectively
+    Modified(AccessGuard<'a, K>, AccessGuard<'a, V>, AccessGuard<'a, V>),
+}
+
+/// Iterator returned by [`diff_tables`]
+pub struct TableDiff<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    inner: BtreeDiff<'a, K, V>,
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Iterator for TableDiff<'a, K, V> {
+    type Item = Result<DiffEntry<'a, K, V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|x| {
+            x.map(|change| match change {
+                BtreeChange::Added(entry) => {
+                    let (page, key_range, value_range) = entry.into_raw();
+                    let key = AccessGuard::with_page(page.clone(), key_range);
+                    DiffEntry::Added(key, AccessGuard::with_page(page, value_range))
+                }
+                BtreeChange::Removed(entry) => {
+                    let (page, key_range, value_range) = entry.into_raw();
+                    let key = AccessGuard::with_page(page.clone(), key_range);
+                    DiffEntry::Removed(key, AccessGuard::with_page(page, value_range))
+                }
+                BtreeChange::Modified(old, new) => {
+                    let (old_page, key_range, old_range) = old.into_raw();
+                    let (new_page, _, new_range) = new.into_raw();
+                    let key = AccessGuard::with_page(old_page.clone(), key_range);
+                    DiffEntry::Modified(
+                        key,
+                        AccessGuard::with_page(old_page, old_range),
+                        AccessGuard::with_page(new_page, new_range),
+                    )
+                }
+            })
+        })
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> FusedIterator for TableDiff<'a, K, V> {}
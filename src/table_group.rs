@@ -0,0 +1,116 @@
+use crate::types::{RedbKey, RedbValue};
+use crate::{ReadableTable, Result, Table, TableDefinition, TableHandle, WriteTransaction};
+use std::borrow::Borrow;
+
+/// A set of related tables with the same key and value types, opened together by
+/// [`WriteTransaction::open_group`]
+///
+/// The tables are closed together when the group is dropped, and the group provides helpers for
+/// keeping the tables consistent with each other, such as removing a key from all of them
+pub struct TableGroup<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> {
+    names: Vec<String>,
+    tables: Vec<Table<'db, 'txn, K, V>>,
+}
+
+impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> TableGroup<'db, 'txn, K, V> {
+    pub(crate) fn new(
+        transaction: &'txn WriteTransaction<'db>,
+        definitions: &[TableDefinition<K, V>],
+    ) -> Result<Self> {
+        let mut names = vec![];
+        let mut tables = vec![];
+        for definition in definitions {
+            // If a table fails to open, those already opened are closed when `tables` is dropped
+            tables.push(transaction.open_table(*definition)?);
+            names.push(definition.name().to_string());
+        }
+
+        Ok(Self { names, tables })
+    }
+
+    /// Returns the number of tables in the group
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// Returns `true` if the group contains no tables
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Returns the table with the given name, if it is part of the group
+    pub fn table(&self, name: &str) -> Option<&Table<'db, 'txn, K, V>> {
+        let index = self.names.iter().position(|x| x == name)?;
+        Some(&self.tables[index])
+    }
+
+    /// Returns the table with the given name, if it is part of the group
+    pub fn table_mut(&mut self, name: &str) -> Option<&mut Table<'db, 'txn, K, V>> {
+        let index = self.names.iter().position(|x| x == name)?;
+        Some(&mut self.tables[index])
+    }
+
+    /// Returns an iterator over the names and tables of the group, in the order they were opened
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Table<'db, 'txn, K, V>)> {
+        self.names
+            .iter()
+            .map(|x| x.as_str())
+            .zip(self.tables.iter())
+    }
+
+    /// Returns an iterator over the names and tables of the group, in the order they were opened
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut Table<'db, 'txn, K, V>)> {
+        self.names
+            .iter()
+            .map(|x| x.as_str())
+            .zip(self.tables.iter_mut())
+    }
+
+    /// Returns `true` if every table in the group contains the given key
+    pub fn contains_in_all<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<bool>
+    where
+        K: 'a,
+    {
+        for table in self.tables.iter() {
+            if table.get(key.borrow())?.is_none() {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Inserts the given key-value pair into every table in the group
+    pub fn insert_all<'a>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'a>>,
+        value: impl Borrow<V::SelfType<'a>>,
+    ) -> Result
+    where
+        K: 'a,
+        V: 'a,
+    {
+        for table in self.tables.iter_mut() {
+            table.insert(key.borrow(), value.borrow())?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the given key from every table in the group
+    ///
+    /// Returns the number of tables which contained the key
+    pub fn remove_all<'a>(&mut self, key: impl Borrow<K::SelfType<'a>>) -> Result<usize>
+    where
+        K: 'a,
+    {
+        let mut removed = 0;
+        for table in self.tables.iter_mut() {
+            if table.remove(key.borrow())?.is_some() {
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+}
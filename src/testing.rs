@@ -0,0 +1,194 @@
+//! Utilities for testing applications built on redb
+//!
+//! [`ModelTester`] checks a table against an in-memory model, which is useful for validating the
+//! encoding and ordering of custom [`RedbValue`] and [`RedbKey`] types.
+
+use crate::types::{RedbKey, RedbValue};
+use crate::{Database, ReadableTable, Result, TableDefinition};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+const MODEL_TABLE_NAME: &str = "redb::testing::model";
+const MAX_OPERATIONS_PER_TRANSACTION: u64 = 16;
+
+// An encoded key, ordered as defined by K
+struct ModelKey<K: RedbKey> {
+    data: Vec<u8>,
+    _key_type: PhantomData<K>,
+}
+
+impl<K: RedbKey> ModelKey<K> {
+    fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            _key_type: Default::default(),
+        }
+    }
+}
+
+impl<K: RedbKey> Clone for ModelKey<K> {
+    fn clone(&self) -> Self {
+        Self::new(self.data.clone())
+    }
+}
+
+impl<K: RedbKey> PartialEq for ModelKey<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: RedbKey> Eq for ModelKey<K> {}
+
+impl<K: RedbKey> PartialOrd for ModelKey<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: RedbKey> Ord for ModelKey<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        K::compare(&self.data, &other.data)
+    }
+}
+
+fn index(random: u64, len: usize) -> usize {
+    let len: u64 = len.try_into().unwrap();
+    (random % len).try_into().unwrap()
+}
+
+/// Applies random sequences of operations to both a table and a `BTreeMap` model, and checks
+/// that they are equivalent after each transaction commits or aborts, and after the database is
+/// reopened
+///
+/// Keys and values are drawn from the candidates added with [`ModelTester::add_key`] and
+/// [`ModelTester::add_value`]. Each value read from the table is decoded and encoded again, and
+/// must match the encoding of the value which was inserted, and the table must return keys in the
+/// order defined by [`RedbKey::compare`]. A mismatch causes a panic.
+pub struct ModelTester<K: RedbKey + 'static, V: RedbValue + 'static> {
+    path: PathBuf,
+    db: Option<Database>,
+    keys: Vec<Vec<u8>>,
+    values: Vec<Vec<u8>>,
+    model: BTreeMap<ModelKey<K>, Vec<u8>>,
+    _value_type: PhantomData<V>,
+}
+
+impl<K: RedbKey + 'static, V: RedbValue + 'static> ModelTester<K, V> {
+    /// Creates a new database at `path` to run the test against
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let db = Database::create(&path)?;
+        let txn = db.begin_write_abort_on_drop()?;
+        txn.open_table(Self::definition())?;
+        txn.commit()?;
+
+        Ok(Self {
+            path,
+            db: Some(db),
+            keys: vec![],
+            values: vec![],
+            model: Default::default(),
+            _value_type: Default::default(),
+        })
+    }
+
+    /// Adds a key which operations may use
+    pub fn add_key<'a>(&mut self, key: impl Borrow<K::SelfType<'a>>)
+    where
+        K: 'a,
+    {
+        self.keys.push(K::as_bytes(key.borrow()).as_ref().to_vec());
+    }
+
+    /// Adds a value which operations may insert
+    pub fn add_value<'a>(&mut self, value: impl Borrow<V::SelfType<'a>>)
+    where
+        V: 'a,
+    {
+        self.values
+            .push(V::as_bytes(value.borrow()).as_ref().to_vec());
+    }
+
+    fn definition() -> TableDefinition<'static, K, V> {
+        TableDefinition::new(MODEL_TABLE_NAME)
+    }
+
+    /// Runs `transactions` random write transactions, using `rng` as the source of randomness
+    ///
+    /// ## Invariant
+    ///
+    /// At least one key and one value must have been added
+    pub fn run(&mut self, transactions: usize, mut rng: impl FnMut() -> u64) -> Result {
+        assert!(!self.keys.is_empty());
+        assert!(!self.values.is_empty());
+        for _ in 0..transactions {
+            let mut model = self.model.clone();
+            let txn = self.db.as_ref().unwrap().begin_write_abort_on_drop()?;
+            {
+                let mut table = txn.open_table(Self::definition())?;
+                for _ in 0..(rng() % MAX_OPERATIONS_PER_TRANSACTION + 1) {
+                    let key = &self.keys[index(rng(), self.keys.len())];
+                    let model_key = ModelKey::new(key.clone());
+                    match rng() % 3 {
+                        0 => {
+                            let value = &self.values[index(rng(), self.values.len())];
+                            table.insert(K::from_bytes(key), V::from_bytes(value))?;
+                            model.insert(model_key, value.clone());
+                        }
+                        1 => {
+                            let removed = table.remove(K::from_bytes(key))?;
+                            let removed =
+                                removed.map(|x| V::as_bytes(&x.value()).as_ref().to_vec());
+                            assert_eq!(removed, model.remove(&model_key));
+                        }
+                        _ => {
+                            let value = table.get(K::from_bytes(key))?;
+                            let value = value.map(|x| V::as_bytes(&x.value()).as_ref().to_vec());
+                            assert_eq!(value.as_ref(), model.get(&model_key));
+                        }
+                    }
+                }
+            }
+
+            match rng() % 4 {
+                0 => {
+                    txn.abort()?;
+                }
+                1 => {
+                    txn.commit()?;
+                    self.model = model;
+                    self.db = None;
+                    self.db = Some(Database::open(&self.path)?);
+                }
+                _ => {
+                    txn.commit()?;
+                    self.model = model;
+                }
+            }
+            self.check()?;
+        }
+
+        Ok(())
+    }
+
+    // Checks that the committed contents of the table match the model
+    fn check(&self) -> Result {
+        let txn = self.db.as_ref().unwrap().begin_read()?;
+        let table = txn.open_table(Self::definition())?;
+        assert_eq!(table.len()?, u64::try_from(self.model.len()).unwrap());
+        let mut iter = table.iter()?;
+        for (model_key, model_value) in self.model.iter() {
+            let (key, value) = iter.next().unwrap()?;
+            let key = K::as_bytes(&key.value()).as_ref().to_vec();
+            assert_eq!(K::compare(&key, &model_key.data), Ordering::Equal);
+            assert_eq!(V::as_bytes(&value.value()).as_ref(), model_value.as_slice());
+        }
+        assert!(iter.next().is_none());
+
+        Ok(())
+    }
+}
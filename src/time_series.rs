@@ -0,0 +1,232 @@
+use crate::types::RedbValue;
+use crate::{
+    AccessGuard, Range, ReadOnlyTable, ReadTransaction, ReadableTable, Result, Table,
+    TableDefinition, WriteTransaction,
+};
+use std::borrow::Borrow;
+use std::ops::{Bound, RangeBounds};
+
+const POINTS_SUFFIX: &str = "::points";
+
+type PointKeyBounds = (Bound<(u64, u64)>, Bound<(u64, u64)>);
+
+// Converts a range of timestamps into a range of keys within the series
+fn key_bounds(series: u64, times: impl RangeBounds<u64>) -> PointKeyBounds {
+    let start = match times.start_bound() {
+        Bound::Included(x) => Bound::Included((series, *x)),
+        Bound::Excluded(x) => Bound::Excluded((series, *x)),
+        Bound::Unbounded => Bound::Included((series, 0)),
+    };
+    let end = match times.end_bound() {
+        Bound::Included(x) => Bound::Included((series, *x)),
+        Bound::Excluded(x) => Bound::Excluded((series, *x)),
+        Bound::Unbounded => Bound::Included((series, u64::MAX)),
+    };
+    (start, end)
+}
+
+fn get<'t, V: RedbValue + 'static>(
+    points: &'t impl ReadableTable<(u64, u64), V>,
+    series: u64,
+    timestamp: u64,
+) -> Result<Option<AccessGuard<'t, V>>> {
+    points.get((series, timestamp))
+}
+
+fn range<'t, V: RedbValue + 'static>(
+    points: &'t impl ReadableTable<(u64, u64), V>,
+    series: u64,
+    times: impl RangeBounds<u64>,
+) -> Result<Range<'t, (u64, u64), V>> {
+    points.range(key_bounds(series, times))
+}
+
+fn last<'t, V: RedbValue + 'static>(
+    points: &'t impl ReadableTable<(u64, u64), V>,
+    series: u64,
+) -> Result<Option<(u64, AccessGuard<'t, V>)>> {
+    match range(points, series, ..)?.next_back() {
+        Some(entry) => {
+            let (key, value) = entry?;
+            Ok(Some((key.value().1, value)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Table of time series, layered on top of a regular table
+///
+/// Each point is keyed by the id of its series and its timestamp, so the points of a series are
+/// stored together in time order. Timestamps are in units of the caller's choosing, and a series
+/// holds at most one point per timestamp.
+///
+/// A time series table named `name` is stored in a table called `name::points`
+pub struct TimeSeriesTable<'db, 'txn, V: RedbValue + 'static> {
+    points: Table<'db, 'txn, (u64, u64), V>,
+}
+
+impl<'db, 'txn, V: RedbValue + 'static> TimeSeriesTable<'db, 'txn, V> {
+    /// Opens the time series table called `name`, creating it if it does not exist
+    pub fn open(transaction: &'txn WriteTransaction<'db>, name: &str) -> Result<Self> {
+        let points = format!("{name}{POINTS_SUFFIX}");
+        Ok(Self {
+            points: transaction.open_table(TableDefinition::new(&points))?,
+        })
+    }
+
+    /// Adds a point to `series`
+    ///
+    /// Returns the previous value, if the series already had a point at `timestamp`
+    pub fn append<'a>(
+        &mut self,
+        series: u64,
+        timestamp: u64,
+        value: impl Borrow<V::SelfType<'a>>,
+    ) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        V: 'a,
+    {
+        self.points.insert((series, timestamp), value)
+    }
+
+    /// Returns the value of `series` at `timestamp`
+    pub fn get(&self, series: u64, timestamp: u64) -> Result<Option<AccessGuard<'_, V>>> {
+        get(&self.points, series, timestamp)
+    }
+
+    /// Returns the points of `series` whose timestamps are in `times`, in time order
+    pub fn range(
+        &self,
+        series: u64,
+        times: impl RangeBounds<u64>,
+    ) -> Result<Range<'_, (u64, u64), V>> {
+        range(&self.points, series, times)
+    }
+
+    /// Returns the timestamp and value of the most recent point in `series`
+    pub fn last(&self, series: u64) -> Result<Option<(u64, AccessGuard<'_, V>)>> {
+        last(&self.points, series)
+    }
+
+    /// Removes the points of `series` older than `before`, and returns how many were removed
+    ///
+    /// Parts of the table which only contain expired points are freed without being read, so
+    /// pruning is cheap even when a large amount of history has expired
+    pub fn prune(&mut self, series: u64, before: u64) -> Result<u64> {
+        self.points.remove_range((series, 0)..(series, before))
+    }
+
+    /// Removes the points of every series older than `before`, and returns how many were removed
+    pub fn prune_all(&mut self, before: u64) -> Result<u64> {
+        let mut removed = 0;
+        let mut next_series = 0;
+        loop {
+            let series = match self.points.range((next_series, 0)..)?.next() {
+                Some(entry) => entry?.0.value().0,
+                None => break,
+            };
+            removed += self.prune(series, before)?;
+            if series == u64::MAX {
+                break;
+            }
+            next_series = series + 1;
+        }
+
+        Ok(removed)
+    }
+
+    /// Compacts the points of `series` older than `before` into one point per `interval`
+    ///
+    /// The points are grouped into buckets, which start at multiples of `interval`, and the
+    /// points of each bucket are replaced by the value which `aggregate` returns for them, stored
+    /// at the start of the bucket. Only buckets which end at or before `before` are compacted, so
+    /// that a bucket which may still receive points is left alone. A bucket which already holds
+    /// a single point at its start is skipped, so downsampling the same range again has no
+    /// effect.
+    ///
+    /// Returns the number of points by which the series shrank
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero
+    pub fn downsample<'r, R: Borrow<V::SelfType<'r>>>(
+        &mut self,
+        series: u64,
+        before: u64,
+        interval: u64,
+        mut aggregate: impl FnMut(&[(u64, AccessGuard<V>)]) -> R,
+    ) -> Result<u64>
+    where
+        V: 'r,
+    {
+        assert!(interval > 0);
+        let mut shrunk = 0;
+        let mut next = 0;
+        loop {
+            let bucket_start = match self.points.range((series, next)..(series, before))?.next() {
+                Some(entry) => {
+                    let timestamp = entry?.0.value().1;
+                    timestamp - timestamp % interval
+                }
+                None => break,
+            };
+            let bucket_end = match bucket_start.checked_add(interval) {
+                Some(end) if end <= before => end,
+                _ => break,
+            };
+            next = bucket_end;
+
+            let bucket = (series, bucket_start)..(series, bucket_end);
+            let points = self
+                .points
+                .range(bucket.clone())?
+                .map(|entry| entry.map(|(key, value)| (key.value().1, value)))
+                .collect::<Result<Vec<_>>>()?;
+            if points.len() == 1 && points[0].0 == bucket_start {
+                continue;
+            }
+            let value = aggregate(&points);
+            shrunk += u64::try_from(points.len()).unwrap() - 1;
+            drop(points);
+
+            self.points.remove_range(bucket)?;
+            self.points.insert((series, bucket_start), value)?;
+        }
+
+        Ok(shrunk)
+    }
+}
+
+/// Read-only view of a [`TimeSeriesTable`]
+pub struct ReadOnlyTimeSeriesTable<'txn, V: RedbValue + 'static> {
+    points: ReadOnlyTable<'txn, (u64, u64), V>,
+}
+
+impl<'txn, V: RedbValue + 'static> ReadOnlyTimeSeriesTable<'txn, V> {
+    /// Opens the time series table called `name`
+    pub fn open(transaction: &'txn ReadTransaction, name: &str) -> Result<Self> {
+        let points = format!("{name}{POINTS_SUFFIX}");
+        Ok(Self {
+            points: transaction.open_table(TableDefinition::new(&points))?,
+        })
+    }
+
+    /// Returns the value of `series` at `timestamp`
+    pub fn get(&self, series: u64, timestamp: u64) -> Result<Option<AccessGuard<'_, V>>> {
+        get(&self.points, series, timestamp)
+    }
+
+    /// Returns the points of `series` whose timestamps are in `times`, in time order
+    pub fn range(
+        &self,
+        series: u64,
+        times: impl RangeBounds<u64>,
+    ) -> Result<Range<'_, (u64, u64), V>> {
+        range(&self.points, series, times)
+    }
+
+    /// Returns the timestamp and value of the most recent point in `series`
+    pub fn last(&self, series: u64) -> Result<Option<(u64, AccessGuard<'_, V>)>> {
+        last(&self.points, series)
+    }
+}
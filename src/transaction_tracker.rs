@@ -116,11 +116,99 @@ impl TransactionTracker {
         self.valid_savepoints.contains(&id)
     }
 
-    pub(crate) fn invalidate_savepoints_after(&mut self, id: SavepointId) {
-        self.valid_savepoints.retain(|x| *x <= id);
+    pub(crate) fn invalidate_savepoints_after(
+        &mut self,
+        id: SavepointId,
+        retained: &[SavepointId],
+    ) {
+        self.valid_savepoints
+            .retain(|x| *x <= id || retained.contains(x));
     }
 
     pub(crate) fn oldest_live_read_transaction(&self) -> Option<TransactionId> {
         self.live_read_transactions.keys().next().cloned()
     }
 }
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    use crate::sync::atomic::{AtomicU64, Ordering};
+    use crate::sync::Mutex;
+    use crate::transaction_tracker::{TransactionId, TransactionTracker};
+    use loom::sync::Arc;
+    use loom::thread;
+
+    // Models a read transaction beginning while two commits are made. A commit reclaims the pages
+    // freed by transactions before the oldest live read, and the reader needs the pages freed by
+    // any transaction after its snapshot. Database::allocate_read_transaction() loads the last
+    // committed id while holding the tracker lock, which is what makes this safe
+    #[test]
+    fn read_transaction_during_commits() {
+        loom::model(|| {
+            let tracker = Arc::new(Mutex::new(TransactionTracker::new()));
+            let last_committed = Arc::new(AtomicU64::new(1));
+
+            let reader = {
+                let tracker = tracker.clone();
+                let last_committed = last_committed.clone();
+                thread::spawn(move || {
+                    let mut tracker = tracker.lock().unwrap();
+                    let id = TransactionId(last_committed.load(Ordering::Acquire));
+                    tracker.register_read_transaction(id);
+                    id
+                })
+            };
+
+            let mut reclaimed_before = vec![];
+            for id in [TransactionId(2), TransactionId(3)] {
+                let oldest_live_read = tracker
+                    .lock()
+                    .unwrap()
+                    .oldest_live_read_transaction()
+                    .unwrap_or(id);
+                reclaimed_before.push(oldest_live_read);
+                last_committed.store(id.0, Ordering::Release);
+            }
+
+            let read_id = reader.join().unwrap();
+            for oldest_live_read in reclaimed_before {
+                assert!(oldest_live_read <= read_id.next());
+            }
+            let mut tracker = tracker.lock().unwrap();
+            tracker.deallocate_read_transaction(read_id);
+            assert_eq!(tracker.oldest_live_read_transaction(), None);
+        });
+    }
+
+    #[test]
+    fn concurrent_savepoints() {
+        loom::model(|| {
+            let tracker = Arc::new(Mutex::new(TransactionTracker::new()));
+
+            let threads: Vec<_> = (0..2)
+                .map(|_| {
+                    let tracker = tracker.clone();
+                    thread::spawn(move || {
+                        let id = tracker.lock().unwrap().allocate_savepoint();
+                        tracker
+                            .lock()
+                            .unwrap()
+                            .register_read_transaction(TransactionId(1));
+                        id
+                    })
+                })
+                .collect();
+            let mut ids: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), 2);
+
+            let tracker = tracker.lock().unwrap();
+            assert!(ids.iter().all(|id| tracker.is_valid_savepoint(*id)));
+            assert_eq!(
+                tracker.live_read_transactions.get(&TransactionId(1)),
+                Some(&2)
+            );
+        });
+    }
+}
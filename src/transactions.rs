@@ -1,31 +1,119 @@
+use crate::cascade::ReferencingTable;
+use crate::content_hash::{compute_content_hash, empty_content_hash, update_content_hash};
+use crate::histogram::{compute_key_histogram, histogram_bucket_size, update_key_histogram};
 use crate::sealed::Sealed;
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::{Mutex, MutexGuard, RwLock};
+use crate::table::TableNamespace;
 use crate::transaction_tracker::{SavepointId, TransactionId, TransactionTracker};
 use crate::tree_store::{
-    Btree, BtreeMut, FreedPageList, FreedTableKey, InternalTableDefinition, PageHint, PageNumber,
-    TableTree, TableType, TransactionalMemory,
+    read_archive, write_archive, AllPageNumbersBtreeIter, Btree, BtreeMut, Checksum, FreedPageList,
+    FreedTableKey, InternalTableDefinition, PageHint, PageNumber, RawBtree, TableTree, TableType,
+    TransactionalMemory,
 };
-use crate::types::{RedbKey, RedbValue};
+use crate::types::{RedbKey, RedbValue, TypeNameCheck};
 use crate::{
-    Database, Error, MultimapTable, MultimapTableDefinition, MultimapTableHandle,
-    ReadOnlyMultimapTable, ReadOnlyTable, ReadableTable, Result, Savepoint, Table, TableDefinition,
-    TableHandle, UntypedMultimapTableHandle, UntypedTableHandle,
+    ContentHash, Database, Error, FillPolicy, KeyHistogram, MultimapTable, MultimapTableDefinition,
+    MultimapTableHandle, OwnedReadTable, ReadOnlyMultimapTable, ReadOnlyTable, ReadableTable,
+    Result, Savepoint, Table, TableDefinition, TableGroup, TableHandle, UntypedMultimapTableHandle,
+    UntypedTableHandle,
 };
 #[cfg(feature = "logging")]
 use log::{info, warn};
-use std::cmp::min;
+use std::borrow::Borrow;
+use std::cmp::{self, min};
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
 use std::marker::PhantomData;
 use std::ops::RangeFull;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{panic, thread};
 
 const NEXT_SAVEPOINT_TABLE: SystemTableDefinition<(), u64> =
     SystemTableDefinition::new("next_savepoint_id");
-const SAVEPOINT_TABLE: SystemTableDefinition<u64, &[u8]> =
+pub(crate) const SAVEPOINT_TABLE: SystemTableDefinition<u64, &[u8]> =
     SystemTableDefinition::new("persistent_savepoints");
+// Creation time of each persistent savepoint, in milliseconds since the Unix epoch. Kept separately
+// so that the savepoint format is unchanged
+const SAVEPOINT_CREATED_TABLE: SystemTableDefinition<u64, u64> =
+    SystemTableDefinition::new("persistent_savepoint_created");
+const SEQUENCE_TABLE: SystemTableDefinition<&str, u64> = SystemTableDefinition::new("sequences");
+// Maps the name of each table with a maintained key histogram to the serialized histogram
+const KEY_HISTOGRAM_TABLE: SystemTableDefinition<&str, &[u8]> =
+    SystemTableDefinition::new("key_histograms");
+// Maps the name of each table with a maintained content hash to the serialized hash
+const CONTENT_HASH_TABLE: SystemTableDefinition<&str, &[u8]> =
+    SystemTableDefinition::new("content_hashes");
+// Number of ids reserved from a sequence each time it is written to the sequence table
+const SEQUENCE_RESERVATION_SIZE: u64 = 1024;
+// Prefix applied to the names of system tables opened by applications, so that they can't collide
+// with the system tables used internally
+const APPLICATION_SYSTEM_TABLE_PREFIX: &str = "app::";
+
+// Orders the serialized keys of a table
+type KeyOrder = fn(&[u8], &[u8]) -> cmp::Ordering;
+
+fn application_system_table_name(name: &str) -> String {
+    format!("{APPLICATION_SYSTEM_TABLE_PREFIX}{name}")
+}
+
+// Reads the summary of the table `name`, such as its key histogram, from the system table
+// `summaries`
+fn read_table_summary<T>(
+    system_tree: &TableTree,
+    summaries: SystemTableDefinition<&str, &[u8]>,
+    name: &str,
+    mem: &TransactionalMemory,
+    parse: impl FnOnce(&[u8]) -> T,
+) -> Result<Option<T>> {
+    if let Some(definition) =
+        system_tree.get_table::<&str, &[u8]>(summaries.name(), TableType::Normal)?
+    {
+        let tree: Btree<&str, &[u8]> = Btree::new(definition.get_root(), PageHint::None, mem)?;
+        Ok(tree.get(&name)?.map(|x| parse(x.value())))
+    } else {
+        Ok(None)
+    }
+}
 
+fn read_key_histogram<K: RedbKey + 'static>(
+    system_tree: &TableTree,
+    name: &str,
+    mem: &TransactionalMemory,
+) -> Result<Option<KeyHistogram<K>>> {
+    read_table_summary(
+        system_tree,
+        KEY_HISTOGRAM_TABLE,
+        name,
+        mem,
+        KeyHistogram::from_bytes,
+    )
+}
+
+fn read_content_hash<K: RedbKey + 'static>(
+    system_tree: &TableTree,
+    name: &str,
+    mem: &TransactionalMemory,
+) -> Result<Option<ContentHash<K>>> {
+    read_table_summary(
+        system_tree,
+        CONTENT_HASH_TABLE,
+        name,
+        mem,
+        ContentHash::from_bytes,
+    )
+}
+
+/// Defines the name and types of a system table
+///
+/// A [`SystemTableDefinition`] should be opened for use by calling
+/// [`ReadTransaction::open_system_table`] or [`WriteTransaction::open_system_table`].
+///
+/// System tables are stored separately from user tables, so they never collide with a table of
+/// the same name and are not returned by `list_tables()`. They are intended for applications and
+/// frameworks built on redb to store their own metadata.
 pub struct SystemTableDefinition<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
     name: &'a str,
     _key_type: PhantomData<K>,
@@ -33,6 +121,11 @@ pub struct SystemTableDefinition<'a, K: RedbKey + 'static, V: RedbValue + 'stati
 }
 
 impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> SystemTableDefinition<'a, K, V> {
+    /// Construct a new system table with given `name`
+    ///
+    /// ## Invariant
+    ///
+    /// `name` must not be empty.
     pub const fn new(name: &'a str) -> Self {
         assert!(!name.is_empty());
         Self {
@@ -73,11 +166,172 @@ impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Display for SystemTableDe
     }
 }
 
+// Ids of a sequence which have been reserved in the sequence table, but not yet handed out.
+// The sequence table stores `end`
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SequenceReservation {
+    next: u64,
+    end: u64,
+}
+
+/// Counts of the changes made to a table by a transaction
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct TableWriteStats {
+    pub(crate) inserted: u64,
+    pub(crate) updated: u64,
+    pub(crate) removed: u64,
+}
+
+impl TableWriteStats {
+    /// Number of keys inserted, which were not previously present
+    pub fn inserted(&self) -> u64 {
+        self.inserted
+    }
+
+    /// Number of keys whose value was replaced
+    pub fn updated(&self) -> u64 {
+        self.updated
+    }
+
+    /// Number of keys removed
+    pub fn removed(&self) -> u64 {
+        self.removed
+    }
+
+    fn merge(&mut self, other: TableWriteStats) {
+        self.inserted += other.inserted;
+        self.updated += other.updated;
+        self.removed += other.removed;
+    }
+}
+
+/// Summary of the changes made by a committed transaction, returned by
+/// [`WriteTransaction::commit_with_summary`]
+#[derive(Debug)]
+pub struct CommitSummary {
+    tables: HashMap<String, TableWriteStats>,
+    allocated_pages: u64,
+    freed_pages: u64,
+    bytes_written: u64,
+}
+
+impl CommitSummary {
+    /// Changes made to the given table, or `None` if it was not modified
+    ///
+    /// Only tables opened with [`WriteTransaction::open_table`] are tracked
+    pub fn table(&self, name: &str) -> Option<TableWriteStats> {
+        self.tables.get(name).copied()
+    }
+
+    /// Iterate over the names of all modified tables, and the changes made to them
+    pub fn tables(&self) -> impl Iterator<Item = (&str, TableWriteStats)> {
+        self.tables
+            .iter()
+            .map(|(name, stats)| (name.as_str(), *stats))
+    }
+
+    /// Number of pages allocated by the transaction
+    pub fn allocated_pages(&self) -> u64 {
+        self.allocated_pages
+    }
+
+    /// Number of pages freed by the transaction, including pages released by earlier transactions
+    /// that were no longer referenced by any reader
+    pub fn freed_pages(&self) -> u64 {
+        self.freed_pages
+    }
+
+    /// Number of bytes written to the database file by the transaction. Unless it was committed
+    /// with [`Durability::None`], these writes were persisted before the commit returned
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+}
+
+/// Information about a persistent savepoint, returned by [`Database::savepoint_metadata`]
+#[derive(Debug, Clone)]
+pub struct SavepointMetadata {
+    pub(crate) id: u64,
+    pub(crate) created: Option<SystemTime>,
+    pub(crate) pinned_bytes: u64,
+}
+
+impl SavepointMetadata {
+    /// Id of the savepoint
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// When the savepoint was created. `None` for savepoints created by an earlier version of redb
+    pub fn created(&self) -> Option<SystemTime> {
+        self.created
+    }
+
+    /// Number of bytes of table pages which are only retained because of this savepoint, since
+    /// they are no longer reachable from the latest commit. Pages shared with other savepoints are
+    /// counted by each of them
+    pub fn pinned_bytes(&self) -> u64 {
+        self.pinned_bytes
+    }
+}
+
+/// A breakdown of how the space in the database file is used, returned by
+/// [`Database::space_report`]
+#[derive(Debug, Clone)]
+pub struct SpaceReport {
+    pub(crate) file_bytes: u64,
+    pub(crate) table_bytes: u64,
+    pub(crate) system_bytes: u64,
+    pub(crate) savepoint_bytes: u64,
+    pub(crate) free_bytes: u64,
+}
+
+impl SpaceReport {
+    /// Size of the database file
+    pub fn file_bytes(&self) -> u64 {
+        self.file_bytes
+    }
+
+    /// Bytes of pages holding user tables, including the table of tables, as of the latest commit
+    pub fn table_bytes(&self) -> u64 {
+        self.table_bytes
+    }
+
+    /// Bytes of pages holding system tables, as of the latest commit
+    pub fn system_bytes(&self) -> u64 {
+        self.system_bytes
+    }
+
+    /// Bytes of pages which are only retained because persistent savepoints reference them.
+    /// Deleting the savepoints allows these pages to be freed
+    pub fn savepoint_bytes(&self) -> u64 {
+        self.savepoint_bytes
+    }
+
+    /// Bytes of pages which are free for reuse. [`Database::compact`] can return these to the
+    /// operating system
+    pub fn free_bytes(&self) -> u64 {
+        self.free_bytes
+    }
+
+    /// Bytes accounted for by none of the other categories: the file header, allocator state,
+    /// pages waiting to be freed once older read transactions complete, and the list of such
+    /// pages
+    pub fn slack_bytes(&self) -> u64 {
+        self.file_bytes.saturating_sub(
+            self.table_bytes + self.system_bytes + self.savepoint_bytes + self.free_bytes,
+        )
+    }
+}
+
 /// Informational storage stats about the database
 #[derive(Debug)]
 pub struct DatabaseStats {
     pub(crate) tree_height: u32,
     pub(crate) allocated_pages: u64,
+    pub(crate) free_pages: u64,
+    pub(crate) largest_free_block: u64,
+    pub(crate) pending_free_pages: u64,
     pub(crate) leaf_pages: u64,
     pub(crate) branch_pages: u64,
     pub(crate) stored_leaf_bytes: u64,
@@ -97,6 +351,24 @@ impl DatabaseStats {
         self.allocated_pages
     }
 
+    /// Number of pages in the file which are free for reuse
+    pub fn free_pages(&self) -> u64 {
+        self.free_pages
+    }
+
+    /// Number of pages in the largest contiguous block of free pages. If this is much smaller than
+    /// [`Self::free_pages`], the free space is fragmented, and large values may require the file to
+    /// grow
+    pub fn largest_free_block(&self) -> u64 {
+        self.largest_free_block
+    }
+
+    /// Number of pages which have been freed, but cannot be reused until the read transactions
+    /// or savepoints that reference them are released, or until a durable commit
+    pub fn pending_free_pages(&self) -> u64 {
+        self.pending_free_pages
+    }
+
     /// Number of leaf pages that store user data
     pub fn leaf_pages(&self) -> u64 {
         self.leaf_pages
@@ -184,6 +456,30 @@ pub enum Durability {
     Paranoid,
 }
 
+/// What happens when a [`WriteTransaction`] is dropped without calling
+/// [`WriteTransaction::commit`] or [`WriteTransaction::abort`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum DropBehavior {
+    /// The transaction is aborted, and its writes are rolled back
+    Abort,
+    /// The transaction is aborted, and then the drop panics. Use this to catch code paths which
+    /// discard a transaction by mistake
+    ///
+    /// No panic occurs if the thread is already panicking
+    Panic,
+}
+
+impl Default for DropBehavior {
+    fn default() -> Self {
+        if cfg!(feature = "strict_drop") {
+            DropBehavior::Panic
+        } else {
+            DropBehavior::Abort
+        }
+    }
+}
+
 /// A read/write transaction
 ///
 /// Only a single [`WriteTransaction`] may exist at a time
@@ -194,6 +490,10 @@ pub struct WriteTransaction<'db> {
     transaction_id: TransactionId,
     table_tree: RwLock<TableTree<'db>>,
     system_table_tree: RwLock<TableTree<'db>>,
+    // Tables which are discarded when the transaction completes. All of their pages are uncommitted,
+    // and so can be freed immediately
+    temp_table_tree: RwLock<TableTree<'db>>,
+    temp_freed_pages: Arc<Mutex<Vec<PageNumber>>>,
     // The table of freed pages by transaction. FreedTableKey -> binary.
     // The binary blob is a length-prefixed array of PageNumber
     freed_tree: Mutex<BtreeMut<'db, FreedTableKey, FreedPageList<'static>>>,
@@ -203,12 +503,39 @@ pub struct WriteTransaction<'db> {
     post_commit_frees: Arc<Mutex<Vec<PageNumber>>>,
     open_tables: Mutex<HashMap<String, &'static panic::Location<'static>>>,
     open_system_tables: Mutex<HashMap<String, &'static panic::Location<'static>>>,
+    open_temp_tables: Mutex<HashMap<String, &'static panic::Location<'static>>>,
     completed: bool,
     dirty: AtomicBool,
     durability: Durability,
+    drop_behavior: DropBehavior,
     // Persistent savepoints created during this transaction
     created_persistent_savepoints: Mutex<HashSet<u64>>,
-    live_write_transaction: MutexGuard<'db, Option<TransactionId>>,
+    // Persistent savepoints removed by restoring an older savepoint. They are released from the
+    // transaction tracker once the transaction commits
+    discarded_persistent_savepoints: Mutex<Vec<Savepoint>>,
+    // Changes made to each user table, merged in as tables are closed
+    table_stats: Mutex<HashMap<String, TableWriteStats>>,
+    // Allocator and file write totals when the transaction began, used to build the CommitSummary
+    allocation_totals_at_start: (u64, u64),
+    bytes_written_at_start: u64,
+    // Tables whose key histogram was enabled or analyzed during this transaction, and so must be
+    // computed on commit
+    analyzed_key_histograms: Mutex<HashSet<String>>,
+    // Tables whose content hash was enabled during this transaction
+    enabled_content_hashes: Mutex<HashSet<String>>,
+    // Root of the table tree described by the stored summaries: the last one committed, or that
+    // of a restored savepoint
+    summarized_root: Option<(PageNumber, Checksum)>,
+    // How to order the keys of each user table opened during this transaction, so that summaries
+    // can be updated from the changes made to the table instead of recomputed
+    key_orders: Mutex<HashMap<String, KeyOrder>>,
+    // Sequence reservations updated during this transaction. Published to the Database on commit
+    sequences: Mutex<HashMap<String, SequenceReservation>>,
+    // Set when a savepoint is restored, since the reservations held by the Database may no longer
+    // match the sequence table
+    sequences_invalidated: bool,
+    // Only None while the transaction is being dropped
+    live_write_transaction: Option<MutexGuard<'db, Option<TransactionId>>>,
 }
 
 impl<'db> WriteTransaction<'db> {
@@ -228,6 +555,7 @@ impl<'db> WriteTransaction<'db> {
         let freed_root = db.get_memory().get_freed_root();
         let freed_pages = Arc::new(Mutex::new(vec![]));
         let post_commit_frees = Arc::new(Mutex::new(vec![]));
+        let temp_freed_pages = Arc::new(Mutex::new(vec![]));
         Ok(Self {
             db,
             transaction_tracker,
@@ -243,6 +571,12 @@ impl<'db> WriteTransaction<'db> {
                 db.get_memory(),
                 freed_pages.clone(),
             )),
+            temp_table_tree: RwLock::new(TableTree::new(
+                None,
+                db.get_memory(),
+                temp_freed_pages.clone(),
+            )),
+            temp_freed_pages,
             freed_tree: Mutex::new(BtreeMut::new(
                 freed_root,
                 db.get_memory(),
@@ -252,15 +586,38 @@ impl<'db> WriteTransaction<'db> {
             post_commit_frees,
             open_tables: Mutex::new(Default::default()),
             open_system_tables: Mutex::new(Default::default()),
+            open_temp_tables: Mutex::new(Default::default()),
             completed: false,
             dirty: AtomicBool::new(false),
             durability: Durability::Immediate,
+            drop_behavior: DropBehavior::default(),
             created_persistent_savepoints: Mutex::new(Default::default()),
-            live_write_transaction,
+            discarded_persistent_savepoints: Mutex::new(Default::default()),
+            table_stats: Mutex::new(Default::default()),
+            allocation_totals_at_start: db.get_memory().allocation_totals(),
+            bytes_written_at_start: db.get_memory().bytes_written(),
+            analyzed_key_histograms: Mutex::new(Default::default()),
+            enabled_content_hashes: Mutex::new(Default::default()),
+            summarized_root: root_page,
+            key_orders: Mutex::new(Default::default()),
+            sequences: Mutex::new(Default::default()),
+            sequences_invalidated: false,
+            live_write_transaction: Some(live_write_transaction),
         })
     }
 
-    fn open_system_table<'txn, K: RedbKey + 'static, V: RedbValue + 'static>(
+    /// Open the given system table
+    ///
+    /// The table will be created if it does not exist
+    pub fn open_system_table<'txn, K: RedbKey + 'static, V: RedbValue + 'static>(
+        &'txn self,
+        definition: SystemTableDefinition<K, V>,
+    ) -> Result<Table<'db, 'txn, K, V>> {
+        let name = application_system_table_name(definition.name());
+        self.open_internal_system_table(SystemTableDefinition::new(&name))
+    }
+
+    pub(crate) fn open_internal_system_table<'txn, K: RedbKey + 'static, V: RedbValue + 'static>(
         &'txn self,
         definition: SystemTableDefinition<K, V>,
     ) -> Result<Table<'db, 'txn, K, V>> {
@@ -278,21 +635,26 @@ impl<'db> WriteTransaction<'db> {
             ));
         }
         self.dirty.store(true, Ordering::Release);
-        self.open_system_tables
-            .lock()
-            .unwrap()
-            .insert(definition.name().to_string(), panic::Location::caller());
 
         let internal_table = self
             .system_table_tree
             .write()
             .unwrap()
-            .get_or_create_table::<K, V>(definition.name(), TableType::Normal)?;
+            .get_or_create_table::<K, V>(
+                definition.name(),
+                TableType::Normal,
+                TypeNameCheck::Strict,
+            )?;
+        self.open_system_tables
+            .lock()
+            .unwrap()
+            .insert(definition.name().to_string(), panic::Location::caller());
 
         Ok(Table::new(
             definition.name(),
-            true,
+            TableNamespace::System,
             internal_table.get_root(),
+            FillPolicy::default(),
             self.freed_pages.clone(),
             self.mem,
             self,
@@ -309,11 +671,22 @@ impl<'db> WriteTransaction<'db> {
     pub fn persistent_savepoint(&self) -> Result<u64> {
         let mut savepoint = self.ephemeral_savepoint()?;
 
-        let mut next_table = self.open_system_table(NEXT_SAVEPOINT_TABLE)?;
-        let mut savepoint_table = self.open_system_table(SAVEPOINT_TABLE)?;
+        let mut next_table = self.open_internal_system_table(NEXT_SAVEPOINT_TABLE)?;
+        let mut savepoint_table = self.open_internal_system_table(SAVEPOINT_TABLE)?;
         next_table.insert((), savepoint.get_id().0 + 1)?;
 
         savepoint_table.insert(savepoint.get_id().0, savepoint.to_bytes().as_slice())?;
+        let created: u64 = self
+            .db
+            .clock()
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+            .try_into()
+            .unwrap();
+        self.open_internal_system_table(SAVEPOINT_CREATED_TABLE)?
+            .insert(savepoint.get_id().0, created)?;
 
         savepoint.set_persistent();
 
@@ -326,7 +699,7 @@ impl<'db> WriteTransaction<'db> {
     }
 
     pub(crate) fn next_persistent_savepoint_id(&self) -> Result<Option<SavepointId>> {
-        let next_table = self.open_system_table(NEXT_SAVEPOINT_TABLE)?;
+        let next_table = self.open_internal_system_table(NEXT_SAVEPOINT_TABLE)?;
         let value = next_table.get(())?;
         if let Some(next_id) = value {
             Ok(Some(SavepointId(next_id.value())))
@@ -337,7 +710,7 @@ impl<'db> WriteTransaction<'db> {
 
     /// Get a persistent savepoint given its id
     pub fn get_persistent_savepoint(&self, id: u64) -> Result<Savepoint> {
-        let table = self.open_system_table(SAVEPOINT_TABLE)?;
+        let table = self.open_internal_system_table(SAVEPOINT_TABLE)?;
         let value = table.get(id)?;
 
         value
@@ -345,12 +718,22 @@ impl<'db> WriteTransaction<'db> {
             .ok_or(Error::InvalidSavepoint)
     }
 
+    pub(crate) fn persistent_savepoint_created(&self, id: u64) -> Result<Option<SystemTime>> {
+        let table = self.open_internal_system_table(SAVEPOINT_CREATED_TABLE)?;
+        let created = table
+            .get(id)?
+            .map(|x| UNIX_EPOCH + Duration::from_millis(x.value()));
+        Ok(created)
+    }
+
     /// Delete the given persistent savepoint.
     ///
     /// Returns `true` if the savepoint existed
     pub fn delete_persistent_savepoint(&self, id: u64) -> Result<bool> {
-        let mut table = self.open_system_table(SAVEPOINT_TABLE)?;
+        let mut table = self.open_internal_system_table(SAVEPOINT_TABLE)?;
         let savepoint = table.remove(id)?;
+        self.open_internal_system_table(SAVEPOINT_CREATED_TABLE)?
+            .remove(id)?;
         if let Some(bytes) = savepoint {
             let savepoint =
                 Savepoint::from_bytes(bytes.value(), self.transaction_tracker.clone(), false);
@@ -364,9 +747,326 @@ impl<'db> WriteTransaction<'db> {
         }
     }
 
+    // Removes the records of every persistent savepoint, without freeing the pages they reference.
+    // Used when those pages are not part of this database
+    pub(crate) fn discard_persistent_savepoint_records(&self) -> Result {
+        self.open_internal_system_table(SAVEPOINT_TABLE)?
+            .drain::<u64>(..)?;
+        self.open_internal_system_table(SAVEPOINT_CREATED_TABLE)?
+            .drain::<u64>(..)?;
+        Ok(())
+    }
+
+    /// Returns the next id from the sequence with the given name
+    ///
+    /// Ids start at 0 and are strictly increasing across transactions. They are reserved in batches,
+    /// so ids may be skipped if the transaction is aborted or the database is closed. An id handed
+    /// out by a committed transaction is only returned again if a savepoint created before it was
+    /// handed out is restored.
+    pub fn next_sequence(&self, name: &str) -> Result<u64> {
+        let mut sequences = self.sequences.lock().unwrap();
+        let reservation = if let Some(reservation) = sequences.get(name) {
+            Some(*reservation)
+        } else if !self.sequences_invalidated {
+            self.db.sequences.lock().unwrap().get(name).copied()
+        } else {
+            None
+        };
+        if let Some(reservation) = reservation {
+            if reservation.next < reservation.end {
+                sequences.insert(
+                    name.to_string(),
+                    SequenceReservation {
+                        next: reservation.next + 1,
+                        end: reservation.end,
+                    },
+                );
+                return Ok(reservation.next);
+            }
+        }
+
+        let mut table = self.open_internal_system_table(SEQUENCE_TABLE)?;
+        let start = table.get(name)?.map(|x| x.value()).unwrap_or(0);
+        let end = start + SEQUENCE_RESERVATION_SIZE;
+        table.insert(name, &end)?;
+        sequences.insert(
+            name.to_string(),
+            SequenceReservation {
+                next: start + 1,
+                end,
+            },
+        );
+
+        Ok(start)
+    }
+
+    /// Store a [`KeyHistogram`] of the given table, whose buckets each contain `bucket_size`
+    /// entries
+    ///
+    /// The histogram is computed when this transaction commits, which requires visiting every leaf
+    /// page of the table. Later commits update the buckets which entries were inserted into or
+    /// removed from, so bucket sizes drift from `bucket_size` as the table changes, and
+    /// [`Self::analyze_key_histogram`] can be used to rebalance them. It is discarded if the table
+    /// is deleted.
+    ///
+    /// Returns [`Error::TableDoesNotExist`] if the table does not exist
+    ///
+    /// ## Invariant
+    ///
+    /// `bucket_size` must be greater than zero
+    pub fn enable_key_histogram(&self, definition: impl TableHandle, bucket_size: u64) -> Result {
+        assert!(bucket_size > 0);
+        let name = definition.name();
+        self.check_table_exists(name)?;
+        // Store an empty histogram, which is replaced when the transaction commits
+        let histogram = compute_key_histogram(None, None, None, bucket_size, self.mem)?;
+        let mut table = self.open_internal_system_table(KEY_HISTOGRAM_TABLE)?;
+        table.insert(name, histogram.as_slice())?;
+        self.analyzed_key_histograms
+            .lock()
+            .unwrap()
+            .insert(name.to_string());
+
+        Ok(())
+    }
+
+    /// Recompute the [`KeyHistogram`] of the given table when this transaction commits, so that its
+    /// buckets each contain the bucket size entries again
+    ///
+    /// Returns `false` if no histogram is stored for the table. See [`Self::enable_key_histogram`]
+    pub fn analyze_key_histogram(&self, definition: impl TableHandle) -> Result<bool> {
+        let name = definition.name();
+        let table = self.open_internal_system_table(KEY_HISTOGRAM_TABLE)?;
+        if table.get(name)?.is_none() {
+            return Ok(false);
+        }
+        self.analyzed_key_histograms
+            .lock()
+            .unwrap()
+            .insert(name.to_string());
+
+        Ok(true)
+    }
+
+    /// Stop storing the [`KeyHistogram`] of the given table
+    ///
+    /// Returns a bool indicating whether a histogram was stored
+    pub fn disable_key_histogram(&self, definition: impl TableHandle) -> Result<bool> {
+        let name = definition.name();
+        self.analyzed_key_histograms.lock().unwrap().remove(name);
+        let mut table = self.open_internal_system_table(KEY_HISTOGRAM_TABLE)?;
+        let existed = table.remove(name)?.is_some();
+        Ok(existed)
+    }
+
+    pub(crate) fn key_histogram<K: RedbKey + 'static>(
+        &self,
+        name: &str,
+    ) -> Result<Option<KeyHistogram<K>>> {
+        read_key_histogram(&self.system_table_tree.read().unwrap(), name, self.mem)
+    }
+
+    /// Maintain a [`ContentHash`] of the given table
+    ///
+    /// The hash is updated whenever a transaction which modified the table commits. Only the
+    /// chunks of entries which contain a modified key are rehashed, unless the table was modified
+    /// without being opened, for example by restoring a savepoint, in which case every entry of
+    /// the table is read. It is discarded if the table is deleted.
+    ///
+    /// Returns [`Error::TableDoesNotExist`] if the table does not exist
+    pub fn enable_content_hash(&self, definition: impl TableHandle) -> Result {
+        let name = definition.name();
+        self.check_table_exists(name)?;
+        // Store an empty hash, which is replaced when the transaction commits
+        let mut table = self.open_internal_system_table(CONTENT_HASH_TABLE)?;
+        table.insert(name, empty_content_hash().as_slice())?;
+        self.enabled_content_hashes
+            .lock()
+            .unwrap()
+            .insert(name.to_string());
+
+        Ok(())
+    }
+
+    /// Stop maintaining the [`ContentHash`] of the given table
+    ///
+    /// Returns a bool indicating whether a hash was maintained
+    pub fn disable_content_hash(&self, definition: impl TableHandle) -> Result<bool> {
+        let name = definition.name();
+        self.enabled_content_hashes.lock().unwrap().remove(name);
+        let mut table = self.open_internal_system_table(CONTENT_HASH_TABLE)?;
+        let existed = table.remove(name)?.is_some();
+        Ok(existed)
+    }
+
+    pub(crate) fn content_hash<K: RedbKey + 'static>(
+        &self,
+        name: &str,
+    ) -> Result<Option<ContentHash<K>>> {
+        read_content_hash(&self.system_table_tree.read().unwrap(), name, self.mem)
+    }
+
+    fn check_table_exists(&self, name: &str) -> Result {
+        if self
+            .table_tree
+            .read()
+            .unwrap()
+            .get_table_untyped(name, TableType::Normal)?
+            .is_none()
+        {
+            return Err(Error::TableDoesNotExist(name.to_string()));
+        }
+        Ok(())
+    }
+
+    // Computes the histograms of tables which were enabled or analyzed by this transaction, updates
+    // those of tables which were modified, and discards those of tables which no longer exist
+    fn update_key_histograms(&self) -> Result {
+        let analyzed = std::mem::take(&mut *self.analyzed_key_histograms.lock().unwrap());
+        self.update_table_summaries(
+            KEY_HISTOGRAM_TABLE,
+            &analyzed,
+            true,
+            |definition, base, histogram| {
+                if let Some((base, compare)) = base {
+                    let raw_tree = |definition: &InternalTableDefinition| {
+                        RawBtree::new(
+                            definition.get_root(),
+                            definition.get_fixed_key_size(),
+                            definition.get_fixed_value_size(),
+                            self.mem,
+                        )
+                    };
+                    update_key_histogram(histogram, &raw_tree(base), &raw_tree(definition), compare)
+                } else {
+                    compute_key_histogram(
+                        definition.get_root().map(|(page, _)| page),
+                        definition.get_fixed_key_size(),
+                        definition.get_fixed_value_size(),
+                        histogram_bucket_size(histogram),
+                        self.mem,
+                    )
+                }
+            },
+        )
+    }
+
+    // Updates the content hashes of tables which were modified by this transaction, and discards
+    // those of tables which no longer exist
+    fn update_content_hashes(&self) -> Result {
+        let enabled = std::mem::take(&mut *self.enabled_content_hashes.lock().unwrap());
+        self.update_table_summaries(
+            CONTENT_HASH_TABLE,
+            &enabled,
+            true,
+            |definition, base, hash| {
+                if let Some((base, compare)) = base {
+                    let raw_tree = |definition: &InternalTableDefinition| {
+                        RawBtree::new(
+                            definition.get_root(),
+                            definition.get_fixed_key_size(),
+                            definition.get_fixed_value_size(),
+                            self.mem,
+                        )
+                    };
+                    update_content_hash(hash, &raw_tree(base), &raw_tree(definition), compare)
+                } else {
+                    compute_content_hash(definition, self.mem)
+                }
+            },
+        )
+    }
+
+    // Recomputes, with `compute`, the summary stored in `summaries` of each table which is in
+    // `enabled`, or if `include_modified` is set, was modified by this transaction. `compute` is
+    // passed the previous summary. For a modified table which is not in `enabled`, it is also
+    // passed the table as described by that summary, and the order of its keys, if the table was
+    // opened during this transaction and its types are unchanged. Summaries of tables which no
+    // longer exist are removed
+    fn update_table_summaries(
+        &self,
+        summaries: SystemTableDefinition<&str, &[u8]>,
+        enabled: &HashSet<String>,
+        include_modified: bool,
+        compute: impl Fn(
+            &InternalTableDefinition,
+            Option<(&InternalTableDefinition, KeyOrder)>,
+            &[u8],
+        ) -> Result<Vec<u8>>,
+    ) -> Result {
+        if self
+            .system_table_tree
+            .read()
+            .unwrap()
+            .get_table_untyped(summaries.name(), TableType::Normal)?
+            .is_none()
+        {
+            return Ok(());
+        }
+        let summarized_tree = TableTree::new(self.summarized_root, self.mem, Default::default());
+        let mut table = self.open_internal_system_table(summaries)?;
+        let mut updated = vec![];
+        let mut removed = vec![];
+        for entry in table.range::<&str>(..)? {
+            let (name, summary) = entry?;
+            let name = name.value().to_string();
+            let definition = match self
+                .table_tree
+                .read()
+                .unwrap()
+                .get_table_untyped(&name, TableType::Normal)
+            {
+                Ok(definition) => definition,
+                Err(Error::TableIsMultimap(_)) => None,
+                Err(err) => return Err(err),
+            };
+            let definition = if let Some(definition) = definition {
+                definition
+            } else {
+                removed.push(name);
+                continue;
+            };
+            if enabled.contains(&name) {
+                updated.push((name, compute(&definition, None, summary.value())?));
+                continue;
+            }
+            if !include_modified {
+                continue;
+            }
+            let summarized = match summarized_tree.get_table_untyped(&name, TableType::Normal) {
+                Ok(definition) => definition,
+                Err(Error::TableIsMultimap(_)) => None,
+                Err(err) => return Err(err),
+            };
+            let summarized_root = summarized
+                .as_ref()
+                .and_then(|x| x.get_root())
+                .map(|(page, _)| page);
+            if definition.get_root().map(|(page, _)| page) == summarized_root {
+                continue;
+            }
+            let key_order = self.key_orders.lock().unwrap().get(&name).copied();
+            let base = match (&summarized, key_order) {
+                (Some(summarized), Some(key_order)) if summarized.has_same_types(&definition) => {
+                    Some((summarized, key_order))
+                }
+                _ => None,
+            };
+            updated.push((name, compute(&definition, base, summary.value())?));
+        }
+        for (name, summary) in updated {
+            table.insert(name.as_str(), summary.as_slice())?;
+        }
+        for name in removed {
+            table.remove(name.as_str())?;
+        }
+
+        Ok(())
+    }
+
     /// List all persistent savepoints
     pub fn list_persistent_savepoints(&self) -> Result<impl Iterator<Item = u64>> {
-        let table = self.open_system_table(SAVEPOINT_TABLE)?;
+        let table = self.open_internal_system_table(SAVEPOINT_TABLE)?;
         let mut savepoints = vec![];
         for savepoint in table.range::<u64>(..)? {
             savepoints.push(savepoint?.0.value());
@@ -413,6 +1113,22 @@ impl<'db> WriteTransaction<'db> {
     ///
     /// Calling this method invalidates all [`Savepoint`]s created after savepoint
     pub fn restore_savepoint(&mut self, savepoint: &Savepoint) -> Result {
+        self.restore_savepoint_retaining(savepoint, None, &[])
+    }
+
+    // Restores `savepoint`, but keeps the persistent savepoints in `retained` valid, even those
+    // created after it. Their pages are freed by this transaction, but are not reused while the
+    // savepoints hold them, so a retained savepoint can itself be restored later.
+    //
+    // `branch_point` must be given when `savepoint` was retained through the restore of an older
+    // savepoint, and be that older savepoint. The entries which were added to the freed tree of
+    // `savepoint` after `branch_point` were never processed by the current state, so they are kept
+    pub(crate) fn restore_savepoint_retaining(
+        &mut self,
+        savepoint: &Savepoint,
+        branch_point: Option<&Savepoint>,
+        retained: &[u64],
+    ) -> Result {
         // Ensure that user does not try to restore a Savepoint that is from a different Database
         assert_eq!(
             self.transaction_tracker.as_ref() as *const _,
@@ -434,10 +1150,38 @@ impl<'db> WriteTransaction<'db> {
             self.transaction_id
         );
         // Restoring a savepoint that reverted a file format or checksum type change could corrupt
-        // the database
-        assert_eq!(self.db.get_memory().get_version(), savepoint.get_version());
+        // the database. Databases with persistent savepoints are not upgraded in place, so every
+        // savepoint uses the current format
+        assert_eq!(savepoint.get_version(), self.db.get_memory().get_version());
         self.dirty.store(true, Ordering::Release);
 
+        // Persistent savepoints are recorded after their snapshot is taken, so the restored system
+        // tables do not contain this savepoint, or any created after it. Retained savepoints are
+        // recorded again once the system tables have been restored
+        let mut retained_records = vec![];
+        for entry in self
+            .open_internal_system_table(SAVEPOINT_TABLE)?
+            .range::<u64>(..)?
+        {
+            let (id, bytes) = entry?;
+            if retained.contains(&id.value()) {
+                let created = self.persistent_savepoint_created(id.value())?;
+                retained_records.push((id.value(), bytes.value().to_vec(), created));
+                continue;
+            }
+            if id.value() < savepoint.get_id().0 {
+                continue;
+            }
+            self.discarded_persistent_savepoints
+                .lock()
+                .unwrap()
+                .push(Savepoint::from_bytes(
+                    bytes.value(),
+                    self.transaction_tracker.clone(),
+                    false,
+                ));
+        }
+
         let allocated_since_savepoint = self
             .mem
             .pages_allocated_since_raw_state(savepoint.get_regional_allocator_states());
@@ -461,6 +1205,18 @@ impl<'db> WriteTransaction<'db> {
             self.mem,
             self.freed_pages.clone(),
         ));
+        // Temporary tables were created after the savepoint, so their pages have already been freed
+        self.temp_table_tree = RwLock::new(TableTree::new(
+            None,
+            self.mem,
+            self.temp_freed_pages.clone(),
+        ));
+        self.temp_freed_pages.lock().unwrap().clear();
+        self.sequences.lock().unwrap().clear();
+        self.sequences_invalidated = true;
+        self.table_stats.lock().unwrap().clear();
+        // The restored system tables hold the summaries of the restored tables
+        self.summarized_root = savepoint.get_user_root();
 
         // Remove any freed pages that have already been processed. Otherwise this would result in a double free
         // We assume below that PageNumber is length 8
@@ -487,7 +1243,13 @@ impl<'db> WriteTransaction<'db> {
         };
         let mut to_remove = vec![];
         for entry in freed_tree.range(..lookup_key)? {
-            to_remove.push(entry?.key());
+            let key: FreedTableKey = entry?.key();
+            if let Some(branch_point) = branch_point {
+                if key.transaction_id > branch_point.get_transaction_id().0 {
+                    continue;
+                }
+            }
+            to_remove.push(key);
         }
         for key in to_remove {
             freed_tree.remove(&key)?;
@@ -500,9 +1262,51 @@ impl<'db> WriteTransaction<'db> {
         self.transaction_tracker
             .lock()
             .unwrap()
-            .invalidate_savepoints_after(savepoint.get_id());
+            .invalidate_savepoints_after(
+                savepoint.get_id(),
+                &retained
+                    .iter()
+                    .map(|id| SavepointId(*id))
+                    .collect::<Vec<_>>(),
+            );
+
+        // The restored system tables may list persistent savepoints which were deleted after this
+        // savepoint was taken, and whose pages may since have been reused
+        let stale: Vec<u64> = {
+            let ids: Vec<u64> = self.list_persistent_savepoints()?.collect();
+            let tracker = self.transaction_tracker.lock().unwrap();
+            ids.into_iter()
+                .filter(|id| !tracker.is_valid_savepoint(SavepointId(*id)))
+                .collect()
+        };
+        for id in stale {
+            self.open_internal_system_table(SAVEPOINT_TABLE)?
+                .remove(id)?;
+            self.open_internal_system_table(SAVEPOINT_CREATED_TABLE)?
+                .remove(id)?;
+        }
 
-        // TODO: I think we also need to release the read transaction on persistent savepoints, since they aren't cleaned up on drop?
+        for (id, bytes, created) in retained_records {
+            self.open_internal_system_table(SAVEPOINT_TABLE)?
+                .insert(id, bytes.as_slice())?;
+            if let Some(created) = created {
+                let created: u64 = created
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis()
+                    .try_into()
+                    .unwrap();
+                self.open_internal_system_table(SAVEPOINT_CREATED_TABLE)?
+                    .insert(id, created)?;
+            }
+            // The restored counter may predate the retained savepoint, and is used to resume
+            // allocating ids when the database is reopened
+            let mut next_table = self.open_internal_system_table(NEXT_SAVEPOINT_TABLE)?;
+            let next = next_table.get(())?.map(|x| x.value()).unwrap_or(0);
+            if next <= id {
+                next_table.insert((), id + 1)?;
+            }
+        }
 
         Ok(())
     }
@@ -513,12 +1317,31 @@ impl<'db> WriteTransaction<'db> {
         self.durability = durability;
     }
 
+    /// Set what happens if this transaction is dropped without being committed or aborted
+    /// Defaults to [`DropBehavior::Abort`], or [`DropBehavior::Panic`] if the `strict_drop`
+    /// feature is enabled
+    pub fn set_drop_behavior(&mut self, behavior: DropBehavior) {
+        self.drop_behavior = behavior;
+    }
+
     /// Open the given table
     ///
     /// The table will be created if it does not exist
     pub fn open_table<'txn, K: RedbKey + 'static, V: RedbValue + 'static>(
         &'txn self,
         definition: TableDefinition<K, V>,
+    ) -> Result<Table<'db, 'txn, K, V>> {
+        self.open_table_relaxed(definition, TypeNameCheck::Strict)
+    }
+
+    /// Open the given table, using `type_name_check` to decide how a mismatch between the stored
+    /// type names and those of `K` and `V` is handled
+    ///
+    /// The table will be created if it does not exist
+    pub fn open_table_relaxed<'txn, K: RedbKey + 'static, V: RedbValue + 'static>(
+        &'txn self,
+        definition: TableDefinition<K, V>,
+        type_name_check: TypeNameCheck,
     ) -> Result<Table<'db, 'txn, K, V>> {
         #[cfg(feature = "logging")]
         info!("Opening table: {}", definition);
@@ -529,22 +1352,81 @@ impl<'db> WriteTransaction<'db> {
             ));
         }
         self.dirty.store(true, Ordering::Release);
+
+        let internal_table = self
+            .table_tree
+            .write()
+            .unwrap()
+            .get_or_create_table::<K, V>(definition.name(), TableType::Normal, type_name_check)?;
+        // Only mark the table as open once its definition has been checked, so that a failed open
+        // can be retried
         self.open_tables
             .lock()
             .unwrap()
             .insert(definition.name().to_string(), panic::Location::caller());
 
+        Ok(Table::new(
+            definition.name(),
+            TableNamespace::User,
+            internal_table.get_root(),
+            definition.fill_policy(),
+            self.freed_pages.clone(),
+            self.mem,
+            self,
+        ))
+    }
+
+    /// Open the given tables as a [`TableGroup`]
+    ///
+    /// The tables will be created if they do not exist. Returns an error, and leaves none of the
+    /// tables open, if any of them cannot be opened
+    pub fn open_group<'txn, K: RedbKey + 'static, V: RedbValue + 'static>(
+        &'txn self,
+        definitions: &[TableDefinition<K, V>],
+    ) -> Result<TableGroup<'db, 'txn, K, V>> {
+        TableGroup::new(self, definitions)
+    }
+
+    /// Open the given temporary table
+    ///
+    /// Temporary tables are only visible to this transaction, and are never written to the
+    /// database. Their pages are freed when the transaction commits or aborts, so they can be used
+    /// as scratch space for data that does not fit in memory. Temporary tables have a separate
+    /// namespace from other tables.
+    ///
+    /// The table will be created if it does not exist
+    pub fn open_temp_table<'txn, K: RedbKey + 'static, V: RedbValue + 'static>(
+        &'txn self,
+        definition: TableDefinition<K, V>,
+    ) -> Result<Table<'db, 'txn, K, V>> {
+        if let Some(location) = self.open_temp_tables.lock().unwrap().get(definition.name()) {
+            return Err(Error::TableAlreadyOpen(
+                definition.name().to_string(),
+                location,
+            ));
+        }
+        self.dirty.store(true, Ordering::Release);
+
         let internal_table = self
-            .table_tree
+            .temp_table_tree
             .write()
             .unwrap()
-            .get_or_create_table::<K, V>(definition.name(), TableType::Normal)?;
+            .get_or_create_table::<K, V>(
+                definition.name(),
+                TableType::Normal,
+                TypeNameCheck::Strict,
+            )?;
+        self.open_temp_tables
+            .lock()
+            .unwrap()
+            .insert(definition.name().to_string(), panic::Location::caller());
 
         Ok(Table::new(
             definition.name(),
-            false,
+            TableNamespace::Temporary,
             internal_table.get_root(),
-            self.freed_pages.clone(),
+            definition.fill_policy(),
+            self.temp_freed_pages.clone(),
             self.mem,
             self,
         ))
@@ -575,7 +1457,11 @@ impl<'db> WriteTransaction<'db> {
             .table_tree
             .write()
             .unwrap()
-            .get_or_create_table::<K, V>(definition.name(), TableType::Multimap)?;
+            .get_or_create_table::<K, V>(
+                definition.name(),
+                TableType::Multimap,
+                TypeNameCheck::Strict,
+            )?;
 
         Ok(MultimapTable::new(
             definition.name(),
@@ -587,6 +1473,51 @@ impl<'db> WriteTransaction<'db> {
         ))
     }
 
+    // Returns an error if the transaction has modified more than the configured limit. Called
+    // before each modification to a table, so that a failed operation leaves the table unchanged
+    pub(crate) fn check_transaction_size(&self) -> Result {
+        if let Some(limit) = self.db.max_transaction_bytes() {
+            if self.mem.uncommitted_bytes() > limit {
+                return Err(Error::TransactionTooLarge(limit));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn record_table_stats(&self, name: &str, stats: TableWriteStats) {
+        if stats == TableWriteStats::default() {
+            return;
+        }
+        self.table_stats
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .merge(stats);
+    }
+
+    // Returns the given table, as it was when this transaction began. Pages of the committed state
+    // are not freed until this transaction commits, so it remains readable while the table is modified
+    pub(crate) fn committed_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        name: &str,
+        namespace: TableNamespace,
+    ) -> Result<Btree<'db, K, V>> {
+        let root = match namespace {
+            TableNamespace::User => self.mem.get_data_root(),
+            TableNamespace::System => self.mem.get_system_root(),
+            TableNamespace::Temporary => {
+                return Btree::new(None, PageHint::Clean, self.mem);
+            }
+        };
+        let tree = TableTree::new(root, self.mem, Default::default());
+        let table_root = tree
+            .get_table::<K, V>(name, TableType::Normal)?
+            .and_then(|definition| definition.get_root());
+        Btree::new(table_root, PageHint::Clean, self.mem)
+    }
+
     pub(crate) fn close_table<K: RedbKey + 'static, V: RedbValue + 'static>(
         &self,
         name: &str,
@@ -605,6 +1536,10 @@ impl<'db> WriteTransaction<'db> {
                 .stage_update_table_root(name, table.get_root());
         } else {
             self.open_tables.lock().unwrap().remove(name).unwrap();
+            self.key_orders
+                .lock()
+                .unwrap()
+                .insert(name.to_string(), K::compare);
             self.table_tree
                 .write()
                 .unwrap()
@@ -612,6 +1547,85 @@ impl<'db> WriteTransaction<'db> {
         }
     }
 
+    pub(crate) fn close_temp_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        name: &str,
+        table: &mut BtreeMut<K, V>,
+    ) {
+        self.open_temp_tables.lock().unwrap().remove(name).unwrap();
+        self.temp_table_tree
+            .write()
+            .unwrap()
+            .stage_update_table_root(name, table.get_root());
+    }
+
+    // Frees all the pages used by temporary tables
+    // Frees the pages of all temporary tables. Returns those which were committed by flush(),
+    // since they must be freed with a normal free(), after any rollback
+    fn free_temp_tables(&mut self) -> Result<Vec<PageNumber>> {
+        let mut tree = self.temp_table_tree.write().unwrap();
+        tree.flush_table_root_updates()?;
+        for name in tree.list_tables(TableType::Normal)? {
+            tree.delete_table(&name, TableType::Normal)?;
+        }
+        if let Some((root, _)) = tree.flush_table_root_updates()? {
+            let iter = AllPageNumbersBtreeIter::new(root, None, None, self.mem)?;
+            let mut freed_pages = self.temp_freed_pages.lock().unwrap();
+            for page_number in iter {
+                freed_pages.push(page_number?);
+            }
+        }
+        *tree = TableTree::new(None, self.mem, self.temp_freed_pages.clone());
+        let mut committed = vec![];
+        for page in self.temp_freed_pages.lock().unwrap().drain(..) {
+            if !self.mem.free_if_uncommitted(page) {
+                committed.push(page);
+            }
+        }
+
+        Ok(committed)
+    }
+
+    /// Remove `key` from the `primary` table, along with every row of the `children` tables which
+    /// references it
+    ///
+    /// The child tables are scanned in full, since they are not indexed by the key they reference.
+    /// Returns the total number of rows removed. If an error is returned, some of the rows may
+    /// already have been removed, so the transaction should be aborted
+    pub fn delete_cascade<'a, K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        primary: TableDefinition<K, V>,
+        key: impl Borrow<K::SelfType<'a>>,
+        children: &[&dyn ReferencingTable<K>],
+    ) -> Result<u64> {
+        let key_bytes = K::as_bytes(key.borrow()).as_ref().to_vec();
+        let mut removed = 0;
+        for child in children {
+            removed += child.remove_references(self, &key_bytes)?;
+        }
+        let mut table = self.open_table(primary)?;
+        if table.remove(key)?.is_some() {
+            removed += 1;
+        }
+
+        Ok(removed)
+    }
+
+    /// Rewrites all of the given table's pages, in key order, into the lowest free pages of the
+    /// database file
+    ///
+    /// This is best-effort: the result is only contiguous if enough contiguous free space is
+    /// available near the start of the file. See [`Table::fragmentation_report`]
+    pub fn defragment_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+    ) -> Result {
+        self.check_transaction_size()?;
+        self.dirty.store(true, Ordering::Release);
+        let mut table = self.open_table(definition)?;
+        table.rewrite()
+    }
+
     /// Delete the given table
     ///
     /// Returns a bool indicating whether the table existed
@@ -658,18 +1672,140 @@ impl<'db> WriteTransaction<'db> {
             .map(|x| x.into_iter().map(UntypedMultimapTableHandle::new))
     }
 
+    /// Restores the tables in an archive written by [`ReadTransaction::export_archive`]
+    ///
+    /// Each table in the archive replaces any existing table of the same name. The archive may
+    /// have been written by a database with a different page size or checksum algorithm. If an
+    /// error is returned, the transaction should be aborted.
+    ///
+    /// The keys of each table are checked to be in order. Tables whose key type is not one of the
+    /// built-in integer, string, or byte types must be opened in this transaction before they are
+    /// imported, so that the order of their keys is known.
+    ///
+    /// Returns the number of tables restored
+    pub fn import_archive(&self, reader: impl Read) -> Result<u64> {
+        #[cfg(feature = "logging")]
+        info!(
+            "Importing archive in transaction id={:?}",
+            self.transaction_id
+        );
+        self.dirty.store(true, Ordering::Release);
+        let mut table_tree = self.table_tree.write().unwrap();
+        read_archive(
+            &mut table_tree,
+            self.mem,
+            self.freed_pages.clone(),
+            reader,
+            |name| {
+                if let Some(location) = self.open_tables.lock().unwrap().get(name) {
+                    Err(Error::TableAlreadyOpen(name.to_string(), location))
+                } else {
+                    Ok(())
+                }
+            },
+            |name| self.key_orders.lock().unwrap().get(name).copied(),
+        )
+    }
+
     /// Commit the transaction
     ///
     /// All writes performed in this transaction will be visible to future transactions, and are
     /// durable as consistent with the [`Durability`] level set by [`Self::set_durability`]
-    pub fn commit(mut self) -> Result {
+    pub fn commit(self) -> Result {
+        self.commit_with_summary()?;
+        Ok(())
+    }
+
+    /// Commit the transaction, and return a summary of the changes it made
+    ///
+    /// See [`Self::commit`]
+    pub fn commit_with_summary(mut self) -> Result<CommitSummary> {
         // Set completed flag first, so that we don't go through the abort() path on drop, if this fails
         self.completed = true;
+        self.update_key_histograms()?;
+        self.update_content_hashes()?;
+        self.table_tree
+            .write()
+            .unwrap()
+            .flush_table_root_updates()?;
+        // Temporary table pages committed by flush() are not reachable by any reader, so can be
+        // freed immediately
+        for page in self.free_temp_tables()? {
+            self.mem.free(page);
+        }
+        self.commit_inner()?;
+        self.publish_sequences();
+        self.notify_key_watches();
+        self.release_discarded_savepoints();
+
+        let (allocated_pages, freed_pages) = self.mem.allocation_totals();
+        Ok(CommitSummary {
+            tables: std::mem::take(&mut *self.table_stats.lock().unwrap()),
+            allocated_pages: allocated_pages - self.allocation_totals_at_start.0,
+            freed_pages: freed_pages - self.allocation_totals_at_start.1,
+            bytes_written: self.mem.bytes_written() - self.bytes_written_at_start,
+        })
+    }
+
+    /// Durably persist all writes performed so far, without ending the transaction
+    ///
+    /// The flushed writes become visible to read transactions which begin afterwards, and survive
+    /// a crash or a later [`Self::abort`], which only rolls back writes made after the last flush.
+    /// This allows a long-running bulk import to checkpoint its progress while retaining exclusive
+    /// write access. The flush is always durable, regardless of [`Self::set_durability`], and also
+    /// resets the count of bytes checked against [`crate::Builder::set_max_transaction_bytes`]
+    pub fn flush(&mut self) -> Result {
+        #[cfg(feature = "logging")]
+        info!("Flushing transaction id={:?}", self.transaction_id);
+        self.update_key_histograms()?;
+        self.update_content_hashes()?;
         self.table_tree
             .write()
             .unwrap()
             .flush_table_root_updates()?;
-        self.commit_inner()
+        self.durable_commit(false, matches!(self.durability, Durability::Paranoid))?;
+        self.publish_sequences();
+        self.notify_key_watches();
+        self.release_discarded_savepoints();
+        // Savepoints created so far are now durable, so must not be deleted if the rest of the
+        // transaction is aborted
+        self.created_persistent_savepoints.lock().unwrap().clear();
+        self.summarized_root = self.mem.get_data_root();
+
+        // Continue under a new transaction id, so that pages freed after this point are tracked
+        // separately from those stored by the flush
+        self.transaction_id = self.db.increment_transaction_id();
+        **self.live_write_transaction.as_mut().unwrap() = Some(self.transaction_id);
+        self.dirty.store(false, Ordering::Release);
+
+        Ok(())
+    }
+
+    fn publish_sequences(&mut self) {
+        let mut db_sequences = self.db.sequences.lock().unwrap();
+        if self.sequences_invalidated {
+            db_sequences.clear();
+            self.sequences_invalidated = false;
+        }
+        db_sequences.extend(self.sequences.lock().unwrap().drain());
+    }
+
+    fn release_discarded_savepoints(&self) {
+        let mut tracker = self.transaction_tracker.lock().unwrap();
+        for savepoint in self
+            .discarded_persistent_savepoints
+            .lock()
+            .unwrap()
+            .drain(..)
+        {
+            tracker.deallocate_savepoint(&savepoint);
+        }
+    }
+
+    fn notify_key_watches(&self) {
+        self.db
+            .key_watches
+            .notify(&self.table_tree.read().unwrap(), self.mem);
     }
 
     fn commit_inner(&mut self) -> Result {
@@ -710,7 +1846,11 @@ impl<'db> WriteTransaction<'db> {
             self.delete_persistent_savepoint(*savepoint)?;
         }
         self.table_tree.write().unwrap().clear_table_root_updates();
+        let committed_temp_pages = self.free_temp_tables()?;
         self.mem.rollback_uncommitted_writes()?;
+        for page in committed_temp_pages {
+            self.mem.free(page);
+        }
         #[cfg(feature = "logging")]
         info!("Finished abort of transaction id={:?}", self.transaction_id);
         Ok(())
@@ -873,7 +2013,7 @@ impl<'db> WriteTransaction<'db> {
                 transaction_id: self.transaction_id.0,
                 pagination_id: pagination_counter,
             };
-            let mut access_guard =
+            let (mut access_guard, _) =
                 freed_tree.insert_reserve(&key, buffer_size.try_into().unwrap())?;
 
             let mut freed_pages = self.freed_pages.lock().unwrap();
@@ -907,9 +2047,24 @@ impl<'db> WriteTransaction<'db> {
         let total_fragmented =
             data_tree_stats.fragmented_bytes() + freed_tree_stats.fragmented_bytes;
 
+        let mut pending_free_pages: u64 =
+            self.freed_pages.lock().unwrap().len().try_into().unwrap();
+        for entry in self
+            .freed_tree
+            .lock()
+            .unwrap()
+            .range::<RangeFull, FreedTableKey>(..)?
+        {
+            let len: u64 = entry?.value().len().try_into().unwrap();
+            pending_free_pages += len;
+        }
+
         Ok(DatabaseStats {
             tree_height: data_tree_stats.tree_height(),
             allocated_pages: self.mem.count_allocated_pages()?,
+            free_pages: self.mem.count_free_pages()?,
+            largest_free_block: self.mem.largest_free_block()?,
+            pending_free_pages,
             leaf_pages: data_tree_stats.leaf_pages(),
             branch_pages: data_tree_stats.branch_pages(),
             stored_leaf_bytes: data_tree_stats.stored_bytes(),
@@ -941,13 +2096,28 @@ impl<'db> WriteTransaction<'db> {
 
 impl<'a> Drop for WriteTransaction<'a> {
     fn drop(&mut self) {
-        *self.live_write_transaction = None;
+        **self.live_write_transaction.as_mut().unwrap() = None;
         if !self.completed && !thread::panicking() {
+            #[cfg(feature = "logging")]
+            if self.dirty.load(Ordering::Acquire) {
+                warn!(
+                    "Write transaction id={:?} dropped without commit. Discarding its writes",
+                    self.transaction_id
+                );
+            }
             #[allow(unused_variables)]
             if let Err(error) = self.abort_inner() {
                 #[cfg(feature = "logging")]
                 warn!("Failure automatically aborting transaction: {}", error);
             }
+            if self.drop_behavior == DropBehavior::Panic {
+                // Release the lock first, so that panicking doesn't poison it
+                self.live_write_transaction.take();
+                panic!(
+                    "Write transaction id={:?} dropped without calling commit() or abort()",
+                    self.transaction_id
+                );
+            }
         }
     }
 }
@@ -959,6 +2129,7 @@ pub struct ReadTransaction<'a> {
     transaction_tracker: Arc<Mutex<TransactionTracker>>,
     mem: &'a TransactionalMemory,
     tree: TableTree<'a>,
+    system_tree: TableTree<'a>,
     transaction_id: TransactionId,
 }
 
@@ -969,22 +2140,98 @@ impl<'db> ReadTransaction<'db> {
         transaction_id: TransactionId,
     ) -> Self {
         let root_page = mem.get_data_root();
+        let system_page = mem.get_system_root();
         Self {
             transaction_tracker,
             mem,
             tree: TableTree::new(root_page, mem, Default::default()),
+            system_tree: TableTree::new(system_page, mem, Default::default()),
             transaction_id,
         }
     }
 
+    pub(crate) fn table_tree(&self) -> &TableTree<'db> {
+        &self.tree
+    }
+
+    pub(crate) fn mem(&self) -> &'db TransactionalMemory {
+        self.mem
+    }
+
     /// Open the given table
     pub fn open_table<K: RedbKey + 'static, V: RedbValue + 'static>(
         &self,
         definition: TableDefinition<K, V>,
     ) -> Result<ReadOnlyTable<K, V>> {
+        self.open_table_relaxed(definition, TypeNameCheck::Strict)
+    }
+
+    /// Open the given table, using `type_name_check` to decide how a mismatch between the stored
+    /// type names and those of `K` and `V` is handled
+    pub fn open_table_relaxed<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+        type_name_check: TypeNameCheck,
+    ) -> Result<ReadOnlyTable<K, V>> {
+        self.open_table_inner(definition, type_name_check)
+    }
+
+    /// Open the given table, and return it bundled with this transaction
+    ///
+    /// The returned table keeps the transaction open until it is dropped, so it can be stored
+    /// or returned from a function without also keeping the transaction in scope
+    pub fn into_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        self,
+        definition: TableDefinition<K, V>,
+    ) -> Result<OwnedReadTable<'db, K, V>> {
+        let table = self.open_table_inner(definition, TypeNameCheck::Strict)?;
+        Ok(OwnedReadTable::new(table, self))
+    }
+
+    // The table only borrows the database, but must not outlive this transaction, because its
+    // pages may be freed once the transaction is dropped
+    fn open_table_inner<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+        type_name_check: TypeNameCheck,
+    ) -> Result<ReadOnlyTable<'db, K, V>> {
         let header = self
             .tree
-            .get_table::<K, V>(definition.name(), TableType::Normal)?
+            .get_table_checked::<K, V>(definition.name(), TableType::Normal, type_name_check)?
+            .ok_or_else(|| Error::TableDoesNotExist(definition.name().to_string()))?;
+
+        ReadOnlyTable::new(header.get_root(), PageHint::Clean, self.mem)
+    }
+
+    /// Returns the distribution of keys in the given table, if a histogram is stored
+    ///
+    /// See [`WriteTransaction::enable_key_histogram`]
+    pub fn key_histogram<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+    ) -> Result<Option<KeyHistogram<K>>> {
+        read_key_histogram(&self.system_tree, definition.name(), self.mem)
+    }
+
+    /// Returns the content hash of the given table, if it is maintained
+    ///
+    /// See [`WriteTransaction::enable_content_hash`]
+    pub fn content_hash<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+    ) -> Result<Option<ContentHash<K>>> {
+        read_content_hash(&self.system_tree, definition.name(), self.mem)
+    }
+
+    /// Open the given system table
+    pub fn open_system_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: SystemTableDefinition<K, V>,
+    ) -> Result<ReadOnlyTable<K, V>> {
+        let name = application_system_table_name(definition.name());
+        let header = self
+            .system_tree
+            .get_table::<K, V>(&name, TableType::Normal)?
             .ok_or_else(|| Error::TableDoesNotExist(definition.name().to_string()))?;
 
         ReadOnlyTable::new(header.get_root(), PageHint::Clean, self.mem)
@@ -1016,6 +2263,18 @@ impl<'db> ReadTransaction<'db> {
             .list_tables(TableType::Multimap)
             .map(|x| x.into_iter().map(UntypedMultimapTableHandle::new))
     }
+
+    /// Writes a logical backup of every table, as of this transaction's snapshot, to `writer`
+    ///
+    /// The archive stores the type metadata and the keys and values of each table, independent of
+    /// how they are laid out in the database file, and can be restored into any database with
+    /// [`WriteTransaction::import_archive`]. System tables are not included.
+    ///
+    /// Returns the number of tables written, or [`Error::TableIsMultimap`] if the database has any
+    /// multimap tables, since they cannot be archived
+    pub fn export_archive(&self, writer: impl Write) -> Result<u64> {
+        write_archive(&self.tree, self.mem, writer)
+    }
 }
 
 impl<'a> Drop for ReadTransaction<'a> {
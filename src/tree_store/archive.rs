@@ -0,0 +1,230 @@
+use crate::sync::Mutex;
+use crate::tree_store::btree::UntypedBtreeMut;
+use crate::tree_store::{
+    InternalTableDefinition, PageNumber, RawBtree, TableTree, TableType, TransactionalMemory,
+    MAX_VALUE_LENGTH,
+};
+use crate::types::RedbValue;
+use crate::{Error, Result};
+use std::cmp::Ordering;
+use std::io;
+use std::io::{Read, Write};
+use std::mem::size_of;
+use std::sync::Arc;
+
+// Archive format:
+// 8 bytes: magic number
+// 1 byte: version
+// 8 bytes: number of tables
+//
+// Followed by each table:
+// 4 bytes: length of name
+// n bytes: name
+// 4 bytes: length of definition
+// n bytes: table definition, as stored in the table tree, without a root
+//
+// Followed by each entry of the table, in key order:
+// 1 byte: ENTRY
+// 4 bytes: length of key
+// n bytes: key
+// 4 bytes: length of value
+// n bytes: value
+//
+// Keys and values are at most MAX_VALUE_LENGTH bytes
+//
+// And then:
+// 1 byte: END
+
+type KeyOrder = fn(&[u8], &[u8]) -> Ordering;
+
+const MAGICNUMBER: [u8; 8] = *b"redbarch";
+const VERSION: u8 = 1;
+const ENTRY: u8 = 1;
+const END: u8 = 0;
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> Result {
+    let len: u32 = bytes.len().try_into().unwrap();
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let mut len = [0; size_of::<u32>()];
+    reader.read_exact(&mut len)?;
+    let len: usize = u32::from_le_bytes(len).try_into().unwrap();
+    // Checked before allocating, since the length has not been validated
+    if len > MAX_VALUE_LENGTH {
+        return Err(Error::ValueTooLarge(len));
+    }
+    let mut buffer = vec![0; len];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn read_u8(reader: &mut impl Read) -> Result<u8> {
+    let mut buffer = [0];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+fn invalid_archive(message: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+// Returns the order of the keys of the table `name`, read from an archive with `definition`.
+// `opened` is the order of the table of the same name, if it has been opened in this transaction
+fn key_order(
+    tables: &TableTree,
+    name: &str,
+    definition: &InternalTableDefinition,
+    opened: Option<KeyOrder>,
+) -> Result<KeyOrder> {
+    if let Some(opened) = opened {
+        if let Some(existing) = tables.get_table_untyped(name, TableType::Normal)? {
+            if existing.get_key_type() == definition.get_key_type() {
+                return Ok(opened);
+            }
+        }
+    }
+    match definition.get_key_type().builtin_key_order() {
+        Some((order, fixed_width)) => {
+            if fixed_width.is_some() && fixed_width != definition.get_fixed_key_size() {
+                return Err(invalid_archive("Invalid table definition"));
+            }
+            Ok(order)
+        }
+        None => Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Key order of table {name} is unknown. It must be opened before it is imported"
+            ),
+        ))),
+    }
+}
+
+// Writes every table in `tables`. Returns the number of tables written, or
+// Error::TableIsMultimap if there are any multimap tables, since they cannot be archived
+pub(crate) fn write_archive(
+    tables: &TableTree,
+    mem: &TransactionalMemory,
+    mut writer: impl Write,
+) -> Result<u64> {
+    if let Some(name) = tables.list_tables(TableType::Multimap)?.into_iter().next() {
+        return Err(Error::TableIsMultimap(name));
+    }
+    let names = tables.list_tables(TableType::Normal)?;
+    let num_tables: u64 = names.len().try_into().unwrap();
+
+    writer.write_all(&MAGICNUMBER)?;
+    writer.write_all(&[VERSION])?;
+    writer.write_all(&num_tables.to_le_bytes())?;
+    for name in names {
+        let mut definition = tables.get_table_untyped(&name, TableType::Normal)?.unwrap();
+        let tree = RawBtree::new(
+            definition.get_root(),
+            definition.get_fixed_key_size(),
+            definition.get_fixed_value_size(),
+            mem,
+        );
+        // Page numbers are meaningless outside of this database
+        definition.set_root(None);
+
+        write_bytes(&mut writer, name.as_bytes())?;
+        write_bytes(&mut writer, &InternalTableDefinition::as_bytes(&definition))?;
+        tree.for_each_entry(|key, value| {
+            writer.write_all(&[ENTRY])?;
+            write_bytes(&mut writer, key)?;
+            write_bytes(&mut writer, value)
+        })?;
+        writer.write_all(&[END])?;
+    }
+    writer.flush()?;
+
+    Ok(num_tables)
+}
+
+// Reads an archive written by write_archive(), replacing any tables in `tables` with the same
+// names. `check_table` is called with the name of each table before it is replaced.
+// `opened_key_order` returns the key order of a table which was opened in this transaction, which
+// is needed to check the order of the keys of tables whose key type is not built-in.
+// Returns the number of tables read
+pub(crate) fn read_archive(
+    tables: &mut TableTree,
+    mem: &TransactionalMemory,
+    freed_pages: Arc<Mutex<Vec<PageNumber>>>,
+    mut reader: impl Read,
+    mut check_table: impl FnMut(&str) -> Result,
+    mut opened_key_order: impl FnMut(&str) -> Option<KeyOrder>,
+) -> Result<u64> {
+    let mut magic_number = [0; MAGICNUMBER.len()];
+    reader.read_exact(&mut magic_number)?;
+    if magic_number != MAGICNUMBER {
+        return Err(invalid_archive("Not an archive"));
+    }
+    if read_u8(&mut reader)? != VERSION {
+        return Err(invalid_archive("Unsupported archive version"));
+    }
+    let mut num_tables = [0; size_of::<u64>()];
+    reader.read_exact(&mut num_tables)?;
+    let num_tables = u64::from_le_bytes(num_tables);
+
+    for _ in 0..num_tables {
+        let name = String::from_utf8(read_bytes(&mut reader)?)
+            .map_err(|_| invalid_archive("Table name is not valid UTF-8"))?;
+        let definition = read_bytes(&mut reader)?;
+        if !InternalTableDefinition::is_valid_encoding(&definition) {
+            return Err(invalid_archive("Invalid table definition"));
+        }
+        let mut definition = InternalTableDefinition::from_bytes(&definition);
+        if definition.get_type() != TableType::Normal {
+            return Err(invalid_archive("Unsupported table type"));
+        }
+        check_table(&name)?;
+        let compare = key_order(tables, &name, &definition, opened_key_order(&name))?;
+
+        let fixed_key_size = definition.get_fixed_key_size();
+        let fixed_value_size = definition.get_fixed_value_size();
+        let mut previous_key: Option<Vec<u8>> = None;
+        let pairs = std::iter::from_fn(|| {
+            let result = match read_u8(&mut reader) {
+                Ok(ENTRY) => {
+                    read_bytes(&mut reader).and_then(|key| Ok((key, read_bytes(&mut reader)?)))
+                }
+                Ok(END) => {
+                    return None;
+                }
+                Ok(_) => Err(invalid_archive("Invalid entry")),
+                Err(err) => Err(err),
+            };
+            Some(result.and_then(|(key, value)| {
+                if matches!(fixed_key_size, Some(x) if x != key.len())
+                    || matches!(fixed_value_size, Some(x) if x != value.len())
+                {
+                    return Err(invalid_archive("Entry does not match table definition"));
+                }
+                // The btree is built assuming that the keys are sorted and unique
+                if let Some(previous) = &previous_key {
+                    if compare(previous, &key) != Ordering::Less {
+                        return Err(invalid_archive("Keys are not in ascending order"));
+                    }
+                }
+                previous_key = Some(key.clone());
+                Ok((key, value))
+            }))
+        });
+        let mut tree = UntypedBtreeMut::new(
+            None,
+            mem,
+            freed_pages.clone(),
+            fixed_key_size,
+            fixed_value_size,
+        );
+        tree.build_from_sorted(pairs)?;
+
+        definition.set_root(tree.get_root());
+        tables.replace_table_untyped(&name, &definition)?;
+    }
+
+    Ok(num_tables)
+}
@@ -1,20 +1,24 @@
+use crate::fragmentation::{fragmentation_report, FragmentationReport};
+use crate::sync::Mutex;
 use crate::tree_store::btree_base::{
-    branch_checksum, leaf_checksum, BranchAccessor, BranchMutator, Checksum, FreePolicy,
-    LeafAccessor, BRANCH, LEAF,
+    branch_checksum, checked_checksum, leaf_checksum, subtree_length, BranchAccessor,
+    BranchBuilder, BranchMutator, Checksum, FillPolicy, FreePolicy, LeafAccessor, LeafBuilder,
+    RawBranchBuilder, BRANCH, LEAF,
 };
-use crate::tree_store::btree_iters::BtreeDrain;
+use crate::tree_store::btree_diff::BtreeDiff;
+use crate::tree_store::btree_iters::{BtreeDrain, EntryGuard};
 use crate::tree_store::btree_mutator::MutateHelper;
 use crate::tree_store::page_store::{Page, PageImpl, TransactionalMemory};
 use crate::tree_store::{AccessGuardMut, BtreeDrainFilter, BtreeRangeIter, PageHint, PageNumber};
 use crate::types::{RedbKey, RedbValue, RedbValueMutInPlace};
-use crate::{AccessGuard, Result};
+use crate::{AccessGuard, Error, Result};
 #[cfg(feature = "logging")]
 use log::trace;
 use std::borrow::Borrow;
-use std::cmp::max;
+use std::cmp::{max, Ordering};
 use std::marker::PhantomData;
-use std::ops::{RangeBounds, RangeFull};
-use std::sync::{Arc, Mutex};
+use std::ops::{Bound, RangeBounds, RangeFull};
+use std::sync::Arc;
 
 pub(crate) struct BtreeStats {
     pub(crate) tree_height: u32,
@@ -25,6 +29,10 @@ pub(crate) struct BtreeStats {
     pub(crate) fragmented_bytes: u64,
 }
 
+// A page built by UntypedBtreeMut::build_from_sorted(), with its checksum, the number of entries
+// beneath it, and the last key beneath it
+type SortedNode = (PageNumber, Checksum, u64, Vec<u8>);
+
 pub(crate) struct UntypedBtreeMut<'a> {
     mem: &'a TransactionalMemory,
     root: Arc<Mutex<Option<(PageNumber, Checksum)>>>,
@@ -57,7 +65,7 @@ impl<'a> UntypedBtreeMut<'a> {
     // Relocate the btree to lower pages
     pub(crate) fn relocate(&mut self) -> Result<bool> {
         if let Some(root) = self.get_root() {
-            if let Some(new_root) = self.relocate_helper(root.0)? {
+            if let Some(new_root) = self.relocate_helper(root.0, false)? {
                 *self.root.lock().unwrap() = Some(new_root);
                 return Ok(true);
             }
@@ -65,15 +73,133 @@ impl<'a> UntypedBtreeMut<'a> {
         Ok(false)
     }
 
-    // Relocates the given page to a lower page if possible, and returns the new page number
+    // Builds the btree bottom-up from `pairs`, which must be in key order and contain no
+    // duplicate keys. Since no keys are compared, this works without knowing the key type
+    pub(crate) fn build_from_sorted(
+        &mut self,
+        pairs: impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>>,
+    ) -> Result {
+        assert!(self.get_root().is_none());
+        let page_size = self.mem.get_page_size();
+
+        // Each node of the level being built, the number of entries beneath it, and the last key
+        // stored beneath it
+        let mut level = vec![];
+        let mut buffer: Vec<(Vec<u8>, Vec<u8>)> = vec![];
+        let mut buffered_bytes = 0;
+        for pair in pairs {
+            let (key, value) = pair?;
+            if !buffer.is_empty()
+                && (LeafBuilder::required_bytes(
+                    buffer.len() + 1,
+                    buffered_bytes + key.len() + value.len(),
+                ) > page_size
+                    || buffer.len() == usize::from(u16::MAX))
+            {
+                level.push(self.build_leaf(&mut buffer)?);
+                buffered_bytes = 0;
+            }
+            buffered_bytes += key.len() + value.len();
+            buffer.push((key, value));
+        }
+        if !buffer.is_empty() {
+            level.push(self.build_leaf(&mut buffer)?);
+        }
+
+        while level.len() > 1 {
+            let mut groups: Vec<Vec<SortedNode>> = vec![];
+            let mut group: Vec<SortedNode> = vec![];
+            let mut key_bytes = 0;
+            for child in level {
+                if let Some((_, _, _, previous_key)) = group.last() {
+                    let required = RawBranchBuilder::required_bytes(
+                        group.len(),
+                        key_bytes + previous_key.len(),
+                        self.key_width,
+                    );
+                    if required > page_size && group.len() >= 2 {
+                        groups.push(std::mem::take(&mut group));
+                        key_bytes = 0;
+                    } else {
+                        key_bytes += previous_key.len();
+                    }
+                }
+                group.push(child);
+            }
+            // A branch must have at least two children
+            if group.len() == 1 && !groups.is_empty() {
+                groups.last_mut().unwrap().append(&mut group);
+            } else {
+                groups.push(group);
+            }
+
+            level = vec![];
+            for group in groups {
+                level.push(self.build_branch(group)?);
+            }
+        }
+
+        *self.root.lock().unwrap() = level.pop().map(|(page, checksum, _, _)| (page, checksum));
+        Ok(())
+    }
+
+    fn build_leaf(&self, pairs: &mut Vec<(Vec<u8>, Vec<u8>)>) -> Result<SortedNode> {
+        let mut builder = LeafBuilder::new(self.mem, pairs.len(), self.key_width, self.value_width);
+        for (key, value) in pairs.iter() {
+            builder.push(key, value);
+        }
+        let page = builder.build()?;
+        let checksum = leaf_checksum(
+            &page,
+            self.key_width,
+            self.value_width,
+            self.mem.checksum_algorithm(),
+        );
+        let length = u64::try_from(pairs.len()).unwrap();
+        let last_key = pairs.pop().unwrap().0;
+        pairs.clear();
+
+        Ok((page.get_page_number(), checksum, length, last_key))
+    }
+
+    fn build_branch(&self, mut children: Vec<SortedNode>) -> Result<SortedNode> {
+        let last_key = std::mem::take(&mut children.last_mut().unwrap().3);
+        let mut builder = BranchBuilder::new(self.mem, children.len(), self.key_width);
+        let mut length = 0;
+        for (page, checksum, child_length, _) in children.iter() {
+            builder.push_child(*page, *checksum, *child_length);
+            length += child_length;
+        }
+        // Each key is the last key beneath the child to its left
+        for (_, _, _, key) in children.iter().take(children.len() - 1) {
+            builder.push_key(key);
+        }
+        let page = builder.build()?;
+        let checksum = branch_checksum(&page, self.key_width, self.mem.checksum_algorithm());
+
+        Ok((page.get_page_number(), checksum, length, last_key))
+    }
+
+    // Copies every page of the btree to the lowest free pages, in key order
+    pub(crate) fn rewrite(&mut self) -> Result {
+        if let Some(root) = self.get_root() {
+            let new_root = self.relocate_helper(root.0, true)?;
+            *self.root.lock().unwrap() = new_root;
+        }
+        Ok(())
+    }
+
+    // Relocates the given page to a lower page if possible, or unconditionally if `always` is set,
+    // and returns the new page number
     fn relocate_helper(
         &mut self,
         page_number: PageNumber,
+        always: bool,
     ) -> Result<Option<(PageNumber, Checksum)>> {
         let old_page = self.mem.get_page(page_number)?;
         let mut new_page = self.mem.allocate_lowest(old_page.memory().len())?;
         let new_page_number = new_page.get_page_number();
-        if !new_page_number.is_before(page_number) {
+        if !always && !new_page_number.is_before(page_number) {
             drop(new_page);
             self.mem.free(new_page_number);
             return Ok(None);
@@ -83,17 +209,22 @@ impl<'a> UntypedBtreeMut<'a> {
 
         let node_mem = old_page.memory();
         let new_checksum = match node_mem[0] {
-            LEAF => leaf_checksum(&new_page, self.key_width, self.value_width),
+            LEAF => leaf_checksum(
+                &new_page,
+                self.key_width,
+                self.value_width,
+                self.mem.checksum_algorithm(),
+            ),
             BRANCH => {
                 let accessor = BranchAccessor::new(&old_page, self.key_width);
                 let mut mutator = BranchMutator::new(&mut new_page);
                 for i in 0..accessor.count_children() {
                     let child = accessor.child_page(i).unwrap();
-                    if let Some((new_child, new_checksum)) = self.relocate_helper(child)? {
+                    if let Some((new_child, new_checksum)) = self.relocate_helper(child, always)? {
                         mutator.write_child_page(i, new_child, new_checksum);
                     }
                 }
-                branch_checksum(&new_page, self.key_width)
+                branch_checksum(&new_page, self.key_width, self.mem.checksum_algorithm())
             }
             _ => unreachable!(),
         };
@@ -109,6 +240,7 @@ pub(crate) struct BtreeMut<'a, K: RedbKey, V: RedbValue> {
     mem: &'a TransactionalMemory,
     root: Arc<Mutex<Option<(PageNumber, Checksum)>>>,
     freed_pages: Arc<Mutex<Vec<PageNumber>>>,
+    fill_policy: FillPolicy,
     _key_type: PhantomData<K>,
     _value_type: PhantomData<V>,
 }
@@ -123,11 +255,16 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
             mem,
             root: Arc::new(Mutex::new(root)),
             freed_pages,
+            fill_policy: FillPolicy::default(),
             _key_type: Default::default(),
             _value_type: Default::default(),
         }
     }
 
+    pub(crate) fn set_fill_policy(&mut self, fill_policy: FillPolicy) {
+        self.fill_policy = fill_policy;
+    }
+
     pub(crate) fn get_root(&self) -> Option<(PageNumber, Checksum)> {
         *(*self.root).lock().unwrap()
     }
@@ -148,6 +285,38 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
         }
     }
 
+    pub(crate) fn rewrite(&mut self) -> Result {
+        let mut tree = UntypedBtreeMut::new(
+            self.get_root(),
+            self.mem,
+            self.freed_pages.clone(),
+            K::fixed_width(),
+            V::fixed_width(),
+        );
+        tree.rewrite()?;
+        *self.root.lock().unwrap() = tree.get_root();
+        Ok(())
+    }
+
+    pub(crate) fn fragmentation_report(&self) -> Result<FragmentationReport> {
+        fragmentation_report(
+            self.get_root().map(|(page, _)| page),
+            K::fixed_width(),
+            V::fixed_width(),
+            self.mem,
+        )
+    }
+
+    pub(crate) fn preload(&self, include_leaves: bool) -> Result<u64> {
+        RawBtree::new(
+            self.get_root(),
+            K::fixed_width(),
+            V::fixed_width(),
+            self.mem,
+        )
+        .preload(include_leaves)
+    }
+
     pub(crate) fn insert(
         &mut self,
         key: &K::SelfType<'_>,
@@ -168,6 +337,7 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
             self.mem,
             freed_pages.as_mut(),
         );
+        operation.set_fill_policy(self.fill_policy);
         let (old_value, _) = operation.insert(key, value)?;
         Ok(old_value)
     }
@@ -183,6 +353,7 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
             self.mem,
             freed_pages.as_mut(),
         );
+        operation.set_fill_policy(self.fill_policy);
         let result = operation.delete(key)?;
         Ok(result)
     }
@@ -196,10 +367,35 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
         let mut root = self.root.lock().unwrap();
         let mut operation: MutateHelper<'_, '_, K, V> =
             MutateHelper::new(&mut root, FreePolicy::Never, self.mem, &mut freed_pages);
+        operation.set_fill_policy(self.fill_policy);
         let result = operation.safe_delete(key)?;
         Ok(result.map(|x| (x, freed_pages)))
     }
 
+    // Removes all entries in the range, and returns the number removed. Unlike drain(), the removed
+    // entries are not returned, so subtrees which are entirely in the range are freed without
+    // reading their contents
+    pub(crate) fn remove_range<'a0, KR: Borrow<K::SelfType<'a0>> + 'a0>(
+        &mut self,
+        range: impl RangeBounds<KR>,
+    ) -> Result<u64>
+    where
+        K: 'a0,
+    {
+        let start = key_bound_bytes::<K, KR>(range.start_bound());
+        let end = key_bound_bytes::<K, KR>(range.end_bound());
+        let mut root = self.root.lock().unwrap();
+        let mut freed_pages = self.freed_pages.lock().unwrap();
+        let mut operation: MutateHelper<'_, '_, K, V> = MutateHelper::new(
+            &mut root,
+            FreePolicy::Uncommitted,
+            self.mem,
+            freed_pages.as_mut(),
+        );
+        operation.set_fill_policy(self.fill_policy);
+        operation.delete_range(as_slice_bound(&start), as_slice_bound(&end))
+    }
+
     #[allow(dead_code)]
     pub(crate) fn print_debug(&self, include_values: bool) -> Result {
         self.read_tree()?.print_debug(include_values)
@@ -240,7 +436,7 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
     >(
         &mut self,
         range: T,
-    ) -> Result<BtreeDrain<'a, K, V>>
+    ) -> Result<(BtreeDrain<'a, K, V>, u64)>
     where
         K: 'a0,
     {
@@ -250,9 +446,12 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
         let mut root = self.root.lock().unwrap();
         let mut operation: MutateHelper<'_, '_, K, V> =
             MutateHelper::new(&mut root, FreePolicy::Never, self.mem, &mut free_on_drop);
+        operation.set_fill_policy(self.fill_policy);
+        let mut removed = 0;
         for entry in iter {
             // TODO: optimize so that we don't have to call safe_delete in a loop
             assert!(operation.safe_delete(entry?.key().borrow())?.is_some());
+            removed += 1;
         }
 
         let result = BtreeDrain::new(
@@ -262,7 +461,7 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
             self.mem,
         );
 
-        Ok(result)
+        Ok((result, removed))
     }
 
     pub(crate) fn drain_filter<
@@ -275,7 +474,7 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
         &mut self,
         range: T,
         predicate: F,
-    ) -> Result<BtreeDrainFilter<'a, K, V, F>>
+    ) -> Result<(BtreeDrainFilter<'a, K, V, F>, u64)>
     where
         K: 'a0,
     {
@@ -285,11 +484,14 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
         let mut root = self.root.lock().unwrap();
         let mut operation: MutateHelper<'_, '_, K, V> =
             MutateHelper::new(&mut root, FreePolicy::Never, self.mem, &mut free_on_drop);
+        operation.set_fill_policy(self.fill_policy);
+        let mut removed = 0;
         for entry in iter {
             // TODO: optimize so that we don't have to call safe_delete in a loop
             let entry = entry?;
             if predicate(entry.key(), entry.value()) {
                 assert!(operation.safe_delete(entry.key().borrow())?.is_some());
+                removed += 1;
             }
         }
 
@@ -301,23 +503,32 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
             self.mem,
         );
 
-        Ok(result)
+        Ok((result, removed))
     }
 
     pub(crate) fn len(&self) -> Result<u64> {
         self.read_tree()?.len()
     }
+
+    pub(crate) fn sample(
+        &self,
+        n: usize,
+        rng: impl FnMut() -> u64,
+    ) -> Result<Vec<EntryGuard<'a, K, V>>> {
+        self.read_tree()?.sample(n, rng)
+    }
 }
 
 impl<'a, K: RedbKey + 'a, V: RedbValueMutInPlace + 'a> BtreeMut<'a, K, V> {
     /// Reserve space to insert a key-value pair
-    /// The returned reference will have length equal to value_length
+    /// The returned reference will have length equal to value_length, and is paired with whether
+    /// the key was already present
     // Return type has the same lifetime as &self, because the tree must not be modified until the mutable guard is dropped
     pub(crate) fn insert_reserve(
         &mut self,
         key: &K::SelfType<'_>,
         value_length: u32,
-    ) -> Result<AccessGuardMut<V>> {
+    ) -> Result<(AccessGuardMut<V>, bool)> {
         #[cfg(feature = "logging")]
         trace!(
             "Btree(root={:?}): Inserting {:?} with {} reserved bytes for the value",
@@ -335,10 +546,59 @@ impl<'a, K: RedbKey + 'a, V: RedbValueMutInPlace + 'a> BtreeMut<'a, K, V> {
             self.mem,
             freed_pages.as_mut(),
         );
-        let (_, mut guard) = operation.insert(key, &V::from_bytes(&value))?;
+        operation.set_fill_policy(self.fill_policy);
+        let (old_value, mut guard) = operation.insert(key, &V::from_bytes(&value))?;
+        let existed = old_value.is_some();
+        drop(old_value);
         drop(root);
         guard.set_root_for_drop::<K>(self.root.clone(), K::as_bytes(key).as_ref())?;
-        Ok(guard)
+        Ok((guard, existed))
+    }
+}
+
+impl<'a, K: RedbKey + 'a> BtreeMut<'a, K, &'static [u8]> {
+    /// Returns a guard for the value of `key`, which can be extended in place by `additional`
+    /// bytes. Returns None if the key is not present, or if its leaf does not have enough free
+    /// space or it or any branch above it has been committed, and so would need to be copied
+    pub(crate) fn reserve_in_place(
+        &mut self,
+        key: &K::SelfType<'_>,
+        additional: usize,
+    ) -> Result<Option<AccessGuardMut<'_, &'static [u8]>>> {
+        let key_bytes = K::as_bytes(key);
+        let key_bytes = key_bytes.as_ref();
+        let mut page_number = match *self.root.lock().unwrap() {
+            Some((root, _)) => root,
+            None => return Ok(None),
+        };
+        loop {
+            if !self.mem.uncommitted(page_number) {
+                return Ok(None);
+            }
+            let page = self.mem.get_page(page_number)?;
+            match page.memory()[0] {
+                LEAF => break,
+                BRANCH => {
+                    let accessor = BranchAccessor::new(&page, K::fixed_width());
+                    page_number = accessor.child_for_key::<K>(key_bytes).1;
+                }
+                _ => unreachable!(),
+            }
+        }
+        let page = self.mem.get_page_mut(page_number)?;
+        let accessor = LeafAccessor::new(page.memory(), K::fixed_width(), None);
+        let entry = match accessor.find_key::<K>(key_bytes) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        drop(accessor);
+        match AccessGuardMut::for_existing_value::<K>(page, entry, additional, self.mem) {
+            Some(mut guard) => {
+                guard.set_root_for_drop::<K>(self.root.clone(), key_bytes)?;
+                Ok(Some(guard))
+            }
+            None => Ok(None),
+        }
     }
 }
 
@@ -364,6 +624,285 @@ impl<'a> RawBtree<'a> {
         }
     }
 
+    // Reads the pages of the btree into the cache, a level at a time, and each level in file order.
+    // Leaves are only read if `include_leaves` is true. Returns the number of pages read
+    pub(crate) fn preload(&self, include_leaves: bool) -> Result<u64> {
+        let mut level = if let Some((root, _)) = self.root {
+            vec![root]
+        } else {
+            return Ok(0);
+        };
+        // The tree is balanced, so the height is that of its first leaf
+        let mut height = 1;
+        let mut page = self.mem.get_page(level[0])?;
+        while page.memory()[0] == BRANCH {
+            let child = BranchAccessor::new(&page, self.fixed_key_size)
+                .child_page(0)
+                .unwrap();
+            page = self.mem.get_page(child)?;
+            height += 1;
+        }
+        drop(page);
+
+        let levels = if include_leaves { height } else { height - 1 };
+        let mut pages = 0;
+        for _ in 0..levels {
+            level.sort_unstable_by_key(|page| self.mem.page_range(*page).start);
+            let mut children = vec![];
+            for page_number in level.drain(..) {
+                let page = self.mem.get_page(page_number)?;
+                pages += 1;
+                if page.memory()[0] == BRANCH {
+                    let accessor = BranchAccessor::new(&page, self.fixed_key_size);
+                    for i in 0..accessor.count_children() {
+                        children.push(accessor.child_page(i).unwrap());
+                    }
+                }
+            }
+            level = children;
+        }
+
+        Ok(pages)
+    }
+
+    // Calls `f` with every key and value in the btree, in key order
+    pub(crate) fn for_each_entry(&self, mut f: impl FnMut(&[u8], &[u8]) -> Result) -> Result {
+        if let Some((root, _)) = self.root {
+            self.for_each_entry_helper(root, &mut f)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn for_each_entry_helper(
+        &self,
+        page_number: PageNumber,
+        f: &mut impl FnMut(&[u8], &[u8]) -> Result,
+    ) -> Result {
+        let page = self.mem.get_page(page_number)?;
+        match page.memory()[0] {
+            LEAF => {
+                let accessor =
+                    LeafAccessor::new(page.memory(), self.fixed_key_size, self.fixed_value_size);
+                for i in 0..accessor.num_pairs() {
+                    let entry = accessor.entry(i).unwrap();
+                    f(entry.key(), entry.value())?;
+                }
+            }
+            BRANCH => {
+                let accessor = BranchAccessor::new(&page, self.fixed_key_size);
+                for i in 0..accessor.count_children() {
+                    self.for_each_entry_helper(accessor.child_page(i).unwrap(), f)?;
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    // Calls `f` with the keys and values in the btree, in key order, starting from the entry at
+    // position `start`, until it returns false. The skipped entries are not read
+    pub(crate) fn for_each_entry_from(
+        &self,
+        start: u64,
+        mut f: impl FnMut(&[u8], &[u8]) -> Result<bool>,
+    ) -> Result {
+        if let Some((root, _)) = self.root {
+            let mut skip = start;
+            self.for_each_entry_from_helper(root, &mut skip, &mut f)?;
+        }
+        Ok(())
+    }
+
+    // Returns false once `f` has returned false
+    fn for_each_entry_from_helper(
+        &self,
+        page_number: PageNumber,
+        skip: &mut u64,
+        f: &mut impl FnMut(&[u8], &[u8]) -> Result<bool>,
+    ) -> Result<bool> {
+        let page = self.mem.get_page(page_number)?;
+        match page.memory()[0] {
+            LEAF => {
+                let accessor =
+                    LeafAccessor::new(page.memory(), self.fixed_key_size, self.fixed_value_size);
+                let first: usize = std::mem::take(skip).try_into().unwrap();
+                for i in first..accessor.num_pairs() {
+                    let entry = accessor.entry(i).unwrap();
+                    if !f(entry.key(), entry.value())? {
+                        return Ok(false);
+                    }
+                }
+            }
+            BRANCH => {
+                let accessor = BranchAccessor::new(&page, self.fixed_key_size);
+                for i in 0..accessor.count_children() {
+                    let length = accessor.child_length(i).unwrap();
+                    if *skip >= length {
+                        *skip -= length;
+                        continue;
+                    }
+                    if !self.for_each_entry_from_helper(accessor.child_page(i).unwrap(), skip, f)? {
+                        return Ok(false);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(true)
+    }
+
+    // Returns the entries which differ between this tree and `newer`, whose keys are ordered by
+    // `compare`
+    pub(crate) fn diff(
+        &self,
+        newer: &RawBtree<'a>,
+        compare: fn(&[u8], &[u8]) -> Ordering,
+    ) -> Result<BtreeDiff<'a, &'static [u8], &'static [u8]>> {
+        BtreeDiff::new_with_layout(
+            self.root.map(|(p, _)| p),
+            self.mem,
+            newer.root.map(|(p, _)| p),
+            newer.mem,
+            self.fixed_key_size,
+            self.fixed_value_size,
+            compare,
+        )
+    }
+
+    // Verifies the checksum of every page, without descending into those which fail. `corrupted`
+    // is called with each failing page, and the exclusive lower and inclusive upper bounds of the
+    // keys which were stored under it, and `entry` is called with every key and value in the
+    // pages which pass
+    pub(crate) fn scan_for_corruption(
+        &self,
+        mut corrupted: impl FnMut(PageNumber, Option<&[u8]>, Option<&[u8]>),
+        mut entry: impl FnMut(&[u8], &[u8]),
+    ) -> Result {
+        if let Some((root, checksum)) = self.root {
+            self.scan_for_corruption_helper(root, checksum, None, None, &mut corrupted, &mut entry)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn scan_for_corruption_helper(
+        &self,
+        page_number: PageNumber,
+        expected_checksum: Checksum,
+        lower: Option<&[u8]>,
+        upper: Option<&[u8]>,
+        corrupted: &mut impl FnMut(PageNumber, Option<&[u8]>, Option<&[u8]>),
+        entry: &mut impl FnMut(&[u8], &[u8]),
+    ) -> Result {
+        let page = self.mem.get_page(page_number)?;
+        let checksum = checked_checksum(
+            &page,
+            self.fixed_key_size,
+            self.fixed_value_size,
+            self.mem.checksum_algorithm(),
+        );
+        if checksum != Some(expected_checksum) {
+            corrupted(page_number, lower, upper);
+            return Ok(());
+        }
+        match page.memory()[0] {
+            LEAF => {
+                let accessor =
+                    LeafAccessor::new(page.memory(), self.fixed_key_size, self.fixed_value_size);
+                for i in 0..accessor.num_pairs() {
+                    let pair = accessor.entry(i).unwrap();
+                    entry(pair.key(), pair.value());
+                }
+            }
+            BRANCH => {
+                let accessor = BranchAccessor::new(&page, self.fixed_key_size);
+                for i in 0..accessor.count_children() {
+                    let child_lower = if i == 0 { lower } else { accessor.key(i - 1) };
+                    let child_upper = accessor.key(i).or(upper);
+                    self.scan_for_corruption_helper(
+                        accessor.child_page(i).unwrap(),
+                        accessor.child_checksum(i).unwrap(),
+                        child_lower,
+                        child_upper,
+                        corrupted,
+                        entry,
+                    )?;
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    // Copies the pages of this tree which differ from those at the same locations in `target`.
+    // Pages are compared from the top down: a page of `target` whose checksum matches the one
+    // stored for it by its parent is kept, and only its children are compared, so pages are read
+    // from this tree only where they differ. `entry` is called with every key and value of the
+    // tree. Returns the number of pages copied
+    pub(crate) fn copy_differing_pages(
+        &self,
+        target: &TransactionalMemory,
+        entry: &mut impl FnMut(&[u8], &[u8]),
+    ) -> Result<u64> {
+        let mut copied = 0;
+        if let Some((root, checksum)) = self.root {
+            self.copy_differing_pages_helper(root, checksum, target, entry, &mut copied)?;
+        }
+        Ok(copied)
+    }
+
+    fn copy_differing_pages_helper(
+        &self,
+        page_number: PageNumber,
+        expected_checksum: Checksum,
+        target: &TransactionalMemory,
+        entry: &mut impl FnMut(&[u8], &[u8]),
+        copied: &mut u64,
+    ) -> Result {
+        let page = target.get_page(page_number)?;
+        let checksum = checked_checksum(
+            &page,
+            self.fixed_key_size,
+            self.fixed_value_size,
+            target.checksum_algorithm(),
+        );
+        let page = if checksum == Some(expected_checksum) {
+            page
+        } else {
+            drop(page);
+            let source = self.mem.get_page(page_number)?;
+            let mut copy = target.get_page_mut(page_number)?;
+            copy.memory_mut().copy_from_slice(source.memory());
+            *copied += 1;
+            source
+        };
+        match page.memory()[0] {
+            LEAF => {
+                let accessor =
+                    LeafAccessor::new(page.memory(), self.fixed_key_size, self.fixed_value_size);
+                for i in 0..accessor.num_pairs() {
+                    let pair = accessor.entry(i).unwrap();
+                    entry(pair.key(), pair.value());
+                }
+            }
+            BRANCH => {
+                let accessor = BranchAccessor::new(&page, self.fixed_key_size);
+                for i in 0..accessor.count_children() {
+                    self.copy_differing_pages_helper(
+                        accessor.child_page(i).unwrap(),
+                        accessor.child_checksum(i).unwrap(),
+                        target,
+                        entry,
+                        copied,
+                    )?;
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
     pub(crate) fn verify_checksum(&self) -> Result<bool> {
         if let Some((root, checksum)) = self.root {
             self.verify_checksum_helper(root, checksum)
@@ -382,10 +921,17 @@ impl<'a> RawBtree<'a> {
         Ok(match node_mem[0] {
             LEAF => {
                 expected_checksum
-                    == leaf_checksum(&page, self.fixed_key_size, self.fixed_value_size)
+                    == leaf_checksum(
+                        &page,
+                        self.fixed_key_size,
+                        self.fixed_value_size,
+                        self.mem.checksum_algorithm(),
+                    )
             }
             BRANCH => {
-                if expected_checksum != branch_checksum(&page, self.fixed_key_size) {
+                let checksum =
+                    branch_checksum(&page, self.fixed_key_size, self.mem.checksum_algorithm());
+                if expected_checksum != checksum {
                     return Ok(false);
                 }
                 let accessor = BranchAccessor::new(&page, self.fixed_key_size);
@@ -435,8 +981,14 @@ impl<'a, K: RedbKey, V: RedbValue> Btree<'a, K, V> {
         })
     }
 
+    pub(crate) fn preload(&self, include_leaves: bool) -> Result<u64> {
+        RawBtree::new(self.root, K::fixed_width(), V::fixed_width(), self.mem)
+            .preload(include_leaves)
+    }
+
     pub(crate) fn get(&self, key: &K::SelfType<'_>) -> Result<Option<AccessGuard<'a, V>>> {
         if let Some(ref root_page) = self.cached_root {
+            self.check_quarantine(root_page.get_page_number())?;
             self.get_helper(root_page.clone(), K::as_bytes(key).as_ref())
         } else {
             Ok(None)
@@ -461,12 +1013,23 @@ impl<'a, K: RedbKey, V: RedbValue> Btree<'a, K, V> {
             BRANCH => {
                 let accessor = BranchAccessor::new(&page, K::fixed_width());
                 let (_, child_page) = accessor.child_for_key::<K>(query);
+                self.check_quarantine(child_page)?;
                 self.get_helper(self.mem.get_page_extended(child_page, self.hint)?, query)
             }
             _ => unreachable!(),
         }
     }
 
+    fn check_quarantine(&self, page: PageNumber) -> Result {
+        if self.mem.check_quarantine(page).is_some() {
+            Err(Error::Corrupted(format!(
+                "Entry is stored in quarantined page {page:?}"
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
     pub(crate) fn range<'a0, T: RangeBounds<KR> + 'a0, KR: Borrow<K::SelfType<'a0>> + 'a0>(
         &self,
         range: T,
@@ -477,7 +1040,71 @@ impl<'a, K: RedbKey, V: RedbValue> Btree<'a, K, V> {
         BtreeRangeIter::new(range, self.root.map(|(p, _)| p), self.mem)
     }
 
+    // Returns the entries which differ between this tree and `newer`
+    pub(crate) fn diff(&self, newer: &Btree<'a, K, V>) -> Result<BtreeDiff<'a, K, V>> {
+        BtreeDiff::new(
+            self.root.map(|(p, _)| p),
+            self.mem,
+            newer.root.map(|(p, _)| p),
+            newer.mem,
+        )
+    }
+
+    // Returns n entries, chosen uniformly with replacement. Each is found by choosing a position
+    // and descending from the root, using the number of entries below each child of a branch
+    pub(crate) fn sample(
+        &self,
+        n: usize,
+        mut rng: impl FnMut() -> u64,
+    ) -> Result<Vec<EntryGuard<'a, K, V>>> {
+        let mut result = vec![];
+        let root = if let Some(ref root) = self.cached_root {
+            root
+        } else {
+            return Ok(result);
+        };
+        self.check_quarantine(root.get_page_number())?;
+        let length = subtree_length(root, K::fixed_width(), V::fixed_width());
+        for _ in 0..n {
+            let mut page = root.clone();
+            let mut position = random_index(length, &mut rng);
+            loop {
+                match page.memory()[0] {
+                    LEAF => {
+                        let accessor =
+                            LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+                        let index = usize::try_from(position).unwrap();
+                        let (key, value) = accessor.entry_ranges(index).unwrap();
+                        result.push(EntryGuard::new(page, key, value));
+                        break;
+                    }
+                    BRANCH => {
+                        let accessor = BranchAccessor::new(&page, K::fixed_width());
+                        let mut index = 0;
+                        while position >= accessor.child_length(index).unwrap() {
+                            position -= accessor.child_length(index).unwrap();
+                            index += 1;
+                        }
+                        let child = accessor.child_page(index).unwrap();
+                        self.check_quarantine(child)?;
+                        page = self.mem.get_page_extended(child, self.hint)?;
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Returns the number of entries, which the root page records unless pages are quarantined
     pub(crate) fn len(&self) -> Result<u64> {
+        if !matches!(self.mem.quarantine(), Some(quarantine) if !quarantine.is_empty()) {
+            return Ok(self.cached_root.as_ref().map_or(0, |root| {
+                subtree_length(root, K::fixed_width(), V::fixed_width())
+            }));
+        }
+        // Entries beneath quarantined pages are not counted, so visit every entry
         let iter: BtreeRangeIter<K, V> = BtreeRangeIter::new::<RangeFull, K::SelfType<'_>>(
             ..,
             self.root.map(|(p, _)| p),
@@ -603,3 +1230,28 @@ fn stats_helper(
         _ => unreachable!(),
     }
 }
+
+// Maps a random u64 to an index in 0..len, with negligible bias
+fn random_index(len: u64, rng: &mut impl FnMut() -> u64) -> u64 {
+    ((u128::from(rng()) * u128::from(len)) >> 64)
+        .try_into()
+        .unwrap()
+}
+
+fn key_bound_bytes<'a, K: RedbKey + 'a, KR: Borrow<K::SelfType<'a>>>(
+    bound: Bound<&KR>,
+) -> Bound<Vec<u8>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(K::as_bytes(key.borrow()).as_ref().to_vec()),
+        Bound::Excluded(key) => Bound::Excluded(K::as_bytes(key.borrow()).as_ref().to_vec()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+fn as_slice_bound(bound: &Bound<Vec<u8>>) -> Bound<&[u8]> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key),
+        Bound::Excluded(key) => Bound::Excluded(key),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
@@ -1,12 +1,15 @@
-use crate::tree_store::page_store::{xxh3_checksum, Page, PageImpl, PageMut, TransactionalMemory};
+use crate::sync::Mutex;
+use crate::tree_store::page_store::{
+    ChecksumAlgorithm, Page, PageImpl, PageMut, TransactionalMemory,
+};
 use crate::tree_store::PageNumber;
 use crate::types::{RedbKey, RedbValue, RedbValueMutInPlace};
 use crate::Result;
 use std::cmp::Ordering;
 use std::marker::PhantomData;
 use std::mem::size_of;
-use std::ops::Range;
-use std::sync::{Arc, Mutex};
+use std::ops::{Deref, Range};
+use std::sync::Arc;
 use std::{mem, thread};
 
 pub(crate) const LEAF: u8 = 1;
@@ -18,20 +21,76 @@ pub(super) fn leaf_checksum<T: Page>(
     page: &T,
     fixed_key_size: Option<usize>,
     fixed_value_size: Option<usize>,
+    algorithm: ChecksumAlgorithm,
 ) -> Checksum {
     let accessor = LeafAccessor::new(page.memory(), fixed_key_size, fixed_value_size);
     // TODO: during verification, the page could be corrupted, so this needs to be safe on
     // arbitrary data
     let end = accessor.value_end(accessor.num_pairs() - 1).unwrap();
-    xxh3_checksum(&page.memory()[..end])
+    algorithm.checksum(&page.memory()[..end])
 }
 
-pub(super) fn branch_checksum<T: Page>(page: &T, fixed_key_size: Option<usize>) -> Checksum {
+pub(super) fn branch_checksum<T: Page>(
+    page: &T,
+    fixed_key_size: Option<usize>,
+    algorithm: ChecksumAlgorithm,
+) -> Checksum {
     let accessor = BranchAccessor::new(page, fixed_key_size);
     // TODO: during verification, the page could be corrupted, so this needs to be safe on
     // arbitrary data
     let end = accessor.key_end(accessor.num_keys() - 1);
-    xxh3_checksum(&page.memory()[..end])
+    algorithm.checksum(&page.memory()[..end])
+}
+
+// Returns the number of entries in the subtree rooted at the given leaf or branch page
+pub(crate) fn subtree_length<T: Page>(
+    page: &T,
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+) -> u64 {
+    match page.memory()[0] {
+        LEAF => {
+            let accessor = LeafAccessor::new(page.memory(), fixed_key_size, fixed_value_size);
+            u64::try_from(accessor.num_pairs()).unwrap()
+        }
+        BRANCH => BranchAccessor::new(page, fixed_key_size).subtree_length(),
+        _ => unreachable!(),
+    }
+}
+
+// Returns the checksum of a leaf or branch page, or None if the page is malformed. Unlike
+// leaf_checksum() and branch_checksum(), this is safe to call on arbitrary data
+pub(super) fn checked_checksum<T: Page>(
+    page: &T,
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+    algorithm: ChecksumAlgorithm,
+) -> Option<Checksum> {
+    let mem = page.memory();
+    if mem.len() < 4 {
+        return None;
+    }
+    let end = match mem[0] {
+        LEAF => {
+            let accessor = LeafAccessor::new(mem, fixed_key_size, fixed_value_size);
+            if accessor.num_pairs() == 0 || accessor.key_section_start() > mem.len() {
+                return None;
+            }
+            accessor.value_end(accessor.num_pairs() - 1)?
+        }
+        BRANCH => {
+            let accessor = BranchAccessor::new(page, fixed_key_size);
+            if accessor.num_keys() == 0 || accessor.key_section_start() > mem.len() {
+                return None;
+            }
+            accessor.key_end(accessor.num_keys() - 1)
+        }
+        _ => return None,
+    };
+    if end > mem.len() {
+        return None;
+    }
+    Some(algorithm.checksum(&mem[..end]))
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -69,6 +128,60 @@ impl FreePolicy {
     }
 }
 
+/// Controls how full the pages of a table are kept, as it is modified
+///
+/// See [`crate::TableDefinition::with_fill_policy`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FillPolicy {
+    split_percent: u8,
+    merge_percent: u8,
+}
+
+impl FillPolicy {
+    /// Full pages are split in half, and pages less than 33% full are merged. This is the default
+    pub const BALANCED: FillPolicy = FillPolicy::new(50, 33);
+
+    /// Full pages keep as much of their data as possible when split, and the new page receives the
+    /// remainder. Use this for tables where keys are mostly inserted in ascending order, so that
+    /// pages are left full instead of half empty
+    pub const APPEND: FillPolicy = FillPolicy::new(100, 33);
+
+    /// `split_percent` is the percentage of a full page's data which stays in the first page when
+    /// it is split. After a removal, pages which are less than `merge_percent` full are merged with
+    /// a neighbor.
+    ///
+    /// ## Invariant
+    ///
+    /// `split_percent` must be between 1 and 100, and `merge_percent` must be less than 50
+    pub const fn new(split_percent: u8, merge_percent: u8) -> Self {
+        assert!(split_percent >= 1 && split_percent <= 100);
+        assert!(merge_percent < 50);
+        Self {
+            split_percent,
+            merge_percent,
+        }
+    }
+
+    pub fn split_percent(&self) -> u8 {
+        self.split_percent
+    }
+
+    pub fn merge_percent(&self) -> u8 {
+        self.merge_percent
+    }
+
+    // Pages with fewer than this many bytes are merged with a neighbor
+    pub(crate) fn merge_threshold(&self, page_size: usize) -> usize {
+        page_size * usize::from(self.merge_percent) / 100
+    }
+}
+
+impl Default for FillPolicy {
+    fn default() -> Self {
+        Self::BALANCED
+    }
+}
+
 enum OnDrop {
     None,
     Free(PageNumber),
@@ -171,7 +284,81 @@ impl<'a, V: RedbValue> AccessGuard<'a, V> {
     }
 
     pub fn value(&self) -> V::SelfType<'_> {
-        V::from_bytes(&self.page.memory()[self.offset..(self.offset + self.len)])
+        V::from_bytes(self.raw_bytes())
+    }
+
+    /// Returns the stored encoding of the value, without decoding it
+    pub fn raw_bytes(&self) -> &[u8] {
+        &self.page.memory()[self.offset..(self.offset + self.len)]
+    }
+}
+
+impl<'a, 'b> AccessGuard<'a, &'b [u8]> {
+    /// Copies the value into a `Vec`, which outlives the guard
+    pub fn to_owned(&self) -> Vec<u8> {
+        self.raw_bytes().to_vec()
+    }
+}
+
+impl<'a, 'b> Deref for AccessGuard<'a, &'b [u8]> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.raw_bytes()
+    }
+}
+
+impl<'a, 'b> AsRef<[u8]> for AccessGuard<'a, &'b [u8]> {
+    fn as_ref(&self) -> &[u8] {
+        self.raw_bytes()
+    }
+}
+
+impl<'a, 'b, const N: usize> AccessGuard<'a, &'b [u8; N]> {
+    /// Copies the value into an array, which outlives the guard
+    pub fn to_owned(&self) -> [u8; N] {
+        *self.value()
+    }
+}
+
+impl<'a, 'b, const N: usize> Deref for AccessGuard<'a, &'b [u8; N]> {
+    type Target = [u8; N];
+
+    fn deref(&self) -> &[u8; N] {
+        self.raw_bytes().try_into().unwrap()
+    }
+}
+
+impl<'a, 'b, const N: usize> AsRef<[u8]> for AccessGuard<'a, &'b [u8; N]> {
+    fn as_ref(&self) -> &[u8] {
+        self.raw_bytes()
+    }
+}
+
+impl<'a, 'b> AccessGuard<'a, &'b str> {
+    /// Copies the value into a `String`, which outlives the guard
+    pub fn to_owned(&self) -> String {
+        self.value().to_string()
+    }
+}
+
+impl<'a, 'b> Deref for AccessGuard<'a, &'b str> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.value()
+    }
+}
+
+impl<'a, 'b> AsRef<str> for AccessGuard<'a, &'b str> {
+    fn as_ref(&self) -> &str {
+        self.value()
+    }
+}
+
+impl<'a, 'b> AsRef<[u8]> for AccessGuard<'a, &'b str> {
+    fn as_ref(&self) -> &[u8] {
+        self.raw_bytes()
     }
 }
 
@@ -207,6 +394,10 @@ pub struct AccessGuardMut<'a, V: RedbValue> {
     page: PageMut<'a>,
     offset: usize,
     len: usize,
+    // Number of bytes reserved for the value, which it can be resized within
+    capacity: usize,
+    // Index of the entry in the leaf
+    entry: usize,
     // child indices starting at root and going to the leaf which holds this value
     tree_path: Vec<usize>,
     key_width: Option<usize>,
@@ -226,12 +417,35 @@ impl<'a, V: RedbValue> AccessGuardMut<'a, V> {
             page,
             offset,
             len,
+            capacity: len,
+            entry: 0,
             tree_path: vec![],
             key_width: K::fixed_width(),
             _value_type: Default::default(),
         }
     }
 
+    // Returns a guard for the existing value at index `entry` of the uncommitted leaf `page`, with
+    // `additional` bytes of capacity beyond its current length. Returns None if the leaf does not
+    // have that much free space
+    pub(crate) fn for_existing_value<K: RedbKey>(
+        page: PageMut<'a>,
+        entry: usize,
+        additional: usize,
+        mem: &'a TransactionalMemory,
+    ) -> Option<Self> {
+        let accessor = LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+        let (start, end) = accessor.value_range(entry).unwrap();
+        let last_value_end = accessor.value_end(accessor.num_pairs() - 1).unwrap();
+        drop(accessor);
+        if page.memory().len() - last_value_end < additional {
+            return None;
+        }
+        let mut guard = Self::new::<K>(page, start, end - start, mem);
+        guard.capacity = end - start + additional;
+        Some(guard)
+    }
+
     fn make_tree_path<K: RedbKey>(
         key: &[u8],
         page_number: PageNumber,
@@ -273,9 +487,17 @@ impl<'a, V: RedbValue> AccessGuardMut<'a, V> {
         )?;
         self.root = root;
         self.tree_path = path;
+        let accessor = LeafAccessor::new(self.page.memory(), self.key_width, V::fixed_width());
+        self.entry = accessor.find_key::<K>(key).unwrap();
         Ok(())
     }
 
+    fn resize(&mut self, len: usize) {
+        let mut mutator = LeafMutator::new(&mut self.page, self.key_width, V::fixed_width());
+        mutator.resize_value(self.entry, len);
+        self.len = len;
+    }
+
     // Repairs the checksums after the user has filled the mutable buffer. This is necessary
     // because the checksums will have been calculated with the values during .insert_reserve(),
     // but the user is given a mutable reference and will have modified the value, which invalidates
@@ -307,8 +529,13 @@ impl<'a, V: RedbValue> AccessGuardMut<'a, V> {
 
     fn checksum_helper<T: Page>(&self, page: &T) -> Checksum {
         match page.memory()[0] {
-            LEAF => leaf_checksum(page, self.key_width, V::fixed_width()),
-            BRANCH => branch_checksum(page, self.key_width),
+            LEAF => leaf_checksum(
+                page,
+                self.key_width,
+                V::fixed_width(),
+                self.mem.checksum_algorithm(),
+            ),
+            BRANCH => branch_checksum(page, self.key_width, self.mem.checksum_algorithm()),
             _ => unreachable!(),
         }
     }
@@ -320,6 +547,43 @@ impl<'a, V: RedbValueMutInPlace> AsMut<V::BaseRefType> for AccessGuardMut<'a, V>
     }
 }
 
+impl<'a, 'b> AccessGuardMut<'a, &'b [u8]> {
+    /// Returns the number of bytes reserved for the value by the call to
+    /// [`insert_reserve()`](crate::Table::insert_reserve), which is the largest length the value
+    /// can be extended to
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Shortens the value to `len` bytes. The rest of its capacity remains reserved until this
+    /// guard is dropped, so it can be extended again with [`Self::extend_to`]
+    ///
+    /// Has no effect if `len` is not less than the current length
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.resize(len);
+        }
+    }
+
+    /// Extends the value to `len` bytes, within its reserved capacity. The added bytes are zero
+    ///
+    /// Has no effect if `len` is not greater than the current length
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is greater than [`Self::capacity`]
+    pub fn extend_to(&mut self, len: usize) {
+        assert!(
+            len <= self.capacity,
+            "length {len} exceeds the reserved capacity of {}",
+            self.capacity
+        );
+        if len > self.len {
+            self.resize(len);
+        }
+    }
+}
+
 impl<'a, V: RedbValue> Drop for AccessGuardMut<'a, V> {
     fn drop(&mut self) {
         // Was dropped before being returned to the user, so no clean up needed
@@ -622,16 +886,30 @@ impl<'a, 'b> LeafBuilder<'a, 'b> {
         required_size > self.mem.get_page_size() && self.pairs.len() > 1
     }
 
-    pub(super) fn build_split(self) -> Result<(PageMut<'b>, &'a [u8], PageMut<'b>)> {
+    pub(super) fn build_split(
+        self,
+        fill_policy: FillPolicy,
+    ) -> Result<(PageMut<'b>, &'a [u8], PageMut<'b>)> {
         let total_size = self.total_key_bytes + self.total_value_bytes;
+        let target_size = total_size * usize::from(fill_policy.split_percent) / 100;
         let mut division = 0;
         let mut first_split_key_bytes = 0;
         let mut first_split_value_bytes = 0;
         for (key, value) in self.pairs.iter().take(self.pairs.len() - 1) {
+            // When biased toward the first page, don't let it grow beyond a single page
+            if fill_policy.split_percent > 50
+                && division > 0
+                && Self::required_bytes(
+                    division + 1,
+                    first_split_key_bytes + first_split_value_bytes + key.len() + value.len(),
+                ) > self.mem.get_page_size()
+            {
+                break;
+            }
             first_split_key_bytes += key.len();
             first_split_value_bytes += value.len();
             division += 1;
-            if first_split_key_bytes + first_split_value_bytes >= total_size / 2 {
+            if first_split_key_bytes + first_split_value_bytes >= target_size {
                 break;
             }
         }
@@ -1124,6 +1402,34 @@ impl<'a: 'b, 'b> LeafMutator<'a, 'b> {
         self.page.memory_mut().copy_within(start..end, dest);
     }
 
+    // Change the length of the value at index i to new_len, and shift all following values. If the
+    // value grows, the page must have enough free space, and the added bytes are zeroed
+    pub(super) fn resize_value(&mut self, i: usize, new_len: usize) {
+        assert!(self.fixed_value_size.is_none());
+        let accessor = LeafAccessor::new(
+            self.page.memory(),
+            self.fixed_key_size,
+            self.fixed_value_size,
+        );
+        let num_pairs = accessor.num_pairs();
+        let (start, end) = accessor.value_range(i).unwrap();
+        let last_value_end = accessor.value_end(num_pairs - 1).unwrap();
+        drop(accessor);
+        let new_end = start + new_len;
+        assert!(last_value_end - end + new_end <= self.page.memory().len());
+
+        let delta = isize::try_from(new_end).unwrap() - isize::try_from(end).unwrap();
+        for j in i..num_pairs {
+            self.update_value_end(j, delta);
+        }
+        self.page
+            .memory_mut()
+            .copy_within(end..last_value_end, new_end);
+        if new_end > end {
+            self.page.memory_mut()[end..new_end].fill(0);
+        }
+    }
+
     fn update_key_end(&mut self, i: usize, delta: isize) {
         if self.fixed_key_size.is_some() {
             return;
@@ -1166,7 +1472,7 @@ impl<'a: 'b, 'b> LeafMutator<'a, 'b> {
 }
 
 // Provides a simple zero-copy way to access a branch page
-pub(super) struct BranchAccessor<'a: 'b, 'b, T: Page + 'a> {
+pub(crate) struct BranchAccessor<'a: 'b, 'b, T: Page + 'a> {
     page: &'b T,
     num_keys: usize,
     fixed_key_size: Option<usize>,
@@ -1174,7 +1480,7 @@ pub(super) struct BranchAccessor<'a: 'b, 'b, T: Page + 'a> {
 }
 
 impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
-    pub(super) fn new(page: &'b T, fixed_key_size: Option<usize>) -> Self {
+    pub(crate) fn new(page: &'b T, fixed_key_size: Option<usize>) -> Self {
         debug_assert_eq!(page.memory()[0], BRANCH);
         let num_keys = u16::from_le_bytes(page.memory()[2..4].try_into().unwrap()) as usize;
         BranchAccessor {
@@ -1230,10 +1536,12 @@ impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
 
     fn key_section_start(&self) -> usize {
         if self.fixed_key_size.is_none() {
-            8 + (PageNumber::serialized_size() + size_of::<Checksum>()) * self.count_children()
+            8 + (PageNumber::serialized_size() + size_of::<Checksum>() + size_of::<u64>())
+                * self.count_children()
                 + size_of::<u32>() * self.num_keys()
         } else {
-            8 + (PageNumber::serialized_size() + size_of::<Checksum>()) * self.count_children()
+            8 + (PageNumber::serialized_size() + size_of::<Checksum>() + size_of::<u64>())
+                * self.count_children()
         }
     }
 
@@ -1250,7 +1558,8 @@ impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
             return self.key_section_start() + fixed * (n + 1);
         }
         let offset = 8
-            + (PageNumber::serialized_size() + size_of::<Checksum>()) * self.count_children()
+            + (PageNumber::serialized_size() + size_of::<Checksum>() + size_of::<u64>())
+                * self.count_children()
             + size_of::<u32>() * n;
         u32::from_le_bytes(
             self.page.memory()[offset..(offset + size_of::<u32>())]
@@ -1268,7 +1577,7 @@ impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
         Some(&self.page.memory()[offset..end])
     }
 
-    pub(super) fn count_children(&self) -> usize {
+    pub(crate) fn count_children(&self) -> usize {
         self.num_keys() + 1
     }
 
@@ -1285,7 +1594,7 @@ impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
         ))
     }
 
-    pub(super) fn child_page(&self, n: usize) -> Option<PageNumber> {
+    pub(crate) fn child_page(&self, n: usize) -> Option<PageNumber> {
         if n >= self.count_children() {
             return None;
         }
@@ -1299,13 +1608,36 @@ impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
         ))
     }
 
+    // Returns the number of entries in the subtree of the nth child
+    pub(crate) fn child_length(&self, n: usize) -> Option<u64> {
+        if n >= self.count_children() {
+            return None;
+        }
+
+        let offset = 8
+            + (size_of::<Checksum>() + PageNumber::serialized_size()) * self.count_children()
+            + size_of::<u64>() * n;
+        Some(u64::from_le_bytes(
+            self.page.memory()[offset..(offset + size_of::<u64>())]
+                .try_into()
+                .unwrap(),
+        ))
+    }
+
+    // Returns the number of entries in the subtree rooted at this branch
+    pub(crate) fn subtree_length(&self) -> u64 {
+        (0..self.count_children())
+            .map(|i| self.child_length(i).unwrap())
+            .sum()
+    }
+
     fn num_keys(&self) -> usize {
         self.num_keys
     }
 }
 
 pub(super) struct BranchBuilder<'a, 'b> {
-    children: Vec<(PageNumber, Checksum)>,
+    children: Vec<(PageNumber, Checksum, u64)>,
     keys: Vec<&'a [u8]>,
     total_key_bytes: usize,
     fixed_key_size: Option<usize>,
@@ -1327,12 +1659,18 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
         }
     }
 
-    pub(super) fn replace_child(&mut self, index: usize, child: PageNumber, checksum: Checksum) {
-        self.children[index] = (child, checksum);
+    pub(super) fn replace_child(
+        &mut self,
+        index: usize,
+        child: PageNumber,
+        checksum: Checksum,
+        length: u64,
+    ) {
+        self.children[index] = (child, checksum, length);
     }
 
-    pub(super) fn push_child(&mut self, child: PageNumber, checksum: Checksum) {
-        self.children.push((child, checksum));
+    pub(super) fn push_child(&mut self, child: PageNumber, checksum: Checksum, length: u64) {
+        self.children.push((child, checksum, length));
     }
 
     pub(super) fn push_key(&mut self, key: &'a [u8]) {
@@ -1344,14 +1682,15 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
         for i in 0..accessor.count_children() {
             let child = accessor.child_page(i).unwrap();
             let checksum = accessor.child_checksum(i).unwrap();
-            self.push_child(child, checksum);
+            let length = accessor.child_length(i).unwrap();
+            self.push_child(child, checksum, length);
         }
         for i in 0..(accessor.count_children() - 1) {
             self.push_key(accessor.key(i).unwrap());
         }
     }
 
-    pub(super) fn to_single_child(&self) -> Option<(PageNumber, Checksum)> {
+    pub(super) fn to_single_child(&self) -> Option<(PageNumber, Checksum, u64)> {
         if self.children.len() > 1 {
             None
         } else {
@@ -1368,10 +1707,12 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
         );
         let mut page = self.mem.allocate(size)?;
         let mut builder = RawBranchBuilder::new(&mut page, self.keys.len(), self.fixed_key_size);
-        builder.write_first_page(self.children[0].0, self.children[0].1);
+        let (page_number, checksum, length) = self.children[0];
+        builder.write_first_page(page_number, checksum, length);
         for i in 1..self.children.len() {
             let key = &self.keys[i - 1];
-            builder.write_nth_key(key.as_ref(), self.children[i].0, self.children[i].1, i - 1);
+            let (page_number, checksum, length) = self.children[i];
+            builder.write_nth_key(key.as_ref(), page_number, checksum, length, i - 1);
         }
         drop(builder);
 
@@ -1387,10 +1728,15 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
         size > self.mem.get_page_size() && self.keys.len() >= 3
     }
 
-    pub(super) fn build_split(self) -> Result<(PageMut<'b>, &'a [u8], PageMut<'b>)> {
+    pub(super) fn build_split(
+        self,
+        fill_policy: FillPolicy,
+    ) -> Result<(PageMut<'b>, &'a [u8], PageMut<'b>)> {
         assert_eq!(self.children.len(), self.keys.len() + 1);
         assert!(self.keys.len() >= 3);
-        let division = self.keys.len() / 2;
+        // The division key moves up to the parent, so each page must keep at least one key
+        let division = (self.keys.len() * usize::from(fill_policy.split_percent) / 100)
+            .clamp(1, self.keys.len() - 2);
         let first_split_key_len: usize = self.keys.iter().take(division).map(|k| k.len()).sum();
         let division_key = self.keys[division];
         let second_split_key_len = self.total_key_bytes - first_split_key_len - division_key.len();
@@ -1399,15 +1745,12 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
             RawBranchBuilder::required_bytes(division, first_split_key_len, self.fixed_key_size);
         let mut page1 = self.mem.allocate(size)?;
         let mut builder = RawBranchBuilder::new(&mut page1, division, self.fixed_key_size);
-        builder.write_first_page(self.children[0].0, self.children[0].1);
+        let (page_number, checksum, length) = self.children[0];
+        builder.write_first_page(page_number, checksum, length);
         for i in 0..division {
             let key = &self.keys[i];
-            builder.write_nth_key(
-                key.as_ref(),
-                self.children[i + 1].0,
-                self.children[i + 1].1,
-                i,
-            );
+            let (page_number, checksum, length) = self.children[i + 1];
+            builder.write_nth_key(key.as_ref(), page_number, checksum, length, i);
         }
         drop(builder);
 
@@ -1422,13 +1765,16 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
             self.keys.len() - division - 1,
             self.fixed_key_size,
         );
-        builder.write_first_page(self.children[division + 1].0, self.children[division + 1].1);
+        let (page_number, checksum, length) = self.children[division + 1];
+        builder.write_first_page(page_number, checksum, length);
         for i in (division + 1)..self.keys.len() {
             let key = &self.keys[i];
+            let (page_number, checksum, length) = self.children[i + 1];
             builder.write_nth_key(
                 key.as_ref(),
-                self.children[i + 1].0,
-                self.children[i + 1].1,
+                page_number,
+                checksum,
+                length,
                 i - division - 1,
             );
         }
@@ -1449,6 +1795,8 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
 // 16 bytes: child page checksum
 // repeating (num_keys + 1 times):
 // 8 bytes: page number
+// repeating (num_keys + 1 times):
+// 8 bytes: number of entries in the child's subtree
 // (optional) repeating (num_keys times):
 // * 4 bytes: key end. Ending offset of the key, exclusive
 // repeating (num_keys times):
@@ -1468,12 +1816,14 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
     ) -> usize {
         if fixed_key_size.is_none() {
             let fixed_size = 8
-                + (PageNumber::serialized_size() + size_of::<Checksum>()) * (num_keys + 1)
+                + (PageNumber::serialized_size() + size_of::<Checksum>() + size_of::<u64>())
+                    * (num_keys + 1)
                 + size_of::<u32>() * num_keys;
             size_of_keys + fixed_size
         } else {
-            let fixed_size =
-                8 + (PageNumber::serialized_size() + size_of::<Checksum>()) * (num_keys + 1);
+            let fixed_size = 8
+                + (PageNumber::serialized_size() + size_of::<Checksum>() + size_of::<u64>())
+                    * (num_keys + 1);
             size_of_keys + fixed_size
         }
     }
@@ -1489,10 +1839,12 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
         page.memory_mut()[2..4].copy_from_slice(&u16::try_from(num_keys).unwrap().to_le_bytes());
         #[cfg(debug_assertions)]
         {
-            // Poison all the child pointers & key offsets, in case the caller forgets to write them
+            // Poison all the child pointers, lengths & key offsets, in case the caller forgets to
+            // write them
             let start = 8 + size_of::<Checksum>() * (num_keys + 1);
             let last = 8
-                + (PageNumber::serialized_size() + size_of::<Checksum>()) * (num_keys + 1)
+                + (PageNumber::serialized_size() + size_of::<Checksum>() + size_of::<u64>())
+                    * (num_keys + 1)
                 + size_of::<u32>() * num_keys;
             for x in &mut page.memory_mut()[start..last] {
                 *x = 0xFF;
@@ -1506,18 +1858,34 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
         }
     }
 
-    pub(super) fn write_first_page(&mut self, page_number: PageNumber, checksum: Checksum) {
-        let offset = 8;
+    pub(super) fn write_first_page(
+        &mut self,
+        page_number: PageNumber,
+        checksum: Checksum,
+        length: u64,
+    ) {
+        self.write_child(0, page_number, checksum, length);
+    }
+
+    fn write_child(&mut self, i: usize, page_number: PageNumber, checksum: Checksum, length: u64) {
+        let offset = 8 + size_of::<Checksum>() * i;
         self.page.memory_mut()[offset..(offset + size_of::<Checksum>())]
             .copy_from_slice(&checksum.to_le_bytes());
-        let offset = 8 + size_of::<Checksum>() * (self.num_keys + 1);
+        let offset =
+            8 + size_of::<Checksum>() * (self.num_keys + 1) + PageNumber::serialized_size() * i;
         self.page.memory_mut()[offset..(offset + PageNumber::serialized_size())]
             .copy_from_slice(&page_number.to_le_bytes());
+        let offset = 8
+            + (size_of::<Checksum>() + PageNumber::serialized_size()) * (self.num_keys + 1)
+            + size_of::<u64>() * i;
+        self.page.memory_mut()[offset..(offset + size_of::<u64>())]
+            .copy_from_slice(&length.to_le_bytes());
     }
 
     fn key_section_start(&self) -> usize {
-        let mut offset =
-            8 + (PageNumber::serialized_size() + size_of::<Checksum>()) * (self.num_keys + 1);
+        let mut offset = 8
+            + (PageNumber::serialized_size() + size_of::<Checksum>() + size_of::<u64>())
+                * (self.num_keys + 1);
         if self.fixed_key_size.is_none() {
             offset += size_of::<u32>() * self.num_keys;
         }
@@ -1530,7 +1898,8 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
             return self.key_section_start() + fixed * (n + 1);
         }
         let offset = 8
-            + (PageNumber::serialized_size() + size_of::<Checksum>()) * (self.num_keys + 1)
+            + (PageNumber::serialized_size() + size_of::<Checksum>() + size_of::<u64>())
+                * (self.num_keys + 1)
             + size_of::<u32>() * n;
         u32::from_le_bytes(
             self.page.memory()[offset..(offset + size_of::<u32>())]
@@ -1546,19 +1915,13 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
         key: &[u8],
         page_number: PageNumber,
         checksum: Checksum,
+        length: u64,
         n: usize,
     ) {
         assert!(n < self.num_keys);
         assert_eq!(n, self.keys_written);
         self.keys_written += 1;
-        let offset = 8 + size_of::<Checksum>() * (n + 1);
-        self.page.memory_mut()[offset..(offset + size_of::<Checksum>())]
-            .copy_from_slice(&checksum.to_le_bytes());
-        let offset = 8
-            + size_of::<Checksum>() * (self.num_keys + 1)
-            + PageNumber::serialized_size() * (n + 1);
-        self.page.memory_mut()[offset..(offset + PageNumber::serialized_size())]
-            .copy_from_slice(&page_number.to_le_bytes());
+        self.write_child(n + 1, page_number, checksum, length);
 
         let data_offset = if n > 0 {
             self.key_end(n - 1)
@@ -1567,16 +1930,17 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
         };
         if self.fixed_key_size.is_none() {
             let offset = 8
-                + (PageNumber::serialized_size() + size_of::<Checksum>()) * (self.num_keys + 1)
+                + (PageNumber::serialized_size() + size_of::<Checksum>() + size_of::<u64>())
+                    * (self.num_keys + 1)
                 + size_of::<u32>() * n;
             self.page.memory_mut()[offset..(offset + size_of::<u32>())].copy_from_slice(
                 &u32::try_from(data_offset + key.len())
                     .unwrap()
                     .to_le_bytes(),
             );
+            debug_assert!(data_offset > offset);
         }
 
-        debug_assert!(data_offset > offset);
         self.page.memory_mut()[data_offset..(data_offset + key.len())].copy_from_slice(key);
     }
 }
@@ -1618,4 +1982,13 @@ impl<'a: 'b, 'b> BranchMutator<'a, 'b> {
         self.page.memory_mut()[offset..(offset + PageNumber::serialized_size())]
             .copy_from_slice(&page_number.to_le_bytes());
     }
+
+    pub(super) fn write_child_length(&mut self, i: usize, length: u64) {
+        debug_assert!(i <= self.num_keys());
+        let offset = 8
+            + (size_of::<Checksum>() + PageNumber::serialized_size()) * (self.num_keys() + 1)
+            + size_of::<u64>() * i;
+        self.page.memory_mut()[offset..(offset + size_of::<u64>())]
+            .copy_from_slice(&length.to_le_bytes());
+    }
 }
@@ -0,0 +1,273 @@
+use crate::tree_store::btree_base::{BranchAccessor, LeafAccessor, BRANCH, LEAF};
+use crate::tree_store::btree_iters::EntryGuard;
+use crate::tree_store::page_store::{Page, PageImpl, TransactionalMemory};
+use crate::tree_store::PageNumber;
+use crate::types::{RedbKey, RedbValue};
+use crate::Result;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+
+pub(crate) enum BtreeChange<'a, K: RedbKey, V: RedbValue> {
+    Added(EntryGuard<'a, K, V>),
+    Removed(EntryGuard<'a, K, V>),
+    Modified(EntryGuard<'a, K, V>, EntryGuard<'a, K, V>),
+}
+
+struct Frame<'a> {
+    page: PageImpl<'a>,
+    // For a leaf, the next entry. For a branch, the next child to descend into
+    index: usize,
+}
+
+// How to move a cursor past a subtree which starts at its current position
+#[derive(Copy, Clone)]
+enum Skip {
+    // The next child of the branch on top of the stack, which has not been loaded
+    Child,
+    // The page at this depth of the stack
+    Frame(usize),
+}
+
+// Position in an in-order walk of a btree, which can skip whole subtrees
+struct DiffCursor<'a> {
+    mem: &'a TransactionalMemory,
+    stack: Vec<Frame<'a>>,
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+}
+
+impl<'a> DiffCursor<'a> {
+    fn new(
+        root: Option<PageNumber>,
+        mem: &'a TransactionalMemory,
+        fixed_key_size: Option<usize>,
+        fixed_value_size: Option<usize>,
+    ) -> Result<Self> {
+        let mut stack = vec![];
+        if let Some(root) = root {
+            stack.push(Frame {
+                page: mem.get_page(root)?,
+                index: 0,
+            });
+        }
+        Ok(Self {
+            mem,
+            stack,
+            fixed_key_size,
+            fixed_value_size,
+        })
+    }
+
+    fn frame_len(&self, frame: &Frame) -> usize {
+        match frame.page.memory()[0] {
+            LEAF => LeafAccessor::new(
+                frame.page.memory(),
+                self.fixed_key_size,
+                self.fixed_value_size,
+            )
+            .num_pairs(),
+            BRANCH => BranchAccessor::new(&frame.page, self.fixed_key_size).count_children(),
+            _ => unreachable!(),
+        }
+    }
+
+    // Pops exhausted pages, and returns false if the walk is complete
+    fn normalize(&mut self) -> bool {
+        while let Some(top) = self.stack.last() {
+            if top.index < self.frame_len(top) {
+                return true;
+            }
+            self.stack.pop();
+        }
+        false
+    }
+
+    fn top_is_leaf(&self) -> bool {
+        self.stack.last().unwrap().page.memory()[0] == LEAF
+    }
+
+    // Returns the subtrees which begin at the current position, from smallest to largest
+    fn starting_subtrees(&self) -> Vec<(PageNumber, Skip)> {
+        let mut result = vec![];
+        let top = self.stack.last().unwrap();
+        if !self.top_is_leaf() {
+            let accessor = BranchAccessor::new(&top.page, self.fixed_key_size);
+            result.push((accessor.child_page(top.index).unwrap(), Skip::Child));
+        }
+        if top.index != 0 {
+            return result;
+        }
+        for depth in (0..self.stack.len()).rev() {
+            result.push((self.stack[depth].page.get_page_number(), Skip::Frame(depth)));
+            // The parent begins here only if this page is its first child
+            if depth > 0 && self.stack[depth - 1].index != 1 {
+                break;
+            }
+        }
+        result
+    }
+
+    fn skip(&mut self, skip: Skip) {
+        match skip {
+            Skip::Child => {
+                self.stack.last_mut().unwrap().index += 1;
+            }
+            Skip::Frame(depth) => {
+                self.stack.truncate(depth);
+            }
+        }
+    }
+
+    fn descend(&mut self) -> Result {
+        let top = self.stack.last_mut().unwrap();
+        let child = BranchAccessor::new(&top.page, self.fixed_key_size)
+            .child_page(top.index)
+            .unwrap();
+        top.index += 1;
+        let page = self.mem.get_page(child)?;
+        self.stack.push(Frame { page, index: 0 });
+        Ok(())
+    }
+
+    fn entry<K: RedbKey, V: RedbValue>(&self) -> EntryGuard<'a, K, V> {
+        let top = self.stack.last().unwrap();
+        let (key, value) = LeafAccessor::new(
+            top.page.memory(),
+            self.fixed_key_size,
+            self.fixed_value_size,
+        )
+        .entry_ranges(top.index)
+        .unwrap();
+        EntryGuard::new(top.page.clone(), key, value)
+    }
+
+    fn advance(&mut self) {
+        self.stack.last_mut().unwrap().index += 1;
+    }
+}
+
+// Iterates over the differences between two btrees, in key order. If both trees are stored in the
+// same database, subtrees which they share are skipped without being read
+pub(crate) struct BtreeDiff<'a, K: RedbKey, V: RedbValue> {
+    old: DiffCursor<'a>,
+    new: DiffCursor<'a>,
+    shared_pages: bool,
+    compare: fn(&[u8], &[u8]) -> Ordering,
+    _key_type: PhantomData<K>,
+    _value_type: PhantomData<V>,
+}
+
+impl<'a, K: RedbKey, V: RedbValue> BtreeDiff<'a, K, V> {
+    pub(crate) fn new(
+        old_root: Option<PageNumber>,
+        old_mem: &'a TransactionalMemory,
+        new_root: Option<PageNumber>,
+        new_mem: &'a TransactionalMemory,
+    ) -> Result<Self> {
+        Self::new_with_layout(
+            old_root,
+            old_mem,
+            new_root,
+            new_mem,
+            K::fixed_width(),
+            V::fixed_width(),
+            K::compare,
+        )
+    }
+
+    // Compares btrees whose entries have the given widths, and whose keys are ordered by `compare`
+    pub(crate) fn new_with_layout(
+        old_root: Option<PageNumber>,
+        old_mem: &'a TransactionalMemory,
+        new_root: Option<PageNumber>,
+        new_mem: &'a TransactionalMemory,
+        fixed_key_size: Option<usize>,
+        fixed_value_size: Option<usize>,
+        compare: fn(&[u8], &[u8]) -> Ordering,
+    ) -> Result<Self> {
+        Ok(Self {
+            old: DiffCursor::new(old_root, old_mem, fixed_key_size, fixed_value_size)?,
+            new: DiffCursor::new(new_root, new_mem, fixed_key_size, fixed_value_size)?,
+            // Pages are immutable once committed, so a page which is reachable from both trees
+            // has the same contents in each
+            shared_pages: std::ptr::eq(old_mem, new_mem),
+            compare,
+            _key_type: Default::default(),
+            _value_type: Default::default(),
+        })
+    }
+
+    // Skips the largest subtree which begins at the current position of both cursors, if any
+    fn skip_shared(&mut self) -> bool {
+        let new_subtrees = self.new.starting_subtrees();
+        for (page, old_skip) in self.old.starting_subtrees().into_iter().rev() {
+            if let Some((_, new_skip)) = new_subtrees.iter().find(|(p, _)| *p == page) {
+                self.old.skip(old_skip);
+                self.new.skip(*new_skip);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn next_change(&mut self) -> Result<Option<BtreeChange<'a, K, V>>> {
+        loop {
+            let old_remaining = self.old.normalize();
+            let new_remaining = self.new.normalize();
+            if old_remaining && new_remaining && self.shared_pages && self.skip_shared() {
+                continue;
+            }
+            if old_remaining && !self.old.top_is_leaf() {
+                self.old.descend()?;
+                continue;
+            }
+            if new_remaining && !self.new.top_is_leaf() {
+                self.new.descend()?;
+                continue;
+            }
+
+            match (old_remaining, new_remaining) {
+                (false, false) => return Ok(None),
+                (true, false) => {
+                    let entry = self.old.entry();
+                    self.old.advance();
+                    return Ok(Some(BtreeChange::Removed(entry)));
+                }
+                (false, true) => {
+                    let entry = self.new.entry();
+                    self.new.advance();
+                    return Ok(Some(BtreeChange::Added(entry)));
+                }
+                (true, true) => {
+                    let old_entry: EntryGuard<K, V> = self.old.entry();
+                    let new_entry: EntryGuard<K, V> = self.new.entry();
+                    match (self.compare)(old_entry.key_bytes(), new_entry.key_bytes()) {
+                        Ordering::Less => {
+                            self.old.advance();
+                            return Ok(Some(BtreeChange::Removed(old_entry)));
+                        }
+                        Ordering::Greater => {
+                            self.new.advance();
+                            return Ok(Some(BtreeChange::Added(new_entry)));
+                        }
+                        Ordering::Equal => {
+                            self.old.advance();
+                            self.new.advance();
+                            if old_entry.value_bytes() != new_entry.value_bytes() {
+                                return Ok(Some(BtreeChange::Modified(old_entry, new_entry)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K: RedbKey, V: RedbValue> Iterator for BtreeDiff<'a, K, V> {
+    type Item = Result<BtreeChange<'a, K, V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_change().transpose()
+    }
+}
@@ -1,4 +1,5 @@
-use crate::tree_store::btree_base::{BranchAccessor, LeafAccessor};
+use crate::sync::Mutex;
+use crate::tree_store::btree_base::{subtree_length, BranchAccessor, LeafAccessor};
 use crate::tree_store::btree_base::{BRANCH, LEAF};
 use crate::tree_store::btree_iters::RangeIterState::{Internal, Leaf};
 use crate::tree_store::page_store::{Page, PageImpl, TransactionalMemory};
@@ -7,11 +8,12 @@ use crate::types::{RedbKey, RedbValue};
 use crate::Result;
 use std::borrow::Borrow;
 use std::collections::Bound;
+use std::iter::FusedIterator;
 use std::marker::PhantomData;
 use std::ops::{Range, RangeBounds};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum RangeIterState<'a> {
     Leaf {
         page: PageImpl<'a>,
@@ -73,8 +75,7 @@ impl<'a> RangeIterState<'a> {
                 mut parent,
             } => {
                 let accessor = BranchAccessor::new(&page, fixed_key_size);
-                let child_page = accessor.child_page(child).unwrap();
-                let child_page = manager.get_page(child_page)?;
+                let child_page_number = accessor.child_page(child).unwrap();
                 let direction = if reverse { -1 } else { 1 };
                 let next_child = isize::try_from(child).unwrap() + direction;
                 if 0 <= next_child && next_child < accessor.count_children().try_into().unwrap() {
@@ -86,6 +87,11 @@ impl<'a> RangeIterState<'a> {
                         parent,
                     }));
                 }
+                if manager.check_quarantine(child_page_number).is_some() {
+                    // Skip the quarantined subtree
+                    return Ok(parent.map(|x| *x));
+                }
+                let child_page = manager.get_page(child_page_number)?;
                 match child_page.memory()[0] {
                     LEAF => {
                         let child_accessor = LeafAccessor::new(
@@ -127,6 +133,52 @@ impl<'a> RangeIterState<'a> {
         }
     }
 
+    // Returns the number of entries which follow this position, or which precede it if `reverse`
+    // is set. The entry at the position is included if `include` is set
+    fn entries_remaining(&self, reverse: bool, include: bool) -> u64 {
+        match self {
+            Leaf {
+                page,
+                fixed_key_size,
+                fixed_value_size,
+                entry,
+                parent,
+            } => {
+                let accessor = LeafAccessor::new(page.memory(), *fixed_key_size, *fixed_value_size);
+                let in_leaf = if reverse {
+                    *entry + 1
+                } else {
+                    accessor.num_pairs() - *entry
+                };
+                let in_leaf = u64::try_from(in_leaf).unwrap() - u64::from(!include);
+                // The parent is positioned on the next child to be entered
+                in_leaf
+                    + parent
+                        .as_ref()
+                        .map_or(0, |parent| parent.entries_remaining(reverse, true))
+            }
+            Internal {
+                page,
+                fixed_key_size,
+                child,
+                parent,
+                ..
+            } => {
+                let accessor = BranchAccessor::new(page, *fixed_key_size);
+                let children = if reverse {
+                    0..(*child + 1)
+                } else {
+                    *child..accessor.count_children()
+                };
+                let in_branch: u64 = children.map(|i| accessor.child_length(i).unwrap()).sum();
+                in_branch
+                    + parent
+                        .as_ref()
+                        .map_or(0, |parent| parent.entries_remaining(reverse, true))
+            }
+        }
+    }
+
     fn get_entry<K: RedbKey, V: RedbValue>(&self) -> Option<EntryGuard<'a, K, V>> {
         match self {
             Leaf {
@@ -155,7 +207,11 @@ pub(crate) struct EntryGuard<'a, K: RedbKey, V: RedbValue> {
 }
 
 impl<'a, K: RedbKey, V: RedbValue> EntryGuard<'a, K, V> {
-    fn new(page: PageImpl<'a>, key_range: Range<usize>, value_range: Range<usize>) -> Self {
+    pub(super) fn new(
+        page: PageImpl<'a>,
+        key_range: Range<usize>,
+        value_range: Range<usize>,
+    ) -> Self {
         Self {
             page,
             key_range,
@@ -169,6 +225,14 @@ impl<'a, K: RedbKey, V: RedbValue> EntryGuard<'a, K, V> {
         self.page.memory()[self.key_range.clone()].to_vec()
     }
 
+    pub(super) fn key_bytes(&self) -> &[u8] {
+        &self.page.memory()[self.key_range.clone()]
+    }
+
+    pub(super) fn value_bytes(&self) -> &[u8] {
+        &self.page.memory()[self.value_range.clone()]
+    }
+
     pub(crate) fn key(&self) -> K::SelfType<'_> {
         K::from_bytes(&self.page.memory()[self.key_range.clone()])
     }
@@ -276,8 +340,14 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> Iterator for BtreeDrain<'a, K, V> {
     fn next(&mut self) -> Option<Self::Item> {
         self.inner.next()
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
 }
 
+impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> ExactSizeIterator for BtreeDrain<'a, K, V> {}
+
 impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> DoubleEndedIterator for BtreeDrain<'a, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.inner.next_back()
@@ -401,11 +471,21 @@ impl<
     }
 }
 
+// Serialized start and end bounds of a range query
+type QueryBounds = (Bound<Vec<u8>>, Bound<Vec<u8>>);
+
 pub(crate) struct BtreeRangeIter<'a, K: RedbKey + 'a, V: RedbValue + 'a> {
     left: Option<RangeIterState<'a>>, // Exclusive. The previous element returned
     right: Option<RangeIterState<'a>>, // Exclusive. The previous element returned
     include_left: bool,               // left is inclusive, instead of exclusive
     include_right: bool,              // right is inclusive, instead of exclusive
+    // The queried range, which is only kept when pages are quarantined. If an end of the range
+    // falls in a quarantined page, that end is positioned on a neighbouring subtree instead, and
+    // the other end must be stopped by comparing its keys to the query
+    query: Option<QueryBounds>,
+    root: Option<PageNumber>,
+    // Number of entries in the whole btree
+    length: u64,
     manager: &'a TransactionalMemory,
     _key_type: PhantomData<K>,
     _value_type: PhantomData<V>,
@@ -420,7 +500,22 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeRangeIter<'a, K, V> {
     where
         K: 'a0,
     {
-        if let Some(root) = table_root {
+        let query = if matches!(manager.quarantine(), Some(quarantine) if !quarantine.is_empty()) {
+            let to_bytes = |bound: Bound<&KR>| match bound {
+                Bound::Included(k) => Bound::Included(K::as_bytes(k.borrow()).as_ref().to_vec()),
+                Bound::Excluded(k) => Bound::Excluded(K::as_bytes(k.borrow()).as_ref().to_vec()),
+                Bound::Unbounded => Bound::Unbounded,
+            };
+            Some((
+                to_bytes(query_range.start_bound()),
+                to_bytes(query_range.end_bound()),
+            ))
+        } else {
+            None
+        };
+        if let Some(root) = table_root.filter(|p| manager.check_quarantine(*p).is_none()) {
+            let length =
+                subtree_length(&manager.get_page(root)?, K::fixed_width(), V::fixed_width());
             let (include_left, left) = match query_range.start_bound() {
                 Bound::Included(k) => find_iter_left::<K, V>(
                     manager.get_page(root)?,
@@ -468,6 +563,9 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeRangeIter<'a, K, V> {
                 right,
                 include_left,
                 include_right,
+                query,
+                root: Some(root),
+                length,
                 manager,
                 _key_type: Default::default(),
                 _value_type: Default::default(),
@@ -478,6 +576,9 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeRangeIter<'a, K, V> {
                 right: None,
                 include_left: false,
                 include_right: false,
+                query,
+                root: None,
+                length: 0,
                 manager,
                 _key_type: Default::default(),
                 _value_type: Default::default(),
@@ -486,9 +587,130 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeRangeIter<'a, K, V> {
     }
 }
 
+impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> Clone for BtreeRangeIter<'a, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            left: self.left.clone(),
+            right: self.right.clone(),
+            include_left: self.include_left,
+            include_right: self.include_right,
+            query: self.query.clone(),
+            root: self.root,
+            length: self.length,
+            manager: self.manager,
+            _key_type: Default::default(),
+            _value_type: Default::default(),
+        }
+    }
+}
+
+impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeRangeIter<'a, K, V> {
+    // Returns the number of entries remaining. The entries which follow the left end, and those
+    // which precede the right end, are counted from the entry counts stored in the branches along
+    // each end's path, and together cover the whole btree plus the entries between the two ends.
+    // When pages are quarantined the entries beneath them are skipped, and an end may be stopped
+    // early by the query, so the remaining entries are instead counted by iterating over them
+    fn remaining(&self) -> usize {
+        if self.query.is_some() {
+            return self.clone().count();
+        }
+        match (&self.left, &self.right) {
+            (Some(left), Some(right)) => {
+                let covered = left.entries_remaining(false, self.include_left)
+                    + right.entries_remaining(true, self.include_right);
+                usize::try_from(covered.saturating_sub(self.length)).unwrap()
+            }
+            _ => 0,
+        }
+    }
+
+    // Skips the next n entries from the front of the range, and returns the number skipped. The
+    // position of the new front in the btree is found from the entry counts along the left end's
+    // path, and it is then found by descending from the root
+    pub(crate) fn skip_forward(&mut self, n: u64) -> Result<u64> {
+        if self.query.is_some() {
+            // Entries beneath quarantined pages are not returned, so visit every entry
+            let mut skipped = 0;
+            while skipped < n {
+                match self.next() {
+                    Some(Ok(_)) => skipped += 1,
+                    Some(Err(err)) => return Err(err),
+                    None => break,
+                }
+            }
+            return Ok(skipped);
+        }
+        let remaining = u64::try_from(self.remaining()).unwrap();
+        if n >= remaining {
+            self.left = None;
+            self.right = None;
+            return Ok(remaining);
+        }
+        if n == 0 {
+            return Ok(0);
+        }
+        let following = self
+            .left
+            .as_ref()
+            .unwrap()
+            .entries_remaining(false, self.include_left);
+        let position = self.length - following + n;
+        let root = self.manager.get_page(self.root.unwrap())?;
+        self.left = Some(find_iter_nth::<K, V>(root, None, position, self.manager)?);
+        self.include_left = true;
+
+        Ok(n)
+    }
+
+    // Returns the next entry, along with all the following entries in the range that are stored in
+    // the same leaf
+    pub(crate) fn next_leaf(&mut self) -> Option<Result<Vec<EntryGuard<'a, K, V>>>> {
+        let first = match self.next()? {
+            Ok(entry) => entry,
+            Err(err) => {
+                return Some(Err(err));
+            }
+        };
+        // Number of entries after the one just returned, in the leaf that it was read from
+        let mut leaf_remaining = if let Some(Leaf {
+            page,
+            fixed_key_size,
+            fixed_value_size,
+            entry,
+            ..
+        }) = &self.left
+        {
+            LeafAccessor::new(page.memory(), *fixed_key_size, *fixed_value_size).num_pairs()
+                - entry
+                - 1
+        } else {
+            0
+        };
+
+        let mut entries = vec![first];
+        while leaf_remaining > 0 {
+            match self.next() {
+                Some(Ok(entry)) => entries.push(entry),
+                Some(Err(err)) => {
+                    return Some(Err(err));
+                }
+                None => break,
+            }
+            leaf_remaining -= 1;
+        }
+
+        Some(Ok(entries))
+    }
+}
+
 impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> Iterator for BtreeRangeIter<'a, K, V> {
     type Item = Result<EntryGuard<'a, K, V>>;
 
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining();
+        (remaining, Some(remaining))
+    }
+
     fn next(&mut self) -> Option<Self::Item> {
         if let (
             Some(Leaf {
@@ -547,13 +769,23 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> Iterator for BtreeRangeIter<'a, K,
             }
 
             self.include_left = false;
-            if self.left.as_ref().unwrap().get_entry::<K, V>().is_some() {
-                return self.left.as_ref().map(|s| s.get_entry().unwrap()).map(Ok);
+            if let Some(entry) = self.left.as_ref().unwrap().get_entry::<K, V>() {
+                if matches!(&self.query, Some((_, end)) if past_end::<K>(entry.key_bytes(), end)) {
+                    self.left = None;
+                    self.right = None;
+                    return None;
+                }
+                return Some(Ok(entry));
             }
         }
     }
 }
 
+// Once either end of the iterator has moved past the other, neither end is advanced again
+impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> FusedIterator for BtreeRangeIter<'a, K, V> {}
+
+impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> ExactSizeIterator for BtreeRangeIter<'a, K, V> {}
+
 impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> DoubleEndedIterator for BtreeRangeIter<'a, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         if let (
@@ -613,13 +845,35 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> DoubleEndedIterator for BtreeRangeI
             }
 
             self.include_right = false;
-            if self.right.as_ref().unwrap().get_entry::<K, V>().is_some() {
-                return self.right.as_ref().map(|s| s.get_entry().unwrap()).map(Ok);
+            if let Some(entry) = self.right.as_ref().unwrap().get_entry::<K, V>() {
+                if matches!(&self.query, Some((start, _)) if before_start::<K>(entry.key_bytes(), start))
+                {
+                    self.left = None;
+                    self.right = None;
+                    return None;
+                }
+                return Some(Ok(entry));
             }
         }
     }
 }
 
+fn past_end<K: RedbKey>(key: &[u8], end: &Bound<Vec<u8>>) -> bool {
+    match end {
+        Bound::Included(end) => K::compare(key, end).is_gt(),
+        Bound::Excluded(end) => K::compare(key, end).is_ge(),
+        Bound::Unbounded => false,
+    }
+}
+
+fn before_start<K: RedbKey>(key: &[u8], start: &Bound<Vec<u8>>) -> bool {
+    match start {
+        Bound::Included(start) => K::compare(key, start).is_lt(),
+        Bound::Excluded(start) => K::compare(key, start).is_le(),
+        Bound::Unbounded => false,
+    }
+}
+
 fn find_iter_unbounded<'a, K: RedbKey, V: RedbValue>(
     page: PageImpl<'a>,
     mut parent: Option<Box<RangeIterState<'a>>>,
@@ -647,7 +901,6 @@ fn find_iter_unbounded<'a, K: RedbKey, V: RedbValue>(
                 0
             };
             let child_page_number = accessor.child_page(child_index).unwrap();
-            let child_page = manager.get_page(child_page_number)?;
             let direction = if reverse { -1isize } else { 1 };
             parent = Some(Box::new(Internal {
                 page,
@@ -658,12 +911,57 @@ fn find_iter_unbounded<'a, K: RedbKey, V: RedbValue>(
                     .unwrap(),
                 parent,
             }));
+            if manager.check_quarantine(child_page_number).is_some() {
+                // Position on the sibling, so that the first transition enters it
+                return Ok(parent.map(|x| *x));
+            }
+            let child_page = manager.get_page(child_page_number)?;
             find_iter_unbounded::<K, V>(child_page, parent, reverse, manager)
         }
         _ => unreachable!(),
     }
 }
 
+// Returns the state positioned on the entry with index n in the btree, counting from zero
+fn find_iter_nth<'a, K: RedbKey, V: RedbValue>(
+    page: PageImpl<'a>,
+    mut parent: Option<Box<RangeIterState<'a>>>,
+    mut n: u64,
+    manager: &'a TransactionalMemory,
+) -> Result<RangeIterState<'a>> {
+    let node_mem = page.memory();
+    match node_mem[0] {
+        LEAF => Ok(Leaf {
+            page,
+            fixed_key_size: K::fixed_width(),
+            fixed_value_size: V::fixed_width(),
+            entry: n.try_into().unwrap(),
+            parent,
+        }),
+        BRANCH => {
+            let accessor = BranchAccessor::new(&page, K::fixed_width());
+            let mut child_index = 0;
+            while n >= accessor.child_length(child_index).unwrap() {
+                n -= accessor.child_length(child_index).unwrap();
+                child_index += 1;
+            }
+            let child_page_number = accessor.child_page(child_index).unwrap();
+            if child_index < accessor.count_children() - 1 {
+                parent = Some(Box::new(Internal {
+                    page,
+                    fixed_key_size: K::fixed_width(),
+                    fixed_value_size: V::fixed_width(),
+                    child: child_index + 1,
+                    parent,
+                }));
+            }
+            let child_page = manager.get_page(child_page_number)?;
+            find_iter_nth::<K, V>(child_page, parent, n, manager)
+        }
+        _ => unreachable!(),
+    }
+}
+
 // Returns a bool indicating whether the first entry pointed to by the state is included in the
 // queried range
 fn find_iter_left<'a, K: RedbKey, V: RedbValue>(
@@ -698,7 +996,6 @@ fn find_iter_left<'a, K: RedbKey, V: RedbValue>(
         BRANCH => {
             let accessor = BranchAccessor::new(&page, K::fixed_width());
             let (child_index, child_page_number) = accessor.child_for_key::<K>(query);
-            let child_page = manager.get_page(child_page_number)?;
             if child_index < accessor.count_children() - 1 {
                 parent = Some(Box::new(Internal {
                     page,
@@ -708,6 +1005,11 @@ fn find_iter_left<'a, K: RedbKey, V: RedbValue>(
                     parent,
                 }));
             }
+            if manager.check_quarantine(child_page_number).is_some() {
+                // Position on the next sibling, excluding the parent state itself
+                return Ok((false, parent.map(|x| *x)));
+            }
+            let child_page = manager.get_page(child_page_number)?;
             find_iter_left::<K, V>(child_page, parent, query, include_query, manager)
         }
         _ => unreachable!(),
@@ -746,7 +1048,6 @@ fn find_iter_right<'a, K: RedbKey, V: RedbValue>(
         BRANCH => {
             let accessor = BranchAccessor::new(&page, K::fixed_width());
             let (child_index, child_page_number) = accessor.child_for_key::<K>(query);
-            let child_page = manager.get_page(child_page_number)?;
             if child_index > 0 && accessor.child_page(child_index - 1).is_some() {
                 parent = Some(Box::new(Internal {
                     page,
@@ -756,6 +1057,11 @@ fn find_iter_right<'a, K: RedbKey, V: RedbValue>(
                     parent,
                 }));
             }
+            if manager.check_quarantine(child_page_number).is_some() {
+                // Position on the previous sibling, excluding the parent state itself
+                return Ok((false, parent.map(|x| *x)));
+            }
+            let child_page = manager.get_page(child_page_number)?;
             find_iter_right::<K, V>(child_page, parent, query, include_query, manager)
         }
         _ => unreachable!(),
@@ -1,6 +1,6 @@
 use crate::tree_store::btree_base::{
-    branch_checksum, leaf_checksum, BranchAccessor, BranchBuilder, BranchMutator, Checksum,
-    FreePolicy, LeafAccessor, LeafBuilder, LeafMutator, BRANCH, LEAF,
+    branch_checksum, leaf_checksum, subtree_length, BranchAccessor, BranchBuilder, BranchMutator,
+    Checksum, FillPolicy, FreePolicy, LeafAccessor, LeafBuilder, LeafMutator, BRANCH, LEAF,
 };
 use crate::tree_store::btree_mutator::DeletionResult::{
     DeletedBranch, DeletedLeaf, PartialBranch, PartialLeaf, Subtree,
@@ -9,13 +9,15 @@ use crate::tree_store::page_store::{Page, PageImpl};
 use crate::tree_store::{AccessGuardMut, PageNumber, TransactionalMemory};
 use crate::types::{RedbKey, RedbValue};
 use crate::{AccessGuard, Result};
-use std::cmp::{max, min};
+use std::cmp::{max, min, Ordering};
 use std::marker::PhantomData;
+use std::ops::Bound;
 
+// Subtrees are returned with their checksum, and the number of entries they contain
 #[derive(Debug)]
 enum DeletionResult {
     // A proper subtree
-    Subtree(PageNumber, Checksum),
+    Subtree(PageNumber, Checksum, u64),
     // A leaf with zero children
     DeletedLeaf,
     // A leaf with fewer entries than desired
@@ -23,7 +25,7 @@ enum DeletionResult {
     // A branch page subtree with fewer children than desired
     PartialBranch(PageNumber, Checksum),
     // Indicates that the branch node was deleted, and includes the only remaining child
-    DeletedBranch(PageNumber, Checksum),
+    DeletedBranch(PageNumber, Checksum, u64),
 }
 
 struct InsertionResult<'a, V: RedbValue> {
@@ -31,8 +33,10 @@ struct InsertionResult<'a, V: RedbValue> {
     new_root: PageNumber,
     // checksum of the root page
     root_checksum: Checksum,
+    // number of entries beneath the root page
+    root_length: u64,
     // Following sibling, if the root had to be split
-    additional_sibling: Option<(Vec<u8>, PageNumber, Checksum)>,
+    additional_sibling: Option<(Vec<u8>, PageNumber, Checksum, u64)>,
     // The inserted value for .insert_reserve() to use
     inserted_value: AccessGuardMut<'a, V>,
     // The previous value, if any
@@ -44,6 +48,7 @@ pub(crate) struct MutateHelper<'a, 'b, K: RedbKey, V: RedbValue> {
     free_policy: FreePolicy,
     mem: &'a TransactionalMemory,
     freed: &'b mut Vec<PageNumber>,
+    fill_policy: FillPolicy,
     _key_type: PhantomData<K>,
     _value_type: PhantomData<V>,
 }
@@ -60,11 +65,16 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
             free_policy,
             mem,
             freed,
+            fill_policy: FillPolicy::default(),
             _key_type: Default::default(),
             _value_type: Default::default(),
         }
     }
 
+    pub(crate) fn set_fill_policy(&mut self, fill_policy: FillPolicy) {
+        self.fill_policy = fill_policy;
+    }
+
     // TODO: can we remove this method now that delete is safe?
     pub(crate) fn safe_delete(
         &mut self,
@@ -79,7 +89,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
             let (deletion_result, found) =
                 self.delete_helper(self.mem.get_page(p)?, checksum, K::as_bytes(key).as_ref())?;
             let new_root = match deletion_result {
-                Subtree(page, checksum) => Some((page, checksum)),
+                Subtree(page, checksum, _) => Some((page, checksum)),
                 DeletedLeaf => None,
                 PartialLeaf { deleted_pair } => {
                     let page = self.mem.get_page(p)?;
@@ -96,7 +106,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     Some((page.get_page_number(), self.checksum_helper(&page)))
                 }
                 PartialBranch(page_number, checksum) => Some((page_number, checksum)),
-                DeletedBranch(remaining_child, checksum) => Some((remaining_child, checksum)),
+                DeletedBranch(remaining_child, checksum, _) => Some((remaining_child, checksum)),
             };
             *self.root = new_root;
             Ok(found)
@@ -119,11 +129,13 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                 V::as_bytes(value).as_ref(),
             )?;
 
-            let new_root = if let Some((key, page2, page2_checksum)) = result.additional_sibling {
+            let new_root = if let Some((key, page2, page2_checksum, page2_length)) =
+                result.additional_sibling
+            {
                 let mut builder = BranchBuilder::new(self.mem, 2, K::fixed_width());
-                builder.push_child(result.new_root, result.root_checksum);
+                builder.push_child(result.new_root, result.root_checksum, result.root_length);
                 builder.push_key(&key);
-                builder.push_child(page2, page2_checksum);
+                builder.push_child(page2, page2_checksum, page2_length);
                 let new_page = builder.build()?;
                 (new_page.get_page_number(), self.checksum_helper(&new_page))
             } else {
@@ -183,10 +195,12 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                         Ok(InsertionResult {
                             new_root: new_page_number,
                             root_checksum: new_page_checksum,
+                            root_length: 1,
                             additional_sibling: Some((
                                 key.to_vec(),
                                 page.get_page_number(),
                                 page_checksum,
+                                1,
                             )),
                             inserted_value: guard,
                             old_value: None,
@@ -196,10 +210,12 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                         Ok(InsertionResult {
                             new_root: page.get_page_number(),
                             root_checksum: page_checksum,
+                            root_length: 1,
                             additional_sibling: Some((
                                 split_key,
                                 new_page_number,
                                 new_page_checksum,
+                                1,
                             )),
                             inserted_value: guard,
                             old_value: None,
@@ -236,10 +252,12 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     let offset = new_page_accessor.offset_of_value(position).unwrap();
                     drop(new_page_accessor);
                     let new_checksum = self.checksum_helper(&page_mut);
+                    let new_length = self.length_helper(&page_mut);
                     let guard = AccessGuardMut::new::<K>(page_mut, offset, value.len(), self.mem);
                     return Ok(InsertionResult {
                         new_root: page_number,
                         root_checksum: new_checksum,
+                        root_length: new_length,
                         additional_sibling: None,
                         inserted_value: guard,
                         old_value: existing_value,
@@ -290,6 +308,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
 
                     let new_page_number = new_page.get_page_number();
                     let new_page_checksum = self.checksum_helper(&new_page);
+                    let new_page_length = self.length_helper(&new_page);
                     let accessor =
                         LeafAccessor::new(new_page.memory(), K::fixed_width(), V::fixed_width());
                     let offset = accessor.offset_of_value(position).unwrap();
@@ -298,12 +317,14 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     InsertionResult {
                         new_root: new_page_number,
                         root_checksum: new_page_checksum,
+                        root_length: new_page_length,
                         additional_sibling: None,
                         inserted_value: guard,
                         old_value: existing_value,
                     }
                 } else {
-                    let (new_page1, split_key, new_page2) = builder.build_split()?;
+                    let (new_page1, split_key, new_page2) =
+                        builder.build_split(self.fill_policy)?;
                     let split_key = split_key.to_vec();
                     let page_number = page.get_page_number();
                     let existing_value = if found {
@@ -328,8 +349,10 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
 
                     let new_page_number = new_page1.get_page_number();
                     let new_page_checksum = self.checksum_helper(&new_page1);
+                    let new_page_length = self.length_helper(&new_page1);
                     let new_page_number2 = new_page2.get_page_number();
                     let new_page2_checksum = self.checksum_helper(&new_page2);
+                    let new_page2_length = self.length_helper(&new_page2);
                     let accessor =
                         LeafAccessor::new(new_page1.memory(), K::fixed_width(), V::fixed_width());
                     let division = accessor.num_pairs();
@@ -354,7 +377,13 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     InsertionResult {
                         new_root: new_page_number,
                         root_checksum: new_page_checksum,
-                        additional_sibling: Some((split_key, new_page_number2, new_page2_checksum)),
+                        root_length: new_page_length,
+                        additional_sibling: Some((
+                            split_key,
+                            new_page_number2,
+                            new_page2_checksum,
+                            new_page2_length,
+                        )),
                         inserted_value: guard,
                         old_value: existing_value,
                     }
@@ -364,6 +393,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                 let accessor = BranchAccessor::new(&page, K::fixed_width());
                 let (child_index, child_page) = accessor.child_for_key::<K>(key);
                 let child_checksum = accessor.child_checksum(child_index).unwrap();
+                let child_length = accessor.child_length(child_index).unwrap();
                 let sub_result =
                     self.insert_helper(self.mem.get_page(child_page)?, child_checksum, key, value)?;
 
@@ -372,11 +402,13 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     // when checksums are disabled
                     if sub_result.new_root == child_page
                         && sub_result.root_checksum == child_checksum
+                        && sub_result.root_length == child_length
                     {
                         // NO-OP. One of our descendants is uncommitted, so there was no change
                         return Ok(InsertionResult {
                             new_root: page.get_page_number(),
                             root_checksum: self.checksum_helper(&page),
+                            root_length: self.length_helper(&page),
                             additional_sibling: None,
                             inserted_value: sub_result.inserted_value,
                             old_value: sub_result.old_value,
@@ -391,9 +423,11 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                             sub_result.new_root,
                             sub_result.root_checksum,
                         );
+                        mutator.write_child_length(child_index, sub_result.root_length);
                         return Ok(InsertionResult {
                             new_root: mutpage.get_page_number(),
                             root_checksum: self.checksum_helper(&mutpage),
+                            root_length: self.length_helper(&mutpage),
                             additional_sibling: None,
                             inserted_value: sub_result.inserted_value,
                             old_value: sub_result.old_value,
@@ -405,34 +439,44 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                 let mut builder =
                     BranchBuilder::new(self.mem, accessor.count_children() + 1, K::fixed_width());
                 if child_index == 0 {
-                    builder.push_child(sub_result.new_root, sub_result.root_checksum);
-                    if let Some((ref index_key2, page2, page2_checksum)) =
+                    builder.push_child(
+                        sub_result.new_root,
+                        sub_result.root_checksum,
+                        sub_result.root_length,
+                    );
+                    if let Some((ref index_key2, page2, page2_checksum, page2_length)) =
                         sub_result.additional_sibling
                     {
                         builder.push_key(index_key2);
-                        builder.push_child(page2, page2_checksum);
+                        builder.push_child(page2, page2_checksum, page2_length);
                     }
                 } else {
                     builder.push_child(
                         accessor.child_page(0).unwrap(),
                         accessor.child_checksum(0).unwrap(),
+                        accessor.child_length(0).unwrap(),
                     );
                 }
                 for i in 1..accessor.count_children() {
                     if let Some(key) = accessor.key(i - 1) {
                         builder.push_key(key);
                         if i == child_index {
-                            builder.push_child(sub_result.new_root, sub_result.root_checksum);
-                            if let Some((ref index_key2, page2, page2_checksum)) =
+                            builder.push_child(
+                                sub_result.new_root,
+                                sub_result.root_checksum,
+                                sub_result.root_length,
+                            );
+                            if let Some((ref index_key2, page2, page2_checksum, page2_length)) =
                                 sub_result.additional_sibling
                             {
                                 builder.push_key(index_key2);
-                                builder.push_child(page2, page2_checksum);
+                                builder.push_child(page2, page2_checksum, page2_length);
                             }
                         } else {
                             builder.push_child(
                                 accessor.child_page(i).unwrap(),
                                 accessor.child_checksum(i).unwrap(),
+                                accessor.child_length(i).unwrap(),
                             );
                         }
                     } else {
@@ -441,14 +485,17 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                 }
 
                 let result = if builder.should_split() {
-                    let (new_page1, split_key, new_page2) = builder.build_split()?;
+                    let (new_page1, split_key, new_page2) =
+                        builder.build_split(self.fill_policy)?;
                     InsertionResult {
                         new_root: new_page1.get_page_number(),
                         root_checksum: self.checksum_helper(&new_page1),
+                        root_length: self.length_helper(&new_page1),
                         additional_sibling: Some((
                             split_key.to_vec(),
                             new_page2.get_page_number(),
                             self.checksum_helper(&new_page2),
+                            self.length_helper(&new_page2),
                         )),
                         inserted_value: sub_result.inserted_value,
                         old_value: sub_result.old_value,
@@ -458,6 +505,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     InsertionResult {
                         new_root: new_page.get_page_number(),
                         root_checksum: self.checksum_helper(&new_page),
+                        root_length: self.length_helper(&new_page),
                         additional_sibling: None,
                         inserted_value: sub_result.inserted_value,
                         old_value: sub_result.old_value,
@@ -484,7 +532,8 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
         let accessor = LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
         let (position, found) = accessor.position::<K>(key);
         if !found {
-            return Ok((Subtree(page.get_page_number(), checksum), None));
+            let length = u64::try_from(accessor.num_pairs()).unwrap();
+            return Ok((Subtree(page.get_page_number(), checksum, length), None));
         }
         let new_kv_bytes = accessor.length_of_pairs(0, accessor.num_pairs())
             - accessor.length_of_pairs(position, position + 1);
@@ -509,6 +558,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
             let mut mutator = LeafMutator::new(&mut temp, K::fixed_width(), V::fixed_width());
             mutator.remove(position);
             let checksum = self.checksum_helper(&temp);
+            let length = self.length_helper(&temp);
             let temp_page_number = temp.get_page_number();
             drop(temp);
             self.mem.free(temp_page_number);
@@ -521,14 +571,14 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                 K::fixed_width(),
                 self.mem,
             );
-            return Ok((Subtree(page_number, checksum), Some(guard)));
+            return Ok((Subtree(page_number, checksum, length), Some(guard)));
         }
 
         let result = if accessor.num_pairs() == 1 {
             DeletedLeaf
-        } else if new_required_bytes < self.mem.get_page_size() / 3 {
-            // Merge when less than 33% full. Splits occur when a page is full and produce two 50%
-            // full pages, so we use 33% instead of 50% to avoid oscillating
+        } else if new_required_bytes < self.fill_policy.merge_threshold(self.mem.get_page_size()) {
+            // Merge when less than 33% full, by default. Splits occur when a page is full and
+            // produce two 50% full pages, so we use 33% instead of 50% to avoid oscillating
             PartialLeaf {
                 deleted_pair: position,
             }
@@ -547,7 +597,11 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                 builder.push(entry.key(), entry.value());
             }
             let new_page = builder.build()?;
-            Subtree(new_page.get_page_number(), self.checksum_helper(&new_page))
+            Subtree(
+                new_page.get_page_number(),
+                self.checksum_helper(&new_page),
+                self.length_helper(&new_page),
+            )
         };
         let free_on_drop = if !uncommitted || matches!(self.free_policy, FreePolicy::Never) {
             // Won't be freed until the end of the transaction, so returning the page
@@ -569,19 +623,24 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
     }
 
     fn finalize_branch_builder(&self, builder: BranchBuilder<'_, '_>) -> Result<DeletionResult> {
-        let result = if let Some((only_child, checksum)) = builder.to_single_child() {
-            DeletedBranch(only_child, checksum)
+        let result = if let Some((only_child, checksum, length)) = builder.to_single_child() {
+            DeletedBranch(only_child, checksum, length)
         } else {
             // TODO: can we optimize away this page allocation?
             // The PartialInternal gets returned, and then the caller has to merge it immediately
             let new_page = builder.build()?;
             let accessor = BranchAccessor::new(&new_page, K::fixed_width());
-            // Merge when less than 33% full. Splits occur when a page is full and produce two 50%
-            // full pages, so we use 33% instead of 50% to avoid oscillating
-            if accessor.total_length() < self.mem.get_page_size() / 3 {
+            // Merge when less than 33% full, by default. Splits occur when a page is full and
+            // produce two 50% full pages, so we use 33% instead of 50% to avoid oscillating
+            if accessor.total_length() < self.fill_policy.merge_threshold(self.mem.get_page_size())
+            {
                 PartialBranch(new_page.get_page_number(), self.checksum_helper(&new_page))
             } else {
-                Subtree(new_page.get_page_number(), self.checksum_helper(&new_page))
+                Subtree(
+                    new_page.get_page_number(),
+                    self.checksum_helper(&new_page),
+                    self.length_helper(&new_page),
+                )
             }
         };
         Ok(result)
@@ -589,12 +648,21 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
 
     fn checksum_helper<T: Page>(&self, page: &T) -> Checksum {
         match page.memory()[0] {
-            LEAF => leaf_checksum(page, K::fixed_width(), V::fixed_width()),
-            BRANCH => branch_checksum(page, K::fixed_width()),
+            LEAF => leaf_checksum(
+                page,
+                K::fixed_width(),
+                V::fixed_width(),
+                self.mem.checksum_algorithm(),
+            ),
+            BRANCH => branch_checksum(page, K::fixed_width(), self.mem.checksum_algorithm()),
             _ => unreachable!(),
         }
     }
 
+    fn length_helper<T: Page>(&self, page: &T) -> u64 {
+        subtree_length(page, K::fixed_width(), V::fixed_width())
+    }
+
     fn delete_branch_helper(
         &mut self,
         page: PageImpl<'a>,
@@ -608,33 +676,46 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
         let (result, found) =
             self.delete_helper(self.mem.get_page(child_page_number)?, child_checksum, key)?;
         if found.is_none() {
-            return Ok((Subtree(original_page_number, checksum), None));
+            let length = accessor.subtree_length();
+            return Ok((Subtree(original_page_number, checksum, length), None));
         }
-        if let Subtree(new_child, new_child_checksum) = result {
-            let (result_page, result_checksum) = if self.mem.uncommitted(original_page_number) {
+        if let Subtree(new_child, new_child_checksum, new_child_length) = result {
+            let (result_page, result_checksum, result_length) = if self
+                .mem
+                .uncommitted(original_page_number)
+            {
                 drop(page);
                 let mut mutpage = self.mem.get_page_mut(original_page_number)?;
                 let mut mutator = BranchMutator::new(&mut mutpage);
                 mutator.write_child_page(child_index, new_child, new_child_checksum);
-                (original_page_number, self.checksum_helper(&mutpage))
+                mutator.write_child_length(child_index, new_child_length);
+                (
+                    original_page_number,
+                    self.checksum_helper(&mutpage),
+                    self.length_helper(&mutpage),
+                )
             } else {
                 let mut builder =
                     BranchBuilder::new(self.mem, accessor.count_children(), K::fixed_width());
                 builder.push_all(&accessor);
-                builder.replace_child(child_index, new_child, new_child_checksum);
+                builder.replace_child(child_index, new_child, new_child_checksum, new_child_length);
                 let new_page = builder.build()?;
                 self.free_policy
                     .conditional_free(original_page_number, self.freed, self.mem);
-                (new_page.get_page_number(), self.checksum_helper(&new_page))
+                (
+                    new_page.get_page_number(),
+                    self.checksum_helper(&new_page),
+                    self.length_helper(&new_page),
+                )
             };
-            return Ok((Subtree(result_page, result_checksum), found));
+            return Ok((Subtree(result_page, result_checksum, result_length), found));
         }
 
         // Child is requesting to be merged with a sibling
         let mut builder = BranchBuilder::new(self.mem, accessor.count_children(), K::fixed_width());
 
         let final_result = match result {
-            Subtree(_, _) => {
+            Subtree(..) => {
                 // Handled in the if above
                 unreachable!();
             }
@@ -646,6 +727,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     builder.push_child(
                         accessor.child_page(i).unwrap(),
                         accessor.child_checksum(i).unwrap(),
+                        accessor.child_length(i).unwrap(),
                     );
                 }
                 let end = if child_index == accessor.count_children() - 1 {
@@ -696,6 +778,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                         child_index,
                         new_page.get_page_number(),
                         self.checksum_helper(&new_page),
+                        self.length_helper(&new_page),
                     );
 
                     let result = self.finalize_branch_builder(builder)?;
@@ -715,6 +798,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     }
                     let page_number = accessor.child_page(i).unwrap();
                     let page_checksum = accessor.child_checksum(i).unwrap();
+                    let page_length = accessor.child_length(i).unwrap();
                     if i == merge_with {
                         let mut child_builder = LeafBuilder::new(
                             self.mem,
@@ -733,21 +817,25 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                                 .push_all_except(&partial_child_accessor, Some(deleted_pair));
                         }
                         if child_builder.should_split() {
-                            let (new_page1, split_key, new_page2) = child_builder.build_split()?;
+                            let (new_page1, split_key, new_page2) =
+                                child_builder.build_split(self.fill_policy)?;
                             builder.push_key(split_key);
                             builder.push_child(
                                 new_page1.get_page_number(),
                                 self.checksum_helper(&new_page1),
+                                self.length_helper(&new_page1),
                             );
                             builder.push_child(
                                 new_page2.get_page_number(),
                                 self.checksum_helper(&new_page2),
+                                self.length_helper(&new_page2),
                             );
                         } else {
                             let new_page = child_builder.build()?;
                             builder.push_child(
                                 new_page.get_page_number(),
                                 self.checksum_helper(&new_page),
+                                self.length_helper(&new_page),
                             );
                         }
 
@@ -756,7 +844,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                             builder.push_key(accessor.key(merged_key_index).unwrap());
                         }
                     } else {
-                        builder.push_child(page_number, page_checksum);
+                        builder.push_child(page_number, page_checksum, page_length);
                         if i < accessor.count_children() - 1 {
                             builder.push_key(accessor.key(i).unwrap());
                         }
@@ -774,7 +862,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
 
                 result
             }
-            DeletedBranch(only_grandchild, grandchild_checksum) => {
+            DeletedBranch(only_grandchild, grandchild_checksum, grandchild_length) => {
                 let merge_with = if child_index == 0 { 1 } else { child_index - 1 };
                 let merge_with_page = self
                     .mem
@@ -787,6 +875,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     }
                     let page_number = accessor.child_page(i).unwrap();
                     let page_checksum = accessor.child_checksum(i).unwrap();
+                    let page_length = accessor.child_length(i).unwrap();
                     if i == merge_with {
                         let mut child_builder = BranchBuilder::new(
                             self.mem,
@@ -795,30 +884,42 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                         );
                         let separator_key = accessor.key(min(child_index, merge_with)).unwrap();
                         if child_index < merge_with {
-                            child_builder.push_child(only_grandchild, grandchild_checksum);
+                            child_builder.push_child(
+                                only_grandchild,
+                                grandchild_checksum,
+                                grandchild_length,
+                            );
                             child_builder.push_key(separator_key);
                         }
                         child_builder.push_all(&merge_with_accessor);
                         if child_index > merge_with {
                             child_builder.push_key(separator_key);
-                            child_builder.push_child(only_grandchild, grandchild_checksum);
+                            child_builder.push_child(
+                                only_grandchild,
+                                grandchild_checksum,
+                                grandchild_length,
+                            );
                         }
                         if child_builder.should_split() {
-                            let (new_page1, separator, new_page2) = child_builder.build_split()?;
+                            let (new_page1, separator, new_page2) =
+                                child_builder.build_split(self.fill_policy)?;
                             builder.push_child(
                                 new_page1.get_page_number(),
                                 self.checksum_helper(&new_page1),
+                                self.length_helper(&new_page1),
                             );
                             builder.push_key(separator);
                             builder.push_child(
                                 new_page2.get_page_number(),
                                 self.checksum_helper(&new_page2),
+                                self.length_helper(&new_page2),
                             );
                         } else {
                             let new_page = child_builder.build()?;
                             builder.push_child(
                                 new_page.get_page_number(),
                                 self.checksum_helper(&new_page),
+                                self.length_helper(&new_page),
                             );
                         }
 
@@ -827,7 +928,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                             builder.push_key(accessor.key(merged_key_index).unwrap());
                         }
                     } else {
-                        builder.push_child(page_number, page_checksum);
+                        builder.push_child(page_number, page_checksum, page_length);
                         if i < accessor.count_children() - 1 {
                             builder.push_key(accessor.key(i).unwrap());
                         }
@@ -858,6 +959,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     }
                     let page_number = accessor.child_page(i).unwrap();
                     let page_checksum = accessor.child_checksum(i).unwrap();
+                    let page_length = accessor.child_length(i).unwrap();
                     if i == merge_with {
                         let mut child_builder = BranchBuilder::new(
                             self.mem,
@@ -876,21 +978,25 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                             child_builder.push_all(&partial_child_accessor);
                         }
                         if child_builder.should_split() {
-                            let (new_page1, separator, new_page2) = child_builder.build_split()?;
+                            let (new_page1, separator, new_page2) =
+                                child_builder.build_split(self.fill_policy)?;
                             builder.push_child(
                                 new_page1.get_page_number(),
                                 self.checksum_helper(&new_page1),
+                                self.length_helper(&new_page1),
                             );
                             builder.push_key(separator);
                             builder.push_child(
                                 new_page2.get_page_number(),
                                 self.checksum_helper(&new_page2),
+                                self.length_helper(&new_page2),
                             );
                         } else {
                             let new_page = child_builder.build()?;
                             builder.push_child(
                                 new_page.get_page_number(),
                                 self.checksum_helper(&new_page),
+                                self.length_helper(&new_page),
                             );
                         }
 
@@ -899,7 +1005,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                             builder.push_key(accessor.key(merged_key_index).unwrap());
                         }
                     } else {
-                        builder.push_child(page_number, page_checksum);
+                        builder.push_child(page_number, page_checksum, page_length);
                         if i < accessor.count_children() - 1 {
                             builder.push_key(accessor.key(i).unwrap());
                         }
@@ -941,4 +1047,370 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
             _ => unreachable!(),
         }
     }
+
+    // Removes every entry in the range, and returns how many were removed. Subtrees which lie
+    // entirely within the range are freed without being rewritten, so only the pages along the
+    // boundaries of the range are modified
+    pub(crate) fn delete_range(&mut self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<u64> {
+        let (root, _) = if let Some(root) = *self.root {
+            root
+        } else {
+            return Ok(0);
+        };
+        let mut height = 0;
+        let mut page = self.mem.get_page(root)?;
+        while page.memory()[0] == BRANCH {
+            let child = BranchAccessor::new(&page, K::fixed_width())
+                .child_page(0)
+                .unwrap();
+            page = self.mem.get_page(child)?;
+            height += 1;
+        }
+        drop(page);
+
+        let mut removed = 0;
+        let range = (start, end);
+        if let Some(mut trees) =
+            self.delete_range_helper(root, height, None, None, &range, &mut removed)?
+        {
+            while trees.len() > 1 {
+                trees = self.build_range_branch(&trees)?;
+            }
+            *self.root = trees.pop().map(|tree| (tree.page, tree.checksum));
+        }
+
+        Ok(removed)
+    }
+
+    // Returns the subtrees which remain once the range is removed from the subtree rooted at
+    // `page_number`, or None if it contains no entries in the range. `lower` is an exclusive lower
+    // bound, and `upper` an inclusive upper bound, on the keys in the subtree. The returned
+    // subtrees are no taller than this one, and are in key order
+    fn delete_range_helper(
+        &mut self,
+        page_number: PageNumber,
+        height: usize,
+        lower: Option<&[u8]>,
+        upper: Option<&[u8]>,
+        range: &KeyRange,
+        removed: &mut u64,
+    ) -> Result<Option<Vec<RangeDeletionTree>>> {
+        let page = self.mem.get_page(page_number)?;
+        let trees = if height == 0 {
+            let accessor = LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+            let mut builder = LeafBuilder::new(
+                self.mem,
+                accessor.num_pairs(),
+                K::fixed_width(),
+                V::fixed_width(),
+            );
+            let mut kept = 0;
+            for i in 0..accessor.num_pairs() {
+                let entry = accessor.entry(i).unwrap();
+                if !range_contains::<K>(range, entry.key()) {
+                    builder.push(entry.key(), entry.value());
+                    kept += 1;
+                }
+            }
+            if kept == accessor.num_pairs() {
+                return Ok(None);
+            }
+            *removed += u64::try_from(accessor.num_pairs() - kept).unwrap();
+            if kept > 0 {
+                let new_page = builder.build()?;
+                vec![RangeDeletionTree {
+                    page: new_page.get_page_number(),
+                    checksum: self.checksum_helper(&new_page),
+                    length: u64::try_from(kept).unwrap(),
+                    height: 0,
+                    upper: upper.map(|x| x.to_vec()),
+                }]
+            } else {
+                vec![]
+            }
+        } else {
+            let accessor = BranchAccessor::new(&page, K::fixed_width());
+            let mut trees = vec![];
+            let mut changed = false;
+            for i in 0..accessor.count_children() {
+                let child_lower = if i == 0 { lower } else { accessor.key(i - 1) };
+                let child_upper = if i == accessor.count_children() - 1 {
+                    upper
+                } else {
+                    accessor.key(i)
+                };
+                let child = accessor.child_page(i).unwrap();
+                let unchanged = RangeDeletionTree {
+                    page: child,
+                    checksum: accessor.child_checksum(i).unwrap(),
+                    length: accessor.child_length(i).unwrap(),
+                    height: height - 1,
+                    upper: child_upper.map(|x| x.to_vec()),
+                };
+                if range_excludes::<K>(range, child_lower, child_upper) {
+                    trees.push(unchanged);
+                } else if range_covers::<K>(range, child_lower, child_upper) {
+                    *removed += self.free_subtree(child, height - 1)?;
+                    changed = true;
+                } else if let Some(remaining) = self.delete_range_helper(
+                    child,
+                    height - 1,
+                    child_lower,
+                    child_upper,
+                    range,
+                    removed,
+                )? {
+                    trees.extend(remaining);
+                    changed = true;
+                } else {
+                    trees.push(unchanged);
+                }
+            }
+            if !changed {
+                return Ok(None);
+            }
+            drop(accessor);
+            drop(page);
+            self.free_policy
+                .conditional_free(page_number, self.freed, self.mem);
+
+            return self.assemble_range_trees(trees, height).map(Some);
+        };
+
+        drop(page);
+        self.free_policy
+            .conditional_free(page_number, self.freed, self.mem);
+
+        Ok(Some(trees))
+    }
+
+    // Combines the subtrees which remain below a branch of the given height, so that they are all
+    // one level shorter than it, and then builds the branch. Returns a single subtree if there is
+    // not enough left to fill a branch
+    fn assemble_range_trees(
+        &mut self,
+        mut trees: Vec<RangeDeletionTree>,
+        height: usize,
+    ) -> Result<Vec<RangeDeletionTree>> {
+        // Subtrees along the boundaries of the range may have lost levels. Join each of them to
+        // a neighbour, so that all leaves remain at the same depth
+        while trees.len() > 1 {
+            let shallow =
+                if let Some(shallow) = trees.iter().position(|tree| tree.height + 1 < height) {
+                    shallow
+                } else {
+                    break;
+                };
+            let left = if shallow > 0 { shallow - 1 } else { 0 };
+            let right_tree = trees.remove(left + 1);
+            let left_tree = trees.remove(left);
+            let joined = self.join_range_trees(left_tree, right_tree)?;
+            trees.splice(left..left, joined);
+        }
+
+        if trees.len() > 1 {
+            self.build_range_branch(&trees)
+        } else {
+            Ok(trees)
+        }
+    }
+
+    // Joins two subtrees, where all the keys in `left` are less than those in `right`. Returns one
+    // subtree, or two if the taller subtree had to be split, of the same height as the taller one
+    // or one level taller if they have the same height
+    fn join_range_trees(
+        &mut self,
+        left: RangeDeletionTree,
+        right: RangeDeletionTree,
+    ) -> Result<Vec<RangeDeletionTree>> {
+        match left.height.cmp(&right.height) {
+            Ordering::Equal => self.build_range_branch(&[left, right]),
+            Ordering::Greater => {
+                // Attach `right` to the right edge of `left`
+                let page = self.mem.get_page(left.page)?;
+                let accessor = BranchAccessor::new(&page, K::fixed_width());
+                let mut children = branch_range_trees(&accessor, left.height);
+                children.last_mut().unwrap().upper = left.upper.clone();
+                drop(accessor);
+                drop(page);
+                if left.height - 1 == right.height {
+                    children.push(right);
+                } else {
+                    let last = children.pop().unwrap();
+                    children.extend(self.join_range_trees(last, right)?);
+                }
+                self.free_policy
+                    .conditional_free(left.page, self.freed, self.mem);
+                self.build_range_branch(&children)
+            }
+            Ordering::Less => {
+                // Attach `left` to the left edge of `right`
+                let page = self.mem.get_page(right.page)?;
+                let accessor = BranchAccessor::new(&page, K::fixed_width());
+                let mut children = branch_range_trees(&accessor, right.height);
+                children.last_mut().unwrap().upper = right.upper.clone();
+                drop(accessor);
+                drop(page);
+                if right.height - 1 == left.height {
+                    children.insert(0, left);
+                } else {
+                    let first = children.remove(0);
+                    let joined = self.join_range_trees(left, first)?;
+                    children.splice(0..0, joined);
+                }
+                self.free_policy
+                    .conditional_free(right.page, self.freed, self.mem);
+                self.build_range_branch(&children)
+            }
+        }
+    }
+
+    // Builds a branch over subtrees of the same height, splitting it if it does not fit in a page
+    fn build_range_branch(&self, trees: &[RangeDeletionTree]) -> Result<Vec<RangeDeletionTree>> {
+        let height = trees[0].height + 1;
+        let mut builder = BranchBuilder::new(self.mem, trees.len(), K::fixed_width());
+        for tree in trees {
+            debug_assert_eq!(tree.height + 1, height);
+            builder.push_child(tree.page, tree.checksum, tree.length);
+        }
+        for tree in &trees[..(trees.len() - 1)] {
+            builder.push_key(tree.upper.as_ref().unwrap());
+        }
+        let upper = trees.last().unwrap().upper.clone();
+        if builder.should_split() {
+            let (page1, split_key, page2) = builder.build_split(self.fill_policy)?;
+            Ok(vec![
+                RangeDeletionTree {
+                    page: page1.get_page_number(),
+                    checksum: self.checksum_helper(&page1),
+                    length: self.length_helper(&page1),
+                    height,
+                    upper: Some(split_key.to_vec()),
+                },
+                RangeDeletionTree {
+                    page: page2.get_page_number(),
+                    checksum: self.checksum_helper(&page2),
+                    length: self.length_helper(&page2),
+                    height,
+                    upper,
+                },
+            ])
+        } else {
+            let page = builder.build()?;
+            Ok(vec![RangeDeletionTree {
+                page: page.get_page_number(),
+                checksum: self.checksum_helper(&page),
+                length: self.length_helper(&page),
+                height,
+                upper,
+            }])
+        }
+    }
+
+    // Frees every page of the subtree, and returns the number of entries it contained
+    fn free_subtree(&mut self, page_number: PageNumber, height: usize) -> Result<u64> {
+        let page = self.mem.get_page(page_number)?;
+        let entries = if height == 0 {
+            let accessor = LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+            u64::try_from(accessor.num_pairs()).unwrap()
+        } else {
+            let accessor = BranchAccessor::new(&page, K::fixed_width());
+            let children: Vec<PageNumber> = (0..accessor.count_children())
+                .map(|i| accessor.child_page(i).unwrap())
+                .collect();
+            drop(accessor);
+            let mut entries = 0;
+            for child in children {
+                entries += self.free_subtree(child, height - 1)?;
+            }
+            entries
+        };
+        drop(page);
+        self.free_policy
+            .conditional_free(page_number, self.freed, self.mem);
+
+        Ok(entries)
+    }
+}
+
+type KeyRange<'r> = (Bound<&'r [u8]>, Bound<&'r [u8]>);
+
+// A subtree which remains after part of a range was deleted from the btree. `upper` is greater
+// than or equal to every key in the subtree, and less than every key which follows it. It is only
+// None for the last subtree of the btree
+struct RangeDeletionTree {
+    page: PageNumber,
+    checksum: Checksum,
+    length: u64,
+    height: usize,
+    upper: Option<Vec<u8>>,
+}
+
+// Returns the children of a branch of the given height. The upper bound of the last child is
+// left unset, since it is not stored in the branch
+fn branch_range_trees<T: Page>(
+    accessor: &BranchAccessor<'_, '_, T>,
+    height: usize,
+) -> Vec<RangeDeletionTree> {
+    (0..accessor.count_children())
+        .map(|i| RangeDeletionTree {
+            page: accessor.child_page(i).unwrap(),
+            checksum: accessor.child_checksum(i).unwrap(),
+            length: accessor.child_length(i).unwrap(),
+            height: height - 1,
+            upper: accessor.key(i).map(|x| x.to_vec()),
+        })
+        .collect()
+}
+
+fn before_start<K: RedbKey>(range: &KeyRange, key: &[u8]) -> bool {
+    match range.0 {
+        Bound::Included(start) => K::compare(key, start).is_lt(),
+        Bound::Excluded(start) => K::compare(key, start).is_le(),
+        Bound::Unbounded => false,
+    }
+}
+
+fn after_end<K: RedbKey>(range: &KeyRange, key: &[u8]) -> bool {
+    match range.1 {
+        Bound::Included(end) => K::compare(key, end).is_gt(),
+        Bound::Excluded(end) => K::compare(key, end).is_ge(),
+        Bound::Unbounded => false,
+    }
+}
+
+fn range_contains<K: RedbKey>(range: &KeyRange, key: &[u8]) -> bool {
+    !before_start::<K>(range, key) && !after_end::<K>(range, key)
+}
+
+// Returns true if no key greater than `lower` and less than or equal to `upper` is in the range
+fn range_excludes<K: RedbKey>(
+    range: &KeyRange,
+    lower: Option<&[u8]>,
+    upper: Option<&[u8]>,
+) -> bool {
+    let below = matches!(upper, Some(upper) if before_start::<K>(range, upper));
+    let above = match (lower, range.1) {
+        (Some(lower), Bound::Included(end) | Bound::Excluded(end)) => {
+            K::compare(lower, end).is_ge()
+        }
+        _ => false,
+    };
+    below || above
+}
+
+// Returns true if every key greater than `lower` and less than or equal to `upper` is in the range
+fn range_covers<K: RedbKey>(range: &KeyRange, lower: Option<&[u8]>, upper: Option<&[u8]>) -> bool {
+    let start_covered = match (lower, range.0) {
+        (_, Bound::Unbounded) => true,
+        (Some(lower), Bound::Included(start) | Bound::Excluded(start)) => {
+            K::compare(lower, start).is_ge()
+        }
+        (None, _) => false,
+    };
+    let end_covered = match upper {
+        Some(upper) => !after_end::<K>(range, upper),
+        None => matches!(range.1, Bound::Unbounded),
+    };
+    start_covered && end_covered
 }
@@ -1,22 +1,33 @@
+mod archive;
 mod btree;
 mod btree_base;
+mod btree_diff;
 mod btree_iters;
 mod btree_mutator;
 mod page_store;
 mod table_tree;
+mod upgrade;
 
+pub(crate) use archive::{read_archive, write_archive};
 pub(crate) use btree::{Btree, BtreeMut, RawBtree};
 pub(crate) use btree_base::Checksum;
-pub use btree_base::{AccessGuard, AccessGuardMut};
-pub(crate) use btree_base::{LeafAccessor, RawLeafBuilder, BRANCH, LEAF};
+pub use btree_base::{AccessGuard, AccessGuardMut, FillPolicy};
+pub(crate) use btree_base::{BranchAccessor, LeafAccessor, RawLeafBuilder, BRANCH, LEAF};
+pub(crate) use btree_diff::{BtreeChange, BtreeDiff};
 pub(crate) use btree_iters::{
     AllPageNumbersBtreeIter, BtreeDrain, BtreeDrainFilter, BtreeRangeIter,
 };
-pub use page_store::Savepoint;
+#[cfg(fuzzing)]
+pub(crate) use page_store::fuzz_header_roundtrip;
 pub(crate) use page_store::{
+    apply_incremental_backup, write_copy, write_incremental_backup, xxh3_checksum, HeaderRecovery,
     Page, PageHint, PageNumber, TransactionalMemory, FILE_FORMAT_VERSION, MAX_VALUE_LENGTH,
     PAGE_SIZE,
 };
+pub use page_store::{AllocationStrategy, CacheStats, ChecksumAlgorithm, Savepoint};
 pub(crate) use table_tree::{
     FreedPageList, FreedTableKey, InternalTableDefinition, TableTree, TableType,
 };
+#[cfg(test)]
+pub(crate) use upgrade::downgrade_tree;
+pub(crate) use upgrade::{upgrade_tree, RewriteValue};
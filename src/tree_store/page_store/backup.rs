@@ -0,0 +1,144 @@
+use crate::transaction_tracker::TransactionId;
+use crate::tree_store::page_store::file_lock::LockedFile;
+use crate::tree_store::page_store::header::DB_HEADER_SIZE;
+use crate::tree_store::page_store::TransactionalMemory;
+use crate::tree_store::{Checksum, Page, PageNumber};
+use crate::{Error, Result};
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::mem::size_of;
+
+// Incremental backup format:
+// 8 bytes: magic number
+// 1 byte: version
+// 8 bytes: length of the database file
+// DB_HEADER_SIZE bytes: database header
+// 8 bytes: number of pages
+//
+// Followed by each page:
+// 8 bytes: offset in the database file
+// 8 bytes: length
+// n bytes: page contents
+
+const MAGICNUMBER: [u8; 8] = *b"redbincr";
+const VERSION: u8 = 1;
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut buffer = [0; size_of::<u64>()];
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+fn invalid_backup(message: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+// Writes the given pages, and a header whose primary commit slot contains the given roots.
+// Returns the number of pages written
+pub(crate) fn write_incremental_backup(
+    mem: &TransactionalMemory,
+    user_root: Option<(PageNumber, Checksum)>,
+    system_root: Option<(PageNumber, Checksum)>,
+    transaction_id: TransactionId,
+    mut pages: Vec<PageNumber>,
+    mut writer: impl Write,
+) -> Result<u64> {
+    let (file_len, header) = mem.header_for_copy(user_root, system_root, transaction_id);
+    pages.sort_unstable();
+
+    writer.write_all(&MAGICNUMBER)?;
+    writer.write_all(&[VERSION])?;
+    writer.write_all(&file_len.to_le_bytes())?;
+    writer.write_all(&header)?;
+    let num_pages: u64 = pages.len().try_into().unwrap();
+    writer.write_all(&num_pages.to_le_bytes())?;
+    for page_number in pages {
+        let offset = mem.page_range(page_number).start;
+        let page = mem.get_page(page_number)?;
+        let len: u64 = page.memory().len().try_into().unwrap();
+        writer.write_all(&offset.to_le_bytes())?;
+        writer.write_all(&len.to_le_bytes())?;
+        writer.write_all(page.memory())?;
+    }
+    writer.flush()?;
+
+    Ok(num_pages)
+}
+
+// Writes the given pages to `file`, at the same offsets, followed by a header whose primary commit
+// slot contains the given roots. Returns the number of pages written
+pub(crate) fn write_copy(
+    mem: &TransactionalMemory,
+    user_root: Option<(PageNumber, Checksum)>,
+    system_root: Option<(PageNumber, Checksum)>,
+    transaction_id: TransactionId,
+    pages: Vec<PageNumber>,
+    file: File,
+) -> Result<u64> {
+    let file = LockedFile::new(file)?;
+    let (file_len, header) = mem.header_for_copy(user_root, system_root, transaction_id);
+
+    file.file().set_len(file_len)?;
+    let num_pages: u64 = pages.len().try_into().unwrap();
+    for page_number in pages {
+        let offset = mem.page_range(page_number).start;
+        let page = mem.get_page(page_number)?;
+        file.write(offset, page.memory())?;
+    }
+    file.file().sync_data()?;
+
+    // Write the header last, so that an interrupted copy is never mistaken for a valid database
+    file.write(0, &header)?;
+    file.file().sync_data()?;
+
+    Ok(num_pages)
+}
+
+// Pages are written before the header, and none of them are reachable from the previous header,
+// so the copy is left in its previous state if this is interrupted
+pub(crate) fn apply_incremental_backup(file: File, mut reader: impl Read) -> Result {
+    let file = LockedFile::new(file)?;
+
+    let mut magic_number = [0; MAGICNUMBER.len()];
+    reader.read_exact(&mut magic_number)?;
+    if magic_number != MAGICNUMBER {
+        return Err(invalid_backup("Not an incremental backup"));
+    }
+    let mut version = [0];
+    reader.read_exact(&mut version)?;
+    if version[0] != VERSION {
+        return Err(invalid_backup("Unsupported incremental backup version"));
+    }
+    let file_len = read_u64(&mut reader)?;
+    let mut header = vec![0; DB_HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    let num_pages = read_u64(&mut reader)?;
+
+    if file.file().metadata()?.len() < file_len {
+        file.file().set_len(file_len)?;
+    }
+    let mut buffer = vec![];
+    for _ in 0..num_pages {
+        let offset = read_u64(&mut reader)?;
+        let len = read_u64(&mut reader)?;
+        if offset < DB_HEADER_SIZE as u64 || offset.saturating_add(len) > file_len {
+            return Err(invalid_backup("Page outside of database file"));
+        }
+        buffer.resize(len.try_into().unwrap(), 0);
+        reader.read_exact(&mut buffer)?;
+        file.write(offset, &buffer)?;
+    }
+    file.file().sync_data()?;
+
+    file.write(0, &header)?;
+    file.file().sync_data()?;
+    // Only shrink the file once the new header is durable, since the previous state may have
+    // referenced pages past the new end of the file
+    if file.file().metadata()?.len() > file_len {
+        file.file().set_len(file_len)?;
+        file.file().sync_data()?;
+    }
+
+    Ok(())
+}
@@ -1,3 +1,5 @@
+#[cfg(debug_assertions)]
+use crate::sync::Mutex;
 use crate::tree_store::page_store::cached_file::WritablePage;
 use crate::tree_store::page_store::page_manager::MAX_MAX_PAGE_ORDER;
 #[cfg(debug_assertions)]
@@ -9,8 +11,6 @@ use std::fmt::{Debug, Formatter};
 use std::marker::PhantomData;
 use std::ops::Range;
 use std::sync::Arc;
-#[cfg(debug_assertions)]
-use std::sync::Mutex;
 
 pub(crate) const MAX_VALUE_LENGTH: usize = 3 * 1024 * 1024 * 1024;
 
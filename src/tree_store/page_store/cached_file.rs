@@ -1,3 +1,5 @@
+use crate::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use crate::sync::{Mutex, RwLock};
 use crate::tree_store::page_store::base::PageHint;
 use crate::tree_store::page_store::file_lock::LockedFile;
 use crate::{Error, Result};
@@ -6,13 +8,10 @@ use std::fs::File;
 use std::io;
 use std::mem;
 use std::ops::{DerefMut, Index, IndexMut};
-#[cfg(any(target_os = "linux", all(unix, not(fuzzing))))]
+#[cfg(all(unix, not(force_safe), any(target_os = "linux", not(fuzzing))))]
 use std::os::unix::io::AsRawFd;
 use std::slice::SliceIndex;
-#[cfg(any(fuzzing, test, feature = "cache_metrics"))]
-use std::sync::atomic::AtomicU64;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::Arc;
 
 pub(super) struct WritablePage<'a> {
     buffer: &'a Mutex<BTreeMap<u64, Arc<Vec<u8>>>>,
@@ -56,6 +55,48 @@ impl<'a, I: SliceIndex<[u8]>> IndexMut<I> for WritablePage<'a> {
     }
 }
 
+/// Statistics of the page cache, returned by [`crate::Database::cache_stats`]
+///
+/// The counters are totals since the database was opened. Pages are cached by their location in
+/// the file, so the statistics cover all tables together
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CacheStats {
+    entries: u64,
+    bytes: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl CacheStats {
+    /// Number of pages in the read cache
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    /// Total size of the pages in the read cache
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Number of page reads which were served from the cache, or from the pages buffered by the
+    /// current write transaction
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Number of page reads which had to read from the file
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Number of pages evicted from the read cache to make room for others. Pages discarded by
+    /// [`crate::Database::evict_cache`] are not counted
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+}
+
 pub(super) struct PagedCachedFile {
     file: LockedFile,
     page_size: u64,
@@ -63,11 +104,13 @@ pub(super) struct PagedCachedFile {
     read_cache_bytes: AtomicUsize,
     max_write_buffer_bytes: usize,
     write_buffer_bytes: AtomicUsize,
-    #[cfg(feature = "cache_metrics")]
     reads_total: AtomicU64,
-    #[cfg(feature = "cache_metrics")]
     reads_hits: AtomicU64,
+    // Number of pages evicted from the read cache to make room for others
+    evictions: AtomicU64,
     fsync_failed: AtomicBool,
+    // Total number of bytes written to the file
+    bytes_written: AtomicU64,
     read_cache: Vec<RwLock<BTreeMap<u64, Arc<Vec<u8>>>>>,
     // TODO: maybe move this cache to WriteTransaction?
     write_buffer: Mutex<BTreeMap<u64, Arc<Vec<u8>>>>,
@@ -91,7 +134,7 @@ impl PagedCachedFile {
 
         // Try to flush any pages in the page cache that are out of sync with disk.
         // See here for why: <https://github.com/cberner/redb/issues/450>
-        #[cfg(target_os = "linux")]
+        #[cfg(all(target_os = "linux", not(force_safe)))]
         unsafe {
             libc::posix_fadvise64(lock.file().as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
         }
@@ -103,11 +146,11 @@ impl PagedCachedFile {
             read_cache_bytes: AtomicUsize::new(0),
             max_write_buffer_bytes,
             write_buffer_bytes: AtomicUsize::new(0),
-            #[cfg(feature = "cache_metrics")]
             reads_total: Default::default(),
-            #[cfg(feature = "cache_metrics")]
             reads_hits: Default::default(),
+            evictions: Default::default(),
             fsync_failed: Default::default(),
+            bytes_written: Default::default(),
             read_cache,
             write_buffer: Mutex::new(BTreeMap::new()),
             #[cfg(any(fuzzing, test))]
@@ -124,6 +167,10 @@ impl PagedCachedFile {
         Ok(self.file.file().metadata()?.len())
     }
 
+    pub(super) fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Acquire)
+    }
+
     const fn lock_stripes() -> usize {
         131
     }
@@ -152,6 +199,8 @@ impl PagedCachedFile {
 
         for (offset, buffer) in write_buffer.iter() {
             self.file.write(*offset, buffer)?;
+            self.bytes_written
+                .fetch_add(buffer.len() as u64, Ordering::AcqRel);
         }
         write_buffer.clear();
 
@@ -183,7 +232,7 @@ impl PagedCachedFile {
                 self.set_fsync_failed(true);
                 // Try to flush any pages in the page cache that are out of sync with disk.
                 // See here for why: <https://github.com/cberner/redb/issues/450>
-                #[cfg(target_os = "linux")]
+                #[cfg(all(target_os = "linux", not(force_safe)))]
                 unsafe {
                     libc::posix_fadvise64(
                         self.file.file().as_raw_fd(),
@@ -202,11 +251,11 @@ impl PagedCachedFile {
     pub(super) fn eventual_flush(&self) -> Result {
         self.check_fsync_failure()?;
 
-        #[cfg(not(target_os = "macos"))]
+        #[cfg(any(not(target_os = "macos"), force_safe))]
         {
             self.flush()?;
         }
-        #[cfg(all(target_os = "macos", not(fuzzing)))]
+        #[cfg(all(target_os = "macos", not(fuzzing), not(force_safe)))]
         {
             self.flush_write_buffer()?;
             let code = unsafe { libc::fcntl(self.file.file().as_raw_fd(), libc::F_BARRIERFSYNC) };
@@ -241,13 +290,11 @@ impl PagedCachedFile {
     pub(super) fn read(&self, offset: u64, len: usize, hint: PageHint) -> Result<Arc<Vec<u8>>> {
         self.check_fsync_failure()?;
         debug_assert_eq!(0, offset % self.page_size);
-        #[cfg(feature = "cache_metrics")]
         self.reads_total.fetch_add(1, Ordering::AcqRel);
 
         if !matches!(hint, PageHint::Clean) {
             let lock = self.write_buffer.lock().unwrap();
             if let Some(cached) = lock.get(&offset) {
-                #[cfg(feature = "cache_metrics")]
                 self.reads_hits.fetch_add(1, Ordering::Release);
                 debug_assert_eq!(cached.len(), len);
                 return Ok(cached.clone());
@@ -258,7 +305,6 @@ impl PagedCachedFile {
         {
             let read_lock = self.read_cache[cache_slot].read().unwrap();
             if let Some(cached) = read_lock.get(&offset) {
-                #[cfg(feature = "cache_metrics")]
                 self.reads_hits.fetch_add(1, Ordering::Release);
                 debug_assert_eq!(cached.len(), len);
                 return Ok(cached.clone());
@@ -268,12 +314,17 @@ impl PagedCachedFile {
         let buffer = Arc::new(self.read_direct(offset, len)?);
         let cache_size = self.read_cache_bytes.fetch_add(len, Ordering::AcqRel);
         let mut write_lock = self.read_cache[cache_slot].write().unwrap();
-        write_lock.insert(offset, buffer.clone());
+        if let Some(replaced) = write_lock.insert(offset, buffer.clone()) {
+            // Another reader missed the cache for this page at the same time, and already inserted it
+            self.read_cache_bytes
+                .fetch_sub(replaced.len(), Ordering::AcqRel);
+        }
         let mut removed = 0;
         if cache_size + len > self.max_read_cache_bytes {
             while removed < len {
                 if let Some((_, v)) = write_lock.pop_first() {
                     removed += v.len();
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
                 } else {
                     break;
                 }
@@ -315,6 +366,23 @@ impl PagedCachedFile {
         }
     }
 
+    pub(super) fn cache_stats(&self) -> CacheStats {
+        let entries = self
+            .read_cache
+            .iter()
+            .map(|slot| slot.read().unwrap().len() as u64)
+            .sum();
+        let reads = self.reads_total.load(Ordering::Acquire);
+        let hits = self.reads_hits.load(Ordering::Acquire);
+        CacheStats {
+            entries,
+            bytes: self.read_cache_bytes.load(Ordering::Acquire) as u64,
+            hits,
+            misses: reads.saturating_sub(hits),
+            evictions: self.evictions.load(Ordering::Acquire),
+        }
+    }
+
     pub(super) fn write(&self, offset: u64, len: usize) -> Result<WritablePage> {
         self.check_fsync_failure()?;
         assert_eq!(0, offset % self.page_size);
@@ -360,6 +428,9 @@ impl PagedCachedFile {
                         let result = self.file.write(offset, &buffer);
                         if result.is_err() {
                             lock.insert(offset, buffer);
+                        } else {
+                            self.bytes_written
+                                .fetch_add(buffer.len() as u64, Ordering::AcqRel);
                         }
                         result?;
                     } else {
@@ -380,3 +451,94 @@ impl PagedCachedFile {
         })
     }
 }
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    use crate::sync::atomic::Ordering;
+    use crate::tree_store::page_store::base::PageHint;
+    use crate::tree_store::page_store::cached_file::PagedCachedFile;
+    use loom::sync::Arc;
+    use loom::thread;
+    use tempfile::NamedTempFile;
+
+    const PAGE_SIZE: usize = 512;
+
+    fn cached_bytes(file: &PagedCachedFile) -> usize {
+        file.read_cache
+            .iter()
+            .map(|slot| {
+                slot.read()
+                    .unwrap()
+                    .values()
+                    .map(|x| x.len())
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    fn new_file(max_read_cache_bytes: usize) -> PagedCachedFile {
+        let file = NamedTempFile::new().unwrap().into_file();
+        file.set_len(4 * PAGE_SIZE as u64).unwrap();
+        PagedCachedFile::new(file, PAGE_SIZE as u64, max_read_cache_bytes, 4 * PAGE_SIZE).unwrap()
+    }
+
+    // Two readers which miss the cache for the same page both insert it
+    #[test]
+    fn concurrent_reads() {
+        loom::model(|| {
+            let file = Arc::new(new_file(4 * PAGE_SIZE));
+
+            let threads: Vec<_> = (0..2)
+                .map(|_| {
+                    let file = file.clone();
+                    thread::spawn(move || {
+                        file.read(0, PAGE_SIZE, PageHint::None).unwrap();
+                    })
+                })
+                .collect();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+
+            assert_eq!(
+                file.read_cache_bytes.load(Ordering::Acquire),
+                cached_bytes(&file)
+            );
+            file.invalidate_cache_all();
+            assert_eq!(file.read_cache_bytes.load(Ordering::Acquire), 0);
+        });
+    }
+
+    // A write to one page, racing with reads which evict from the read cache
+    #[test]
+    fn write_during_reads() {
+        loom::model(|| {
+            let file = Arc::new(new_file(PAGE_SIZE));
+            file.read(PAGE_SIZE as u64, PAGE_SIZE, PageHint::None)
+                .unwrap();
+
+            let reader = {
+                let file = file.clone();
+                thread::spawn(move || {
+                    file.read(2 * PAGE_SIZE as u64, PAGE_SIZE, PageHint::None)
+                        .unwrap();
+                })
+            };
+            {
+                let mut page = file.write(PAGE_SIZE as u64, PAGE_SIZE).unwrap();
+                page.mem_mut()[0] = 1;
+            }
+            reader.join().unwrap();
+
+            let page = file
+                .read(PAGE_SIZE as u64, PAGE_SIZE, PageHint::None)
+                .unwrap();
+            assert_eq!(page[0], 1);
+            assert_eq!(
+                file.read_cache_bytes.load(Ordering::Acquire),
+                cached_bytes(&file)
+            );
+            assert!(cached_bytes(&file) <= 2 * PAGE_SIZE);
+        });
+    }
+}
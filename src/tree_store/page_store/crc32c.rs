@@ -0,0 +1,88 @@
+// CRC-32C (Castagnoli), as used by iSCSI and ext4. Uses the SSE 4.2 crc32 instruction when it's
+// available, and otherwise falls back to a lookup table
+
+const POLYNOMIAL: u32 = 0x82F6_3B78;
+
+const TABLE: [u32; 256] = build_table();
+
+const fn build_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i: u32 = 0;
+    while i < 256 {
+        let mut crc = i;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i as usize] = crc;
+        i += 1;
+    }
+
+    table
+}
+
+pub(crate) fn crc32c(data: &[u8]) -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("sse4.2") {
+            return unsafe { crc32c_sse42(data) };
+        }
+    }
+
+    crc32c_table(data)
+}
+
+fn crc32c_table(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc = TABLE[usize::from(crc.to_le_bytes()[0] ^ byte)] ^ (crc >> 8);
+    }
+
+    !crc
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+unsafe fn crc32c_sse42(data: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u64, _mm_crc32_u8};
+
+    let mut crc = u64::from(!0u32);
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        crc = _mm_crc32_u64(crc, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    let mut crc: u32 = crc.try_into().unwrap();
+    for byte in chunks.remainder() {
+        crc = _mm_crc32_u8(crc, *byte);
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tree_store::page_store::crc32c::{crc32c, crc32c_table};
+
+    #[test]
+    fn check_values() {
+        // Test vectors from RFC 3720, appendix B.4
+        assert_eq!(crc32c(&[]), 0);
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(crc32c(&[0; 32]), 0x8A91_36AA);
+        assert_eq!(crc32c(&[0xFF; 32]), 0x62A8_AB43);
+        let ascending: Vec<u8> = (0..32).collect();
+        assert_eq!(crc32c(&ascending), 0x46DD_794E);
+
+        let data: Vec<u8> = (0..1000u32)
+            .map(|x| u8::try_from(x * 7 % 256).unwrap())
+            .collect();
+        for len in 0..data.len() {
+            assert_eq!(crc32c(&data[..len]), crc32c_table(&data[..len]));
+        }
+    }
+}
@@ -3,9 +3,18 @@ use std::fs::File;
 use std::io;
 use std::os::unix::fs::FileExt;
 use std::os::unix::io::AsRawFd;
+use std::sync::Mutex;
+
+// Alignment of offsets, lengths, and buffers used for I/O on files opened with O_DIRECT.
+// Devices with a larger logical block size than this are very rare
+const DIRECT_IO_ALIGNMENT: usize = 4096;
 
 pub(crate) struct LockedFile {
     file: File,
+    // If the file was opened with O_DIRECT, all I/O is done through aligned buffers
+    direct_io: bool,
+    // Serializes writes which only cover part of an aligned block, since they read-modify-write it
+    partial_write: Mutex<()>,
 }
 
 impl LockedFile {
@@ -20,7 +29,19 @@ impl LockedFile {
                 Err(Error::Io(err))
             }
         } else {
-            Ok(Self { file })
+            #[cfg(all(target_os = "linux", not(force_safe)))]
+            let direct_io = {
+                let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+                flags != -1 && flags & libc::O_DIRECT != 0
+            };
+            #[cfg(any(not(target_os = "linux"), force_safe))]
+            let direct_io = false;
+
+            Ok(Self {
+                file,
+                direct_io,
+                partial_write: Mutex::new(()),
+            })
         }
     }
 
@@ -29,6 +50,9 @@ impl LockedFile {
     }
 
     pub(crate) fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        if self.direct_io {
+            return self.read_aligned(offset, len);
+        }
         let mut buffer = vec![0; len];
         self.file
             .read_exact_at(&mut buffer, offset)
@@ -37,8 +61,65 @@ impl LockedFile {
     }
 
     pub(crate) fn write(&self, offset: u64, data: &[u8]) -> Result {
+        if self.direct_io {
+            return self.write_aligned(offset, data);
+        }
         self.file.write_all_at(data, offset).map_err(Error::from)
     }
+
+    fn read_aligned(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let start = align_down(offset);
+        let end = align_up(offset + len as u64);
+        let mut storage = AlignedBuffer::new((end - start).try_into().unwrap());
+        let buffer = storage.as_mut();
+        let read = self.read_at_most(buffer, start)?;
+        let skip: usize = (offset - start).try_into().unwrap();
+        if read < skip + len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        Ok(buffer[skip..(skip + len)].to_vec())
+    }
+
+    fn write_aligned(&self, offset: u64, data: &[u8]) -> Result {
+        let start = align_down(offset);
+        let end = align_up(offset + data.len() as u64);
+        let mut storage = AlignedBuffer::new((end - start).try_into().unwrap());
+        let buffer = storage.as_mut();
+        let skip: usize = (offset - start).try_into().unwrap();
+        if start == offset && end == offset + data.len() as u64 {
+            buffer.copy_from_slice(data);
+            return self.file.write_all_at(buffer, start).map_err(Error::from);
+        }
+
+        let _guard = self.partial_write.lock().unwrap();
+        let original_len = self.file.metadata()?.len();
+        self.read_at_most(buffer, start)?;
+        buffer[skip..(skip + data.len())].copy_from_slice(data);
+        self.file.write_all_at(buffer, start)?;
+        // Writing whole blocks may have extended the file past the end of the data
+        let len = std::cmp::max(original_len, offset + data.len() as u64);
+        if end > len {
+            self.file.set_len(len)?;
+        }
+
+        Ok(())
+    }
+
+    // Reads into buffer until it is full, or the end of the file is reached
+    fn read_at_most(&self, buffer: &mut [u8], offset: u64) -> Result<usize> {
+        let mut read = 0;
+        while read < buffer.len() {
+            match self.file.read_at(&mut buffer[read..], offset + read as u64) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(read)
+    }
 }
 
 impl Drop for LockedFile {
@@ -46,3 +127,30 @@ impl Drop for LockedFile {
         unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) };
     }
 }
+
+fn align_down(offset: u64) -> u64 {
+    offset - offset % DIRECT_IO_ALIGNMENT as u64
+}
+
+fn align_up(offset: u64) -> u64 {
+    align_down(offset + DIRECT_IO_ALIGNMENT as u64 - 1)
+}
+
+// Zeroed buffer whose start is aligned to DIRECT_IO_ALIGNMENT
+struct AlignedBuffer {
+    data: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl AlignedBuffer {
+    fn new(len: usize) -> Self {
+        let data = vec![0; len + DIRECT_IO_ALIGNMENT];
+        let start = data.as_ptr().align_offset(DIRECT_IO_ALIGNMENT);
+        Self { data, start, len }
+    }
+
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.data[self.start..(self.start + self.len)]
+    }
+}
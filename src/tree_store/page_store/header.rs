@@ -1,7 +1,10 @@
 use crate::transaction_tracker::TransactionId;
 use crate::tree_store::page_store::layout::{DatabaseLayout, RegionLayout};
-use crate::tree_store::page_store::page_manager::{xxh3_checksum, FILE_FORMAT_VERSION};
+use crate::tree_store::page_store::page_manager::{
+    xxh3_checksum, ChecksumAlgorithm, FILE_FORMAT_VERSION,
+};
 use crate::tree_store::{Checksum, PageNumber};
+use crate::{Error, Result};
 use std::mem::size_of;
 
 // Database layout:
@@ -12,7 +15,8 @@ use std::mem::size_of;
 // Header (first 64 bytes):
 // 9 bytes: magic number
 // 1 byte: god byte
-// 2 byte: padding
+// 1 byte: page checksum algorithm
+// 1 byte: padding
 // 4 bytes: page size
 // Definition of region
 // 4 bytes: region header pages
@@ -39,7 +43,8 @@ use std::mem::size_of;
 // Inspired by PNG's magic number
 pub(super) const MAGICNUMBER: [u8; 9] = [b'r', b'e', b'd', b'b', 0x1A, 0x0A, 0xA9, 0x0D, 0x0A];
 const GOD_BYTE_OFFSET: usize = MAGICNUMBER.len();
-const PAGE_SIZE_OFFSET: usize = GOD_BYTE_OFFSET + size_of::<u8>() + 2; // +2 for padding
+const CHECKSUM_ALGORITHM_OFFSET: usize = GOD_BYTE_OFFSET + size_of::<u8>();
+const PAGE_SIZE_OFFSET: usize = CHECKSUM_ALGORITHM_OFFSET + size_of::<u8>() + 1; // +1 for padding
 const REGION_HEADER_PAGES_OFFSET: usize = PAGE_SIZE_OFFSET + size_of::<u32>();
 const REGION_MAX_DATA_PAGES_OFFSET: usize = REGION_HEADER_PAGES_OFFSET + size_of::<u32>();
 const TRANSACTION_SIZE: usize = 192;
@@ -93,6 +98,7 @@ pub(super) struct HeaderRepairInfo {
 pub(super) struct DatabaseHeader {
     primary_slot: usize,
     pub(super) recovery_required: bool,
+    checksum_algorithm: u8,
     page_size: u32,
     region_header_pages: u32,
     region_max_data_pages: u32,
@@ -104,6 +110,7 @@ impl DatabaseHeader {
         layout: DatabaseLayout,
         transaction_id: TransactionId,
         region_tracker: PageNumber,
+        checksum_algorithm: ChecksumAlgorithm,
     ) -> Self {
         #[allow(clippy::assertions_on_constants)]
         {
@@ -114,6 +121,7 @@ impl DatabaseHeader {
         Self {
             primary_slot: 0,
             recovery_required: true,
+            checksum_algorithm: checksum_algorithm.to_u8(),
             page_size: layout.full_region_layout().page_size(),
             region_header_pages: layout.full_region_layout().get_header_pages(),
             region_max_data_pages: layout.full_region_layout().num_pages(),
@@ -121,6 +129,15 @@ impl DatabaseHeader {
         }
     }
 
+    pub(super) fn checksum_algorithm(&self) -> Result<ChecksumAlgorithm> {
+        ChecksumAlgorithm::from_u8(self.checksum_algorithm).ok_or_else(|| {
+            Error::Corrupted(format!(
+                "Unknown checksum algorithm: {}",
+                self.checksum_algorithm
+            ))
+        })
+    }
+
     pub(super) fn page_size(&self) -> u32 {
         self.page_size
     }
@@ -145,6 +162,13 @@ impl DatabaseHeader {
         &mut self.transaction_slots[self.primary_slot ^ 1]
     }
 
+    // Only valid when the slots are otherwise laid out identically in both versions
+    pub(super) fn set_version(&mut self, version: u8) {
+        for slot in self.transaction_slots.iter_mut() {
+            slot.version = version;
+        }
+    }
+
     pub(super) fn swap_primary_slot(&mut self) {
         self.primary_slot ^= 1;
     }
@@ -155,6 +179,7 @@ impl DatabaseHeader {
 
         let primary_slot = usize::from(data[GOD_BYTE_OFFSET] & PRIMARY_BIT != 0);
         let recovery_required = (data[GOD_BYTE_OFFSET] & RECOVERY_REQUIRED) != 0;
+        let checksum_algorithm = data[CHECKSUM_ALGORITHM_OFFSET];
         let page_size = get_u32(&data[PAGE_SIZE_OFFSET..]);
         let region_header_pages = get_u32(&data[REGION_HEADER_PAGES_OFFSET..]);
         let region_max_data_pages = get_u32(&data[REGION_MAX_DATA_PAGES_OFFSET..]);
@@ -173,6 +198,7 @@ impl DatabaseHeader {
         let result = Self {
             primary_slot,
             recovery_required,
+            checksum_algorithm,
             page_size,
             region_header_pages,
             region_max_data_pages,
@@ -202,6 +228,7 @@ impl DatabaseHeader {
         if self.recovery_required {
             result[GOD_BYTE_OFFSET] |= RECOVERY_REQUIRED;
         }
+        result[CHECKSUM_ALGORITHM_OFFSET] = self.checksum_algorithm;
         result[PAGE_SIZE_OFFSET..(PAGE_SIZE_OFFSET + size_of::<u32>())]
             .copy_from_slice(&self.page_size.to_le_bytes());
         result[REGION_HEADER_PAGES_OFFSET..(REGION_HEADER_PAGES_OFFSET + size_of::<u32>())]
@@ -385,20 +412,37 @@ impl TransactionHeader {
     }
 }
 
+// Parses `data` as a database header, and checks that the result can be serialized and parsed
+// again losslessly
+#[cfg(fuzzing)]
+pub(crate) fn fuzz_header_roundtrip(data: &[u8]) {
+    let mut padded = [0; DB_HEADER_SIZE];
+    let len = std::cmp::min(data.len(), DB_HEADER_SIZE);
+    padded[..len].copy_from_slice(&data[..len]);
+    let (header, _) = DatabaseHeader::from_bytes(&padded);
+    let bytes = header.to_bytes(true, false);
+    let (reparsed, repair) = DatabaseHeader::from_bytes(&bytes);
+    assert!(!repair.invalid_magic_number);
+    assert!(!repair.primary_corrupted);
+    assert!(!repair.secondary_corrupted);
+    assert_eq!(reparsed.to_bytes(true, false), bytes);
+}
+
 #[cfg(test)]
 mod test {
-    use crate::db::TableDefinition;
+    use crate::db::{MultimapTableDefinition, TableDefinition};
+    use crate::tree_store::downgrade_tree;
     use crate::tree_store::page_store::header::{
-        GOD_BYTE_OFFSET, MAGICNUMBER, PAGE_SIZE, PRIMARY_BIT, RECOVERY_REQUIRED,
-        TRANSACTION_0_OFFSET, TRANSACTION_1_OFFSET, USER_ROOT_CHECKSUM_OFFSET,
+        DatabaseHeader, DB_HEADER_SIZE, GOD_BYTE_OFFSET, MAGICNUMBER, PAGE_SIZE, PRIMARY_BIT,
+        RECOVERY_REQUIRED, TRANSACTION_0_OFFSET, TRANSACTION_1_OFFSET, USER_ROOT_CHECKSUM_OFFSET,
     };
-    use crate::tree_store::page_store::TransactionalMemory;
-    #[cfg(not(target_os = "windows"))]
+    use crate::tree_store::page_store::{ChecksumAlgorithm, TransactionalMemory};
     use crate::Error;
-    use crate::{Database, ReadableTable};
+    use crate::{Database, ReadableMultimapTable, ReadableTable};
     use std::fs::OpenOptions;
     use std::io::{Read, Seek, SeekFrom, Write};
     use std::mem::size_of;
+    use std::path::Path;
     use tempfile::NamedTempFile;
 
     const X: TableDefinition<&str, &str> = TableDefinition::new("x");
@@ -451,10 +495,12 @@ mod test {
         .unwrap();
         file.write_all(&[0; size_of::<u128>()]).unwrap();
 
-        assert!(TransactionalMemory::new(file, PAGE_SIZE, None, 0, 0)
-            .unwrap()
-            .needs_repair()
-            .unwrap());
+        assert!(
+            TransactionalMemory::new(file, PAGE_SIZE, None, 0, 0, ChecksumAlgorithm::Xxh3)
+                .unwrap()
+                .needs_repair()
+                .unwrap()
+        );
 
         #[allow(unused_mut)]
         let mut db2 = Database::create(tmpfile.path()).unwrap();
@@ -534,10 +580,12 @@ mod test {
         buffer[0] |= RECOVERY_REQUIRED;
         file.write_all(&buffer).unwrap();
 
-        assert!(TransactionalMemory::new(file, PAGE_SIZE, None, 0, 0)
-            .unwrap()
-            .needs_repair()
-            .unwrap());
+        assert!(
+            TransactionalMemory::new(file, PAGE_SIZE, None, 0, 0, ChecksumAlgorithm::Xxh3)
+                .unwrap()
+                .needs_repair()
+                .unwrap()
+        );
 
         Database::open(tmpfile.path()).unwrap();
     }
@@ -580,14 +628,189 @@ mod test {
         buffer[0] |= RECOVERY_REQUIRED;
         file.write_all(&buffer).unwrap();
 
-        assert!(TransactionalMemory::new(file, PAGE_SIZE, None, 0, 0)
-            .unwrap()
-            .needs_repair()
-            .unwrap());
+        assert!(
+            TransactionalMemory::new(file, PAGE_SIZE, None, 0, 0, ChecksumAlgorithm::Xxh3)
+                .unwrap()
+                .needs_repair()
+                .unwrap()
+        );
 
         Database::open(tmpfile.path()).unwrap();
     }
 
+    #[test]
+    fn upgrade_file_format() {
+        let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let db = Database::builder().create(tmpfile.path()).unwrap();
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(X).unwrap();
+            table.insert("hello", "world").unwrap();
+        }
+        write_txn.commit().unwrap();
+        drop(db);
+
+        // Version 114 files only differ in their version, since the checksum algorithm byte was
+        // zero padding
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(tmpfile.path())
+            .unwrap();
+        let mut buffer = vec![0; DB_HEADER_SIZE];
+        file.read_exact(&mut buffer).unwrap();
+        let (mut header, _) = DatabaseHeader::from_bytes(&buffer);
+        header.set_version(114);
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(&header.to_bytes(true, false)).unwrap();
+        drop(file);
+
+        assert!(matches!(
+            Database::open(tmpfile.path()),
+            Err(Error::UpgradeRequired(114))
+        ));
+        assert!(Database::needs_upgrade(tmpfile.path()).unwrap());
+        assert!(Database::upgrade(tmpfile.path()).unwrap());
+        assert!(!Database::needs_upgrade(tmpfile.path()).unwrap());
+
+        let db = Database::open(tmpfile.path()).unwrap();
+        assert!(matches!(
+            Database::upgrade(tmpfile.path()),
+            Err(Error::DatabaseAlreadyOpen)
+        ));
+        let read_txn = db.begin_read().unwrap();
+        let table = read_txn.open_table(X).unwrap();
+        assert_eq!(table.get("hello").unwrap().unwrap().value(), "world");
+        drop(table);
+        drop(read_txn);
+        drop(db);
+        assert!(!Database::upgrade(tmpfile.path()).unwrap());
+    }
+
+    // Rewrites the branch pages of the database with the layout used before file format version
+    // 116, and marks it as version 115
+    fn downgrade_to_115(path: &Path) {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        let mem =
+            TransactionalMemory::new(file, PAGE_SIZE, None, 0, 0, ChecksumAlgorithm::Xxh3).unwrap();
+        let downgrade =
+            |root| Database::rewrite_tables_recursive(root, &mem, downgrade_tree).unwrap();
+        let data_root = mem.get_data_root().map(downgrade);
+        let system_root = mem.get_system_root().map(downgrade);
+        mem.finish_upgrade(data_root, system_root).unwrap();
+        drop(mem);
+
+        // The old pages are leaked, rather than requiring recovery, which must be done by the
+        // version of redb that wrote the file
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        let mut buffer = vec![0; DB_HEADER_SIZE];
+        file.read_exact(&mut buffer).unwrap();
+        let (mut header, _) = DatabaseHeader::from_bytes(&buffer);
+        header.set_version(115);
+        header.recovery_required = false;
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(&header.to_bytes(true, false)).unwrap();
+    }
+
+    #[test]
+    fn upgrade_branch_pages() {
+        let numbers: TableDefinition<u64, &[u8]> = TableDefinition::new("numbers");
+        let names: TableDefinition<&str, u64> = TableDefinition::new("names");
+        let multimap: MultimapTableDefinition<u64, u64> = MultimapTableDefinition::new("multimap");
+
+        let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let db = Database::builder().create(tmpfile.path()).unwrap();
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(numbers).unwrap();
+            for i in 0..10_000u64 {
+                table.insert(i, i.to_le_bytes().as_slice()).unwrap();
+            }
+            let mut table = write_txn.open_table(names).unwrap();
+            for i in 0..5_000u64 {
+                table.insert(format!("name{i}").as_str(), i).unwrap();
+            }
+            let mut table = write_txn.open_multimap_table(multimap).unwrap();
+            for i in 0..5_000u64 {
+                table.insert(0, i).unwrap();
+            }
+            table.insert(1, 1).unwrap();
+        }
+        write_txn.commit().unwrap();
+        drop(db);
+
+        downgrade_to_115(tmpfile.path());
+        assert!(matches!(
+            Database::open(tmpfile.path()),
+            Err(Error::UpgradeRequired(115))
+        ));
+        assert!(Database::upgrade(tmpfile.path()).unwrap());
+        assert!(!Database::needs_upgrade(tmpfile.path()).unwrap());
+
+        let db = Database::open(tmpfile.path()).unwrap();
+        let read_txn = db.begin_read().unwrap();
+        {
+            let table = read_txn.open_table(numbers).unwrap();
+            assert_eq!(table.range(100..9_900).unwrap().len(), 9_800);
+            for (i, entry) in table.iter().unwrap().enumerate() {
+                let (key, value) = entry.unwrap();
+                assert_eq!(key.value(), u64::try_from(i).unwrap());
+                assert_eq!(value.value(), key.value().to_le_bytes());
+            }
+            let table = read_txn.open_table(names).unwrap();
+            assert_eq!(table.iter().unwrap().len(), 5_000);
+            assert_eq!(table.get("name1234").unwrap().unwrap().value(), 1234);
+            let table = read_txn.open_multimap_table(multimap).unwrap();
+            let values: Vec<u64> = table
+                .get(0)
+                .unwrap()
+                .map(|value| value.unwrap().value())
+                .collect();
+            assert_eq!(values, (0..5_000).collect::<Vec<u64>>());
+            assert_eq!(table.get(1).unwrap().count(), 1);
+        }
+        drop(read_txn);
+
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(numbers).unwrap();
+            table.remove_range(..5_000u64).unwrap();
+            assert_eq!(table.iter().unwrap().len(), 5_000);
+        }
+        write_txn.commit().unwrap();
+    }
+
+    #[test]
+    fn upgrade_with_persistent_savepoint() {
+        let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let db = Database::builder().create(tmpfile.path()).unwrap();
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(X).unwrap();
+            table.insert("hello", "world").unwrap();
+        }
+        write_txn.commit().unwrap();
+        let write_txn = db.begin_write().unwrap();
+        write_txn.persistent_savepoint().unwrap();
+        write_txn.commit().unwrap();
+        drop(db);
+
+        downgrade_to_115(tmpfile.path());
+        assert!(matches!(
+            Database::upgrade(tmpfile.path()),
+            Err(Error::UpgradeRequired(115))
+        ));
+        assert!(Database::needs_upgrade(tmpfile.path()).unwrap());
+    }
+
     #[test]
     fn magic_number() {
         // Test compliance with some, but not all, provisions recommended by
@@ -1,7 +1,9 @@
+mod backup;
 mod base;
 mod bitmap;
 mod buddy_allocator;
 mod cached_file;
+mod crc32c;
 mod file_lock;
 mod header;
 mod layout;
@@ -11,9 +13,16 @@ mod savepoint;
 #[allow(dead_code)]
 mod xxh3;
 
+pub(crate) use backup::{apply_incremental_backup, write_copy, write_incremental_backup};
 pub(crate) use base::{Page, PageHint, PageNumber, MAX_VALUE_LENGTH};
+pub use cached_file::CacheStats;
+#[cfg(fuzzing)]
+pub(crate) use header::fuzz_header_roundtrip;
 pub(crate) use header::PAGE_SIZE;
-pub(crate) use page_manager::{xxh3_checksum, TransactionalMemory, FILE_FORMAT_VERSION};
+pub(crate) use page_manager::{
+    xxh3_checksum, HeaderRecovery, TransactionalMemory, FILE_FORMAT_VERSION,
+};
+pub use page_manager::{AllocationStrategy, ChecksumAlgorithm};
 pub use savepoint::Savepoint;
 
 pub(super) use base::{PageImpl, PageMut};
@@ -1,9 +1,14 @@
+use crate::pressure::{Pressure, SizeLimit};
+use crate::quarantine::{Quarantine, QuarantinedPage};
+use crate::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::sync::Mutex;
 use crate::transaction_tracker::TransactionId;
 use crate::tree_store::btree_base::Checksum;
 use crate::tree_store::page_store::base::PageHint;
 use crate::tree_store::page_store::bitmap::{BtreeBitmap, BtreeBitmapMut};
 use crate::tree_store::page_store::buddy_allocator::BuddyAllocator;
-use crate::tree_store::page_store::cached_file::PagedCachedFile;
+use crate::tree_store::page_store::cached_file::{CacheStats, PagedCachedFile};
+use crate::tree_store::page_store::crc32c::crc32c;
 use crate::tree_store::page_store::header::{DatabaseHeader, DB_HEADER_SIZE, MAGICNUMBER};
 use crate::tree_store::page_store::layout::DatabaseLayout;
 use crate::tree_store::page_store::region::{RegionHeaderAccessor, RegionHeaderMutator};
@@ -21,9 +26,10 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::convert::TryInto;
 use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
 use std::mem::size_of;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Mutex;
+use std::ops::Range;
 
 // Regions have a maximum size of 4GiB. A `4GiB - overhead` value is the largest that can be represented,
 // because the leaf node format uses 32bit offsets
@@ -37,7 +43,10 @@ const MIN_DESIRED_USABLE_BYTES: u64 = 1024 * 1024;
 const NUM_REGIONS: u32 = 1000;
 
 // TODO: set to 1, when version 1.0 is released
-pub(crate) const FILE_FORMAT_VERSION: u8 = 114;
+pub(crate) const FILE_FORMAT_VERSION: u8 = 116;
+// Oldest file format version which can be upgraded in place. Version 115 added the checksum
+// algorithm to the header, and 116 the number of entries beneath each child of a branch page
+pub(crate) const MIN_UPGRADABLE_VERSION: u8 = 114;
 
 fn ceil_log2(x: usize) -> u8 {
     if x.is_power_of_two() {
@@ -51,6 +60,68 @@ pub(crate) fn xxh3_checksum(data: &[u8]) -> Checksum {
     hash128_with_seed(data, 0)
 }
 
+/// Algorithm used to checksum the pages of a database
+///
+/// The algorithm is chosen when the database is created, with
+/// [`crate::Builder::set_checksum_algorithm`], and is stored in the file. The header of the
+/// database is always checksummed with XXH3
+///
+/// XXH3 is the default, since `benches/checksum_benchmark.rs` shows it writing faster than
+/// CRC-32C, even with hardware acceleration, while detecting more corruption. Disabling checksums
+/// roughly halves the time of large bulk loads
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ChecksumAlgorithm {
+    /// 128bit XXH3
+    #[default]
+    Xxh3,
+    /// 32bit CRC-32C, which is hardware accelerated on x86_64 processors with SSE 4.2
+    Crc32c,
+    /// Pages are not checksummed, so corruption of the file will not be detected
+    Disabled,
+}
+
+/// Strategy used to choose which free page to allocate
+///
+/// See [`crate::Builder::set_allocation_strategy`]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum AllocationStrategy {
+    /// Any free page of the required size, whichever is quickest to find
+    #[default]
+    Any,
+    /// The free page with the lowest address. This packs data toward the start of the file, so
+    /// pages written together are more likely to be adjacent, and the end of the file is more
+    /// likely to be free so that it can be truncated. Allocation is slightly slower
+    Lowest,
+}
+
+impl ChecksumAlgorithm {
+    pub(crate) fn checksum(self, data: &[u8]) -> Checksum {
+        match self {
+            ChecksumAlgorithm::Xxh3 => xxh3_checksum(data),
+            ChecksumAlgorithm::Crc32c => crc32c(data).into(),
+            ChecksumAlgorithm::Disabled => 0,
+        }
+    }
+
+    pub(super) fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ChecksumAlgorithm::Xxh3),
+            1 => Some(ChecksumAlgorithm::Crc32c),
+            2 => Some(ChecksumAlgorithm::Disabled),
+            _ => None,
+        }
+    }
+
+    pub(super) fn to_u8(self) -> u8 {
+        match self {
+            ChecksumAlgorithm::Xxh3 => 0,
+            ChecksumAlgorithm::Crc32c => 1,
+            ChecksumAlgorithm::Disabled => 2,
+        }
+    }
+}
+
 // Tracks the page orders that MAY BE free in each region. This data structure is optimistic, so
 // a region may not actually have a page free for a given order
 //
@@ -378,12 +449,30 @@ impl InMemoryState {
     }
 }
 
+// How the commit slot was chosen, when opening a file which was not shutdown cleanly
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct HeaderRecovery {
+    pub(crate) primary_corrupted: bool,
+    pub(crate) secondary_corrupted: bool,
+    // True if the secondary slot was promoted to be the primary
+    pub(crate) swapped_slot: bool,
+}
+
 pub(crate) struct TransactionalMemory {
     // Pages allocated since the last commit
     allocated_since_commit: Mutex<HashSet<PageNumber>>,
+    // Total size of the pages in allocated_since_commit
+    allocated_since_commit_bytes: AtomicU64,
     log_since_commit: Mutex<Vec<AllocationOp>>,
+    // Running totals of the number of pages allocated and freed
+    total_allocated_pages: AtomicU64,
+    total_freed_pages: AtomicU64,
     // True if the allocator state was corrupted when the file was opened
     needs_recovery: AtomicBool,
+    header_recovery: Option<HeaderRecovery>,
+    // Pages which failed checksum verification at open, and are skipped by reads
+    quarantine: Option<Quarantine>,
+    size_limit: Option<SizeLimit>,
     storage: PagedCachedFile,
     state: Mutex<InMemoryState>,
     // The current layout for the active transaction.
@@ -397,11 +486,15 @@ pub(crate) struct TransactionalMemory {
     read_page_ref_counts: Mutex<HashMap<PageNumber, u64>>,
     // Indicates that a non-durable commit has been made, so reads should be served from the secondary meta page
     read_from_secondary: AtomicBool,
+    // Indicates that the next commit should release all free space at the end of the file
+    trim_free_tail: AtomicBool,
     page_size: u32,
     // We store these separately from the layout because they're static, and accessed on the get_page()
     // code path where there is no locking
     region_size: u64,
     region_header_with_padding_size: u64,
+    checksum_algorithm: ChecksumAlgorithm,
+    allocation_strategy: AllocationStrategy,
     deferred_error: Mutex<Option<Error>>,
 }
 
@@ -413,7 +506,49 @@ impl TransactionalMemory {
         requested_region_size: Option<u64>,
         read_cache_size_bytes: usize,
         write_cache_size_bytes: usize,
+        checksum_algorithm: ChecksumAlgorithm,
     ) -> Result<Self> {
+        // Only databases opened for an upgrade are skipped
+        Ok(Self::new_inner(
+            file,
+            page_size,
+            requested_region_size,
+            read_cache_size_bytes,
+            write_cache_size_bytes,
+            checksum_algorithm,
+            false,
+        )?
+        .unwrap())
+    }
+
+    // Opens a database in an older file format, so that its pages can be rewritten with
+    // finish_upgrade(). It must have been shut down cleanly, since recovery must be performed by a
+    // version of redb which can read the file as it is. Returns None if the database already uses
+    // the current format
+    pub(crate) fn open_for_upgrade(mut file: File) -> Result<Option<Self>> {
+        let mut header_bytes = vec![0; DB_HEADER_SIZE];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header_bytes)?;
+        let (header, repair_info) = DatabaseHeader::from_bytes(&header_bytes);
+        if repair_info.invalid_magic_number {
+            return Err(io::Error::from(io::ErrorKind::InvalidData).into());
+        }
+        let page_size = header.page_size() as usize;
+        let checksum_algorithm = header.checksum_algorithm()?;
+
+        Self::new_inner(file, page_size, None, 0, 0, checksum_algorithm, true)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new_inner(
+        file: File,
+        page_size: usize,
+        requested_region_size: Option<u64>,
+        read_cache_size_bytes: usize,
+        write_cache_size_bytes: usize,
+        checksum_algorithm: ChecksumAlgorithm,
+        upgrade: bool,
+    ) -> Result<Option<Self>> {
         assert!(page_size.is_power_of_two() && page_size >= DB_HEADER_SIZE);
 
         let region_size = requested_region_size.unwrap_or(MAX_USABLE_REGION_SPACE);
@@ -473,7 +608,8 @@ impl TransactionalMemory {
                 PageNumber::new(0, page_number, required_order)
             };
 
-            let mut header = DatabaseHeader::new(layout, TransactionId(0), tracker_page);
+            let mut header =
+                DatabaseHeader::new(layout, TransactionId(0), tracker_page, checksum_algorithm);
 
             header.recovery_required = false;
             storage
@@ -495,13 +631,14 @@ impl TransactionalMemory {
         let (mut header, repair_info) = DatabaseHeader::from_bytes(&header_bytes);
 
         assert_eq!(header.page_size() as usize, page_size);
+        let checksum_algorithm = header.checksum_algorithm()?;
         let version = header.primary_slot().version;
         if version > FILE_FORMAT_VERSION {
             return Err(Error::Corrupted(format!(
                 "Expected file format version {FILE_FORMAT_VERSION}, found {version}",
             )));
         }
-        if version < FILE_FORMAT_VERSION {
+        if version < FILE_FORMAT_VERSION && !upgrade {
             return Err(Error::UpgradeRequired(version));
         }
         let version = header.secondary_slot().version;
@@ -510,14 +647,33 @@ impl TransactionalMemory {
                 "Expected file format version {FILE_FORMAT_VERSION}, found {version}",
             )));
         }
-        if version < FILE_FORMAT_VERSION {
+        if version < FILE_FORMAT_VERSION && !upgrade {
             return Err(Error::UpgradeRequired(version));
         }
+        if upgrade {
+            let version = cmp::min(
+                header.primary_slot().version,
+                header.secondary_slot().version,
+            );
+            if version == FILE_FORMAT_VERSION {
+                return Ok(None);
+            }
+            if version < MIN_UPGRADABLE_VERSION
+                || header.recovery_required
+                || repair_info.primary_corrupted
+                || repair_info.secondary_corrupted
+            {
+                return Err(Error::UpgradeRequired(version));
+            }
+        }
 
         let needs_recovery = header.recovery_required;
+        let mut header_recovery = None;
         if needs_recovery {
+            let mut swapped_slot = false;
             if repair_info.primary_corrupted {
                 header.swap_primary_slot();
+                swapped_slot = true;
             } else {
                 // If the secondary is a valid commit, verify that the primary is newer. This handles an edge case where:
                 // * the primary bit is flipped to the secondary
@@ -526,8 +682,14 @@ impl TransactionalMemory {
                     header.secondary_slot().transaction_id > header.primary_slot().transaction_id;
                 if secondary_newer && !repair_info.secondary_corrupted {
                     header.swap_primary_slot();
+                    swapped_slot = true;
                 }
             }
+            header_recovery = Some(HeaderRecovery {
+                primary_corrupted: repair_info.primary_corrupted,
+                secondary_corrupted: repair_info.secondary_corrupted,
+                swapped_slot,
+            });
             assert!(!repair_info.invalid_magic_number);
             storage
                 .write(0, DB_HEADER_SIZE)?
@@ -545,10 +707,16 @@ impl TransactionalMemory {
 
         assert!(page_size >= DB_HEADER_SIZE);
 
-        Ok(Self {
+        Ok(Some(Self {
             allocated_since_commit: Mutex::new(HashSet::new()),
+            allocated_since_commit_bytes: AtomicU64::new(0),
+            total_allocated_pages: AtomicU64::new(0),
+            total_freed_pages: AtomicU64::new(0),
             log_since_commit: Mutex::new(vec![]),
             needs_recovery: AtomicBool::new(needs_recovery),
+            header_recovery,
+            quarantine: None,
+            size_limit: None,
             storage,
             layout: Mutex::new(InProgressLayout {
                 layout,
@@ -560,11 +728,14 @@ impl TransactionalMemory {
             #[cfg(debug_assertions)]
             read_page_ref_counts: Mutex::new(HashMap::new()),
             read_from_secondary: AtomicBool::new(false),
+            trim_free_tail: AtomicBool::new(false),
             page_size: page_size.try_into().unwrap(),
             region_size,
             region_header_with_padding_size: region_header_size,
+            checksum_algorithm,
+            allocation_strategy: AllocationStrategy::default(),
             deferred_error: Mutex::new(None),
-        })
+        }))
     }
 
     pub(crate) fn set_deferred_error(&self, err: Error) {
@@ -576,10 +747,14 @@ impl TransactionalMemory {
         self.storage.set_crash_countdown(value);
     }
 
-    pub(crate) fn clear_read_cache(&mut self) {
+    pub(crate) fn clear_read_cache(&self) {
         self.storage.invalidate_cache_all()
     }
 
+    pub(crate) fn cache_stats(&self) -> CacheStats {
+        self.storage.cache_stats()
+    }
+
     pub(crate) fn clear_cache_and_reload(&mut self) -> Result {
         assert!(self.allocated_since_commit.lock().unwrap().is_empty());
         assert!(self.log_since_commit.lock().unwrap().is_empty());
@@ -590,9 +765,12 @@ impl TransactionalMemory {
         let header_bytes = self.storage.read_direct(0, DB_HEADER_SIZE)?;
         let (mut header, repair_info) = DatabaseHeader::from_bytes(&header_bytes);
         // TODO: should probably consolidate this logic with Self::new()
+        let mut header_recovery = None;
         if header.recovery_required {
+            let mut swapped_slot = false;
             if repair_info.primary_corrupted {
                 header.swap_primary_slot();
+                swapped_slot = true;
             } else {
                 // If the secondary is a valid commit, verify that the primary is newer. This handles an edge case where:
                 // * the primary bit is flipped to the secondary
@@ -601,8 +779,14 @@ impl TransactionalMemory {
                     header.secondary_slot().transaction_id > header.primary_slot().transaction_id;
                 if secondary_newer && !repair_info.secondary_corrupted {
                     header.swap_primary_slot();
+                    swapped_slot = true;
                 }
             }
+            header_recovery = Some(HeaderRecovery {
+                primary_corrupted: repair_info.primary_corrupted,
+                secondary_corrupted: repair_info.secondary_corrupted,
+                swapped_slot,
+            });
             if repair_info.invalid_magic_number {
                 return Err(Corrupted("Invalid magic number".to_string()));
             }
@@ -615,6 +799,7 @@ impl TransactionalMemory {
 
         self.needs_recovery
             .store(header.recovery_required, Ordering::Release);
+        self.header_recovery = header_recovery;
         let state = InMemoryState::from_bytes(header.clone(), &self.storage)?;
         *self.state.lock().unwrap() = state;
         let layout = header.primary_slot().layout;
@@ -636,6 +821,43 @@ impl TransactionalMemory {
         self.storage.flush()
     }
 
+    pub(crate) fn set_quarantine(&mut self, quarantine: Quarantine) {
+        self.quarantine = Some(quarantine);
+    }
+
+    pub(crate) fn set_size_limit(&mut self, limit: SizeLimit) {
+        self.size_limit = Some(limit);
+    }
+
+    pub(crate) fn pressure(&self) -> Option<Pressure> {
+        Some(self.size_limit.as_ref()?.pressure(self.file_len()))
+    }
+
+    fn database_full(&self) -> Error {
+        Error::DatabaseFull(self.size_limit.as_ref().unwrap().max_bytes())
+    }
+
+    // Called after the file grows, without holding any locks, since the callback may call back
+    // into the database
+    fn notify_pressure(&self) {
+        if let Some(limit) = self.size_limit.as_ref() {
+            limit.notify(self.file_len());
+        }
+    }
+
+    pub(crate) fn quarantine(&self) -> Option<&Quarantine> {
+        self.quarantine.as_ref()
+    }
+
+    // Returns the quarantined page, and notifies the quarantine callback, if `page` is quarantined
+    pub(crate) fn check_quarantine(&self, page: PageNumber) -> Option<&QuarantinedPage> {
+        self.quarantine.as_ref()?.check(page)
+    }
+
+    pub(crate) fn header_recovery(&self) -> Option<HeaderRecovery> {
+        self.header_recovery
+    }
+
     pub(crate) fn needs_repair(&self) -> Result<bool> {
         Ok(self.state.lock().unwrap().header.recovery_required)
     }
@@ -817,7 +1039,16 @@ impl TransactionalMemory {
         let mut layout = self.layout.lock().unwrap();
 
         // Trim surplus file space, before finalizing the commit
-        let shrunk = self.try_shrink(&mut state, &mut layout)?;
+        let shrunk = if self.trim_free_tail.swap(false, Ordering::AcqRel) {
+            // Removing a free region may leave free space at the end of the one before it
+            let mut shrunk = false;
+            while self.try_shrink(&mut state, &mut layout, true)? {
+                shrunk = true;
+            }
+            shrunk
+        } else {
+            self.try_shrink(&mut state, &mut layout, false)?
+        };
 
         let mut secondary = state.header.secondary_slot_mut();
         secondary.transaction_id = transaction_id;
@@ -853,6 +1084,8 @@ impl TransactionalMemory {
 
         self.log_since_commit.lock().unwrap().clear();
         self.allocated_since_commit.lock().unwrap().clear();
+        self.allocated_since_commit_bytes
+            .store(0, Ordering::Release);
         self.read_from_secondary.store(false, Ordering::Release);
 
         Ok(())
@@ -885,6 +1118,8 @@ impl TransactionalMemory {
 
         self.log_since_commit.lock().unwrap().clear();
         self.allocated_since_commit.lock().unwrap().clear();
+        self.allocated_since_commit_bytes
+            .store(0, Ordering::Release);
         self.storage.write_barrier()?;
         // TODO: maybe we can remove this flag and just update the in-memory DatabaseHeader state?
         self.read_from_secondary.store(true, Ordering::Release);
@@ -957,6 +1192,8 @@ impl TransactionalMemory {
             }
         }
         self.allocated_since_commit.lock().unwrap().clear();
+        self.allocated_since_commit_bytes
+            .store(0, Ordering::Release);
 
         // Shrinking only happens during commit
         assert!(restore.len() <= layout.layout.len());
@@ -1061,6 +1298,31 @@ impl TransactionalMemory {
         })
     }
 
+    // Returns the oldest file format version of the two commit slots, without opening the database
+    pub(crate) fn file_format_version(mut file: &File) -> Result<u8> {
+        let mut header_bytes = vec![0; DB_HEADER_SIZE];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header_bytes)?;
+        let (header, repair_info) = DatabaseHeader::from_bytes(&header_bytes);
+        if repair_info.invalid_magic_number {
+            return Err(io::Error::from(io::ErrorKind::InvalidData).into());
+        }
+        let version = cmp::max(
+            header.primary_slot().version,
+            header.secondary_slot().version,
+        );
+        if version > FILE_FORMAT_VERSION {
+            return Err(Error::Corrupted(format!(
+                "Expected file format version {FILE_FORMAT_VERSION}, found {version}",
+            )));
+        }
+
+        Ok(cmp::min(
+            header.primary_slot().version,
+            header.secondary_slot().version,
+        ))
+    }
+
     pub(crate) fn get_version(&self) -> u8 {
         let state = self.state.lock().unwrap();
         if self.read_from_secondary.load(Ordering::Acquire) {
@@ -1126,6 +1388,8 @@ impl TransactionalMemory {
             .lock()
             .unwrap()
             .push(AllocationOp::Free(page));
+        self.total_freed_pages
+            .fetch_add(1 << page.page_order, Ordering::AcqRel);
 
         let address_range = page.address_range(
             self.page_size as u64,
@@ -1143,6 +1407,8 @@ impl TransactionalMemory {
     // Frees the page if it was allocated since the last commit. Returns true, if the page was freed
     pub(crate) fn free_if_uncommitted(&self, page: PageNumber) -> bool {
         if self.allocated_since_commit.lock().unwrap().remove(&page) {
+            self.allocated_since_commit_bytes
+                .fetch_sub(page.page_size_bytes(self.page_size), Ordering::AcqRel);
             let mut state = self.state.lock().unwrap();
             // Free in the regional allocator
             let mut region = state.get_region_mut(page.region);
@@ -1158,6 +1424,8 @@ impl TransactionalMemory {
                 .lock()
                 .unwrap()
                 .push(AllocationOp::FreeUncommitted(page));
+            self.total_freed_pages
+                .fetch_add(1 << page.page_order, Ordering::AcqRel);
 
             let address_range = page.address_range(
                 self.page_size as u64,
@@ -1177,6 +1445,24 @@ impl TransactionalMemory {
         }
     }
 
+    // Returns the total number of pages allocated and freed since the database was opened
+    pub(crate) fn allocation_totals(&self) -> (u64, u64) {
+        (
+            self.total_allocated_pages.load(Ordering::Acquire),
+            self.total_freed_pages.load(Ordering::Acquire),
+        )
+    }
+
+    // Returns the total number of bytes written to the file since the database was opened
+    pub(crate) fn bytes_written(&self) -> u64 {
+        self.storage.bytes_written()
+    }
+
+    // Returns the total size of the pages allocated since the last commit
+    pub(crate) fn uncommitted_bytes(&self) -> u64 {
+        self.allocated_since_commit_bytes.load(Ordering::Acquire)
+    }
+
     // Page has not been committed
     pub(crate) fn uncommitted(&self, page: PageNumber) -> bool {
         self.allocated_since_commit.lock().unwrap().contains(&page)
@@ -1189,13 +1475,16 @@ impl TransactionalMemory {
         let mut state = self.state.lock().unwrap();
         let mut layout = self.layout.lock().unwrap();
 
+        let mut grew = false;
         let page_number =
             if let Some(page_number) = self.allocate_lowest_helper(&mut state, required_order)? {
                 page_number
             } else {
                 self.grow(&mut state, &mut layout, required_order)?;
+                grew = true;
+                // The growth may have been limited by the maximum size
                 self.allocate_lowest_helper(&mut state, required_order)?
-                    .unwrap()
+                    .ok_or_else(|| self.database_full())?
             };
 
         #[cfg(debug_assertions)]
@@ -1215,10 +1504,16 @@ impl TransactionalMemory {
             .lock()
             .unwrap()
             .insert(page_number);
+        self.allocated_since_commit_bytes.fetch_add(
+            page_number.page_size_bytes(self.page_size),
+            Ordering::AcqRel,
+        );
         self.log_since_commit
             .lock()
             .unwrap()
             .push(AllocationOp::Allocate(page_number));
+        self.total_allocated_pages
+            .fetch_add(1 << page_number.page_order, Ordering::AcqRel);
 
         let address_range = page_number.address_range(
             self.page_size as u64,
@@ -1242,6 +1537,12 @@ impl TransactionalMemory {
             mem.mem_mut().fill(0xFF);
         }
 
+        drop(layout);
+        drop(state);
+        if grew {
+            self.notify_pressure();
+        }
+
         Ok(PageMut {
             mem,
             page_number,
@@ -1308,10 +1609,20 @@ impl TransactionalMemory {
         }
     }
 
+    // Request that the next commit releases all the free pages at the end of the file, rather than
+    // only shrinking it when at least half of the last region is free
+    pub(crate) fn trim_free_tail_on_commit(&self) {
+        self.trim_free_tail.store(true, Ordering::Release);
+    }
+
+    // Shrinks the last region, if enough of it is free. If `trim_all` is true, all of its trailing
+    // free pages are released, otherwise only half of them are, and only if they make up at least
+    // half of the region
     fn try_shrink(
         &self,
         state: &mut InMemoryState,
         in_progress_layout: &mut InProgressLayout,
+        trim_all: bool,
     ) -> Result<bool> {
         let (layout, tracker_page) = (
             &mut in_progress_layout.layout,
@@ -1324,14 +1635,19 @@ impl TransactionalMemory {
         let trailing_free = last_allocator.trailing_free_pages();
         let last_allocator_len = last_allocator.len();
         drop(last_allocator);
-        if trailing_free < last_allocator_len / 2 {
+        if trailing_free < last_allocator_len / 2 && !trim_all {
             return Ok(false);
         }
         let reduce_to_pages = if layout.num_regions() > 1 && trailing_free == last_allocator_len {
             0
+        } else if trim_all {
+            max(MIN_USABLE_PAGES, last_allocator_len - trailing_free)
         } else {
             max(MIN_USABLE_PAGES, last_allocator_len - trailing_free / 2)
         };
+        if reduce_to_pages >= last_allocator_len {
+            return Ok(false);
+        }
 
         let new_usable_bytes = if reduce_to_pages == 0 {
             layout.usable_bytes() - last_region.usable_bytes()
@@ -1388,11 +1704,29 @@ impl TransactionalMemory {
                 layout.usable_bytes() + required_growth * 2,
             )
         };
-        let new_layout = DatabaseLayout::calculate(
+        let mut new_layout = DatabaseLayout::calculate(
             next_desired_size,
             state.header.region_max_data_pages(),
             self.page_size,
         )?;
+        if let Some(max_bytes) = self.size_limit.as_ref().map(SizeLimit::max_bytes) {
+            if new_layout.len() > max_bytes {
+                // Grow as far as the limit allows. Removing space never adds region headers, so
+                // the overhead of the clamped layout is no larger
+                let overhead = new_layout.len() - new_layout.usable_bytes();
+                let page_size = u64::from(self.page_size);
+                let clamped = max_bytes.saturating_sub(overhead) / page_size * page_size;
+                if clamped <= layout.usable_bytes() {
+                    return Err(Error::DatabaseFull(max_bytes));
+                }
+                new_layout = DatabaseLayout::calculate(
+                    clamped,
+                    state.header.region_max_data_pages(),
+                    self.page_size,
+                )?;
+                assert!(new_layout.len() <= max_bytes);
+            }
+        }
         assert!(new_layout.len() >= layout.len());
 
         self.storage.resize(new_layout.len())?;
@@ -1403,18 +1737,25 @@ impl TransactionalMemory {
     }
 
     pub(crate) fn allocate(&self, allocation_size: usize) -> Result<PageMut> {
+        if self.allocation_strategy == AllocationStrategy::Lowest {
+            return self.allocate_lowest(allocation_size);
+        }
         let required_pages = (allocation_size + self.get_page_size() - 1) / self.get_page_size();
         let required_order = ceil_log2(required_pages);
 
         let mut state = self.state.lock().unwrap();
         let mut layout = self.layout.lock().unwrap();
 
+        let mut grew = false;
         let page_number =
             if let Some(page_number) = self.allocate_helper(&mut state, required_order)? {
                 page_number
             } else {
                 self.grow(&mut state, &mut layout, required_order)?;
-                self.allocate_helper(&mut state, required_order)?.unwrap()
+                grew = true;
+                // The growth may have been limited by the maximum size
+                self.allocate_helper(&mut state, required_order)?
+                    .ok_or_else(|| self.database_full())?
             };
 
         #[cfg(debug_assertions)]
@@ -1434,10 +1775,16 @@ impl TransactionalMemory {
             .lock()
             .unwrap()
             .insert(page_number);
+        self.allocated_since_commit_bytes.fetch_add(
+            page_number.page_size_bytes(self.page_size),
+            Ordering::AcqRel,
+        );
         self.log_since_commit
             .lock()
             .unwrap()
             .push(AllocationOp::Allocate(page_number));
+        self.total_allocated_pages
+            .fetch_add(1 << page_number.page_order, Ordering::AcqRel);
 
         let address_range = page_number.address_range(
             self.page_size as u64,
@@ -1461,6 +1808,12 @@ impl TransactionalMemory {
             mem.mem_mut().fill(0xFF);
         }
 
+        drop(layout);
+        drop(state);
+        if grew {
+            self.notify_pressure();
+        }
+
         Ok(PageMut {
             mem,
             page_number,
@@ -1483,9 +1836,191 @@ impl TransactionalMemory {
         Ok(count)
     }
 
+    // Counts the allocated pages recorded in the allocator state, without assuming that it is
+    // consistent with the current layout. Used before repair, when the state may be stale.
+    // Regions whose header is unreadable are counted as empty
+    pub(crate) fn count_recorded_allocated_pages(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        state
+            .allocators
+            .region_headers
+            .iter()
+            .filter_map(|header| RegionHeaderAccessor::try_new(header))
+            .map(|region| u64::from(region.allocator().count_allocated_pages()))
+            .sum()
+    }
+
+    pub(crate) fn count_free_pages(&self) -> Result<u64> {
+        let state = self.state.lock().unwrap();
+        let layout = self.layout.lock().unwrap();
+        let mut count = 0u64;
+        for i in 0..layout.layout.num_regions() {
+            let region = state.get_region(i);
+            count += u64::from(region.allocator().count_free_pages());
+        }
+
+        Ok(count)
+    }
+
+    // Returns the number of pages in the largest block which can be allocated without growing the
+    // file
+    pub(crate) fn largest_free_block(&self) -> Result<u64> {
+        let state = self.state.lock().unwrap();
+        let layout = self.layout.lock().unwrap();
+        let mut largest = 0u64;
+        for i in 0..layout.layout.num_regions() {
+            let region = state.get_region(i);
+            if let Some(order) = region.allocator().highest_free_order() {
+                largest = max(largest, 1 << order);
+            }
+        }
+
+        Ok(largest)
+    }
+
     pub(crate) fn get_page_size(&self) -> usize {
         self.page_size.try_into().unwrap()
     }
+
+    // Length of the database file, including any growth by the current transaction
+    pub(crate) fn file_len(&self) -> u64 {
+        self.layout.lock().unwrap().layout.len()
+    }
+
+    pub(crate) fn checksum_algorithm(&self) -> ChecksumAlgorithm {
+        self.checksum_algorithm
+    }
+
+    pub(crate) fn set_allocation_strategy(&mut self, strategy: AllocationStrategy) {
+        self.allocation_strategy = strategy;
+    }
+
+    // Writes pages which were modified outside of a transaction to disk
+    pub(crate) fn flush_pages(&self) -> Result {
+        self.storage.flush()
+    }
+
+    pub(crate) fn page_range(&self, page_number: PageNumber) -> Range<u64> {
+        page_number.address_range(
+            self.page_size as u64,
+            self.region_size,
+            self.region_header_with_padding_size,
+            self.page_size,
+        )
+    }
+
+    // Returns the file length and header for a copy of the database which only contains the given
+    // roots. The copy is marked as requiring recovery, so that its allocator state is rebuilt from
+    // the reachable pages when it is opened
+    pub(super) fn header_for_copy(
+        &self,
+        user_root: Option<(PageNumber, Checksum)>,
+        system_root: Option<(PageNumber, Checksum)>,
+        transaction_id: TransactionId,
+    ) -> (u64, [u8; DB_HEADER_SIZE]) {
+        let mut header = self.state.lock().unwrap().header.clone();
+        let mut slot = header.primary_slot().clone();
+        slot.user_root = user_root;
+        slot.system_root = system_root;
+        slot.freed_root = None;
+        slot.transaction_id = transaction_id;
+        let file_len = slot.layout.len();
+        // Recovery may select either slot, so both must contain the copy's state
+        *header.secondary_slot_mut() = slot.clone();
+        header.swap_primary_slot();
+        *header.secondary_slot_mut() = slot;
+        header.recovery_required = true;
+
+        (file_len, header.to_bytes(true, false))
+    }
+
+    // Returns true if the pages of `other` are stored at the same locations as those of this
+    // database, with the same checksums, so that one can be a physical copy of the other
+    pub(crate) fn has_same_page_layout(&self, other: &TransactionalMemory) -> bool {
+        self.page_size == other.page_size
+            && self.region_size == other.region_size
+            && self.region_header_with_padding_size == other.region_header_with_padding_size
+            && self.checksum_algorithm == other.checksum_algorithm
+    }
+
+    // Grows the file to at least the length of `other`, so that its pages can be copied to the same
+    // locations in this database
+    pub(crate) fn grow_to_fit(&self, other: &TransactionalMemory) -> Result {
+        let len = other.file_len();
+        if self.storage.file_len()? < len {
+            self.storage.resize(len)?;
+        }
+        Ok(())
+    }
+
+    // Switches this database to the latest commit of `other`, whose reachable pages must already
+    // have been copied to the same locations. The allocator state is not copied, so the database
+    // is marked as requiring recovery, which rebuilds it and frees the pages of the previous commit
+    pub(crate) fn adopt_commit_of(&self, other: &TransactionalMemory) -> Result {
+        #[cfg(debug_assertions)]
+        debug_assert!(self.open_dirty_pages.lock().unwrap().is_empty());
+        assert!(self.allocated_since_commit.lock().unwrap().is_empty());
+        self.grow_to_fit(other)?;
+        self.storage.flush()?;
+
+        let (_, header) = other.header_for_copy(
+            other.get_data_root(),
+            other.get_system_root(),
+            other.get_last_committed_transaction_id()?,
+        );
+        let (header, _) = DatabaseHeader::from_bytes(&header);
+        let layout = header.primary_slot().layout;
+        let tracker_page = header.primary_slot().region_tracker;
+        self.write_header(&header, false)?;
+        self.storage.flush()?;
+        self.storage.invalidate_cache_all();
+
+        *self.state.lock().unwrap() = InMemoryState {
+            header,
+            allocators: Allocators::new(layout),
+        };
+        *self.layout.lock().unwrap() = InProgressLayout {
+            layout,
+            tracker_page,
+        };
+        self.read_from_secondary.store(false, Ordering::Release);
+        self.needs_recovery.store(true, Ordering::Release);
+
+        Ok(())
+    }
+
+    // Completes an upgrade of a database opened with open_for_upgrade(), by storing the roots of
+    // its rewritten tables in both commit slots. The allocator state is not updated, so the
+    // database is marked as requiring recovery, which rebuilds it and frees the old pages
+    pub(crate) fn finish_upgrade(
+        &self,
+        data_root: Option<(PageNumber, Checksum)>,
+        system_root: Option<(PageNumber, Checksum)>,
+    ) -> Result {
+        #[cfg(debug_assertions)]
+        debug_assert!(self.open_dirty_pages.lock().unwrap().is_empty());
+        self.storage.flush()?;
+
+        let mut state = self.state.lock().unwrap();
+        let layout = self.layout.lock().unwrap();
+        let mut slot = state.header.primary_slot().clone();
+        slot.user_root = data_root;
+        slot.system_root = system_root;
+        slot.freed_root = None;
+        slot.layout = layout.layout;
+        slot.region_tracker = layout.tracker_page;
+        *state.header.secondary_slot_mut() = slot.clone();
+        state.header.swap_primary_slot();
+        *state.header.secondary_slot_mut() = slot;
+        state.header.set_version(FILE_FORMAT_VERSION);
+        state.header.recovery_required = true;
+        self.write_header(&state.header, false)?;
+        self.storage.flush()?;
+        // Leave the recovery flag set when this is dropped
+        self.needs_recovery.store(true, Ordering::Release);
+
+        Ok(())
+    }
 }
 
 impl Drop for TransactionalMemory {
@@ -1540,3 +2075,55 @@ impl Drop for TransactionalMemory {
         }
     }
 }
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    use crate::tree_store::page_store::page_manager::TransactionalMemory;
+    use crate::tree_store::Page;
+    use crate::ChecksumAlgorithm;
+    use loom::sync::Arc;
+    use loom::thread;
+    use tempfile::NamedTempFile;
+
+    const PAGE_SIZE: usize = 512;
+
+    // An uncommitted page is freed while another page is allocated, which may reuse it. The
+    // allocation must not be affected by the free, such as by having its write cancelled
+    #[test]
+    fn free_during_allocate() {
+        loom::model(|| {
+            let file = NamedTempFile::new().unwrap().into_file();
+            let mem = Arc::new(
+                TransactionalMemory::new(file, PAGE_SIZE, None, 0, 0, ChecksumAlgorithm::Xxh3)
+                    .unwrap(),
+            );
+            mem.begin_writable().unwrap();
+            let freed = mem.allocate(PAGE_SIZE).unwrap().get_page_number();
+            let kept = mem.allocate(PAGE_SIZE).unwrap().get_page_number();
+            let (allocated_before, freed_before) = mem.allocation_totals();
+
+            let freer = {
+                let mem = mem.clone();
+                thread::spawn(move || {
+                    assert!(mem.free_if_uncommitted(freed));
+                })
+            };
+            let allocated = {
+                let mut page = mem.allocate(PAGE_SIZE).unwrap();
+                page.memory_mut()[0] = 1;
+                page.get_page_number()
+            };
+            freer.join().unwrap();
+
+            assert_ne!(allocated, kept);
+            assert!(mem.uncommitted(allocated));
+            assert!(mem.uncommitted(kept));
+            assert_eq!(mem.get_page(allocated).unwrap().memory()[0], 1);
+            assert_eq!(
+                mem.allocation_totals(),
+                (allocated_before + 1, freed_before + 1)
+            );
+            assert_eq!(mem.uncommitted_bytes(), 2 * PAGE_SIZE as u64);
+        });
+    }
+}
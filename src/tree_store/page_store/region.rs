@@ -55,6 +55,15 @@ impl<'a> RegionHeaderAccessor<'a> {
         Self { mem: data }
     }
 
+    // Returns None if the header is not initialized, or is from an unknown version
+    pub(crate) fn try_new(data: &'a [u8]) -> Option<Self> {
+        if data.first() == Some(&REGION_FORMAT_VERSION) {
+            Some(Self { mem: data })
+        } else {
+            None
+        }
+    }
+
     fn get_allocator_len(&self) -> usize {
         u32::from_le_bytes(
             self.mem[ALLOCATOR_LENGTH_OFFSET..(ALLOCATOR_LENGTH_OFFSET + size_of::<u32>())]
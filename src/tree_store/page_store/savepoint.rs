@@ -1,7 +1,8 @@
+use crate::sync::Mutex;
 use crate::transaction_tracker::{SavepointId, TransactionId, TransactionTracker};
 use crate::tree_store::{Checksum, PageNumber, TransactionalMemory};
 use std::mem::size_of;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 // on-disk format:
 // * 1 byte: version
@@ -1,15 +1,17 @@
+use crate::sync::Mutex;
 use crate::tree_store::btree::{btree_stats, UntypedBtreeMut};
 use crate::tree_store::btree_base::Checksum;
 use crate::tree_store::btree_iters::AllPageNumbersBtreeIter;
 use crate::tree_store::{BtreeMut, BtreeRangeIter, PageNumber, TransactionalMemory};
-use crate::types::{RedbKey, RedbValue, RedbValueMutInPlace, TypeName};
+use crate::types::{RedbKey, RedbValue, RedbValueMutInPlace, TypeName, TypeNameCheck};
 use crate::{DatabaseStats, Error, Result};
+#[cfg(feature = "logging")]
+use log::warn;
 use std::cmp::max;
 use std::collections::HashMap;
-use std::mem;
 use std::mem::size_of;
 use std::ops::RangeFull;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 // Forward compatibility feature in case alignment can be supported in the future
 // See https://github.com/cberner/redb/issues/360
@@ -22,10 +24,12 @@ pub(crate) struct FreedTableKey {
 }
 
 impl RedbValue for FreedTableKey {
-    type SelfType<'a> = FreedTableKey
+    type SelfType<'a>
+        = FreedTableKey
     where
         Self: 'a;
-    type AsBytes<'a> = [u8; 2 * size_of::<u64>()]
+    type AsBytes<'a>
+        = [u8; 2 * size_of::<u64>()]
     where
         Self: 'a;
 
@@ -123,12 +127,14 @@ impl FreedPageListMut {
 }
 
 impl RedbValue for FreedPageList<'_> {
-    type SelfType<'a> = FreedPageList<'a>
-        where
-            Self: 'a;
-    type AsBytes<'a> = &'a [u8]
-        where
-            Self: 'a;
+    type SelfType<'a>
+        = FreedPageList<'a>
+    where
+        Self: 'a;
+    type AsBytes<'a>
+        = &'a [u8]
+    where
+        Self: 'a;
 
     fn fixed_width() -> Option<usize> {
         None
@@ -164,7 +170,8 @@ impl RedbValueMutInPlace for FreedPageList<'_> {
     }
 
     fn from_bytes_mut(data: &mut [u8]) -> &mut Self::BaseRefType {
-        unsafe { mem::transmute(data) }
+        // Safety: FreedPageListMut is repr(transparent) over [u8]
+        unsafe { &mut *(data as *mut [u8] as *mut FreedPageListMut) }
     }
 }
 
@@ -211,6 +218,10 @@ impl InternalTableDefinition {
         self.table_root
     }
 
+    pub(crate) fn set_root(&mut self, table_root: Option<(PageNumber, Checksum)>) {
+        self.table_root = table_root;
+    }
+
     pub(crate) fn get_fixed_key_size(&self) -> Option<usize> {
         self.fixed_key_size
     }
@@ -219,6 +230,16 @@ impl InternalTableDefinition {
         self.fixed_value_size
     }
 
+    // Returns the width of the values stored in the table's btree. Multimap tables store the values
+    // of each key in a variable width collection
+    pub(crate) fn get_fixed_tree_value_size(&self) -> Option<usize> {
+        if self.table_type == TableType::Multimap {
+            None
+        } else {
+            self.fixed_value_size
+        }
+    }
+
     pub(crate) fn get_key_alignment(&self) -> usize {
         self.key_alignment
     }
@@ -230,6 +251,43 @@ impl InternalTableDefinition {
     pub(crate) fn get_type(&self) -> TableType {
         self.table_type
     }
+
+    pub(crate) fn get_key_type(&self) -> &TypeName {
+        &self.key_type
+    }
+
+    // Returns true if `data` can be parsed by from_bytes(), for definitions read from outside of
+    // the database
+    pub(crate) fn is_valid_encoding(data: &[u8]) -> bool {
+        let key_type_offset = 2
+            + PageNumber::serialized_size()
+            + size_of::<Checksum>()
+            + 2 * (1 + size_of::<u32>())
+            + 3 * size_of::<u32>();
+        if data.len() < key_type_offset || !matches!(data[0], 1 | 2) {
+            return false;
+        }
+        let key_type_len = u32::from_le_bytes(
+            data[(key_type_offset - size_of::<u32>())..key_type_offset]
+                .try_into()
+                .unwrap(),
+        ) as usize;
+        if data.len() - key_type_offset < key_type_len {
+            return false;
+        }
+        let (key_type, value_type) = data[key_type_offset..].split_at(key_type_len);
+        TypeName::is_valid_encoding(key_type) && TypeName::is_valid_encoding(value_type)
+    }
+
+    // Returns true if the keys and values of both tables have the same types, so that the entries
+    // of their btrees can be compared
+    pub(crate) fn has_same_types(&self, other: &Self) -> bool {
+        self.table_type == other.table_type
+            && self.fixed_key_size == other.fixed_key_size
+            && self.fixed_value_size == other.fixed_value_size
+            && self.key_type == other.key_type
+            && self.value_type == other.value_type
+    }
 }
 
 impl RedbValue for InternalTableDefinition {
@@ -510,17 +568,43 @@ impl<'txn> TableTree<'txn> {
         &self,
         name: &str,
         table_type: TableType,
+    ) -> Result<Option<InternalTableDefinition>> {
+        self.get_table_checked::<K, V>(name, table_type, TypeNameCheck::Strict)
+    }
+
+    // root_page: the root of the master table
+    pub(crate) fn get_table_checked<K: RedbKey, V: RedbValue>(
+        &self,
+        name: &str,
+        table_type: TableType,
+        type_name_check: TypeNameCheck,
     ) -> Result<Option<InternalTableDefinition>> {
         Ok(
             if let Some(definition) = self.get_table_untyped(name, table_type)? {
                 // Do additional checks on the types to be sure they match
                 if definition.key_type != K::type_name() || definition.value_type != V::type_name()
                 {
-                    return Err(Error::TableTypeMismatch {
-                        table: name.to_string(),
-                        key: definition.key_type,
-                        value: definition.value_type,
-                    });
+                    match type_name_check {
+                        TypeNameCheck::Strict => {
+                            return Err(Error::TableTypeMismatch {
+                                table: name.to_string(),
+                                key: definition.key_type,
+                                value: definition.value_type,
+                            });
+                        }
+                        TypeNameCheck::Warn => {
+                            #[cfg(feature = "logging")]
+                            warn!(
+                                "Opening table {} with types <{}, {}>, but it was created with <{}, {}>",
+                                name,
+                                K::type_name().name(),
+                                V::type_name().name(),
+                                definition.key_type.name(),
+                                definition.value_type.name()
+                            );
+                        }
+                        TypeNameCheck::Ignore => {}
+                    }
                 }
                 if definition.get_fixed_key_size() != K::fixed_width() {
                     return Err(Error::TypeDefinitionChanged {
@@ -568,14 +652,26 @@ impl<'txn> TableTree<'txn> {
         Ok(false)
     }
 
+    // Replaces the table `name`, if it exists, with one described by `definition`
+    pub(crate) fn replace_table_untyped(
+        &mut self,
+        name: &str,
+        definition: &InternalTableDefinition,
+    ) -> Result {
+        self.delete_table(name, definition.get_type())?;
+        self.tree.insert(&name, definition)?;
+        Ok(())
+    }
+
     // Returns a tuple of the table id and the new root page
     // root_page: the root of the master table
     pub(crate) fn get_or_create_table<K: RedbKey, V: RedbValue>(
         &mut self,
         name: &str,
         table_type: TableType,
+        type_name_check: TypeNameCheck,
     ) -> Result<InternalTableDefinition> {
-        if let Some(found) = self.get_table::<K, V>(name, table_type)? {
+        if let Some(found) = self.get_table_checked::<K, V>(name, table_type, type_name_check)? {
             return Ok(found);
         }
 
@@ -657,6 +753,9 @@ impl<'txn> TableTree<'txn> {
         Ok(DatabaseStats {
             tree_height: master_tree_stats.tree_height + max_subtree_height,
             allocated_pages: self.mem.count_allocated_pages()?,
+            free_pages: self.mem.count_free_pages()?,
+            largest_free_block: self.mem.largest_free_block()?,
+            pending_free_pages: 0,
             leaf_pages,
             branch_pages,
             stored_leaf_bytes: total_stored_bytes,
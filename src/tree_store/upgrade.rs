@@ -0,0 +1,276 @@
+use crate::tree_store::btree_base::{
+    branch_checksum, leaf_checksum, BranchBuilder, Checksum, LeafAccessor, LeafBuilder, BRANCH,
+    LEAF,
+};
+use crate::tree_store::page_store::{Page, PageImpl, TransactionalMemory};
+use crate::tree_store::PageNumber;
+use crate::{Error, Result};
+use std::mem::size_of;
+
+// Returns a replacement for a value in a leaf, or None to keep it
+pub(crate) type RewriteValue<'a> = dyn FnMut(&[u8]) -> Result<Option<Vec<u8>>> + 'a;
+
+// Layout of branch pages before file format version 116, which did not store the number of
+// entries in each child's subtree:
+// 1 byte: type
+// 1 byte: padding (padding to 16bits aligned)
+// 2 bytes: num_keys (number of keys)
+// 4 byte: padding (padding to 64bits aligned)
+// repeating (num_keys + 1 times):
+// 16 bytes: child page checksum
+// repeating (num_keys + 1 times):
+// 8 bytes: page number
+// (optional) repeating (num_keys times):
+// * 4 bytes: key end. Ending offset of the key, exclusive
+// repeating (num_keys times):
+// * n bytes: key data
+struct LegacyBranchAccessor<'a> {
+    data: &'a [u8],
+    num_keys: usize,
+    fixed_key_size: Option<usize>,
+}
+
+impl<'a> LegacyBranchAccessor<'a> {
+    fn new(data: &'a [u8], fixed_key_size: Option<usize>) -> Self {
+        debug_assert_eq!(data[0], BRANCH);
+        let num_keys = u16::from_le_bytes(data[2..4].try_into().unwrap()) as usize;
+        Self {
+            data,
+            num_keys,
+            fixed_key_size,
+        }
+    }
+
+    fn count_children(&self) -> usize {
+        self.num_keys + 1
+    }
+
+    fn child(&self, n: usize) -> (PageNumber, Checksum) {
+        let offset = 8 + size_of::<Checksum>() * n;
+        let checksum = Checksum::from_le_bytes(
+            self.data[offset..(offset + size_of::<Checksum>())]
+                .try_into()
+                .unwrap(),
+        );
+        let offset =
+            8 + size_of::<Checksum>() * self.count_children() + PageNumber::serialized_size() * n;
+        let page_number = PageNumber::from_le_bytes(
+            self.data[offset..(offset + PageNumber::serialized_size())]
+                .try_into()
+                .unwrap(),
+        );
+
+        (page_number, checksum)
+    }
+
+    fn key_section_start(&self) -> usize {
+        let start =
+            8 + (size_of::<Checksum>() + PageNumber::serialized_size()) * self.count_children();
+        if self.fixed_key_size.is_none() {
+            start + size_of::<u32>() * self.num_keys
+        } else {
+            start
+        }
+    }
+
+    fn key_end(&self, n: usize) -> usize {
+        if let Some(fixed) = self.fixed_key_size {
+            return self.key_section_start() + fixed * (n + 1);
+        }
+        let offset = 8
+            + (size_of::<Checksum>() + PageNumber::serialized_size()) * self.count_children()
+            + size_of::<u32>() * n;
+        u32::from_le_bytes(
+            self.data[offset..(offset + size_of::<u32>())]
+                .try_into()
+                .unwrap(),
+        ) as usize
+    }
+
+    fn key(&self, n: usize) -> &'a [u8] {
+        let start = if n == 0 {
+            self.key_section_start()
+        } else {
+            self.key_end(n - 1)
+        };
+        &self.data[start..self.key_end(n)]
+    }
+}
+
+// Rewrites the tree rooted at `root`, which was written before file format version 116, in the
+// current format and returns its new root. Every branch page is rewritten, with the number of
+// entries in each child's subtree. Leaves keep their layout, so they are only rewritten if
+// `upgrade_value` returns a replacement for one of their values. The old pages are not freed
+pub(crate) fn upgrade_tree(
+    root: (PageNumber, Checksum),
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+    mem: &TransactionalMemory,
+    upgrade_value: &mut RewriteValue,
+) -> Result<(PageNumber, Checksum)> {
+    let (page_number, checksum, _) =
+        upgrade_helper(root, fixed_key_size, fixed_value_size, mem, upgrade_value)?;
+    Ok((page_number, checksum))
+}
+
+fn upgrade_helper(
+    root: (PageNumber, Checksum),
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+    mem: &TransactionalMemory,
+    upgrade_value: &mut RewriteValue,
+) -> Result<(PageNumber, Checksum, u64)> {
+    let page = mem.get_page(root.0)?;
+    match page.memory()[0] {
+        LEAF => rewrite_leaf(
+            root,
+            &page,
+            fixed_key_size,
+            fixed_value_size,
+            mem,
+            upgrade_value,
+        ),
+        BRANCH => {
+            let accessor = LegacyBranchAccessor::new(page.memory(), fixed_key_size);
+            let mut builder = BranchBuilder::new(mem, accessor.count_children(), fixed_key_size);
+            let mut length = 0;
+            for i in 0..accessor.count_children() {
+                let (child, checksum, child_length) = upgrade_helper(
+                    accessor.child(i),
+                    fixed_key_size,
+                    fixed_value_size,
+                    mem,
+                    upgrade_value,
+                )?;
+                builder.push_child(child, checksum, child_length);
+                length += child_length;
+            }
+            for i in 0..accessor.num_keys {
+                builder.push_key(accessor.key(i));
+            }
+            let new_page = builder.build()?;
+            let checksum = branch_checksum(&new_page, fixed_key_size, mem.checksum_algorithm());
+
+            Ok((new_page.get_page_number(), checksum, length))
+        }
+        _ => Err(Error::Corrupted(format!(
+            "Invalid page type in {:?}",
+            root.0
+        ))),
+    }
+}
+
+// Rewrites the leaf, if `rewrite_value` returns a replacement for one of its values, and returns
+// its root and number of entries
+fn rewrite_leaf(
+    root: (PageNumber, Checksum),
+    page: &PageImpl,
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+    mem: &TransactionalMemory,
+    rewrite_value: &mut RewriteValue,
+) -> Result<(PageNumber, Checksum, u64)> {
+    let accessor = LeafAccessor::new(page.memory(), fixed_key_size, fixed_value_size);
+    let length = u64::try_from(accessor.num_pairs()).unwrap();
+    let mut values = vec![];
+    for i in 0..accessor.num_pairs() {
+        values.push(rewrite_value(accessor.entry(i).unwrap().value())?);
+    }
+    if values.iter().all(Option::is_none) {
+        return Ok((root.0, root.1, length));
+    }
+
+    let mut builder = LeafBuilder::new(mem, accessor.num_pairs(), fixed_key_size, fixed_value_size);
+    for (i, value) in values.iter().enumerate() {
+        let entry = accessor.entry(i).unwrap();
+        builder.push(
+            entry.key(),
+            value.as_deref().unwrap_or_else(|| entry.value()),
+        );
+    }
+    let new_page = builder.build()?;
+    let checksum = leaf_checksum(
+        &new_page,
+        fixed_key_size,
+        fixed_value_size,
+        mem.checksum_algorithm(),
+    );
+
+    Ok((new_page.get_page_number(), checksum, length))
+}
+
+// Rewrites the tree rooted at `root` with the branch page layout used before file format version
+// 116, so that upgrades can be tested
+#[cfg(test)]
+pub(crate) fn downgrade_tree(
+    root: (PageNumber, Checksum),
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+    mem: &TransactionalMemory,
+    downgrade_value: &mut RewriteValue,
+) -> Result<(PageNumber, Checksum)> {
+    use crate::tree_store::btree_base::BranchAccessor;
+
+    let page = mem.get_page(root.0)?;
+    if page.memory()[0] == LEAF {
+        let (page_number, checksum, _) = rewrite_leaf(
+            root,
+            &page,
+            fixed_key_size,
+            fixed_value_size,
+            mem,
+            downgrade_value,
+        )?;
+        return Ok((page_number, checksum));
+    }
+
+    let accessor = BranchAccessor::new(&page, fixed_key_size);
+    let mut children = vec![];
+    for i in 0..accessor.count_children() {
+        children.push(downgrade_tree(
+            (
+                accessor.child_page(i).unwrap(),
+                accessor.child_checksum(i).unwrap(),
+            ),
+            fixed_key_size,
+            fixed_value_size,
+            mem,
+            downgrade_value,
+        )?);
+    }
+    let keys: Vec<&[u8]> = (0..(children.len() - 1))
+        .map(|i| accessor.key(i).unwrap())
+        .collect();
+
+    let header_size = 8 + (size_of::<Checksum>() + PageNumber::serialized_size()) * children.len();
+    let key_ends_size = if fixed_key_size.is_none() {
+        size_of::<u32>() * keys.len()
+    } else {
+        0
+    };
+    let key_bytes: usize = keys.iter().map(|key| key.len()).sum();
+    let mut new_page = mem.allocate(header_size + key_ends_size + key_bytes)?;
+    let data = new_page.memory_mut();
+    data[0] = BRANCH;
+    data[2..4].copy_from_slice(&u16::try_from(keys.len()).unwrap().to_le_bytes());
+    for (i, (page_number, checksum)) in children.iter().enumerate() {
+        let offset = 8 + size_of::<Checksum>() * i;
+        data[offset..(offset + size_of::<Checksum>())].copy_from_slice(&checksum.to_le_bytes());
+        let offset = 8 + size_of::<Checksum>() * children.len() + PageNumber::serialized_size() * i;
+        data[offset..(offset + PageNumber::serialized_size())]
+            .copy_from_slice(&page_number.to_le_bytes());
+    }
+    let mut key_end = header_size + key_ends_size;
+    for (i, key) in keys.iter().enumerate() {
+        data[key_end..(key_end + key.len())].copy_from_slice(key);
+        key_end += key.len();
+        if fixed_key_size.is_none() {
+            let offset = header_size + size_of::<u32>() * i;
+            data[offset..(offset + size_of::<u32>())]
+                .copy_from_slice(&u32::try_from(key_end).unwrap().to_le_bytes());
+        }
+    }
+    let checksum = mem.checksum_algorithm().checksum(&data[..key_end]);
+
+    Ok((new_page.get_page_number(), checksum))
+}
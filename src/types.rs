@@ -1,4 +1,4 @@
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
 use std::convert::TryInto;
 use std::fmt::Debug;
 
@@ -25,6 +25,21 @@ impl TypeClassification {
     }
 }
 
+/// Controls how a difference between the type names stored for a table and the type names of the
+/// [`RedbKey`] and [`RedbValue`] types it is opened with is handled.
+///
+/// The stored fixed width and alignment of the types must always match, regardless of this setting.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum TypeNameCheck {
+    /// A type name mismatch returns [`crate::Error::TableTypeMismatch`]
+    Strict,
+    /// A type name mismatch is logged, if the `logging` feature is enabled, and the table is opened
+    Warn,
+    /// Type names are not compared
+    Ignore,
+}
+
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct TypeName {
     classification: TypeClassification,
@@ -65,9 +80,48 @@ impl TypeName {
         }
     }
 
+    // Returns true if `bytes` can be parsed by from_bytes(), for type names read from outside of
+    // the database
+    pub(crate) fn is_valid_encoding(bytes: &[u8]) -> bool {
+        matches!(bytes.first(), Some(1 | 2)) && std::str::from_utf8(&bytes[1..]).is_ok()
+    }
+
     pub(crate) fn name(&self) -> &str {
         &self.name
     }
+
+    // Returns the key order of the built-in type with this name, and the fixed width it requires,
+    // or None if this is not a built-in key type, or is one composed of other types
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn builtin_key_order(
+        &self,
+    ) -> Option<(fn(&[u8], &[u8]) -> Ordering, Option<usize>)> {
+        fn key<K: RedbKey>() -> (fn(&[u8], &[u8]) -> Ordering, Option<usize>) {
+            (K::compare, K::fixed_width())
+        }
+
+        if self.classification != TypeClassification::Internal {
+            return None;
+        }
+        let order = match self.name.as_str() {
+            "()" => key::<()>(),
+            "u8" => key::<u8>(),
+            "u16" => key::<u16>(),
+            "u32" => key::<u32>(),
+            "u64" => key::<u64>(),
+            "u128" => key::<u128>(),
+            "i8" => key::<i8>(),
+            "i16" => key::<i16>(),
+            "i32" => key::<i32>(),
+            "i64" => key::<i64>(),
+            "i128" => key::<i128>(),
+            // These are encoded such that their byte order is their key order
+            "&[u8]" | "&str" | "OrderedF32" | "OrderedF64" => key::<&[u8]>(),
+            name if name.starts_with("[u8;") || name.starts_with("BigEndian<") => key::<&[u8]>(),
+            _ => return None,
+        };
+        Some(order)
+    }
 }
 
 pub trait RedbValue: Debug {
@@ -200,6 +254,9 @@ impl<T: RedbValue> RedbValue for Option<T> {
         if let Some(x) = value {
             result[0] = 1;
             result.extend_from_slice(T::as_bytes(x).as_ref());
+        } else if let Some(fixed_width) = T::fixed_width() {
+            // None must still fill the fixed width
+            result.extend_from_slice(&vec![0; fixed_width]);
         }
         result
     }
@@ -209,6 +266,46 @@ impl<T: RedbValue> RedbValue for Option<T> {
     }
 }
 
+// Stored with the same encoding as T. Only the comparison is reversed, since complementing the
+// bytes would not reverse the order of variable width keys, where one key is a prefix of another
+impl<T: RedbValue> RedbValue for Reverse<T> {
+    type SelfType<'a> = Reverse<T::SelfType<'a>>
+    where
+        Self: 'a;
+    type AsBytes<'a> = T::AsBytes<'a>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        T::fixed_width()
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Reverse<T::SelfType<'a>>
+    where
+        Self: 'a,
+    {
+        Reverse(T::from_bytes(data))
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> T::AsBytes<'a>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        T::as_bytes(&value.0)
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::internal(&format!("Reverse<{}>", T::type_name().name()))
+    }
+}
+
+impl<T: RedbKey> RedbKey for Reverse<T> {
+    fn compare(data1: &[u8], data2: &[u8]) -> Ordering {
+        T::compare(data2, data1)
+    }
+}
+
 impl RedbValue for &[u8] {
     type SelfType<'a> = &'a [u8]
     where
@@ -383,3 +480,188 @@ be_impl!(i64);
 be_impl!(i128);
 be_value!(f32);
 be_value!(f64);
+
+macro_rules! ordered_float {
+    ($name:ident, $t:ty, $bits:ty) => {
+        #[doc = concat!("An `", stringify!($t), "` which can be used as a [`RedbKey`]")]
+        ///
+        /// Keys are ordered by `total_cmp()`, so negative zero sorts before positive zero, and NaNs
+        /// sort after positive infinity (or before negative infinity, if their sign bit is set).
+        /// The value is stored such that the byte order of the encoding matches this ordering.
+        #[derive(Copy, Clone, Debug, Default)]
+        pub struct $name(pub $t);
+
+        impl $name {
+            const SIGN_BIT: $bits = 1 << (<$bits>::BITS - 1);
+
+            fn encode(&self) -> [u8; std::mem::size_of::<$t>()] {
+                let bits = self.0.to_bits();
+                let bits = if bits & Self::SIGN_BIT != 0 {
+                    !bits
+                } else {
+                    bits | Self::SIGN_BIT
+                };
+                bits.to_be_bytes()
+            }
+
+            fn decode(data: &[u8]) -> Self {
+                let bits = <$bits>::from_be_bytes(data.try_into().unwrap());
+                let bits = if bits & Self::SIGN_BIT != 0 {
+                    bits & !Self::SIGN_BIT
+                } else {
+                    !bits
+                };
+                Self(<$t>::from_bits(bits))
+            }
+        }
+
+        impl From<$t> for $name {
+            fn from(value: $t) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for $t {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+
+        impl RedbValue for $name {
+            type SelfType<'a> = $name;
+            type AsBytes<'a> = [u8; std::mem::size_of::<$t>()] where Self: 'a;
+
+            fn fixed_width() -> Option<usize> {
+                Some(std::mem::size_of::<$t>())
+            }
+
+            fn from_bytes<'a>(data: &'a [u8]) -> $name
+            where
+                Self: 'a,
+            {
+                Self::decode(data)
+            }
+
+            fn as_bytes<'a, 'b: 'a>(
+                value: &'a Self::SelfType<'b>,
+            ) -> [u8; std::mem::size_of::<$t>()]
+            where
+                Self: 'a,
+                Self: 'b,
+            {
+                value.encode()
+            }
+
+            fn type_name() -> TypeName {
+                TypeName::internal(stringify!($name))
+            }
+        }
+
+        impl RedbKey for $name {
+            fn compare(data1: &[u8], data2: &[u8]) -> Ordering {
+                data1.cmp(data2)
+            }
+        }
+    };
+}
+
+ordered_float!(OrderedF32, f32, u32);
+ordered_float!(OrderedF64, f64, u64);
+
+/// An integer key which is stored in big-endian byte order
+///
+/// The native integer types are stored in little-endian order, so their byte order does not match
+/// their numeric order. The encoding of a `BigEndian` key sorts byte-wise in numeric order, so it
+/// can be concatenated with other keys to build composite `&[u8]` keys. Signed integers are stored
+/// with their sign bit flipped, so that negative values sort before positive values.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct BigEndian<T>(pub T);
+
+impl<T> From<T> for BigEndian<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+macro_rules! big_endian_impl {
+    ($t:ty, $flip:expr) => {
+        impl BigEndian<$t> {
+            /// Returns the encoding of this key, which sorts byte-wise in numeric order
+            pub fn encode(&self) -> [u8; std::mem::size_of::<$t>()] {
+                (self.0 ^ $flip).to_be_bytes()
+            }
+
+            /// Decodes a key from the output of [`Self::encode`]
+            pub fn decode(bytes: [u8; std::mem::size_of::<$t>()]) -> Self {
+                Self(<$t>::from_be_bytes(bytes) ^ $flip)
+            }
+        }
+
+        impl RedbValue for BigEndian<$t> {
+            type SelfType<'a> = BigEndian<$t>;
+            type AsBytes<'a> = [u8; std::mem::size_of::<$t>()] where Self: 'a;
+
+            fn fixed_width() -> Option<usize> {
+                Some(std::mem::size_of::<$t>())
+            }
+
+            fn from_bytes<'a>(data: &'a [u8]) -> BigEndian<$t>
+            where
+                Self: 'a,
+            {
+                BigEndian::<$t>::decode(data.try_into().unwrap())
+            }
+
+            fn as_bytes<'a, 'b: 'a>(
+                value: &'a Self::SelfType<'b>,
+            ) -> [u8; std::mem::size_of::<$t>()]
+            where
+                Self: 'a,
+                Self: 'b,
+            {
+                value.encode()
+            }
+
+            fn type_name() -> TypeName {
+                TypeName::internal(concat!("BigEndian<", stringify!($t), ">"))
+            }
+        }
+
+        impl RedbKey for BigEndian<$t> {
+            fn compare(data1: &[u8], data2: &[u8]) -> Ordering {
+                data1.cmp(data2)
+            }
+        }
+    };
+}
+
+big_endian_impl!(u8, 0);
+big_endian_impl!(u16, 0);
+big_endian_impl!(u32, 0);
+big_endian_impl!(u64, 0);
+big_endian_impl!(u128, 0);
+big_endian_impl!(i8, i8::MIN);
+big_endian_impl!(i16, i16::MIN);
+big_endian_impl!(i32, i32::MIN);
+big_endian_impl!(i64, i64::MIN);
+big_endian_impl!(i128, i128::MIN);
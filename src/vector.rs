@@ -0,0 +1,298 @@
+use crate::types::{RedbKey, RedbValue, TypeName};
+use crate::{AccessGuard, ReadableTable, Result};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt::{Debug, Formatter};
+
+const ELEMENT_SIZE: usize = std::mem::size_of::<f32>();
+// Number of independent accumulators, so that the distance loops can be vectorized
+const LANES: usize = 8;
+
+#[derive(Clone)]
+enum VectorData<'a, const D: usize> {
+    Stored(&'a [u8]),
+    Owned([f32; D]),
+}
+
+/// A vector of `D` `f32`s, such as an embedding, which can be stored as a table value
+///
+/// Elements are stored in little-endian order. Values read from a table borrow the stored bytes,
+/// so reading elements does not copy the vector.
+#[derive(Clone)]
+pub struct FixedVector<'a, const D: usize> {
+    data: VectorData<'a, D>,
+}
+
+impl<'a, const D: usize> FixedVector<'a, D> {
+    /// Returns a vector with the given elements
+    pub fn new(elements: [f32; D]) -> Self {
+        Self {
+            data: VectorData::Owned(elements),
+        }
+    }
+
+    /// Returns the element at `index`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= D`
+    pub fn get(&self, index: usize) -> f32 {
+        assert!(index < D);
+        match &self.data {
+            VectorData::Stored(bytes) => {
+                let start = index * ELEMENT_SIZE;
+                f32::from_le_bytes(bytes[start..(start + ELEMENT_SIZE)].try_into().unwrap())
+            }
+            VectorData::Owned(elements) => elements[index],
+        }
+    }
+
+    /// Returns an iterator over the elements
+    pub fn iter(&self) -> impl Iterator<Item = f32> + '_ {
+        (0..D).map(|i| self.get(i))
+    }
+
+    /// Returns the elements as an array
+    pub fn to_array(&self) -> [f32; D] {
+        match &self.data {
+            VectorData::Stored(_) => {
+                let mut result = [0.0; D];
+                for (i, element) in result.iter_mut().enumerate() {
+                    *element = self.get(i);
+                }
+                result
+            }
+            VectorData::Owned(elements) => *elements,
+        }
+    }
+
+    /// Returns the elements as a slice, without copying them
+    ///
+    /// Returns `None` if the vector was read from a table, and the stored bytes are not aligned
+    /// for `f32`, the target is big-endian, or redb was built with `--cfg force_safe`. In that
+    /// case, use [`Self::to_array`]
+    pub fn as_slice(&self) -> Option<&[f32]> {
+        match &self.data {
+            #[cfg(all(target_endian = "little", not(force_safe)))]
+            VectorData::Stored(bytes) => {
+                // Every bit pattern is a valid f32, and the slice is only used if it is aligned
+                let (prefix, elements, _) = unsafe { bytes.align_to::<f32>() };
+                if prefix.is_empty() {
+                    Some(elements)
+                } else {
+                    None
+                }
+            }
+            #[cfg(any(not(target_endian = "little"), force_safe))]
+            VectorData::Stored(_) => None,
+            VectorData::Owned(elements) => Some(elements),
+        }
+    }
+}
+
+impl<const D: usize> From<[f32; D]> for FixedVector<'_, D> {
+    fn from(elements: [f32; D]) -> Self {
+        Self::new(elements)
+    }
+}
+
+impl<const D: usize> PartialEq for FixedVector<'_, D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<const D: usize> Debug for FixedVector<'_, D> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<const D: usize> RedbValue for FixedVector<'_, D> {
+    type SelfType<'a> = FixedVector<'a, D>
+    where
+        Self: 'a;
+    type AsBytes<'a> = Cow<'a, [u8]>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        Some(D * ELEMENT_SIZE)
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> FixedVector<'a, D>
+    where
+        Self: 'a,
+    {
+        assert_eq!(data.len(), D * ELEMENT_SIZE);
+        FixedVector {
+            data: VectorData::Stored(data),
+        }
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Cow<'a, [u8]>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        match &value.data {
+            VectorData::Stored(bytes) => Cow::Borrowed(bytes),
+            VectorData::Owned(elements) => {
+                let mut result = Vec::with_capacity(D * ELEMENT_SIZE);
+                for element in elements {
+                    result.extend_from_slice(&element.to_le_bytes());
+                }
+                Cow::Owned(result)
+            }
+        }
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::internal(&format!("FixedVector<{D}>"))
+    }
+}
+
+/// Distance function used by [`knn_scan`]. Smaller distances are nearer
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Distance {
+    /// Euclidean (L2) distance
+    Euclidean,
+    /// One minus the cosine similarity. Vectors with a norm of zero are at distance one from
+    /// every vector
+    Cosine,
+    /// Negated dot product, so that the vectors with the largest dot product are nearest
+    DotProduct,
+}
+
+struct Sums {
+    dot: f32,
+    // Squared norm of the first vector
+    squared_norm: f32,
+    squared_distance: f32,
+}
+
+#[inline(always)]
+fn sums_generic(a: &[f32], b: &[f32]) -> Sums {
+    let mut dot = [0.0; LANES];
+    let mut squared_norm = [0.0; LANES];
+    let mut squared_distance = [0.0; LANES];
+    let chunks = a.chunks_exact(LANES).zip(b.chunks_exact(LANES));
+    for (a, b) in chunks {
+        for i in 0..LANES {
+            dot[i] += a[i] * b[i];
+            squared_norm[i] += a[i] * a[i];
+            squared_distance[i] += (a[i] - b[i]) * (a[i] - b[i]);
+        }
+    }
+    let remainder = a.len() - a.len() % LANES;
+    for (a, b) in a[remainder..].iter().zip(b[remainder..].iter()) {
+        dot[0] += a * b;
+        squared_norm[0] += a * a;
+        squared_distance[0] += (a - b) * (a - b);
+    }
+    Sums {
+        dot: dot.iter().sum(),
+        squared_norm: squared_norm.iter().sum(),
+        squared_distance: squared_distance.iter().sum(),
+    }
+}
+
+#[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), not(force_safe)))]
+#[target_feature(enable = "avx2")]
+unsafe fn sums_avx2(a: &[f32], b: &[f32]) -> Sums {
+    sums_generic(a, b)
+}
+
+fn sums(a: &[f32], b: &[f32]) -> Sums {
+    #[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), not(force_safe)))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { sums_avx2(a, b) };
+        }
+    }
+    sums_generic(a, b)
+}
+
+impl Distance {
+    fn compute(&self, vector: &[f32], query: &[f32], query_norm: f32) -> f32 {
+        let sums = sums(vector, query);
+        match self {
+            Distance::Euclidean => sums.squared_distance.sqrt(),
+            Distance::Cosine => {
+                let norms = sums.squared_norm.sqrt() * query_norm;
+                if norms == 0.0 {
+                    1.0
+                } else {
+                    1.0 - sums.dot / norms
+                }
+            }
+            Distance::DotProduct => -sums.dot,
+        }
+    }
+}
+
+struct Candidate<'a, K: RedbKey + 'static> {
+    distance: f32,
+    key: AccessGuard<'a, K>,
+}
+
+impl<K: RedbKey> PartialEq for Candidate<'_, K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: RedbKey> Eq for Candidate<'_, K> {}
+
+impl<K: RedbKey> PartialOrd for Candidate<'_, K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: RedbKey> Ord for Candidate<'_, K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance)
+    }
+}
+
+/// Returns the keys of the `k` vectors in `table` which are nearest to `query`, and their
+/// distances, from nearest to furthest
+///
+/// This is an exact search, which reads every vector in the table, so it is best suited to tables
+/// of up to a few hundred thousand vectors. Only `k` candidates are held in memory at a time.
+/// Distances are computed with SIMD instructions where the target supports them.
+pub fn knn_scan<'t, K: RedbKey + 'static, const D: usize>(
+    table: &'t impl ReadableTable<K, FixedVector<'static, D>>,
+    query: &[f32; D],
+    k: usize,
+    distance: Distance,
+) -> Result<Vec<(AccessGuard<'t, K>, f32)>> {
+    if k == 0 {
+        return Ok(vec![]);
+    }
+    let query_norm = query.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let mut nearest: BinaryHeap<Candidate<K>> = BinaryHeap::with_capacity(k + 1);
+    for entry in table.iter()? {
+        let (key, value) = entry?;
+        let vector = value.value();
+        let d = match vector.as_slice() {
+            Some(elements) => distance.compute(elements, query, query_norm),
+            None => distance.compute(&vector.to_array(), query, query_norm),
+        };
+        if nearest.len() == k && nearest.peek().unwrap().distance.total_cmp(&d).is_le() {
+            continue;
+        }
+        nearest.push(Candidate { distance: d, key });
+        if nearest.len() > k {
+            nearest.pop();
+        }
+    }
+
+    Ok(nearest
+        .into_sorted_vec()
+        .into_iter()
+        .map(|candidate| (candidate.key, candidate.distance))
+        .collect())
+}
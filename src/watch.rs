@@ -0,0 +1,154 @@
+use crate::tree_store::{Btree, PageHint, TableTree, TableType, TransactionalMemory};
+use crate::types::{RedbKey, RedbValue};
+use crate::{ReadTransaction, Result};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+
+// Reads the current value of a watched key, in serialized form
+type ValueReader =
+    Box<dyn Fn(&TableTree, &TransactionalMemory) -> Result<Option<Vec<u8>>> + Send + Sync>;
+
+// Shared between a KeyWatch and its registration
+struct WatchState {
+    // Number of commits which have changed the key
+    changes: u64,
+    waker: Option<Waker>,
+}
+
+struct Watch {
+    read: ValueReader,
+    // Value as of the last commit
+    value: Option<Vec<u8>>,
+    state: Weak<Mutex<WatchState>>,
+}
+
+// The keys being watched, which are checked after each commit
+pub(crate) struct KeyWatches {
+    watches: Mutex<Vec<Watch>>,
+}
+
+impl KeyWatches {
+    pub(crate) fn new() -> Self {
+        Self {
+            watches: Mutex::new(vec![]),
+        }
+    }
+
+    pub(crate) fn watch<'db, K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        table: &str,
+        key: &[u8],
+        begin_read: impl FnOnce() -> Result<ReadTransaction<'db>>,
+    ) -> Result<KeyWatch> {
+        let table = table.to_string();
+        let key = key.to_vec();
+        let read: ValueReader =
+            Box::new(move |tree, mem| read_value::<K, V>(tree, mem, &table, &key));
+
+        // The snapshot is taken while holding the lock, so that a concurrent commit is either
+        // included in it, or is checked against it after the watch is registered
+        let mut watches = self.watches.lock().unwrap();
+        let txn = begin_read()?;
+        let value = read(txn.table_tree(), txn.mem())?;
+        let state = Arc::new(Mutex::new(WatchState {
+            changes: 0,
+            waker: None,
+        }));
+        watches.push(Watch {
+            read,
+            value,
+            state: Arc::downgrade(&state),
+        });
+
+        Ok(KeyWatch { state, seen: 0 })
+    }
+
+    // Wakes the watches whose key was changed by a commit. `tree` is the table tree as committed
+    pub(crate) fn notify(&self, tree: &TableTree, mem: &TransactionalMemory) {
+        let mut watches = self.watches.lock().unwrap();
+        watches.retain_mut(|watch| {
+            let state = if let Some(state) = watch.state.upgrade() {
+                state
+            } else {
+                return false;
+            };
+            let changed = match (watch.read)(tree, mem) {
+                Ok(value) => {
+                    let changed = value != watch.value;
+                    watch.value = value;
+                    changed
+                }
+                // The commit has already succeeded, so err on the side of reporting a change
+                Err(_) => true,
+            };
+            if changed {
+                let mut state = state.lock().unwrap();
+                state.changes += 1;
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            }
+            true
+        });
+    }
+}
+
+fn read_value<K: RedbKey + 'static, V: RedbValue + 'static>(
+    tree: &TableTree,
+    mem: &TransactionalMemory,
+    table: &str,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>> {
+    if let Some(definition) = tree.get_table::<K, V>(table, TableType::Normal)? {
+        let btree: Btree<K, V> = Btree::new(definition.get_root(), PageHint::None, mem)?;
+        Ok(btree
+            .get(&K::from_bytes(key))?
+            .map(|value| value.raw_bytes().to_vec()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// A subscription to changes of a single key, returned by [`crate::Database::watch_key`]
+///
+/// A change is any commit which inserts, modifies or removes the key, including by deleting its
+/// table. Writing the same value again is not a change. The watch is cancelled when dropped
+pub struct KeyWatch {
+    state: Arc<Mutex<WatchState>>,
+    seen: u64,
+}
+
+impl KeyWatch {
+    /// Returns a future which resolves once the key has been changed by a commit since the last
+    /// time a future returned by this method resolved, or since the watch was created
+    ///
+    /// Changes made by several commits before the future is polled are reported only once, so
+    /// the value should be read again after the future resolves, rather than counting changes
+    pub fn changed(&mut self) -> KeyChanged<'_> {
+        KeyChanged { watch: self }
+    }
+}
+
+/// Future returned by [`KeyWatch::changed`]
+pub struct KeyChanged<'a> {
+    watch: &'a mut KeyWatch,
+}
+
+impl<'a> Future for KeyChanged<'a> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let changes = {
+            let mut state = self.watch.state.lock().unwrap();
+            if state.changes == self.watch.seen {
+                state.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            state.changes
+        };
+        self.watch.seen = changes;
+        Poll::Ready(())
+    }
+}
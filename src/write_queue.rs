@@ -0,0 +1,165 @@
+use crate::{Database, Result, WriteTransaction};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+
+// Shared between a WriteFuture and the queued write which completes it
+struct Completion<R> {
+    result: Option<Result<R>>,
+    waker: Option<Waker>,
+}
+
+trait QueuedWrite: Send {
+    // Runs the write in `txn`. The value it returns is held until the transaction completes
+    fn run(&mut self, txn: &WriteTransaction) -> Result;
+
+    // Resolves the future with the held value, or with the error which prevented the commit
+    fn complete(self: Box<Self>, result: Result);
+}
+
+struct Write<R, F> {
+    f: F,
+    value: Option<R>,
+    completion: Arc<Mutex<Completion<R>>>,
+}
+
+impl<R: Send, F: FnMut(&WriteTransaction) -> Result<R> + Send> QueuedWrite for Write<R, F> {
+    fn run(&mut self, txn: &WriteTransaction) -> Result {
+        self.value = Some((self.f)(txn)?);
+        Ok(())
+    }
+
+    fn complete(mut self: Box<Self>, result: Result) {
+        let result = result.map(|_| self.value.take().unwrap());
+        let mut completion = self.completion.lock().unwrap();
+        completion.result = Some(result);
+        if let Some(waker) = completion.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Executes writes submitted from many threads or async tasks serially, on a dedicated thread
+///
+/// Writes which are queued at the same time are run in a single write transaction, up to
+/// `max_batch_size` at a time, so that they share the cost of a commit. If any write in a batch
+/// fails, or the commit fails, the batch is aborted and each of its writes is retried in its own
+/// transaction, so that errors are only returned to the write which caused them. Writes may
+/// therefore be run more than once.
+///
+/// Dropping the queue waits for all submitted writes to complete. A panic in a write stops the
+/// queue
+pub struct WriteQueue {
+    sender: Option<Mutex<Sender<Box<dyn QueuedWrite>>>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl WriteQueue {
+    /// Construct a queue which writes to `db`
+    ///
+    /// ## Invariant
+    ///
+    /// `max_batch_size` must be greater than zero
+    pub fn new(db: Arc<Database>, max_batch_size: usize) -> Self {
+        assert!(max_batch_size > 0);
+        let (sender, receiver) = channel();
+        let worker = thread::spawn(move || Self::run_worker(&db, receiver, max_batch_size));
+        Self {
+            sender: Some(Mutex::new(sender)),
+            worker: Some(worker),
+        }
+    }
+
+    /// Queues `f` to be run in a write transaction. The returned future resolves once the
+    /// transaction has committed
+    pub fn submit<R: Send + 'static>(
+        &self,
+        f: impl FnMut(&WriteTransaction) -> Result<R> + Send + 'static,
+    ) -> WriteFuture<R> {
+        let completion = Arc::new(Mutex::new(Completion {
+            result: None,
+            waker: None,
+        }));
+        let write = Write {
+            f,
+            value: None,
+            completion: completion.clone(),
+        };
+        self.sender
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .send(Box::new(write))
+            .unwrap();
+
+        WriteFuture { completion }
+    }
+
+    fn run_worker(db: &Database, receiver: Receiver<Box<dyn QueuedWrite>>, max_batch_size: usize) {
+        while let Ok(first) = receiver.recv() {
+            let mut batch = vec![first];
+            while batch.len() < max_batch_size {
+                if let Ok(write) = receiver.try_recv() {
+                    batch.push(write);
+                } else {
+                    break;
+                }
+            }
+
+            if batch.len() > 1 && Self::run_batch(db, &mut batch).is_ok() {
+                for write in batch {
+                    write.complete(Ok(()));
+                }
+                continue;
+            }
+            for mut write in batch {
+                let result = Self::run_batch(db, std::slice::from_mut(&mut write));
+                write.complete(result);
+            }
+        }
+    }
+
+    fn run_batch(db: &Database, batch: &mut [Box<dyn QueuedWrite>]) -> Result {
+        // The transaction is aborted when dropped, if a write fails
+        let txn = db.begin_write_abort_on_drop()?;
+        for write in batch.iter_mut() {
+            write.run(&txn)?;
+        }
+        txn.commit()
+    }
+}
+
+impl Drop for WriteQueue {
+    fn drop(&mut self) {
+        // Closing the channel stops the worker, once it has drained the queue
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() && !thread::panicking() {
+                panic!("WriteQueue worker panicked");
+            }
+        }
+    }
+}
+
+/// Future returned by [`WriteQueue::submit`], which resolves to the result of the write
+pub struct WriteFuture<R> {
+    completion: Arc<Mutex<Completion<R>>>,
+}
+
+impl<R> Future for WriteFuture<R> {
+    type Output = Result<R>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut completion = self.completion.lock().unwrap();
+        if let Some(result) = completion.result.take() {
+            Poll::Ready(result)
+        } else {
+            completion.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
@@ -6,3 +6,50 @@ from unittest import TestCase
 class TableTestCase(TestCase):
     def test_import(self):
         import redb
+
+    def test_tables(self):
+        from tempfile import NamedTemporaryFile
+        from redb import Database
+
+        with NamedTemporaryFile() as tmpfile:
+            db = Database.create(tmpfile.name)
+            with db.begin_write() as txn:
+                table = txn.open_table("data")
+                for key in [b"a", b"b", b"c", b"d"]:
+                    self.assertIsNone(table.insert(key, key * 3))
+                self.assertEqual(table.insert(b"d", b"x"), b"ddd")
+                self.assertEqual(table.remove(b"a"), b"aaa")
+                self.assertIsNone(table.remove(b"a"))
+                self.assertEqual(table.get(b"b"), b"bbb")
+                self.assertEqual(len(table), 3)
+
+            with db.begin_read() as txn:
+                self.assertEqual(txn.list_tables(), ["data"])
+                table = txn.open_table("data")
+                self.assertEqual(table.get(b"d"), b"x")
+                self.assertIsNone(table.get(b"a"))
+                self.assertEqual(table.range(), [(b"b", b"bbb"), (b"c", b"ccc"), (b"d", b"x")])
+                self.assertEqual(table.range(b"c", b"d"), [(b"c", b"ccc")])
+                with self.assertRaises(RuntimeError):
+                    txn.open_table("missing")
+
+    def test_abort(self):
+        from tempfile import NamedTemporaryFile
+        from redb import Database
+
+        with NamedTemporaryFile() as tmpfile:
+            db = Database.create(tmpfile.name)
+            with self.assertRaises(KeyError):
+                with db.begin_write() as txn:
+                    txn.open_table("data").insert(b"a", b"1")
+                    raise KeyError()
+
+            txn = db.begin_write()
+            table = txn.open_table("data")
+            table.insert(b"b", b"2")
+            txn.abort()
+            with self.assertRaises(ValueError):
+                table.get(b"a")
+
+            with db.begin_write() as txn:
+                self.assertEqual(len(txn.open_table("data")), 0)
@@ -0,0 +1,161 @@
+#![cfg(feature = "arrow")]
+
+use redb::arrow::arrow_array::cast::AsArray;
+use redb::arrow::arrow_array::types::{Float64Type, UInt32Type, UInt64Type};
+use redb::arrow::arrow_schema::DataType;
+use redb::arrow::ArrowMapping;
+use redb::{Database, TableDefinition};
+use tempfile::NamedTempFile;
+
+#[test]
+fn to_arrow() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let definition: TableDefinition<u64, f64> = TableDefinition::new("x");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        for i in 0..1000u64 {
+            table.insert(i, i as f64 / 4.0).unwrap();
+        }
+        let batch = table.to_arrow(..10, &ArrowMapping::default()).unwrap();
+        assert_eq!(batch.num_rows(), 10);
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    let batch = table
+        .to_arrow(100..600, &ArrowMapping::new("id", "score"))
+        .unwrap();
+    let schema = batch.schema();
+    assert_eq!(schema.field(0).name(), "id");
+    assert_eq!(schema.field(0).data_type(), &DataType::UInt64);
+    assert!(!schema.field(0).is_nullable());
+    assert_eq!(schema.field(1).name(), "score");
+    assert_eq!(schema.field(1).data_type(), &DataType::Float64);
+
+    let ids = batch.column(0).as_primitive::<UInt64Type>();
+    let scores = batch.column(1).as_primitive::<Float64Type>();
+    assert_eq!(ids.len(), 500);
+    for i in 0..500 {
+        assert_eq!(ids.value(i), 100 + i as u64);
+        assert_eq!(scores.value(i), (100 + i) as f64 / 4.0);
+    }
+
+    let empty = table.to_arrow(1000.., &ArrowMapping::default()).unwrap();
+    assert_eq!(empty.num_rows(), 0);
+    assert_eq!(empty.num_columns(), 2);
+}
+
+#[test]
+fn to_arrow_variable_width() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let definition: TableDefinition<&str, Option<u32>> = TableDefinition::new("x");
+    let blobs: TableDefinition<&[u8; 2], &[u8]> = TableDefinition::new("blobs");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        table.insert("a", Some(1)).unwrap();
+        table.insert("b", None).unwrap();
+        table.insert("c", Some(3)).unwrap();
+        let mut table = write_txn.open_table(blobs).unwrap();
+        table.insert(&[1, 2], [7u8; 10].as_slice()).unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    let batch = table
+        .to_arrow::<&str>(.., &ArrowMapping::default())
+        .unwrap();
+    assert_eq!(batch.schema().field(0).data_type(), &DataType::Utf8);
+    assert!(batch.schema().field(1).is_nullable());
+    let keys = batch.column(0).as_string::<i32>();
+    let values = batch.column(1).as_primitive::<UInt32Type>();
+    assert_eq!(
+        keys.iter().collect::<Vec<_>>(),
+        vec![Some("a"), Some("b"), Some("c")]
+    );
+    assert_eq!(
+        values.iter().collect::<Vec<_>>(),
+        vec![Some(1), None, Some(3)]
+    );
+
+    let table = read_txn.open_table(blobs).unwrap();
+    let batch = table
+        .to_arrow::<&[u8; 2]>(.., &ArrowMapping::default())
+        .unwrap();
+    assert_eq!(
+        batch.schema().field(0).data_type(),
+        &DataType::FixedSizeBinary(2)
+    );
+    assert_eq!(batch.column(0).as_fixed_size_binary().value(0), &[1, 2]);
+    assert_eq!(batch.column(1).as_binary::<i32>().value(0), &[7u8; 10]);
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn export_parquet() {
+    use redb::arrow::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::fs::File;
+
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let definition: TableDefinition<u64, &str> = TableDefinition::new("x");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        for i in 0..70_000u64 {
+            table.insert(i, i.to_string().as_str()).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    // Changes committed after the read transaction started are not exported
+    let write_txn = db.begin_write().unwrap();
+    write_txn
+        .open_table(definition)
+        .unwrap()
+        .insert(70_000, "new")
+        .unwrap();
+    write_txn.commit().unwrap();
+
+    let table = read_txn.open_table(definition).unwrap();
+    let output = NamedTempFile::new().unwrap();
+    let mapping = ArrowMapping::new("id", "name");
+    assert_eq!(
+        table.export_parquet(output.path(), &mapping).unwrap(),
+        70_000
+    );
+
+    let builder =
+        ParquetRecordBatchReaderBuilder::try_new(File::open(output.path()).unwrap()).unwrap();
+    assert_eq!(builder.metadata().num_row_groups(), 2);
+    assert_eq!(builder.schema().as_ref(), &mapping.schema::<u64, &str>());
+    let mut expected = 0u64;
+    for batch in builder.build().unwrap() {
+        let batch = batch.unwrap();
+        let ids = batch.column(0).as_primitive::<UInt64Type>();
+        let names = batch.column(1).as_string::<i32>();
+        for i in 0..batch.num_rows() {
+            assert_eq!(ids.value(i), expected);
+            assert_eq!(names.value(i), expected.to_string());
+            expected += 1;
+        }
+    }
+    assert_eq!(expected, 70_000);
+
+    // An empty table produces a file with no rows
+    let empty: TableDefinition<u64, &str> = TableDefinition::new("empty");
+    let write_txn = db.begin_write().unwrap();
+    let table = write_txn.open_table(empty).unwrap();
+    assert_eq!(table.export_parquet(output.path(), &mapping).unwrap(), 0);
+    drop(table);
+    write_txn.abort().unwrap();
+    let builder =
+        ParquetRecordBatchReaderBuilder::try_new(File::open(output.path()).unwrap()).unwrap();
+    assert_eq!(builder.metadata().file_metadata().num_rows(), 0);
+}
@@ -1,8 +1,10 @@
+use rand::Rng;
 use redb::{
-    Database, MultimapTableDefinition, MultimapTableHandle, Range, ReadableTable, RedbKey,
-    RedbValue, TableDefinition, TableHandle, TypeName,
+    merge_tables, BigEndian, Database, Durability, Error, MergedRange, MultimapTableDefinition,
+    MultimapTableHandle, OrderedF32, OrderedF64, Range, ReadableTable, RedbKey, RedbValue,
+    SystemTableDefinition, TableDefinition, TableHandle, TypeName,
 };
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
 use std::sync;
 use tempfile::NamedTempFile;
 
@@ -286,6 +288,66 @@ fn list_tables() {
     assert_eq!(multimap_tables, &["mx", "my"]);
 }
 
+#[test]
+fn system_tables() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let definition: TableDefinition<&str, u64> = TableDefinition::new("x");
+    let system_definition: SystemTableDefinition<&str, u64> = SystemTableDefinition::new("x");
+    // Same name as a table used internally by redb
+    let savepoints_definition: SystemTableDefinition<&str, &str> =
+        SystemTableDefinition::new("persistent_savepoints");
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        table.insert("hello", &1).unwrap();
+        let mut system_table = write_txn.open_system_table(system_definition).unwrap();
+        system_table.insert("hello", &2).unwrap();
+        let mut savepoints_table = write_txn.open_system_table(savepoints_definition).unwrap();
+        savepoints_table.insert("hello", "world").unwrap();
+    }
+    let tables: Vec<String> = write_txn
+        .list_tables()
+        .unwrap()
+        .map(|h| h.name().to_string())
+        .collect();
+    assert_eq!(tables, &["x"]);
+    write_txn.commit().unwrap();
+
+    let mut write_txn = db.begin_write().unwrap();
+    write_txn.set_durability(Durability::Immediate);
+    let savepoint = write_txn.persistent_savepoint().unwrap();
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    assert_eq!(table.get("hello").unwrap().unwrap().value(), 1);
+    let system_table = read_txn.open_system_table(system_definition).unwrap();
+    assert_eq!(system_table.get("hello").unwrap().unwrap().value(), 2);
+    let savepoints_table = read_txn.open_system_table(savepoints_definition).unwrap();
+    assert_eq!(
+        savepoints_table.get("hello").unwrap().unwrap().value(),
+        "world"
+    );
+    assert_eq!(read_txn.list_tables().unwrap().count(), 1);
+    let missing: SystemTableDefinition<&str, u64> = SystemTableDefinition::new("y");
+    assert!(matches!(
+        read_txn.open_system_table(missing),
+        Err(Error::TableDoesNotExist(_))
+    ));
+
+    let write_txn = db.begin_write().unwrap();
+    assert_eq!(
+        write_txn
+            .list_persistent_savepoints()
+            .unwrap()
+            .collect::<Vec<_>>(),
+        vec![savepoint]
+    );
+}
+
 #[test]
 // Test that these signatures compile
 fn tuple_type_function_lifetime() {
@@ -730,6 +792,145 @@ fn insert_reserve() {
     );
 }
 
+#[test]
+fn insert_reserve_resize() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let def: TableDefinition<&str, &[u8]> = TableDefinition::new("x");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(def).unwrap();
+        table.insert("a", b"first".as_slice()).unwrap();
+        table.insert("c", b"last".as_slice()).unwrap();
+        let mut reserved = table.insert_reserve("b", 100).unwrap();
+        assert_eq!(reserved.capacity(), 100);
+        reserved.truncate(3);
+        assert_eq!(reserved.as_mut().len(), 3);
+        reserved.as_mut().copy_from_slice(b"xyz");
+        reserved.extend_to(6);
+        assert_eq!(reserved.as_mut(), b"xyz\0\0\0");
+        reserved.as_mut()[3..].copy_from_slice(b"123");
+        // No effect, since these don't move the length in the requested direction
+        reserved.truncate(10);
+        reserved.extend_to(2);
+        assert_eq!(reserved.as_mut(), b"xyz123");
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(def).unwrap();
+    assert_eq!(table.get("a").unwrap().unwrap().value(), b"first");
+    assert_eq!(table.get("b").unwrap().unwrap().value(), b"xyz123");
+    assert_eq!(table.get("c").unwrap().unwrap().value(), b"last");
+}
+
+#[test]
+#[should_panic]
+fn insert_reserve_extend_past_capacity() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let def: TableDefinition<&str, &[u8]> = TableDefinition::new("x");
+    let write_txn = db.begin_write().unwrap();
+    let mut table = write_txn.open_table(def).unwrap();
+    let mut reserved = table.insert_reserve("a", 10).unwrap();
+    reserved.extend_to(11);
+}
+
+#[test]
+fn append() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(def).unwrap();
+        table.append(0, b"hello").unwrap();
+        table.append(0, b" ").unwrap();
+        table.append(1, b"other").unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(def).unwrap();
+        table.append(0, b"world").unwrap();
+        table.append(0, b"").unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(def).unwrap();
+    assert_eq!(table.get(0).unwrap().unwrap().value(), b"hello world");
+    assert_eq!(table.get(1).unwrap().unwrap().value(), b"other");
+    drop(table);
+    drop(read_txn);
+
+    // Enough appends to split the tree, so that values are extended both in place and by copying
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(def).unwrap();
+        for round in 0..50u8 {
+            for key in 2..200 {
+                table.append(key, &[round; 7]).unwrap();
+            }
+        }
+        let expected: Vec<u8> = (0..50u8).flat_map(|round| [round; 7]).collect();
+        for key in 2..200 {
+            assert_eq!(table.get(key).unwrap().unwrap().value(), expected);
+        }
+    }
+    write_txn.commit().unwrap();
+    drop(db);
+
+    let db = Database::open(tmpfile.path()).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(def).unwrap();
+    assert_eq!(table.len().unwrap(), 200);
+    assert_eq!(table.get(199).unwrap().unwrap().value().len(), 350);
+    assert_eq!(table.get(0).unwrap().unwrap().value(), b"hello world");
+}
+
+#[test]
+fn get_range_of() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let value: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(def).unwrap();
+        table.insert(0, value.as_slice()).unwrap();
+        assert_eq!(
+            table.get_range_of(0, 10..20).unwrap().unwrap(),
+            &value[10..20]
+        );
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(def).unwrap();
+    assert_eq!(
+        table.get_range_of(0, 50_000..50_100).unwrap().unwrap(),
+        &value[50_000..50_100]
+    );
+    assert_eq!(
+        table.get_range_of(0, 99_990..=99_999).unwrap().unwrap(),
+        &value[99_990..]
+    );
+    assert_eq!(table.get_range_of(0, ..5).unwrap().unwrap(), &value[..5]);
+    // Ranges are clamped to the length of the value
+    assert_eq!(
+        table.get_range_of(0, 99_995..200_000).unwrap().unwrap(),
+        &value[99_995..]
+    );
+    assert!(table
+        .get_range_of(0, 200_000..)
+        .unwrap()
+        .unwrap()
+        .is_empty());
+    assert!(table.get_range_of(1, ..).unwrap().is_none());
+}
+
 #[test]
 fn delete() {
     let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
@@ -890,7 +1091,7 @@ fn u64_type() {
             .range(0..2)
             .unwrap()
             .map(|item| item.unwrap().1.value())
-            .sum()
+            .sum::<u64>()
     );
     assert_eq!(1, table.get(&0).unwrap().unwrap().value());
 }
@@ -940,6 +1141,170 @@ fn f32_type() {
     assert_eq!(0.3, table.get(&0).unwrap().unwrap().value());
 }
 
+#[test]
+fn ordered_float_type() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let definition: TableDefinition<OrderedF64, u64> = TableDefinition::new("x");
+
+    let values = [
+        f64::NEG_INFINITY,
+        -1.5,
+        -f64::MIN_POSITIVE,
+        -0.0,
+        0.0,
+        1e-10,
+        2.5,
+        f64::INFINITY,
+        f64::NAN,
+    ];
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        for (i, value) in values.iter().enumerate().rev() {
+            table.insert(OrderedF64(*value), &(i as u64)).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    let mut iter = table.iter().unwrap();
+    for (i, value) in values.iter().enumerate() {
+        let (key, index) = iter.next().unwrap().unwrap();
+        assert_eq!(key.value().0.to_bits(), value.to_bits());
+        assert_eq!(index.value(), i as u64);
+    }
+    assert!(iter.next().is_none());
+
+    let in_range: Vec<u64> = table
+        .range(OrderedF64(0.0)..OrderedF64(10.0))
+        .unwrap()
+        .map(|x| x.unwrap().1.value())
+        .collect();
+    assert_eq!(in_range, vec![4, 5, 6]);
+
+    let definition: TableDefinition<OrderedF32, ()> = TableDefinition::new("y");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        table.insert(OrderedF32(1.0), ()).unwrap();
+        table.insert(OrderedF32(-1.0), ()).unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    let keys: Vec<f32> = table
+        .iter()
+        .unwrap()
+        .map(|x| x.unwrap().0.value().0)
+        .collect();
+    assert_eq!(keys, vec![-1.0, 1.0]);
+}
+
+#[test]
+fn big_endian_type() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    assert_eq!(BigEndian(0x0102u16).encode(), [1, 2]);
+    assert_eq!(BigEndian(-1i8).encode(), [0x7F]);
+    assert_eq!(BigEndian::<i64>::decode(BigEndian(-5i64).encode()).0, -5);
+
+    let definition: TableDefinition<BigEndian<i32>, ()> = TableDefinition::new("x");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        for i in [300, -1, i32::MAX, 0, i32::MIN, 255, 256] {
+            table.insert(BigEndian(i), ()).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    let keys: Vec<i32> = table
+        .iter()
+        .unwrap()
+        .map(|x| x.unwrap().0.value().0)
+        .collect();
+    assert_eq!(keys, vec![i32::MIN, -1, 0, 255, 256, 300, i32::MAX]);
+
+    // The encoding can be used as a prefix of a composite byte key
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(SLICE_TABLE).unwrap();
+        for i in [256u64, 1, 2] {
+            let mut key = BigEndian(i).encode().to_vec();
+            key.extend_from_slice(b"suffix");
+            table.insert(key.as_slice(), [].as_slice()).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(SLICE_TABLE).unwrap();
+    let prefixes: Vec<u64> = table
+        .iter()
+        .unwrap()
+        .map(|x| BigEndian::<u64>::decode(x.unwrap().0.value()[..8].try_into().unwrap()).0)
+        .collect();
+    assert_eq!(prefixes, vec![1, 2, 256]);
+}
+
+#[test]
+fn reverse_key_type() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let definition: TableDefinition<Reverse<u64>, u64> = TableDefinition::new("x");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        for i in [3, 1, 4, 5, 2] {
+            table.insert(Reverse(i), i * 10).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    let keys: Vec<u64> = table
+        .iter()
+        .unwrap()
+        .map(|x| x.unwrap().0.value().0)
+        .collect();
+    assert_eq!(keys, vec![5, 4, 3, 2, 1]);
+    let keys: Vec<u64> = table
+        .range(Reverse(4)..)
+        .unwrap()
+        .map(|x| x.unwrap().0.value().0)
+        .collect();
+    assert_eq!(keys, vec![4, 3, 2, 1]);
+    assert_eq!(table.get(Reverse(3)).unwrap().unwrap().value(), 30);
+
+    let definition: TableDefinition<Reverse<&str>, ()> = TableDefinition::new("y");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        for key in ["a", "ab", "b"] {
+            table.insert(Reverse(key), ()).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    let keys: Vec<String> = table
+        .iter()
+        .unwrap()
+        .map(|x| x.unwrap().0.value().0.to_string())
+        .collect();
+    assert_eq!(keys, vec!["b", "ab", "a"]);
+}
+
 #[test]
 fn str_type() {
     let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
@@ -1394,3 +1759,264 @@ fn signature_lifetimes() {
     }
     write_txn.commit().unwrap();
 }
+
+#[test]
+fn merge() {
+    const BASE: TableDefinition<u64, &str> = TableDefinition::new("base");
+    const DELTA: TableDefinition<u64, &str> = TableDefinition::new("delta");
+
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut base = write_txn.open_table(BASE).unwrap();
+        for i in [1, 2, 3, 5] {
+            base.insert(i, "base").unwrap();
+        }
+        let mut delta = write_txn.open_table(DELTA).unwrap();
+        for i in [0, 3, 4, 6] {
+            delta.insert(i, "delta").unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let base = read_txn.open_table(BASE).unwrap();
+    let delta = read_txn.open_table(DELTA).unwrap();
+    let expected = [
+        (0, "delta"),
+        (1, "base"),
+        (2, "base"),
+        (3, "delta"),
+        (4, "delta"),
+        (5, "base"),
+        (6, "delta"),
+    ];
+
+    let merged: Vec<(u64, String)> = merge_tables([&base, &delta])
+        .unwrap()
+        .map(|entry| {
+            let (key, value) = entry.unwrap();
+            (key.value(), value.value().to_string())
+        })
+        .collect();
+    let expected_vec: Vec<(u64, String)> =
+        expected.iter().map(|(k, v)| (*k, v.to_string())).collect();
+    assert_eq!(merged, expected_vec);
+
+    // Reversing the order of the tables changes which one takes precedence
+    let mut iter = merge_tables([&delta, &base]).unwrap();
+    for _ in 0..3 {
+        iter.next().unwrap().unwrap();
+    }
+    assert_eq!(iter.next().unwrap().unwrap().1.value(), "base");
+
+    // Alternate between the two ends, so that they meet in the middle
+    let mut iter = merge_tables([&base, &delta]).unwrap();
+    for i in 0..expected.len() {
+        let index = if i % 2 == 0 {
+            i / 2
+        } else {
+            expected.len() - 1 - i / 2
+        };
+        let (key, value) = if i % 2 == 0 {
+            iter.next().unwrap().unwrap()
+        } else {
+            iter.next_back().unwrap().unwrap()
+        };
+        assert_eq!((key.value(), value.value()), expected[index]);
+    }
+    assert!(iter.next().is_none());
+    assert!(iter.next_back().is_none());
+
+    // Ranges of different tables can be merged too
+    let iter = MergedRange::new(vec![base.range(2..).unwrap(), delta.range(..4).unwrap()]);
+    let keys: Vec<u64> = iter.map(|entry| entry.unwrap().0.value()).collect();
+    assert_eq!(keys, vec![0, 2, 3, 5]);
+}
+
+#[test]
+fn snapshot_iter() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 0..1000 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        table.insert(5000, 5000).unwrap();
+        let mut expected = 0;
+        for entry in table.snapshot_iter().unwrap() {
+            let (key, value) = entry.unwrap();
+            assert_eq!(key.value(), expected);
+            assert_eq!(value.value(), expected);
+            table.remove(key.value()).unwrap();
+            table.insert(key.value() + 1000, value.value() * 2).unwrap();
+            expected += 1;
+        }
+        assert_eq!(expected, 1000);
+        assert_eq!(table.len().unwrap(), 1001);
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    let mut iter = table.iter().unwrap();
+    for i in 0..1000 {
+        let (key, value) = iter.next().unwrap().unwrap();
+        assert_eq!(key.value(), i + 1000);
+        assert_eq!(value.value(), i * 2);
+    }
+    assert_eq!(iter.next().unwrap().unwrap().0.value(), 5000);
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn range_skip_to_nth() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 0..10_000 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+
+    let mut iter = table.range(100..9000).unwrap();
+    iter.skip_to_nth(0).unwrap();
+    assert_eq!(iter.next().unwrap().unwrap().0.value(), 100);
+    iter.skip_to_nth(5000).unwrap();
+    assert_eq!(iter.next().unwrap().unwrap().0.value(), 5101);
+    assert_eq!(iter.next_back().unwrap().unwrap().0.value(), 8999);
+    iter.skip_to_nth(3896).unwrap();
+    assert_eq!(iter.next().unwrap().unwrap().0.value(), 8998);
+    assert!(iter.next().is_none());
+
+    let mut iter = table.range(100..=200).unwrap();
+    iter.skip_to_nth(100).unwrap();
+    assert_eq!(iter.next().unwrap().unwrap().0.value(), 200);
+    let mut iter = table.range(100..=200).unwrap();
+    iter.skip_to_nth(101).unwrap();
+    assert!(iter.next().is_none());
+    assert!(iter.next_back().is_none());
+
+    for (start, end, n) in [
+        (0, 10_000, 9_999),
+        (7, 7_000, 1234),
+        (500, 501, 0),
+        (0, 3, 2),
+    ] {
+        let mut expected = table.range(start..end).unwrap();
+        let mut actual = table.range(start..end).unwrap();
+        actual.skip_to_nth(n).unwrap();
+        let expected_key = expected
+            .nth(n.try_into().unwrap())
+            .map(|x| x.unwrap().0.value());
+        assert_eq!(actual.next().map(|x| x.unwrap().0.value()), expected_key);
+        assert_eq!(
+            actual.map(|x| x.unwrap().0.value()).collect::<Vec<_>>(),
+            expected.map(|x| x.unwrap().0.value()).collect::<Vec<_>>()
+        );
+    }
+
+    // The counts are kept up to date as entries are removed
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in (0..10_000).step_by(3) {
+            table.remove(&i).unwrap();
+        }
+        let mut iter = table.range(1000..).unwrap();
+        assert_eq!(iter.skip_to_nth(4000).unwrap(), 4000);
+        assert_eq!(iter.next().unwrap().unwrap().0.value(), 7000);
+        assert_eq!(iter.len(), 1999);
+        assert_eq!(iter.skip_to_nth(5000).unwrap(), 1999);
+        assert!(iter.next().is_none());
+    }
+    write_txn.commit().unwrap();
+}
+
+#[test]
+fn sample() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        assert!(table.sample(10, || 0).unwrap().is_empty());
+        for i in 0..10_000 {
+            table.insert(i, i * 2).unwrap();
+        }
+        // The smallest and largest random values select the first and last entries
+        let first = table.sample(1, || 0).unwrap();
+        assert_eq!(first[0].0.value(), 0);
+        let last = table.sample(1, || u64::MAX).unwrap();
+        assert_eq!(last[0].0.value(), 9_999);
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    let mut rng = rand::thread_rng();
+    let samples = table.sample(1000, || rng.gen()).unwrap();
+    assert_eq!(samples.len(), 1000);
+    let mut low_half = 0;
+    for (key, value) in samples.iter() {
+        assert!(key.value() < 10_000);
+        assert_eq!(value.value(), key.value() * 2);
+        if key.value() < 5_000 {
+            low_half += 1;
+        }
+    }
+    assert!((300..700).contains(&low_half));
+}
+
+#[test]
+fn nth_and_rank() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 0..10_000 {
+            table.insert(i * 2, i).unwrap();
+        }
+        assert_eq!(table.len().unwrap(), 10_000);
+        assert_eq!(table.nth(0).unwrap().unwrap().0.value(), 0);
+        assert_eq!(table.rank(12_345).unwrap(), 6_173);
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    for index in [0, 1, 2_500, 5_000, 9_999] {
+        let (key, value) = table.nth(index).unwrap().unwrap();
+        assert_eq!(key.value(), index * 2);
+        assert_eq!(value.value(), index);
+        assert_eq!(table.rank(key.value()).unwrap(), index);
+        assert_eq!(table.rank(key.value() + 1).unwrap(), index + 1);
+    }
+    assert!(table.nth(10_000).unwrap().is_none());
+    assert_eq!(table.rank(u64::MAX).unwrap(), 10_000);
+
+    // The median of the keys
+    let (median, _) = table.nth(table.len().unwrap() / 2).unwrap().unwrap();
+    assert_eq!(median.value(), 10_000);
+
+    let mut iter = table.range(100..200).unwrap();
+    assert_eq!(iter.skip_to_nth(10).unwrap(), 10);
+    assert_eq!(iter.skip_to_nth(100).unwrap(), 40);
+    assert!(iter.next().is_none());
+}
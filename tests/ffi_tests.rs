@@ -0,0 +1,166 @@
+#![cfg(feature = "ffi")]
+
+use redb::ffi::*;
+use std::ffi::{CStr, CString};
+use std::ptr;
+use tempfile::NamedTempFile;
+
+fn empty_buffer() -> RedbBuffer {
+    RedbBuffer {
+        data: ptr::null_mut(),
+        len: 0,
+    }
+}
+
+unsafe fn take(buffer: RedbBuffer) -> Vec<u8> {
+    let data = if buffer.len == 0 {
+        vec![]
+    } else {
+        std::slice::from_raw_parts(buffer.data, buffer.len).to_vec()
+    };
+    redb_buffer_free(buffer);
+    data
+}
+
+unsafe fn read_get(db: *mut RedbDatabase, table: &CStr, key: &[u8]) -> Option<Vec<u8>> {
+    let txn = redb_begin_read(db);
+    assert!(!txn.is_null());
+    let mut value = empty_buffer();
+    let status = redb_read_get(txn, table.as_ptr(), key.as_ptr(), key.len(), &mut value);
+    redb_read_transaction_free(txn);
+    match status {
+        REDB_OK => Some(take(value)),
+        REDB_NOT_FOUND => None,
+        _ => panic!(),
+    }
+}
+
+unsafe fn collect(iter: *mut RedbIterator) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut entries = vec![];
+    loop {
+        let mut key = empty_buffer();
+        let mut value = empty_buffer();
+        match redb_iterator_next(iter, &mut key, &mut value) {
+            REDB_OK => entries.push((take(key), take(value))),
+            REDB_NOT_FOUND => break,
+            _ => panic!(),
+        }
+    }
+    redb_iterator_free(iter);
+    entries
+}
+
+#[test]
+fn c_api() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let path = CString::new(tmpfile.path().to_str().unwrap()).unwrap();
+    let table = CString::new("data").unwrap();
+    let missing = CString::new("missing").unwrap();
+
+    unsafe {
+        let db = redb_database_create(path.as_ptr());
+        assert!(!db.is_null());
+        assert_eq!(read_get(db, &table, b"a"), None);
+
+        let txn = redb_begin_write(db);
+        for (key, value) in [(b"a", b"1"), (b"b", b"2"), (b"c", b"3"), (b"d", b"4")] {
+            let status = redb_insert(
+                txn,
+                table.as_ptr(),
+                key.as_ptr(),
+                key.len(),
+                value.as_ptr(),
+                value.len(),
+            );
+            assert_eq!(status, REDB_OK);
+        }
+        assert_eq!(
+            redb_insert(txn, table.as_ptr(), b"e".as_ptr(), 1, ptr::null(), 0),
+            REDB_OK
+        );
+        assert_eq!(redb_remove(txn, table.as_ptr(), b"a".as_ptr(), 1), REDB_OK);
+        assert_eq!(
+            redb_remove(txn, table.as_ptr(), b"a".as_ptr(), 1),
+            REDB_NOT_FOUND
+        );
+        let mut value = empty_buffer();
+        assert_eq!(
+            redb_write_get(txn, table.as_ptr(), b"b".as_ptr(), 1, &mut value),
+            REDB_OK
+        );
+        assert_eq!(take(value), b"2");
+        // Uncommitted changes are not visible to readers
+        assert_eq!(read_get(db, &table, b"b"), None);
+        assert_eq!(redb_commit(txn), REDB_OK);
+
+        assert_eq!(read_get(db, &table, b"b"), Some(b"2".to_vec()));
+        assert_eq!(read_get(db, &table, b"e"), Some(vec![]));
+        assert_eq!(read_get(db, &missing, b"b"), None);
+
+        let txn = redb_begin_write(db);
+        redb_insert(txn, table.as_ptr(), b"f".as_ptr(), 1, b"6".as_ptr(), 1);
+        assert_eq!(redb_abort(txn), REDB_OK);
+        assert_eq!(read_get(db, &table, b"f"), None);
+
+        let txn = redb_begin_read(db);
+        let all = collect(redb_iter(
+            txn,
+            table.as_ptr(),
+            ptr::null(),
+            0,
+            ptr::null(),
+            0,
+        ));
+        assert_eq!(
+            all,
+            vec![
+                (b"b".to_vec(), b"2".to_vec()),
+                (b"c".to_vec(), b"3".to_vec()),
+                (b"d".to_vec(), b"4".to_vec()),
+                (b"e".to_vec(), vec![]),
+            ]
+        );
+        let range = collect(redb_iter(
+            txn,
+            table.as_ptr(),
+            b"c".as_ptr(),
+            1,
+            b"e".as_ptr(),
+            1,
+        ));
+        assert_eq!(
+            range,
+            vec![
+                (b"c".to_vec(), b"3".to_vec()),
+                (b"d".to_vec(), b"4".to_vec())
+            ]
+        );
+        assert!(collect(redb_iter(
+            txn,
+            missing.as_ptr(),
+            ptr::null(),
+            0,
+            ptr::null(),
+            0
+        ))
+        .is_empty());
+        redb_read_transaction_free(txn);
+        redb_database_close(db);
+
+        // Errors are reported through redb_last_error
+        let db = redb_database_open(path.as_ptr());
+        assert!(!db.is_null());
+        let txn = redb_begin_write(db);
+        let invalid = CString::new(vec![0xff]).unwrap();
+        assert_eq!(
+            redb_insert(txn, invalid.as_ptr(), b"a".as_ptr(), 1, b"1".as_ptr(), 1),
+            REDB_ERROR
+        );
+        assert!(!redb_last_error().is_null());
+        redb_abort(txn);
+        redb_database_close(db);
+
+        let directory = CString::new(std::env::temp_dir().to_str().unwrap()).unwrap();
+        assert!(redb_database_open(directory.as_ptr()).is_null());
+    }
+}
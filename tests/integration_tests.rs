@@ -1,12 +1,25 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::ErrorKind;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tempfile::NamedTempFile;
 
 use rand::prelude::SliceRandom;
 use rand::Rng;
+use redb::spatial::{self, BoundingBox, Hilbert2D, Morton2D, SpatialKey};
+use redb::testing::ModelTester;
 use redb::ReadableMultimapTable;
 use redb::{
-    Builder, Database, Durability, Error, MultimapTableDefinition, ReadableTable, TableDefinition,
+    AllocationStrategy, BlobStore, Builder, CacheTable, ChecksumAlgorithm, Clock, ContentHash,
+    Database, DiffEntry, Distance, DropBehavior, Durability, Error, ExternalSorter,
+    FileProtectionClass, FillPolicy, FixedVector, ForeignKey, History, ImportProgress, Importer,
+    InvertedIndex, JobQueue, LeaseTable, MultimapTableDefinition, Outbox, OwnedReadTable,
+    PriorityQueueTable, ReadOnlyBlobStore, ReadOnlyCacheTable, ReadOnlyInvertedIndex,
+    ReadOnlyOutbox, ReadOnlyPriorityQueueTable, ReadOnlyTimeSeriesTable, ReadableTable, RedbValue,
+    RetryPolicy, SearchMode, StagingTable, TableDefinition, TimeSeriesTable, TypeNameCheck,
 };
 
 const ELEMENTS: usize = 100;
@@ -935,6 +948,177 @@ fn range_query_reversed() {
     assert!(iter.next().is_none());
 }
 
+#[test]
+fn range_size_hint() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 0..10 {
+            table.insert(&i, &i).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    let mut iter = table.range(3..7).unwrap();
+    assert_eq!(iter.size_hint(), (4, Some(4)));
+    iter.next().unwrap().unwrap();
+    assert_eq!(iter.size_hint(), (3, Some(3)));
+    iter.next_back().unwrap().unwrap();
+    assert_eq!(iter.size_hint(), (2, Some(2)));
+    assert_eq!(iter.count(), 2);
+
+    let iter = table.range(3..=7).unwrap();
+    assert_eq!(iter.size_hint(), (5, Some(5)));
+    let iter = table.range(20..).unwrap();
+    assert_eq!(iter.size_hint(), (0, Some(0)));
+    assert_eq!(table.iter().unwrap().size_hint(), (10, Some(10)));
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 10..10_000 {
+            table.insert(&i, &i).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.iter().unwrap().len(), 10_000);
+    assert_eq!(table.range(9_000..20_000).unwrap().len(), 1_000);
+    #[allow(clippy::reversed_empty_ranges)]
+    let iter = table.range(5_000..10).unwrap();
+    assert_eq!(iter.len(), 0);
+    for (start, end) in [(0, 10_000), (5, 9_000), (1_000, 1_010), (4_000, 4_000)] {
+        let mut iter = table.range(start..end).unwrap();
+        let mut remaining = (end - start) as usize;
+        loop {
+            assert_eq!(iter.size_hint(), (remaining, Some(remaining)));
+            // Alternate between the two ends
+            let next = if remaining % 2 == 0 {
+                iter.next()
+            } else {
+                iter.next_back()
+            };
+            if next.is_none() {
+                break;
+            }
+            remaining -= 1;
+        }
+        assert_eq!(remaining, 0);
+    }
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        assert_eq!(table.drain(100..9_900).unwrap().len(), 9_800);
+    }
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        table
+            .drain_filter::<u64, _>(.., |k, _| k % 3 != 0)
+            .unwrap()
+            .for_each(drop);
+        assert_eq!(table.range(..50).unwrap().len(), 17);
+        assert_eq!(table.remove_range(9_950..).unwrap(), 17);
+        assert_eq!(table.iter().unwrap().len(), table.iter().unwrap().count());
+    }
+    write_txn.commit().unwrap();
+}
+
+#[test]
+fn range_clone_and_fused() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 0..1_000 {
+            table.insert(&i, &i).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    let mut iter = table.range(100..900).unwrap();
+    for i in 100..400 {
+        assert_eq!(iter.next().unwrap().unwrap().0.value(), i);
+    }
+    assert_eq!(iter.next_back().unwrap().unwrap().0.value(), 899);
+
+    // The clone continues from where the original was, and advances independently of it
+    let mut forked = iter.clone();
+    for i in 400..899 {
+        assert_eq!(forked.next().unwrap().unwrap().0.value(), i);
+    }
+    assert!(forked.next().is_none());
+    assert_eq!(iter.next().unwrap().unwrap().0.value(), 400);
+    assert_eq!(iter.next_back().unwrap().unwrap().0.value(), 898);
+    assert_eq!(iter.count(), 497);
+
+    // Exhausted iterators keep returning None from both ends
+    for _ in 0..3 {
+        assert!(forked.next().is_none());
+        assert!(forked.next_back().is_none());
+    }
+    let mut iter = table.range(2_000..).unwrap();
+    assert!(iter.next().is_none());
+    assert!(iter.next().is_none());
+    assert!(iter.clone().next().is_none());
+}
+
+#[test]
+fn range_next_leaf() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 0..10_000 {
+            table.insert(&i, &i).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    let mut iter = table.range(10..9_000).unwrap();
+    assert_eq!(iter.next().unwrap().unwrap().0.value(), 10);
+    let mut expected = 11;
+    let mut batches = 0;
+    while let Some(batch) = iter.next_leaf() {
+        let batch = batch.unwrap();
+        assert!(!batch.is_empty());
+        for (key, value) in batch {
+            assert_eq!(key.value(), expected);
+            assert_eq!(value.value(), expected);
+            expected += 1;
+        }
+        batches += 1;
+    }
+    assert_eq!(expected, 9_000);
+    // Entries are spread over many leaves, so they can't all be returned in one batch
+    assert!(batches > 1);
+    assert!(iter.next().is_none());
+
+    // Batches and single entries can be mixed, and the end of the range is respected
+    let mut iter = table.range(100..=105).unwrap();
+    assert_eq!(iter.next_back().unwrap().unwrap().0.value(), 105);
+    let batch = iter.next_leaf().unwrap().unwrap();
+    assert!(batch.len() <= 5);
+    assert_eq!(batch[0].0.value(), 100);
+    // Entries after the batch remain in the iterator
+    assert_eq!(batch.len() + iter.count(), 5);
+}
+
 #[test]
 fn alias_table() {
     let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
@@ -1066,6 +1250,33 @@ fn does_not_exist() {
     }
 }
 
+#[test]
+fn file_format_upgrade() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    drop(Database::create(tmpfile.path()).unwrap());
+    assert!(!Database::needs_upgrade(tmpfile.path()).unwrap());
+    assert!(!Database::upgrade(tmpfile.path()).unwrap());
+    Database::open(tmpfile.path()).unwrap();
+
+    // Rewrite the version byte of the first commit slot to an older format
+    let mut data = fs::read(tmpfile.path()).unwrap();
+    data[64] = 113;
+    fs::write(tmpfile.path(), data).unwrap();
+    assert!(Database::needs_upgrade(tmpfile.path()).unwrap());
+    assert!(matches!(
+        Database::upgrade(tmpfile.path()),
+        Err(Error::UpgradeRequired(113))
+    ));
+
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    fs::write(tmpfile.path(), [0u8; 1024]).unwrap();
+    if let Err(Error::Io(e)) = Database::needs_upgrade(tmpfile.path()) {
+        assert!(matches!(e.kind(), ErrorKind::InvalidData));
+    } else {
+        panic!();
+    }
+}
+
 #[test]
 fn wrong_types() {
     let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
@@ -1093,6 +1304,169 @@ fn wrong_types() {
     ));
 }
 
+#[test]
+fn relaxed_type_names() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let definition: TableDefinition<u64, u64> = TableDefinition::new("x");
+    // Same widths, but different type names
+    let renamed_definition: TableDefinition<i64, i64> = TableDefinition::new("x");
+    let wrong_width_definition: TableDefinition<u32, u32> = TableDefinition::new("x");
+
+    let txn = db.begin_write().unwrap();
+    txn.open_table(definition).unwrap().insert(&1, &2).unwrap();
+    txn.commit().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    assert!(matches!(
+        txn.open_table_relaxed(renamed_definition, TypeNameCheck::Strict),
+        Err(Error::TableTypeMismatch { .. })
+    ));
+    {
+        let mut table = txn
+            .open_table_relaxed(renamed_definition, TypeNameCheck::Ignore)
+            .unwrap();
+        assert_eq!(table.get(&1).unwrap().unwrap().value(), 2);
+        table.insert(&3, &4).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    let table = txn
+        .open_table_relaxed(renamed_definition, TypeNameCheck::Warn)
+        .unwrap();
+    assert_eq!(table.get(&3).unwrap().unwrap().value(), 4);
+    // The stored type names are left unchanged
+    txn.open_table(definition).unwrap();
+    assert!(matches!(
+        txn.open_table_relaxed(wrong_width_definition, TypeNameCheck::Ignore),
+        Err(Error::TypeDefinitionChanged { .. })
+    ));
+}
+
+#[test]
+fn sequences() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    for i in 0..10 {
+        assert_eq!(write_txn.next_sequence("a").unwrap(), i);
+    }
+    assert_eq!(write_txn.next_sequence("b").unwrap(), 0);
+    write_txn.commit().unwrap();
+
+    // Ids handed out by an aborted transaction are not reused, but ids are never repeated
+    let write_txn = db.begin_write().unwrap();
+    assert_eq!(write_txn.next_sequence("a").unwrap(), 10);
+    write_txn.abort().unwrap();
+    let write_txn = db.begin_write().unwrap();
+    let mut last = write_txn.next_sequence("a").unwrap();
+    assert!(last >= 10);
+    for _ in 0..5_000 {
+        let next = write_txn.next_sequence("a").unwrap();
+        assert!(next > last);
+        last = next;
+    }
+    write_txn.commit().unwrap();
+
+    let savepoint_txn = db.begin_write().unwrap();
+    let savepoint = savepoint_txn.ephemeral_savepoint().unwrap();
+    savepoint_txn.commit().unwrap();
+    let write_txn = db.begin_write().unwrap();
+    for _ in 0..2_000 {
+        last = write_txn.next_sequence("a").unwrap();
+    }
+    write_txn.commit().unwrap();
+    let mut write_txn = db.begin_write().unwrap();
+    write_txn.restore_savepoint(&savepoint).unwrap();
+    let restored = write_txn.next_sequence("a").unwrap();
+    write_txn.commit().unwrap();
+    drop(savepoint);
+    // The ids handed out after the savepoint were rolled back, so the sequence may repeat them,
+    // but never the ids committed before it
+    assert!(restored <= last);
+    let write_txn = db.begin_write().unwrap();
+    assert!(write_txn.next_sequence("a").unwrap() > restored);
+    write_txn.commit().unwrap();
+
+    drop(db);
+    let db = Database::open(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    assert!(write_txn.next_sequence("a").unwrap() > restored);
+    assert!(write_txn.next_sequence("b").unwrap() > 0);
+    assert_eq!(write_txn.next_sequence("c").unwrap(), 0);
+    write_txn.commit().unwrap();
+}
+
+#[test]
+fn commit_summary() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 0..100 {
+            table.insert(&i, &i).unwrap();
+        }
+    }
+    let summary = write_txn.commit_with_summary().unwrap();
+    let stats = summary.table("u64").unwrap();
+    assert_eq!(stats.inserted(), 100);
+    assert_eq!(stats.updated(), 0);
+    assert_eq!(stats.removed(), 0);
+    assert!(summary.allocated_pages() > 0);
+    assert!(summary.bytes_written() > 0);
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        // Entries can't be drained from pages that were modified earlier in the transaction
+        table
+            .drain_filter(0..100, |k, _| k >= 90 || k % 10 == 5)
+            .unwrap();
+        table.insert(&100, &100).unwrap();
+        for i in 0..10 {
+            table.insert(&i, &(i + 1)).unwrap();
+        }
+        table.remove(&50).unwrap();
+        // Removing a missing key is not counted
+        table.remove(&1_000).unwrap();
+        table.pop_first().unwrap();
+    }
+    write_txn.open_table(STR_TABLE).unwrap();
+    let summary = write_txn.commit_with_summary().unwrap();
+    let stats = summary.table("u64").unwrap();
+    // Key 5 was drained, so it is inserted again rather than updated
+    assert_eq!(stats.inserted(), 2);
+    assert_eq!(stats.updated(), 9);
+    assert_eq!(stats.removed(), 10 + 9 + 1 + 1);
+    assert!(summary.table("x").is_none());
+    assert_eq!(summary.tables().count(), 1);
+    assert!(summary.freed_pages() > 0);
+
+    let write_txn = db.begin_write().unwrap();
+    write_txn
+        .open_table(U64_TABLE)
+        .unwrap()
+        .drain(80..)
+        .unwrap();
+    let summary = write_txn.commit_with_summary().unwrap();
+    assert_eq!(summary.table("u64").unwrap().removed(), 10);
+
+    // Stats from the same table opened multiple times are combined
+    let write_txn = db.begin_write().unwrap();
+    for i in 0..3 {
+        let mut table = write_txn.open_table(STR_TABLE).unwrap();
+        table.insert(i.to_string().as_str(), "value").unwrap();
+    }
+    let summary = write_txn.commit_with_summary().unwrap();
+    assert_eq!(summary.table("x").unwrap().inserted(), 3);
+    assert!(summary.table("u64").is_none());
+}
+
 #[test]
 fn tree_balance() {
     const EXPECTED_ORDER: usize = 9;
@@ -1294,3 +1668,3546 @@ fn compaction() {
     let file_size2 = tmpfile.as_file().metadata().unwrap().len();
     assert!(file_size2 < file_size);
 }
+
+#[test]
+fn truncate_free_tail() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let definition: TableDefinition<u32, &[u8]> = TableDefinition::new("x");
+
+    let big_value = vec![0u8; 100 * 1024];
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        for i in 0..100 {
+            table.insert(&i, big_value.as_slice()).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    // Delete the most recently written values, which are at the end of the file
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        for i in 70..100 {
+            table.remove(&i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    db.begin_write().unwrap().commit().unwrap();
+
+    let file_size = tmpfile.as_file().metadata().unwrap().len();
+    let released = db.truncate_free_tail().unwrap();
+    assert!(released > 0);
+    let file_size2 = tmpfile.as_file().metadata().unwrap().len();
+    assert_eq!(file_size2, file_size - released);
+    assert_eq!(db.truncate_free_tail().unwrap(), 0);
+
+    drop(db);
+
+    let db = Database::open(tmpfile.path()).unwrap();
+    assert!(db.last_recovery_report().is_none());
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(definition).unwrap();
+    assert_eq!(table.len().unwrap(), 70);
+    for entry in table.iter().unwrap() {
+        assert_eq!(entry.unwrap().1.value(), big_value.as_slice());
+    }
+}
+
+#[test]
+fn blob_store() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let small = b"hello world".to_vec();
+    let shared = vec![7u8; 64 * 1024];
+    let mut large1 = shared.clone();
+    large1.extend_from_slice(&[1u8; 1000]);
+    let mut large2 = shared.clone();
+    large2.extend_from_slice(&[2u8; 1000]);
+
+    let write_txn = db.begin_write().unwrap();
+    let (small_hash, large1_hash, large2_hash) = {
+        let mut store = BlobStore::open(&write_txn, "assets").unwrap();
+        let small_hash = store.put(&small).unwrap();
+        assert_eq!(store.put(&small).unwrap(), small_hash);
+        assert_eq!(store.references(small_hash).unwrap(), 2);
+        let large1_hash = store.put(&large1).unwrap();
+        let large2_hash = store.put(&large2).unwrap();
+        assert_ne!(large1_hash, large2_hash);
+        assert_eq!(store.get(large1_hash).unwrap().unwrap(), large1);
+        (small_hash, large1_hash, large2_hash)
+    };
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let store = ReadOnlyBlobStore::open(&read_txn, "assets").unwrap();
+    assert_eq!(store.get(small_hash).unwrap().unwrap(), small);
+    assert_eq!(store.get(large2_hash).unwrap().unwrap(), large2);
+    drop(store);
+    drop(read_txn);
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut store = BlobStore::open(&write_txn, "assets").unwrap();
+        assert!(store.release(small_hash).unwrap());
+        assert_eq!(store.get(small_hash).unwrap().unwrap(), small);
+        assert!(store.release(small_hash).unwrap());
+        assert!(store.get(small_hash).unwrap().is_none());
+        assert!(!store.release(small_hash).unwrap());
+
+        // The shared chunk must survive the release of one of the blobs that uses it
+        assert!(store.release(large1_hash).unwrap());
+        assert!(store.get(large1_hash).unwrap().is_none());
+        assert_eq!(store.get(large2_hash).unwrap().unwrap(), large2);
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table: TableDefinition<u128, &[u8]> = TableDefinition::new("assets::chunks");
+    assert_eq!(read_txn.open_table(table).unwrap().len().unwrap(), 2);
+}
+
+#[test]
+fn lease_table() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let ttl = Duration::from_secs(10);
+    let at = Duration::from_secs;
+
+    let write_txn = db.begin_write().unwrap();
+    let mut lease = {
+        let mut leases = LeaseTable::open(&write_txn, "jobs").unwrap();
+        let lease = leases.acquire("compaction", at(100), ttl).unwrap().unwrap();
+        assert_eq!(lease.token(), 1);
+        assert_eq!(lease.expires_at(), at(110));
+        assert!(leases
+            .acquire("compaction", at(105), ttl)
+            .unwrap()
+            .is_none());
+        assert!(leases.acquire("backup", at(105), ttl).unwrap().is_some());
+        lease
+    };
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut leases = LeaseTable::open(&write_txn, "jobs").unwrap();
+        assert!(leases.renew(&mut lease, at(108), ttl).unwrap());
+        assert_eq!(lease.expires_at(), at(118));
+        assert_eq!(
+            leases.holder("compaction", at(115)).unwrap(),
+            Some(lease.clone())
+        );
+        assert!(leases
+            .acquire("compaction", at(115), ttl)
+            .unwrap()
+            .is_none());
+
+        // Once expired, the lease can be taken over, and the old holder is fenced out
+        let next = leases.acquire("compaction", at(118), ttl).unwrap().unwrap();
+        assert_eq!(next.token(), 2);
+        assert!(!leases.is_held(&lease, at(118)).unwrap());
+        assert!(!leases.renew(&mut lease, at(118), ttl).unwrap());
+        assert!(!leases.release(lease.clone(), at(118)).unwrap());
+
+        // Released leases can be acquired immediately, but do not reuse tokens
+        assert!(leases.release(next, at(119)).unwrap());
+        assert!(leases.holder("compaction", at(119)).unwrap().is_none());
+        let next = leases.acquire("compaction", at(119), ttl).unwrap().unwrap();
+        assert_eq!(next.token(), 3);
+    }
+    write_txn.commit().unwrap();
+}
+
+#[test]
+fn outbox() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut orders = write_txn.open_table(U64_TABLE).unwrap();
+        let mut outbox = Outbox::open(&write_txn, "events").unwrap();
+        for i in 0..10 {
+            orders.insert(i, i).unwrap();
+            assert_eq!(outbox.push(format!("order {i}").as_bytes()).unwrap(), i);
+        }
+    }
+    write_txn.commit().unwrap();
+
+    // Messages pushed by an aborted transaction are never stored
+    let write_txn = db.begin_write().unwrap();
+    Outbox::open(&write_txn, "events")
+        .unwrap()
+        .push(b"aborted")
+        .unwrap();
+    write_txn.abort().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let outbox = ReadOnlyOutbox::open(&read_txn, "events").unwrap();
+    assert_eq!(outbox.len().unwrap(), 10);
+    let batch = outbox.poll_batch(4).unwrap();
+    assert_eq!(batch.len(), 4);
+    assert_eq!(batch[0].id(), 0);
+    assert_eq!(batch[3].payload(), b"order 3");
+    drop(outbox);
+    drop(read_txn);
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut outbox = Outbox::open(&write_txn, "events").unwrap();
+        assert_eq!(outbox.ack_through(batch[3].id()).unwrap(), 4);
+        assert!(outbox.ack(7).unwrap());
+        assert!(!outbox.ack(7).unwrap());
+        let ids: Vec<u64> = outbox
+            .poll_batch(100)
+            .unwrap()
+            .iter()
+            .map(|x| x.id())
+            .collect();
+        assert_eq!(ids, vec![4, 5, 6, 8, 9]);
+        assert_eq!(outbox.ack_through(100).unwrap(), 5);
+        assert!(outbox.is_empty().unwrap());
+        // Ids are not reused once the outbox is empty
+        assert!(outbox.push(b"next").unwrap() >= 10);
+    }
+    write_txn.commit().unwrap();
+}
+
+#[test]
+fn fill_policy() {
+    let value = vec![0u8; 100];
+
+    let mut leaf_pages = vec![];
+    for policy in [FillPolicy::BALANCED, FillPolicy::APPEND] {
+        let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let db = Database::create(tmpfile.path()).unwrap();
+        let definition: TableDefinition<u64, &[u8]> =
+            TableDefinition::new("x").with_fill_policy(policy);
+        // Insert in ascending order, with a commit between each insert so that every page which
+        // fills up is split, rather than being rebuilt in place
+        for i in 0..1000u64 {
+            let txn = db.begin_write().unwrap();
+            {
+                let mut table = txn.open_table(definition).unwrap();
+                table.insert(&i, value.as_slice()).unwrap();
+            }
+            txn.commit().unwrap();
+        }
+
+        let txn = db.begin_write().unwrap();
+        leaf_pages.push(txn.stats().unwrap().leaf_pages());
+        {
+            let mut table = txn.open_table(definition).unwrap();
+            for i in 0..1000u64 {
+                assert_eq!(table.get(&i).unwrap().unwrap().value(), value.as_slice());
+            }
+            for i in (0..1000u64).step_by(2) {
+                assert!(table.remove(&i).unwrap().is_some());
+            }
+            assert_eq!(table.len().unwrap(), 500);
+        }
+        txn.commit().unwrap();
+    }
+    // Appending with even splits leaves pages half empty
+    assert!(leaf_pages[1] * 3 / 2 < leaf_pages[0]);
+
+    let policy = FillPolicy::new(70, 10);
+    assert_eq!(policy.split_percent(), 70);
+    assert_eq!(policy.merge_percent(), 10);
+    assert_eq!(FillPolicy::default(), FillPolicy::BALANCED);
+}
+
+#[test]
+fn create_in_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("new.redb");
+    {
+        let db = Database::create(&path).unwrap();
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(U64_TABLE).unwrap();
+            table.insert(&0, &1).unwrap();
+        }
+        txn.commit().unwrap();
+    }
+    // Creating a database which already exists opens it, rather than re-initializing it
+    let db = Database::create(&path).unwrap();
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.get(&0).unwrap().unwrap().value(), 1);
+}
+
+#[test]
+fn direct_io() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+
+    let value = vec![7u8; 1000];
+    for direct_io in [true, false, true] {
+        let db = Database::builder()
+            .set_direct_io(direct_io)
+            .create(tmpfile.path())
+            .unwrap();
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(SLICE_TABLE).unwrap();
+            let offset = table.len().unwrap();
+            for i in offset..(offset + 1000) {
+                table
+                    .insert(i.to_le_bytes().as_slice(), value.as_slice())
+                    .unwrap();
+            }
+        }
+        txn.commit().unwrap();
+
+        let txn = db.begin_read().unwrap();
+        let table = txn.open_table(SLICE_TABLE).unwrap();
+        let len = table.len().unwrap();
+        for i in 0..len {
+            assert_eq!(
+                table
+                    .get(i.to_le_bytes().as_slice())
+                    .unwrap()
+                    .unwrap()
+                    .value(),
+                value
+            );
+        }
+    }
+}
+
+#[test]
+fn checksum_algorithm() {
+    for algorithm in [
+        ChecksumAlgorithm::Xxh3,
+        ChecksumAlgorithm::Crc32c,
+        ChecksumAlgorithm::Disabled,
+    ] {
+        let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        {
+            let db = Database::builder()
+                .set_checksum_algorithm(algorithm)
+                .create(tmpfile.path())
+                .unwrap();
+            let txn = db.begin_write().unwrap();
+            {
+                let mut table = txn.open_table(U64_TABLE).unwrap();
+                for i in 0..1000 {
+                    table.insert(&i, &(i * 2)).unwrap();
+                }
+            }
+            txn.commit().unwrap();
+        }
+
+        // The algorithm is stored in the file, so the builder setting is ignored when reopening
+        let db = Database::builder()
+            .set_checksum_algorithm(ChecksumAlgorithm::Xxh3)
+            .open(tmpfile.path())
+            .unwrap();
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(U64_TABLE).unwrap();
+            for i in 0..1000 {
+                assert_eq!(table.get(&i).unwrap().unwrap().value(), i * 2);
+            }
+            for i in 0..500 {
+                table.remove(&i).unwrap();
+            }
+        }
+        txn.commit().unwrap();
+        drop(db);
+
+        let db = Database::open(tmpfile.path()).unwrap();
+        let txn = db.begin_read().unwrap();
+        let table = txn.open_table(U64_TABLE).unwrap();
+        assert_eq!(table.len().unwrap(), 500);
+        assert_eq!(table.get(&999).unwrap().unwrap().value(), 1998);
+    }
+}
+
+#[test]
+fn incremental_backup() {
+    let source_file: NamedTempFile = NamedTempFile::new().unwrap();
+    let copy_file: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(source_file.path()).unwrap();
+    let multimap_def: MultimapTableDefinition<u64, &[u8]> = MultimapTableDefinition::new("mm");
+
+    let check_copy = |expected_len: u64| {
+        // Opening the copy modifies it, so check a scratch copy instead
+        let scratch: NamedTempFile = NamedTempFile::new().unwrap();
+        fs::copy(copy_file.path(), scratch.path()).unwrap();
+        let copy = Database::open(scratch.path()).unwrap();
+        let source_txn = db.begin_read().unwrap();
+        let source_table = source_txn.open_table(U64_TABLE).unwrap();
+        let copy_txn = copy.begin_read().unwrap();
+        let copy_table = copy_txn.open_table(U64_TABLE).unwrap();
+        assert_eq!(copy_table.len().unwrap(), expected_len);
+        for (expected, actual) in source_table.iter().unwrap().zip(copy_table.iter().unwrap()) {
+            let (expected_key, expected_value) = expected.unwrap();
+            let (key, value) = actual.unwrap();
+            assert_eq!(expected_key.value(), key.value());
+            assert_eq!(expected_value.value(), value.value());
+        }
+        let copy_slices = copy_txn.open_table(SLICE_TABLE).unwrap();
+        assert_eq!(copy_slices.len().unwrap(), 500);
+        let source_multimap = source_txn.open_multimap_table(multimap_def).unwrap();
+        let copy_multimap = copy_txn.open_multimap_table(multimap_def).unwrap();
+        assert_eq!(
+            source_multimap.get(&0).unwrap().count(),
+            copy_multimap.get(&0).unwrap().count()
+        );
+    };
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..10_000 {
+            table.insert(&i, &i).unwrap();
+        }
+        let mut slice_table = txn.open_table(SLICE_TABLE).unwrap();
+        let value = vec![1u8; 4000];
+        for i in 0..500u64 {
+            slice_table
+                .insert(i.to_le_bytes().as_slice(), value.as_slice())
+                .unwrap();
+        }
+        let mut multimap = txn.open_multimap_table(multimap_def).unwrap();
+        let value = vec![0u8; 100];
+        for i in 0..1000u64 {
+            let mut value = value.clone();
+            value[..8].copy_from_slice(&i.to_le_bytes());
+            multimap.insert(&0, value.as_slice()).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let mut full = vec![];
+    let first_marker = db.incremental_backup(None, &mut full).unwrap();
+    Database::apply_incremental(copy_file.path(), full.as_slice()).unwrap();
+    check_copy(10_000);
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..10 {
+            table.insert(&i, &(i + 1)).unwrap();
+            table.remove(&(9_000 + i)).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let mut incremental = vec![];
+    let second_marker = db
+        .incremental_backup(Some(first_marker), &mut incremental)
+        .unwrap();
+    assert!(incremental.len() < full.len() / 2);
+    Database::apply_incremental(copy_file.path(), incremental.as_slice()).unwrap();
+    check_copy(9_990);
+
+    let txn = db.begin_write().unwrap();
+    assert!(txn.delete_persistent_savepoint(first_marker).unwrap());
+    assert!(txn.delete_persistent_savepoint(second_marker).unwrap());
+    txn.commit().unwrap();
+
+    // An unknown marker creates no new savepoint
+    assert!(matches!(
+        db.incremental_backup(Some(first_marker), vec![]),
+        Err(Error::InvalidSavepoint)
+    ));
+    let txn = db.begin_write().unwrap();
+    assert_eq!(txn.list_persistent_savepoints().unwrap().count(), 0);
+    txn.abort().unwrap();
+
+    // Truncated backups are rejected, without modifying the copy
+    let result = Database::apply_incremental(copy_file.path(), &incremental[..100]);
+    assert!(matches!(result, Err(Error::Io(_))));
+    check_copy(9_990);
+}
+
+#[test]
+fn sync_from_replica() {
+    let follower_file: NamedTempFile = NamedTempFile::new().unwrap();
+    let replica_file: NamedTempFile = NamedTempFile::new().unwrap();
+
+    let value = b"replicated value".repeat(10);
+    {
+        let db = Database::create(follower_file.path()).unwrap();
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(SLICE_TABLE).unwrap();
+            for i in 0..1000u64 {
+                table
+                    .insert(i.to_le_bytes().as_slice(), value.as_slice())
+                    .unwrap();
+            }
+        }
+        txn.commit().unwrap();
+    }
+    fs::copy(follower_file.path(), replica_file.path()).unwrap();
+
+    // Corrupt one of the values in the follower
+    let mut data = fs::read(follower_file.path()).unwrap();
+    let offset = data
+        .windows(value.len())
+        .position(|window| window == value.as_slice())
+        .unwrap();
+    data[offset..(offset + 10)].copy_from_slice(b"corrupted!");
+    fs::write(follower_file.path(), &data).unwrap();
+
+    let mut follower = Database::open(follower_file.path()).unwrap();
+    let replica = Database::open(replica_file.path()).unwrap();
+    assert_eq!(follower.sync_from(&replica).unwrap(), 1);
+    // Nothing left to repair
+    assert_eq!(follower.sync_from(&replica).unwrap(), 0);
+
+    let txn = follower.begin_read().unwrap();
+    let table = txn.open_table(SLICE_TABLE).unwrap();
+    for i in 0..1000u64 {
+        let actual = table.get(i.to_le_bytes().as_slice()).unwrap().unwrap();
+        assert_eq!(actual.value(), value.as_slice());
+    }
+    drop(table);
+    drop(txn);
+
+    // A follower which has diverged is reset to the replica's commit
+    let multimap_def: MultimapTableDefinition<u64, u64> = MultimapTableDefinition::new("multimap");
+    let txn = follower.begin_write().unwrap();
+    txn.persistent_savepoint().unwrap();
+    {
+        let mut table = txn.open_table(SLICE_TABLE).unwrap();
+        table.remove(0u64.to_le_bytes().as_slice()).unwrap();
+        let mut table = txn.open_multimap_table(multimap_def).unwrap();
+        table.insert(1, 1).unwrap();
+    }
+    txn.commit().unwrap();
+    drop(replica);
+    let replica = Database::open(replica_file.path()).unwrap();
+    let txn = replica.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(SLICE_TABLE).unwrap();
+        table.insert(b"new".as_slice(), b"x".as_slice()).unwrap();
+        let mut table = txn.open_multimap_table(multimap_def).unwrap();
+        for i in 0..2000u64 {
+            table.insert(2, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let savepoint = follower
+        .begin_write()
+        .unwrap()
+        .ephemeral_savepoint()
+        .unwrap();
+
+    assert!(follower.sync_from(&replica).unwrap() > 0);
+    assert_eq!(follower.sync_from(&replica).unwrap(), 0);
+    let check = |db: &Database| {
+        let txn = db.begin_read().unwrap();
+        let table = txn.open_table(SLICE_TABLE).unwrap();
+        assert_eq!(table.len().unwrap(), 1001);
+        assert!(table.get(0u64.to_le_bytes().as_slice()).unwrap().is_some());
+        assert_eq!(table.get(b"new".as_slice()).unwrap().unwrap().value(), b"x");
+        let table = txn.open_multimap_table(multimap_def).unwrap();
+        assert!(table.get(1).unwrap().next().is_none());
+        assert_eq!(table.get(2).unwrap().count(), 2000);
+    };
+    check(&follower);
+    assert!(follower.list_savepoints().unwrap().is_empty());
+    let mut txn = follower.begin_write().unwrap();
+    assert!(matches!(
+        txn.restore_savepoint(&savepoint),
+        Err(Error::InvalidSavepoint)
+    ));
+    txn.abort().unwrap();
+    drop(savepoint);
+
+    // The follower can be written to and reopened afterwards
+    let txn = follower.begin_write().unwrap();
+    txn.open_table(SLICE_TABLE)
+        .unwrap()
+        .insert(b"after".as_slice(), b"y".as_slice())
+        .unwrap();
+    txn.commit().unwrap();
+    drop(follower);
+    let follower = Database::open(follower_file.path()).unwrap();
+    let txn = follower.begin_read().unwrap();
+    assert!(txn
+        .open_table(SLICE_TABLE)
+        .unwrap()
+        .get(b"after".as_slice())
+        .unwrap()
+        .is_some());
+    drop(txn);
+    drop(follower);
+
+    // A replica with a different checksum algorithm cannot be a physical copy
+    let other_file: NamedTempFile = NamedTempFile::new().unwrap();
+    let other = Builder::new()
+        .set_checksum_algorithm(ChecksumAlgorithm::Crc32c)
+        .create(other_file.path())
+        .unwrap();
+    let mut follower = Database::open(follower_file.path()).unwrap();
+    assert!(matches!(
+        follower.sync_from(&other),
+        Err(Error::ReplicaMismatch)
+    ));
+    check(&replica);
+}
+
+#[test]
+fn temp_table() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.insert(0, 0).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let allocated_before = db.begin_write().unwrap().stats().unwrap().allocated_pages();
+
+    let txn = db.begin_write().unwrap();
+    {
+        // Temporary tables do not share a namespace with regular tables
+        let mut table = txn.open_temp_table(U64_TABLE).unwrap();
+        assert!(table.is_empty().unwrap());
+        for i in 0..10_000 {
+            table.insert(i, i).unwrap();
+        }
+        assert!(matches!(
+            txn.open_temp_table(U64_TABLE),
+            Err(Error::TableAlreadyOpen(_, _))
+        ));
+    }
+    {
+        // Contents persist until the end of the transaction
+        let table = txn.open_temp_table(U64_TABLE).unwrap();
+        assert_eq!(table.len().unwrap(), 10_000);
+        let table = txn.open_table(U64_TABLE).unwrap();
+        assert_eq!(table.len().unwrap(), 1);
+    }
+    let summary = txn.commit_with_summary().unwrap();
+    assert!(summary.freed_pages() > 10);
+
+    let txn = db.begin_write().unwrap();
+    assert_eq!(txn.stats().unwrap().allocated_pages(), allocated_before);
+    assert_eq!(txn.list_tables().unwrap().count(), 1);
+    assert!(txn.open_temp_table(U64_TABLE).unwrap().is_empty().unwrap());
+    {
+        let mut table = txn.open_temp_table(U64_TABLE).unwrap();
+        for i in 0..10_000 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    txn.abort().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    assert_eq!(txn.stats().unwrap().allocated_pages(), allocated_before);
+    assert!(txn.open_temp_table(U64_TABLE).unwrap().is_empty().unwrap());
+}
+
+#[test]
+fn external_sort() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let txn = db.begin_write().unwrap();
+
+    let mut rng = rand::thread_rng();
+    let mut expected: Vec<(u64, u64)> = (0..10_000).map(|i| (rng.gen_range(0..1000), i)).collect();
+    {
+        let mut sorter: ExternalSorter<u64, u64> = ExternalSorter::new(&txn, 10_000);
+        for (key, value) in expected.iter() {
+            sorter.push(key, value).unwrap();
+        }
+        // Equal keys are returned in the order they were pushed
+        expected.sort_by_key(|(key, _)| *key);
+
+        let actual: Vec<(u64, u64)> = sorter
+            .sorted()
+            .unwrap()
+            .map(|entry| {
+                let (key, value) = entry.unwrap();
+                (key.value(), value.value())
+            })
+            .collect();
+        assert_eq!(actual, expected);
+
+        // Pairs can continue to be pushed after iterating
+        sorter.push(500, 10_000).unwrap();
+        assert_eq!(sorter.sorted().unwrap().count(), expected.len() + 1);
+    }
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        let mut sorter: ExternalSorter<u64, u64> = ExternalSorter::new(&txn, 1 << 20);
+        for i in (0..100).rev() {
+            sorter.push(i, i).unwrap();
+        }
+        for entry in sorter.sorted().unwrap() {
+            let (key, value) = entry.unwrap();
+            table.insert(key.value(), value.value()).unwrap();
+        }
+        assert_eq!(table.len().unwrap(), 100);
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn key_histogram() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let txn = db.begin_write().unwrap();
+    assert!(matches!(
+        txn.enable_key_histogram(U64_TABLE, 100),
+        Err(Error::TableDoesNotExist(_))
+    ));
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..1000 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    txn.enable_key_histogram(U64_TABLE, 100).unwrap();
+    {
+        // The histogram is computed when the transaction commits
+        let table = txn.open_table(U64_TABLE).unwrap();
+        let histogram = table.key_histogram().unwrap().unwrap();
+        assert_eq!(histogram.bucket_size(), 100);
+        assert_eq!(histogram.entries(), 0);
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    let histogram = txn.key_histogram(U64_TABLE).unwrap().unwrap();
+    assert_eq!(histogram.entries(), 1000);
+    assert_eq!(histogram.buckets().len(), 10);
+    for (i, bucket) in histogram.buckets().iter().enumerate() {
+        let i: u64 = i.try_into().unwrap();
+        assert_eq!(bucket.min(), i * 100);
+        assert_eq!(bucket.max(), i * 100 + 99);
+        assert_eq!(bucket.count(), 100);
+    }
+
+    // Commits update the buckets which entries were inserted into or removed from
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..50 {
+            table.remove(i).unwrap();
+        }
+        table.remove(999).unwrap();
+        table.insert(999, 0).unwrap();
+        table.remove(450).unwrap();
+        table.insert(1000, 0).unwrap();
+    }
+    txn.commit().unwrap();
+    let txn = db.begin_read().unwrap();
+    let histogram = txn.key_histogram(U64_TABLE).unwrap().unwrap();
+    assert_eq!(histogram.entries(), 950);
+    assert_eq!(histogram.buckets().len(), 10);
+    assert_eq!(histogram.buckets()[0].min(), 50);
+    assert_eq!(histogram.buckets()[0].max(), 99);
+    assert_eq!(histogram.buckets()[0].count(), 50);
+    assert_eq!(histogram.buckets()[4].count(), 99);
+    assert_eq!(histogram.buckets()[9].max(), 1000);
+    assert_eq!(histogram.buckets()[9].count(), 101);
+
+    // Buckets which become empty are dropped, and those which grow too large are split
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 100..200 {
+            table.remove(i).unwrap();
+        }
+        for i in 1001..1200 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let txn = db.begin_read().unwrap();
+    let histogram = txn.key_histogram(U64_TABLE).unwrap().unwrap();
+    assert_eq!(histogram.entries(), 1049);
+    let counts: Vec<u64> = histogram.buckets().iter().map(|x| x.count()).collect();
+    assert_eq!(
+        counts,
+        vec![50, 100, 100, 99, 100, 100, 100, 100, 100, 100, 100]
+    );
+    assert_eq!(histogram.buckets()[1].min(), 200);
+    assert_eq!(histogram.buckets()[8].min(), 900);
+    assert_eq!(histogram.buckets()[8].max(), 999);
+    assert_eq!(histogram.buckets()[9].min(), 1000);
+    assert_eq!(histogram.buckets()[10].max(), 1199);
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 1000..1200 {
+            table.remove(i).unwrap();
+        }
+        table.insert(999, 999).unwrap();
+        for i in 0..50 {
+            table.insert(i, i).unwrap();
+        }
+        table.insert(450, 450).unwrap();
+    }
+    txn.commit().unwrap();
+    let txn = db.begin_read().unwrap();
+    let histogram = txn.key_histogram(U64_TABLE).unwrap().unwrap();
+    assert_eq!(histogram.entries(), 900);
+    assert_eq!(histogram.buckets().len(), 9);
+    assert!(histogram.buckets().iter().all(|x| x.count() == 100));
+    assert_eq!(histogram.buckets()[0].min(), 0);
+    assert_eq!(histogram.buckets()[3].max(), 499);
+    assert_eq!(histogram.buckets()[8].max(), 999);
+
+    // Analyzing rebalances the buckets
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..50 {
+            table.remove(i).unwrap();
+        }
+    }
+    assert!(txn.analyze_key_histogram(U64_TABLE).unwrap());
+    txn.commit().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    {
+        let table = txn.open_table(U64_TABLE).unwrap();
+        let histogram = table.key_histogram().unwrap().unwrap();
+        assert_eq!(histogram.entries(), 850);
+        assert_eq!(histogram.buckets().len(), 9);
+        assert_eq!(histogram.buckets()[0].min(), 50);
+        assert_eq!(histogram.buckets()[0].max(), 249);
+        assert_eq!(histogram.buckets()[8].min(), 950);
+        assert_eq!(histogram.buckets()[8].max(), 999);
+        assert_eq!(histogram.buckets()[8].count(), 50);
+    }
+    txn.delete_table(U64_TABLE).unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.insert(0, 0).unwrap();
+    }
+    assert!(txn.analyze_key_histogram(U64_TABLE).unwrap());
+    txn.commit().unwrap();
+
+    // The histogram is kept, since the table exists when the transaction commits
+    let txn = db.begin_write().unwrap();
+    {
+        let table = txn.open_table(U64_TABLE).unwrap();
+        let histogram = table.key_histogram().unwrap().unwrap();
+        assert_eq!(histogram.entries(), 1);
+    }
+    assert!(txn.disable_key_histogram(U64_TABLE).unwrap());
+    assert!(!txn.disable_key_histogram(U64_TABLE).unwrap());
+    assert!(!txn.analyze_key_histogram(U64_TABLE).unwrap());
+    txn.commit().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    assert!(txn
+        .open_table(U64_TABLE)
+        .unwrap()
+        .key_histogram()
+        .unwrap()
+        .is_none());
+    txn.enable_key_histogram(U64_TABLE, 10).unwrap();
+    txn.commit().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    txn.delete_table(U64_TABLE).unwrap();
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    assert!(txn.key_histogram(U64_TABLE).unwrap().is_none());
+}
+
+#[test]
+fn key_histogram_incremental() {
+    let table_def: TableDefinition<u64, u64> = TableDefinition::new("x");
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let txn = db.begin_write().unwrap();
+    txn.open_table(table_def).unwrap();
+    txn.enable_key_histogram(table_def, 50).unwrap();
+    txn.commit().unwrap();
+
+    // Checks that the buckets partition the entries of the table, in order
+    fn check(db: &Database, table_def: TableDefinition<u64, u64>) {
+        let txn = db.begin_read().unwrap();
+        let histogram = txn.key_histogram(table_def).unwrap().unwrap();
+        let table = txn.open_table(table_def).unwrap();
+        assert_eq!(histogram.entries(), table.len().unwrap());
+        let mut position = 0;
+        for bucket in histogram.buckets() {
+            assert!(bucket.count() > 0 && bucket.count() <= 100);
+            let (min, _) = table.nth(position).unwrap().unwrap();
+            assert_eq!(bucket.min(), min.value());
+            position += bucket.count();
+            let (max, _) = table.nth(position - 1).unwrap().unwrap();
+            assert_eq!(bucket.max(), max.value());
+        }
+    }
+
+    let mut rng = rand::thread_rng();
+    for i in 0..20 {
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(table_def).unwrap();
+            if i % 5 == 0 {
+                for key in 0..500 {
+                    table.insert(i * 1_000 + key, key).unwrap();
+                }
+            }
+            for _ in 0..200 {
+                let key = rng.gen_range(0..25_000);
+                if rng.gen_bool(0.5) {
+                    table.insert(key, rng.gen::<u64>()).unwrap();
+                } else {
+                    table.remove(key).unwrap();
+                }
+            }
+        }
+        txn.commit().unwrap();
+        check(&db, table_def);
+    }
+
+    let txn = db.begin_write().unwrap();
+    txn.open_table(table_def).unwrap().drain::<u64>(..).unwrap();
+    txn.commit().unwrap();
+    check(&db, table_def);
+    let txn = db.begin_read().unwrap();
+    assert!(txn
+        .key_histogram(table_def)
+        .unwrap()
+        .unwrap()
+        .buckets()
+        .is_empty());
+}
+
+#[test]
+fn table_group() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let definition1: TableDefinition<u64, u64> = TableDefinition::new("t1");
+    let definition2: TableDefinition<u64, u64> = TableDefinition::new("t2");
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut group = txn.open_group(&[definition1, definition2]).unwrap();
+        assert_eq!(group.len(), 2);
+        group.insert_all(1, 1).unwrap();
+        group.insert_all(2, 2).unwrap();
+        group.table_mut("t1").unwrap().insert(3, 3).unwrap();
+        assert!(group.contains_in_all(1).unwrap());
+        assert!(!group.contains_in_all(3).unwrap());
+        assert!(group.table("t3").is_none());
+        assert_eq!(group.remove_all(3).unwrap(), 1);
+        assert_eq!(group.remove_all(1).unwrap(), 2);
+        let names: Vec<&str> = group.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["t1", "t2"]);
+    }
+    // A failed open leaves none of the tables open
+    assert!(matches!(
+        txn.open_group(&[definition1, definition1]),
+        Err(Error::TableAlreadyOpen(_, _))
+    ));
+    txn.open_table(definition1).unwrap();
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    for definition in [definition1, definition2] {
+        let table = txn.open_table(definition).unwrap();
+        assert_eq!(table.len().unwrap(), 1);
+        assert_eq!(table.get(2).unwrap().unwrap().value(), 2);
+    }
+}
+
+#[test]
+fn delete_cascade() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let users: TableDefinition<&str, u64> = TableDefinition::new("users");
+    // post id -> author
+    let posts: TableDefinition<u64, &str> = TableDefinition::new("posts");
+    // (user, follower) -> ()
+    let followers: TableDefinition<(&str, &str), ()> = TableDefinition::new("followers");
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(users).unwrap();
+        table.insert("alice", 0).unwrap();
+        table.insert("bob", 1).unwrap();
+        let mut table = txn.open_table(posts).unwrap();
+        for i in 0..10 {
+            table
+                .insert(i, if i % 2 == 0 { "alice" } else { "bob" })
+                .unwrap();
+        }
+        let mut table = txn.open_table(followers).unwrap();
+        table.insert(("alice", "bob"), ()).unwrap();
+        table.insert(("bob", "alice"), ()).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    let post_author = ForeignKey::new(posts, |_, author: &str, user: &str| author == user);
+    let followed = ForeignKey::new(followers, |(user, _): (&str, &str), _, key: &str| {
+        user == key
+    });
+    assert_eq!(
+        txn.delete_cascade(users, "alice", &[&post_author, &followed])
+            .unwrap(),
+        7
+    );
+    assert_eq!(
+        txn.delete_cascade(users, "carol", &[&post_author, &followed])
+            .unwrap(),
+        0
+    );
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(users).unwrap();
+    assert!(table.get("alice").unwrap().is_none());
+    assert_eq!(table.len().unwrap(), 1);
+    let table = txn.open_table(posts).unwrap();
+    assert_eq!(table.len().unwrap(), 5);
+    for entry in table.iter().unwrap() {
+        assert_eq!(entry.unwrap().1.value(), "bob");
+    }
+    let table = txn.open_table(followers).unwrap();
+    assert_eq!(table.len().unwrap(), 1);
+    assert!(table.get(("bob", "alice")).unwrap().is_some());
+}
+
+#[test]
+fn run_write_retries() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let mut attempts = 0;
+    let value = db
+        .run_write(|txn| {
+            attempts += 1;
+            let mut table = txn.open_table(U64_TABLE)?;
+            table.insert(attempts, attempts)?;
+            if attempts < 3 {
+                return Err(Error::Io(ErrorKind::WouldBlock.into()));
+            }
+            Ok(attempts)
+        })
+        .unwrap();
+    assert_eq!(value, 3);
+    // Only the successful attempt was committed
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), 1);
+    assert_eq!(table.get(3).unwrap().unwrap().value(), 3);
+
+    // Errors which are not transient are returned immediately
+    let mut attempts = 0;
+    let result: Result<(), Error> = db.run_write(|_| {
+        attempts += 1;
+        Err(Error::TableDoesNotExist("x".to_string()))
+    });
+    assert!(matches!(result, Err(Error::TableDoesNotExist(_))));
+    assert_eq!(attempts, 1);
+
+    let mut policy = RetryPolicy::new();
+    policy
+        .set_max_attempts(2)
+        .set_backoff(Duration::ZERO, Duration::ZERO)
+        .set_transient_errors(|err| matches!(err, Error::TableDoesNotExist(_)));
+    let mut attempts = 0;
+    let result: Result<(), Error> = db.run_write_with_policy(&policy, |_| {
+        attempts += 1;
+        Err(Error::TableDoesNotExist("x".to_string()))
+    });
+    assert!(matches!(result, Err(Error::TableDoesNotExist(_))));
+    assert_eq!(attempts, 2);
+}
+
+// Advances only when slept on
+#[derive(Clone)]
+struct MockClock(Arc<Mutex<SystemTime>>);
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+}
+
+#[test]
+fn mock_clock() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let clock = MockClock(Arc::new(Mutex::new(start)));
+    let db = Builder::new()
+        .set_clock(clock.clone())
+        .create(tmpfile.path())
+        .unwrap();
+    assert_eq!(db.clock().now(), start);
+
+    let txn = db.begin_write().unwrap();
+    let id = txn.persistent_savepoint().unwrap();
+    txn.commit().unwrap();
+    assert_eq!(db.savepoint_metadata(id).unwrap().created(), Some(start));
+
+    // Retries wait on the clock, so the backoff is observable without sleeping
+    let mut policy = RetryPolicy::new();
+    policy
+        .set_max_attempts(4)
+        .set_backoff(Duration::from_secs(10), Duration::from_secs(15));
+    let mut attempts = 0;
+    db.run_write_with_policy(&policy, |_| {
+        attempts += 1;
+        if attempts < 4 {
+            return Err(Error::Io(ErrorKind::WouldBlock.into()));
+        }
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(clock.now(), start + Duration::from_secs(10 + 15 + 15));
+}
+
+#[test]
+fn model_tester() {
+    let mut rng = rand::thread_rng();
+
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let mut tester: ModelTester<u64, &str> = ModelTester::new(tmpfile.path()).unwrap();
+    for i in 0..100 {
+        tester.add_key(i);
+    }
+    for value in ["", "a", "hello", "world"] {
+        tester.add_value(value);
+    }
+    tester.run(100, || rng.gen()).unwrap();
+
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let mut tester: ModelTester<(&str, i32), Option<u64>> =
+        ModelTester::new(tmpfile.path()).unwrap();
+    for key in ["", "a", "b", "ab"] {
+        for i in -2..2 {
+            tester.add_key((key, i));
+        }
+    }
+    tester.add_value(None);
+    tester.add_value(Some(0));
+    tester.add_value(Some(u64::MAX));
+    tester.run(100, || rng.gen()).unwrap();
+}
+
+#[test]
+fn access_guard_deref() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let array_definition: TableDefinition<u64, &[u8; 3]> = TableDefinition::new("array");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(SLICE_TABLE).unwrap();
+        table
+            .insert(b"hello".as_slice(), b"world".as_slice())
+            .unwrap();
+        let mut table = write_txn.open_table(STR_TABLE).unwrap();
+        table.insert("hello", "world").unwrap();
+        let mut table = write_txn.open_table(array_definition).unwrap();
+        table.insert(0, &[1, 2, 3]).unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let owned_slice: Vec<u8>;
+    let owned_str: String;
+    let owned_array: [u8; 3];
+    {
+        let read_txn = db.begin_read().unwrap();
+        let table = read_txn.open_table(SLICE_TABLE).unwrap();
+        let value = table.get(b"hello".as_slice()).unwrap().unwrap();
+        assert_eq!(value.len(), 5);
+        assert!(value.starts_with(b"wor"));
+        assert_eq!(AsRef::<[u8]>::as_ref(&value), b"world");
+        owned_slice = value.to_owned();
+
+        let table = read_txn.open_table(STR_TABLE).unwrap();
+        let value = table.get("hello").unwrap().unwrap();
+        assert!(value.ends_with("rld"));
+        assert_eq!(AsRef::<str>::as_ref(&value), "world");
+        assert_eq!(AsRef::<[u8]>::as_ref(&value), b"world");
+        owned_str = value.to_owned();
+
+        let table = read_txn.open_table(array_definition).unwrap();
+        let value = table.get(0).unwrap().unwrap();
+        assert_eq!(value[2], 3);
+        owned_array = value.to_owned();
+    }
+    assert_eq!(owned_slice, b"world");
+    assert_eq!(owned_str, "world");
+    assert_eq!(owned_array, [1, 2, 3]);
+}
+
+#[test]
+fn access_guard_raw_bytes() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        table.insert(1, 0x0102_0304).unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    let value = table.get(1).unwrap().unwrap();
+    assert_eq!(value.raw_bytes(), 0x0102_0304u64.to_le_bytes());
+    let (key, value) = table.iter().unwrap().next().unwrap().unwrap();
+    assert_eq!(key.raw_bytes(), 1u64.to_le_bytes());
+    assert_eq!(value.raw_bytes(), 0x0102_0304u64.to_le_bytes());
+}
+
+#[test]
+fn owned_read_table() {
+    fn open_table(db: &Database) -> OwnedReadTable<'_, &'static str, &'static str> {
+        db.begin_read().unwrap().into_table(STR_TABLE).unwrap()
+    }
+
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(STR_TABLE).unwrap();
+        table.insert("hello", "world").unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let table = open_table(&db);
+
+    // Later writes are not visible, since the table holds its transaction open
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(STR_TABLE).unwrap();
+        table.insert("hello", "world2").unwrap();
+        table.insert("hi", "world").unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    assert_eq!(table.len().unwrap(), 1);
+    assert_eq!(table.get("hello").unwrap().unwrap().value(), "world");
+    let other = table.transaction().open_table(STR_TABLE).unwrap();
+    assert!(other.get("hi").unwrap().is_none());
+    drop(other);
+    drop(table);
+
+    assert_eq!(open_table(&db).len().unwrap(), 2);
+}
+
+#[test]
+fn max_transaction_bytes() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::builder()
+        .set_max_transaction_bytes(64 * 1024)
+        .create(tmpfile.path())
+        .unwrap();
+    let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let value = vec![0u8; 1024];
+
+    let write_txn = db.begin_write().unwrap();
+    let mut inserted = 0;
+    {
+        let mut table = write_txn.open_table(table_def).unwrap();
+        let result = loop {
+            if let Err(err) = table.insert(inserted, value.as_slice()) {
+                break err;
+            }
+            inserted += 1;
+        };
+        assert!(matches!(result, Error::TransactionTooLarge(limit) if limit == 64 * 1024));
+        assert!(inserted > 0);
+        assert!(table.get(inserted).unwrap().is_none());
+        assert!(matches!(
+            table.remove(0),
+            Err(Error::TransactionTooLarge(_))
+        ));
+    }
+    // The transaction can still be committed
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(table_def).unwrap();
+    assert_eq!(table.len().unwrap(), inserted);
+    drop(table);
+    drop(read_txn);
+
+    let entries = (0..1000u64).map(|i| (i, value.as_slice()));
+    let transactions = db.insert_split(table_def, entries).unwrap();
+    assert!(transactions > 1);
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(table_def).unwrap();
+    assert_eq!(table.len().unwrap(), 1000);
+
+    // Without a limit, the entries are inserted in a single transaction
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let entries = (0..1000u64).map(|i| (i, value.as_slice()));
+    assert_eq!(db.insert_split(table_def, entries).unwrap(), 1);
+}
+
+#[test]
+fn transaction_larger_than_cache() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::builder()
+        .set_cache_size(64 * 1024)
+        .create(tmpfile.path())
+        .unwrap();
+    let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let value = vec![0xFFu8; 1024];
+    let count = 4096u64;
+
+    // Aborting discards the pages which were written to the file early
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(table_def).unwrap();
+        for i in 0..count {
+            table.insert(i, value.as_slice()).unwrap();
+        }
+    }
+    write_txn.abort().unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let table = write_txn.open_table(table_def).unwrap();
+        assert!(table.is_empty().unwrap());
+    }
+    write_txn.abort().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(table_def).unwrap();
+        for i in 0..count {
+            table.insert(i, value.as_slice()).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+    drop(db);
+
+    let db = Database::open(tmpfile.path()).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(table_def).unwrap();
+    assert_eq!(table.len().unwrap(), count);
+    for (i, entry) in table.iter().unwrap().enumerate() {
+        let (key, value) = entry.unwrap();
+        assert_eq!(key.value(), i as u64);
+        assert_eq!(value.value(), [0xFFu8; 1024].as_slice());
+    }
+}
+
+#[test]
+fn drop_behavior() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let mut write_txn = db.begin_write().unwrap();
+    write_txn.set_drop_behavior(DropBehavior::Panic);
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        table.insert(0, 0).unwrap();
+    }
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(write_txn)));
+    assert!(result.is_err());
+
+    // The transaction was aborted before panicking
+    let mut write_txn = db.begin_write().unwrap();
+    write_txn.set_drop_behavior(DropBehavior::Panic);
+    {
+        let table = write_txn.open_table(U64_TABLE).unwrap();
+        assert!(table.is_empty().unwrap());
+    }
+    write_txn.commit().unwrap();
+
+    let mut write_txn = db.begin_write().unwrap();
+    write_txn.set_drop_behavior(DropBehavior::Abort);
+    drop(write_txn);
+}
+
+#[test]
+fn free_page_stats() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let value = vec![0u8; 1024];
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(table_def).unwrap();
+        for i in 0..1000 {
+            table.insert(i, value.as_slice()).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    // A live read transaction prevents the deleted pages from being reused
+    let read_txn = db.begin_read().unwrap();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(table_def).unwrap();
+        for i in 0..1000 {
+            table.remove(i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let txn = db.begin_write().unwrap();
+    let stats = txn.stats().unwrap();
+    assert!(stats.pending_free_pages() > 250);
+    assert!(stats.largest_free_block() <= stats.free_pages());
+    txn.abort().unwrap();
+    drop(read_txn);
+
+    // Once the read transaction is released, later commits free the pages
+    for _ in 0..2 {
+        let txn = db.begin_write().unwrap();
+        txn.commit().unwrap();
+    }
+    let txn = db.begin_write().unwrap();
+    let free_stats = txn.stats().unwrap();
+    assert!(free_stats.pending_free_pages() < stats.pending_free_pages());
+    assert!(free_stats.free_pages() > stats.free_pages());
+    assert!(free_stats.largest_free_block() > 0);
+    assert!(free_stats.largest_free_block() <= free_stats.free_pages());
+}
+
+#[test]
+fn allocation_strategy() {
+    let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let value = vec![0u8; 1024];
+    for strategy in [AllocationStrategy::Any, AllocationStrategy::Lowest] {
+        let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let db = Database::builder()
+            .set_allocation_strategy(strategy)
+            .create(tmpfile.path())
+            .unwrap();
+        for round in 0..10u64 {
+            let txn = db.begin_write().unwrap();
+            {
+                let mut table = txn.open_table(table_def).unwrap();
+                for i in 0..100 {
+                    table.insert(round * 100 + i, value.as_slice()).unwrap();
+                }
+                if round > 0 {
+                    for i in 0..100 {
+                        table.remove((round - 1) * 100 + i).unwrap();
+                    }
+                }
+            }
+            txn.commit().unwrap();
+        }
+        drop(db);
+
+        let db = Database::builder()
+            .set_allocation_strategy(strategy)
+            .open(tmpfile.path())
+            .unwrap();
+        let txn = db.begin_read().unwrap();
+        let table = txn.open_table(table_def).unwrap();
+        assert_eq!(table.len().unwrap(), 100);
+        assert_eq!(
+            table.iter().unwrap().next().unwrap().unwrap().0.value(),
+            900
+        );
+    }
+}
+
+#[test]
+fn defragment_table() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let other_def: TableDefinition<u64, &[u8]> = TableDefinition::new("y");
+    let value = vec![0u8; 1024];
+
+    // Interleave writes to two tables, so that their pages are mixed together in the file
+    for round in 0..20u64 {
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(table_def).unwrap();
+            let mut other = txn.open_table(other_def).unwrap();
+            for i in 0..20 {
+                table.insert(i * 20 + round, value.as_slice()).unwrap();
+                other.insert(i * 20 + round, value.as_slice()).unwrap();
+            }
+        }
+        txn.commit().unwrap();
+    }
+
+    let txn = db.begin_write().unwrap();
+    let before = txn
+        .open_table(table_def)
+        .unwrap()
+        .fragmentation_report()
+        .unwrap();
+    assert!(before.pages() > 1);
+    assert!(before.span_bytes() >= before.bytes());
+    assert!(before.discontinuities() > 0);
+    txn.defragment_table(table_def).unwrap();
+    let after = txn
+        .open_table(table_def)
+        .unwrap()
+        .fragmentation_report()
+        .unwrap();
+    assert_eq!(after.pages(), before.pages());
+    assert!(after.discontinuities() <= before.discontinuities());
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(table_def).unwrap();
+    assert_eq!(table.len().unwrap(), 400);
+    for (i, entry) in table.iter().unwrap().enumerate() {
+        let (key, value) = entry.unwrap();
+        assert_eq!(key.value(), i as u64);
+        assert_eq!(value.value().len(), 1024);
+    }
+}
+
+#[test]
+fn flush() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let mut txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.insert(0, 0).unwrap();
+    }
+    txn.flush().unwrap();
+
+    // Flushed writes are visible to new readers
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.get(0).unwrap().unwrap().value(), 0);
+    drop(table);
+    drop(read_txn);
+
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.insert(1, 1).unwrap();
+        table.remove(0).unwrap();
+        let mut temp = txn.open_temp_table(SLICE_TABLE).unwrap();
+        temp.insert(b"hello".as_slice(), b"world".as_slice())
+            .unwrap();
+    }
+    txn.flush().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.insert(2, 2).unwrap();
+        // Temporary tables survive a flush, but are never persisted
+        let temp = txn.open_temp_table(SLICE_TABLE).unwrap();
+        assert_eq!(
+            temp.get(b"hello".as_slice()).unwrap().unwrap().value(),
+            b"world"
+        );
+    }
+    // Only the writes after the last flush are rolled back
+    txn.abort().unwrap();
+    drop(db);
+
+    let db = Database::open(tmpfile.path()).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    assert!(table.get(0).unwrap().is_none());
+    assert_eq!(table.get(1).unwrap().unwrap().value(), 1);
+    assert!(table.get(2).unwrap().is_none());
+    assert!(read_txn.open_table(SLICE_TABLE).is_err());
+}
+
+#[test]
+fn resumable_import() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    // The first run dies partway through the third chunk
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let entries = (0..1000u64).map(|i| {
+            assert!(i < 250);
+            (i, i * 2)
+        });
+        Importer::new(&db, "numbers", U64_TABLE)
+            .set_chunk_size(100)
+            .import(entries)
+    }));
+    assert!(result.is_err());
+    drop(db);
+
+    let db = Database::open(tmpfile.path()).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    assert_eq!(read_txn.open_table(U64_TABLE).unwrap().len().unwrap(), 200);
+    drop(read_txn);
+
+    let mut progress = vec![];
+    let mut produced = 0;
+    let entries = (0..1000u64).map(|i| {
+        produced += 1;
+        (i, i * 2)
+    });
+    let imported = Importer::new(&db, "numbers", U64_TABLE)
+        .set_chunk_size(400)
+        .set_progress_callback(|x: ImportProgress| progress.push(x))
+        .import(entries)
+        .unwrap();
+    assert_eq!(imported, 1000);
+    assert_eq!(produced, 1000);
+    assert_eq!(progress.len(), 2);
+    assert_eq!(progress[0].imported(), 600);
+    assert_eq!(progress[0].resumed_from(), 200);
+    assert!(!progress[0].finished());
+    assert_eq!(progress[1].imported(), 1000);
+    assert!(progress[1].finished());
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), 1000);
+    for (i, entry) in table.iter().unwrap().enumerate() {
+        let (key, value) = entry.unwrap();
+        assert_eq!(key.value(), i as u64);
+        assert_eq!(value.value(), 2 * i as u64);
+    }
+    drop(table);
+    drop(read_txn);
+
+    // The cursor is removed once the import finishes, so the same name starts from scratch
+    let imported = Importer::new(&db, "numbers", U64_TABLE)
+        .import([(5000u64, 0u64)])
+        .unwrap();
+    assert_eq!(imported, 1);
+}
+
+#[test]
+fn archive_roundtrip() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let empty_def: TableDefinition<u64, u64> = TableDefinition::new("empty");
+    let big_value = vec![7u8; 10_000];
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..10_000u64 {
+            table.insert(i, i * 3).unwrap();
+        }
+        let mut table = txn.open_table(STR_TABLE).unwrap();
+        for i in 0..2_000u64 {
+            table.insert(format!("key{i}").as_str(), "value").unwrap();
+        }
+        let mut table = txn.open_table(SLICE_TABLE).unwrap();
+        table
+            .insert(b"big".as_slice(), big_value.as_slice())
+            .unwrap();
+        table.insert(b"small".as_slice(), b"x".as_slice()).unwrap();
+        txn.open_table(empty_def).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let mut archive = vec![];
+    let read_txn = db.begin_read().unwrap();
+    assert_eq!(read_txn.export_archive(&mut archive).unwrap(), 4);
+    drop(read_txn);
+
+    let tmpfile2: NamedTempFile = NamedTempFile::new().unwrap();
+    let db2 = Builder::new()
+        .set_checksum_algorithm(ChecksumAlgorithm::Crc32c)
+        .create(tmpfile2.path())
+        .unwrap();
+    let txn = db2.begin_write().unwrap();
+    {
+        // Existing tables are replaced
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.insert(1_000_000, 0).unwrap();
+    }
+    assert_eq!(txn.import_archive(archive.as_slice()).unwrap(), 4);
+    {
+        // The restored tables can be modified
+        let mut table = txn.open_table(STR_TABLE).unwrap();
+        table.insert("key1000", "updated").unwrap();
+        table.insert("zzz", "new").unwrap();
+    }
+    txn.commit().unwrap();
+
+    {
+        let txn = db2.begin_read().unwrap();
+        let table = txn.open_table(U64_TABLE).unwrap();
+        assert_eq!(table.len().unwrap(), 10_000);
+        for i in 0..10_000u64 {
+            assert_eq!(table.get(i).unwrap().unwrap().value(), i * 3);
+        }
+        assert!(table.get(1_000_000).unwrap().is_none());
+        assert_eq!(table.range(5000..5003).unwrap().count(), 3);
+        let table = txn.open_table(STR_TABLE).unwrap();
+        assert_eq!(table.len().unwrap(), 2_001);
+        assert_eq!(table.get("key999").unwrap().unwrap().value(), "value");
+        assert_eq!(table.get("key1000").unwrap().unwrap().value(), "updated");
+        let table = txn.open_table(SLICE_TABLE).unwrap();
+        assert_eq!(
+            table.get(b"big".as_slice()).unwrap().unwrap().value(),
+            big_value.as_slice()
+        );
+        assert_eq!(
+            table.get(b"small".as_slice()).unwrap().unwrap().value(),
+            b"x"
+        );
+        assert!(txn.open_table(empty_def).unwrap().is_empty().unwrap());
+
+        // Tables are checked against their type metadata as usual
+        let wrong_def: TableDefinition<u64, &str> = TableDefinition::new("u64");
+        assert!(matches!(
+            txn.open_table(wrong_def),
+            Err(Error::TableTypeMismatch { .. })
+        ));
+    }
+    drop(db2);
+
+    let db2 = Database::open(tmpfile2.path()).unwrap();
+    let txn = db2.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.get(9_999).unwrap().unwrap().value(), 29_997);
+}
+
+#[test]
+fn archive_validation() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let pair_def: TableDefinition<(u64, u64), u64> = TableDefinition::new("pairs");
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.insert(0x1111, 1).unwrap();
+        table.insert(0x2222, 2).unwrap();
+        txn.open_table(pair_def).unwrap().insert((1, 2), 3).unwrap();
+    }
+    txn.commit().unwrap();
+    let mut archive = vec![];
+    db.begin_read()
+        .unwrap()
+        .export_archive(&mut archive)
+        .unwrap();
+
+    let import = |archive: &[u8]| {
+        let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+        let db = Database::create(tmpfile.path()).unwrap();
+        let txn = db.begin_write().unwrap();
+        // The order of tuple keys is only known once the table has been opened
+        txn.open_table(pair_def).unwrap();
+        let result = txn.import_archive(archive);
+        txn.abort().unwrap();
+        result
+    };
+    assert_eq!(import(&archive).unwrap(), 2);
+
+    // Keys which are out of order, or duplicated, are rejected
+    let replace_key = |key: u64| {
+        let old = 0x2222u64.to_le_bytes();
+        let position = archive.windows(old.len()).position(|x| x == old).unwrap();
+        let mut corrupt = archive.clone();
+        corrupt[position..(position + old.len())].copy_from_slice(&key.to_le_bytes());
+        corrupt
+    };
+    assert!(matches!(import(&replace_key(0x3333)), Ok(2)));
+    for key in [0x1111, 0x1000] {
+        let err = import(&replace_key(key)).unwrap_err();
+        assert!(matches!(err, Error::Io(err) if err.kind() == ErrorKind::InvalidData));
+    }
+
+    // An archive cut off at any point returns an error
+    for len in 0..archive.len() {
+        assert!(import(&archive[..len]).is_err());
+    }
+
+    // Lengths beyond the maximum value length are rejected before they are allocated
+    let mut oversized = archive[..17].to_vec();
+    oversized.extend_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(import(&oversized), Err(Error::ValueTooLarge(_))));
+
+    // Without the table being opened first, the order of the tuple keys is unknown
+    let tmpfile2: NamedTempFile = NamedTempFile::new().unwrap();
+    let db2 = Database::create(tmpfile2.path()).unwrap();
+    let txn = db2.begin_write().unwrap();
+    let err = txn.import_archive(archive.as_slice()).unwrap_err();
+    assert!(matches!(err, Error::Io(err) if err.kind() == ErrorKind::InvalidInput));
+    txn.abort().unwrap();
+
+    // Multimap tables cannot be archived
+    let multimap_def: MultimapTableDefinition<u64, u64> = MultimapTableDefinition::new("multimap");
+    let txn = db.begin_write().unwrap();
+    txn.open_multimap_table(multimap_def).unwrap();
+    txn.commit().unwrap();
+    assert!(matches!(
+        db.begin_read().unwrap().export_archive(&mut vec![]),
+        Err(Error::TableIsMultimap(name)) if name == "multimap"
+    ));
+}
+
+#[test]
+fn copy_to() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..1000 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let copy: NamedTempFile = NamedTempFile::new().unwrap();
+    assert!(db.copy_to(copy.path()).unwrap() > 0);
+
+    // Changes made after the copy are not included
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.insert(5000, 5000).unwrap();
+        table.remove(0).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let copy_db = Database::open(copy.path()).unwrap();
+    let txn = copy_db.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), 1000);
+    assert_eq!(table.get(0).unwrap().unwrap().value(), 0);
+    assert!(table.get(5000).unwrap().is_none());
+    drop(table);
+    drop(txn);
+
+    // The copy is writable
+    let txn = copy_db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.insert(1000, 1000).unwrap();
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn savepoint_management() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let value = vec![0u8; 1024];
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(table_def).unwrap();
+        for i in 0..100 {
+            table.insert(i, value.as_slice()).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    let id = txn.persistent_savepoint().unwrap();
+    txn.commit().unwrap();
+    assert_eq!(db.list_savepoints().unwrap(), vec![id]);
+    let before = db.savepoint_metadata(id).unwrap();
+    assert_eq!(before.id(), id);
+    assert!(before.created().unwrap().elapsed().unwrap() < Duration::from_secs(60));
+
+    // Deleting the data leaves its pages retained only by the savepoint
+    let txn = db.begin_write().unwrap();
+    txn.delete_table(table_def).unwrap();
+    txn.commit().unwrap();
+    let after = db.savepoint_metadata(id).unwrap();
+    assert!(after.pinned_bytes() >= before.pinned_bytes() + 100 * 1024);
+
+    assert!(db.delete_savepoint(id).unwrap());
+    assert!(!db.delete_savepoint(id).unwrap());
+    assert!(db.list_savepoints().unwrap().is_empty());
+    assert!(matches!(
+        db.savepoint_metadata(id),
+        Err(Error::InvalidSavepoint)
+    ));
+}
+
+#[test]
+fn space_report() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let value = vec![0u8; 1024];
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(table_def).unwrap();
+        for i in 0..100 {
+            table.insert(i, value.as_slice()).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let report = db.space_report().unwrap();
+    assert!(report.table_bytes() >= 100 * 1024);
+    assert_eq!(report.savepoint_bytes(), 0);
+    assert_eq!(
+        report.table_bytes()
+            + report.system_bytes()
+            + report.savepoint_bytes()
+            + report.free_bytes()
+            + report.slack_bytes(),
+        report.file_bytes()
+    );
+
+    let txn = db.begin_write().unwrap();
+    let id = txn.persistent_savepoint().unwrap();
+    txn.commit().unwrap();
+    let txn = db.begin_write().unwrap();
+    txn.delete_table(table_def).unwrap();
+    txn.commit().unwrap();
+
+    // The deleted table is retained by the savepoint
+    let report = db.space_report().unwrap();
+    assert!(report.table_bytes() < 100 * 1024);
+    assert!(report.savepoint_bytes() >= 100 * 1024);
+
+    assert!(db.delete_savepoint(id).unwrap());
+    // Pages are freed by the commit after the one which released them
+    for _ in 0..2 {
+        db.begin_write().unwrap().commit().unwrap();
+    }
+    let report = db.space_report().unwrap();
+    assert_eq!(report.savepoint_bytes(), 0);
+    assert!(report.free_bytes() >= 100 * 1024);
+}
+
+#[test]
+fn pressure() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let max_size = 64 * 1024 * 1024;
+    let notified = Arc::new(AtomicUsize::new(0));
+    let notified2 = notified.clone();
+    let db = Builder::new()
+        .set_max_size(max_size)
+        .set_pressure_callback(50, move |pressure| {
+            assert!(pressure.percent() >= 50);
+            assert_eq!(pressure.max_bytes(), max_size);
+            notified2.fetch_add(1, Ordering::SeqCst);
+        })
+        .create(tmpfile.path())
+        .unwrap();
+
+    let pressure = db.pressure().unwrap();
+    assert_eq!(pressure.max_bytes(), max_size);
+    assert!(pressure.percent() < 50);
+    assert_eq!(notified.load(Ordering::SeqCst), 0);
+
+    let value = vec![0u8; 1024 * 1024];
+    let mut i = 0;
+    while db.pressure().unwrap().percent() < 50 {
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(table_def).unwrap();
+            table.insert(i, value.as_slice()).unwrap();
+        }
+        txn.commit().unwrap();
+        i += 1;
+    }
+    assert!(notified.load(Ordering::SeqCst) > 0);
+    assert_eq!(
+        db.pressure().unwrap().file_bytes(),
+        fs::metadata(tmpfile.path()).unwrap().len()
+    );
+
+    // Without a maximum size, there is no pressure
+    drop(db);
+    let db = Database::open(tmpfile.path()).unwrap();
+    assert!(db.pressure().is_none());
+}
+
+#[test]
+fn max_size() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let max_size = 8 * 1024 * 1024;
+    let db = Builder::new()
+        .set_max_size(max_size)
+        .create(tmpfile.path())
+        .unwrap();
+
+    let value = vec![0u8; 100 * 1024];
+    let mut committed = 0;
+    loop {
+        let txn = db.begin_write().unwrap();
+        let result = txn
+            .open_table(table_def)
+            .unwrap()
+            .insert(committed, value.as_slice())
+            .map(|_| ());
+        match result.and_then(|_| txn.commit()) {
+            Ok(()) => committed += 1,
+            Err(Error::DatabaseFull(limit)) => {
+                assert_eq!(limit, max_size);
+                break;
+            }
+            Err(err) => panic!("{err}"),
+        }
+    }
+    assert!(committed > 0);
+    assert!(fs::metadata(tmpfile.path()).unwrap().len() <= max_size);
+
+    // Freed space can still be reused
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(table_def).unwrap();
+        assert_eq!(table.len().unwrap(), committed);
+        table.remove_range(0..committed / 2).unwrap();
+    }
+    txn.commit().unwrap();
+    db.begin_write().unwrap().commit().unwrap();
+    let txn = db.begin_write().unwrap();
+    txn.open_table(table_def)
+        .unwrap()
+        .insert(committed, value.as_slice())
+        .unwrap();
+    txn.commit().unwrap();
+    assert!(fs::metadata(tmpfile.path()).unwrap().len() <= max_size);
+}
+
+#[test]
+fn cache_stats() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let db = Builder::new()
+        .set_cache_size(1024 * 1024)
+        .create(tmpfile.path())
+        .unwrap();
+
+    let value = vec![0u8; 1024];
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(table_def).unwrap();
+        for i in 0..2000 {
+            table.insert(i, value.as_slice()).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    db.evict_cache();
+    let stats = db.cache_stats();
+    assert_eq!(stats.entries(), 0);
+    assert_eq!(stats.bytes(), 0);
+
+    let read_all = || {
+        let txn = db.begin_read().unwrap();
+        let table = txn.open_table(table_def).unwrap();
+        for i in 0..2000 {
+            assert_eq!(table.get(i).unwrap().unwrap().value(), value.as_slice());
+        }
+    };
+    read_all();
+    let stats2 = db.cache_stats();
+    assert!(stats2.entries() > 0);
+    assert!(stats2.bytes() <= 1024 * 1024);
+    assert!(stats2.misses() > stats.misses());
+    assert!(stats2.hits() > stats.hits());
+    // The table is larger than the cache
+    assert!(stats2.evictions() > 0);
+
+    db.evict_cache();
+    let stats3 = db.cache_stats();
+    assert_eq!(stats3.entries(), 0);
+    assert_eq!(stats3.bytes(), 0);
+    assert_eq!(stats3.evictions(), stats2.evictions());
+}
+
+#[test]
+fn preload() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let value = vec![0u8; 100];
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(table_def).unwrap();
+        for i in 0..10_000 {
+            table.insert(i, value.as_slice()).unwrap();
+        }
+        // Most pages are leaves
+        let branches = table.preload(false).unwrap();
+        let all = table.preload(true).unwrap();
+        assert!(branches > 0);
+        assert!(all > 10 * branches);
+    }
+    txn.commit().unwrap();
+
+    db.evict_cache();
+    let branches = db.preload_tables(&["x"], false).unwrap();
+    let cached = db.cache_stats().entries();
+    assert!(cached >= branches);
+    let all = db.preload_tables(&["x"], true).unwrap();
+    assert!(all > branches);
+    assert!(db.cache_stats().entries() >= cached + all - branches - 1);
+    let misses = db.cache_stats().misses();
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(table_def).unwrap();
+    assert_eq!(table.preload(true).unwrap(), all);
+    for i in 0..10_000 {
+        assert_eq!(table.get(i).unwrap().unwrap().value(), value.as_slice());
+    }
+    // Every page was already cached
+    assert_eq!(db.cache_stats().misses(), misses);
+
+    assert!(matches!(
+        db.preload_tables(&["x", "missing"], false),
+        Err(Error::TableDoesNotExist(_))
+    ));
+}
+
+#[test]
+fn file_protection_class() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let table_def: TableDefinition<u64, u64> = TableDefinition::new("x");
+
+    {
+        let db = Builder::new()
+            .set_file_protection_class(FileProtectionClass::CompleteUntilFirstUserAuthentication)
+            .create(tmpfile.path())
+            .unwrap();
+        let txn = db.begin_write().unwrap();
+        txn.open_table(table_def).unwrap().insert(1, 1).unwrap();
+        txn.commit().unwrap();
+    }
+
+    let db = Builder::new()
+        .set_file_protection_class(FileProtectionClass::CompleteUnlessOpen)
+        .open(tmpfile.path())
+        .unwrap();
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(table_def).unwrap();
+    assert_eq!(table.get(1).unwrap().unwrap().value(), 1);
+}
+
+#[test]
+fn recovery_report() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let copy: NamedTempFile = NamedTempFile::new().unwrap();
+    let table_def: TableDefinition<u64, u64> = TableDefinition::new("x");
+
+    {
+        let db = Database::create(tmpfile.path()).unwrap();
+        assert!(db.last_recovery_report().is_none());
+        let txn = db.begin_write().unwrap();
+        txn.open_table(table_def).unwrap().insert(1, 1).unwrap();
+        txn.commit().unwrap();
+        // Copying the file while the database is open captures it as if the process had crashed
+        fs::copy(tmpfile.path(), copy.path()).unwrap();
+    }
+
+    // The original was shutdown cleanly
+    let db = Database::open(tmpfile.path()).unwrap();
+    assert!(db.last_recovery_report().is_none());
+    drop(db);
+
+    let db = Database::open(copy.path()).unwrap();
+    let report = db.last_recovery_report().unwrap();
+    assert!(!report.primary_header_corrupted());
+    assert!(!report.primary_commit_discarded());
+    assert!(report.recovered_transaction_id() > 0);
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(table_def).unwrap();
+    assert_eq!(table.get(1).unwrap().unwrap().value(), 1);
+}
+
+#[test]
+fn quarantine_corrupted_pages() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let table_def: TableDefinition<u64, &str> = TableDefinition::new("x");
+    let value = |i: u64| format!("value-{i:08}-").repeat(8);
+
+    {
+        let db = Database::create(tmpfile.path()).unwrap();
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(table_def).unwrap();
+            for i in 0..2000 {
+                table.insert(i, value(i).as_str()).unwrap();
+            }
+        }
+        txn.commit().unwrap();
+    }
+
+    // Corrupt the leaf holding key 1000, and any stale copies of it
+    let mut data = fs::read(tmpfile.path()).unwrap();
+    let pattern = value(1000);
+    let mut corrupted = 0;
+    for i in 0..(data.len() - pattern.len()) {
+        if &data[i..(i + pattern.len())] == pattern.as_bytes() {
+            data[i] = b'X';
+            corrupted += 1;
+        }
+    }
+    assert!(corrupted > 0);
+    fs::write(tmpfile.path(), data).unwrap();
+
+    let skipped = Arc::new(AtomicUsize::new(0));
+    let skipped2 = skipped.clone();
+    let db = Builder::new()
+        .set_quarantine_corrupted_pages(move |_| {
+            skipped2.fetch_add(1, Ordering::SeqCst);
+        })
+        .open(tmpfile.path())
+        .unwrap();
+    let quarantined = db.quarantined_pages();
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].table(), Some("x"));
+    let lower = quarantined[0].lower_bound().map(u64::from_bytes);
+    let upper = quarantined[0].upper_bound().map(u64::from_bytes);
+    let is_lost =
+        |i: u64| !matches!(lower, Some(x) if i <= x) && !matches!(upper, Some(x) if i > x);
+    assert!(is_lost(1000));
+    assert!(!is_lost(0) || !is_lost(1999));
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(table_def).unwrap();
+    assert!(matches!(table.get(1000), Err(Error::Corrupted(_))));
+    let expected: Vec<u64> = (0..2000).filter(|i| !is_lost(*i)).collect();
+    let keys: Vec<u64> = table
+        .iter()
+        .unwrap()
+        .map(|e| e.unwrap().0.value())
+        .collect();
+    assert_eq!(keys, expected);
+    let mut keys: Vec<u64> = table
+        .iter()
+        .unwrap()
+        .rev()
+        .map(|e| e.unwrap().0.value())
+        .collect();
+    keys.reverse();
+    assert_eq!(keys, expected);
+    assert_eq!(table.len().unwrap(), u64::try_from(expected.len()).unwrap());
+    assert_eq!(table.iter().unwrap().len(), expected.len());
+    assert!(skipped.load(Ordering::SeqCst) > 0);
+    for i in expected.iter().copied().step_by(97) {
+        assert_eq!(table.get(i).unwrap().unwrap().value(), value(i));
+    }
+
+    // Ranges which start or end inside the quarantined page
+    let keys: Vec<u64> = table
+        .range(..=1000)
+        .unwrap()
+        .map(|e| e.unwrap().0.value())
+        .collect();
+    let expected_before: Vec<u64> = (0..=1000).filter(|i| !is_lost(*i)).collect();
+    assert_eq!(keys, expected_before);
+    assert_eq!(table.range(..=1000).unwrap().len(), expected_before.len());
+    let keys: Vec<u64> = table
+        .range(1000..)
+        .unwrap()
+        .rev()
+        .map(|e| e.unwrap().0.value())
+        .collect();
+    let expected_after: Vec<u64> = (1000..2000).rev().filter(|i| !is_lost(*i)).collect();
+    assert_eq!(keys, expected_after);
+    let keys: Vec<u64> = table
+        .range(..=1000)
+        .unwrap()
+        .rev()
+        .map(|e| e.unwrap().0.value())
+        .collect();
+    assert_eq!(
+        keys,
+        expected_before.iter().rev().copied().collect::<Vec<_>>()
+    );
+    let keys: Vec<u64> = table
+        .range(1000..)
+        .unwrap()
+        .map(|e| e.unwrap().0.value())
+        .collect();
+    assert_eq!(
+        keys,
+        expected_after.iter().rev().copied().collect::<Vec<_>>()
+    );
+    assert_eq!(table.range(1000..=1000).unwrap().count(), 0);
+    drop(table);
+    drop(txn);
+
+    assert!(matches!(db.begin_write(), Err(Error::Corrupted(_))));
+}
+
+#[test]
+fn content_hash() {
+    let table_def: TableDefinition<u64, u64> = TableDefinition::new("x");
+    let file1: NamedTempFile = NamedTempFile::new().unwrap();
+    let file2: NamedTempFile = NamedTempFile::new().unwrap();
+    let db1 = Database::create(file1.path()).unwrap();
+    let db2 = Database::create(file2.path()).unwrap();
+
+    for db in [&db1, &db2] {
+        let txn = db.begin_write().unwrap();
+        txn.open_table(table_def).unwrap();
+        txn.enable_content_hash(table_def).unwrap();
+        txn.commit().unwrap();
+    }
+
+    // The same entries, written in a different order and with different page layouts
+    let txn = db1.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(table_def).unwrap();
+        for i in 0..10_000 {
+            table.insert(i, i * 2).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    for chunk in (0..10_000u64).collect::<Vec<_>>().chunks(1000).rev() {
+        let txn = db2.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(table_def).unwrap();
+            for &i in chunk.iter().rev() {
+                table.insert(i, i * 2).unwrap();
+                table.insert(i + 100_000, 0).unwrap();
+            }
+            for &i in chunk {
+                table.remove(i + 100_000).unwrap();
+            }
+        }
+        txn.commit().unwrap();
+    }
+
+    let hash1 = db1
+        .begin_read()
+        .unwrap()
+        .content_hash(table_def)
+        .unwrap()
+        .unwrap();
+    let hash2 = db2
+        .begin_read()
+        .unwrap()
+        .content_hash(table_def)
+        .unwrap()
+        .unwrap();
+    assert_eq!(hash1.entries(), 10_000);
+    assert!(hash1.chunks().len() > 1);
+    assert_eq!(hash1.root(), hash2.root());
+    assert!(hash1.divergent_chunks(&hash2).is_empty());
+
+    let txn = db2.begin_write().unwrap();
+    txn.open_table(table_def).unwrap().insert(5000, 0).unwrap();
+    txn.commit().unwrap();
+    let txn = db2.begin_write().unwrap();
+    let hash2 = txn
+        .open_table(table_def)
+        .unwrap()
+        .content_hash()
+        .unwrap()
+        .unwrap();
+    assert_ne!(hash1.root(), hash2.root());
+    let divergent = hash2.divergent_chunks(&hash1);
+    assert_eq!(divergent.len(), 1);
+    assert!(divergent[0].first() <= 5000 && divergent[0].last() >= 5000);
+    assert_eq!(hash1.divergent_chunks(&hash2).len(), 1);
+
+    assert!(txn.disable_content_hash(table_def).unwrap());
+    txn.commit().unwrap();
+    let txn = db2.begin_read().unwrap();
+    assert!(txn.content_hash(table_def).unwrap().is_none());
+}
+
+#[test]
+fn content_hash_incremental() {
+    let table_def: TableDefinition<u64, u64> = TableDefinition::new("x");
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let mut db = Database::create(tmpfile.path()).unwrap();
+    let txn = db.begin_write().unwrap();
+    txn.open_table(table_def).unwrap();
+    txn.enable_content_hash(table_def).unwrap();
+    txn.commit().unwrap();
+
+    // Returns the root, and the hash, size and bounds of each chunk
+    fn summarize(hash: &ContentHash<u64>) -> (u128, Vec<(u128, u64, u64, u64)>) {
+        let chunks = hash
+            .chunks()
+            .iter()
+            .map(|x| (x.hash(), x.count(), x.first(), x.last()))
+            .collect();
+        (hash.root(), chunks)
+    }
+    // Checks the maintained hash against one computed from every entry of the table
+    fn check(db: &mut Database, table_def: TableDefinition<u64, u64>) {
+        let maintained = db
+            .begin_read()
+            .unwrap()
+            .content_hash(table_def)
+            .unwrap()
+            .unwrap();
+        let txn = db.begin_write().unwrap();
+        txn.disable_content_hash(table_def).unwrap();
+        txn.enable_content_hash(table_def).unwrap();
+        txn.commit().unwrap();
+        let computed = db
+            .begin_read()
+            .unwrap()
+            .content_hash(table_def)
+            .unwrap()
+            .unwrap();
+        assert_eq!(summarize(&maintained), summarize(&computed));
+        let levels = computed.levels();
+        if computed.chunks().is_empty() {
+            assert!(levels.is_empty());
+        } else {
+            assert_eq!(levels.last().unwrap().len(), 1);
+            assert_eq!(levels.last().unwrap()[0].hash(), computed.root());
+            assert_eq!(
+                levels.last().unwrap()[0].chunks(),
+                0..computed.chunks().len()
+            );
+        }
+    }
+
+    let mut rng = rand::thread_rng();
+    for i in 0..20 {
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(table_def).unwrap();
+            if i % 5 == 0 {
+                for key in 0..2_000 {
+                    table.insert(i * 1_000 + key, key).unwrap();
+                }
+            }
+            for _ in 0..300 {
+                let key = rng.gen_range(0..25_000);
+                if rng.gen_bool(0.5) {
+                    table.insert(key, rng.gen::<u64>()).unwrap();
+                } else {
+                    table.remove(key).unwrap();
+                }
+            }
+        }
+        txn.commit().unwrap();
+        if i % 4 == 3 {
+            check(&mut db, table_def);
+        }
+    }
+
+    // Changes made after restoring a savepoint are applied to the hash of the restored table
+    let txn = db.begin_write().unwrap();
+    let savepoint = txn.ephemeral_savepoint().unwrap();
+    txn.open_table(table_def)
+        .unwrap()
+        .insert(100_000, 0)
+        .unwrap();
+    txn.commit().unwrap();
+    let mut txn = db.begin_write().unwrap();
+    txn.restore_savepoint(&savepoint).unwrap();
+    txn.open_table(table_def).unwrap().remove(5_000).unwrap();
+    txn.commit().unwrap();
+    check(&mut db, table_def);
+
+    // Removing every entry leaves no chunks
+    let txn = db.begin_write().unwrap();
+    txn.open_table(table_def).unwrap().drain::<u64>(..).unwrap();
+    txn.commit().unwrap();
+    check(&mut db, table_def);
+}
+
+#[test]
+fn diff_tables() {
+    let table_def: TableDefinition<u64, u64> = TableDefinition::new("x");
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(table_def).unwrap();
+        for i in 0..10_000 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let old_txn = db.begin_read().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(table_def).unwrap();
+        table.remove(17).unwrap();
+        table.insert(5000, 0).unwrap();
+        // Rewriting a value with itself is not a change
+        table.insert(6000, 6000).unwrap();
+        for i in 20_000..20_010 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let new_txn = db.begin_read().unwrap();
+
+    let old = old_txn.open_table(table_def).unwrap();
+    let new = new_txn.open_table(table_def).unwrap();
+    let mut added = vec![];
+    let mut removed = vec![];
+    let mut modified = vec![];
+    for entry in redb::diff_tables(&old, &new).unwrap() {
+        match entry.unwrap() {
+            DiffEntry::Added(key, value) => added.push((key.value(), value.value())),
+            DiffEntry::Removed(key, value) => removed.push((key.value(), value.value())),
+            DiffEntry::Modified(key, old_value, new_value) => {
+                modified.push((key.value(), old_value.value(), new_value.value()))
+            }
+        }
+    }
+    assert_eq!(added, (20_000..20_010).map(|i| (i, i)).collect::<Vec<_>>());
+    assert_eq!(removed, vec![(17, 17)]);
+    assert_eq!(modified, vec![(5000, 5000, 0)]);
+
+    // Reversing the arguments reverses the changes
+    let reversed = redb::diff_tables(&new, &old).unwrap();
+    let mut count = 0;
+    for entry in reversed {
+        match entry.unwrap() {
+            DiffEntry::Added(key, _) => assert_eq!(key.value(), 17),
+            DiffEntry::Removed(key, _) => assert!(key.value() >= 20_000),
+            DiffEntry::Modified(key, old_value, new_value) => {
+                assert_eq!(key.value(), 5000);
+                assert_eq!(old_value.value(), 0);
+                assert_eq!(new_value.value(), 5000);
+            }
+        }
+        count += 1;
+    }
+    assert_eq!(count, 12);
+    assert_eq!(redb::diff_tables(&new, &new).unwrap().count(), 0);
+
+    // Tables in another database share no pages, and are compared entry by entry
+    let tmpfile2: NamedTempFile = NamedTempFile::new().unwrap();
+    let db2 = Database::create(tmpfile2.path()).unwrap();
+    let txn = db2.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(table_def).unwrap();
+        for i in 1..10_000 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let txn = db2.begin_read().unwrap();
+    let other = txn.open_table(table_def).unwrap();
+    let changes: Vec<_> = redb::diff_tables(&other, &old)
+        .unwrap()
+        .map(|entry| match entry.unwrap() {
+            DiffEntry::Added(key, _) => key.value(),
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(changes, vec![0]);
+}
+
+#[test]
+fn remove_range() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    // Long keys keep the fanout low, so that the tree is several levels deep
+    let definition: TableDefinition<&str, u64> = TableDefinition::new("x");
+    let key = |i: u64| format!("{i:0>1000}");
+    let mut rng = rand::thread_rng();
+    let mut expected = BTreeMap::new();
+
+    let write_txn = db.begin_write().unwrap();
+    write_txn.open_table(definition).unwrap();
+    write_txn.commit().unwrap();
+    let write_txn = db.begin_write().unwrap();
+    let empty_stats = write_txn.stats().unwrap();
+    write_txn.abort().unwrap();
+
+    for round in 0..40 {
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(definition).unwrap();
+            // Insert some entries in the same transaction, so that uncommitted pages are removed
+            for _ in 0..(if round == 0 { 5000 } else { 200 }) {
+                let k = key(rng.gen_range(0..10_000));
+                let value: u64 = rng.gen();
+                table.insert(k.as_str(), value).unwrap();
+                expected.insert(k, value);
+            }
+
+            let start = rng.gen_range(0..10_000);
+            let (start, end) = (key(start), key(rng.gen_range(start..10_001)));
+            let (start, end) = (start.as_str(), end.as_str());
+            let (removed, keys): (u64, Vec<String>) = match round % 4 {
+                0 => (
+                    table.remove_range(start..end).unwrap(),
+                    expected
+                        .range(start.to_string()..end.to_string())
+                        .map(|(k, _)| k.clone())
+                        .collect(),
+                ),
+                1 => (
+                    table.remove_range(start..=end).unwrap(),
+                    expected
+                        .range(start.to_string()..=end.to_string())
+                        .map(|(k, _)| k.clone())
+                        .collect(),
+                ),
+                2 => (
+                    table.remove_range(..end).unwrap(),
+                    expected
+                        .range(..end.to_string())
+                        .map(|(k, _)| k.clone())
+                        .collect(),
+                ),
+                _ => (
+                    table.remove_range(start..).unwrap(),
+                    expected
+                        .range(start.to_string()..)
+                        .map(|(k, _)| k.clone())
+                        .collect(),
+                ),
+            };
+            assert_eq!(removed, keys.len() as u64);
+            for k in keys {
+                expected.remove(&k);
+            }
+
+            assert_eq!(table.len().unwrap(), expected.len() as u64);
+            let mut iter = table.iter().unwrap();
+            for (k, value) in expected.iter() {
+                let (actual_key, actual_value) = iter.next().unwrap().unwrap();
+                assert_eq!(actual_key.value(), k);
+                assert_eq!(actual_value.value(), *value);
+            }
+            assert!(iter.next().is_none());
+            drop(iter);
+            let reversed: Vec<String> = table
+                .iter()
+                .unwrap()
+                .rev()
+                .map(|x| x.unwrap().0.value().to_string())
+                .collect();
+            assert!(reversed.iter().eq(expected.keys().rev()));
+            for _ in 0..100 {
+                let k = key(rng.gen_range(0..10_000));
+                assert_eq!(
+                    table.get(k.as_str()).unwrap().map(|x| x.value()),
+                    expected.get(&k).cloned()
+                );
+            }
+        }
+        write_txn.commit().unwrap();
+    }
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        assert_eq!(
+            table.remove_range::<&str>(..).unwrap(),
+            expected.len() as u64
+        );
+        assert!(table.is_empty().unwrap());
+        assert_eq!(table.remove_range::<&str>(..).unwrap(), 0);
+    }
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    let stats = write_txn.stats().unwrap();
+    assert_eq!(stats.leaf_pages(), empty_stats.leaf_pages());
+    assert_eq!(stats.branch_pages(), empty_stats.branch_pages());
+}
+
+#[test]
+fn time_series() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut series = TimeSeriesTable::<u64>::open(&write_txn, "metrics").unwrap();
+        for timestamp in 0..1000 {
+            series.append(1, timestamp, timestamp).unwrap();
+            series.append(2, timestamp, 2 * timestamp).unwrap();
+        }
+        assert_eq!(series.append(1, 10, 100).unwrap().unwrap().value(), 10);
+        series.append(1, 10, 10).unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let series = ReadOnlyTimeSeriesTable::<u64>::open(&read_txn, "metrics").unwrap();
+    assert_eq!(series.get(2, 5).unwrap().unwrap().value(), 10);
+    let points: Vec<u64> = series
+        .range(1, 10..13)
+        .unwrap()
+        .map(|x| x.unwrap().1.value())
+        .collect();
+    assert_eq!(points, vec![10, 11, 12]);
+    assert_eq!(series.range(1, 998..).unwrap().count(), 2);
+    let (timestamp, value) = series.last(2).unwrap().unwrap();
+    assert_eq!((timestamp, value.value()), (999, 1998));
+    assert!(series.last(3).unwrap().is_none());
+    drop(value);
+    drop(series);
+    drop(read_txn);
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut series = TimeSeriesTable::<u64>::open(&write_txn, "metrics").unwrap();
+        assert_eq!(series.prune(1, 100).unwrap(), 100);
+        assert_eq!(series.prune_all(200).unwrap(), 300);
+        assert_eq!(
+            series
+                .range(2, ..)
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap()
+                .0
+                .value(),
+            (2, 200)
+        );
+
+        // Sum each bucket of 100 points, up to the incomplete bucket which contains 950
+        let shrunk = series
+            .downsample(1, 950, 100, |points| {
+                points.iter().map(|(_, value)| value.value()).sum::<u64>()
+            })
+            .unwrap();
+        assert_eq!(shrunk, 7 * 99);
+        let points: Vec<(u64, u64)> = series
+            .range(1, ..1000)
+            .unwrap()
+            .map(|x| {
+                let (key, value) = x.unwrap();
+                (key.value().1, value.value())
+            })
+            .take(8)
+            .collect();
+        assert_eq!(points[0], (200, (200..300).sum()));
+        assert_eq!(points[6], (800, (800..900).sum()));
+        assert_eq!(points[7], (900, 900));
+        assert_eq!(series.range(1, ..).unwrap().count(), 7 + 100);
+
+        // Buckets which were already downsampled are left alone
+        assert_eq!(series.downsample(1, 950, 100, |_| 0u64).unwrap(), 0);
+        assert_eq!(
+            series.get(1, 200).unwrap().unwrap().value(),
+            (200..300).sum::<u64>()
+        );
+    }
+    write_txn.commit().unwrap();
+}
+
+#[test]
+fn history() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let definition: TableDefinition<&str, u64> = TableDefinition::new("created");
+    let values = |db: &Database| -> Vec<u64> {
+        let read_txn = db.begin_read().unwrap();
+        let table = read_txn.open_table(U64_TABLE).unwrap();
+        table
+            .iter()
+            .unwrap()
+            .map(|x| x.unwrap().0.value())
+            .collect()
+    };
+    let has_table = |db: &Database| {
+        let read_txn = db.begin_read().unwrap();
+        let exists = read_txn.open_table(definition).is_ok();
+        exists
+    };
+
+    let mut history = History::new(&db, "history");
+    history.set_max_actions(3);
+    assert!(!history.undo().unwrap());
+    for i in 0..5 {
+        let write_txn = history.begin_action().unwrap();
+        write_txn
+            .open_table(U64_TABLE)
+            .unwrap()
+            .insert(i, i)
+            .unwrap();
+        if i == 4 {
+            write_txn
+                .open_table(definition)
+                .unwrap()
+                .insert("x", 1)
+                .unwrap();
+        }
+        write_txn.commit().unwrap();
+    }
+    assert_eq!(history.undo_len().unwrap(), 3);
+
+    assert!(history.undo().unwrap());
+    assert!(!has_table(&db));
+    assert!(history.undo().unwrap());
+    assert_eq!(values(&db), vec![0, 1, 2]);
+    assert_eq!(history.undo_len().unwrap(), 1);
+    assert_eq!(history.redo_len().unwrap(), 2);
+
+    assert!(history.redo().unwrap());
+    assert_eq!(values(&db), vec![0, 1, 2, 3]);
+    assert!(history.redo().unwrap());
+    assert!(has_table(&db));
+    assert!(!history.redo().unwrap());
+    assert_eq!(history.undo_len().unwrap(), 3);
+    assert!(history.undo().unwrap());
+    assert!(history.undo().unwrap());
+    assert_eq!(values(&db), vec![0, 1, 2]);
+
+    // A new action discards the undone ones
+    let write_txn = history.begin_action().unwrap();
+    write_txn
+        .open_table(U64_TABLE)
+        .unwrap()
+        .insert(10, 10)
+        .unwrap();
+    write_txn.commit().unwrap();
+    assert_eq!(history.undo_len().unwrap(), 2);
+    assert_eq!(history.redo_len().unwrap(), 0);
+    assert!(!history.redo().unwrap());
+    assert_eq!(values(&db), vec![0, 1, 2, 10]);
+    // The action log is a system table, so it is hidden from the user's tables
+    let read_txn = db.begin_read().unwrap();
+    assert_eq!(read_txn.list_tables().unwrap().count(), 1);
+    drop(read_txn);
+    drop(history);
+    drop(db);
+
+    // Actions can still be undone after the database is reopened
+    let db = Database::open(tmpfile.path()).unwrap();
+    let history = History::new(&db, "history");
+    assert_eq!(history.undo_len().unwrap(), 2);
+    assert!(history.undo().unwrap());
+    assert_eq!(values(&db), vec![0, 1, 2]);
+    // The undone action keeps its savepoints from before and after it, and the oldest action's
+    // savepoint was removed by the cap
+    let write_txn = db.begin_write().unwrap();
+    assert_eq!(write_txn.list_persistent_savepoints().unwrap().count(), 3);
+    write_txn.abort().unwrap();
+
+    // Changes made outside of an action are rolled back by redo
+    let write_txn = db.begin_write().unwrap();
+    write_txn
+        .open_table(U64_TABLE)
+        .unwrap()
+        .insert(20, 20)
+        .unwrap();
+    write_txn.commit().unwrap();
+    assert!(history.redo().unwrap());
+    assert_eq!(values(&db), vec![0, 1, 2, 10]);
+    assert!(history.undo().unwrap());
+
+    // No further back than the cap
+    assert!(history.undo().unwrap());
+    assert!(!history.undo().unwrap());
+    assert_eq!(values(&db), vec![0, 1]);
+}
+
+#[derive(Debug, PartialEq)]
+struct Reading {
+    temperature: f64,
+    humidity: u8,
+    status: Option<u32>,
+}
+
+redb::columnar_table! {
+    struct ReadingTable<u64>, ReadOnlyReadingTable for Reading {
+        temperature: f64,
+        humidity: u8,
+        status: Option<u32>,
+    }
+}
+
+#[test]
+fn columnar_table() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = ReadingTable::open(&write_txn, "readings").unwrap();
+        assert!(table.is_empty().unwrap());
+        for i in 0..10u64 {
+            let row = Reading {
+                temperature: i as f64 / 2.0,
+                humidity: 40 + i as u8,
+                status: if i % 2 == 0 { Some(i as u32) } else { None },
+            };
+            table.insert(i, &row).unwrap();
+        }
+        assert_eq!(table.len().unwrap(), 10);
+        assert!(table.remove(3).unwrap());
+        assert!(!table.remove(3).unwrap());
+        assert_eq!(table.get(3).unwrap(), None);
+        table
+            .insert(
+                4,
+                &Reading {
+                    temperature: -1.0,
+                    humidity: 0,
+                    status: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(table.humidity().get(4).unwrap().unwrap().value(), 0);
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = ReadOnlyReadingTable::open(&read_txn, "readings").unwrap();
+    assert_eq!(table.len().unwrap(), 9);
+    assert_eq!(
+        table.get(2).unwrap(),
+        Some(Reading {
+            temperature: 1.0,
+            humidity: 42,
+            status: Some(2),
+        })
+    );
+    assert_eq!(
+        table.get(4).unwrap(),
+        Some(Reading {
+            temperature: -1.0,
+            humidity: 0,
+            status: None,
+        })
+    );
+    assert_eq!(table.get(10).unwrap(), None);
+
+    // Each column is a regular table, stored under the table name and the field name
+    let humidity: Vec<u8> = table
+        .humidity()
+        .range(5..8)
+        .unwrap()
+        .map(|x| x.unwrap().1.value())
+        .collect();
+    assert_eq!(humidity, vec![45, 46, 47]);
+    let statuses = read_txn
+        .open_table(TableDefinition::<u64, Option<u32>>::new("readings::status"))
+        .unwrap();
+    assert_eq!(statuses.len().unwrap(), 9);
+    assert_eq!(statuses.get(8).unwrap().unwrap().value(), Some(8));
+}
+
+#[test]
+fn staging_table() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let mut expected = BTreeMap::new();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 0..50 {
+            table.insert(i * 2, i).unwrap();
+            expected.insert(i * 2, i);
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let mut staging = StagingTable::new(read_txn.open_table(U64_TABLE).unwrap());
+    let mut rng = rand::thread_rng();
+    let mut history = vec![];
+    for _ in 0..500 {
+        let key: u64 = rng.gen_range(0..120);
+        history.push(expected.clone());
+        if rng.gen_range(0..3u32) == 0 {
+            staging.remove(key);
+            expected.remove(&key);
+        } else {
+            let value: u64 = rng.gen_range(0..1000);
+            staging.insert(key, value);
+            expected.insert(key, value);
+        }
+        if rng.gen_range(0..4u32) == 0 {
+            assert!(staging.undo());
+            expected = history.pop().unwrap();
+        }
+
+        let key: u64 = rng.gen_range(0..120);
+        assert_eq!(
+            staging.get(key).unwrap().map(|x| x.value()),
+            expected.get(&key).copied()
+        );
+        let start: u64 = rng.gen_range(0..120);
+        let end: u64 = rng.gen_range(0..120);
+        let actual: Vec<(u64, u64)> = staging
+            .range(start..end)
+            .unwrap()
+            .map(|x| {
+                let (key, value) = x.unwrap();
+                (key.value(), value.value())
+            })
+            .collect();
+        let model: Vec<(u64, u64)> = if start <= end {
+            expected.range(start..end).map(|(k, v)| (*k, *v)).collect()
+        } else {
+            vec![]
+        };
+        assert_eq!(actual, model);
+    }
+    let all: Vec<(u64, u64)> = staging
+        .iter()
+        .unwrap()
+        .map(|x| {
+            let (key, value) = x.unwrap();
+            (key.value(), value.value())
+        })
+        .collect();
+    assert_eq!(all, expected.clone().into_iter().collect::<Vec<_>>());
+
+    // Nothing is written until the modifications are applied
+    let other_txn = db.begin_read().unwrap();
+    assert_eq!(other_txn.open_table(U64_TABLE).unwrap().len().unwrap(), 50);
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        staging.apply(&mut table).unwrap();
+    }
+    write_txn.commit().unwrap();
+    staging.clear();
+    assert_eq!(staging.staged_len(), 0);
+    assert!(!staging.undo());
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    let all: Vec<(u64, u64)> = table
+        .iter()
+        .unwrap()
+        .map(|x| {
+            let (key, value) = x.unwrap();
+            (key.value(), value.value())
+        })
+        .collect();
+    assert_eq!(all, expected.into_iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn cache_table() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut cache = CacheTable::<u64, &str>::open(&write_txn, "cache").unwrap();
+        cache.set_max_entries(3);
+        for i in 0..3 {
+            assert_eq!(cache.insert(i, "value").unwrap(), 0);
+        }
+        assert_eq!(cache.size_bytes().unwrap(), 3 * (8 + 5));
+        // Reading 0 makes 1 the least recently used entry
+        assert_eq!(cache.get(0).unwrap().unwrap().value(), "value");
+        assert_eq!(cache.insert(3, "value").unwrap(), 1);
+        assert!(cache.peek(1).unwrap().is_none());
+        assert_eq!(cache.len().unwrap(), 3);
+    }
+    write_txn.commit().unwrap();
+
+    // Reads in an aborted transaction do not change the access order
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut cache = CacheTable::<u64, &str>::open(&write_txn, "cache").unwrap();
+        cache.get(2).unwrap().unwrap();
+    }
+    write_txn.abort().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut cache = CacheTable::<u64, &str>::open(&write_txn, "cache").unwrap();
+        cache.set_max_entries(3);
+        // Peeking does not mark 2 as used, so it is evicted next
+        cache.peek(2).unwrap().unwrap();
+        assert_eq!(cache.insert(4, "value").unwrap(), 1);
+        assert!(cache.peek(2).unwrap().is_none());
+
+        // Replacing an entry updates the size, and does not evict anything
+        assert_eq!(cache.insert(0, "longer value").unwrap(), 0);
+        assert_eq!(cache.size_bytes().unwrap(), 3 * (8 + 5) + 7);
+
+        // The order is now 3, 4, 0. Evict the two oldest, to make room for a large value
+        cache.set_max_bytes(50);
+        assert_eq!(cache.insert(5, "large value").unwrap(), 2);
+        assert_eq!(cache.size_bytes().unwrap(), 20 + 19);
+        assert!(cache.peek(0).unwrap().is_some());
+
+        // A value which is larger than the whole budget is evicted immediately
+        assert_eq!(cache.insert(6, "x".repeat(100).as_str()).unwrap(), 3);
+        assert!(cache.is_empty().unwrap());
+        assert_eq!(cache.size_bytes().unwrap(), 0);
+
+        cache.insert(7, "value").unwrap();
+        assert_eq!(cache.remove(7).unwrap().unwrap().value(), "value");
+        assert!(cache.remove(7).unwrap().is_none());
+        cache.insert(8, "value").unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let cache = ReadOnlyCacheTable::<u64, &str>::open(&read_txn, "cache").unwrap();
+    assert_eq!(cache.get(8).unwrap().unwrap().value(), "value");
+    assert_eq!(cache.len().unwrap(), 1);
+    assert_eq!(cache.size_bytes().unwrap(), 13);
+}
+
+#[test]
+fn job_queue() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let timeout = Duration::from_secs(30);
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut queue = JobQueue::open(&write_txn, "jobs").unwrap();
+        assert_eq!(queue.enqueue(b"a").unwrap(), 0);
+        assert_eq!(queue.enqueue(b"b").unwrap(), 1);
+        queue
+            .enqueue_at(b"later", Duration::from_secs(100))
+            .unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    let mut leased = {
+        let mut queue = JobQueue::open(&write_txn, "jobs").unwrap();
+        queue.set_max_attempts(2);
+        let leased = queue.lease(Duration::from_secs(1), timeout, 10).unwrap();
+        assert_eq!(leased.len(), 2);
+        assert_eq!(leased[0].payload(), b"a");
+        assert_eq!(leased[0].attempts(), 1);
+        assert_eq!(leased[1].expires_at(), Duration::from_secs(31));
+        // Leased jobs are hidden from other workers
+        assert!(queue
+            .lease(Duration::from_secs(2), timeout, 10)
+            .unwrap()
+            .is_empty());
+        assert!(queue.ack(&leased[0]).unwrap());
+        assert!(!queue.ack(&leased[0]).unwrap());
+        leased
+    };
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut queue = JobQueue::open(&write_txn, "jobs").unwrap();
+        queue.set_max_attempts(2);
+        assert!(queue
+            .extend(&mut leased[1], Duration::from_secs(20), timeout)
+            .unwrap());
+        assert!(queue
+            .lease(Duration::from_secs(40), timeout, 10)
+            .unwrap()
+            .is_empty());
+        // After the lease expires, the job becomes visible again, and the old lease is stale
+        let retried = queue.lease(Duration::from_secs(60), timeout, 1).unwrap();
+        assert_eq!(retried[0].id(), 1);
+        assert_eq!(retried[0].attempts(), 2);
+        assert!(!queue.ack(&leased[1]).unwrap());
+        assert!(!queue
+            .nack(&leased[1], Duration::from_secs(60), Duration::ZERO)
+            .unwrap());
+        // The job has used up its attempts, so it is dead lettered
+        assert!(queue
+            .nack(&retried[0], Duration::from_secs(61), Duration::ZERO)
+            .unwrap());
+        assert_eq!(queue.len().unwrap(), 1);
+        let dead = queue.dead_letters(10).unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!((dead[0].id(), dead[0].attempts()), (1, 2));
+        assert_eq!(dead[0].payload(), b"b");
+
+        let later = queue.lease(Duration::from_secs(100), timeout, 10).unwrap();
+        assert_eq!(later[0].payload(), b"later");
+        assert!(queue
+            .nack(&later[0], Duration::from_secs(100), Duration::from_secs(5))
+            .unwrap());
+        assert!(queue
+            .lease(Duration::from_secs(104), timeout, 10)
+            .unwrap()
+            .is_empty());
+        // A job whose last lease expires without being acknowledged is dead lettered on the next
+        // lease
+        assert_eq!(
+            queue.lease(Duration::from_secs(105), timeout, 10).unwrap()[0].attempts(),
+            2
+        );
+        assert!(queue
+            .lease(Duration::from_secs(200), timeout, 10)
+            .unwrap()
+            .is_empty());
+        assert!(queue.is_empty().unwrap());
+        assert_eq!(queue.dead_letters(10).unwrap().len(), 2);
+
+        assert!(queue.requeue_dead_letter(1).unwrap());
+        assert!(!queue.requeue_dead_letter(1).unwrap());
+        assert!(queue.remove_dead_letter(2).unwrap());
+        assert!(queue.dead_letters(10).unwrap().is_empty());
+        let requeued = queue.lease(Duration::from_secs(200), timeout, 10).unwrap();
+        assert_eq!((requeued[0].id(), requeued[0].attempts()), (1, 1));
+    }
+    write_txn.abort().unwrap();
+
+    // Aborting the transaction leaves the queue as it was
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut queue = JobQueue::open(&write_txn, "jobs").unwrap();
+        assert_eq!(queue.len().unwrap(), 2);
+        assert!(queue.dead_letters(10).unwrap().is_empty());
+        assert!(queue
+            .lease(Duration::from_secs(2), timeout, 10)
+            .unwrap()
+            .is_empty());
+    }
+    write_txn.commit().unwrap();
+}
+
+#[test]
+fn priority_queue() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut queue = PriorityQueueTable::<u32, &str>::open(&write_txn, "jobs").unwrap();
+        assert!(queue.pop_min().unwrap().is_none());
+        queue.push(5, "e").unwrap();
+        queue.push(1, "a").unwrap();
+        queue.push(3, "c1").unwrap();
+        queue.push(3, "c2").unwrap();
+        queue.push(2, "b").unwrap();
+        let (priority, value) = queue.peek_min().unwrap().unwrap();
+        assert_eq!((priority.value(), value.value()), (1, "a"));
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let queue = ReadOnlyPriorityQueueTable::<u32, &str>::open(&read_txn, "jobs").unwrap();
+    assert_eq!(queue.len().unwrap(), 5);
+    assert_eq!(queue.peek_min().unwrap().unwrap().1.value(), "a");
+    drop(queue);
+    drop(read_txn);
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut queue = PriorityQueueTable::<u32, &str>::open(&write_txn, "jobs").unwrap();
+        {
+            let (priority, value) = queue.pop_min().unwrap().unwrap();
+            assert_eq!((priority.value(), value.value()), (1, "a"));
+        }
+        // Entries with equal priority are popped in the order they were pushed
+        let popped: Vec<(u32, String)> = queue
+            .pop_min_batch(3)
+            .unwrap()
+            .iter()
+            .map(|(priority, value)| (priority.value(), value.value().to_string()))
+            .collect();
+        assert_eq!(
+            popped,
+            vec![
+                (2, "b".to_string()),
+                (3, "c1".to_string()),
+                (3, "c2".to_string())
+            ]
+        );
+        assert_eq!(queue.pop_min_batch(10).unwrap().len(), 1);
+        assert!(queue.is_empty().unwrap());
+        assert!(queue.pop_min_batch(10).unwrap().is_empty());
+    }
+    write_txn.commit().unwrap();
+
+    let mut rng = rand::thread_rng();
+    let mut expected = vec![];
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut queue = PriorityQueueTable::<u64, u64>::open(&write_txn, "random").unwrap();
+        for i in 0..5000u64 {
+            let priority: u64 = rng.gen_range(0..100);
+            queue.push(priority, i).unwrap();
+            expected.push((priority, i));
+        }
+        // Stable sort keeps equal priorities in insertion order
+        expected.sort_by_key(|(priority, _)| *priority);
+        let mut popped = vec![];
+        while !queue.is_empty().unwrap() {
+            let n: u64 = rng.gen_range(1..500);
+            for (priority, value) in queue.pop_min_batch(n.try_into().unwrap()).unwrap() {
+                popped.push((priority.value(), value.value()));
+            }
+        }
+        assert_eq!(popped, expected);
+    }
+    write_txn.commit().unwrap();
+}
+
+#[test]
+fn spatial_keys() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..1000 {
+        let (x, y): (u32, u32) = (rng.gen(), rng.gen());
+        let morton = Morton2D::new(x, y);
+        assert_eq!(Morton2D::from_curve_index(morton.curve_index()), morton);
+        let hilbert = Hilbert2D::new(x, y);
+        assert_eq!(Hilbert2D::from_curve_index(hilbert.curve_index()), hilbert);
+    }
+    assert_eq!(Morton2D::new(0b11, 0b01).curve_index(), 0b0111);
+
+    // Consecutive points along the Hilbert curve are adjacent
+    let start: u64 = rng.gen();
+    let mut previous = Hilbert2D::from_curve_index(start);
+    for index in (start + 1)..(start + 1000) {
+        let point = Hilbert2D::from_curve_index(index);
+        let distance = previous.x().abs_diff(point.x()) + previous.y().abs_diff(point.y());
+        assert_eq!(distance, 1);
+        previous = point;
+    }
+}
+
+fn check_spatial_query<K: SpatialKey>(points: &[(u32, u32)]) {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let definition: TableDefinition<K, u64> = TableDefinition::new("points");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        for (i, (x, y)) in points.iter().enumerate() {
+            table.insert(K::new(*x, *y), i as u64).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let mut rng = rand::thread_rng();
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    for i in 0..200 {
+        let (x1, x2, y1, y2): (u32, u32, u32, u32) = if i % 2 == 0 {
+            (
+                rng.gen_range(0..256),
+                rng.gen_range(0..256),
+                rng.gen_range(0..256),
+                rng.gen_range(0..256),
+            )
+        } else {
+            (rng.gen(), rng.gen(), rng.gen(), rng.gen())
+        };
+        let bbox = BoundingBox::new(x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2));
+
+        let ranges = spatial::range_query::<K>(&bbox);
+        assert!(ranges.len() <= 64);
+        for pair in ranges.windows(2) {
+            assert!(pair[0].end().curve_index() < pair[1].start().curve_index());
+        }
+        let mut found = vec![];
+        for range in ranges {
+            for entry in table.range(range).unwrap() {
+                let point = entry.unwrap().0.value();
+                if bbox.contains(point.x(), point.y()) {
+                    found.push((point.x(), point.y()));
+                }
+            }
+        }
+        found.sort_unstable();
+        let mut expected: Vec<(u32, u32)> = points
+            .iter()
+            .filter(|(x, y)| bbox.contains(*x, *y))
+            .cloned()
+            .collect();
+        expected.sort_unstable();
+        expected.dedup();
+        assert_eq!(found, expected);
+    }
+}
+
+#[test]
+fn spatial_range_query() {
+    let mut rng = rand::thread_rng();
+    let mut points: Vec<(u32, u32)> = (0..2000)
+        .map(|_| (rng.gen_range(0..256), rng.gen_range(0..256)))
+        .collect();
+    points.extend((0..2000).map(|_| (rng.gen::<u32>(), rng.gen::<u32>())));
+    check_spatial_query::<Morton2D>(&points);
+    check_spatial_query::<Hilbert2D>(&points);
+
+    // A box which is aligned to the curve is covered by a single range
+    let bbox = BoundingBox::new(64, 128, 127, 191);
+    let ranges = spatial::range_query::<Morton2D>(&bbox);
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(*ranges[0].start(), Morton2D::new(64, 128));
+    assert_eq!(*ranges[0].end(), Morton2D::new(127, 191));
+    assert_eq!(spatial::range_query::<Hilbert2D>(&bbox).len(), 1);
+}
+
+#[test]
+fn inverted_index() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let words = ["apple", "banana", "cherry", "date", "elder", "fig", "grape"];
+    let mut rng = rand::thread_rng();
+    let mut model: BTreeMap<u64, Vec<&str>> = BTreeMap::new();
+
+    // Random ids, so that ids are added to the middle and the front of posting lists
+    for _ in 0..5 {
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut index = InvertedIndex::open(&write_txn, "docs").unwrap();
+            for _ in 0..400 {
+                let id = rng.gen_range(0..3000);
+                let document: Vec<&str> = words
+                    .iter()
+                    .filter(|_| rng.gen_range(0..3u32) == 0)
+                    .cloned()
+                    .collect();
+                index
+                    .insert(id, &document.join(", ").to_uppercase())
+                    .unwrap();
+                model.insert(id, document);
+            }
+            for _ in 0..50 {
+                let id = rng.gen_range(0..3000);
+                assert_eq!(index.remove(id).unwrap(), model.remove(&id).is_some());
+            }
+            assert_eq!(index.len().unwrap(), model.len() as u64);
+        }
+        write_txn.commit().unwrap();
+    }
+
+    let read_txn = db.begin_read().unwrap();
+    let index = ReadOnlyInvertedIndex::open(&read_txn, "docs").unwrap();
+    let queries: [&[&str]; 5] = [
+        &["apple"],
+        &["apple", "fig"],
+        &["banana", "cherry", "grape"],
+        &["date", "missing"],
+        &[],
+    ];
+    for terms in queries {
+        for mode in [SearchMode::All, SearchMode::Any] {
+            let results: Vec<u64> = index
+                .search(terms, mode)
+                .unwrap()
+                .map(|x| x.unwrap())
+                .collect();
+            let expected: Vec<u64> = model
+                .iter()
+                .filter(|(_, document)| {
+                    !terms.is_empty()
+                        && match mode {
+                            SearchMode::All => terms.iter().all(|t| document.contains(t)),
+                            SearchMode::Any => terms.iter().any(|t| document.contains(t)),
+                        }
+                })
+                .map(|(id, _)| *id)
+                .collect();
+            assert_eq!(results, expected);
+        }
+    }
+    drop(index);
+    drop(read_txn);
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let tokenizer = |text: &str| text.split(';').map(|x| x.to_string()).collect();
+        let mut index = InvertedIndex::open_with_tokenizer(&write_txn, "tags", tokenizer).unwrap();
+        index.insert(1, "red;big").unwrap();
+        index.insert(2, "Red;small").unwrap();
+        assert_eq!(index.tokenize("a;b"), vec!["a", "b"]);
+        let results: Vec<u64> = index
+            .search(&["red"], SearchMode::Any)
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(results, vec![1]);
+        index.insert(1, "small").unwrap();
+        let results: Vec<u64> = index
+            .search(&["small"], SearchMode::All)
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(results, vec![1, 2]);
+        assert_eq!(index.search(&["red"], SearchMode::All).unwrap().count(), 0);
+    }
+    write_txn.commit().unwrap();
+}
+
+#[test]
+fn knn_scan() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let definition: TableDefinition<u64, FixedVector<19>> = TableDefinition::new("embeddings");
+    let mut rng = rand::thread_rng();
+    let mut vectors = vec![];
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        for i in 0..500u64 {
+            let mut vector = [0.0f32; 19];
+            for x in vector.iter_mut() {
+                *x = rng.gen_range(0..2000u32) as f32 / 1000.0 - 1.0;
+            }
+            table.insert(i, FixedVector::new(vector)).unwrap();
+            vectors.push(vector);
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    let stored = table.get(7).unwrap().unwrap();
+    assert_eq!(stored.value().to_array(), vectors[7]);
+    assert_eq!(stored.value().get(3), vectors[7][3]);
+    assert_eq!(stored.value(), FixedVector::new(vectors[7]));
+    if let Some(elements) = stored.value().as_slice() {
+        assert_eq!(elements, vectors[7]);
+    }
+    assert_eq!(
+        FixedVector::new(vectors[7]).as_slice(),
+        Some(&vectors[7][..])
+    );
+
+    let query = vectors[42];
+    for metric in [Distance::Euclidean, Distance::Cosine, Distance::DotProduct] {
+        let distance = |v: &[f32; 19]| -> f32 {
+            let dot: f32 = v.iter().zip(query.iter()).map(|(a, b)| a * b).sum();
+            let norm = |x: &[f32; 19]| x.iter().map(|a| a * a).sum::<f32>().sqrt();
+            match metric {
+                Distance::Euclidean => v
+                    .iter()
+                    .zip(query.iter())
+                    .map(|(a, b)| (a - b) * (a - b))
+                    .sum::<f32>()
+                    .sqrt(),
+                Distance::Cosine => 1.0 - dot / (norm(v) * norm(&query)),
+                Distance::DotProduct => -dot,
+            }
+        };
+        let mut expected: Vec<(u64, f32)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i as u64, distance(v)))
+            .collect();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let nearest = redb::knn_scan(&table, &query, 10, metric).unwrap();
+        assert_eq!(nearest.len(), 10);
+        for ((key, d), (expected_key, expected_d)) in nearest.iter().zip(expected.iter()) {
+            assert!((d - expected_d).abs() < 1e-4);
+            if key.value() != *expected_key {
+                // Only ties may be reordered
+                assert!((distance(&vectors[key.value() as usize]) - expected_d).abs() < 1e-4);
+            }
+        }
+        if metric != Distance::DotProduct {
+            assert_eq!(nearest[0].0.value(), 42);
+        }
+    }
+    assert!(redb::knn_scan(&table, &query, 0, Distance::Euclidean)
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        redb::knn_scan(&table, &query, 1000, Distance::Euclidean)
+            .unwrap()
+            .len(),
+        500
+    );
+}
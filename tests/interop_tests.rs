@@ -0,0 +1,112 @@
+#![cfg(feature = "interop")]
+
+use redb::{Database, Error, ReadableTable, TableDefinition};
+use std::io::ErrorKind;
+use tempfile::NamedTempFile;
+
+const STR_TABLE: TableDefinition<&str, u64> = TableDefinition::new("str");
+
+#[cfg(any(feature = "interop_lmdb", feature = "interop_sled"))]
+const SLICE_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("slice");
+
+#[cfg(any(feature = "interop_lmdb", feature = "interop_sled"))]
+fn assert_imported(db: &Database, expected: u64) {
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(SLICE_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), expected);
+    for i in 0..expected {
+        let key = i.to_be_bytes();
+        let value = table.get(key.as_slice()).unwrap().unwrap();
+        assert_eq!(value.value(), (i * 2).to_le_bytes().as_slice());
+    }
+}
+
+#[cfg(feature = "interop_lmdb")]
+#[test]
+fn import_lmdb() {
+    use lmdb::Transaction;
+
+    let dir = tempfile::tempdir().unwrap();
+    let env = lmdb::Environment::new().open(dir.path()).unwrap();
+    let source = env.open_db(None).unwrap();
+    let mut txn = env.begin_rw_txn().unwrap();
+    for i in 0..100u64 {
+        txn.put(
+            source,
+            &i.to_be_bytes(),
+            &(i * 2).to_le_bytes(),
+            lmdb::WriteFlags::empty(),
+        )
+        .unwrap();
+    }
+    txn.commit().unwrap();
+
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let imported = redb::interop::from_lmdb(&env, source, &db, SLICE_TABLE).unwrap();
+    assert_eq!(imported, 100);
+    assert_imported(&db, 100);
+}
+
+#[cfg(feature = "interop_sled")]
+#[test]
+fn import_sled() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = sled::Config::new().path(dir.path()).open().unwrap();
+    for i in 0..100u64 {
+        source
+            .insert(i.to_be_bytes(), (i * 2).to_le_bytes().as_slice())
+            .unwrap();
+    }
+
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let imported = redb::interop::from_sled(&source, &db, SLICE_TABLE).unwrap();
+    assert_eq!(imported, 100);
+    assert_imported(&db, 100);
+}
+
+#[test]
+fn export_csv() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(STR_TABLE).unwrap();
+        table.insert("plain", 1).unwrap();
+        table.insert("with,comma", 2).unwrap();
+        table.insert("with \"quote\"", 3).unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(STR_TABLE).unwrap();
+    let mut output = vec![];
+    assert_eq!(table.export_csv(&mut output).unwrap(), 3);
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "key,value\nplain,1\n\"with \"\"quote\"\"\",3\n\"with,comma\",2\n"
+    );
+}
+
+#[test]
+fn import_jsonl() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(STR_TABLE).unwrap();
+        let input = "{\"key\": \"a\", \"value\": 1}\n\n{\"key\": \"b\", \"value\": 2}\n";
+        assert_eq!(table.import_jsonl(input.as_bytes()).unwrap(), 2);
+        assert_eq!(table.get("a").unwrap().unwrap().value(), 1);
+        assert_eq!(table.get("b").unwrap().unwrap().value(), 2);
+
+        let input = "{\"key\": \"c\", \"value\": \"not a number\"}\n";
+        if let Err(Error::Io(e)) = table.import_jsonl(input.as_bytes()) {
+            assert!(matches!(e.kind(), ErrorKind::InvalidData));
+        } else {
+            panic!();
+        }
+    }
+    write_txn.commit().unwrap();
+}
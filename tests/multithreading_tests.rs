@@ -1,9 +1,38 @@
-use redb::{Database, ReadableTable, TableDefinition};
+use redb::{Database, Error, ReadableTable, TableDefinition, WriteQueue};
+use std::future::Future;
 use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
 use std::thread;
 use tempfile::NamedTempFile;
 
 const TABLE: TableDefinition<&str, &str> = TableDefinition::new("x");
+const U64_TABLE: TableDefinition<u64, u64> = TableDefinition::new("u64");
+
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+fn is_ready<F: Future>(future: F) -> bool {
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    Box::pin(future).as_mut().poll(&mut cx).is_ready()
+}
 
 #[test]
 fn len() {
@@ -62,3 +91,103 @@ fn multithreaded_insert() {
     let table = read_txn.open_table(DEF2).unwrap();
     assert_eq!(table.len().unwrap(), 2);
 }
+
+#[test]
+fn write_queue() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::create(tmpfile.path()).unwrap());
+    let queue = Arc::new(WriteQueue::new(db.clone(), 16));
+
+    let mut threads = vec![];
+    for i in 0..4u64 {
+        let queue = queue.clone();
+        threads.push(thread::spawn(move || {
+            let mut futures = vec![];
+            for j in 0..25u64 {
+                let key = i * 100 + j;
+                futures.push(queue.submit(move |txn| {
+                    let mut table = txn.open_table(U64_TABLE)?;
+                    table.insert(key, key)?;
+                    Ok(key)
+                }));
+            }
+            for (j, future) in futures.into_iter().enumerate() {
+                let j: u64 = j.try_into().unwrap();
+                assert_eq!(block_on(future).unwrap(), i * 100 + j);
+            }
+        }));
+    }
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    // A failed write does not affect the others in its batch
+    let failed = queue.submit(|txn| {
+        let mut table = txn.open_table(U64_TABLE)?;
+        table.insert(1000, 1000)?;
+        Err::<(), Error>(Error::TableDoesNotExist("x".to_string()))
+    });
+    let succeeded = queue.submit(|txn| {
+        let mut table = txn.open_table(U64_TABLE)?;
+        table.insert(1001, 1001)?;
+        Ok(())
+    });
+    assert!(matches!(block_on(failed), Err(Error::TableDoesNotExist(_))));
+    block_on(succeeded).unwrap();
+    drop(queue);
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), 101);
+    assert!(table.get(1000).unwrap().is_none());
+    assert!(table.get(1001).unwrap().is_some());
+}
+
+#[test]
+fn watch_key() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::create(tmpfile.path()).unwrap());
+    let write = |key: &str, value: Option<&str>| {
+        let txn = db.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(TABLE).unwrap();
+            if let Some(value) = value {
+                table.insert(key, value).unwrap();
+            } else {
+                table.remove(key).unwrap();
+            }
+        }
+        txn.commit().unwrap();
+    };
+
+    // The table does not exist yet
+    let mut watch = db.watch_key(TABLE, "config").unwrap();
+    write("other", Some("value"));
+    assert!(!is_ready(watch.changed()));
+
+    let db2 = db.clone();
+    let writer = thread::spawn(move || {
+        let txn = db2.begin_write().unwrap();
+        txn.open_table(TABLE)
+            .unwrap()
+            .insert("config", "a")
+            .unwrap();
+        txn.commit().unwrap();
+    });
+    block_on(watch.changed());
+    writer.join().unwrap();
+    assert!(!is_ready(watch.changed()));
+
+    // Rewriting the same value is not a change
+    write("config", Some("a"));
+    assert!(!is_ready(watch.changed()));
+
+    // Changes made before polling are coalesced
+    write("config", Some("b"));
+    write("config", None);
+    assert!(is_ready(watch.changed()));
+    assert!(!is_ready(watch.changed()));
+
+    drop(watch);
+    write("config", Some("c"));
+}
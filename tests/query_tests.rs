@@ -0,0 +1,106 @@
+#![cfg(feature = "query")]
+
+use redb::query::Queryable;
+use redb::{Database, TableDefinition};
+use tempfile::NamedTempFile;
+
+const U64_TABLE: TableDefinition<u64, u64> = TableDefinition::new("u64");
+const STR_TABLE: TableDefinition<&str, u64> = TableDefinition::new("str");
+
+#[test]
+fn key_range_pushdown() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 0..1000u64 {
+            table.insert(i, i * 2).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    let keys = |query: redb::query::Query<u64, u64, _>| -> Vec<u64> {
+        query
+            .select(|key, _| key)
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect()
+    };
+
+    assert_eq!(table.query().count().unwrap(), 1000);
+    // Ranges are intersected
+    assert_eq!(
+        keys(table.query().key_range(10..20).key_range(15..=30)),
+        (15..20).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        keys(table.query().key_range(..=20).key_range(20..)),
+        vec![20]
+    );
+    assert!(keys(table.query().key_range(..20).key_range(20..)).is_empty());
+    assert!(keys(table.query().key_range(30..40).key_range(50..)).is_empty());
+
+    // Offsets are applied to matching entries, with or without filters
+    assert_eq!(
+        keys(table.query().key_range(100..).offset(500).limit(3)),
+        vec![600, 601, 602]
+    );
+    assert_eq!(
+        keys(
+            table
+                .query()
+                .filter(|key, _| key % 10 == 0)
+                .offset(5)
+                .limit(3)
+        ),
+        vec![50, 60, 70]
+    );
+    assert_eq!(
+        keys(table.query().key_range(..100).reverse().offset(2).limit(3)),
+        vec![97, 96, 95]
+    );
+    assert!(keys(table.query().limit(0)).is_empty());
+    assert!(keys(table.query().offset(1000)).is_empty());
+}
+
+#[test]
+fn filter_and_select() {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Database::create(tmpfile.path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(STR_TABLE).unwrap();
+        for (name, age) in [("alice", 31), ("amos", 17), ("anne", 45), ("bob", 52)] {
+            table.insert(name, age).unwrap();
+        }
+        // Queries can also be run over a table in a write transaction
+        assert_eq!(table.query().filter(|_, age| age >= 18).count().unwrap(), 3);
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(STR_TABLE).unwrap();
+    let min_age = 18;
+    let adults: Vec<String> = table
+        .query()
+        .key_range("a".."b")
+        .filter(|_, age| age >= min_age)
+        .filter(|name, _| name.len() > 4)
+        .select(|name, age| format!("{name}={age}"))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(adults, vec!["alice=31"]);
+
+    let names: Vec<String> = table
+        .query()
+        .reverse()
+        .select(|name, _| name.to_string())
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(names, vec!["bob", "anne", "amos", "alice"]);
+}
@@ -0,0 +1,151 @@
+#![cfg(feature = "server")]
+
+use redb::server::{Client, Server};
+use redb::{Database, ReadableTable, TableDefinition};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+
+const U64_TABLE: TableDefinition<u64, u64> = TableDefinition::new("u64");
+const SLICE_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("slice");
+
+fn start_server() -> (NamedTempFile, Arc<Database>, Server) {
+    let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::create(tmpfile.path()).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = Server::spawn(db.clone(), listener).unwrap();
+    (tmpfile, db, server)
+}
+
+#[test]
+fn requests() {
+    let (_tmpfile, db, server) = start_server();
+    let mut client = Client::connect(server.local_addr()).unwrap();
+
+    assert_eq!(client.get("slice", b"a").unwrap(), None);
+    assert!(client.scan("slice", b"", None, 10).unwrap().is_empty());
+    assert!(!client.remove("slice", b"a").unwrap());
+    for key in [b"a", b"b", b"c", b"d"] {
+        client.insert("slice", key, &[key[0]; 3]).unwrap();
+    }
+    client.insert("slice", b"e", b"").unwrap();
+    assert_eq!(client.get("slice", b"b").unwrap(), Some(b"bbb".to_vec()));
+    assert_eq!(client.get("slice", b"e").unwrap(), Some(vec![]));
+    assert!(client.remove("slice", b"a").unwrap());
+    assert_eq!(
+        client.scan("slice", b"b", Some(b"d"), 10).unwrap(),
+        vec![
+            (b"b".to_vec(), b"bbb".to_vec()),
+            (b"c".to_vec(), b"ccc".to_vec())
+        ]
+    );
+    let keys: Vec<Vec<u8>> = client
+        .scan("slice", b"", None, 3)
+        .unwrap()
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(keys, vec![b"b".to_vec(), b"c".to_vec(), b"d".to_vec()]);
+
+    // Each request is committed immediately
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(SLICE_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), 4);
+    assert_eq!(table.get(b"c".as_slice()).unwrap().unwrap().value(), b"ccc");
+    drop(table);
+    drop(read_txn);
+
+    // Errors are returned to the client, and the connection remains usable
+    let write_txn = db.begin_write().unwrap();
+    write_txn
+        .open_table(U64_TABLE)
+        .unwrap()
+        .insert(0, 0)
+        .unwrap();
+    write_txn.commit().unwrap();
+    let err = client.get("u64", b"a").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Other);
+    assert_eq!(client.get("slice", b"d").unwrap(), Some(b"ddd".to_vec()));
+}
+
+#[test]
+fn transactions() {
+    let (_tmpfile, _db, server) = start_server();
+    let mut client = Client::connect(server.local_addr()).unwrap();
+    let mut other = Client::connect(server.local_addr()).unwrap();
+
+    assert_eq!(client.commit().unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(client.abort().unwrap_err().kind(), ErrorKind::NotFound);
+
+    client.begin().unwrap();
+    assert!(client.begin().is_err());
+    assert_eq!(client.get("slice", b"a").unwrap(), None);
+    client.insert("slice", b"a", b"1").unwrap();
+    client.insert("slice", b"b", b"2").unwrap();
+    // Writes are visible within the transaction, but not to other clients
+    assert_eq!(client.get("slice", b"a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(client.scan("slice", b"", None, 10).unwrap().len(), 2);
+    assert_eq!(other.get("slice", b"a").unwrap(), None);
+    client.commit().unwrap();
+    assert_eq!(other.get("slice", b"a").unwrap(), Some(b"1".to_vec()));
+
+    client.begin().unwrap();
+    assert!(client.remove("slice", b"a").unwrap());
+    client.insert("slice", b"c", b"3").unwrap();
+    client.abort().unwrap();
+    assert_eq!(
+        other.scan("slice", b"", None, 10).unwrap(),
+        vec![
+            (b"a".to_vec(), b"1".to_vec()),
+            (b"b".to_vec(), b"2".to_vec())
+        ]
+    );
+
+    // A transaction is aborted if its client disconnects
+    client.begin().unwrap();
+    client.insert("slice", b"d", b"4").unwrap();
+    drop(client);
+    other.begin().unwrap();
+    assert_eq!(other.get("slice", b"d").unwrap(), None);
+    other.commit().unwrap();
+
+    // Dropping the server stops new connections, but open ones are still served
+    let addr = server.local_addr();
+    drop(server);
+    assert!(Client::connect(addr).is_err());
+    assert_eq!(other.get("slice", b"b").unwrap(), Some(b"2".to_vec()));
+}
+
+#[test]
+fn limits() {
+    let (_tmpfile, _db, server) = start_server();
+    let mut client = Client::connect(server.local_addr()).unwrap();
+
+    // Scans return at most 1024 entries, however large the limit
+    client.begin().unwrap();
+    for i in 0u32..1100 {
+        client.insert("slice", &i.to_be_bytes(), b"").unwrap();
+    }
+    client.commit().unwrap();
+    let entries = client.scan("slice", b"", None, u32::MAX).unwrap();
+    assert_eq!(entries.len(), 1024);
+    let mut start = entries.last().unwrap().0.clone();
+    start.push(0);
+    assert_eq!(
+        client.scan("slice", &start, None, u32::MAX).unwrap().len(),
+        76
+    );
+
+    // A message longer than the maximum closes the connection, without waiting for its contents
+    let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+    stream.write_all(&u32::MAX.to_le_bytes()).unwrap();
+    let mut buf = [0; 1];
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+
+    // Other connections are unaffected
+    assert_eq!(
+        client.get("slice", &0u32.to_be_bytes()).unwrap(),
+        Some(vec![])
+    );
+}